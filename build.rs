@@ -0,0 +1,68 @@
+//! Embeds build metadata for `acip_sidecar::build_info`.
+//!
+//! Every value is optional: a field that cannot be found (no `.git` in a source tarball, no
+//! `git` binary) is simply not set and reads as `unknown` at runtime. Packagers building from a
+//! tarball can supply `ACIP_BUILD_GIT_DESCRIBE` themselves; `SOURCE_DATE_EPOCH` pins the build
+//! timestamp for reproducible builds.
+
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    for var in ["ACIP_BUILD_GIT_DESCRIBE", "SOURCE_DATE_EPOCH"] {
+        println!("cargo:rerun-if-env-changed={var}");
+    }
+    // Only watch what exists: a missing path would make cargo rerun this on every build.
+    for path in [".git/HEAD", ".git/index", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+
+    let describe = std::env::var("ACIP_BUILD_GIT_DESCRIBE")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+        .or_else(|| {
+            output(
+                "git",
+                &["describe", "--tags", "--always", "--dirty", "--abbrev=12"],
+            )
+        });
+    set("ACIP_BUILD_GIT_DESCRIBE", describe);
+
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH").ok().or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs().to_string())
+    });
+    set("ACIP_BUILD_TIMESTAMP", timestamp);
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    set("ACIP_BUILD_RUSTC", output(&rustc, &["--version"]));
+    set("ACIP_BUILD_PROFILE", std::env::var("PROFILE").ok());
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(k, _)| k.strip_prefix("CARGO_FEATURE_").map(str::to_string))
+        .map(|f| f.to_lowercase().replace('_', "-"))
+        .collect();
+    features.sort();
+    set("ACIP_BUILD_FEATURES", Some(features.join(",")));
+}
+
+/// Trimmed stdout of a successful run; `None` when the program is missing or fails.
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    let s = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    (!s.is_empty()).then_some(s)
+}
+
+fn set(key: &str, value: Option<String>) {
+    if let Some(v) = value {
+        println!("cargo:rustc-env={key}={v}");
+    }
+}
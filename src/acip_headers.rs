@@ -0,0 +1,323 @@
+//! Reading `X-ACIP-*` request headers.
+//!
+//! HTTP lets a header repeat, and proxies may fold repeats into one comma-separated line, so a
+//! request can carry `X-ACIP-Allow-Tools: false` from the application and a second `true`
+//! added further upstream. Which one `HeaderMap::get` returns is an accident of ordering, so
+//! ACIP headers are read here instead, looking at every value. Only headers whose values are
+//! single tokens are split on commas; a URL or marker may contain one and is read whole.
+//!
+//! [`reject_duplicates`] checks each request before its handler runs. By default a header with
+//! more than one value is refused with `400 duplicate_header`. In `use_strictest` mode the
+//! security-relevant headers resolve to their most restrictive value instead (`false` for
+//! allow-tools, the first policy in `security.policy_ranking`) and the conflict is reported
+//! in the decision reasons; other ACIP headers, and policies missing from the ranking, are
+//! still refused. Every duplicate is logged at `warn` with the connection details.
+//!
+//! Before that check, a deprecated header ([`crate::deprecations`]) is moved onto its
+//! replacement, so handlers only ever read the new name, and the response carries
+//! `X-ACIP-Deprecated: <old>=<replacement>`. Past its sunset release it is refused with
+//! `400 removed_header`.
+
+use crate::deprecations::{self, DeprecationUse, Form, Registry};
+use crate::introspection;
+use crate::token_auth::Actor;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+pub const PREFIX: &str = "x-acip-";
+pub const POLICY: &str = "x-acip-policy";
+pub const ALLOW_TOOLS: &str = "x-acip-allow-tools";
+/// Checked by token auth, which refuses any repeat before this module sees the request.
+const TOKEN: &str = "x-acip-token";
+
+/// `error` code of the `400` answered to a request with a repeated ACIP header.
+pub const ERROR_CODE: &str = "duplicate_header";
+
+/// Handling of an ACIP header with more than one value (`security.duplicate_headers`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateHeaders {
+    /// Refuse the request.
+    #[default]
+    Reject,
+    /// Use the most restrictive value of a security-relevant header; refuse the rest.
+    UseStrictest,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderRules {
+    pub duplicates: DuplicateHeaders,
+    /// Policy names, most restrictive first (`security.policy_ranking`).
+    pub policy_ranking: Vec<String>,
+    /// Deprecated headers to move onto their replacements.
+    pub deprecations: Registry,
+}
+
+/// A repeated header resolved under [`DuplicateHeaders::UseStrictest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeaderConflict {
+    pub header: String,
+    pub values: Vec<String>,
+    pub resolved: String,
+}
+
+impl HeaderConflict {
+    /// Decision reason flagging the conflict.
+    pub fn reason(&self) -> String {
+        format!(
+            "header_conflict: {} sent {} values ({}); used {}",
+            self.header,
+            self.values.len(),
+            self.values.join(", "),
+            self.resolved
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("duplicate header {header} ({count} values)")]
+pub struct DuplicateHeader {
+    pub header: String,
+    pub count: usize,
+}
+
+impl IntoResponse for DuplicateHeader {
+    fn into_response(self) -> Response {
+        introspection::json_error(
+            StatusCode::BAD_REQUEST,
+            ERROR_CODE,
+            json!({"header": self.header, "count": self.count}),
+        )
+        .into_response()
+    }
+}
+
+/// ACIP headers whose values are single tokens, so a comma in one can only be a folded repeat.
+/// Any other ACIP header (a callback URL, an origin marker) is read verbatim.
+const LIST_HEADERS: [&str; 4] = [
+    POLICY,
+    ALLOW_TOOLS,
+    crate::cache_bypass::HEADER,
+    crate::slow_requests::FORCE_HEADER,
+];
+
+/// Every value of `name`, one per line, trimmed, empty values dropped. For [`LIST_HEADERS`]
+/// each comma-separated item counts as a value too. A value that is not visible ASCII counts
+/// as one opaque item.
+pub fn values(headers: &HeaderMap, name: &str) -> Vec<String> {
+    let list = LIST_HEADERS.iter().any(|h| name.eq_ignore_ascii_case(h));
+    let mut out = Vec::new();
+    for v in headers.get_all(name) {
+        match v.to_str() {
+            Ok(s) if list => out.extend(
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string),
+            ),
+            Ok(s) => out.extend(Some(s.trim()).filter(|s| !s.is_empty()).map(str::to_string)),
+            Err(_) => out.push(String::from_utf8_lossy(v.as_bytes()).into_owned()),
+        }
+    }
+    out
+}
+
+fn truthy(v: &str) -> bool {
+    matches!(v.to_lowercase().as_str(), "1" | "true" | "yes")
+}
+
+/// `X-ACIP-Allow-Tools`: true only if every value says so.
+pub fn allow_tools(headers: &HeaderMap) -> bool {
+    let vals = values(headers, ALLOW_TOOLS);
+    !vals.is_empty() && vals.iter().all(|v| truthy(v))
+}
+
+impl HeaderRules {
+    /// Policy named by `X-ACIP-Policy` (`default` when absent). Repeated values resolve to
+    /// the most restrictive ranked policy; values that cannot be ranked are returned joined,
+    /// which no policy matches.
+    pub fn policy_name(&self, headers: &HeaderMap) -> String {
+        let vals = values(headers, POLICY);
+        match vals.as_slice() {
+            [] => "default".to_string(),
+            [one] => one.clone(),
+            _ => self
+                .strictest_policy(&vals)
+                .unwrap_or_else(|| vals.join(", ")),
+        }
+    }
+
+    fn strictest_policy(&self, vals: &[String]) -> Option<String> {
+        let rank = |v: &String| self.policy_ranking.iter().position(|p| p == v);
+        let mut best: Option<(usize, &String)> = None;
+        for v in vals {
+            let r = rank(v)?;
+            if best.is_none_or(|(b, _)| r < b) {
+                best = Some((r, v));
+            }
+        }
+        best.map(|(_, v)| v.clone())
+    }
+
+    /// The most restrictive of `vals` for `name`, if `name` is security-relevant and the
+    /// values can be ordered.
+    fn strictest(&self, name: &str, vals: &[String]) -> Option<String> {
+        match name {
+            ALLOW_TOOLS => Some(vals.iter().all(|v| truthy(v)).to_string()),
+            POLICY => self.strictest_policy(vals),
+            _ => None,
+        }
+    }
+
+    /// Check every ACIP header of a request. Returns the conflicts resolved under
+    /// `use_strictest` (empty when each header has at most one value).
+    pub fn check(&self, headers: &HeaderMap) -> Result<Vec<HeaderConflict>, DuplicateHeader> {
+        let mut names: Vec<&str> = headers
+            .keys()
+            .map(|k| k.as_str())
+            .filter(|k| k.starts_with(PREFIX) && *k != TOKEN)
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut conflicts = Vec::new();
+        for name in names {
+            let vals = values(headers, name);
+            if vals.len() < 2 {
+                continue;
+            }
+            let resolved = match self.duplicates {
+                DuplicateHeaders::Reject => None,
+                DuplicateHeaders::UseStrictest => self.strictest(name, &vals),
+            };
+            let Some(resolved) = resolved else {
+                return Err(DuplicateHeader {
+                    header: name.to_string(),
+                    count: vals.len(),
+                });
+            };
+            conflicts.push(HeaderConflict {
+                header: name.to_string(),
+                values: vals,
+                resolved,
+            });
+        }
+        Ok(conflicts)
+    }
+}
+
+/// Refuse requests to `router` whose ACIP headers `rules` cannot settle, after moving
+/// deprecated headers onto their replacements.
+pub fn reject_duplicates<S>(router: Router<S>, rules: Arc<HeaderRules>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(from_fn_with_state(rules, duplicate_header_middleware))
+}
+
+/// Move the deprecated headers in `headers` onto their replacements (a replacement that is
+/// already present wins); the answer to send instead once one is past its sunset.
+#[allow(clippy::result_large_err)]
+fn translate_deprecated(
+    registry: &Registry,
+    headers: &mut HeaderMap,
+) -> Result<Vec<DeprecationUse>, Response> {
+    let mut uses = vec![];
+    for d in registry.entries(Form::Header) {
+        let values: Vec<HeaderValue> = headers.get_all(d.old).iter().cloned().collect();
+        if values.is_empty() {
+            continue;
+        }
+        let used = registry.check(d).map_err(|_| {
+            introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "removed_header",
+                json!({"header": d.old, "replacement": d.replacement, "removed_in": d.sunset}),
+            )
+            .into_response()
+        })?;
+        deprecations::record(&used);
+        headers.remove(d.old);
+        let Ok(replacement) = HeaderName::try_from(d.replacement) else {
+            continue;
+        };
+        if !headers.contains_key(&replacement) {
+            for v in values {
+                let v = match d.translate {
+                    Some(f) => f(toml::Value::String(
+                        String::from_utf8_lossy(v.as_bytes()).into_owned(),
+                    ))
+                    .ok()
+                    .and_then(|t| t.as_str().and_then(|t| HeaderValue::from_str(t).ok())),
+                    None => Some(v),
+                };
+                if let Some(v) = v {
+                    headers.append(replacement.clone(), v);
+                }
+            }
+        }
+        uses.push(used);
+    }
+    Ok(uses)
+}
+
+async fn duplicate_header_middleware(
+    State(rules): State<Arc<HeaderRules>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let deprecated = match translate_deprecated(&rules.deprecations, req.headers_mut()) {
+        Ok(uses) => uses,
+        Err(resp) => return resp,
+    };
+    let outcome = rules.check(req.headers());
+    let duplicates: Vec<(String, usize, Option<String>)> = match &outcome {
+        Ok(conflicts) => conflicts
+            .iter()
+            .map(|c| (c.header.clone(), c.values.len(), Some(c.resolved.clone())))
+            .collect(),
+        Err(e) => vec![(e.header.clone(), e.count, None)],
+    };
+
+    // A duplicated ACIP header is suspicious in itself: record who sent it.
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.to_string());
+    let headers = req.headers();
+    let text = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    for (header, count, resolved) in &duplicates {
+        tracing::warn!(
+            header,
+            count,
+            resolved,
+            peer = peer.as_deref().unwrap_or("unknown"),
+            forwarded_for = text("x-forwarded-for"),
+            user_agent = text("user-agent"),
+            actor = req.extensions().get::<Actor>().map(|a| a.name.as_str()),
+            method = %req.method(),
+            path = req.uri().path(),
+            "duplicate ACIP header"
+        );
+    }
+
+    let mut resp = match outcome {
+        Ok(_) => next.run(req).await,
+        Err(e) => e.into_response(),
+    };
+    for used in deprecated {
+        let marker = format!("{}={}", used.old, used.replacement);
+        if let Ok(v) = HeaderValue::from_str(&marker) {
+            resp.headers_mut().append(deprecations::RESPONSE_HEADER, v);
+        }
+    }
+    resp
+}
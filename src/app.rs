@@ -1,35 +1,203 @@
-use crate::{routes, state, token_auth};
-use axum::{extract::DefaultBodyLimit, routing::get, Router};
+use crate::token_auth::{Scope, TokenSet};
+use crate::{
+    acip_headers, cache_bypass, capabilities, drain, experiments, feeds, hashing, incidents, jobs,
+    read_only, redact, routes, slow_requests, state, stats_aggregate, token_auth, uploads,
+};
+use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{get, post, put},
+    Router,
+};
 use std::sync::Arc;
 
+/// Largest request body accepted on `/v1/acip/*` JSON routes (JSON + base64 overhead).
+pub const MAX_REQUEST_BODY_BYTES: usize = 1_500_000;
+
 pub async fn health() -> &'static str {
     "ok"
 }
 
+/// Build the main Axum router with the single legacy token (all scopes), or none.
+pub fn build_router(
+    state: Arc<state::AppState>,
+    token: Option<String>,
+    extra_protected: Router<Arc<state::AppState>>,
+) -> Router {
+    let token = token.map(crate::secrets::Secret::new);
+    build_router_with_tokens(state, TokenSet::legacy(token), extra_protected)
+}
+
 /// Build the main Axum router.
 ///
-/// - `/health` is always unprotected.
+/// - `/health`, `/health/live` and the readiness probes are always unprotected.
 /// - All `/v1/acip/*` routes are placed behind token auth (if enabled) and a body limit.
-pub fn build_router(
+/// - Read-only routes need the `read` scope; feed refreshes need `reputation_admin`,
+///   aggregate stats `platform_admin`, slow request timings and incident views `support`,
+///   policy experiments `policy_admin`.
+/// - `extra_protected` routes and the resumable upload routes take new work, need the `ingest`
+///   scope and are gated by the maintenance drain. Polling an async job also needs `ingest`
+///   but is not new work, so it stays available while draining.
+/// - `/v1/acip/admin/*` routes are refused unless a token is configured, and each needs its
+///   own scope.
+/// - With `server.read_only`, the ingest, upload, feed refresh, drain and resume routes answer
+///   `403 read_only_mode`.
+/// - Origins listed in `[cors]` may call the read, ingest, upload and job routes from a
+///   browser; see [`crate::cors`].
+/// - `X-ACIP-Force-Timing` and `X-ACIP-Bypass-Cache` on an ingest route need the `support`
+///   scope as well.
+/// - A repeated `X-ACIP-*` header is refused (`400 duplicate_header`) or, in `use_strictest`
+///   mode, resolved to its most restrictive value; see [`acip_headers`].
+/// - Every response, including errors, passes through the output redaction layer.
+pub fn build_router_with_tokens(
     state: Arc<state::AppState>,
-    token: Option<String>,
+    tokens: TokenSet,
     extra_protected: Router<Arc<state::AppState>>,
 ) -> Router {
-    // Apply token auth and body size limits to protected routes.
-    let protected = token_auth::with_token_auth(
+    let tokens = Arc::new(tokens);
+
+    let read = token_auth::require_scope(
         Router::new()
             .route("/v1/acip/schema", get(routes::get_schema))
+            .route("/v1/acip/capabilities", get(capabilities::get_capabilities))
             .route("/v1/acip/policies", get(routes::list_policies))
             .route("/v1/acip/policy", get(routes::get_policy))
             .route("/v1/acip/status", get(crate::status::get_status))
-            .merge(extra_protected)
-            // Limit request bodies (JSON + base64) to reduce DoS risk.
-            .layer(DefaultBodyLimit::max(1_500_000)),
-        token,
+            .route("/v1/acip/reputation", get(routes::get_reputation))
+            .route("/v1/acip/reputation/records", get(routes::list_reputation))
+            .route("/v1/acip/stats", get(routes::get_stats)),
+        Scope::Read,
+    );
+    let reputation_admin = token_auth::require_scope(
+        read_only::reject_writes(
+            Router::new().route("/v1/acip/feeds/:name/refresh", post(feeds::post_refresh)),
+            state.read_only,
+        ),
+        Scope::ReputationAdmin,
+    );
+    let platform_admin = token_auth::require_scope(
+        Router::new().route(
+            "/v1/acip/stats/aggregate",
+            get(stats_aggregate::get_aggregate),
+        ),
+        Scope::PlatformAdmin,
+    );
+    let policy_admin = token_auth::require_scope(
+        Router::new().route(
+            "/v1/acip/experiments",
+            get(experiments::get_experiments).post(experiments::post_experiment),
+        ),
+        Scope::PolicyAdmin,
+    );
+    let support = token_auth::require_scope(
+        Router::new()
+            .route(
+                "/v1/acip/slow_requests",
+                get(slow_requests::get_slow_requests),
+            )
+            .route(
+                "/v1/acip/incidents/:request_id",
+                get(incidents::get_incident),
+            ),
+        Scope::Support,
+    );
+    // Chunk bodies are raw bytes and may exceed the JSON body limit below.
+    let chunk_limit = state.uploads.settings().chunk_bytes as usize + 64 * 1024;
+    let uploads = Router::new()
+        .route("/v1/acip/uploads", post(uploads::post_upload))
+        .route("/v1/acip/uploads/:id", get(uploads::get_upload))
+        .route(
+            "/v1/acip/uploads/:id/chunks/:n",
+            put(uploads::put_chunk).layer(DefaultBodyLimit::max(chunk_limit)),
+        )
+        .route(
+            "/v1/acip/uploads/:id/complete",
+            post(uploads::post_complete),
+        );
+    // ACIP_UPLOAD_MAX_SESSIONS=0 turns resumable uploads off.
+    let extra_protected = if state.uploads.settings().max_sessions > 0 {
+        extra_protected.merge(uploads)
+    } else {
+        extra_protected
+    };
+    let ingest = token_auth::require_scope(
+        read_only::reject_writes(
+            cache_bypass::require_bypass_scope(slow_requests::require_force_scope(
+                extra_protected.layer(middleware::from_fn_with_state(
+                    state.drain.clone(),
+                    drain::gate_new_work,
+                )),
+            )),
+            state.read_only,
+        ),
+        Scope::Ingest,
+    );
+    let job_polling = token_auth::require_scope(
+        read_only::reject_writes(
+            Router::new().route("/v1/acip/jobs/:id", get(jobs::get_job)),
+            state.read_only,
+        ),
+        Scope::Ingest,
+    );
+
+    // Apply token auth and body size limits to protected routes.
+    let protect = |routes: Router<Arc<state::AppState>>| {
+        token_auth::with_token_auth(
+            acip_headers::reject_duplicates(
+                // Limit request bodies (JSON + base64) to reduce DoS risk.
+                routes.layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_BYTES)),
+                state.header_rules.clone(),
+            ),
+            tokens.clone(),
+        )
+    };
+    // Browser clients on a `[cors]` origin may call these; the preflight is answered before
+    // token auth, which it carries no token for.
+    let browser = protect(read.merge(ingest).merge(job_polling));
+    let browser = match state.cors.layer() {
+        Some(cors) => browser.layer(cors),
+        None => browser,
+    };
+    let protected = protect(
+        reputation_admin
+            .merge(platform_admin)
+            .merge(policy_admin)
+            .merge(support),
+    )
+    .merge(browser);
+    let admin = token_auth::with_admin_token_auth(
+        acip_headers::reject_duplicates(
+            token_auth::require_scope(
+                read_only::reject_writes(
+                    Router::new()
+                        .route("/v1/acip/admin/drain", post(drain::post_drain))
+                        .route("/v1/acip/admin/resume", post(drain::post_resume)),
+                    state.read_only,
+                ),
+                Scope::Drain,
+            )
+            .merge(token_auth::require_scope(
+                Router::new()
+                    .route("/v1/acip/admin/incidents/check", get(incidents::get_check))
+                    .route("/v1/acip/admin/hashing/lookup", get(hashing::get_lookup)),
+                Scope::Support,
+            )),
+            state.header_rules.clone(),
+        ),
+        tokens,
     );
 
+    let redaction = state.redaction.clone();
     Router::new()
         .route("/health", get(health))
+        .route("/health/live", get(health))
+        .route("/health/ready", get(drain::get_ready))
+        .route("/ready", get(drain::get_ready))
         .merge(protected)
+        .merge(admin)
+        .layer(middleware::from_fn_with_state(
+            redaction,
+            redact::redact_response,
+        ))
         .with_state(state)
 }
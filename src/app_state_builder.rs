@@ -1,7 +1,20 @@
-use crate::{secrets, state};
+//! [`AppStateBuilder`]: the one way the server and the tests assemble an [`AppState`].
+//!
+//! The required inputs are the resolved config and the secret store. Every component can be
+//! overridden; the rest are built from the config, in memory, on the builder's clock. Stores
+//! that live on disk (reputation and stats files, the hashing salt) are only ever passed in.
+//!
+//! [`AppStateBuilder::build`] checks the components against each other and reports every
+//! violation at once ([`BuildError`]).
+
+use crate::config::Config;
+use crate::model_pinning::{LogNotifier, ModelVersionMonitor};
+use crate::reputation::{Clock, SystemClock};
+use crate::state::{self, AppState};
+use crate::{secrets, server_config, startup, token_auth};
 use anyhow::Result;
 use reqwest::Client;
-use std::{sync::Arc, time::Duration};
+use std::{fmt, sync::Arc, time::Duration};
 
 /// Build a reqwest client with sane defaults for ACIP.
 pub fn build_http_client() -> Result<Client> {
@@ -11,23 +24,784 @@ pub fn build_http_client() -> Result<Client> {
         .build()?)
 }
 
-/// Build the shared AppState.
-///
-/// This is a small helper to keep `main.rs` focused on config/CLI parsing and server wiring.
-pub fn build_app_state(
-    policy: state::Policy,
-    normalize: state::NormalizeSettings,
-    http: Client,
+/// Everything that kept a state from being built.
+#[derive(Debug, thiserror::Error)]
+pub struct BuildError {
+    pub violations: Vec<String>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid app state ({} problems)", self.violations.len())?;
+        for v in &self.violations {
+            write!(f, "\n  - {v}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Assembles an [`AppState`]; see the module docs.
+pub struct AppStateBuilder {
+    config: Option<Config>,
     secrets: Arc<dyn secrets::SecretStore>,
-    policies: crate::policy_store::PolicyStore,
-    reputation: Arc<dyn crate::reputation::ReputationStore>,
-) -> Arc<state::AppState> {
-    Arc::new(state::AppState {
-        policy,
-        normalize,
-        http,
-        secrets,
-        policies,
-        reputation,
-    })
+    clock: Arc<dyn Clock>,
+    token_required: bool,
+    read_only: Option<bool>,
+    policy: Option<state::Policy>,
+    normalize: Option<state::NormalizeSettings>,
+    http: Option<Client>,
+    policies: Option<crate::policy_store::PolicyStore>,
+    reputation: Option<Arc<dyn crate::reputation::ReputationStore>>,
+    reputation_thresholds: Option<crate::reputation_policy::ReputationThresholds>,
+    stats: Option<Arc<crate::stats::DecisionStats>>,
+    verdicts: Option<Arc<crate::verdicts::VerdictHistory>>,
+    redaction: Option<Arc<crate::redact::Redaction>>,
+    drain: Option<Arc<crate::drain::DrainControl>>,
+    tmp: Option<Arc<crate::tmpdir::TmpDirManager>>,
+    uploads: Option<Arc<crate::uploads::UploadStore>>,
+    model_versions: Option<Arc<ModelVersionMonitor>>,
+    loop_guard: Option<Arc<crate::loop_guard::LoopGuard>>,
+    feeds: Option<Arc<crate::feeds::FeedRegistry>>,
+    jobs: Option<Arc<crate::jobs::JobStore>>,
+    header_rules: Option<Arc<crate::acip_headers::HeaderRules>>,
+    slow_requests: Option<Arc<crate::slow_requests::SlowRequestLog>>,
+    content_types: Option<Arc<crate::content_types::ContentTypeRules>>,
+    siem: Option<Arc<crate::siem::SiemExport>>,
+    patterns: Option<Arc<crate::patterns::PatternPack>>,
+    incidents: Option<Arc<crate::incidents::IncidentLog>>,
+    model_override: Option<Arc<dyn crate::sentry::ModelClient>>,
+    telemetry: Option<Arc<crate::telemetry::Telemetry>>,
+    disconnects: Option<Arc<crate::disconnect::Disconnects>>,
+    hashing: Option<Arc<crate::hashing::IdHasher>>,
+    blocking: Option<Arc<crate::blocking::BlockingPool>>,
+    experiments: Option<Arc<crate::experiments::ExperimentRegistry>>,
+    extractor_health: Option<Arc<crate::extractor_health::ExtractorHealth>>,
+    notify: Option<Arc<crate::notify::Notifier>>,
+    cors: Option<Arc<crate::cors::CorsPolicy>>,
+    environment: Option<Arc<crate::environment::Report>>,
+    chunks: Option<Arc<crate::chunking::ChunkIndex>>,
+    probes: Option<Arc<crate::probing::Prober>>,
+    remediation: Option<Arc<crate::remediation::Remediation>>,
+    risk_headers: Option<crate::risk_headers::RiskHeaders>,
+}
+
+impl AppStateBuilder {
+    pub fn new(config: Option<Config>, secrets: Arc<dyn secrets::SecretStore>) -> Self {
+        Self {
+            config,
+            secrets,
+            clock: Arc::new(SystemClock),
+            token_required: false,
+            read_only: None,
+            policy: None,
+            normalize: None,
+            http: None,
+            policies: None,
+            reputation: None,
+            reputation_thresholds: None,
+            stats: None,
+            verdicts: None,
+            redaction: None,
+            drain: None,
+            tmp: None,
+            uploads: None,
+            model_versions: None,
+            loop_guard: None,
+            feeds: None,
+            jobs: None,
+            header_rules: None,
+            slow_requests: None,
+            content_types: None,
+            siem: None,
+            patterns: None,
+            incidents: None,
+            model_override: None,
+            telemetry: None,
+            disconnects: None,
+            hashing: None,
+            blocking: None,
+            experiments: None,
+            extractor_health: None,
+            notify: None,
+            cors: None,
+            environment: None,
+            chunks: None,
+            probes: None,
+            remediation: None,
+            risk_headers: None,
+        }
+    }
+
+    /// No config, secrets from the environment, and a `default` policy with the built-in
+    /// models instead of the env-configured ones.
+    pub fn for_tests() -> Self {
+        let mut policies = std::collections::BTreeMap::new();
+        policies.insert(
+            "default".to_string(),
+            crate::model_policy::PolicyConfig::default(),
+        );
+        Self::new(None, Arc::new(secrets::EnvStore)).with_policies(
+            crate::policy_store::PolicyStore::from_file(crate::policy_store::PoliciesFile {
+                policies,
+            }),
+        )
+    }
+
+    /// Time source of the components built here.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Routes need a token; [`Self::build_with_tokens`] resolves them from the secret store.
+    pub fn with_token_required(mut self, required: bool) -> Self {
+        self.token_required = required;
+        self
+    }
+
+    /// Overrides `server.read_only`.
+    pub fn with_read_only(mut self, read_only: bool) -> Self {
+        self.read_only = Some(read_only);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: state::Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    pub fn with_normalize(mut self, normalize: state::NormalizeSettings) -> Self {
+        self.normalize = Some(normalize);
+        self
+    }
+
+    pub fn with_http(mut self, http: Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    pub fn with_policies(mut self, policies: crate::policy_store::PolicyStore) -> Self {
+        self.policies = Some(policies);
+        self
+    }
+
+    pub fn with_reputation(
+        mut self,
+        reputation: Arc<dyn crate::reputation::ReputationStore>,
+    ) -> Self {
+        self.reputation = Some(reputation);
+        self
+    }
+
+    pub fn with_reputation_thresholds(
+        mut self,
+        thresholds: crate::reputation_policy::ReputationThresholds,
+    ) -> Self {
+        self.reputation_thresholds = Some(thresholds);
+        self
+    }
+
+    pub fn with_stats(mut self, stats: Arc<crate::stats::DecisionStats>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    pub fn with_verdicts(mut self, verdicts: Arc<crate::verdicts::VerdictHistory>) -> Self {
+        self.verdicts = Some(verdicts);
+        self
+    }
+
+    pub fn with_redaction(mut self, redaction: Arc<crate::redact::Redaction>) -> Self {
+        self.redaction = Some(redaction);
+        self
+    }
+
+    pub fn with_drain(mut self, drain: Arc<crate::drain::DrainControl>) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    pub fn with_tmp(mut self, tmp: Arc<crate::tmpdir::TmpDirManager>) -> Self {
+        self.tmp = Some(tmp);
+        self
+    }
+
+    pub fn with_uploads(mut self, uploads: Arc<crate::uploads::UploadStore>) -> Self {
+        self.uploads = Some(uploads);
+        self
+    }
+
+    pub fn with_model_versions(mut self, monitor: Arc<ModelVersionMonitor>) -> Self {
+        self.model_versions = Some(monitor);
+        self
+    }
+
+    pub fn with_loop_guard(mut self, guard: Arc<crate::loop_guard::LoopGuard>) -> Self {
+        self.loop_guard = Some(guard);
+        self
+    }
+
+    pub fn with_feeds(mut self, feeds: Arc<crate::feeds::FeedRegistry>) -> Self {
+        self.feeds = Some(feeds);
+        self
+    }
+
+    pub fn with_jobs(mut self, jobs: Arc<crate::jobs::JobStore>) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    pub fn with_header_rules(mut self, rules: Arc<crate::acip_headers::HeaderRules>) -> Self {
+        self.header_rules = Some(rules);
+        self
+    }
+
+    pub fn with_slow_requests(mut self, log: Arc<crate::slow_requests::SlowRequestLog>) -> Self {
+        self.slow_requests = Some(log);
+        self
+    }
+
+    pub fn with_content_types(
+        mut self,
+        rules: Arc<crate::content_types::ContentTypeRules>,
+    ) -> Self {
+        self.content_types = Some(rules);
+        self
+    }
+
+    pub fn with_siem(mut self, siem: Arc<crate::siem::SiemExport>) -> Self {
+        self.siem = Some(siem);
+        self
+    }
+
+    pub fn with_patterns(mut self, patterns: Arc<crate::patterns::PatternPack>) -> Self {
+        self.patterns = Some(patterns);
+        self
+    }
+
+    pub fn with_incidents(mut self, incidents: Arc<crate::incidents::IncidentLog>) -> Self {
+        self.incidents = Some(incidents);
+        self
+    }
+
+    /// Answer every model request with `model` (see [`AppState::model_override`]).
+    pub fn with_model(mut self, model: Arc<dyn crate::sentry::ModelClient>) -> Self {
+        self.model_override = Some(model);
+        self
+    }
+
+    pub fn with_telemetry(mut self, telemetry: Arc<crate::telemetry::Telemetry>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    pub fn with_disconnects(mut self, disconnects: Arc<crate::disconnect::Disconnects>) -> Self {
+        self.disconnects = Some(disconnects);
+        self
+    }
+
+    pub fn with_hashing(mut self, hashing: Arc<crate::hashing::IdHasher>) -> Self {
+        self.hashing = Some(hashing);
+        self
+    }
+
+    pub fn with_blocking(mut self, blocking: Arc<crate::blocking::BlockingPool>) -> Self {
+        self.blocking = Some(blocking);
+        self
+    }
+
+    pub fn with_experiments(
+        mut self,
+        experiments: Arc<crate::experiments::ExperimentRegistry>,
+    ) -> Self {
+        self.experiments = Some(experiments);
+        self
+    }
+
+    pub fn with_extractor_health(
+        mut self,
+        health: Arc<crate::extractor_health::ExtractorHealth>,
+    ) -> Self {
+        self.extractor_health = Some(health);
+        self
+    }
+
+    pub fn with_notify(mut self, notify: Arc<crate::notify::Notifier>) -> Self {
+        self.notify = Some(notify);
+        self
+    }
+
+    pub fn with_cors(mut self, cors: Arc<crate::cors::CorsPolicy>) -> Self {
+        self.cors = Some(cors);
+        self
+    }
+
+    pub fn with_environment(mut self, report: Arc<crate::environment::Report>) -> Self {
+        self.environment = Some(report);
+        self
+    }
+
+    pub fn with_chunks(mut self, chunks: Arc<crate::chunking::ChunkIndex>) -> Self {
+        self.chunks = Some(chunks);
+        self
+    }
+
+    pub fn with_probes(mut self, probes: Arc<crate::probing::Prober>) -> Self {
+        self.probes = Some(probes);
+        self
+    }
+
+    pub fn with_remediation(mut self, remediation: Arc<crate::remediation::Remediation>) -> Self {
+        self.remediation = Some(remediation);
+        self
+    }
+
+    pub fn with_risk_headers(mut self, headers: crate::risk_headers::RiskHeaders) -> Self {
+        self.risk_headers = Some(headers);
+        self
+    }
+
+    pub fn build(self) -> Result<Arc<AppState>, BuildError> {
+        self.build_with_tokens().map(|(state, _)| state)
+    }
+
+    /// The state and the tokens its routes accept.
+    pub fn build_with_tokens(self) -> Result<(Arc<AppState>, token_auth::TokenSet), BuildError> {
+        let mut errors = Errors::default();
+        let cfg = self.config.as_ref();
+        let clock = self.clock.clone();
+        let read_only = self
+            .read_only
+            .unwrap_or_else(|| server_config::read_only(cfg));
+
+        let tokens = errors.take(
+            "tokens",
+            startup::resolve_tokens(
+                self.token_required,
+                &self.secrets,
+                &server_config::token_env(cfg),
+                &server_config::named_tokens(cfg),
+            ),
+        );
+        let http = match self.http {
+            Some(h) => Some(h),
+            None => errors.take("http client", build_http_client()),
+        };
+        let egress_http = errors.take("egress client", crate::egress::client());
+        let policies = match self.policies {
+            Some(p) => Some(p),
+            None => errors.take("policies", startup::build_policy_store(&self.secrets, None)),
+        };
+        let regex_limits =
+            crate::regex_guard::RegexLimits::from_config(cfg.and_then(|c| c.regex.as_ref()));
+        let reputation = self.reputation.unwrap_or_else(|| {
+            let rep = cfg.and_then(|c| c.reputation.as_ref());
+            let store = crate::reputation::InMemoryReputationStore::new().with_limits(
+                crate::reputation_limits::CardinalityGuard::new(
+                    crate::reputation_limits::CardinalitySettings::from_config(rep),
+                    crate::reputation_policy::ReputationThresholds::from_config(rep),
+                ),
+            );
+            if read_only {
+                Arc::new(crate::reputation::ReadOnlyReputationStore(store))
+            } else {
+                Arc::new(store)
+            }
+        });
+        let stats = self.stats.unwrap_or_else(|| {
+            let stats = crate::stats::DecisionStats::in_memory(
+                crate::stats::StatsSettings::from_env(),
+                clock.clone(),
+            );
+            Arc::new(if read_only {
+                stats.into_read_only()
+            } else {
+                stats
+            })
+        });
+        let slow_requests = self.slow_requests.unwrap_or_else(|| {
+            let log = crate::slow_requests::SlowRequestLog::in_memory(
+                crate::slow_requests::SlowRequestSettings::from_env(),
+                clock.clone(),
+            );
+            Arc::new(if read_only { log.into_read_only() } else { log })
+        });
+        let hashing = self.hashing.unwrap_or_default();
+        let redaction = match self.redaction {
+            Some(r) => Some(r),
+            None => {
+                let r = crate::redact::Redaction::default();
+                errors
+                    .take(
+                        "redaction",
+                        r.reload_with_limits(cfg.and_then(|c| c.redaction.as_ref()), &regex_limits),
+                    )
+                    .map(|()| Arc::new(r))
+            }
+        };
+        let tmp = self.tmp.unwrap_or_else(|| {
+            Arc::new(crate::tmpdir::TmpDirManager::new(
+                crate::tmpdir::TmpDirSettings::from_env(),
+            ))
+        });
+        let uploads = self.uploads.unwrap_or_else(|| {
+            Arc::new(crate::uploads::UploadStore::new(
+                crate::uploads::UploadSettings::from_env(),
+                tmp.clone(),
+                clock.clone(),
+            ))
+        });
+        let loop_guard = match self.loop_guard {
+            Some(g) => Some(g),
+            None => errors
+                .take(
+                    "loop_protection",
+                    crate::loop_guard::LoopGuard::new(
+                        crate::loop_guard::LoopSettings::from_config(
+                            cfg.and_then(|c| c.loop_protection.as_ref()),
+                        ),
+                    ),
+                )
+                .map(Arc::new),
+        };
+        let patterns = match self.patterns {
+            Some(p) => Some(p),
+            None => errors
+                .take(
+                    "patterns",
+                    crate::patterns::PatternPack::new(
+                        cfg.map(|c| c.patterns.as_slice()).unwrap_or_default(),
+                        &regex_limits,
+                        clock.clone(),
+                    ),
+                )
+                .map(Arc::new),
+        };
+        let siem = match self.siem {
+            Some(s) => Some(s),
+            None => errors
+                .take(
+                    "siem",
+                    crate::siem::SiemExport::from_config(cfg.and_then(|c| c.siem.as_ref())),
+                )
+                .map(Arc::new),
+        };
+        let cors = match self.cors {
+            Some(c) => Some(c),
+            None => errors
+                .take(
+                    "cors",
+                    crate::cors::CorsPolicy::from_config(cfg.and_then(|c| c.cors.as_ref())),
+                )
+                .map(Arc::new),
+        };
+        let chunks = match self.chunks {
+            Some(c) => Some(c),
+            None => errors
+                .take(
+                    "chunking",
+                    crate::chunking::ChunkingSettings::from_config(
+                        cfg.and_then(|c| c.chunking.as_ref()),
+                    ),
+                )
+                .map(|s| Arc::new(crate::chunking::ChunkIndex::new(s))),
+        };
+        let probes = match self.probes {
+            Some(p) => Some(p),
+            None => errors
+                .take(
+                    "probing",
+                    crate::probing::Prober::from_config(cfg.and_then(|c| c.probing.as_ref())),
+                )
+                .map(|p| Arc::new(p.with_clock(clock.clone()))),
+        };
+        let remediation = match self.remediation {
+            Some(r) => Some(r),
+            None => {
+                let pattern_ids: Vec<String> = cfg
+                    .map(|c| c.patterns.iter().map(|p| p.id.clone()).collect())
+                    .unwrap_or_default();
+                errors
+                    .take(
+                        "remediation",
+                        crate::remediation::Remediation::from_config(
+                            cfg.and_then(|c| c.remediation.as_ref()),
+                            &pattern_ids,
+                        ),
+                    )
+                    .map(Arc::new)
+            }
+        };
+        let risk_headers = match self.risk_headers {
+            Some(h) => Some(h),
+            None => errors.take(
+                "response_headers",
+                crate::risk_headers::RiskHeaders::from_config(
+                    cfg.and_then(|c| c.response_headers.as_ref()),
+                ),
+            ),
+        };
+        // Components that need an HTTP client.
+        let (mut telemetry, mut feeds, mut notify, mut model_versions) = (None, None, None, None);
+        if let (Some(http), Some(egress_http)) = (&http, &egress_http) {
+            telemetry = match self.telemetry {
+                Some(t) => Some(t),
+                None => errors
+                    .take(
+                        "telemetry",
+                        crate::telemetry::Telemetry::from_config(
+                            cfg.and_then(|c| c.telemetry.as_ref()),
+                            http.clone(),
+                        ),
+                    )
+                    .map(Arc::new),
+            };
+            feeds = match (self.feeds, &telemetry) {
+                (Some(f), _) => Some(f),
+                (None, Some(telemetry)) => errors
+                    .take(
+                        "feeds",
+                        crate::feeds::FeedRegistry::from_config(
+                            cfg.map(|c| c.feeds.as_slice()).unwrap_or_default(),
+                            egress_http.clone(),
+                            clock.clone(),
+                        ),
+                    )
+                    .map(|f| Arc::new(f.with_telemetry(telemetry.as_ref().clone()))),
+                (None, None) => None,
+            };
+            notify = match self.notify {
+                Some(n) => Some(n),
+                None => errors
+                    .take(
+                        "notify",
+                        crate::notify::Notifier::from_config(
+                            cfg.and_then(|c| c.notify.as_ref()),
+                            egress_http.clone(),
+                        ),
+                    )
+                    .map(Arc::new),
+            };
+            model_versions = match self.model_versions {
+                Some(m) => Some(m),
+                None => errors
+                    .take(
+                        "model version webhook",
+                        crate::model_pinning::WebhookNotifier::from_env(egress_http.clone()),
+                    )
+                    .map(|webhook| {
+                        Arc::new(match webhook {
+                            Some(webhook) => ModelVersionMonitor::new(Arc::new(webhook)),
+                            None => ModelVersionMonitor::new(Arc::new(LogNotifier)),
+                        })
+                    }),
+            };
+        }
+
+        // Cross-component invariants.
+        if read_only != reputation.is_read_only() {
+            errors.push(store_mismatch(
+                "reputation",
+                read_only,
+                reputation.storage(),
+            ));
+        }
+        if read_only != stats.is_read_only() {
+            errors.push(store_mismatch("stats", read_only, stats.storage()));
+        }
+        if read_only != slow_requests.is_read_only() {
+            errors.push(store_mismatch(
+                "slow request",
+                read_only,
+                slow_requests.storage(),
+            ));
+        }
+        if let Some(siem) = &siem {
+            if cfg.is_some_and(|c| c.siem.is_some()) && !siem.is_enabled() {
+                errors.push("[siem] is configured but the SIEM export has no destination".into());
+            }
+        }
+        if let Some(notify) = &notify {
+            if cfg.is_some_and(|c| c.notify.is_some()) && !notify.is_enabled() {
+                errors.push("[notify] is configured but the notifier has no webhook".into());
+            }
+        }
+        if let Some(telemetry) = &telemetry {
+            let endpoint = cfg
+                .and_then(|c| c.telemetry.as_ref())
+                .is_some_and(|t| t.otlp_endpoint.is_some());
+            if cfg!(feature = "otel") && endpoint && !telemetry.is_enabled() {
+                errors.push(
+                    "telemetry.otlp_endpoint is set but the span exporter is disabled".into(),
+                );
+            }
+        }
+        if let Some(policies) = &policies {
+            if let Some(feeds) = &feeds {
+                errors.take("policies", feeds.check_policy_refs(policies));
+            }
+            if let Some(remediation) = &remediation {
+                errors.take("policies", remediation.check_policy_refs(policies));
+            }
+        }
+
+        let (
+            Some(tokens),
+            Some(http),
+            Some(egress_http),
+            Some(policies),
+            Some(redaction),
+            Some(loop_guard),
+            Some(patterns),
+            Some(siem),
+            Some(cors),
+            Some(chunks),
+            Some(probes),
+            Some(remediation),
+            Some(risk_headers),
+            Some(telemetry),
+            Some(feeds),
+            Some(notify),
+            Some(model_versions),
+        ) = (
+            tokens,
+            http,
+            egress_http,
+            policies,
+            redaction,
+            loop_guard,
+            patterns,
+            siem,
+            cors,
+            chunks,
+            probes,
+            remediation,
+            risk_headers,
+            telemetry,
+            feeds,
+            notify,
+            model_versions,
+        )
+        else {
+            return Err(errors.into_error());
+        };
+        if !errors.0.is_empty() {
+            return Err(errors.into_error());
+        }
+
+        let state = Arc::new(AppState {
+            policy: self.policy.unwrap_or_else(|| {
+                let eff = server_config::effective_settings(&Default::default(), cfg);
+                state::Policy {
+                    head: eff.head,
+                    tail: eff.tail,
+                    full_if_lte: eff.full_if_lte,
+                }
+            }),
+            normalize: self.normalize.unwrap_or_else(|| {
+                state::NormalizeSettings::from_config(cfg.and_then(|c| c.normalize.as_ref()))
+            }),
+            secrets: self.secrets,
+            policies,
+            reputation,
+            reputation_thresholds: self.reputation_thresholds.unwrap_or_else(|| {
+                crate::reputation_policy::ReputationThresholds::from_config(
+                    cfg.and_then(|c| c.reputation.as_ref()),
+                )
+            }),
+            stats,
+            verdicts: self.verdicts.unwrap_or_else(|| {
+                Arc::new(crate::verdicts::VerdictHistory::default().with_hashing(hashing.clone()))
+            }),
+            redaction,
+            drain: self.drain.unwrap_or_default(),
+            tmp,
+            uploads,
+            model_versions,
+            loop_guard,
+            feeds,
+            read_only,
+            jobs: self.jobs.unwrap_or_else(|| {
+                Arc::new(crate::jobs::JobStore::new(
+                    crate::jobs::JobSettings::from_env(),
+                    clock.clone(),
+                ))
+            }),
+            header_rules: self
+                .header_rules
+                .unwrap_or_else(|| Arc::new(server_config::header_rules(cfg))),
+            slow_requests,
+            content_types: self.content_types.unwrap_or_else(|| {
+                Arc::new(crate::content_types::ContentTypeRules::from_config(
+                    cfg.and_then(|c| c.content_types.as_ref()),
+                ))
+            }),
+            siem,
+            patterns,
+            incidents: self.incidents.unwrap_or_default(),
+            model_override: self.model_override,
+            telemetry,
+            disconnects: self.disconnects.unwrap_or_else(|| {
+                Arc::new(crate::disconnect::Disconnects::new(
+                    crate::disconnect::OnClientDisconnect::from_config(cfg),
+                ))
+            }),
+            hashing,
+            blocking: self
+                .blocking
+                .unwrap_or_else(|| Arc::new(crate::blocking::BlockingPool::from_config(cfg))),
+            experiments: self.experiments.unwrap_or_default(),
+            extractor_health: self.extractor_health.unwrap_or_else(|| {
+                Arc::new(crate::extractor_health::ExtractorHealth::new(
+                    crate::extractor_health::BreakerSettings::from_env(),
+                    clock.clone(),
+                ))
+            }),
+            notify,
+            cors,
+            environment: self.environment.unwrap_or_default(),
+            chunks,
+            probes,
+            remediation,
+            risk_headers,
+            http,
+            egress_http,
+        });
+        Ok((state, tokens))
+    }
+}
+
+/// Violations collected while building.
+#[derive(Default)]
+struct Errors(Vec<String>);
+
+impl Errors {
+    fn push(&mut self, violation: String) {
+        self.0.push(violation);
+    }
+
+    fn take<T>(&mut self, what: &str, result: Result<T>) -> Option<T> {
+        result
+            .map_err(|e| self.0.push(format!("{what}: {e:#}")))
+            .ok()
+    }
+
+    fn into_error(self) -> BuildError {
+        BuildError { violations: self.0 }
+    }
+}
+
+fn store_mismatch(
+    name: &str,
+    read_only: bool,
+    storage: Option<crate::storage::StoreInfo>,
+) -> String {
+    match (read_only, storage) {
+        (true, _) => format!("server.read_only is set but the {name} store accepts writes"),
+        (false, Some(info)) => format!(
+            "the {name} store persists to {} but was opened read-only",
+            info.path
+        ),
+        (false, None) => format!("the {name} store is read-only but server.read_only is not set"),
+    }
 }
@@ -0,0 +1,170 @@
+//! Bounded base64 decoding for `bytes_b64` payloads.
+//!
+//! Callers send base64 produced by many different encoders, so decoding is deliberately
+//! tolerant of *formatting* (embedded whitespace, missing padding, standard vs urlsafe
+//! alphabet) and strict about *content*: anything that is not base64 is rejected with the
+//! byte offset of the first bad character, never silently misdecoded.
+//!
+//! Decoding is incremental and checks the size limit as output is produced, so the output
+//! buffer never grows beyond `max_bytes`.
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+/// Default cap on decoded `bytes_b64` payloads (~1.1MB).
+pub const DEFAULT_MAX_DECODED_BYTES: usize = 1_125_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alphabet {
+    Standard,
+    UrlSafe,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum B64Error {
+    #[error("invalid base64 character {byte:#04x} at offset {offset}")]
+    InvalidChar { offset: usize, byte: u8 },
+
+    #[error("base64 mixes standard and urlsafe alphabets at offset {offset}")]
+    MixedAlphabet { offset: usize },
+
+    #[error("truncated base64 input at offset {offset}")]
+    Truncated { offset: usize },
+
+    #[error("decoded payload exceeds {max_bytes} bytes")]
+    TooLarge { max_bytes: usize },
+}
+
+impl B64Error {
+    /// Byte offset into the encoded input of the first bad character, if any.
+    pub fn offset(&self) -> Option<usize> {
+        match self {
+            B64Error::InvalidChar { offset, .. }
+            | B64Error::MixedAlphabet { offset }
+            | B64Error::Truncated { offset } => Some(*offset),
+            B64Error::TooLarge { .. } => None,
+        }
+    }
+
+    /// Stable machine-readable reason.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            B64Error::InvalidChar { .. } => "invalid_character",
+            B64Error::MixedAlphabet { .. } => "mixed_alphabet",
+            B64Error::Truncated { .. } => "truncated",
+            B64Error::TooLarge { .. } => "too_large",
+        }
+    }
+}
+
+fn sextet(b: u8) -> Option<(u8, Option<Alphabet>)> {
+    match b {
+        b'A'..=b'Z' => Some((b - b'A', None)),
+        b'a'..=b'z' => Some((b - b'a' + 26, None)),
+        b'0'..=b'9' => Some((b - b'0' + 52, None)),
+        b'+' => Some((62, Some(Alphabet::Standard))),
+        b'/' => Some((63, Some(Alphabet::Standard))),
+        b'-' => Some((62, Some(Alphabet::UrlSafe))),
+        b'_' => Some((63, Some(Alphabet::UrlSafe))),
+        _ => None,
+    }
+}
+
+/// Encode bytes as padded standard base64 (what the sidecar expects on the wire).
+pub fn encode(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+/// Decode standard or urlsafe base64, padded or not, ignoring ASCII whitespace.
+///
+/// Fails with [`B64Error::TooLarge`] as soon as the decoded output would exceed `max_bytes`.
+pub fn decode_bounded(input: &str, max_bytes: usize) -> Result<Vec<u8>, B64Error> {
+    // Upper bound on the decoded size; whitespace and padding only make it smaller.
+    let estimate = input.len().div_ceil(4) * 3;
+    let mut out = Vec::with_capacity(estimate.min(max_bytes));
+
+    let mut alphabet: Option<Alphabet> = None;
+    let mut quad = [0u8; 4];
+    let mut n = 0usize;
+    let mut pad = 0usize;
+    let mut last_offset = 0usize;
+
+    fn emit(out: &mut Vec<u8>, bytes: &[u8], max_bytes: usize) -> Result<(), B64Error> {
+        if out.len() + bytes.len() > max_bytes {
+            return Err(B64Error::TooLarge { max_bytes });
+        }
+        out.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    for (offset, &b) in input.as_bytes().iter().enumerate() {
+        if b.is_ascii_whitespace() {
+            continue;
+        }
+
+        if b == b'=' {
+            // Padding may only complete a partial quad (2 or 3 sextets), up to 4 symbols.
+            if n < 2 || n + pad >= 4 {
+                return Err(B64Error::InvalidChar { offset, byte: b });
+            }
+            pad += 1;
+            continue;
+        }
+        if pad > 0 {
+            // Nothing but padding/whitespace may follow padding.
+            return Err(B64Error::InvalidChar { offset, byte: b });
+        }
+
+        let Some((v, alpha)) = sextet(b) else {
+            return Err(B64Error::InvalidChar { offset, byte: b });
+        };
+        if let Some(a) = alpha {
+            match alphabet {
+                None => alphabet = Some(a),
+                Some(seen) if seen != a => return Err(B64Error::MixedAlphabet { offset }),
+                Some(_) => {}
+            }
+        }
+
+        quad[n] = v;
+        n += 1;
+        last_offset = offset;
+        if n == 4 {
+            emit(
+                &mut out,
+                &[
+                    (quad[0] << 2) | (quad[1] >> 4),
+                    (quad[1] << 4) | (quad[2] >> 2),
+                    (quad[2] << 6) | quad[3],
+                ],
+                max_bytes,
+            )?;
+            n = 0;
+        }
+    }
+
+    if pad > 0 && n + pad != 4 {
+        return Err(B64Error::Truncated {
+            offset: input.len(),
+        });
+    }
+
+    match n {
+        0 => {}
+        1 => {
+            return Err(B64Error::Truncated {
+                offset: last_offset,
+            })
+        }
+        2 => emit(&mut out, &[(quad[0] << 2) | (quad[1] >> 4)], max_bytes)?,
+        _ => emit(
+            &mut out,
+            &[
+                (quad[0] << 2) | (quad[1] >> 4),
+                (quad[1] << 4) | (quad[2] >> 2),
+            ],
+            max_bytes,
+        )?,
+    }
+
+    Ok(out)
+}
@@ -1,9 +1,10 @@
 use anyhow::{Context, Result};
-use acip_sidecar::extract::{self, ExtractRequest, ExtractResponse, ExtractStats};
+use acip_sidecar::extract::{self, ExtractOutcome, ExtractRequest, ExtractResponse, ExtractStats};
 use std::{
     fs::OpenOptions,
     io::{Read, Write},
 };
+use zeroize::Zeroizing;
 
 fn write_response(out_path: Option<&str>, payload: &[u8]) -> Result<()> {
     if let Some(path) = out_path {
@@ -34,7 +35,8 @@ fn write_diag(err_path: Option<&str>, err: &anyhow::Error) {
 fn run(out_path: Option<&str>) -> Result<()> {
     // Protocol: first line is JSON request; remaining bytes are payload.
     let mut stdin = std::io::stdin();
-    let mut buf: Vec<u8> = vec![];
+    // The request line may carry a document password.
+    let mut buf: Zeroizing<Vec<u8>> = Zeroizing::new(vec![]);
     stdin.read_to_end(&mut buf).context("read stdin")?;
 
     let Some(pos) = buf.iter().position(|b| *b == b'\n') else {
@@ -75,6 +77,11 @@ fn run(out_path: Option<&str>) -> Result<()> {
         }
     }
 
+    // Hidden debug mode used by tests: break the output protocol in one specific way.
+    if let Ok(mode) = std::env::var("ACIP_EXTRACTOR_SELFTEST_FAIL") {
+        return selftest_fail(mode.trim(), &req, out_path);
+    }
+
     if std::env::var("ACIP_EXTRACTOR_SELFTEST_LARGE")
         .ok()
         .is_some_and(|v| v.trim() == "1")
@@ -92,18 +99,117 @@ fn run(out_path: Option<&str>) -> Result<()> {
                 ocr_used: false,
                 ocr_chars: 0,
             },
+            outcome: ExtractOutcome::Extracted,
         };
         let out = serde_json::to_vec(&resp).context("serialize response")?;
         write_response(out_path, &out)?;
         return Ok(());
     }
 
+    // Hidden debug mode used by the soak test: a helper slow enough to be killed mid-run.
+    if let Some(ms) = std::env::var("ACIP_EXTRACTOR_SELFTEST_DELAY_MS")
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+    {
+        std::thread::sleep(std::time::Duration::from_millis(ms));
+    }
+
+    // Hidden debug mode used by the soak test: PDFs without Poppler.
+    if matches!(req.kind, extract::ExtractKind::Pdf)
+        && std::env::var("ACIP_EXTRACTOR_SELFTEST_PDF")
+            .ok()
+            .is_some_and(|v| v.trim() == "1")
+    {
+        let resp = selftest_pdf(&req, payload);
+        let out = serde_json::to_vec(&resp).context("serialize response")?;
+        write_response(out_path, &out)?;
+        return Ok(());
+    }
+
     let resp = extract::extract(&req, payload).context("extract")?;
     let out = serde_json::to_vec(&resp).context("serialize response")?;
     write_response(out_path, &out)?;
     Ok(())
 }
 
+/// The "text layer" of a PDF in selftest mode: every line that is not a PDF comment.
+fn selftest_pdf(req: &ExtractRequest, payload: &[u8]) -> ExtractResponse {
+    let text: Vec<String> = String::from_utf8_lossy(payload)
+        .lines()
+        .filter(|l| !l.starts_with('%'))
+        .map(str::to_string)
+        .collect();
+    let text = text.join("\n");
+    ExtractResponse {
+        ok: true,
+        kind: req.kind.clone(),
+        warnings: Vec::new(),
+        stats: ExtractStats {
+            pages: Some(1),
+            text_chars: text.chars().count(),
+            ocr_used: false,
+            ocr_chars: 0,
+        },
+        text,
+        outcome: ExtractOutcome::Extracted,
+    }
+}
+
+/// Simulate one helper failure for `run_helper`'s classification tests.
+fn selftest_fail(mode: &str, req: &ExtractRequest, out_path: Option<&str>) -> Result<()> {
+    let valid = serde_json::to_vec(&ExtractResponse {
+        ok: true,
+        kind: req.kind.clone(),
+        text: "selftest".to_string(),
+        warnings: Vec::new(),
+        stats: ExtractStats::default(),
+        outcome: ExtractOutcome::Extracted,
+    })
+    .context("serialize response")?;
+    match mode {
+        "exit" => {
+            // More stderr than the parent keeps, ending with a recognisable line.
+            let mut stderr = std::io::stderr();
+            for i in 0..2000 {
+                let _ = writeln!(stderr, "selftest noise line {i}");
+            }
+            let _ = writeln!(stderr, "selftest_exit_marker");
+            std::process::exit(3);
+        }
+        "signal" => {
+            // What the OOM killer does.
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(libc::getpid(), libc::SIGKILL);
+            }
+            anyhow::bail!("selftest_signal_unavailable");
+        }
+        "hang" => {
+            std::thread::sleep(std::time::Duration::from_secs(30));
+            Ok(())
+        }
+        "empty" => write_response(out_path, b""),
+        "truncated" => write_response(out_path, &valid[..valid.len() / 2]),
+        "malformed" => {
+            let mut out = b"debug: request parsed\n".to_vec();
+            out.extend_from_slice(&valid);
+            write_response(out_path, &out)
+        }
+        "schema" => {
+            let mut v: serde_json::Value = serde_json::from_slice(&valid)?;
+            v["stats"]["ocr_used"] = serde_json::json!("no");
+            write_response(out_path, &serde_json::to_vec(&v)?)
+        }
+        "no_output" => {
+            if let Some(path) = out_path {
+                std::fs::remove_file(path).context("remove ACIP_EXTRACTOR_OUT")?;
+            }
+            Ok(())
+        }
+        other => anyhow::bail!("unknown ACIP_EXTRACTOR_SELFTEST_FAIL mode {other:?}"),
+    }
+}
+
 fn main() -> Result<()> {
     let out_path = std::env::var("ACIP_EXTRACTOR_OUT").ok();
     let err_path = std::env::var("ACIP_EXTRACTOR_ERR").ok();
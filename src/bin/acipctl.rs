@@ -1,28 +1,54 @@
-use acip_sidecar::config;
+use acip_sidecar::command_line::CommandLine;
+use acip_sidecar::{
+    b64, build_info, client, config, deprecations, jobs, patterns, regex_guard, storage,
+};
 use anyhow::{Context, Result};
-use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use serde_json::Value;
 use std::{
     fs,
     io::{self, Read, Write},
     path::PathBuf,
+    time::Duration,
 };
 
 /// acipctl — configure and exercise a running ACIP Sidecar.
 ///
 /// Designed to work even when the sidecar runs in Docker: this tool can
 /// generate/validate config files and can call the sidecar HTTP API.
+///
+/// Commands that call the sidecar exit 2 when it cannot be reached, 3 when it answers with an
+/// error status and 4 when its answer cannot be read; any other failure exits 1.
 #[derive(Debug, Parser)]
 #[command(name = "acipctl")]
-#[command(version)]
+#[command(version, disable_version_flag = true)]
 struct Cli {
+    /// Print version and build information (as JSON with --output json)
+    #[arg(long, short = 'V')]
+    version: bool,
+
     /// Base URL for the sidecar (used by commands that call the HTTP API)
     #[arg(long, default_value = "http://127.0.0.1:18795")]
     url: String,
 
+    /// Auth token sent as X-ACIP-Token (default: $ACIP_AUTH_TOKEN)
+    #[arg(long)]
+    token: Option<String>,
+
+    /// Policy to use, sent as X-ACIP-Policy on every call
+    #[arg(long, global = true)]
+    policy: Option<String>,
+
+    /// Output format; `json` prints results and failures as JSON on stdout
+    #[arg(long, global = true, value_enum, default_value_t = Output::Text)]
+    output: Output,
+
+    /// Do not warn when the sidecar's version is incompatible with this acipctl's
+    #[arg(long, global = true)]
+    no_version_check: bool,
+
     #[command(subcommand)]
-    cmd: Cmd,
+    cmd: Option<Cmd>,
 }
 
 #[derive(Debug, Subcommand)]
@@ -35,8 +61,142 @@ enum Cmd {
         cmd: ConfigCmd,
     },
 
-    /// GET /health
-    Health,
+    /// Check user regexes before deploying them.
+    Patterns {
+        #[command(subcommand)]
+        cmd: PatternsCmd,
+    },
+
+    /// Inspect the store files on disk.
+    Storage {
+        #[command(subcommand)]
+        cmd: StorageCmd,
+    },
+
+    /// Check a deployment for problems: deprecated config keys and env vars, and the
+    /// deprecated forms the running sidecar has seen (GET /v1/acip/status).
+    ///
+    /// Exit codes: 0 no errors (warnings allowed), 1 errors.
+    Doctor {
+        /// Config file to check
+        #[arg(long, default_value = "/etc/acip/config.toml")]
+        path: PathBuf,
+    },
+
+    /// GET /health (or the readiness endpoint with --ready).
+    ///
+    /// Exit codes: 0 healthy, 1 degraded, 2 unreachable.
+    Health {
+        /// Probe the readiness endpoint (/ready) instead of /health
+        #[arg(long, default_value_t = false)]
+        ready: bool,
+
+        /// Suppress all output (for container HEALTHCHECK lines)
+        #[arg(long, short, default_value_t = false)]
+        quiet: bool,
+
+        /// Bound each attempt (e.g. 500ms, 2s, 1m)
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// Extra attempts after a failed probe
+        #[arg(long, default_value_t = 0)]
+        retries: u32,
+
+        /// Delay between attempts (e.g. 2s)
+        #[arg(long, default_value = "2s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+
+    /// GET /v1/acip/stats, rendered as a plain-text table.
+    Stats {
+        /// Window size in days (today included)
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+
+        /// Grouping for rows
+        #[arg(long, default_value = "policy", value_parser = ["policy", "pattern", "source_type", "model", "confidence"])]
+        group_by: String,
+
+        /// Print the raw JSON instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// GET /v1/acip/slow_requests: recorded slow ingest runs, slowest first.
+    ///
+    /// The stage that took longest is shown in brackets.
+    SlowRequests {
+        /// Only runs recorded within this long (e.g. 30m, 1h, 2d)
+        #[arg(long, default_value = "1h", value_parser = parse_duration)]
+        since: Duration,
+
+        /// Most records fetched
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+
+        /// Print the raw JSON instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// GET /v1/acip/incidents/{request_id}: everything one ingest request left behind.
+    ///
+    /// Shows the audit entry, the decision, reputation events and the async job or upload it
+    /// ran for.
+    Incident {
+        /// The response's `origin.request_id`
+        request_id: String,
+
+        /// Print the raw JSON instead of a summary
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// POST /v1/acip/admin/drain: stop taking new ingest work (in-flight work completes).
+    ///
+    /// Exit codes: 0 drained (idle, with --wait-for-idle), 1 timed out waiting for idle.
+    Drain {
+        /// Name shown on /status as the drain initiator (default: $USER)
+        #[arg(long)]
+        initiated_by: Option<String>,
+
+        /// Poll /v1/acip/status until no ingest requests are in flight
+        #[arg(long, default_value_t = false)]
+        wait_for_idle: bool,
+
+        /// Give up waiting for idle after this long (e.g. 120s, 5m)
+        #[arg(long, default_value = "120s", value_parser = parse_duration)]
+        timeout: Duration,
+
+        /// Delay between polls (e.g. 500ms)
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+
+    /// POST /v1/acip/admin/resume: accept ingest work again.
+    Resume,
+
+    /// GET /v1/acip/reputation/records: list reputation records, ordered by key.
+    ///
+    /// Pages are fetched lazily as output is written.
+    Reputation {
+        /// Only keys starting with this (e.g. host:)
+        #[arg(long)]
+        prefix: Option<String>,
+
+        /// Records fetched per request
+        #[arg(long, default_value_t = 100)]
+        page_size: usize,
+
+        /// Print one JSON record per line instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Fetch every record in one streamed (NDJSON) response instead of pages
+        #[arg(long, default_value_t = false)]
+        stream: bool,
+    },
 
     /// Ingest a local file via /v1/acip/ingest_source
     IngestFile {
@@ -59,9 +219,43 @@ enum Cmd {
         #[arg(long, default_value_t = false)]
         allow_tools: bool,
 
-        /// Optional policy name to use (header X-ACIP-Policy)
+        /// Evaluate fresh for an investigation (header X-ACIP-Bypass-Cache, needs the
+        /// `support` scope): `refresh` (default) replaces the remembered verdict, `ghost`
+        /// leaves it untouched
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "refresh",
+              value_parser = ["refresh", "ghost"])]
+        bypass_cache: Option<String>,
+
+        /// The file already holds base64 (standard or urlsafe, any wrapping); decode and
+        /// validate it locally with the sidecar's limits before sending
+        #[arg(long, default_value_t = false)]
+        b64: bool,
+
+        /// Send files larger than --resumable-threshold in chunks via /v1/acip/uploads,
+        /// resuming an interrupted earlier attempt when one is recorded
+        #[arg(long, default_value_t = false, conflicts_with = "b64")]
+        resumable: bool,
+
+        /// File size (bytes) above which --resumable uses chunked upload
+        #[arg(long, default_value_t = b64::DEFAULT_MAX_DECODED_BYTES as u64)]
+        resumable_threshold: u64,
+
+        /// Progress file for --resumable (default: <path>.acip-upload.json)
         #[arg(long)]
-        policy: Option<String>,
+        state_file: Option<PathBuf>,
+
+        /// Submit as an async job (mode=async) and print the job instead of the decision
+        #[arg(long = "async", default_value_t = false, conflicts_with = "resumable")]
+        async_job: bool,
+
+        /// With --async: poll the job until it completes and print its result
+        #[arg(long, default_value_t = false, requires = "async_job")]
+        wait: bool,
+
+        /// Send the file as multipart/form-data (a `metadata` and a `file` part) instead of
+        /// base64 inside JSON
+        #[arg(long, default_value_t = false, conflicts_with_all = ["resumable", "async_job"])]
+        multipart: bool,
     },
 
     /// Ingest raw text (reads stdin) via /v1/acip/ingest_source
@@ -74,11 +268,30 @@ enum Cmd {
         content_type: String,
         #[arg(long, default_value_t = false)]
         allow_tools: bool,
-        #[arg(long)]
-        policy: Option<String>,
+
+        /// Evaluate fresh for an investigation (header X-ACIP-Bypass-Cache, needs the
+        /// `support` scope): `refresh` (default) replaces the remembered verdict, `ghost`
+        /// leaves it untouched
+        #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "refresh",
+              value_parser = ["refresh", "ghost"])]
+        bypass_cache: Option<String>,
+
+        /// Submit as an async job (mode=async) and print the job instead of the decision
+        #[arg(long = "async", default_value_t = false)]
+        async_job: bool,
+
+        /// With --async: poll the job until it completes and print its result
+        #[arg(long, default_value_t = false, requires = "async_job")]
+        wait: bool,
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Output {
+    Text,
+    Json,
+}
+
 #[derive(Debug, Clone, clap::ValueEnum)]
 enum RestartMode {
     /// systemd global service (default). Runs: sudo systemctl restart acip-sidecar
@@ -164,19 +377,215 @@ enum ConfigCmd {
     },
 }
 
-fn main() -> Result<()> {
+#[derive(Debug, Subcommand)]
+enum PatternsCmd {
+    /// Compile every `[[patterns]]` and `[[redaction.rules]]` regex in a config file under its
+    /// `[regex]` limits, then time each against bundled adversarial long inputs.
+    ///
+    /// Exit codes: 0 all ok, 1 a pattern is rejected or slower than `pattern_scan_budget_us`.
+    Lint {
+        /// Config file (or a fragment holding only those sections)
+        path: PathBuf,
+
+        /// Print the reports as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum StorageCmd {
+    /// Report each store file's format version and the migrations starting the sidecar would
+    /// run, without applying them.
+    ///
+    /// Files default to the `file:` backends of ACIP_REPUTATION_STORE, ACIP_STATS_STORE and
+    /// ACIP_SLOW_REQUEST_STORE. Exit codes: 0 all current, 1 migrations pending, 2 a file this
+    /// build cannot open (newer or unreadable).
+    Check {
+        /// Reputation store file
+        #[arg(long)]
+        reputation: Option<PathBuf>,
+
+        /// Stats store file
+        #[arg(long)]
+        stats: Option<PathBuf>,
+
+        /// Slow request store file
+        #[arg(long)]
+        slow_requests: Option<PathBuf>,
+
+        /// Print the reports as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+}
+
+fn main() {
     let cli = Cli::parse();
+    let (output, url) = (cli.output, cli.url.clone());
+    let code = match run(cli) {
+        Ok(code) => code,
+        Err(e) => report_error(&e, output, &url),
+    };
+    std::process::exit(code);
+}
+
+/// Run the command; `Ok` carries its exit code.
+fn run(cli: Cli) -> Result<i32> {
+    let json_output = cli.output == Output::Json;
+    if cli.version {
+        let build = build_info::current();
+        if json_output {
+            print_json(&serde_json::json!(build));
+        } else {
+            println!("acipctl {}", build.summary());
+        }
+        return Ok(0);
+    }
+    let Some(cmd) = cli.cmd else {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::MissingSubcommand,
+                "a subcommand is required",
+            )
+            .exit()
+    };
+    let token = cli.token.or_else(|| std::env::var("ACIP_AUTH_TOKEN").ok());
+    let c = client::Client::new(&cli.url, token.as_deref()).with_policy(cli.policy);
+    if !cli.no_version_check && cmd.checks_version() {
+        warn_on_version_skew(&c);
+    }
 
-    match cli.cmd {
+    match cmd {
         Cmd::Config { cmd } => handle_config(cmd)?,
 
-        Cmd::Health => {
-            let u = format!("{}/health", cli.url.trim_end_matches('/'));
-            let txt = reqwest::blocking::get(&u)
-                .with_context(|| format!("GET {u}"))?
-                .text()
-                .context("read response")?;
-            println!("{txt}");
+        Cmd::Patterns {
+            cmd: PatternsCmd::Lint { path, json },
+        } => return lint_patterns(&path, json || json_output),
+
+        Cmd::Storage {
+            cmd:
+                StorageCmd::Check {
+                    reputation,
+                    stats,
+                    slow_requests,
+                    json,
+                },
+        } => return check_storage([reputation, stats, slow_requests], json || json_output),
+
+        Cmd::Doctor { path } => return Ok(doctor(&c, &path, json_output)),
+
+        Cmd::Health {
+            ready,
+            quiet,
+            timeout,
+            retries,
+            interval,
+        } => {
+            let path = if ready { "/ready" } else { "/health" };
+            let probe = HealthProbe {
+                path,
+                timeout,
+                retries,
+                interval,
+            };
+            return Ok(health_check(&c, &probe, quiet, cli.output, &cli.url));
+        }
+
+        Cmd::Stats {
+            days,
+            group_by,
+            json,
+        } => {
+            let query = [("days", days.to_string()), ("group_by", group_by.clone())];
+            let v: Value = c.get_json("/v1/acip/stats", &query)?;
+            if json || json_output {
+                print_json(&v);
+            } else {
+                print!("{}", render_stats_table(&group_by, &v));
+            }
+        }
+
+        Cmd::SlowRequests { since, limit, json } => {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            let query = [
+                ("since", now.saturating_sub(since).as_secs().to_string()),
+                ("limit", limit.to_string()),
+            ];
+            let v: Value = c.get_json("/v1/acip/slow_requests", &query)?;
+            if json || json_output {
+                print_json(&v);
+            } else {
+                print!("{}", render_slow_requests_table(&v));
+            }
+        }
+
+        Cmd::Incident { request_id, json } => {
+            let v: Value = c.get_json(&format!("/v1/acip/incidents/{request_id}"), &[])?;
+            if json || json_output {
+                print_json(&v);
+            } else {
+                print!("{}", render_incident(&v));
+            }
+        }
+
+        Cmd::Drain {
+            initiated_by,
+            wait_for_idle,
+            timeout,
+            interval,
+        } => {
+            let initiated_by = initiated_by
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "acipctl".to_string());
+            let v = admin_post(
+                &c,
+                "drain",
+                serde_json::json!({ "initiated_by": initiated_by }),
+            )?;
+            print_json(&v);
+            if wait_for_idle {
+                wait_until_idle(&c, timeout, interval)?;
+                if !json_output {
+                    println!("idle: no ingest requests in flight");
+                }
+            }
+        }
+
+        Cmd::Resume => {
+            let v = admin_post(&c, "resume", serde_json::json!({}))?;
+            print_json(&v);
+        }
+
+        Cmd::Reputation {
+            prefix,
+            page_size,
+            json,
+            stream,
+        } => {
+            let query: Vec<(&str, String)> = prefix.into_iter().map(|p| ("prefix", p)).collect();
+            let path = "/v1/acip/reputation/records";
+            let records: Box<dyn Iterator<Item = Result<Value>>> = if stream {
+                Box::new(c.stream_items::<Value>(path, &query)?)
+            } else {
+                Box::new(c.paginate::<Value>(path, &query, page_size))
+            };
+            for rec in records {
+                let rec = rec?;
+                if json || json_output {
+                    println!("{rec}");
+                } else {
+                    println!(
+                        "{}\trisk={}\tseen={}\tattacks={}",
+                        rec["key"].as_str().unwrap_or_default(),
+                        rec["risk_score"],
+                        rec["seen_count"],
+                        rec["suspected_attack_count"],
+                    );
+                }
+            }
         }
 
         Cmd::IngestFile {
@@ -185,18 +594,74 @@ fn main() -> Result<()> {
             content_type,
             path,
             allow_tools,
-            policy,
+            bypass_cache,
+            b64: is_b64,
+            resumable,
+            resumable_threshold,
+            state_file,
+            async_job,
+            wait,
+            multipart,
         } => {
-            let bytes = fs::read(&path).with_context(|| format!("read {path:?}"))?;
-            ingest_bytes(
-                &cli.url,
-                &source_id,
-                &source_type,
-                &content_type,
-                &bytes,
-                allow_tools,
-                policy.as_deref(),
-            )?;
+            let size = fs::metadata(&path)
+                .with_context(|| format!("stat {path:?}"))?
+                .len();
+            let headers = ingest_headers(allow_tools, bypass_cache);
+            if resumable && size > resumable_threshold {
+                let state_file = state_file.unwrap_or_else(|| {
+                    let mut p = path.clone().into_os_string();
+                    p.push(".acip-upload.json");
+                    PathBuf::from(p)
+                });
+                let meta = serde_json::json!({
+                    "source_id": source_id,
+                    "source_type": source_type,
+                    "content_type": content_type,
+                });
+                let v = c.upload_resumable(
+                    &path,
+                    &meta,
+                    &headers,
+                    &state_file,
+                    &client::RetryPolicy::default(),
+                )?;
+                print_json(&v);
+                return Ok(0);
+            }
+
+            let bytes = if is_b64 {
+                let encoded =
+                    fs::read_to_string(&path).with_context(|| format!("read {path:?}"))?;
+                b64::decode_bounded(&encoded, b64::DEFAULT_MAX_DECODED_BYTES)
+                    .with_context(|| format!("invalid base64 in {path:?}"))?
+            } else {
+                fs::read(&path).with_context(|| format!("read {path:?}"))?
+            };
+            if multipart {
+                let metadata = serde_json::json!({
+                    "source_id": source_id,
+                    "source_type": source_type,
+                    "content_type": content_type,
+                });
+                let filename = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let v = c.ingest_multipart(&metadata, &filename, &content_type, bytes, &headers)?;
+                print_json(&v);
+                return Ok(0);
+            }
+            let body = serde_json::json!({
+              "source_id": source_id,
+              "source_type": source_type,
+              "content_type": content_type,
+              "bytes_b64": b64::encode(&bytes)
+            });
+            if async_job {
+                ingest_job(&c, &body, &headers, wait)?;
+            } else {
+                ingest(&c, &body, &headers)?;
+            }
         }
 
         Cmd::IngestText {
@@ -204,39 +669,551 @@ fn main() -> Result<()> {
             source_type,
             content_type,
             allow_tools,
-            policy,
+            bypass_cache,
+            async_job,
+            wait,
         } => {
             let mut s = String::new();
             io::stdin().read_to_string(&mut s).context("read stdin")?;
+            let headers = ingest_headers(allow_tools, bypass_cache);
 
             // Send as text field; sidecar also accepts bytes_b64.
-            let u = format!("{}/v1/acip/ingest_source", cli.url.trim_end_matches('/'));
-            let mut req = reqwest::blocking::Client::new().post(&u);
-            if allow_tools {
-                req = req.header("X-ACIP-Allow-Tools", "true");
-            }
-            if let Some(p) = policy {
-                req = req.header("X-ACIP-Policy", p);
-            }
-
             let body = serde_json::json!({
               "source_id": source_id,
               "source_type": source_type,
               "content_type": content_type,
               "text": s
             });
+            if async_job {
+                ingest_job(&c, &body, &headers, wait)?;
+            } else {
+                ingest(&c, &body, &headers)?;
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+/// The [`client::ApiError`] behind `e`, if the sidecar was involved.
+fn api_error(e: &anyhow::Error) -> Option<&client::ApiError> {
+    e.chain().find_map(|c| c.downcast_ref::<client::ApiError>())
+}
+
+/// Exit code of a failed command: 2 sidecar unreachable, 3 error status, 4 unreadable
+/// answer, 1 anything else.
+fn exit_code(e: &anyhow::Error) -> i32 {
+    match api_error(e) {
+        Some(client::ApiError::Connect { .. }) => 2,
+        Some(client::ApiError::Status { .. }) => 3,
+        Some(client::ApiError::Parse { .. }) => 4,
+        None => 1,
+    }
+}
+
+impl Cmd {
+    /// Commands that talk to the sidecar, except `health` (a quiet probe for container
+    /// health checks) and `doctor` (which reports skew as a finding).
+    fn checks_version(&self) -> bool {
+        !matches!(
+            self,
+            Cmd::Config { .. }
+                | Cmd::Patterns { .. }
+                | Cmd::Storage { .. }
+                | Cmd::Doctor { .. }
+                | Cmd::Health { .. }
+        )
+    }
+}
+
+/// One line on stderr when the sidecar's version is incompatible; a sidecar that cannot be
+/// asked is left to the command itself to report.
+fn warn_on_version_skew(c: &client::Client) {
+    if let Ok(Some(warning)) = c.version_skew() {
+        eprintln!("warning: {warning} (--no-version-check to silence)");
+    }
+}
+
+/// Print `e` (to stderr, or as `{"error": {...}}` on stdout with `--output json`) and return
+/// its exit code.
+fn report_error(e: &anyhow::Error, output: Output, url: &str) -> i32 {
+    let api = api_error(e);
+    let hint = matches!(api, Some(client::ApiError::Connect { .. })).then(|| {
+        format!("is the sidecar running and listening on {url}? Point --url at it otherwise")
+    });
+    match output {
+        Output::Text => {
+            eprintln!("error: {e:#}");
+            if let Some(hint) = &hint {
+                eprintln!("hint: {hint}");
+            }
+        }
+        Output::Json => {
+            let mut v = api
+                .map(client::ApiError::to_json)
+                .unwrap_or_else(|| serde_json::json!({ "kind": "local" }));
+            v["message"] = Value::from(format!("{e:#}"));
+            if let Some(hint) = hint {
+                v["hint"] = Value::from(hint);
+            }
+            print_json(&serde_json::json!({ "error": v }));
+        }
+    }
+    exit_code(e)
+}
+
+fn print_json(v: &Value) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(v).unwrap_or_else(|_| v.to_string())
+    );
+}
+
+/// POST /v1/acip/admin/<action>, returning the JSON body.
+fn admin_post(c: &client::Client, action: &str, body: Value) -> Result<Value> {
+    c.call(reqwest::Method::POST, &format!("/v1/acip/admin/{action}"))
+        .json(&body)
+        .send_json()
+}
+
+/// Poll `drain.in_flight` on /v1/acip/status until it reaches zero or `timeout` passes.
+fn wait_until_idle(c: &client::Client, timeout: Duration, interval: Duration) -> Result<()> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        let v: Value = c.get_json("/v1/acip/status", &[])?;
+        let in_flight = v["drain"]["in_flight"]
+            .as_u64()
+            .context("status response has no drain.in_flight")?;
+        if in_flight == 0 {
+            return Ok(());
+        }
+        if std::time::Instant::now() >= deadline {
+            anyhow::bail!("timed out after {timeout:?} with {in_flight} ingest request(s) in flight");
+        }
+        eprintln!("waiting: {in_flight} ingest request(s) in flight");
+        std::thread::sleep(interval);
+    }
+}
+
+/// Outcome of a single health probe.
+#[derive(Debug)]
+enum HealthOutcome {
+    Healthy,
+    /// Server answered but reported problems; holds the failing check names.
+    Degraded(Vec<String>),
+    /// Connection failed or timed out.
+    Unreachable(anyhow::Error),
+}
+
+impl HealthOutcome {
+    fn exit_code(&self) -> i32 {
+        match self {
+            HealthOutcome::Healthy => 0,
+            HealthOutcome::Degraded(_) => 1,
+            HealthOutcome::Unreachable(e) => exit_code(e),
+        }
+    }
+}
+
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let t = s.trim();
+    let (num, unit) = match t.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(i) => (&t[..i], &t[i..]),
+        None => (t, "s"),
+    };
+    let n: f64 = num
+        .parse()
+        .map_err(|_| format!("invalid duration: {s:?}"))?;
+    let secs = match unit {
+        "ms" => n / 1000.0,
+        "s" => n,
+        "m" => n * 60.0,
+        "h" => n * 3600.0,
+        "d" => n * 86400.0,
+        _ => return Err(format!("invalid duration unit in {s:?} (use ms/s/m/h/d)")),
+    };
+    if !secs.is_finite() || secs < 0.0 {
+        return Err(format!("invalid duration: {s:?}"));
+    }
+    Ok(Duration::from_secs_f64(secs))
+}
+
+fn check_is_ok(v: &Value) -> bool {
+    match v {
+        Value::Bool(b) => *b,
+        Value::String(s) => status_is_ok(s),
+        Value::Object(o) => {
+            if let Some(ok) = o.get("ok").and_then(Value::as_bool) {
+                return ok;
+            }
+            o.get("status")
+                .and_then(Value::as_str)
+                .map(status_is_ok)
+                .unwrap_or(false)
+        }
+        _ => false,
+    }
+}
+
+fn status_is_ok(s: &str) -> bool {
+    matches!(
+        s.trim().to_lowercase().as_str(),
+        "ok" | "healthy" | "ready" | "pass"
+    )
+}
+
+/// Interpret a health/readiness response.
+///
+/// JSON bodies may report `status` (ok|healthy|ready|degraded|...), an `ok`/`ready` boolean,
+/// and a `checks` object or array; any failing check makes the result degraded. A plain-text
+/// `ok` body is accepted for servers that predate the JSON form.
+fn classify_health(status: reqwest::StatusCode, body: &str) -> HealthOutcome {
+    let mut failing: Vec<String> = vec![];
+    let healthy = match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(o)) => {
+            match o.get("checks") {
+                Some(Value::Object(checks)) => {
+                    for (name, v) in checks {
+                        if !check_is_ok(v) {
+                            failing.push(name.clone());
+                        }
+                    }
+                }
+                Some(Value::Array(checks)) => {
+                    for (i, v) in checks.iter().enumerate() {
+                        if !check_is_ok(v) {
+                            let name = v
+                                .get("name")
+                                .and_then(Value::as_str)
+                                .map(str::to_string)
+                                .unwrap_or_else(|| format!("check[{i}]"));
+                            failing.push(name);
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if let Some(s) = o.get("status").and_then(Value::as_str) {
+                status_is_ok(s)
+            } else if let Some(b) = o
+                .get("ready")
+                .or_else(|| o.get("ok"))
+                .and_then(Value::as_bool)
+            {
+                b
+            } else {
+                failing.is_empty()
+            }
+        }
+        _ => body.trim().eq_ignore_ascii_case("ok"),
+    };
 
-            let resp = req.json(&body).send().with_context(|| format!("POST {u}"))?;
+    if status.is_success() && healthy && failing.is_empty() {
+        return HealthOutcome::Healthy;
+    }
+    if failing.is_empty() {
+        if status.is_success() {
+            failing.push("status".to_string());
+        } else {
+            failing.push(format!("http_{}", status.as_u16()));
+        }
+    }
+    HealthOutcome::Degraded(failing)
+}
+
+/// What `health` asks for and how hard it tries.
+struct HealthProbe {
+    path: &'static str,
+    timeout: Duration,
+    retries: u32,
+    interval: Duration,
+}
+
+fn probe_health(c: &client::Client, probe: &HealthProbe) -> (HealthOutcome, Option<String>) {
+    let answer = c
+        .call(reqwest::Method::GET, probe.path)
+        .timeout(probe.timeout)
+        .send_raw()
+        .and_then(|resp| {
             let status = resp.status();
-            let v: Value = resp.json().context("parse json")?;
-            println!("{}", serde_json::to_string_pretty(&v).unwrap_or_else(|_| v.to_string()));
-            if !status.is_success() {
-                anyhow::bail!("request failed: {status}");
+            Ok((status, resp.text()?))
+        });
+    match answer {
+        Ok((status, body)) => (classify_health(status, &body), Some(body)),
+        Err(e) => (HealthOutcome::Unreachable(e), None),
+    }
+}
+
+fn health_check(
+    c: &client::Client,
+    probe: &HealthProbe,
+    quiet: bool,
+    output: Output,
+    url: &str,
+) -> i32 {
+    let mut attempt = 0;
+    let (outcome, body) = loop {
+        let (outcome, body) = probe_health(c, probe);
+        if matches!(outcome, HealthOutcome::Healthy) || attempt >= probe.retries {
+            break (outcome, body);
+        }
+        attempt += 1;
+        std::thread::sleep(probe.interval);
+    };
+
+    if quiet {
+        return outcome.exit_code();
+    }
+    let body = body.unwrap_or_default();
+    match (&outcome, output) {
+        (HealthOutcome::Unreachable(e), _) => return report_error(e, output, url),
+        (HealthOutcome::Healthy, Output::Text) => println!("{}", body.trim_end()),
+        (HealthOutcome::Degraded(failing), Output::Text) => {
+            println!("{}", body.trim_end());
+            eprintln!("degraded: {}", failing.join(", "));
+        }
+        (_, Output::Json) => {
+            let failing = match &outcome {
+                HealthOutcome::Degraded(failing) => failing.clone(),
+                _ => vec![],
+            };
+            let body = serde_json::from_str(&body).unwrap_or(Value::String(body));
+            print_json(&serde_json::json!({
+                "status": if failing.is_empty() { "healthy" } else { "degraded" },
+                "failing": failing,
+                "body": body,
+            }));
+        }
+    }
+    outcome.exit_code()
+}
+
+fn stats_columns(group_by: &str) -> &'static [&'static str] {
+    match group_by {
+        "pattern" => &[
+            "pattern",
+            "hits",
+            "escalated_share",
+            "false_positives",
+            "overturn_rate",
+        ],
+        "source_type" => &[
+            "source_type",
+            "decisions",
+            "avg_severity",
+            "degraded",
+            "garbled",
+        ],
+        "model" => &[
+            "model",
+            "parses",
+            "repaired",
+            "invalid",
+            "repair_rate",
+            "invalid_rate",
+        ],
+        "confidence" => &[
+            "confidence",
+            "decisions",
+            "second_opinions",
+            "overturn_rate",
+            "revalidations",
+            "divergence_rate",
+        ],
+        _ => &[
+            "policy",
+            "decisions",
+            "allow",
+            "sanitize",
+            "block",
+            "needs_review",
+            "escalation_rate",
+            "revalidations",
+            "revalidation_agreement_rate",
+        ],
+    }
+}
+
+fn stats_cell(row: &Value, col: &str) -> String {
+    match col {
+        "allow" | "sanitize" | "block" | "needs_review" => {
+            row["by_action"][col].as_u64().unwrap_or(0).to_string()
+        }
+        "degraded" | "garbled" => row["by_quality"][col].as_u64().unwrap_or(0).to_string(),
+        "escalation_rate"
+        | "escalated_share"
+        | "overturn_rate"
+        | "revalidation_agreement_rate"
+        | "repair_rate"
+        | "invalid_rate"
+        | "divergence_rate" => row[col]
+            .as_f64()
+            .map(|f| format!("{:.1}%", f * 100.0))
+            .unwrap_or_default(),
+        "avg_severity" => row[col]
+            .as_f64()
+            .map(|f| format!("{f:.1}"))
+            .unwrap_or_default(),
+        _ => match &row[col] {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        },
+    }
+}
+
+/// Render a `/v1/acip/stats` body as an aligned table (first column left, numbers right).
+fn render_stats_table(group_by: &str, v: &Value) -> String {
+    let cols = stats_columns(group_by);
+    let rows: Vec<Vec<String>> = v["rows"]
+        .as_array()
+        .map(|rows| {
+            rows.iter()
+                .map(|r| cols.iter().map(|c| stats_cell(r, c)).collect())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut widths: Vec<usize> = cols.iter().map(|c| c.len()).collect();
+    for row in &rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: &[String]| -> String {
+        let mut out = String::new();
+        for (i, (cell, w)) in cells.iter().zip(&widths).enumerate() {
+            if i == 0 {
+                out.push_str(&format!("{cell:<w$}"));
+            } else {
+                out.push_str(&format!("  {cell:>w$}"));
             }
         }
+        out.trim_end().to_string() + "\n"
+    };
+
+    let header: Vec<String> = cols.iter().map(|c| c.to_string()).collect();
+    let mut out = line(&header);
+    for row in &rows {
+        out.push_str(&line(row));
     }
+    if rows.is_empty() {
+        out.push_str("(no decisions in window)\n");
+    }
+    out
+}
 
-    Ok(())
+/// Slow request records as a table, slowest first, with the dominant stage in brackets.
+fn render_slow_requests_table(v: &Value) -> String {
+    let mut records: Vec<&Value> = v["records"]
+        .as_array()
+        .map(|r| r.iter().collect())
+        .unwrap_or_default();
+    records.sort_by(|a, b| {
+        let ms = |r: &Value| r["total_ms"].as_f64().unwrap_or_default();
+        ms(b).total_cmp(&ms(a))
+    });
+
+    let mut out = format!(
+        "{:<24}  {:>10}  {:<9}  {:<14}  stages (ms)\n",
+        "request_id", "total_ms", "reason", "dominant"
+    );
+    for r in &records {
+        let dominant = r["dominant_stage"].as_str().unwrap_or_default();
+        let stages: Vec<String> = r["stages"]
+            .as_array()
+            .map(|s| s.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .map(|s| {
+                let name = s["stage"].as_str().unwrap_or_default();
+                let cell = format!("{name}={:.0}", s["ms"].as_f64().unwrap_or_default());
+                if name == dominant {
+                    format!("[{cell}]")
+                } else {
+                    cell
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{:<24}  {:>10.1}  {:<9}  {:<14}  {}\n",
+            r["request_id"].as_str().unwrap_or_default(),
+            r["total_ms"].as_f64().unwrap_or_default(),
+            r["reason"].as_str().unwrap_or_default(),
+            dominant,
+            stages.join(" ")
+        ));
+    }
+    if records.is_empty() {
+        out.push_str("(no slow requests in window)\n");
+    }
+    out
+}
+
+fn render_incident(v: &Value) -> String {
+    let s = |v: &Value| match v {
+        Value::String(s) => s.clone(),
+        Value::Null => "-".to_string(),
+        other => other.to_string(),
+    };
+    let audit = &v["audit"];
+    let mut out = format!("request    {}\n", s(&v["request_id"]));
+    out.push_str(&format!(
+        "audit      {} actor={} policy={} source={} ({}) http={}\n",
+        s(&audit["id"]),
+        s(&audit["actor"]),
+        s(&audit["policy"]),
+        s(&audit["source_id"]),
+        s(&audit["source_type"]),
+        s(&audit["http_status"])
+    ));
+    let d = &v["decision"];
+    if d.is_null() {
+        out.push_str(&format!("error      {}\n", s(&audit["error"])));
+    } else {
+        out.push_str(&format!(
+            "decision   {} risk={} tools_allowed={} threat_score={}\n",
+            s(&d["action"]),
+            s(&d["risk_level"]),
+            s(&d["tools_allowed"]),
+            s(&d["threat_score"])
+        ));
+    }
+    let events = v["reputation_events"].as_array();
+    for e in events.map(|a| a.as_slice()).unwrap_or_default() {
+        let effective = match e["effective_risk"].as_u64() {
+            Some(r) => format!(" effective={r}"),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "reputation {} {} risk {} -> {}{effective}\n",
+            s(&e["kind"]),
+            s(&e["key"]),
+            s(&e["risk_before"]),
+            s(&e["risk_after"])
+        ));
+    }
+    if !v["job"].is_null() {
+        let j = &v["job"];
+        let (id, status) = (s(&j["job_id"]), s(&j["status"]));
+        out.push_str(&format!("job        {id} status={status}\n"));
+    }
+    if !v["webhook"].is_null() {
+        out.push_str(&format!("webhook    {}\n", s(&v["webhook"])));
+    }
+    if !v["upload_id"].is_null() {
+        out.push_str(&format!("upload     {}\n", s(&v["upload_id"])));
+    }
+    if !v["slow_request"].is_null() {
+        out.push_str(&format!(
+            "slow       total_ms={} dominant={}\n",
+            s(&v["slow_request"]["total_ms"]),
+            s(&v["slow_request"]["dominant_stage"])
+        ));
+    }
+    out
 }
 
 fn handle_config(cmd: ConfigCmd) -> Result<()> {
@@ -247,7 +1224,13 @@ fn handle_config(cmd: ConfigCmd) -> Result<()> {
             Ok(())
         }
         ConfigCmd::Validate { path } => {
-            let _ = config::Config::load(&path).with_context(|| format!("load {path:?}"))?;
+            let raw = fs::read_to_string(&path).with_context(|| format!("read {path:?}"))?;
+            let (cfg, uses) = config::Config::parse_with(&raw, &deprecations::Registry::builtin())
+                .with_context(|| format!("load {path:?}"))?;
+            cfg.validate().with_context(|| format!("load {path:?}"))?;
+            for used in &uses {
+                eprintln!("warning: {}", used.message());
+            }
             eprintln!("OK: {path:?}");
             Ok(())
         }
@@ -288,6 +1271,193 @@ fn handle_config(cmd: ConfigCmd) -> Result<()> {
     }
 }
 
+/// `patterns lint`; returns the exit code.
+fn lint_patterns(path: &PathBuf, json: bool) -> Result<i32> {
+    let raw = fs::read_to_string(path).with_context(|| format!("read {path:?}"))?;
+    let cfg = config::Config::parse(&raw).with_context(|| format!("parse {path:?}"))?;
+    let limits = regex_guard::RegexLimits::from_config(cfg.regex.as_ref());
+    limits.validate()?;
+
+    let mut entries: Vec<(String, String)> = cfg
+        .patterns
+        .iter()
+        .map(|p| (format!("patterns.{}", p.id), p.regex.clone()))
+        .collect();
+    for rule in cfg.redaction.iter().flat_map(|r| r.rules.iter()) {
+        if let Some(re) = &rule.regex {
+            entries.push((format!("redaction.{}", rule.label), re.clone()));
+        }
+    }
+
+    let reports = patterns::lint(&entries, &limits);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for r in &reports {
+            match &r.error {
+                Some(e) => println!("{} status=rejected error={e:?}", r.label),
+                None => println!(
+                    "{} status={} size={} slowest_us={} input={}",
+                    r.label,
+                    serde_json::to_value(r.status)?.as_str().unwrap_or_default(),
+                    r.compiled_bytes.unwrap_or_default(),
+                    r.slowest_us.unwrap_or_default(),
+                    r.slowest_input.unwrap_or_default(),
+                ),
+            }
+        }
+        eprintln!(
+            "{} regexes; budget {}us per scan",
+            reports.len(),
+            limits.pattern_scan_budget.as_micros()
+        );
+    }
+    let failed = reports.iter().any(|r| r.status != patterns::LintStatus::Ok);
+    Ok(i32::from(failed))
+}
+
+/// `storage check`; `paths` override the configured files of [`storage::FORMATS`], in order.
+/// Returns the exit code.
+fn check_storage(paths: [Option<PathBuf>; 3], json: bool) -> Result<i32> {
+    let reports: Vec<storage::CheckReport> = storage::FORMATS
+        .into_iter()
+        .zip(paths)
+        .filter_map(|(format, path)| {
+            let path = path.or_else(|| format.configured_path())?;
+            Some(storage::check(&path, format))
+        })
+        .collect();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else if reports.is_empty() {
+        eprintln!("no file-backed stores configured");
+    } else {
+        for r in &reports {
+            let version = r.version.map_or("-".to_string(), |v| v.to_string());
+            println!(
+                "{} path={} status={} version={version} supported={}",
+                r.format,
+                r.path,
+                serde_json::to_value(r.status)?.as_str().unwrap_or_default(),
+                r.supported,
+            );
+            for m in &r.pending {
+                println!("  migrate v{} -> v{}: {}", m.from, m.to, m.summary);
+            }
+            if let Some(e) = &r.error {
+                println!("  error: {e}");
+            }
+        }
+    }
+    let code = reports
+        .iter()
+        .map(|r| match r.status {
+            storage::CheckStatus::Missing | storage::CheckStatus::Current => 0,
+            storage::CheckStatus::MigrationPending => 1,
+            storage::CheckStatus::TooNew | storage::CheckStatus::Unreadable => 2,
+        })
+        .max()
+        .unwrap_or(0);
+    Ok(code)
+}
+
+/// One `acipctl doctor` finding.
+#[derive(Debug, serde::Serialize)]
+struct Finding {
+    check: &'static str,
+    /// `warn` or `error`.
+    level: &'static str,
+    message: String,
+}
+
+impl Finding {
+    fn warn(check: &'static str, message: String) -> Self {
+        Self {
+            check,
+            level: "warn",
+            message,
+        }
+    }
+
+    fn error(check: &'static str, message: String) -> Self {
+        Self {
+            check,
+            level: "error",
+            message,
+        }
+    }
+}
+
+/// Check the config file at `path`, this process's environment and the running sidecar;
+/// returns the exit code.
+fn doctor(c: &client::Client, path: &PathBuf, json: bool) -> i32 {
+    let registry = deprecations::Registry::builtin();
+    let mut findings = vec![];
+
+    match fs::read_to_string(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            findings.push(Finding::warn("config", format!("{path:?} not found")));
+        }
+        Err(e) => findings.push(Finding::error("config", format!("read {path:?}: {e}"))),
+        Ok(raw) => match config::Config::parse_with(&raw, &registry)
+            .and_then(|(cfg, uses)| cfg.validate().map(|()| uses))
+        {
+            Ok(uses) => {
+                for used in uses {
+                    findings.push(Finding::warn("config", used.message()));
+                }
+            }
+            Err(e) => findings.push(Finding::error("config", format!("{path:?}: {e:#}"))),
+        },
+    }
+
+    match registry.apply_env(None, |name| std::env::var(name).ok()) {
+        Ok((_, uses)) => {
+            for used in uses {
+                findings.push(Finding::warn("env", used.message()));
+            }
+        }
+        Err(e) => findings.push(Finding::error("env", format!("{e:#}"))),
+    }
+
+    match c.get_json::<Value>("/v1/acip/status", &[]) {
+        Ok(v) => {
+            let uses: Vec<deprecations::DeprecationUse> =
+                serde_json::from_value(v["deprecations_in_use"].clone()).unwrap_or_default();
+            for used in uses {
+                findings.push(Finding::warn("sidecar", used.message()));
+            }
+            if let Some(warning) = client::status_version(&v)
+                .and_then(|s| build_info::skew_warning(&build_info::current().version, s))
+            {
+                findings.push(Finding::warn("version", warning));
+            }
+            // Host limits the sidecar found at startup; hard conflicts are errors.
+            let conflicts = v["environment"]["conflicts"].as_array();
+            for c in conflicts.into_iter().flatten() {
+                let message = c["message"].as_str().unwrap_or_default().to_string();
+                findings.push(if c["severity"] == "hard" {
+                    Finding::error("environment", message)
+                } else {
+                    Finding::warn("environment", message)
+                });
+            }
+        }
+        Err(e) => findings.push(Finding::warn("sidecar", format!("not checked: {e:#}"))),
+    }
+
+    if json {
+        print_json(&serde_json::json!({ "findings": findings }));
+    } else if findings.is_empty() {
+        println!("no problems found");
+    } else {
+        for f in &findings {
+            println!("{:<5} {}: {}", f.level, f.check, f.message);
+        }
+    }
+    i32::from(findings.iter().any(|f| f.level == "error"))
+}
+
 fn parse_toml_value(s: &str) -> toml_edit::Item {
     let t = s.trim();
     if matches!(t.to_lowercase().as_str(), "true" | "false") {
@@ -329,7 +1499,8 @@ fn set_config_value(path: &PathBuf, dotted_key: &str, value: &str) -> Result<()>
 
     // Validate by deserializing with the real config struct.
     let new_txt = doc.to_string();
-    let _: config::Config = toml::from_str(&new_txt).context("validate config")?;
+    let cfg = config::Config::parse(&new_txt).context("validate config")?;
+    cfg.validate().context("validate config")?;
 
     write_atomic(path, &new_txt)?;
     eprintln!("OK: set {dotted_key} in {path:?}");
@@ -365,7 +1536,7 @@ fn unset_config_value(path: &PathBuf, dotted_key: &str) -> Result<()> {
     }
 
     let new_txt = doc.to_string();
-    let _: config::Config = toml::from_str(&new_txt).context("validate config")?;
+    config::Config::parse(&new_txt).context("validate config")?;
 
     write_atomic(path, &new_txt)?;
     eprintln!("OK: unset {dotted_key} in {path:?}");
@@ -382,86 +1553,86 @@ fn write_atomic(path: &PathBuf, contents: &str) -> Result<()> {
 }
 
 fn restart_service(mode: RestartMode, compose_file: &str, compose_service: &str) -> Result<()> {
+    let run = |cmd: &CommandLine| -> Result<bool> {
+        let st = cmd
+            .to_command()
+            .status()
+            .with_context(|| format!("run {cmd}"))?;
+        Ok(st.success())
+    };
     match mode {
         RestartMode::System => {
             // Try without sudo first; if it fails, try sudo.
-            let status = std::process::Command::new("systemctl")
-                .args(["restart", "acip-sidecar"])
-                .status();
-            if status.map(|s| s.success()).unwrap_or(false) {
+            let cmd = CommandLine::new("systemctl").args(["restart", "acip-sidecar"]);
+            if run(&cmd).unwrap_or(false) {
                 return Ok(());
             }
-            let st2 = std::process::Command::new("sudo")
-                .args(["systemctl", "restart", "acip-sidecar"])
-                .status()
-                .context("run sudo systemctl")?;
-            if !st2.success() {
+            if !run(&cmd.wrapped("sudo"))? {
                 anyhow::bail!("restart failed");
             }
             Ok(())
         }
         RestartMode::User => {
-            let st = std::process::Command::new("systemctl")
-                .args(["--user", "restart", "acip-sidecar"])
-                .status()
-                .context("run systemctl --user")?;
-            if !st.success() {
+            let cmd = CommandLine::new("systemctl").args(["--user", "restart", "acip-sidecar"]);
+            if !run(&cmd)? {
                 anyhow::bail!("restart failed");
             }
             Ok(())
         }
         RestartMode::DockerCompose => {
             // By design: print the command, do not execute.
-            println!(
-                "docker compose -f {} restart {}",
-                shell_escape(compose_file),
-                shell_escape(compose_service)
-            );
+            let cmd = CommandLine::new("docker")
+                .args(["compose", "-f", compose_file, "restart", compose_service]);
+            println!("{cmd}");
             Ok(())
         }
     }
 }
 
-fn shell_escape(s: &str) -> String {
-    if s.chars().all(|c| c.is_ascii_alphanumeric() || "-._/:".contains(c)) {
-        return s.to_string();
+/// Submit `body` as an async ingest job and print it; with `wait`, poll until it finishes
+/// and print the final job (a failed job is an error).
+fn ingest_job(
+    c: &client::Client,
+    body: &Value,
+    headers: &[(&str, String)],
+    wait: bool,
+) -> Result<()> {
+    let mut job = c.ingest_async(body, headers)?;
+    if wait {
+        eprintln!("submitted job {}", job.job_id);
+        job = c.wait_for_job(
+            &job.job_id,
+            Duration::from_millis(500),
+            Duration::from_secs(5),
+        )?;
+    }
+    print_json(&serde_json::to_value(&job)?);
+    if job.status == jobs::JobState::Failed {
+        let code = job.http_status.unwrap_or_default();
+        anyhow::bail!("job {} failed: {code}", job.job_id);
     }
-    format!("'{}'", s.replace('\'', "'\\''"))
+    Ok(())
 }
 
-fn ingest_bytes(
-    base_url: &str,
-    source_id: &str,
-    source_type: &str,
-    content_type: &str,
-    bytes: &[u8],
-    allow_tools: bool,
-    policy: Option<&str>,
-) -> Result<()> {
-    let u = format!("{}/v1/acip/ingest_source", base_url.trim_end_matches('/'));
-
-    let mut req = reqwest::blocking::Client::new().post(&u);
+/// Tool and cache bypass selection headers of an ingest command.
+fn ingest_headers(allow_tools: bool, bypass_cache: Option<String>) -> Vec<(&'static str, String)> {
+    let mut headers = vec![];
     if allow_tools {
-        req = req.header("X-ACIP-Allow-Tools", "true");
+        headers.push(("X-ACIP-Allow-Tools", "true".to_string()));
     }
-    if let Some(p) = policy {
-        req = req.header("X-ACIP-Policy", p);
+    if let Some(mode) = bypass_cache {
+        headers.push(("X-ACIP-Bypass-Cache", mode));
     }
+    headers
+}
 
-    let b64 = B64.encode(bytes);
-    let body = serde_json::json!({
-      "source_id": source_id,
-      "source_type": source_type,
-      "content_type": content_type,
-      "bytes_b64": b64
-    });
-
-    let resp = req.json(&body).send().with_context(|| format!("POST {u}"))?;
-    let status = resp.status();
-    let v: Value = resp.json().context("parse json")?;
-    println!("{}", serde_json::to_string_pretty(&v).unwrap_or_else(|_| v.to_string()));
-    if !status.is_success() {
-        anyhow::bail!("request failed: {status}");
-    }
+/// POST `body` to /v1/acip/ingest_source and print the decision.
+fn ingest(c: &client::Client, body: &Value, headers: &[(&str, String)]) -> Result<()> {
+    let v: Value = c
+        .call(reqwest::Method::POST, "/v1/acip/ingest_source")
+        .headers(headers)
+        .json(body)
+        .send_json()?;
+    print_json(&v);
     Ok(())
 }
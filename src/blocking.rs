@@ -0,0 +1,105 @@
+//! Bounded pool for blocking work in the request path.
+//!
+//! Store writes (reputation, stats, verdict history, the slow-request log), upload chunk and
+//! assembly I/O and the temp-dir free-space check all touch the disk synchronously. They run
+//! here instead of on a runtime worker, so a slow disk delays the requests waiting on it but
+//! cannot stall the runtime.
+//!
+//! The pool is a semaphore in front of tokio's blocking threads: at most `size` jobs run at
+//! once and the rest wait asynchronously for a permit. The size is `server.blocking_pool_size`
+//! (`ACIP_BLOCKING_POOL_SIZE`), independent of extraction: each extractor helper already runs
+//! on its own blocking thread and is never queued behind store writes.
+
+use crate::config;
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Instant,
+};
+use tokio::sync::Semaphore;
+
+pub const DEFAULT_POOL_SIZE: usize = 8;
+
+pub struct BlockingPool {
+    size: usize,
+    permits: Semaphore,
+    waiting: AtomicU64,
+    completed: AtomicU64,
+    max_wait_us: AtomicU64,
+}
+
+impl Default for BlockingPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_POOL_SIZE)
+    }
+}
+
+impl BlockingPool {
+    /// A pool running at most `size` jobs at once (at least one).
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            permits: Semaphore::new(size),
+            waiting: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            max_wait_us: AtomicU64::new(0),
+        }
+    }
+
+    /// `server.blocking_pool_size`, overridden by `ACIP_BLOCKING_POOL_SIZE`.
+    pub fn from_config(cfg: Option<&config::Config>) -> Self {
+        let size = std::env::var("ACIP_BLOCKING_POOL_SIZE")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .or_else(|| {
+                cfg.and_then(|c| c.server.as_ref())
+                    .and_then(|s| s.blocking_pool_size)
+            })
+            .unwrap_or(DEFAULT_POOL_SIZE);
+        Self::new(size)
+    }
+
+    /// Run `f` on a blocking thread once a pool slot is free. A panic in `f` is resumed here.
+    pub async fn run<T, F>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queued = Instant::now();
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        // The semaphore is never closed.
+        let permit = self.permits.acquire().await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        let _permit = permit.expect("blocking pool semaphore closed");
+        self.max_wait_us
+            .fetch_max(queued.elapsed().as_micros() as u64, Ordering::Relaxed);
+
+        let out = match tokio::task::spawn_blocking(f).await {
+            Ok(out) => out,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        };
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        out
+    }
+
+    pub fn snapshot(&self) -> BlockingPoolSnapshot {
+        BlockingPoolSnapshot {
+            size: self.size,
+            busy: self.size - self.permits.available_permits(),
+            waiting: self.waiting.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            max_wait_ms: self.max_wait_us.load(Ordering::Relaxed) / 1000,
+        }
+    }
+}
+
+/// `blocking_pool` in `/v1/acip/status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockingPoolSnapshot {
+    pub size: usize,
+    pub busy: usize,
+    pub waiting: u64,
+    pub completed: u64,
+    pub max_wait_ms: u64,
+}
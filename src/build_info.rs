@@ -0,0 +1,108 @@
+//! What exactly this binary is: package version, `git describe`, build time, rustc, build
+//! profile and cargo features.
+//!
+//! `build.rs` embeds the values at compile time; nothing is looked up at runtime. A value the
+//! build could not find (a source tarball has no `.git`) reads as `unknown` rather than failing
+//! the build. The same [`BuildInfo`] is `version` in `/v1/acip/status`, `provenance.build` in
+//! ingest responses, the startup log line and `acipctl --version --output json`.
+
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+/// A field the build could not determine.
+pub const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    /// `CARGO_PKG_VERSION`.
+    pub version: String,
+    /// `git describe --tags --always --dirty`.
+    pub git_describe: String,
+    /// Uncommitted changes at build time; `None` when `git_describe` is unknown.
+    pub git_dirty: Option<bool>,
+    /// Unix seconds (`SOURCE_DATE_EPOCH` when set).
+    pub build_timestamp: Option<u64>,
+    pub rustc: String,
+    /// `debug` or `release`.
+    pub profile: String,
+    /// Enabled cargo features, sorted.
+    pub features: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Build from the `ACIP_BUILD_*` values `lookup` returns (the names `build.rs` sets).
+    pub fn from_lookup(version: &str, lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let get = |key: &str| lookup(key).filter(|v| !v.trim().is_empty());
+        let git_describe = get("ACIP_BUILD_GIT_DESCRIBE");
+        Self {
+            version: version.to_string(),
+            git_dirty: git_describe.as_ref().map(|d| d.ends_with("-dirty")),
+            git_describe: git_describe.unwrap_or_else(|| UNKNOWN.to_string()),
+            build_timestamp: get("ACIP_BUILD_TIMESTAMP").and_then(|v| v.trim().parse().ok()),
+            rustc: get("ACIP_BUILD_RUSTC").unwrap_or_else(|| UNKNOWN.to_string()),
+            profile: get("ACIP_BUILD_PROFILE").unwrap_or_else(|| UNKNOWN.to_string()),
+            features: get("ACIP_BUILD_FEATURES")
+                .map(|v| v.split(',').map(str::to_string).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// One line for logs and `acipctl --version`.
+    pub fn summary(&self) -> String {
+        let features = if self.features.is_empty() {
+            "none".to_string()
+        } else {
+            self.features.join(",")
+        };
+        format!(
+            "{} (git {}, {}, {}, features: {features})",
+            self.version, self.git_describe, self.profile, self.rustc
+        )
+    }
+}
+
+/// This binary's build.
+pub fn current() -> &'static BuildInfo {
+    static CURRENT: OnceLock<BuildInfo> = OnceLock::new();
+    CURRENT.get_or_init(|| {
+        BuildInfo::from_lookup(env!("CARGO_PKG_VERSION"), |key| {
+            let v = match key {
+                "ACIP_BUILD_GIT_DESCRIBE" => option_env!("ACIP_BUILD_GIT_DESCRIBE"),
+                "ACIP_BUILD_TIMESTAMP" => option_env!("ACIP_BUILD_TIMESTAMP"),
+                "ACIP_BUILD_RUSTC" => option_env!("ACIP_BUILD_RUSTC"),
+                "ACIP_BUILD_PROFILE" => option_env!("ACIP_BUILD_PROFILE"),
+                "ACIP_BUILD_FEATURES" => option_env!("ACIP_BUILD_FEATURES"),
+                _ => None,
+            };
+            v.map(str::to_string)
+        })
+    })
+}
+
+/// `major.minor.patch`; a pre-release suffix is ignored and missing parts are 0.
+pub fn parse_version(v: &str) -> (u64, u64, u64) {
+    let core = v.trim().split(['-', '+']).next().unwrap_or_default();
+    let mut parts = core.split('.').map(|p| p.parse().unwrap_or(0));
+    (
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+        parts.next().unwrap_or(0),
+    )
+}
+
+/// Whether `a` and `b` are expected to speak the same API: the same major version, or for
+/// `0.x` releases the same minor version (semver's compatibility rule).
+pub fn compatible(a: &str, b: &str) -> bool {
+    let (a, b) = (parse_version(a), parse_version(b));
+    a.0 == b.0 && (a.0 != 0 || a.1 == b.1)
+}
+
+/// The warning for a client at `client` talking to a server at `server`, if they are not
+/// [`compatible`].
+pub fn skew_warning(client: &str, server: &str) -> Option<String> {
+    (!compatible(client, server)).then(|| {
+        format!(
+            "client version {client} does not match server version {server}; upgrade the older one"
+        )
+    })
+}
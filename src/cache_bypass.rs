@@ -0,0 +1,150 @@
+//! `X-ACIP-Bypass-Cache`: evaluate one request fresh, for investigations.
+//!
+//! The pipeline never answers from a remembered verdict alone: every run extracts, scans and
+//! asks the models again (a large revision of a recent input may show them only what changed,
+//! see [`crate::chunking`]; a bypassing run never does). What a run leaves behind is its entry in the last-decision map
+//! ([`crate::verdicts`]), which later runs of the same content are compared against. The
+//! header decides what a bypassing run does with it:
+//!
+//! - `refresh` (also `true`, `1`, `yes`): replace the remembered verdict with the fresh one;
+//! - `ghost`: leave the map, the revalidation counters and the chunk index untouched.
+//!
+//! `false`, `0` and `no` are the same as no header. The header needs the `support` scope
+//! ([`require_bypass_scope`]), so ordinary callers cannot use it. A bypassing run states its
+//! mode in `cache_bypass` of the decision and of its audit entry, and is counted per mode and
+//! token under `cache_bypass` in `/v1/acip/status`.
+
+use crate::acip_headers;
+use crate::introspection;
+use crate::token_auth::{Actor, Scope};
+use axum::{
+    extract::Request,
+    http::{HeaderMap, StatusCode},
+    middleware::{from_fn, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{collections::BTreeMap, sync::Mutex};
+
+pub const HEADER: &str = "x-acip-bypass-cache";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BypassMode {
+    /// The fresh verdict replaces the remembered one.
+    Refresh,
+    /// Nothing is remembered from the run.
+    Ghost,
+}
+
+impl BypassMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Refresh => "refresh",
+            Self::Ghost => "ghost",
+        }
+    }
+}
+
+/// The mode `headers` ask for; `Err` carries a value that is not one.
+pub fn requested(headers: &HeaderMap) -> Result<Option<BypassMode>, String> {
+    let Some(v) = acip_headers::values(headers, HEADER).into_iter().next() else {
+        return Ok(None);
+    };
+    match v.to_lowercase().as_str() {
+        "refresh" | "true" | "1" | "yes" => Ok(Some(BypassMode::Refresh)),
+        "ghost" => Ok(Some(BypassMode::Ghost)),
+        "false" | "0" | "no" => Ok(None),
+        _ => Err(v),
+    }
+}
+
+/// The mode of a request that passed [`require_bypass_scope`].
+pub fn mode(headers: &HeaderMap) -> Option<BypassMode> {
+    requested(headers).ok().flatten()
+}
+
+/// Refuse `X-ACIP-Bypass-Cache` from actors without the `support` scope
+/// (403 `insufficient_scope`), and unknown modes (400 `invalid_header`). Must sit inside
+/// token auth.
+pub fn require_bypass_scope<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(from_fn(bypass_middleware))
+}
+
+async fn bypass_middleware(req: Request, next: Next) -> Response {
+    match requested(req.headers()) {
+        Ok(None) => return next.run(req).await,
+        Ok(Some(_)) => {}
+        Err(value) => {
+            return introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_header",
+                json!({
+                    "header": HEADER,
+                    "value": value,
+                    "expected": ["refresh", "ghost", "true", "false"],
+                }),
+            )
+            .into_response()
+        }
+    }
+    let actor = req
+        .extensions()
+        .get::<Actor>()
+        .cloned()
+        .unwrap_or_else(Actor::anonymous);
+    if actor.has(Scope::Support) {
+        return next.run(req).await;
+    }
+    introspection::json_error(
+        StatusCode::FORBIDDEN,
+        "insufficient_scope",
+        json!({
+            "required": Scope::Support.as_str(),
+            "token": actor.name,
+            "header": HEADER,
+        }),
+    )
+    .into_response()
+}
+
+/// Bypassing runs per mode and token, since start.
+#[derive(Default)]
+pub struct BypassCounts {
+    counts: Mutex<BTreeMap<(BypassMode, String), u64>>,
+}
+
+impl BypassCounts {
+    pub fn record(&self, mode: BypassMode, actor: &str) {
+        *self
+            .counts
+            .lock()
+            .unwrap()
+            .entry((mode, actor.to_string()))
+            .or_default() += 1;
+    }
+
+    /// `{"refresh": {"total": n, "by_token": {...}}, "ghost": ...}`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let counts = self.counts.lock().unwrap();
+        let mut out = serde_json::Map::new();
+        for mode in [BypassMode::Refresh, BypassMode::Ghost] {
+            let by_token: BTreeMap<&str, u64> = counts
+                .iter()
+                .filter(|((m, _), _)| *m == mode)
+                .map(|((_, actor), n)| (actor.as_str(), *n))
+                .collect();
+            let total: u64 = by_token.values().sum();
+            out.insert(
+                mode.as_str().to_string(),
+                json!({"total": total, "by_token": by_token}),
+            );
+        }
+        serde_json::Value::Object(out)
+    }
+}
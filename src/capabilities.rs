@@ -0,0 +1,390 @@
+//! `GET /v1/acip/capabilities`: what this deployment accepts, for the presenting token.
+//!
+//! The document is assembled from the live state on each request (it is cheap), so it always
+//! matches the limits and routes the caller will actually meet. Its `ETag` is a hash of the
+//! document itself, which covers policy revisions and every config knob it reports; callers
+//! poll with `If-None-Match` and get `304` until something changes.
+
+use crate::ingest::{PolicyInfo, SourceType};
+use crate::state::AppState;
+use crate::token_auth::{Actor, Scope};
+use crate::url_allowlist::EffectiveAllowlist;
+use crate::{app, b64, content_types, introspection};
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Accepted `source_type` values.
+    pub source_types: Vec<String>,
+    pub content_types: Vec<ContentTypeSupport>,
+    pub limits: Limits,
+    pub features: Features,
+    pub endpoints: Vec<Endpoint>,
+    pub schema_versions: SchemaVersions,
+    /// `live`, `stub` or `stub-open` (`ACIP_SENTRY_MODE`).
+    pub sentry_mode: String,
+    /// Policies the presenting token may select, ordered by name.
+    pub policies: Vec<PolicyCapability>,
+    pub token: TokenInfo,
+}
+
+/// How a content type is turned into model-facing text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContentTypeSupport {
+    pub content_type: String,
+    /// `text`, `html` or `extractor` (out-of-process PDF/SVG extraction).
+    pub handling: String,
+    pub available: bool,
+}
+
+/// Size limits enforced on this deployment. No request rate limits are enforced.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Limits {
+    /// Largest JSON request body on `/v1/acip/*` (larger bodies get `413`).
+    pub max_request_body_bytes: u64,
+    /// Largest decoded `bytes_b64` payload.
+    pub max_decoded_bytes: u64,
+    /// Characters of input the normalizer reads; the rest is not analyzed.
+    pub max_input_chars: u64,
+    /// Resumable uploads: largest `total_bytes`, chunk size and open sessions.
+    pub max_upload_bytes: u64,
+    pub upload_chunk_bytes: u64,
+    pub max_upload_sessions: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Features {
+    pub resumable_uploads: bool,
+    pub pdf_extraction: bool,
+    pub svg_extraction: bool,
+    /// Admin routes exist only when tokens are configured.
+    pub admin: bool,
+    /// `server.read_only`: queries only; mutating endpoints answer `403 read_only_mode`.
+    #[serde(default)]
+    pub read_only: bool,
+    /// `ingest_source?mode=async` and job polling.
+    #[serde(default)]
+    pub async_jobs: bool,
+    /// Not part of this release; reported so clients can probe uniformly.
+    pub streaming: bool,
+    pub batch: bool,
+    pub grpc: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub method: String,
+    pub path: String,
+    pub scope: Scope,
+    /// Served on this deployment.
+    pub available: bool,
+    /// The presenting token holds `scope`.
+    pub allowed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaVersions {
+    /// Versions of the decision schema (`GET /v1/acip/schema`).
+    pub decision: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyCapability {
+    pub name: String,
+    pub revision: Option<String>,
+    pub truncation: PolicyInfo,
+    /// Headers a request must carry to run under this policy.
+    pub required_headers: Vec<String>,
+    /// Headers the policy honors when present.
+    pub optional_headers: Vec<String>,
+    /// Content types accepted under this policy (`unsupported_content_type` otherwise).
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    /// Domains whose links are not external references under this policy.
+    #[serde(default)]
+    pub url_allowlist: EffectiveAllowlist,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+const ENDPOINTS: &[(&str, &str, Scope)] = &[
+    ("POST", "/v1/acip/ingest_source", Scope::Ingest),
+    ("POST", "/v1/acip/uploads", Scope::Ingest),
+    ("GET", "/v1/acip/uploads/:id", Scope::Ingest),
+    ("PUT", "/v1/acip/uploads/:id/chunks/:n", Scope::Ingest),
+    ("POST", "/v1/acip/uploads/:id/complete", Scope::Ingest),
+    ("GET", "/v1/acip/jobs/:id", Scope::Ingest),
+    ("GET", "/v1/acip/capabilities", Scope::Read),
+    ("GET", "/v1/acip/schema", Scope::Read),
+    ("GET", "/v1/acip/policies", Scope::Read),
+    ("GET", "/v1/acip/policy", Scope::Read),
+    ("GET", "/v1/acip/status", Scope::Read),
+    ("GET", "/v1/acip/reputation", Scope::Read),
+    ("GET", "/v1/acip/reputation/records", Scope::Read),
+    ("GET", "/v1/acip/stats", Scope::Read),
+    ("GET", "/v1/acip/stats/aggregate", Scope::PlatformAdmin),
+    ("GET", "/v1/acip/slow_requests", Scope::Support),
+    ("GET", "/v1/acip/incidents/:request_id", Scope::Support),
+    (
+        "POST",
+        "/v1/acip/feeds/:name/refresh",
+        Scope::ReputationAdmin,
+    ),
+    ("POST", "/v1/acip/admin/drain", Scope::Drain),
+    ("POST", "/v1/acip/admin/resume", Scope::Drain),
+    ("GET", "/v1/acip/admin/incidents/check", Scope::Support),
+];
+
+impl Capabilities {
+    /// The document as seen by `actor`.
+    pub fn build(state: &AppState, actor: &Actor) -> Self {
+        let uploads = state.uploads.settings();
+        let resumable_uploads = uploads.max_sessions > 0;
+        // Without tokens every request is anonymous and admin routes are refused.
+        let tokens_enabled = actor.name != Actor::ANONYMOUS;
+        let extractor = content_types::extractor_available();
+
+        let source_types = SourceType::ALL
+            .iter()
+            .filter_map(|t| serde_json::to_value(t).ok())
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect();
+
+        let content_types = content_types::SUPPORTED
+            .iter()
+            .map(|&(ct, handling)| ContentTypeSupport {
+                content_type: ct.to_string(),
+                handling: handling.to_string(),
+                available: content_types::handling_available(handling),
+            })
+            .collect();
+
+        let endpoints = ENDPOINTS
+            .iter()
+            .map(|&(method, path, scope)| {
+                let available = if path.starts_with("/v1/acip/uploads") {
+                    resumable_uploads
+                } else if path.starts_with("/v1/acip/admin/") {
+                    tokens_enabled
+                } else {
+                    true
+                };
+                let available = available && !(state.read_only && mutating(path));
+                Endpoint {
+                    method: method.to_string(),
+                    path: path.to_string(),
+                    scope,
+                    available,
+                    allowed: actor.has(scope),
+                }
+            })
+            .collect();
+
+        let truncation = PolicyInfo {
+            head: state.policy.head,
+            tail: state.policy.tail,
+            full_if_lte: state.policy.full_if_lte,
+        };
+        let policies = state
+            .policies
+            .list()
+            .into_iter()
+            .map(|name| {
+                let mut required_headers = vec![];
+                if tokens_enabled {
+                    required_headers.push("x-acip-token".to_string());
+                }
+                if name != "default" {
+                    required_headers.push("x-acip-policy".to_string());
+                }
+                PolicyCapability {
+                    revision: state.policies.revision(&name),
+                    truncation: truncation.clone(),
+                    required_headers,
+                    optional_headers: vec!["x-acip-allow-tools".to_string()],
+                    content_types: content_types::effective(state.policies.get(&name)),
+                    url_allowlist: state.feeds.effective_allowlist(
+                        &state
+                            .policies
+                            .get(&name)
+                            .map(|p| p.url_allowlist.clone())
+                            .unwrap_or_default(),
+                    ),
+                    name,
+                }
+            })
+            .collect();
+
+        let sentry_mode = std::env::var("ACIP_SENTRY_MODE")
+            .map(|m| m.trim().to_lowercase())
+            .unwrap_or_else(|_| "live".to_string());
+
+        Self {
+            source_types,
+            content_types,
+            limits: Limits {
+                max_request_body_bytes: app::MAX_REQUEST_BODY_BYTES as u64,
+                max_decoded_bytes: b64::DEFAULT_MAX_DECODED_BYTES as u64,
+                max_input_chars: state.normalize.max_input_chars as u64,
+                max_upload_bytes: uploads.max_upload_bytes,
+                upload_chunk_bytes: uploads.chunk_bytes,
+                max_upload_sessions: uploads.max_sessions as u64,
+            },
+            features: Features {
+                resumable_uploads,
+                pdf_extraction: extractor,
+                svg_extraction: extractor,
+                admin: tokens_enabled,
+                read_only: state.read_only,
+                async_jobs: !state.read_only,
+                streaming: false,
+                batch: false,
+                grpc: false,
+            },
+            endpoints,
+            schema_versions: SchemaVersions {
+                decision: vec![introspection::DECISION_SCHEMA_VERSION],
+            },
+            sentry_mode,
+            policies,
+            token: TokenInfo {
+                name: actor.name.clone(),
+                scopes: actor.scopes.iter().copied().collect(),
+            },
+        }
+    }
+
+    /// Strong entity tag for this document.
+    pub fn etag(&self) -> String {
+        let body = serde_json::to_vec(self).unwrap_or_default();
+        format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..16])
+    }
+
+    pub fn policy(&self, name: &str) -> Option<&PolicyCapability> {
+        self.policies.iter().find(|p| p.name == name)
+    }
+
+    /// Check an `ingest_source` request against this document before sending it.
+    pub fn check_ingest(
+        &self,
+        body_bytes: u64,
+        source_type: &str,
+        policy: &str,
+    ) -> Result<(), Rejection> {
+        if self.features.read_only {
+            return Err(Rejection::ReadOnlyMode);
+        }
+        let endpoint = self
+            .endpoints
+            .iter()
+            .find(|e| e.path == "/v1/acip/ingest_source");
+        if let Some(e) = endpoint.filter(|e| !e.allowed) {
+            return Err(Rejection::NotAllowed { scope: e.scope });
+        }
+        if body_bytes > self.limits.max_request_body_bytes {
+            return Err(Rejection::PayloadTooLarge {
+                size: body_bytes,
+                max: self.limits.max_request_body_bytes,
+            });
+        }
+        if !self.source_types.iter().any(|t| t == source_type) {
+            return Err(Rejection::UnknownSourceType(source_type.to_string()));
+        }
+        if self.policy(policy).is_none() {
+            return Err(Rejection::UnknownPolicy(policy.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Check a declared content type against `policy`'s accepted list. The sidecar also sniffs
+    /// the bytes, so passing this does not guarantee acceptance. Documents from sidecars that do
+    /// not report the list pass.
+    pub fn check_content_type(&self, policy: &str, content_type: &str) -> Result<(), Rejection> {
+        let declared = content_types::essence(content_type);
+        let accepted = self.policy(policy).map(|p| &p.content_types);
+        match accepted.filter(|list| !list.is_empty()) {
+            Some(list) if !list.contains(&declared) => Err(Rejection::UnsupportedContentType {
+                content_type: declared,
+                policy: policy.to_string(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Why [`Capabilities::check_ingest`] refused a request.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Rejection {
+    #[error("request body is {size} bytes; this sidecar accepts at most {max}")]
+    PayloadTooLarge { size: u64, max: u64 },
+    #[error("source_type {0:?} is not accepted")]
+    UnknownSourceType(String),
+    #[error("policy {0:?} is not available to this token")]
+    UnknownPolicy(String),
+    #[error("content type {content_type:?} is not accepted under policy {policy:?}")]
+    UnsupportedContentType {
+        content_type: String,
+        policy: String,
+    },
+    #[error("token lacks the {} scope", .scope.as_str())]
+    NotAllowed { scope: Scope },
+    #[error("sidecar is in read-only mode")]
+    ReadOnlyMode,
+}
+
+/// Endpoints refused in read-only mode.
+fn mutating(path: &str) -> bool {
+    path == "/v1/acip/ingest_source"
+        || path.starts_with("/v1/acip/jobs/")
+        || path.starts_with("/v1/acip/uploads")
+        || path.starts_with("/v1/acip/feeds/")
+        || path == "/v1/acip/admin/drain"
+        || path == "/v1/acip/admin/resume"
+}
+
+/// `If-None-Match` lists `etag` (or is `*`).
+fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim().trim_start_matches("W/"))
+        .any(|t| t == etag || t == "*")
+}
+
+pub async fn get_capabilities(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+    headers: HeaderMap,
+) -> Response {
+    let actor = actor.map(|Extension(a)| a).unwrap_or_else(Actor::anonymous);
+    let doc = Capabilities::build(&state, &actor);
+    let etag = doc.etag();
+    let etag_value = HeaderValue::from_str(&etag).expect("hex etag is a valid header value");
+
+    if not_modified(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(header::ETAG, etag_value)]).into_response();
+    }
+    (
+        StatusCode::OK,
+        [
+            (header::ETAG, etag_value),
+            (header::CACHE_CONTROL, HeaderValue::from_static("no-cache")),
+        ],
+        Json(doc),
+    )
+        .into_response()
+}
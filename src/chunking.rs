@@ -0,0 +1,466 @@
+//! Content-defined chunk hashes for large inputs, and partial reuse of a prior verdict.
+//!
+//! A single SHA-256 over the whole input changes with any edit, so a 400 MB bundle with one
+//! page appended looks like new content. With `chunking.mode = "content_defined"`, inputs of at
+//! least `min_bytes` are also cut into chunks at content-defined boundaries (a FastCDC-style
+//! gear hash: normalized chunking around `avg_chunk_bytes`, never below a quarter or above
+//! eight times that) and each chunk is hashed. An edit then only changes the chunks it touches.
+//!
+//! The chunk lists of recent inputs are kept per (tenant, policy), where the tenant is the
+//! token the input came with. When a new plain-text input shares at least `similarity` of its
+//! bytes with a kept one whose verdict is still fresh (see [`crate::verdicts`]), the sentry
+//! model reads only the changed chunks, each with `context_bytes` around it, and the decision
+//! is the stricter of that run and the prior verdict. The local scans still cover the whole
+//! input and the response fences all of it; a sanitize verdict becomes needs_review, since the
+//! model's sanitized text would only cover the changed regions. Markup is normalized as a whole document and
+//! helper-extracted formats (PDF, SVG) cannot be extracted by byte range, so those are chunked
+//! and indexed but always run in full. Below `min_bytes`, or in `whole` mode, nothing changes.
+//!
+//! Chunk hashes are plain SHA-256 like the last-decision map keys and never leave the process.
+
+use crate::config::ChunkingConfig;
+use crate::hashing;
+use crate::sentry::{Action, Decision, RiskLevel};
+use crate::verdicts::VerdictRecord;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::Range,
+    sync::Mutex,
+};
+
+pub const DEFAULT_MIN_BYTES: usize = 64 * 1024 * 1024;
+pub const DEFAULT_AVG_CHUNK_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_SIMILARITY: f64 = 0.9;
+pub const DEFAULT_CONTEXT_BYTES: usize = 4096;
+pub const DEFAULT_MAX_FILES: usize = 256;
+/// Smallest accepted `avg_chunk_bytes`.
+pub const MIN_AVG_CHUNK_BYTES: usize = 256;
+/// Put between the changed regions of a partially reused input.
+pub const OMITTED_MARKER: &str = "\n[... unchanged content omitted ...]\n";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkingMode {
+    /// One SHA-256 over the whole input.
+    #[default]
+    Whole,
+    /// Also content-defined chunk hashes above `min_bytes`.
+    ContentDefined,
+}
+
+/// A validated `[chunking]` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkingSettings {
+    pub mode: ChunkingMode,
+    pub min_bytes: usize,
+    pub avg_chunk_bytes: usize,
+    /// Least fraction of shared bytes for a partial reuse.
+    pub similarity: f64,
+    pub context_bytes: usize,
+    /// Chunk lists kept over all tenants; the oldest is dropped first.
+    pub max_files: usize,
+}
+
+impl Default for ChunkingSettings {
+    fn default() -> Self {
+        Self {
+            mode: ChunkingMode::Whole,
+            min_bytes: DEFAULT_MIN_BYTES,
+            avg_chunk_bytes: DEFAULT_AVG_CHUNK_BYTES,
+            similarity: DEFAULT_SIMILARITY,
+            context_bytes: DEFAULT_CONTEXT_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+        }
+    }
+}
+
+impl ChunkingSettings {
+    pub fn from_config(cfg: Option<&ChunkingConfig>) -> Result<Self> {
+        let d = Self::default();
+        let Some(cfg) = cfg else {
+            return Ok(d);
+        };
+        let s = Self {
+            mode: cfg.mode.unwrap_or(d.mode),
+            min_bytes: cfg.min_bytes.unwrap_or(d.min_bytes),
+            avg_chunk_bytes: cfg.avg_chunk_bytes.unwrap_or(d.avg_chunk_bytes),
+            similarity: cfg.similarity.unwrap_or(d.similarity),
+            context_bytes: cfg.context_bytes.unwrap_or(d.context_bytes),
+            max_files: cfg.max_files.unwrap_or(d.max_files).max(1),
+        };
+        if s.avg_chunk_bytes < MIN_AVG_CHUNK_BYTES {
+            bail!("chunking.avg_chunk_bytes must be at least {MIN_AVG_CHUNK_BYTES}");
+        }
+        if !(s.similarity > 0.0 && s.similarity <= 1.0) {
+            bail!("chunking.similarity must be in (0, 1]");
+        }
+        Ok(s)
+    }
+}
+
+/// One content-defined chunk of an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub offset: usize,
+    pub len: usize,
+    /// Plain SHA-256 hex of the chunk's bytes.
+    pub hash: String,
+}
+
+impl Chunk {
+    pub fn range(&self) -> Range<usize> {
+        self.offset..self.offset + self.len
+    }
+}
+
+/// Random-looking per-byte values for the rolling hash (splitmix64 of the byte value).
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut z = (i as u64)
+            .wrapping_add(1)
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// `bits` one bits at the top of the word, where the gear hash mixes the last 64 bytes.
+fn top_mask(bits: u32) -> u64 {
+    !0u64 << (64 - bits.clamp(1, 63))
+}
+
+/// Chunk ends of `data` for an average chunk size of `avg` bytes.
+pub fn cut_points(data: &[u8], avg: usize) -> Vec<usize> {
+    let avg = avg.max(MIN_AVG_CHUNK_BYTES);
+    let (min, max) = (avg / 4, avg * 8);
+    let bits = avg.ilog2();
+    // Harder to match before the average size, easier after: sizes cluster around `avg`.
+    let (mask_small, mask_large) = (top_mask(bits + 1), top_mask(bits - 1));
+
+    let mut cuts = vec![];
+    let mut start = 0;
+    while start < data.len() {
+        let rest = &data[start..];
+        let end = rest.len().min(max);
+        let mut cut = end;
+        if end > min {
+            let normal = avg.min(end);
+            let mut fp = 0u64;
+            let mut i = min;
+            while i < end {
+                fp = (fp << 1).wrapping_add(GEAR[rest[i] as usize]);
+                let mask = if i < normal { mask_small } else { mask_large };
+                if fp & mask == 0 {
+                    cut = i + 1;
+                    break;
+                }
+                i += 1;
+            }
+        }
+        start += cut;
+        cuts.push(start);
+    }
+    cuts
+}
+
+/// Cut `data` into content-defined chunks and hash each.
+pub fn chunk(data: &[u8], avg: usize) -> Vec<Chunk> {
+    let mut offset = 0;
+    cut_points(data, avg)
+        .into_iter()
+        .map(|end| {
+            let c = Chunk {
+                offset,
+                len: end - offset,
+                hash: hashing::internal_only_digest(&data[offset..end]),
+            };
+            offset = end;
+            c
+        })
+        .collect()
+}
+
+/// How much of a new input an indexed one already covered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlap {
+    /// Plain SHA-256 of the indexed input.
+    pub prior_sha: String,
+    /// Shared bytes over the new input's length.
+    pub shared_fraction: f64,
+    pub chunks: usize,
+    pub chunks_reused: usize,
+    /// Byte ranges of the new input not found in the indexed one, merged and in order.
+    pub changed: Vec<Range<usize>>,
+}
+
+impl Overlap {
+    pub fn changed_fraction(&self) -> f64 {
+        1.0 - self.shared_fraction
+    }
+}
+
+/// Compare `chunks` with an indexed chunk list. A chunk counts as shared when it followed the
+/// same chunk (or started the input) there too, so moving content around is a change at
+/// each seam.
+pub fn overlap(prior_sha: &str, prior: &[(String, usize)], chunks: &[Chunk]) -> Overlap {
+    let mut known: HashSet<(&str, &str)> = HashSet::new();
+    let mut prev = "";
+    for (h, _) in prior {
+        known.insert((prev, h.as_str()));
+        prev = h;
+    }
+    let total: usize = chunks.iter().map(|c| c.len).sum();
+    let (mut shared, mut reused) = (0, 0);
+    let mut changed: Vec<Range<usize>> = vec![];
+    let mut prev = "";
+    for c in chunks {
+        let seen = known.contains(&(prev, c.hash.as_str()));
+        prev = &c.hash;
+        if seen {
+            shared += c.len;
+            reused += 1;
+            continue;
+        }
+        match changed.last_mut() {
+            Some(last) if last.end == c.offset => last.end = c.offset + c.len,
+            _ => changed.push(c.range()),
+        }
+    }
+    Overlap {
+        prior_sha: prior_sha.to_string(),
+        shared_fraction: if total == 0 {
+            0.0
+        } else {
+            shared as f64 / total as f64
+        },
+        chunks: chunks.len(),
+        chunks_reused: reused,
+        changed,
+    }
+}
+
+/// The changed regions of `text`, each widened by `context` bytes on both sides (to character
+/// boundaries), merged where they touch and joined with [`OMITTED_MARKER`].
+pub fn changed_text(text: &str, changed: &[Range<usize>], context: usize) -> String {
+    let mut windows: Vec<Range<usize>> = vec![];
+    for r in changed {
+        let mut start = r.start.saturating_sub(context).min(text.len());
+        let mut end = r.end.saturating_add(context).min(text.len());
+        while !text.is_char_boundary(start) {
+            start -= 1;
+        }
+        while !text.is_char_boundary(end) {
+            end += 1;
+        }
+        match windows.last_mut() {
+            Some(last) if start <= last.end => last.end = last.end.max(end),
+            _ => windows.push(start..end),
+        }
+    }
+    let mut out = String::new();
+    if windows.first().is_some_and(|w| w.start > 0) {
+        out.push_str(OMITTED_MARKER);
+    }
+    for (i, w) in windows.iter().enumerate() {
+        if i > 0 {
+            out.push_str(OMITTED_MARKER);
+        }
+        out.push_str(&text[w.clone()]);
+    }
+    if windows.last().is_some_and(|w| w.end < text.len()) {
+        out.push_str(OMITTED_MARKER);
+    }
+    out
+}
+
+fn action_rank(a: &Action) -> u8 {
+    match a {
+        Action::Allow => 0,
+        Action::Sanitize => 1,
+        Action::NeedsReview => 2,
+        Action::Block => 3,
+    }
+}
+
+fn risk_rank(r: &RiskLevel) -> u8 {
+    match r {
+        RiskLevel::Low => 0,
+        RiskLevel::Medium => 1,
+        RiskLevel::High => 2,
+    }
+}
+
+/// The stricter of `decision` (on the changed regions) and the prior verdict, per field.
+pub fn merge_prior(mut decision: Decision, prior: &VerdictRecord) -> Decision {
+    decision.tools_allowed &= prior.tools_allowed;
+    if risk_rank(&prior.risk_level) > risk_rank(&decision.risk_level) {
+        decision.risk_level = prior.risk_level.clone();
+    }
+    if action_rank(&prior.action) > action_rank(&decision.action) {
+        decision.action = prior.action.clone();
+    }
+    decision
+}
+
+/// Partial reuse as reported in the ingest response.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChunkReuse {
+    /// The earlier input's id ([`crate::hashing::IdHasher::export_id`] of its digest).
+    pub prior_content_id: String,
+    pub changed_fraction: f64,
+    pub chunks: usize,
+    pub chunks_reused: usize,
+}
+
+/// Finish a partially reused run: `decision` (on the changed regions) merged with the prior
+/// verdict, fencing the whole input as `fenced`, with a `partial_reuse` reason.
+pub fn apply_reuse(
+    decision: Decision,
+    prior: &VerdictRecord,
+    reuse: &ChunkReuse,
+    fenced: String,
+) -> Decision {
+    let mut d = merge_prior(decision, prior);
+    d.fenced_content = fenced;
+    if d.action == Action::Sanitize {
+        d.action = Action::NeedsReview;
+    }
+    d.reasons.push(format!(
+        "partial_reuse: {:.1}% of the content changed ({} of {} chunks read); the rest keeps \
+         the verdict of {}",
+        reuse.changed_fraction * 100.0,
+        reuse.chunks - reuse.chunks_reused,
+        reuse.chunks,
+        reuse.prior_content_id
+    ));
+    d
+}
+
+#[derive(Debug, Clone)]
+struct Indexed {
+    tenant: String,
+    policy: String,
+    sha: String,
+    chunks: Vec<(String, usize)>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Counters {
+    indexed: u64,
+    partial_reuses: u64,
+    /// Chunks whose text went through the pipeline.
+    chunks_extracted: u64,
+    /// Chunks taken from a prior input instead.
+    chunks_reused: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    files: VecDeque<Indexed>,
+    counters: Counters,
+}
+
+/// Chunk lists of recent large inputs, per tenant and policy.
+#[derive(Default)]
+pub struct ChunkIndex {
+    settings: ChunkingSettings,
+    inner: Mutex<Inner>,
+}
+
+impl ChunkIndex {
+    pub fn new(settings: ChunkingSettings) -> Self {
+        Self {
+            settings,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn settings(&self) -> &ChunkingSettings {
+        &self.settings
+    }
+
+    /// Whether an input of `len` bytes is chunked.
+    pub fn applies(&self, len: usize) -> bool {
+        self.settings.mode == ChunkingMode::ContentDefined && len >= self.settings.min_bytes
+    }
+
+    pub fn chunk(&self, data: &[u8]) -> Vec<Chunk> {
+        chunk(data, self.settings.avg_chunk_bytes)
+    }
+
+    /// The kept input of `tenant` under `policy` sharing the most with `chunks`, if it shares
+    /// at least `similarity` and something changed. The same content (`sha`) is not a partial
+    /// match.
+    pub fn best_match(
+        &self,
+        tenant: &str,
+        policy: &str,
+        sha: &str,
+        chunks: &[Chunk],
+    ) -> Option<Overlap> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .files
+            .iter()
+            .filter(|f| f.tenant == tenant && f.policy == policy && f.sha != sha)
+            .map(|f| overlap(&f.sha, &f.chunks, chunks))
+            .filter(|o| o.shared_fraction >= self.settings.similarity && !o.changed.is_empty())
+            .max_by(|a, b| a.shared_fraction.total_cmp(&b.shared_fraction))
+    }
+
+    /// Keep the chunk list of `sha` for later inputs of `tenant` under `policy`.
+    pub fn record(&self, tenant: &str, policy: &str, sha: &str, chunks: &[Chunk]) {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .files
+            .retain(|f| !(f.tenant == tenant && f.policy == policy && f.sha == sha));
+        inner.files.push_back(Indexed {
+            tenant: tenant.to_string(),
+            policy: policy.to_string(),
+            sha: sha.to_string(),
+            chunks: chunks.iter().map(|c| (c.hash.clone(), c.len)).collect(),
+        });
+        while inner.files.len() > self.settings.max_files {
+            inner.files.pop_front();
+        }
+        inner.counters.indexed += 1;
+    }
+
+    /// Count a chunked run: `extracted` chunks read, `reused` taken from a prior input.
+    pub fn account(&self, extracted: usize, reused: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.counters.chunks_extracted += extracted as u64;
+        inner.counters.chunks_reused += reused as u64;
+        if reused > 0 {
+            inner.counters.partial_reuses += 1;
+        }
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        let inner = self.inner.lock().unwrap();
+        let mut v = json!({
+            "mode": self.settings.mode,
+            "min_bytes": self.settings.min_bytes,
+            "avg_chunk_bytes": self.settings.avg_chunk_bytes,
+            "similarity": self.settings.similarity,
+            "files": inner.files.len(),
+            "max_files": self.settings.max_files,
+        });
+        if let (Some(obj), Value::Object(counters)) = (
+            v.as_object_mut(),
+            serde_json::to_value(&inner.counters).unwrap_or_default(),
+        ) {
+            obj.extend(counters);
+        }
+        v
+    }
+}
@@ -0,0 +1,870 @@
+//! Typed blocking client for the sidecar's HTTP API (used by `acipctl` and tests).
+//!
+//! Every request goes through [`Client::call`], so the token and selected policy are always
+//! attached and a failure always has an [`ApiError`] at the root of its chain.
+//!
+//! Do not call it from inside an async runtime; `reqwest::blocking` panics there.
+
+use anyhow::{anyhow, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::build_info;
+use crate::capabilities::{Capabilities, Rejection};
+use crate::jobs::{JobState, JobStatus};
+use crate::multipart;
+use crate::secrets::Secret;
+
+pub struct Client {
+    base_url: String,
+    token: Option<Secret>,
+    /// Sent as `X-ACIP-Policy` on every call unless the call names its own.
+    policy: Option<String>,
+    http: reqwest::blocking::Client,
+    /// Last capabilities document and its ETag.
+    capabilities: Mutex<Option<(String, Capabilities)>>,
+}
+
+impl Client {
+    pub fn new(base_url: &str, token: Option<&str>) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            token: token.map(Secret::new),
+            policy: None,
+            http: reqwest::blocking::Client::new(),
+            capabilities: Mutex::new(None),
+        }
+    }
+
+    /// Select `policy` for every call (`X-ACIP-Policy`).
+    pub fn with_policy(mut self, policy: Option<String>) -> Self {
+        self.policy = policy.filter(|p| !p.trim().is_empty());
+        self
+    }
+
+    /// Start a call to `path`, with the token and policy attached.
+    pub fn call(&self, method: reqwest::Method, path: &str) -> ApiCall {
+        let url = format!("{}{}", self.base_url, path);
+        let mut req = self.http.request(method.clone(), &url);
+        // A token that is not a valid header value could never authenticate anyway.
+        if let Some(Ok(t)) = self.token.as_ref().map(Secret::header_value) {
+            req = req.header("X-ACIP-Token", t);
+        }
+        if let Some(p) = &self.policy {
+            req = req.header("X-ACIP-Policy", p);
+        }
+        ApiCall {
+            request: format!("{method} {url}"),
+            req,
+        }
+    }
+
+    /// GET `path` with `query` and decode the JSON body; non-2xx responses are errors.
+    pub fn get_json<T: DeserializeOwned>(&self, path: &str, query: &[(&str, String)]) -> Result<T> {
+        self.call(reqwest::Method::GET, path)
+            .query(query)
+            .send_json()
+    }
+
+    /// Iterate every item of a paginated list endpoint, fetching pages of `page_size` only as
+    /// the iterator reaches them.
+    pub fn paginate<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+        page_size: usize,
+    ) -> Pages<'_, T> {
+        Pages {
+            client: self,
+            path: path.to_string(),
+            query: query
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.clone()))
+                .collect(),
+            page_size,
+            buffer: VecDeque::new(),
+            next: Some(None),
+        }
+    }
+}
+
+/// The package version in a `/v1/acip/status` body: `version.version`, or the plain string
+/// older servers report.
+pub fn status_version(status: &Value) -> Option<&str> {
+    match &status["version"] {
+        Value::String(s) => Some(s),
+        other => other["version"].as_str(),
+    }
+}
+
+#[derive(Deserialize)]
+struct PageBody<T> {
+    items: Vec<T>,
+    next_cursor: Option<String>,
+}
+
+/// Lazy iterator returned by [`Client::paginate`]. Stops after the first error.
+pub struct Pages<'a, T> {
+    client: &'a Client,
+    path: String,
+    query: Vec<(String, String)>,
+    page_size: usize,
+    buffer: VecDeque<T>,
+    /// `None` when done; `Some(None)` before the first page; `Some(Some(c))` for a cursor.
+    next: Option<Option<String>>,
+}
+
+impl<T: DeserializeOwned> Pages<'_, T> {
+    fn fetch(&mut self, cursor: Option<String>) -> Result<PageBody<T>> {
+        let mut query: Vec<(&str, String)> = self
+            .query
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.clone()))
+            .collect();
+        query.push(("limit", self.page_size.to_string()));
+        if let Some(c) = cursor {
+            query.push(("cursor", c));
+        }
+        let v: Value = self.client.get_json(&self.path, &query)?;
+        serde_json::from_value(v).with_context(|| format!("{} is not a paginated list", self.path))
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for Pages<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() {
+            let cursor = self.next.take()?;
+            match self.fetch(cursor) {
+                Ok(page) => {
+                    self.buffer.extend(page.items);
+                    self.next = page.next_cursor.map(Some);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+impl Client {
+    /// The server's capabilities document, revalidated with `If-None-Match` when one is
+    /// already cached.
+    pub fn capabilities(&self) -> Result<Capabilities> {
+        let cached = self.capabilities.lock().unwrap().clone();
+        let mut call = self.call(reqwest::Method::GET, "/v1/acip/capabilities");
+        if let Some((etag, _)) = &cached {
+            call = call.header(reqwest::header::IF_NONE_MATCH.as_str(), etag);
+        }
+        let resp = call.send_raw()?;
+        if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+            if let Some((_, caps)) = cached {
+                return Ok(caps);
+            }
+        }
+        let etag = resp
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let caps: Capabilities = resp.json()?;
+        if let Some(etag) = etag {
+            *self.capabilities.lock().unwrap() = Some((etag, caps.clone()));
+        }
+        Ok(caps)
+    }
+
+    /// Cached capabilities, fetching them on first use.
+    fn cached_capabilities(&self) -> Result<Capabilities> {
+        if let Some((_, caps)) = self.capabilities.lock().unwrap().clone() {
+            return Ok(caps);
+        }
+        self.capabilities()
+    }
+
+    /// POST an `ingest_source` request and return the decision.
+    ///
+    /// The request is first checked against the cached capabilities (body size, source type,
+    /// policy, scope), so a request the server would refuse is not sent at all.
+    pub fn ingest(&self, body: &Value, headers: &[(&str, String)]) -> Result<Value> {
+        self.send_ingest("/v1/acip/ingest_source", body, headers)?
+            .json()
+    }
+
+    /// Submit an `ingest_source` request as an async job (`mode=async`) and return the
+    /// pending job; poll it with [`Client::wait_for_job`].
+    pub fn ingest_async(&self, body: &Value, headers: &[(&str, String)]) -> Result<JobStatus> {
+        let resp = self.send_ingest("/v1/acip/ingest_source?mode=async", body, headers)?;
+        if resp.status() != reqwest::StatusCode::ACCEPTED {
+            return Err(resp.into_error());
+        }
+        resp.json()
+    }
+
+    /// Poll a job until it is `complete` or `failed`, waiting `poll` between the first
+    /// checks and doubling the wait up to `max_poll`.
+    pub fn wait_for_job(
+        &self,
+        job_id: &str,
+        poll: Duration,
+        max_poll: Duration,
+    ) -> Result<JobStatus> {
+        let path = format!("/v1/acip/jobs/{job_id}");
+        let mut wait = poll;
+        loop {
+            let job: JobStatus = self.get_json(&path, &[])?;
+            if matches!(job.status, JobState::Complete | JobState::Failed) {
+                return Ok(job);
+            }
+            std::thread::sleep(wait);
+            wait = (wait * 2).min(max_poll);
+        }
+    }
+
+    /// The server's package version from `/v1/acip/status`.
+    pub fn server_version(&self) -> Result<String> {
+        let v: Value = self.get_json("/v1/acip/status", &[])?;
+        status_version(&v)
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("/v1/acip/status does not report a version"))
+    }
+
+    /// A one-line warning when the server is not [`build_info::compatible`] with this client.
+    pub fn version_skew(&self) -> Result<Option<String>> {
+        let server = self.server_version()?;
+        Ok(build_info::skew_warning(
+            &build_info::current().version,
+            &server,
+        ))
+    }
+
+    /// POST an `ingest_source` request as `multipart/form-data` and return the decision:
+    /// `metadata` is the JSON request without `text`/`bytes_b64`, and `content` is sent as the
+    /// `file` part as is, without base64. Checked against the capabilities like
+    /// [`Client::ingest`].
+    pub fn ingest_multipart(
+        &self,
+        metadata: &Value,
+        filename: &str,
+        content_type: &str,
+        content: Vec<u8>,
+        headers: &[(&str, String)],
+    ) -> Result<Value> {
+        let (form_type, body) = multipart::encode(&[
+            multipart::Part::field("metadata", serde_json::to_vec(metadata)?),
+            multipart::Part::file("file", filename, content_type, content),
+        ]);
+        // The server derives a missing source type from the content type; only check one
+        // that is given.
+        let source_type = metadata["source_type"].as_str();
+        let content_type = metadata["content_type"].as_str().unwrap_or(content_type);
+        self.check_ingest(body.len(), source_type, Some(content_type), headers)?;
+
+        self.call(reqwest::Method::POST, "/v1/acip/ingest_source")
+            .header("content-type", form_type)
+            .headers(headers)
+            .body(body)
+            .send_json()
+    }
+
+    fn send_ingest(
+        &self,
+        path: &str,
+        body: &Value,
+        headers: &[(&str, String)],
+    ) -> Result<ApiResponse> {
+        let encoded = serde_json::to_vec(body)?;
+        let source_type = Some(body["source_type"].as_str().unwrap_or_default());
+        self.check_ingest(
+            encoded.len(),
+            source_type,
+            body["content_type"].as_str(),
+            headers,
+        )?;
+
+        self.call(reqwest::Method::POST, path)
+            .header("content-type", "application/json")
+            .headers(headers)
+            .body(encoded)
+            .send_raw()
+    }
+
+    /// Refuse an ingest the cached capabilities say the server would refuse.
+    fn check_ingest(
+        &self,
+        body_bytes: usize,
+        source_type: Option<&str>,
+        content_type: Option<&str>,
+        headers: &[(&str, String)],
+    ) -> Result<()> {
+        let policy = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("x-acip-policy"))
+            .map(|(_, v)| v.trim())
+            .or(self.policy.as_deref())
+            .filter(|v| !v.is_empty())
+            .unwrap_or("default");
+        let caps = self.cached_capabilities()?;
+        // A form without a source type is checked as `file`, which every server accepts.
+        caps.check_ingest(body_bytes as u64, source_type.unwrap_or("file"), policy)
+            .context("request refused before sending")?;
+        if let Some(content_type) = content_type {
+            caps.check_content_type(policy, content_type)
+                .context("request refused before sending")?;
+        }
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Items of a streamed list endpoint, read as they arrive.
+    ///
+    /// NDJSON is requested; a server answering with a JSON array (or a paginated `items`
+    /// envelope) is also accepted, but that body is parsed in one piece.
+    pub fn stream_items<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<ItemStream<T>> {
+        let resp = self
+            .call(reqwest::Method::GET, path)
+            .query(query)
+            .header(
+                reqwest::header::ACCEPT.as_str(),
+                format!("{}, application/json;q=0.5", crate::json_stream::NDJSON),
+            )
+            .send()?;
+        let ndjson = resp
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|ct| ct.starts_with(crate::json_stream::NDJSON));
+        if ndjson {
+            return Ok(ItemStream {
+                source: ItemSource::Lines(BufReader::new(resp.into_inner()).lines()),
+                _item: PhantomData,
+            });
+        }
+        let (request, status) = (resp.request().to_string(), resp.status());
+        let not_a_list = |v: &Value| ApiError::Parse {
+            request: request.clone(),
+            status,
+            reason: "not a list".to_string(),
+            excerpt: excerpt(&v.to_string()),
+        };
+        let items = match resp.json::<Value>()? {
+            Value::Array(items) => items,
+            Value::Object(mut o) => match o.remove("items") {
+                Some(Value::Array(items)) => items,
+                _ => return Err(not_a_list(&Value::Object(o)).into()),
+            },
+            other => return Err(not_a_list(&other).into()),
+        };
+        Ok(ItemStream {
+            source: ItemSource::Parsed(items.into_iter()),
+            _item: PhantomData,
+        })
+    }
+}
+
+/// Iterator returned by [`Client::stream_items`]. Stops at the end of the body.
+pub struct ItemStream<T> {
+    source: ItemSource,
+    _item: PhantomData<T>,
+}
+
+enum ItemSource {
+    Lines(std::io::Lines<BufReader<reqwest::blocking::Response>>),
+    Parsed(std::vec::IntoIter<Value>),
+}
+
+impl<T: DeserializeOwned> Iterator for ItemStream<T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            ItemSource::Lines(lines) => loop {
+                let line = match lines.next()? {
+                    Ok(l) => l,
+                    Err(e) => return Some(Err(anyhow!(e).context("read stream"))),
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Some(serde_json::from_str(&line).context("parse stream item"));
+            },
+            ItemSource::Parsed(items) => items
+                .next()
+                .map(|v| serde_json::from_value(v).context("parse list item")),
+        }
+    }
+}
+
+/// Local record of an interrupted resumable upload, so a later run can pick it up.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UploadState {
+    pub upload_id: String,
+    pub total_bytes: u64,
+    pub sha256: String,
+}
+
+/// How hard [`Client::upload_resumable`] tries before giving up on a chunk.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Extra attempts per chunk after the first failure.
+    pub chunk_retries: u32,
+    /// Wait before the first retry; doubled for each further one.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            chunk_retries: 5,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Size and hex SHA-256 of the file at `path`, read in blocks.
+fn file_digest(path: &Path) -> Result<(u64, String)> {
+    let mut f = fs::File::open(path).with_context(|| format!("open {path:?}"))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = f.read(&mut buf).with_context(|| format!("read {path:?}"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        total += n as u64;
+    }
+    Ok((total, hex::encode(hasher.finalize())))
+}
+
+fn read_chunk(path: &Path, offset: u64, len: u64) -> Result<Vec<u8>> {
+    let mut f = fs::File::open(path).with_context(|| format!("open {path:?}"))?;
+    f.seek(SeekFrom::Start(offset))?;
+    let mut buf = vec![0u8; len as usize];
+    f.read_exact(&mut buf)
+        .with_context(|| format!("read {len} bytes at {offset} from {path:?}"))?;
+    Ok(buf)
+}
+
+/// How a call to the sidecar failed. Errors returned by [`Client`] carry one of these as
+/// their root cause whenever the sidecar was involved; `acipctl` renders and maps them to
+/// exit codes.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// No response at all: refused connection, DNS, TLS, timeout, or a body cut off.
+    #[error("{request}: no response from the sidecar: {reason}")]
+    Connect { request: String, reason: String },
+    /// The sidecar answered with a non-2xx status. `code` is the body's `error` when it is
+    /// the usual `{"error", "extra"}` shape; otherwise `message` holds the raw body, cut short.
+    #[error("{request} failed: {status}{}{}",
+        .code.as_ref().map(|c| format!(" {c}")).unwrap_or_default(),
+        if .message.is_empty() { String::new() } else { format!(": {}", .message) })]
+    Status {
+        request: String,
+        status: reqwest::StatusCode,
+        code: Option<String>,
+        message: String,
+        /// The body when it is JSON, else `null`.
+        body: Value,
+    },
+    /// The sidecar answered, but not with what the call expects.
+    #[error("{request}: unreadable {status} response: {reason}: {excerpt}")]
+    Parse {
+        request: String,
+        status: reqwest::StatusCode,
+        reason: String,
+        excerpt: String,
+    },
+}
+
+impl ApiError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Connect { .. } => "connect",
+            Self::Status { .. } => "status",
+            Self::Parse { .. } => "parse",
+        }
+    }
+
+    /// The error for a non-2xx answer with body `raw`.
+    pub fn status(request: &str, status: reqwest::StatusCode, raw: &[u8]) -> Self {
+        let body: Value = serde_json::from_slice(raw).unwrap_or(Value::Null);
+        let code = body["error"].as_str().map(str::to_string);
+        let message = match (&code, &body) {
+            (Some(_), _) => match (&body["message"], &body["extra"]) {
+                (Value::String(m), _) | (_, Value::String(m)) => m.clone(),
+                (_, Value::Null) => String::new(),
+                (_, Value::Object(extra)) if extra.is_empty() => String::new(),
+                (_, extra) => excerpt(&extra.to_string()),
+            },
+            (None, Value::Null) => excerpt(String::from_utf8_lossy(raw).trim()),
+            (None, v) => excerpt(&v.to_string()),
+        };
+        Self::Status {
+            request: request.to_string(),
+            status,
+            code,
+            message,
+            body,
+        }
+    }
+
+    /// Machine-readable form: `kind`, `request` and the fields of the variant.
+    pub fn to_json(&self) -> Value {
+        let mut v = match self {
+            Self::Connect { request, reason } => {
+                serde_json::json!({"request": request, "reason": reason})
+            }
+            Self::Status {
+                request,
+                status,
+                code,
+                message,
+                body,
+            } => serde_json::json!({
+                "request": request,
+                "status": status.as_u16(),
+                "code": code,
+                "message": message,
+                "body": body,
+            }),
+            Self::Parse {
+                request,
+                status,
+                reason,
+                excerpt,
+            } => serde_json::json!({
+                "request": request,
+                "status": status.as_u16(),
+                "reason": reason,
+                "excerpt": excerpt,
+            }),
+        };
+        v["kind"] = Value::from(self.kind());
+        v
+    }
+}
+
+/// Longest body text quoted in an [`ApiError`].
+const EXCERPT_CHARS: usize = 300;
+
+fn excerpt(s: &str) -> String {
+    if s.chars().count() <= EXCERPT_CHARS {
+        return s.to_string();
+    }
+    let cut: String = s.chars().take(EXCERPT_CHARS).collect();
+    format!("{cut}… ({} bytes)", s.len())
+}
+
+/// `reason` of `e` with its sources, which is where reqwest says what actually went wrong.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut out = e.to_string();
+    let mut source = e.source();
+    while let Some(s) = source {
+        out.push_str(&format!(": {s}"));
+        source = s.source();
+    }
+    out
+}
+
+/// One request, started with [`Client::call`]. Every way it can fail is an [`ApiError`].
+pub struct ApiCall {
+    /// `METHOD url`, for errors.
+    request: String,
+    req: reqwest::blocking::RequestBuilder,
+}
+
+impl ApiCall {
+    pub fn query(mut self, query: &[(&str, String)]) -> Self {
+        self.req = self.req.query(query);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: impl AsRef<str>) -> Self {
+        self.req = self.req.header(name, value.as_ref());
+        self
+    }
+
+    pub fn headers(self, headers: &[(&str, String)]) -> Self {
+        headers.iter().fold(self, |call, (k, v)| call.header(k, v))
+    }
+
+    pub fn json(mut self, body: &Value) -> Self {
+        self.req = self.req.json(body);
+        self
+    }
+
+    pub fn body(mut self, body: Vec<u8>) -> Self {
+        self.req = self.req.body(body);
+        self
+    }
+
+    /// Bound the whole call, connecting included.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.req = self.req.timeout(timeout);
+        self
+    }
+
+    /// Send; any answer, whatever its status, is `Ok`.
+    pub fn send_raw(self) -> Result<ApiResponse> {
+        match self.req.send() {
+            Ok(resp) => Ok(ApiResponse {
+                request: self.request,
+                resp,
+            }),
+            Err(e) => Err(ApiError::Connect {
+                request: self.request,
+                reason: error_chain(&e),
+            }
+            .into()),
+        }
+    }
+
+    /// Send; a non-2xx answer is an error.
+    pub fn send(self) -> Result<ApiResponse> {
+        self.send_raw()?.ok()
+    }
+
+    /// Send and decode the JSON body of a 2xx answer.
+    pub fn send_json<T: DeserializeOwned>(self) -> Result<T> {
+        self.send_raw()?.json()
+    }
+}
+
+/// An answer to an [`ApiCall`].
+pub struct ApiResponse {
+    request: String,
+    resp: reqwest::blocking::Response,
+}
+
+impl ApiResponse {
+    pub fn request(&self) -> &str {
+        &self.request
+    }
+
+    pub fn status(&self) -> reqwest::StatusCode {
+        self.resp.status()
+    }
+
+    pub fn headers(&self) -> &reqwest::header::HeaderMap {
+        self.resp.headers()
+    }
+
+    pub fn into_inner(self) -> reqwest::blocking::Response {
+        self.resp
+    }
+
+    /// `self`, or the error for it when the status is not 2xx.
+    pub fn ok(self) -> Result<Self> {
+        if self.status().is_success() {
+            return Ok(self);
+        }
+        Err(self.into_error())
+    }
+
+    /// The [`ApiError::Status`] for this answer, with [`Rejection::ReadOnlyMode`] as context
+    /// when a read-only sidecar refused the call.
+    pub fn into_error(self) -> anyhow::Error {
+        let status = self.status();
+        let raw = self.resp.bytes().unwrap_or_default();
+        let err = ApiError::status(&self.request, status, &raw);
+        let read_only = matches!(&err, ApiError::Status { code: Some(c), .. }
+            if c == crate::read_only::ERROR_CODE);
+        let err = anyhow::Error::new(err);
+        if read_only {
+            return err.context(Rejection::ReadOnlyMode);
+        }
+        err
+    }
+
+    /// The whole body as text, whatever the status.
+    pub fn text(self) -> Result<String> {
+        let request = self.request;
+        self.resp.text().map_err(|e| {
+            ApiError::Connect {
+                request,
+                reason: format!("read response: {}", error_chain(&e)),
+            }
+            .into()
+        })
+    }
+
+    /// Decode the JSON body of a 2xx answer.
+    pub fn json<T: DeserializeOwned>(self) -> Result<T> {
+        let this = self.ok()?;
+        let status = this.status();
+        let request = this.request.clone();
+        let raw = this.text()?;
+        serde_json::from_str(&raw).map_err(|e| {
+            ApiError::Parse {
+                request,
+                status,
+                reason: e.to_string(),
+                excerpt: excerpt(raw.trim()),
+            }
+            .into()
+        })
+    }
+}
+
+impl Client {
+    /// Upload `path` with the resumable upload protocol and return the ingest decision.
+    ///
+    /// `meta` holds the `ingest_source` metadata fields (`source_id`, `source_type`,
+    /// `content_type`, ...); `headers` are sent when the session is created (policy and tool
+    /// selection). Progress is recorded in `state_file`: if a previous run for the same file
+    /// content left one behind and the session is still open, only the missing chunks are
+    /// sent. The state file is removed once the upload is completed.
+    pub fn upload_resumable(
+        &self,
+        path: &Path,
+        meta: &Value,
+        headers: &[(&str, String)],
+        state_file: &Path,
+        retry: &RetryPolicy,
+    ) -> Result<Value> {
+        let (total_bytes, sha256) = file_digest(path)?;
+
+        let resumed = self.resume_session(state_file, total_bytes, &sha256)?;
+        let status = match resumed {
+            Some(status) => status,
+            None => {
+                let status = self.create_session(meta, headers, total_bytes)?;
+                let state = UploadState {
+                    upload_id: status.upload_id.clone(),
+                    total_bytes,
+                    sha256: sha256.clone(),
+                };
+                fs::write(state_file, serde_json::to_vec_pretty(&state)?)
+                    .with_context(|| format!("write {state_file:?}"))?;
+                status
+            }
+        };
+
+        for index in &status.missing {
+            let offset = index * status.chunk_bytes;
+            let len = status.chunk_bytes.min(total_bytes - offset);
+            let chunk = read_chunk(path, offset, len)?;
+            self.put_chunk_with_retry(&status.upload_id, *index, &chunk, retry)?;
+        }
+
+        let resp = self
+            .call(
+                reqwest::Method::POST,
+                &format!("/v1/acip/uploads/{}/complete", status.upload_id),
+            )
+            .json(&serde_json::json!({ "sha256": sha256 }))
+            .send_raw()
+            .context("complete upload")?;
+        let code = resp.status();
+        if code.is_success() || code == reqwest::StatusCode::UNPROCESSABLE_ENTITY {
+            // Done, or the session can never complete with this content: start fresh next time.
+            let _ = fs::remove_file(state_file);
+        }
+        resp.json().context("complete upload")
+    }
+
+    /// The open session recorded in `state_file`, if it matches this content and still exists.
+    fn resume_session(
+        &self,
+        state_file: &Path,
+        total_bytes: u64,
+        sha256: &str,
+    ) -> Result<Option<UploadStatusBody>> {
+        let Ok(raw) = fs::read(state_file) else {
+            return Ok(None);
+        };
+        let Ok(state) = serde_json::from_slice::<UploadState>(&raw) else {
+            return Ok(None);
+        };
+        if state.total_bytes != total_bytes || state.sha256 != sha256 {
+            return Ok(None);
+        }
+        let resp = self
+            .call(
+                reqwest::Method::GET,
+                &format!("/v1/acip/uploads/{}", state.upload_id),
+            )
+            .send_raw()
+            .context("query upload session")?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            // Expired or the sidecar restarted; the chunks are gone.
+            return Ok(None);
+        }
+        Ok(Some(resp.json().context("query upload session")?))
+    }
+
+    fn create_session(
+        &self,
+        meta: &Value,
+        headers: &[(&str, String)],
+        total_bytes: u64,
+    ) -> Result<UploadStatusBody> {
+        let mut body = meta.clone();
+        body["total_bytes"] = Value::from(total_bytes);
+        self.call(reqwest::Method::POST, "/v1/acip/uploads")
+            .json(&body)
+            .headers(headers)
+            .send_json()
+            .context("create upload session")
+    }
+
+    fn put_chunk_with_retry(
+        &self,
+        upload_id: &str,
+        index: u64,
+        chunk: &[u8],
+        retry: &RetryPolicy,
+    ) -> Result<()> {
+        let sha = hex::encode(Sha256::digest(chunk));
+        let path = format!("/v1/acip/uploads/{upload_id}/chunks/{index}");
+        let mut wait = retry.backoff;
+        let mut attempt = 0;
+        loop {
+            let res = self
+                .call(reqwest::Method::PUT, &path)
+                .header("X-ACIP-Chunk-Sha256", &sha)
+                .header("content-type", "application/octet-stream")
+                .body(chunk.to_vec())
+                .send_raw();
+            let err = match res {
+                Ok(resp) if resp.status().is_success() => return Ok(()),
+                // Client errors will not go away by retrying.
+                Ok(resp) if resp.status().is_client_error() => {
+                    return Err(resp.into_error().context(format!("chunk {index} rejected")));
+                }
+                Ok(resp) => resp.into_error().context(format!("chunk {index} failed")),
+                Err(e) => e.context(format!("chunk {index} failed")),
+            };
+            if attempt >= retry.chunk_retries {
+                return Err(err);
+            }
+            attempt += 1;
+            std::thread::sleep(wait);
+            wait *= 2;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct UploadStatusBody {
+    upload_id: String,
+    chunk_bytes: u64,
+    missing: Vec<u64>,
+}
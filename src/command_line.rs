@@ -0,0 +1,97 @@
+//! Building external commands.
+//!
+//! A [`CommandLine`] is a program plus arguments. It is executed from its argv (no shell is
+//! involved), and rendered with [`quote`] only when shown to a user to copy and paste.
+
+use std::fmt;
+use std::process::Command;
+
+/// Characters that never need quoting in a POSIX shell word.
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c)
+}
+
+/// Quote `s` as a single POSIX shell word.
+///
+/// Words made only of safe characters are returned as is; anything else (including the empty
+/// string) is single-quoted, with embedded single quotes written as `'\''`.
+pub fn quote(s: &str) -> String {
+    if !s.is_empty() && s.chars().all(is_safe) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// A program and its arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandLine {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: vec![],
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// The same command run through `wrapper` (e.g. `sudo`).
+    pub fn wrapped(self, wrapper: impl Into<String>) -> Self {
+        CommandLine::new(wrapper).arg(self.program).args(self.args)
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Program followed by arguments, for execution.
+    pub fn argv(&self) -> Vec<String> {
+        std::iter::once(self.program.clone())
+            .chain(self.args.iter().cloned())
+            .collect()
+    }
+
+    /// A [`Command`] running the argv directly.
+    pub fn to_command(&self) -> Command {
+        let mut cmd = Command::new(&self.program);
+        cmd.args(&self.args);
+        cmd
+    }
+
+    /// Copy-pasteable form for a POSIX shell.
+    pub fn display(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // A leading `NAME=value` word would be taken as a variable assignment.
+        let program = if self.program.contains('=') {
+            format!("'{}'", self.program.replace('\'', r"'\''"))
+        } else {
+            quote(&self.program)
+        };
+        f.write_str(&program)?;
+        for a in &self.args {
+            write!(f, " {}", quote(a))?;
+        }
+        Ok(())
+    }
+}
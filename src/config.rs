@@ -1,33 +1,62 @@
+use crate::deprecations::{self, DeprecationUse, Form, Registry};
 use anyhow::Result;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::path::Path;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Config {
     pub service: Option<ServiceConfig>,
     pub server: Option<ServerConfig>,
     pub policy: Option<PolicyConfig>,
     pub security: Option<SecurityConfig>,
     pub normalize: Option<NormalizeConfig>,
+    pub reputation: Option<ReputationConfig>,
+    pub redaction: Option<RedactionConfig>,
+    pub loop_protection: Option<LoopProtectionConfig>,
+    pub content_types: Option<ContentTypesConfig>,
+    pub siem: Option<SiemConfig>,
+    pub notify: Option<NotifyConfig>,
+    pub cors: Option<CorsConfig>,
+    pub startup: Option<StartupConfig>,
+    pub chunking: Option<ChunkingConfig>,
+    pub probing: Option<ProbingConfig>,
+    pub remediation: Option<RemediationConfig>,
+    pub response_headers: Option<ResponseHeadersConfig>,
+    pub regex: Option<RegexConfig>,
+    pub telemetry: Option<TelemetryConfig>,
+    pub hashing: Option<HashingConfig>,
+    /// User detection patterns, run on every ingest after the built-in scanners.
+    #[serde(default)]
+    pub patterns: Vec<PatternConfig>,
+    /// External domain/reputation feeds, keyed by name (order does not matter).
+    #[serde(default)]
+    pub feeds: Vec<FeedConfig>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServiceConfig {
     pub user: Option<String>,
     pub group: Option<String>,
     pub enforce_identity: Option<bool>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: Option<String>,
     pub port: Option<u16>,
     /// Optional Unix domain socket path (Linux/macOS). If set, the server binds this socket
     /// instead of TCP host:port.
     pub unix_socket: Option<String>,
+    /// Serve queries only: mutating endpoints are refused and nothing is written to the
+    /// stores. Meant for investigation instances pointed at a copy of production data.
+    pub read_only: Option<bool>,
+    /// What a synchronous ingest whose client disconnected still writes. See `disconnect`.
+    pub on_client_disconnect: Option<crate::disconnect::OnClientDisconnect>,
+    /// Blocking store and disk jobs run at once (default 8). See `blocking`.
+    pub blocking_pool_size: Option<usize>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PolicyConfig {
     pub policies_file: Option<String>,
     pub head: Option<usize>,
@@ -35,11 +64,31 @@ pub struct PolicyConfig {
     pub full_if_lte: Option<usize>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SecurityConfig {
     pub allow_insecure_loopback: Option<bool>,
     pub require_token: Option<bool>,
     pub token_env: Option<String>,
+    /// Keep the process dumpable (core dumps, `/proc/<pid>/mem` for the same user). Off by
+    /// default: a dump would hold every loaded secret.
+    pub allow_core_dumps: Option<bool>,
+    /// Named tokens with scopes, in addition to (or instead of) the `token_env` token.
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// Repeated `X-ACIP-*` headers: `reject` (default) or `use_strictest`.
+    pub duplicate_headers: Option<crate::acip_headers::DuplicateHeaders>,
+    /// Policy names, most restrictive first; `use_strictest` picks the first of repeated
+    /// `X-ACIP-Policy` values.
+    #[serde(default)]
+    pub policy_ranking: Vec<String>,
+}
+
+/// A named API token: its value comes from the secrets store under `secret`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenConfig {
+    pub name: String,
+    pub secret: String,
+    pub scopes: Vec<String>,
 }
 
 pub const DEFAULT_NORMALIZE_MAX_INPUT_CHARS: usize = 400_000;
@@ -49,6 +98,10 @@ pub const DEFAULT_NORMALIZE_WINDOW_TAIL_CHARS: usize = 200_000;
 pub const DEFAULT_NORMALIZE_ADVERSARIAL_THRESHOLD: u8 = 3;
 pub const DEFAULT_NORMALIZE_ADVERSARIAL_TIGHTEN_FACTOR: f64 = 0.5;
 
+pub const DEFAULT_NORMALIZE_DECODE_MAX_REGIONS: usize =
+    crate::decode_scan::DEFAULT_DECODE_MAX_REGIONS;
+pub const DEFAULT_NORMALIZE_DECODE_MAX_BYTES: usize = crate::decode_scan::DEFAULT_DECODE_MAX_BYTES;
+
 fn default_normalize_max_input_chars() -> usize {
     DEFAULT_NORMALIZE_MAX_INPUT_CHARS
 }
@@ -69,7 +122,15 @@ fn default_normalize_adversarial_tighten_factor() -> f64 {
     DEFAULT_NORMALIZE_ADVERSARIAL_TIGHTEN_FACTOR
 }
 
-#[derive(Debug, Clone, Deserialize)]
+fn default_normalize_decode_max_regions() -> usize {
+    DEFAULT_NORMALIZE_DECODE_MAX_REGIONS
+}
+
+fn default_normalize_decode_max_bytes() -> usize {
+    DEFAULT_NORMALIZE_DECODE_MAX_BYTES
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct NormalizeConfig {
     #[serde(default = "default_normalize_max_input_chars")]
     pub max_input_chars: usize,
@@ -85,12 +146,345 @@ pub struct NormalizeConfig {
     /// Factor to tighten caps when adversarial markup is detected.
     #[serde(default = "default_normalize_adversarial_tighten_factor")]
     pub adversarial_tighten_factor: f64,
+
+    /// Decode-and-rescan budget: encoded regions decoded per input.
+    #[serde(default = "default_normalize_decode_max_regions")]
+    pub decode_max_regions: usize,
+
+    /// Decode-and-rescan budget: total decoded bytes per input.
+    #[serde(default = "default_normalize_decode_max_bytes")]
+    pub decode_max_bytes: usize,
+}
+
+/// Reputation scoring knobs. Every field is optional; `ACIP_REP_*` env vars override.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReputationConfig {
+    pub medium_score: Option<u64>,
+    pub high_score: Option<u64>,
+    pub bad_actor_score: Option<u64>,
+    pub half_life_base_days: Option<f64>,
+    pub half_life_k: Option<f64>,
+
+    /// Largest fraction (0.0..=1.0) of the decayed risk score that trust can discount.
+    /// `0.0` disables the trust discount.
+    pub trust_max_discount: Option<f64>,
+    /// Account age (days) at which the age half of the discount is fully earned.
+    pub trust_full_age_days: Option<f64>,
+    /// Clean ingests at which the volume half of the discount is fully earned.
+    pub trust_full_clean_ingests: Option<u64>,
+
+    /// Most records the store keeps (unset or `0`: unbounded). See `reputation_limits`.
+    pub max_records: Option<usize>,
+    /// Give a key a record only from its second clean sighting (or first risky one).
+    pub prefilter: Option<bool>,
+    /// Keys per generation of the pre-filter's recent-keys window.
+    pub prefilter_keys: Option<usize>,
+}
+
+/// Strings that must never leave the box in a response or log line. See `redact`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RedactionConfig {
+    #[serde(default)]
+    pub rules: Vec<RedactionRuleConfig>,
+}
+
+/// One redaction rule: exactly one of `literal` (ASCII case-insensitive) or `regex`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RedactionRuleConfig {
+    /// Matches are replaced with `[REDACTED:<label>]`.
+    pub label: String,
+    #[serde(default)]
+    pub literal: Option<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+}
+
+/// `[loop_protection]`: what to do with content this sidecar (or a chain of them) emitted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LoopProtectionConfig {
+    /// `reject`, `flag` (default) or `ignore`.
+    pub mode: Option<crate::loop_guard::LoopProtection>,
+    pub max_hops: Option<u32>,
+    pub instance_id: Option<String>,
+}
+
+/// `[content_types]`: inputs `ingest_source` refuses beyond the supported list.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentTypesConfig {
+    /// Declared types to refuse, on top of the executable MIME types always refused.
+    #[serde(default)]
+    pub reject: Vec<String>,
+    /// `reject` (default) or `needs_review`.
+    pub on_unknown_binary: Option<crate::content_types::UnknownBinaryHandling>,
+    /// Share of non-text bytes (0.0..=1.0) above which unrecognised input is an unknown binary.
+    pub unknown_binary_ratio: Option<f64>,
+}
+
+/// `[siem]`: export completed decisions to a SIEM (see [`crate::siem`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SiemConfig {
+    /// `ocsf` or `ecs`.
+    pub format: crate::siem::SiemFormat,
+    /// Local file path (JSON lines) or `https://` URL (a JSON array per batch).
+    pub destination: String,
+    pub batch_size: Option<usize>,
+    pub flush_interval_secs: Option<u64>,
+    /// Events held while the destination is unreachable; the oldest are dropped beyond this.
+    pub spool_capacity: Option<usize>,
+    /// Hash `source_id`, `url` and `title` with the deployment salt before export (default true).
+    pub hash_observables: Option<bool>,
+    /// TOML file merged over the built-in field mappings.
+    pub mappings: Option<String>,
+}
+
+/// `[notify]`: POST each decision to a webhook, and/or to a callback the request names (see
+/// [`crate::notify`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// `https://` URL (or `http://` to a loopback host) whose host is in `ACIP_EGRESS_HOSTS`.
+    pub webhook_url: Option<String>,
+    /// `none` (default), `per_key` or `global`.
+    pub ordering: Option<crate::notify::DeliveryOrdering>,
+    /// How long a failing delivery may hold up its queue before it is spooled (default 300).
+    pub max_blockage_secs: Option<u64>,
+    /// First retry delay, doubled after each failure (default 1000).
+    pub retry_initial_ms: Option<u64>,
+    /// Undeliverable events kept; the oldest are dropped beyond this.
+    pub spool_capacity: Option<usize>,
+    /// Let a sync `ingest_source` name one callback URL for its decision (default false).
+    pub per_request_callbacks: Option<bool>,
+    /// Callbacks one token may request per minute (default 60).
+    pub callbacks_per_minute: Option<u32>,
+    /// Accept `http://` callback URLs (default false: `https://` only).
+    pub callback_allow_http: Option<bool>,
+    /// Accept IP address callback hosts (default false).
+    pub callback_allow_ip_literals: Option<bool>,
+}
+
+/// `[cors]`: origins a browser client may call the ingest and read endpoints from (see
+/// [`crate::cors`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// Exact origins (`https://review.example.com`); empty leaves CORS off.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// How long a browser may cache a preflight answer (default 600).
+    pub max_age_secs: Option<u64>,
+}
+
+/// `[startup]`: the host environment check (see [`crate::environment`]).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StartupConfig {
+    /// `warn` (default) logs conflicts; `strict` refuses to start on a hard one.
+    pub environment: Option<crate::environment::Mode>,
+    /// Extractor helpers to plan memory and pids for (default: one per CPU).
+    pub extractor_concurrency: Option<usize>,
+}
+
+/// `[chunking]`: content-defined chunk hashes for large inputs (see [`crate::chunking`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChunkingConfig {
+    /// `whole` (default) or `content_defined`.
+    pub mode: Option<crate::chunking::ChunkingMode>,
+    /// Inputs at least this large are chunked (default 64 MiB).
+    pub min_bytes: Option<usize>,
+    /// Average chunk size (default 1 MiB, at least 256).
+    pub avg_chunk_bytes: Option<usize>,
+    /// Least fraction of shared bytes for a partial reuse (default 0.9).
+    pub similarity: Option<f64>,
+    /// Unchanged bytes read on each side of a changed region (default 4096).
+    pub context_bytes: Option<usize>,
+    /// Chunk lists kept over all tenants (default 256).
+    pub max_files: Option<usize>,
+}
+
+/// `[probing]`: active probes of the links in escalated documents (see [`crate::probing`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProbingConfig {
+    /// Off by default; policies still opt in with `active_probing`.
+    pub enabled: Option<bool>,
+    /// Probe mechanisms allowed out: `dns`, `rdap`, `tls`, `head` (default all four).
+    pub mechanisms: Option<Vec<crate::probing::Mechanism>>,
+    /// RDAP base URL (`https://`, or `http://` to a loopback host); `rdap` is skipped without.
+    pub rdap_endpoint: Option<String>,
+    /// `ip:port` of the DNS server (default: the first `nameserver` in `/etc/resolv.conf`).
+    pub dns_server: Option<String>,
+    /// Local `threat_score` from which a document counts as escalated (default 10).
+    pub min_threat_score: Option<u8>,
+    /// Hosts probed per document (default 5).
+    pub max_urls_per_document: Option<usize>,
+    /// Probes over all documents per minute (default 60).
+    pub probes_per_minute: Option<u32>,
+    /// Limit for each probe step (default 2000).
+    pub timeout_ms: Option<u64>,
+    /// How long a host's results are reused (default 3600).
+    pub cache_ttl_secs: Option<u64>,
+    /// Redirects a `head` probe follows (default 3).
+    pub max_redirects: Option<usize>,
+    /// Registrations younger than this are a finding (default 30).
+    pub recent_registration_days: Option<u32>,
+    /// PEM file of extra CA certificates for the `tls` and `head` probes.
+    pub tls_ca_file: Option<String>,
+    /// Probe hosts that resolve to loopback, private or link-local addresses (default false).
+    pub allow_private_addresses: Option<bool>,
+}
+
+/// `[remediation]`: hints attached to non-allow decisions (see [`crate::remediation`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RemediationConfig {
+    /// TOML file of `[[hint]]` entries merged over the built-in ones by id; re-read on SIGHUP.
+    pub hints_file: Option<String>,
+}
+
+/// `[response_headers]`: decision summary headers on ingest responses (see
+/// [`crate::risk_headers`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResponseHeadersConfig {
+    /// Add `X-ACIP-Patterns` (default false).
+    pub patterns: Option<bool>,
+    /// Pattern ids in `X-ACIP-Patterns` (default 5, at most 32).
+    pub max_patterns: Option<usize>,
+}
+
+/// `[regex]`: limits for every user-supplied regex (see [`crate::regex_guard`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegexConfig {
+    /// Hard cap on a compiled regex (the `regex` crate's `size_limit`).
+    pub size_limit_bytes: Option<usize>,
+    /// Lazy DFA cache per regex (`dfa_size_limit`).
+    pub dfa_size_limit_bytes: Option<usize>,
+    pub nest_limit: Option<u32>,
+    /// Largest measured compiled size accepted when the config is validated.
+    pub compiled_budget_bytes: Option<usize>,
+    /// Time one `[[patterns]]` entry may take on one ingest before it earns a strike.
+    pub pattern_scan_budget_us: Option<u64>,
+    /// Time the whole pattern pack may take on one ingest; later patterns are skipped.
+    pub pack_scan_budget_us: Option<u64>,
+    /// Strikes after which a pattern is disabled until restart.
+    pub demote_after_strikes: Option<u32>,
+}
+
+/// `[telemetry]`: span export for distributed tracing (see [`crate::telemetry`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    /// OTLP/HTTP collector base URL, e.g. `http://otel-collector:4318`. Needs the `otel` build
+    /// feature; without an endpoint spans are not exported.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` on exported spans (default `acip-sidecar`).
+    pub service_name: Option<String>,
+}
+
+/// `[hashing]`: the salt of exported identifier hashes (see [`crate::hashing`]).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HashingConfig {
+    /// Where a generated salt is kept when `ACIP_HASHING_SALT` is not set
+    /// (default `/var/lib/acip/hashing.salt`).
+    pub salt_file: Option<String>,
+    /// Rotation window: also accept the salt in `ACIP_HASHING_PREVIOUS_SALT` for lookups.
+    pub accept_previous_salt: Option<bool>,
+}
+
+/// One `[[patterns]]` entry: a match adds `user_pattern:<id>` to the assessment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatternConfig {
+    pub id: String,
+    pub regex: String,
+    pub attack_type: crate::threat::AttackType,
+    /// Added to the threat score on a match (default 3).
+    pub score: Option<u8>,
+}
+
+/// One `[[feeds]]` entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: crate::feeds::FeedKind,
+    /// Local file path or `https://` URL.
+    pub source: String,
+    #[serde(default)]
+    pub format: crate::feeds::FeedFormat,
+    /// `csv`: column index (0-based) or header name.
+    pub column: Option<String>,
+    /// `jsonl`: dotted path of the field holding the domain.
+    pub field: Option<String>,
+    pub refresh_secs: Option<u64>,
+    pub max_bytes: Option<u64>,
+    /// Reported stale once the last successful refresh is older than this many intervals.
+    pub stale_after_intervals: Option<u32>,
+    /// `reputation_seed`: minimum risk score for listed hosts.
+    pub seed_score: Option<u64>,
 }
 
 impl Config {
+    /// Read, parse and validate a config file. Deprecated keys in it are recorded (and logged
+    /// once) in [`crate::deprecations`].
     pub fn load(path: impl AsRef<Path>) -> Result<Self> {
         let raw = std::fs::read_to_string(path.as_ref())?;
-        let cfg: Self = toml::from_str(&raw)?;
+        let (cfg, uses) = Self::parse_with(&raw, &Registry::builtin())?;
+        cfg.validate()?;
+        for used in &uses {
+            deprecations::record(used);
+        }
         Ok(cfg)
     }
+
+    /// Deserialize without [`Config::validate`]. A field error names its key path,
+    /// e.g. `server.port: ...`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        Ok(Self::parse_with(raw, &Registry::builtin())?.0)
+    }
+
+    /// [`Config::parse`], moving the deprecated keys of `registry` onto their replacements
+    /// first; returns the ones that were used.
+    pub fn parse_with(raw: &str, registry: &Registry) -> Result<(Self, Vec<DeprecationUse>)> {
+        let mut uses = vec![];
+        let mut translated = None;
+        if registry.entries(Form::ConfigKey).next().is_some() {
+            // A syntax error is left to the parse below, which reports it with its position.
+            if let Ok(mut doc) = toml::from_str::<toml::Value>(raw) {
+                uses = registry.translate_config(&mut doc)?;
+                if !uses.is_empty() {
+                    translated = Some(toml::to_string(&doc)?);
+                }
+            }
+        }
+        let raw = translated.as_deref().unwrap_or(raw);
+        let cfg = serde_path_to_error::deserialize(toml::Deserializer::new(raw)).map_err(|e| {
+            if e.path().iter().next().is_none() {
+                anyhow::anyhow!("{}", e.inner())
+            } else {
+                anyhow::anyhow!("{}: {}", e.path(), e.inner())
+            }
+        })?;
+        Ok((cfg, uses))
+    }
+
+    /// Checks that deserialization alone cannot express.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(sec) = &self.security {
+            for t in &sec.tokens {
+                crate::token_auth::parse_scopes(&t.scopes)
+                    .map_err(|e| anyhow::anyhow!("security.tokens {:?}: {e}", t.name))?;
+            }
+        }
+        if let Some(id) = self
+            .loop_protection
+            .as_ref()
+            .and_then(|l| l.instance_id.as_deref())
+        {
+            crate::loop_guard::validate_instance_id(id)?;
+        }
+        crate::feeds::FeedSpec::from_configs(
+            &self.feeds,
+            &crate::egress::allowed_hosts_from_env(),
+        )?;
+        let limits = crate::regex_guard::RegexLimits::from_config(self.regex.as_ref());
+        limits.validate()?;
+        crate::patterns::PatternPack::compile(&self.patterns, &limits)?;
+        let pattern_ids: Vec<String> = self.patterns.iter().map(|p| p.id.clone()).collect();
+        crate::remediation::Remediation::from_config(self.remediation.as_ref(), &pattern_ids)?;
+        crate::risk_headers::RiskHeaders::from_config(self.response_headers.as_ref())?;
+        Ok(())
+    }
 }
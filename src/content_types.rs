@@ -0,0 +1,377 @@
+//! Which inputs `ingest_source` accepts, checked in layers before anything is scanned.
+//!
+//! 1. `reject`: executables recognised by magic number (PE, ELF, Mach-O) whatever the declared
+//!    type, and declared types on the reject list (executable MIME types by default, plus
+//!    `content_types.reject`).
+//! 2. `global`: types this deployment turns into text ([`SUPPORTED`]; extractor types only where
+//!    the extractor runs). A recognised binary format outside that list is refused here even when
+//!    declared as text. No archive format is extracted, so archives never reach a nesting limit:
+//!    they all stop at this layer.
+//! 3. `policy`: the policy's `content_types`, when it sets one.
+//!
+//! Encrypted PDFs pass the layers: the extractor opens them with the caller's
+//! `document_password`, and the policy's `on_encrypted` decides what happens when it cannot.
+//!
+//! Input with no recognised magic number whose leading bytes are mostly not text is an unknown
+//! binary; `content_types.on_unknown_binary` refuses it (default) or sends it to review.
+
+use crate::config::ContentTypesConfig;
+use crate::introspection;
+use crate::model_policy::PolicyConfig;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+/// `error` of the `415` response.
+pub const ERROR_CODE: &str = "unsupported_content_type";
+
+/// Leading bytes inspected for the unknown-binary check.
+pub const SNIFF_BYTES: usize = 8192;
+
+/// Share of non-text bytes above which unrecognised input is an unknown binary.
+pub const DEFAULT_BINARY_RATIO: f64 = 0.3;
+
+/// Reported as the sniffed type of an unknown binary.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Declared types refused whatever the bytes look like.
+pub const DEFAULT_REJECT: &[&str] = &[
+    "application/x-msdownload",
+    "application/x-dosexec",
+    "application/vnd.microsoft.portable-executable",
+    "application/x-executable",
+    "application/x-elf",
+    "application/x-sharedlib",
+    "application/x-mach-binary",
+];
+
+/// Content types turned into model-facing text, with how: `text`, `html` or `extractor`
+/// (out-of-process PDF/SVG extraction).
+pub const SUPPORTED: &[(&str, &str)] = &[
+    ("text/plain", "text"),
+    ("text/html", "html"),
+    ("application/xhtml+xml", "html"),
+    ("image/svg+xml", "extractor"),
+    ("application/pdf", "extractor"),
+];
+
+/// The extractor runs on Unix only.
+pub fn extractor_available() -> bool {
+    cfg!(unix)
+}
+
+/// Whether a [`SUPPORTED`] handling is available on this deployment.
+pub fn handling_available(handling: &str) -> bool {
+    handling != "extractor" || extractor_available()
+}
+
+/// The globally accepted types: [`SUPPORTED`] minus what this deployment cannot handle.
+pub fn globally_supported() -> Vec<&'static str> {
+    SUPPORTED
+        .iter()
+        .filter(|(_, handling)| handling_available(handling))
+        .map(|(ct, _)| *ct)
+        .collect()
+}
+
+/// Types a policy accepts: its `content_types` narrowed to the global list, or the whole global
+/// list when it sets none.
+pub fn effective(policy: Option<&PolicyConfig>) -> Vec<String> {
+    let global = globally_supported();
+    match policy.map(|p| &p.content_types).filter(|l| !l.is_empty()) {
+        Some(list) => global
+            .into_iter()
+            .filter(|ct| list.iter().any(|p| essence(p) == *ct))
+            .map(str::to_string)
+            .collect(),
+        None => global.into_iter().map(str::to_string).collect(),
+    }
+}
+
+/// Check a policy's `content_types` at load time: every entry must be a [`SUPPORTED`] type.
+pub fn validate_policy_list(policy: &str, list: &[String]) -> anyhow::Result<()> {
+    for ct in list {
+        if !SUPPORTED.iter().any(|(s, _)| *s == essence(ct)) {
+            let known: Vec<&str> = SUPPORTED.iter().map(|(s, _)| *s).collect();
+            anyhow::bail!(
+                "policy '{policy}': content_types entry {ct:?} is not a supported type ({})",
+                known.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Media type without parameters, lowercased: `Text/HTML; charset=utf-8` -> `text/html`.
+pub fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// The layer that refused an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layer {
+    Reject,
+    Global,
+    Policy,
+}
+
+/// What to do with an unknown binary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnknownBinaryHandling {
+    /// Refuse it with `unsupported_content_type`.
+    #[default]
+    Reject,
+    /// Answer `needs_review` with tools off; nothing is sent to a model.
+    NeedsReview,
+}
+
+/// Broad class of a recognised format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Executable,
+    Archive,
+    Document,
+    Image,
+}
+
+/// A format recognised by its magic number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sniffed {
+    pub content_type: &'static str,
+    pub kind: Kind,
+}
+
+const MACHO: &str = "application/x-mach-binary";
+const SEVEN_ZIP: &str = "application/x-7z-compressed";
+
+/// Magic numbers at offset 0. PE is checked separately: `MZ` alone is too common in text.
+const MAGIC: &[(&[u8], &str, Kind)] = &[
+    (b"\x7fELF", "application/x-elf", Kind::Executable),
+    (&[0xfe, 0xed, 0xfa, 0xce], MACHO, Kind::Executable),
+    (&[0xfe, 0xed, 0xfa, 0xcf], MACHO, Kind::Executable),
+    (&[0xce, 0xfa, 0xed, 0xfe], MACHO, Kind::Executable),
+    (&[0xcf, 0xfa, 0xed, 0xfe], MACHO, Kind::Executable),
+    // Universal binaries (and Java class files, which share the magic).
+    (&[0xca, 0xfe, 0xba, 0xbe], MACHO, Kind::Executable),
+    (b"PK\x03\x04", "application/zip", Kind::Archive),
+    (&[0x1f, 0x8b], "application/gzip", Kind::Archive),
+    (b"7z\xbc\xaf'\x1c", SEVEN_ZIP, Kind::Archive),
+    (b"Rar!\x1a\x07", "application/vnd.rar", Kind::Archive),
+    (b"%PDF-", "application/pdf", Kind::Document),
+    (b"\x89PNG\r\n\x1a\n", "image/png", Kind::Image),
+    (&[0xff, 0xd8, 0xff], "image/jpeg", Kind::Image),
+    (b"GIF87a", "image/gif", Kind::Image),
+    (b"GIF89a", "image/gif", Kind::Image),
+];
+
+/// Recognise `bytes` by magic number.
+pub fn sniff(bytes: &[u8]) -> Option<Sniffed> {
+    if is_pe(bytes) {
+        return Some(Sniffed {
+            content_type: "application/vnd.microsoft.portable-executable",
+            kind: Kind::Executable,
+        });
+    }
+    MAGIC
+        .iter()
+        .find(|(magic, _, _)| bytes.starts_with(magic))
+        .map(|&(_, content_type, kind)| Sniffed { content_type, kind })
+}
+
+/// `MZ` header whose `e_lfanew` points at a `PE\0\0` signature.
+fn is_pe(bytes: &[u8]) -> bool {
+    if !bytes.starts_with(b"MZ") || bytes.len() < 0x40 {
+        return false;
+    }
+    let offset = u32::from_le_bytes([bytes[0x3c], bytes[0x3d], bytes[0x3e], bytes[0x3f]]) as usize;
+    bytes.get(offset..offset.saturating_add(4)) == Some(b"PE\0\0".as_slice())
+}
+
+/// A PDF with an encryption dictionary: its text cannot be extracted without the key.
+pub fn is_encrypted_pdf(bytes: &[u8]) -> bool {
+    bytes.windows(b"/Encrypt".len()).any(|w| w == b"/Encrypt")
+}
+
+/// Share of the first [`SNIFF_BYTES`] that are control characters or invalid UTF-8.
+pub fn non_text_ratio(bytes: &[u8]) -> f64 {
+    let head = &bytes[..bytes.len().min(SNIFF_BYTES)];
+    if head.is_empty() {
+        return 0.0;
+    }
+    let mut non_text = 0usize;
+    let mut rest = head;
+    loop {
+        let (valid, invalid) = match std::str::from_utf8(rest) {
+            Ok(s) => (s, 0),
+            Err(e) => {
+                let valid = std::str::from_utf8(&rest[..e.valid_up_to()]).unwrap_or_default();
+                // A sequence cut off by the window is not counted.
+                (valid, e.error_len().unwrap_or(0))
+            }
+        };
+        non_text += valid
+            .chars()
+            .filter(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b'))
+            .count();
+        non_text += invalid;
+        if invalid == 0 {
+            break;
+        }
+        rest = &rest[valid.len() + invalid..];
+    }
+    non_text as f64 / head.len() as f64
+}
+
+/// Why an input was refused (the `extra` of the `415` response).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Unsupported {
+    pub layer: Layer,
+    /// `executable`, `rejected_type`, `not_supported`, `unsupported_format`,
+    /// `not_allowed_by_policy`, `unknown_binary`, or (after extraction, under
+    /// `on_encrypted = "reject"`) `encrypted_unreadable` / `decryption_failed`.
+    pub reason: &'static str,
+    /// The declared type, without parameters.
+    pub declared: String,
+    /// The type the bytes were recognised as; [`OCTET_STREAM`] for an unknown binary.
+    pub sniffed: Option<String>,
+    pub policy: String,
+    /// Types accepted at the refusing layer (`global` and `policy` only).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accepted: Vec<String>,
+}
+
+impl IntoResponse for Unsupported {
+    fn into_response(self) -> Response {
+        introspection::json_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ERROR_CODE,
+            serde_json::json!(self),
+        )
+        .into_response()
+    }
+}
+
+/// Outcome of a successful [`ContentTypeRules::screen`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Screening {
+    Accepted,
+    /// Unknown binary under `on_unknown_binary = "needs_review"`.
+    NeedsReview(Unsupported),
+}
+
+/// `[content_types]`.
+#[derive(Debug, Clone)]
+pub struct ContentTypeRules {
+    /// Declared types refused at the `reject` layer.
+    pub reject: Vec<String>,
+    pub on_unknown_binary: UnknownBinaryHandling,
+    /// See [`DEFAULT_BINARY_RATIO`].
+    pub binary_ratio: f64,
+}
+
+impl Default for ContentTypeRules {
+    fn default() -> Self {
+        Self {
+            reject: DEFAULT_REJECT.iter().map(|s| s.to_string()).collect(),
+            on_unknown_binary: UnknownBinaryHandling::default(),
+            binary_ratio: DEFAULT_BINARY_RATIO,
+        }
+    }
+}
+
+impl ContentTypeRules {
+    /// `reject` adds to [`DEFAULT_REJECT`]; the defaults cannot be removed.
+    pub fn from_config(cfg: Option<&ContentTypesConfig>) -> Self {
+        let mut rules = Self::default();
+        let Some(cfg) = cfg else {
+            return rules;
+        };
+        rules.reject.extend(cfg.reject.iter().map(|ct| essence(ct)));
+        if let Some(mode) = cfg.on_unknown_binary {
+            rules.on_unknown_binary = mode;
+        }
+        if let Some(ratio) = cfg.unknown_binary_ratio {
+            rules.binary_ratio = ratio.clamp(0.0, 1.0);
+        }
+        rules
+    }
+
+    /// Run the layers on one input declared as `declared` under `policy_name`.
+    pub fn screen(
+        &self,
+        policy_name: &str,
+        policy: Option<&PolicyConfig>,
+        declared: &str,
+        bytes: &[u8],
+    ) -> Result<Screening, Unsupported> {
+        let declared = essence(declared);
+        let sniffed = sniff(bytes);
+        let refuse = |layer, reason, sniffed: Option<&str>, accepted: Vec<String>| Unsupported {
+            layer,
+            reason,
+            declared: declared.clone(),
+            sniffed: sniffed.map(str::to_string),
+            policy: policy_name.to_string(),
+            accepted,
+        };
+        let sniffed_type = sniffed.map(|s| s.content_type);
+
+        if sniffed.is_some_and(|s| s.kind == Kind::Executable) {
+            return Err(refuse(Layer::Reject, "executable", sniffed_type, vec![]));
+        }
+        if self.reject.contains(&declared) {
+            return Err(refuse(Layer::Reject, "rejected_type", sniffed_type, vec![]));
+        }
+
+        let global = globally_supported();
+        let global_list = || -> Vec<String> { global.iter().map(|s| s.to_string()).collect() };
+        if !global.contains(&declared.as_str()) {
+            return Err(refuse(
+                Layer::Global,
+                "not_supported",
+                sniffed_type,
+                global_list(),
+            ));
+        }
+        if let Some(ct) = sniffed_type.filter(|ct| !global.contains(ct)) {
+            return Err(refuse(
+                Layer::Global,
+                "unsupported_format",
+                Some(ct),
+                global_list(),
+            ));
+        }
+
+        let accepted = effective(policy);
+        if !accepted.contains(&declared) {
+            return Err(refuse(
+                Layer::Policy,
+                "not_allowed_by_policy",
+                sniffed_type,
+                accepted,
+            ));
+        }
+
+        // Extractor types are parsed by the extractor, which rejects what it cannot read.
+        let text_handled = SUPPORTED
+            .iter()
+            .any(|(ct, handling)| *ct == declared && *handling != "extractor");
+        if sniffed.is_none() && text_handled && non_text_ratio(bytes) > self.binary_ratio {
+            let unknown = refuse(Layer::Global, "unknown_binary", Some(OCTET_STREAM), vec![]);
+            return match self.on_unknown_binary {
+                UnknownBinaryHandling::Reject => Err(unknown),
+                UnknownBinaryHandling::NeedsReview => Ok(Screening::NeedsReview(unknown)),
+            };
+        }
+        Ok(Screening::Accepted)
+    }
+}
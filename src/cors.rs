@@ -0,0 +1,109 @@
+//! Cross-origin access for browser clients.
+//!
+//! Off unless `[cors] allowed_origins` lists at least one origin. Listed origins may call the
+//! read endpoints, `ingest_source`, resumable uploads and job polling; admin, support and other
+//! privileged routes never answer a preflight. Credentials are not allowed: the sidecar
+//! authenticates with the `X-ACIP-Token` header only and never reads cookies, so a page on
+//! another origin cannot ride on a user's session (no CSRF).
+
+use crate::config::CorsConfig;
+use anyhow::{anyhow, bail};
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use url::Url;
+
+pub const DEFAULT_MAX_AGE_SECS: u64 = 600;
+
+/// Request headers a browser client may send.
+pub const ALLOWED_HEADERS: [&str; 6] = [
+    "content-type",
+    "x-acip-token",
+    "x-acip-policy",
+    "x-acip-allow-tools",
+    "x-acip-bypass-cache",
+    "x-acip-force-timing",
+];
+
+/// Response headers a browser client may read.
+pub const EXPOSED_HEADERS: [&str; 7] = [
+    "x-acip-deprecated",
+    "etag",
+    crate::risk_headers::ACTION,
+    crate::risk_headers::RISK_LEVEL,
+    crate::risk_headers::TOOLS_ALLOWED,
+    crate::risk_headers::REQUEST_ID,
+    crate::risk_headers::PATTERNS,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct CorsPolicy {
+    origins: Vec<String>,
+    max_age: Duration,
+}
+
+impl CorsPolicy {
+    /// Each origin must be an exact `scheme://host[:port]`; `*` is refused.
+    pub fn from_config(cfg: Option<&CorsConfig>) -> anyhow::Result<Self> {
+        let Some(cfg) = cfg else {
+            return Ok(Self::default());
+        };
+        let origins = cfg
+            .allowed_origins
+            .iter()
+            .map(|o| parse_origin(o))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self {
+            origins,
+            max_age: Duration::from_secs(cfg.max_age_secs.unwrap_or(DEFAULT_MAX_AGE_SECS)),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.origins.is_empty()
+    }
+
+    pub fn origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    /// The layer answering preflights and tagging responses, or `None` when CORS is off.
+    pub fn layer(&self) -> Option<CorsLayer> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let origins = self
+            .origins
+            .iter()
+            .filter_map(|o| HeaderValue::from_str(o).ok());
+        Some(
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(origins))
+                .allow_methods([Method::GET, Method::POST, Method::PUT])
+                .allow_headers(ALLOWED_HEADERS.map(HeaderName::from_static))
+                .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+                .allow_credentials(false)
+                .max_age(self.max_age),
+        )
+    }
+}
+
+/// `raw` as the browser sends it in `Origin`.
+fn parse_origin(raw: &str) -> anyhow::Result<String> {
+    let raw = raw.trim();
+    if raw == "*" {
+        bail!("cors allowed_origins: list origins explicitly instead of \"*\"");
+    }
+    let url = Url::parse(raw).map_err(|e| anyhow!("cors allowed_origins {raw:?}: {e}"))?;
+    if !matches!(url.scheme(), "http" | "https")
+        || url.host().is_none()
+        || !url.username().is_empty()
+        || url.password().is_some()
+        || url.path() != "/"
+        || url.query().is_some()
+        || url.fragment().is_some()
+    {
+        bail!("cors allowed_origins {raw:?}: expected scheme://host[:port]");
+    }
+    Ok(url.origin().ascii_serialization())
+}
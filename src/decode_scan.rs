@@ -0,0 +1,284 @@
+//! Bounded decode-and-rescan for HTML-entity and percent-encoded payloads.
+//!
+//! The phrase scanners match literal text, so `&#105;gnore previous` or `ignore%20previous`
+//! would slip past them. This pass finds clusters of encoded tokens, decodes them one level
+//! (plus at most one more pass for double encoding) and rescans the decoded view. The cost is
+//! bounded by [`DecodeBudget`]: a cap on regions and on total decoded bytes.
+//!
+//! Decoding only feeds detection; the model-facing and fenced text stay as they were.
+
+use crate::threat::{self, DetectedPattern, ScanStage, ThreatAssessment};
+
+/// Encoded tokens at most this many bytes apart belong to one region.
+const MERGE_GAP_BYTES: usize = 16;
+/// Plain text kept on each side of a region so phrases split around a token still match.
+const CONTEXT_BYTES: usize = 48;
+/// Regions are cut at this size so one huge encoded blob cannot take the whole budget.
+const MAX_REGION_BYTES: usize = 4096;
+
+pub const DEFAULT_DECODE_MAX_REGIONS: usize = 256;
+pub const DEFAULT_DECODE_MAX_BYTES: usize = 64 * 1024;
+
+/// Named entities worth decoding: markup escapes plus the punctuation/whitespace names used to
+/// break up trigger phrases. Anything else is left as is.
+const NAMED_ENTITIES: &[(&str, char)] = &[
+    ("amp", '&'),
+    ("lt", '<'),
+    ("gt", '>'),
+    ("quot", '"'),
+    ("apos", '\''),
+    ("nbsp", ' '),
+    ("Tab", '\t'),
+    ("NewLine", '\n'),
+    ("colon", ':'),
+    ("sol", '/'),
+    ("period", '.'),
+    ("comma", ','),
+    ("lpar", '('),
+    ("rpar", ')'),
+    ("percnt", '%'),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeBudget {
+    pub max_regions: usize,
+    pub max_decoded_bytes: usize,
+}
+
+impl Default for DecodeBudget {
+    fn default() -> Self {
+        Self {
+            max_regions: DEFAULT_DECODE_MAX_REGIONS,
+            max_decoded_bytes: DEFAULT_DECODE_MAX_BYTES,
+        }
+    }
+}
+
+/// Work done by one decode pass; every counter stays within the [`DecodeBudget`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Encoded regions found in the input (decoded or not).
+    pub regions_found: usize,
+    /// Regions decoded, across both passes.
+    pub regions_decoded: usize,
+    /// Decoded output bytes, across both passes.
+    pub decoded_bytes: usize,
+    /// Regions that were still encoded after the first pass and got a second one.
+    pub second_pass_regions: usize,
+    /// Some regions were skipped because the budget ran out.
+    pub budget_exhausted: bool,
+}
+
+/// Length of the encoded token starting at `i`, if any.
+fn token_len(b: &[u8], i: usize) -> Option<usize> {
+    match b[i] {
+        b'%' => {
+            let ok =
+                i + 2 < b.len() && b[i + 1].is_ascii_hexdigit() && b[i + 2].is_ascii_hexdigit();
+            ok.then_some(3)
+        }
+        b'&' => {
+            let rest = &b[i + 1..];
+            if rest.first() == Some(&b'#') {
+                let (digits, max, start) = match rest.get(1) {
+                    Some(b'x') | Some(b'X') => (
+                        rest[2..]
+                            .iter()
+                            .take_while(|c| c.is_ascii_hexdigit())
+                            .count(),
+                        6,
+                        2,
+                    ),
+                    _ => (
+                        rest[1..].iter().take_while(|c| c.is_ascii_digit()).count(),
+                        7,
+                        1,
+                    ),
+                };
+                if digits == 0 || digits > max {
+                    return None;
+                }
+                // Browsers accept numeric references without the trailing `;`.
+                let semi = usize::from(rest.get(start + digits) == Some(&b';'));
+                Some(1 + start + digits + semi)
+            } else {
+                let name = rest.iter().take_while(|c| c.is_ascii_alphabetic()).count();
+                let known = NAMED_ENTITIES
+                    .iter()
+                    .any(|(n, _)| n.as_bytes() == &rest[..name]);
+                (known && rest.get(name) == Some(&b';')).then_some(name + 2)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Decode every entity and percent escape in `s`, one level.
+pub fn decode_once(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out: Vec<u8> = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let Some(len) = token_len(b, i) else {
+            out.push(b[i]);
+            i += 1;
+            continue;
+        };
+        let tok = &s[i..i + len];
+        if let Some(hex) = tok.strip_prefix('%') {
+            out.push(u8::from_str_radix(hex, 16).unwrap_or(b'?'));
+        } else {
+            let body = tok[1..].trim_end_matches(';');
+            let ch = if let Some(num) = body.strip_prefix('#') {
+                let value = match num.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => num.parse::<u32>().ok(),
+                };
+                value
+                    .and_then(char::from_u32)
+                    .filter(|c| *c != '\0')
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+            } else {
+                NAMED_ENTITIES
+                    .iter()
+                    .find(|(n, _)| *n == body)
+                    .map(|(_, c)| *c)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER)
+            };
+            let mut buf = [0u8; 4];
+            out.extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+        }
+        i += len;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn floor_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+fn ceil_boundary(s: &str, mut i: usize) -> usize {
+    while i < s.len() && !s.is_char_boundary(i) {
+        i += 1;
+    }
+    i
+}
+
+/// Byte ranges of encoded-token clusters in `s`, widened by [`CONTEXT_BYTES`] on each side.
+pub fn find_regions(s: &str) -> Vec<std::ops::Range<usize>> {
+    let b = s.as_bytes();
+    let mut clusters: Vec<std::ops::Range<usize>> = vec![];
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'%' && b[i] != b'&' {
+            i += 1;
+            continue;
+        }
+        let Some(len) = token_len(b, i) else {
+            i += 1;
+            continue;
+        };
+        match clusters.last_mut() {
+            Some(c) if i - c.end <= MERGE_GAP_BYTES && i + len - c.start <= MAX_REGION_BYTES => {
+                c.end = i + len;
+            }
+            _ => clusters.push(i..i + len),
+        }
+        i += len;
+    }
+
+    clusters
+        .into_iter()
+        .map(|c| {
+            let start = ceil_boundary(s, c.start.saturating_sub(CONTEXT_BYTES));
+            let end = floor_boundary(s, (c.end + CONTEXT_BYTES).min(s.len()));
+            start..end
+        })
+        .collect()
+}
+
+/// Reserve budget for decoding `len` input bytes. Decoded output is never longer than its
+/// input, so the input length is a safe upper bound.
+fn charge(stats: &mut DecodeStats, budget: &DecodeBudget, len: usize) -> bool {
+    if stats.regions_decoded >= budget.max_regions
+        || stats.decoded_bytes + len > budget.max_decoded_bytes
+    {
+        stats.budget_exhausted = true;
+        return false;
+    }
+    stats.regions_decoded += 1;
+    true
+}
+
+/// Decoded views of the encoded regions of `text`, within `budget`.
+pub fn decode_regions(text: &str, budget: &DecodeBudget) -> (Vec<String>, DecodeStats) {
+    let mut stats = DecodeStats::default();
+    let mut views = vec![];
+
+    let regions = find_regions(text);
+    stats.regions_found = regions.len();
+
+    for r in regions {
+        let region = &text[r];
+        if !charge(&mut stats, budget, region.len()) {
+            break;
+        }
+        let decoded = decode_once(region);
+        stats.decoded_bytes += decoded.len();
+
+        // Double encoding: exactly one more pass over what is still encoded, never more.
+        if !find_regions(&decoded).is_empty() && charge(&mut stats, budget, decoded.len()) {
+            stats.second_pass_regions += 1;
+            let twice = decode_once(&decoded);
+            stats.decoded_bytes += twice.len();
+            views.push(twice);
+        }
+        views.push(decoded);
+    }
+
+    (views, stats)
+}
+
+/// [`threat::assess`] over `text` plus its decoded views.
+///
+/// Matches only found in decoded content are attributed to [`ScanStage::DecodedContent`] and add
+/// an [`threat::OBFUSCATION_INDICATOR`].
+pub fn assess_with_decoding(text: &str, budget: &DecodeBudget) -> (ThreatAssessment, DecodeStats) {
+    let mut a = ThreatAssessment::none();
+    for hit in threat::scan(text) {
+        a.detected.push(DetectedPattern {
+            indicator: hit.indicator.clone(),
+            stage: ScanStage::Raw,
+        });
+        a.add(hit.ty, hit.indicator, hit.score);
+    }
+
+    let (views, stats) = decode_regions(text, budget);
+    let mut hidden = false;
+    for view in &views {
+        for hit in threat::scan(view) {
+            if a.indicators.contains(&hit.indicator) {
+                continue;
+            }
+            hidden = true;
+            a.detected.push(DetectedPattern {
+                indicator: hit.indicator.clone(),
+                stage: ScanStage::DecodedContent,
+            });
+            a.add(hit.ty, hit.indicator, hit.score);
+        }
+    }
+    if hidden {
+        a.add(
+            threat::AttackType::Obfuscation,
+            threat::OBFUSCATION_INDICATOR,
+            threat::OBFUSCATION_SCORE,
+        );
+    }
+
+    a.normalize();
+    (a, stats)
+}
@@ -0,0 +1,305 @@
+//! Deprecated config keys, env vars and headers, declared in one registry.
+//!
+//! Each [`Deprecation`] names the old form, its replacement and the release in which the old
+//! form stops working. Before that release a use is accepted and moved onto the replacement
+//! (through the entry's `translate` function when the value changes shape), logged once per
+//! entry at `warn`, listed under `deprecations_in_use` in `/v1/acip/status`, and reported by
+//! `acipctl config validate` and `acipctl doctor`. From the sunset release on, compared with
+//! this build's `CARGO_PKG_VERSION`, the same use is an error; nothing has to be edited when
+//! that release ships.
+//!
+//! - Config keys are rewritten in the TOML document before it is deserialized
+//!   ([`Registry::translate_config`]); setting both the old and the new key is an error.
+//! - Env vars are written onto their config key at startup ([`Registry::apply_env`]) and win
+//!   over the file, as they always did.
+//! - Headers are copied onto their replacement before any handler reads them, and the
+//!   response says so with `X-ACIP-Deprecated: <old>=<replacement>`
+//!   ([`crate::acip_headers::reject_duplicates`]).
+
+use crate::build_info::parse_version;
+use crate::config::Config;
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Response header naming each deprecated request header that was used.
+pub const RESPONSE_HEADER: &str = "x-acip-deprecated";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Form {
+    /// A dotted config file key.
+    ConfigKey,
+    /// An environment variable; its replacement is a config key.
+    Env,
+    /// A request header.
+    Header,
+}
+
+/// Maps an old value onto the shape of its replacement; `Err` says why it cannot.
+pub type Translate = fn(toml::Value) -> Result<toml::Value, String>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Deprecation {
+    pub form: Form,
+    pub old: &'static str,
+    pub replacement: &'static str,
+    /// First release in which `old` is refused.
+    pub sunset: &'static str,
+    /// `None` moves the value unchanged.
+    pub translate: Option<Translate>,
+}
+
+/// Entries are identified by their names; `translate` is not compared.
+impl PartialEq for Deprecation {
+    fn eq(&self, other: &Self) -> bool {
+        (self.form, self.old, self.replacement, self.sunset)
+            == (other.form, other.old, other.replacement, other.sunset)
+    }
+}
+
+impl Eq for Deprecation {}
+
+/// A deprecated form found in use.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeprecationUse {
+    pub form: Form,
+    pub old: String,
+    pub replacement: String,
+    pub sunset: String,
+}
+
+impl DeprecationUse {
+    pub fn message(&self) -> String {
+        format!(
+            "{} is deprecated and stops working in {}; set {} instead",
+            self.old, self.sunset, self.replacement
+        )
+    }
+}
+
+fn integer(v: toml::Value) -> Result<toml::Value, String> {
+    let s = v.as_str().ok_or("expected a string")?.trim();
+    let n: u64 = s.parse().map_err(|_| format!("{s:?} is not an integer"))?;
+    i64::try_from(n)
+        .map(toml::Value::Integer)
+        .map_err(|_| format!("{s} is too large"))
+}
+
+fn float(v: toml::Value) -> Result<toml::Value, String> {
+    let s = v.as_str().ok_or("expected a string")?.trim();
+    s.parse()
+        .map(toml::Value::Float)
+        .map_err(|_| format!("{s:?} is not a number"))
+}
+
+const fn env(old: &'static str, replacement: &'static str, translate: Translate) -> Deprecation {
+    Deprecation {
+        form: Form::Env,
+        old,
+        replacement,
+        sunset: "0.3.0",
+        translate: Some(translate),
+    }
+}
+
+/// Every deprecation this build knows about.
+pub const BUILTIN: &[Deprecation] = &[
+    env("ACIP_REP_MED", "reputation.medium_score", integer),
+    env("ACIP_REP_HIGH", "reputation.high_score", integer),
+    env("ACIP_REP_BAD", "reputation.bad_actor_score", integer),
+    env(
+        "ACIP_REP_HALFLIFE_BASE_DAYS",
+        "reputation.half_life_base_days",
+        float,
+    ),
+    env("ACIP_REP_HALFLIFE_K", "reputation.half_life_k", float),
+    env(
+        "ACIP_REP_TRUST_MAX_DISCOUNT",
+        "reputation.trust_max_discount",
+        float,
+    ),
+    env(
+        "ACIP_REP_TRUST_FULL_AGE_DAYS",
+        "reputation.trust_full_age_days",
+        float,
+    ),
+    env(
+        "ACIP_REP_TRUST_FULL_CLEAN_INGESTS",
+        "reputation.trust_full_clean_ingests",
+        integer,
+    ),
+];
+
+/// The deprecations in force and the release they are judged against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    entries: Vec<Deprecation>,
+    version: String,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Registry {
+    /// [`BUILTIN`], judged against this build's version.
+    pub fn builtin() -> Self {
+        Self {
+            entries: BUILTIN.to_vec(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    pub fn with_entry(mut self, entry: Deprecation) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    /// Judge sunsets as if this were release `version`.
+    pub fn with_version(mut self, version: &str) -> Self {
+        self.version = version.to_string();
+        self
+    }
+
+    pub fn entries(&self, form: Form) -> impl Iterator<Item = &Deprecation> {
+        self.entries.iter().filter(move |d| d.form == form)
+    }
+
+    /// The use of `d`, or an error once its sunset release is reached.
+    pub fn check(&self, d: &Deprecation) -> Result<DeprecationUse> {
+        if parse_version(&self.version) >= parse_version(d.sunset) {
+            bail!(
+                "{} was removed in {}; set {} instead",
+                d.old,
+                d.sunset,
+                d.replacement
+            );
+        }
+        Ok(DeprecationUse {
+            form: d.form,
+            old: d.old.to_string(),
+            replacement: d.replacement.to_string(),
+            sunset: d.sunset.to_string(),
+        })
+    }
+
+    fn translate(d: &Deprecation, v: toml::Value) -> Result<toml::Value> {
+        match d.translate {
+            Some(f) => f(v).map_err(|e| anyhow!("{}: {e}", d.old)),
+            None => Ok(v),
+        }
+    }
+
+    /// Move deprecated keys of a config document onto their replacements.
+    pub fn translate_config(&self, doc: &mut toml::Value) -> Result<Vec<DeprecationUse>> {
+        let mut uses = vec![];
+        for d in self.entries(Form::ConfigKey) {
+            let Some(v) = take(doc, d.old) else {
+                continue;
+            };
+            let used = self.check(d)?;
+            if get(doc, d.replacement).is_some() {
+                bail!(
+                    "{} and {} are both set; keep only {}",
+                    d.old,
+                    d.replacement,
+                    d.replacement
+                );
+            }
+            put(doc, d.replacement, Self::translate(d, v)?)?;
+            uses.push(used);
+        }
+        Ok(uses)
+    }
+
+    /// Write the deprecated env vars `env` returns onto their config keys. Without a config
+    /// file the uses are only checked: the reputation thresholds still read the env vars
+    /// themselves until the sunset.
+    pub fn apply_env(
+        &self,
+        cfg: Option<Config>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(Option<Config>, Vec<DeprecationUse>)> {
+        let set: Vec<_> = self
+            .entries(Form::Env)
+            .filter_map(|d| env(d.old).map(|v| (d, v)))
+            .collect();
+        let mut uses = vec![];
+        for (d, _) in &set {
+            uses.push(self.check(d)?);
+        }
+        let cfg = match cfg {
+            Some(cfg) if !set.is_empty() => cfg,
+            cfg => return Ok((cfg, uses)),
+        };
+        let mut doc = toml::Value::try_from(&cfg)?;
+        for (d, v) in set {
+            put(
+                &mut doc,
+                d.replacement,
+                Self::translate(d, toml::Value::String(v))?,
+            )?;
+        }
+        let cfg = Config::parse(&toml::to_string(&doc)?)?;
+        cfg.validate()?;
+        Ok((Some(cfg), uses))
+    }
+}
+
+fn get<'a>(doc: &'a toml::Value, path: &str) -> Option<&'a toml::Value> {
+    path.split('.').try_fold(doc, |v, k| v.get(k))
+}
+
+fn take(doc: &mut toml::Value, path: &str) -> Option<toml::Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((p, k)) => (p.split('.').try_fold(doc, |v, k| v.get_mut(k))?, k),
+        None => (doc, path),
+    };
+    parent.as_table_mut()?.remove(key)
+}
+
+fn put(doc: &mut toml::Value, path: &str, value: toml::Value) -> Result<()> {
+    let (parents, key) = match path.rsplit_once('.') {
+        Some((p, k)) => (Some(p), k),
+        None => (None, path),
+    };
+    let mut v = doc;
+    for k in parents.into_iter().flat_map(|p| p.split('.')) {
+        v = v
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("{path}: {k} is not a table"))?
+            .entry(k)
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+    }
+    v.as_table_mut()
+        .ok_or_else(|| anyhow!("{path}: parent is not a table"))?
+        .insert(key.to_string(), value);
+    Ok(())
+}
+
+static IN_USE: Mutex<BTreeMap<String, DeprecationUse>> = Mutex::new(BTreeMap::new());
+
+/// Note a use; the first one of each entry is logged. True when it had not been seen yet.
+pub fn record(used: &DeprecationUse) -> bool {
+    let mut in_use = IN_USE.lock().unwrap();
+    if in_use.contains_key(&used.old) {
+        return false;
+    }
+    tracing::warn!(
+        old = %used.old,
+        replacement = %used.replacement,
+        sunset = %used.sunset,
+        "{}",
+        used.message()
+    );
+    in_use.insert(used.old.clone(), used.clone());
+    true
+}
+
+/// Deprecated forms used since startup, by old name.
+pub fn in_use() -> Vec<DeprecationUse> {
+    IN_USE.lock().unwrap().values().cloned().collect()
+}
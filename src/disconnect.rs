@@ -0,0 +1,172 @@
+//! Cancellation of synchronous ingests whose client has gone away.
+//!
+//! `ingest_source` runs the pipeline on its own task and waits for it behind a
+//! [`DisconnectGuard`]. When the client disconnects, hyper drops the handler, the guard cancels
+//! the run's [`CancelToken`], and the pipeline stops at its next stage boundary: the extractor
+//! helper is killed as on a timeout, and an in-flight model call is dropped, which aborts its
+//! HTTP request.
+//!
+//! What happens to the run's side effects is `server.on_client_disconnect`:
+//!
+//! - `complete_side_effects` (default): the reputation update is made from whatever the run had
+//!   gathered (a bare sighting if nothing was scanned yet), and the audit entry and SIEM event
+//!   are written with `error: client_disconnected` and the stage the disconnect was noticed at.
+//!   Disconnecting does not get an attacker out of reputation consequences.
+//! - `abandon`: nothing more is written. Side effects already made stay.
+//!
+//! Async jobs are not affected: their client connection is only the submission.
+
+use crate::config;
+use crate::slow_requests::Stage;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::Notify;
+
+/// `error` of the response (and audit entry) of a run cut short by a disconnect.
+pub const ERROR_CODE: &str = "client_disconnected";
+
+/// Status recorded for a disconnected run: nginx's "client closed request". Nobody receives it.
+pub const STATUS: u16 = 499;
+
+/// `server.on_client_disconnect`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnClientDisconnect {
+    #[default]
+    CompleteSideEffects,
+    Abandon,
+}
+
+impl OnClientDisconnect {
+    /// `server.on_client_disconnect`, overridden by `ACIP_ON_CLIENT_DISCONNECT`.
+    pub fn from_config(cfg: Option<&config::Config>) -> Self {
+        if let Ok(v) = std::env::var("ACIP_ON_CLIENT_DISCONNECT") {
+            match v.trim() {
+                "complete_side_effects" => return Self::CompleteSideEffects,
+                "abandon" => return Self::Abandon,
+                other => {
+                    tracing::warn!(value = other, "ignoring unknown ACIP_ON_CLIENT_DISCONNECT")
+                }
+            }
+        }
+        cfg.and_then(|c| c.server.as_ref())
+            .and_then(|s| s.on_client_disconnect)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Cancellation flag shared by a run and whoever may cancel it. The default token is never
+/// cancelled unless [`CancelToken::cancel`] is called on it.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<Inner>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once the token is cancelled (at once if it already is).
+    pub async fn cancelled(&self) {
+        loop {
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// A guard that cancels this token when dropped, unless disarmed first.
+    pub fn drop_guard(&self) -> DisconnectGuard {
+        DisconnectGuard(Some(self.clone()))
+    }
+}
+
+/// Held by a handler while it waits for its run; see [`CancelToken::drop_guard`].
+pub struct DisconnectGuard(Option<CancelToken>);
+
+impl DisconnectGuard {
+    /// The handler finished normally: dropping the guard no longer cancels.
+    pub fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for DisconnectGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// Disconnect handling mode and counters.
+#[derive(Default)]
+pub struct Disconnects {
+    mode: OnClientDisconnect,
+    total: AtomicU64,
+    by_stage: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Disconnects {
+    pub fn new(mode: OnClientDisconnect) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
+    }
+
+    pub fn mode(&self) -> OnClientDisconnect {
+        self.mode
+    }
+
+    /// Count a run cut short at `stage`.
+    pub fn record(&self, stage: Stage) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        *self
+            .by_stage
+            .lock()
+            .unwrap()
+            .entry(stage.as_str())
+            .or_default() += 1;
+    }
+
+    pub fn snapshot(&self) -> DisconnectSnapshot {
+        DisconnectSnapshot {
+            mode: self.mode,
+            cancelled: self.total.load(Ordering::Relaxed),
+            by_stage: self.by_stage.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// `client_disconnects` in `/v1/acip/status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DisconnectSnapshot {
+    pub mode: OnClientDisconnect,
+    /// Runs cut short since startup.
+    pub cancelled: u64,
+    /// The same, by the stage the disconnect was noticed at.
+    pub by_stage: BTreeMap<&'static str, u64>,
+}
@@ -0,0 +1,234 @@
+//! Maintenance drain: stop accepting new ingest work without stopping the process.
+//!
+//! While draining, ingest routes answer 503 with a structured `draining` error; requests already
+//! admitted run to completion and are tracked by an in-flight gauge so operators can wait for
+//! idle. Read-only routes and background work are unaffected. The state lives in memory only:
+//! it survives config reloads but not a restart.
+
+use crate::introspection;
+use crate::reputation::{self, Clock};
+use crate::state::AppState;
+use crate::token_auth::{self, Actor};
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+};
+
+/// `retry_after` hint (seconds) sent to callers rejected while draining.
+pub const DRAIN_RETRY_AFTER_SECS: u64 = 30;
+
+/// Who started the current drain, and when.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DrainInfo {
+    pub initiated_by: String,
+    pub since_unix: u64,
+}
+
+pub struct DrainControl {
+    clock: Arc<dyn Clock>,
+    current: Mutex<Option<DrainInfo>>,
+    in_flight: AtomicUsize,
+}
+
+impl Default for DrainControl {
+    fn default() -> Self {
+        Self::new(Arc::new(reputation::SystemClock))
+    }
+}
+
+impl DrainControl {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            current: Mutex::new(None),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Start draining. Idempotent: a second call keeps the original initiator and time.
+    ///
+    /// Returns the active drain and whether this call started it.
+    pub fn drain(&self, initiated_by: &str) -> (DrainInfo, bool) {
+        let mut current = self.current.lock().unwrap();
+        if let Some(info) = current.as_ref() {
+            return (info.clone(), false);
+        }
+        let info = DrainInfo {
+            initiated_by: initiated_by.to_string(),
+            since_unix: self.clock.now_unix(),
+        };
+        *current = Some(info.clone());
+        (info, true)
+    }
+
+    /// Stop draining. Idempotent: returns the drain that was ended, if any.
+    pub fn resume(&self) -> Option<DrainInfo> {
+        self.current.lock().unwrap().take()
+    }
+
+    pub fn info(&self) -> Option<DrainInfo> {
+        self.current.lock().unwrap().clone()
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.current.lock().unwrap().is_some()
+    }
+
+    /// Ingest requests admitted and not yet finished.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Admit one request unless draining. The guard keeps it counted until dropped.
+    pub fn try_begin(self: &Arc<Self>) -> Result<InFlightGuard, DrainInfo> {
+        // Count first, then check: a drain that starts in between still sees this request.
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard(self.clone());
+        match self.info() {
+            Some(info) => Err(info),
+            None => Ok(guard),
+        }
+    }
+
+    /// JSON view for `/status` and the admin endpoints.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let info = self.info();
+        json!({
+            "draining": info.is_some(),
+            "initiated_by": info.as_ref().map(|i| i.initiated_by.clone()),
+            "since_unix": info.as_ref().map(|i| i.since_unix),
+            "in_flight": self.in_flight(),
+        })
+    }
+}
+
+pub struct InFlightGuard(Arc<DrainControl>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Gate for routes that take new work: 503 while draining, otherwise counted as in flight.
+pub async fn gate_new_work(
+    State(drain): State<Arc<DrainControl>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match drain.try_begin() {
+        Ok(_guard) => next.run(req).await,
+        Err(info) => {
+            let mut resp = introspection::json_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "draining",
+                json!({
+                    "retry_after": DRAIN_RETRY_AFTER_SECS,
+                    "since_unix": info.since_unix,
+                }),
+            )
+            .into_response();
+            resp.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(DRAIN_RETRY_AFTER_SECS),
+            );
+            resp
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DrainRequest {
+    /// Operator or automation starting the drain (shown on `/status`).
+    #[serde(default)]
+    pub initiated_by: Option<String>,
+}
+
+/// POST /v1/acip/admin/drain
+pub async fn post_drain(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+    body: Option<Json<DrainRequest>>,
+) -> impl IntoResponse {
+    let initiated_by = body
+        .and_then(|Json(b)| b.initiated_by)
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let (info, started) = state.drain.drain(&initiated_by);
+    if started {
+        tracing::warn!(
+            initiated_by = %info.initiated_by,
+            actor = %token_auth::actor_name(actor),
+            in_flight = state.drain.in_flight(),
+            "Drain started: rejecting new ingest work"
+        );
+    }
+
+    let mut v = state.drain.snapshot();
+    v["changed"] = json!(started);
+    (StatusCode::OK, Json(v))
+}
+
+/// POST /v1/acip/admin/resume
+pub async fn post_resume(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+) -> impl IntoResponse {
+    let ended = state.drain.resume();
+    if let Some(info) = &ended {
+        tracing::warn!(
+            initiated_by = %info.initiated_by,
+            actor = %token_auth::actor_name(actor),
+            since_unix = info.since_unix,
+            "Drain ended: accepting ingest work"
+        );
+    }
+
+    let mut v = state.drain.snapshot();
+    v["changed"] = json!(ended.is_some());
+    (StatusCode::OK, Json(v))
+}
+
+/// GET /health/ready (and /ready): not ready while draining, so load balancers route away.
+/// Stale external feeds and an open extractor breaker report `degraded` but stay ready: the
+/// last good data keeps serving, and documents that need no extraction are unaffected.
+pub async fn get_ready(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let draining = state.drain.is_draining();
+    let stale_feeds = state.feeds.stale_feeds();
+    let extractor_available = state.extractor_health.is_available();
+    let status = if draining {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    let label = if draining {
+        "draining"
+    } else if !stale_feeds.is_empty() || !extractor_available {
+        "degraded"
+    } else {
+        "ok"
+    };
+    let mut v = json!({
+        "status": label,
+        "checks": {
+            "accepting_traffic": !draining,
+            "feeds_fresh": stale_feeds.is_empty(),
+            "extractor_available": extractor_available,
+        },
+    });
+    if !stale_feeds.is_empty() {
+        v["stale_feeds"] = json!(stale_feeds);
+    }
+    (status, Json(v))
+}
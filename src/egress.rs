@@ -0,0 +1,123 @@
+//! Outbound destinations: the URLs the sidecar calls on its own (the notify webhook and
+//! per-request callbacks, job callbacks, the SIEM endpoint, feed sources, the model-version
+//! webhook).
+//!
+//! Every such URL passes [`EgressPolicy::check`] against one host allowlist, `ACIP_EGRESS_HOSTS`
+//! (comma-separated): `https://` only, no user or password, a host name rather than an IP
+//! address, and a host on the list. A destination an operator sets in the config file may also
+//! be `http://` to a loopback host ([`EgressPolicy::with_loopback`]); a URL a caller names may
+//! not. Deliveries and fetches go through [`client`], which does not follow redirects, so an
+//! allowed host cannot send a request on to one that is not.
+
+use reqwest::{redirect, Client, RequestBuilder, Response};
+use std::{collections::HashSet, time::Duration};
+use url::{Host, Url};
+
+/// The allowlist variable.
+pub const HOSTS_ENV: &str = "ACIP_EGRESS_HOSTS";
+
+/// Hosts outbound URLs may point at (`ACIP_EGRESS_HOSTS`, comma-separated).
+pub fn allowed_hosts_from_env() -> HashSet<String> {
+    std::env::var(HOSTS_ENV)
+        .unwrap_or_default()
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .filter(|h| !h.is_empty())
+        .collect()
+}
+
+/// The client outbound deliveries and fetches use: ACIP timeouts, no redirects.
+pub fn client() -> anyhow::Result<Client> {
+    Ok(Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(30))
+        .redirect(redirect::Policy::none())
+        .build()?)
+}
+
+/// Send `req`. Anything but a 2xx answer is an error, a redirect included: it is not followed.
+pub async fn send(req: RequestBuilder) -> Result<Response, String> {
+    let resp = req.send().await.map_err(|e| e.without_url().to_string())?;
+    let status = resp.status();
+    if status.is_redirection() {
+        return Err(format!("redirect ({status}) not followed"));
+    }
+    resp.error_for_status()
+        .map_err(|e| e.without_url().to_string())
+}
+
+/// What an outbound URL may look like.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EgressPolicy {
+    /// Hosts a URL may point at: lowercase, IPv6 addresses without brackets.
+    pub allowed_hosts: HashSet<String>,
+    /// Accept `http://` URLs, not only `https://`.
+    pub allow_http: bool,
+    /// Accept IP address hosts, not only names.
+    pub allow_ip_literals: bool,
+    /// Accept `http://` and IP address hosts when the host is loopback.
+    pub allow_loopback: bool,
+}
+
+impl EgressPolicy {
+    /// `https://` to a host name on `allowed_hosts`.
+    pub fn new(allowed_hosts: HashSet<String>) -> Self {
+        Self {
+            allowed_hosts,
+            ..Self::default()
+        }
+    }
+
+    /// The strict policy over `ACIP_EGRESS_HOSTS`.
+    pub fn from_env() -> Self {
+        Self::new(allowed_hosts_from_env())
+    }
+
+    /// Also accept `http://` to a loopback host: for destinations set in the config file.
+    pub fn with_loopback(mut self) -> Self {
+        self.allow_loopback = true;
+        self
+    }
+
+    /// `raw` as a URL this policy allows, or why it is refused.
+    pub fn check(&self, raw: &str) -> Result<Url, &'static str> {
+        let url = Url::parse(raw.trim()).map_err(|_| "invalid URL")?;
+        let loopback = self.allow_loopback && is_loopback(&url);
+        match url.scheme() {
+            "https" => {}
+            "http" if self.allow_http || loopback => {}
+            _ if self.allow_http => return Err("scheme must be http or https"),
+            _ => return Err("scheme must be https"),
+        }
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err("credentials in the URL");
+        }
+        match url.host() {
+            None => return Err("no host"),
+            Some(Host::Domain(_)) => {}
+            Some(_) if self.allow_ip_literals || loopback => {}
+            Some(_) => return Err("IP address hosts are not allowed"),
+        }
+        if !self.allowed_hosts.contains(&bare_host(&url)) {
+            return Err("host not in ACIP_EGRESS_HOSTS");
+        }
+        Ok(url)
+    }
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Domain(d)) => d.eq_ignore_ascii_case("localhost"),
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+/// `url`'s host, lowercase and without IPv6 brackets.
+fn bare_host(url: &Url) -> String {
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    host.trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string()
+}
@@ -0,0 +1,559 @@
+//! Startup check of the limits the host actually enforces.
+//!
+//! The extractor helper gets its own rlimits (`ACIP_EXTRACTOR_RLIMIT_*`), but the host can
+//! undercut them without saying so: a process hard limit below the configured one, a cgroup
+//! memory limit below `rlimit_as_mb` (the OOM killer fires before our limit does), a `noexec`
+//! temp dir. At startup the sidecar probes its own rlimits, the cgroup memory and pid limits,
+//! the temp dir and the monotonic clock, and compares them with the extractor settings.
+//!
+//! Conflicts are logged, shown on `/status` under `environment` and reported by
+//! `acipctl doctor`. A hard conflict is a configured limit above what the host can enforce;
+//! with `[startup] environment = "strict"` the sidecar refuses to start on one. Anything the
+//! probe cannot read (another OS, a cgroup file hidden in an unprivileged container) is
+//! reported as unknown and never checked.
+
+use crate::{config, extract, tmpdir::TmpDirSettings};
+use serde::{Deserialize, Serialize, Serializer};
+use std::path::Path;
+
+const MB: u64 = 1024 * 1024;
+
+/// What a hard conflict does at startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mode {
+    /// Log it and start anyway.
+    #[default]
+    Warn,
+    /// Refuse to start.
+    Strict,
+}
+
+impl Mode {
+    /// `startup.environment`, overridden by `ACIP_STARTUP_ENVIRONMENT`.
+    pub fn from_config(cfg: Option<&config::Config>) -> Self {
+        match std::env::var("ACIP_STARTUP_ENVIRONMENT").ok().as_deref() {
+            Some("strict") => Self::Strict,
+            Some("warn") => Self::Warn,
+            _ => cfg
+                .and_then(|c| c.startup.as_ref())
+                .and_then(|s| s.environment)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// A host ceiling: a number, no limit, or not readable here.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Ceiling {
+    #[default]
+    Unknown,
+    Unlimited,
+    Limit(u64),
+}
+
+impl Ceiling {
+    /// True when the ceiling is known and below `value`.
+    pub fn below(self, value: u64) -> bool {
+        matches!(self, Self::Limit(limit) if limit < value)
+    }
+}
+
+impl Serialize for Ceiling {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Unknown => s.serialize_str("unknown"),
+            Self::Unlimited => s.serialize_str("unlimited"),
+            Self::Limit(n) => s.serialize_u64(*n),
+        }
+    }
+}
+
+/// The sidecar's own hard rlimits, which the helper inherits and cannot raise.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Rlimits {
+    pub nofile: Ceiling,
+    pub address_space_bytes: Ceiling,
+    pub file_size_bytes: Ceiling,
+    pub nproc: Ceiling,
+    pub cpu_secs: Ceiling,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Cgroup {
+    /// 1 or 2; `None` when no cgroup was found.
+    pub version: Option<u8>,
+    pub memory_bytes: Ceiling,
+    pub pids: Ceiling,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TmpDir {
+    pub path: String,
+    pub free_bytes: Option<u64>,
+    pub noexec: Option<bool>,
+}
+
+/// What the host enforces, as far as it could be read.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Probe {
+    pub os: String,
+    pub rlimits: Rlimits,
+    pub cgroup: Cgroup,
+    pub tmpdir: TmpDir,
+    pub monotonic_clock: Option<bool>,
+}
+
+/// The extractor limits the sidecar is configured to apply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExtractorLimits {
+    /// Helpers expected to run at once (`startup.extractor_concurrency`).
+    pub concurrency: u64,
+    pub rlimit_as_mb: u64,
+    pub rlimit_nofile: u64,
+    pub rlimit_fsize_mb: u64,
+    pub rlimit_nproc: Option<u64>,
+    pub rlimit_cpu_secs: u64,
+    pub tmp_min_free_mb: u64,
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl ExtractorLimits {
+    /// The `ACIP_EXTRACTOR_*` settings as the helper applies them. Extraction itself is not
+    /// capped; `startup.extractor_concurrency` says how many helpers to plan for (default: one
+    /// per CPU).
+    pub fn from_config(cfg: Option<&config::Config>, tmp: &TmpDirSettings) -> Self {
+        let concurrency = cfg
+            .and_then(|c| c.startup.as_ref())
+            .and_then(|s| s.extractor_concurrency)
+            .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+        let timeout =
+            env_u64("ACIP_EXTRACTOR_TIMEOUT_SECS").unwrap_or(extract::DEFAULT_TIMEOUT_SECS);
+        Self {
+            concurrency: concurrency.max(1) as u64,
+            rlimit_as_mb: env_u64("ACIP_EXTRACTOR_RLIMIT_AS_MB")
+                .unwrap_or(extract::DEFAULT_RLIMIT_AS_MB),
+            rlimit_nofile: env_u64("ACIP_EXTRACTOR_RLIMIT_NOFILE")
+                .unwrap_or(extract::DEFAULT_RLIMIT_NOFILE),
+            rlimit_fsize_mb: env_u64("ACIP_EXTRACTOR_RLIMIT_FSIZE_MB")
+                .unwrap_or(extract::DEFAULT_RLIMIT_FSIZE_MB),
+            rlimit_nproc: env_u64("ACIP_EXTRACTOR_RLIMIT_NPROC"),
+            rlimit_cpu_secs: timeout.saturating_add(5).max(1),
+            tmp_min_free_mb: tmp.min_free_bytes / MB,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// A configured limit the host will not let the helper have.
+    Hard,
+    /// Likely to cause failures under load, but not a limit that cannot be applied.
+    Soft,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictClass {
+    NofileAboveHardLimit,
+    AddressSpaceAboveHardLimit,
+    FileSizeAboveHardLimit,
+    NprocAboveHardLimit,
+    CpuAboveHardLimit,
+    AddressSpaceAboveCgroupMemory,
+    ConcurrentMemoryAboveCgroup,
+    PidsBelowConcurrency,
+    TmpdirNoexec,
+    TmpdirLowSpace,
+    NoMonotonicClock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Conflict {
+    pub class: ConflictClass,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Conflict {
+    fn hard(class: ConflictClass, message: String) -> Self {
+        Self {
+            class,
+            severity: Severity::Hard,
+            message,
+        }
+    }
+
+    fn soft(class: ConflictClass, message: String) -> Self {
+        Self {
+            class,
+            severity: Severity::Soft,
+            message,
+        }
+    }
+}
+
+/// Compare the configured extractor limits with what `probe` found. Unknown ceilings are
+/// skipped.
+pub fn check(probe: &Probe, limits: &ExtractorLimits) -> Vec<Conflict> {
+    use ConflictClass::*;
+
+    let mut out = vec![];
+    let rl = &probe.rlimits;
+    let as_bytes = limits.rlimit_as_mb.saturating_mul(MB);
+    let fsize_bytes = limits.rlimit_fsize_mb.saturating_mul(MB);
+
+    if rl.nofile.below(limits.rlimit_nofile) {
+        out.push(Conflict::hard(
+            NofileAboveHardLimit,
+            format!(
+                "rlimit_nofile {} is above the process hard limit {}",
+                limits.rlimit_nofile,
+                show(rl.nofile)
+            ),
+        ));
+    }
+    if rl.address_space_bytes.below(as_bytes) {
+        out.push(Conflict::hard(
+            AddressSpaceAboveHardLimit,
+            format!(
+                "rlimit_as_mb {} is above the process hard limit {}",
+                limits.rlimit_as_mb,
+                show_mb(rl.address_space_bytes)
+            ),
+        ));
+    }
+    if rl.file_size_bytes.below(fsize_bytes) {
+        out.push(Conflict::hard(
+            FileSizeAboveHardLimit,
+            format!(
+                "rlimit_fsize_mb {} is above the process hard limit {}",
+                limits.rlimit_fsize_mb,
+                show_mb(rl.file_size_bytes)
+            ),
+        ));
+    }
+    if let Some(nproc) = limits.rlimit_nproc.filter(|n| rl.nproc.below(*n)) {
+        out.push(Conflict::hard(
+            NprocAboveHardLimit,
+            format!(
+                "rlimit_nproc {nproc} is above the process hard limit {}",
+                show(rl.nproc)
+            ),
+        ));
+    }
+    if rl.cpu_secs.below(limits.rlimit_cpu_secs) {
+        out.push(Conflict::hard(
+            CpuAboveHardLimit,
+            format!(
+                "helper CPU limit {}s (timeout + 5) is above the process hard limit {}",
+                limits.rlimit_cpu_secs,
+                show(rl.cpu_secs)
+            ),
+        ));
+    }
+
+    let memory = probe.cgroup.memory_bytes;
+    if memory.below(as_bytes) {
+        out.push(Conflict::hard(
+            AddressSpaceAboveCgroupMemory,
+            format!(
+                "rlimit_as_mb {} is above the cgroup memory limit {}; the OOM killer fires first",
+                limits.rlimit_as_mb,
+                show_mb(memory)
+            ),
+        ));
+    } else if memory.below(as_bytes.saturating_mul(limits.concurrency)) {
+        out.push(Conflict::soft(
+            ConcurrentMemoryAboveCgroup,
+            format!(
+                "{} concurrent helpers x rlimit_as_mb {} exceeds the cgroup memory limit {}",
+                limits.concurrency,
+                limits.rlimit_as_mb,
+                show_mb(memory)
+            ),
+        ));
+    }
+    if probe
+        .cgroup
+        .pids
+        .below(limits.concurrency.saturating_add(1))
+    {
+        out.push(Conflict::soft(
+            PidsBelowConcurrency,
+            format!(
+                "cgroup pids limit {} leaves no room for {} concurrent helpers",
+                show(probe.cgroup.pids),
+                limits.concurrency
+            ),
+        ));
+    }
+
+    let tmp = &probe.tmpdir;
+    if tmp.noexec == Some(true) {
+        out.push(Conflict::soft(
+            TmpdirNoexec,
+            format!("temp dir {} is on a noexec mount", tmp.path),
+        ));
+    }
+    let needed = limits
+        .tmp_min_free_mb
+        .saturating_add(limits.rlimit_fsize_mb)
+        .saturating_mul(MB);
+    if let Some(free) = tmp.free_bytes.filter(|f| *f < needed) {
+        out.push(Conflict::soft(
+            TmpdirLowSpace,
+            format!(
+                "temp dir {} has {} MB free, below the {} MB floor plus one {} MB helper file",
+                tmp.path,
+                free / MB,
+                limits.tmp_min_free_mb,
+                limits.rlimit_fsize_mb
+            ),
+        ));
+    }
+
+    if probe.monotonic_clock == Some(false) {
+        out.push(Conflict::soft(
+            NoMonotonicClock,
+            "CLOCK_MONOTONIC is unavailable; timeouts may misfire".to_string(),
+        ));
+    }
+    out
+}
+
+fn show(c: Ceiling) -> String {
+    match c {
+        Ceiling::Limit(n) => n.to_string(),
+        Ceiling::Unlimited => "unlimited".to_string(),
+        Ceiling::Unknown => "unknown".to_string(),
+    }
+}
+
+fn show_mb(c: Ceiling) -> String {
+    match c {
+        Ceiling::Limit(n) => format!("{} MB", n / MB),
+        other => show(other),
+    }
+}
+
+/// The startup probe and its verdict, as shown on `/status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    pub mode: Mode,
+    pub probe: Probe,
+    pub extractor: Option<ExtractorLimits>,
+    pub conflicts: Vec<Conflict>,
+}
+
+impl Report {
+    /// Probe the host and check it against the configured extractor limits.
+    pub fn assess(cfg: Option<&config::Config>, tmp: &TmpDirSettings) -> Self {
+        let probe = probe(&tmp.base);
+        let limits = ExtractorLimits::from_config(cfg, tmp);
+        Self {
+            mode: Mode::from_config(cfg),
+            conflicts: check(&probe, &limits),
+            probe,
+            extractor: Some(limits),
+        }
+    }
+
+    pub fn hard_conflicts(&self) -> impl Iterator<Item = &Conflict> {
+        self.conflicts
+            .iter()
+            .filter(|c| c.severity == Severity::Hard)
+    }
+
+    /// Log every conflict; in strict mode, fail on a hard one.
+    pub fn enforce(&self) -> anyhow::Result<()> {
+        for c in &self.conflicts {
+            tracing::warn!(class = ?c.class, severity = ?c.severity, "environment: {}", c.message);
+        }
+        let hard: Vec<_> = self.hard_conflicts().map(|c| c.message.as_str()).collect();
+        if self.mode == Mode::Strict && !hard.is_empty() {
+            anyhow::bail!(
+                "startup.environment = strict: {} hard conflict(s): {}",
+                hard.len(),
+                hard.join("; ")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Read what the host enforces. Never fails: anything unreadable stays unknown.
+pub fn probe(tmpdir: &Path) -> Probe {
+    let (free_bytes, noexec) = statvfs(tmpdir);
+    Probe {
+        os: std::env::consts::OS.to_string(),
+        rlimits: rlimits(),
+        cgroup: cgroup(),
+        tmpdir: TmpDir {
+            path: tmpdir.display().to_string(),
+            free_bytes,
+            noexec,
+        },
+        monotonic_clock: monotonic_clock(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn rlimits() -> Rlimits {
+    fn hard(resource: libc::__rlimit_resource_t) -> Ceiling {
+        let mut lim = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if unsafe { libc::getrlimit(resource, &mut lim) } != 0 {
+            return Ceiling::Unknown;
+        }
+        if lim.rlim_max == libc::RLIM_INFINITY {
+            Ceiling::Unlimited
+        } else {
+            Ceiling::Limit(lim.rlim_max)
+        }
+    }
+    Rlimits {
+        nofile: hard(libc::RLIMIT_NOFILE),
+        address_space_bytes: hard(libc::RLIMIT_AS),
+        file_size_bytes: hard(libc::RLIMIT_FSIZE),
+        nproc: hard(libc::RLIMIT_NPROC),
+        cpu_secs: hard(libc::RLIMIT_CPU),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rlimits() -> Rlimits {
+    Rlimits::default()
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup() -> Cgroup {
+    let Ok(membership) = std::fs::read_to_string("/proc/self/cgroup") else {
+        return Cgroup::default();
+    };
+    cgroup_at(Path::new("/sys/fs/cgroup"), &membership)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup() -> Cgroup {
+    Cgroup::default()
+}
+
+/// Limits for the cgroups listed in `membership` (`/proc/self/cgroup`) under `root`. The
+/// effective limit is the lowest along the path, so every ancestor is read; in a container
+/// with a private cgroup namespace the own path is `/` and only the root files exist.
+#[cfg(target_os = "linux")]
+fn cgroup_at(root: &Path, membership: &str) -> Cgroup {
+    let mut unified = None;
+    let (mut memory_v1, mut pids_v1) = (None, None);
+    for line in membership.lines() {
+        let mut fields = line.splitn(3, ':');
+        let (Some(_), Some(controllers), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        if controllers.is_empty() {
+            unified = Some(path);
+        }
+        for c in controllers.split(',') {
+            match c {
+                "memory" => memory_v1 = Some(path),
+                "pids" => pids_v1 = Some(path),
+                _ => {}
+            }
+        }
+    }
+
+    if memory_v1.is_some() || pids_v1.is_some() {
+        // v1 reports "no limit" as a huge page-aligned number.
+        let memory = memory_v1.map_or(Ceiling::Unknown, |p| {
+            match lowest(&root.join("memory"), p, "memory.limit_in_bytes") {
+                Ceiling::Limit(n) if n >= 1 << 62 => Ceiling::Unlimited,
+                c => c,
+            }
+        });
+        let pids = pids_v1.map_or(Ceiling::Unknown, |p| {
+            lowest(&root.join("pids"), p, "pids.max")
+        });
+        return Cgroup {
+            version: Some(1),
+            memory_bytes: memory,
+            pids,
+        };
+    }
+    match unified {
+        Some(p) => Cgroup {
+            version: Some(2),
+            memory_bytes: lowest(root, p, "memory.max"),
+            pids: lowest(root, p, "pids.max"),
+        },
+        None => Cgroup::default(),
+    }
+}
+
+/// The lowest readable `file` from `base/path` up to `base`. Unknown if none was readable.
+#[cfg(target_os = "linux")]
+fn lowest(base: &Path, path: &str, file: &str) -> Ceiling {
+    let mut dir = base.join(path.trim_start_matches('/'));
+    let mut found = Ceiling::Unknown;
+    loop {
+        let value = std::fs::read_to_string(dir.join(file)).ok();
+        match value.as_deref().map(str::trim) {
+            Some("max") if found == Ceiling::Unknown => found = Ceiling::Unlimited,
+            Some(v) => {
+                if let Ok(n) = v.parse::<u64>() {
+                    found = match found {
+                        Ceiling::Limit(m) => Ceiling::Limit(m.min(n)),
+                        _ => Ceiling::Limit(n),
+                    };
+                }
+            }
+            None => {}
+        }
+        if dir == base || !dir.pop() || !dir.starts_with(base) {
+            return found;
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn statvfs(path: &Path) -> (Option<u64>, Option<bool>) {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return (None, None);
+    };
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c.as_ptr(), &mut st) } != 0 {
+        return (None, None);
+    }
+    let free = (st.f_bavail as u64).saturating_mul(st.f_frsize as u64);
+    (Some(free), Some(st.f_flag & libc::ST_NOEXEC != 0))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn statvfs(_path: &Path) -> (Option<u64>, Option<bool>) {
+    (None, None)
+}
+
+#[cfg(unix)]
+fn monotonic_clock() -> Option<bool> {
+    let read = || {
+        let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+        (unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) } == 0)
+            .then_some((ts.tv_sec, ts.tv_nsec))
+    };
+    Some(matches!((read(), read()), (Some(a), Some(b)) if b >= a))
+}
+
+#[cfg(not(unix))]
+fn monotonic_clock() -> Option<bool> {
+    None
+}
@@ -0,0 +1,570 @@
+//! Scoped, time-limited policy experiments with automatic rollback.
+//!
+//! `POST /v1/acip/experiments` applies a modified definition of one policy to a fraction of its
+//! traffic. The definition is sparse, merged over the policy as an `extends` child would be.
+//! A run is assigned an arm from a hash of the experiment id and its reputation key
+//! (`source_id:<id>`), so a source stays in one arm for the whole experiment. Everything not in
+//! the experimental arm is the control arm.
+//!
+//! Each arm counts runs, decisions, blocks and errors (non-2xx answers; disconnects are not
+//! counted). Once both arms have `min_decisions` decisions, the guardrails are checked after
+//! every run:
+//!
+//! - `max_block_rate_delta_pct`: the experimental block rate exceeds control's by more than
+//!   this many percentage points;
+//! - `max_error_rate_pct`: the experimental error rate exceeds this.
+//!
+//! A tripped guardrail rolls the experiment back; `max_duration_secs` ends it. Either way the
+//! whole policy is back on its current definition, and the outcome goes to the change journal
+//! and the log. One experiment per policy may be active. While draining or in read-only mode
+//! no run is assigned to the experimental arm.
+//!
+//! Decisions of the experimental arm carry the experiment id in `provenance` and in their
+//! audit entry, and are counted in the stats under the policy `<policy>@<experiment id>`.
+//! Experiments live in memory: a restart ends them.
+
+use crate::introspection;
+use crate::model_policy::PolicyConfig;
+use crate::policy_store::{DeclaredPolicies, PolicyDecl, PolicyStore};
+use crate::reputation::{Clock, SystemClock};
+use crate::sentry::Action;
+use crate::state::AppState;
+use crate::stats::DAY_SECS;
+use crate::token_auth::{self, Actor};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// Longest allowed `max_duration_secs`.
+pub const MAX_DURATION_SECS: u64 = 7 * DAY_SECS;
+
+/// Decisions each arm needs before the guardrails are checked.
+pub const DEFAULT_MIN_DECISIONS: u64 = 20;
+
+/// Change journal entries and finished experiments kept (oldest dropped first).
+pub const JOURNAL_CAPACITY: usize = 256;
+
+/// Assignment resolution: fractions are applied in steps of 1/10000.
+const BUCKETS: u64 = 10_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Guardrails {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block_rate_delta_pct: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_error_rate_pct: Option<f64>,
+    /// Decisions per arm before the guardrails apply (default [`DEFAULT_MIN_DECISIONS`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_decisions: Option<u64>,
+}
+
+/// Body of `POST /v1/acip/experiments`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ExperimentRequest {
+    /// The policy experimented on.
+    pub policy: String,
+    /// Fields to change, as in a policies file entry (without `extends`).
+    pub definition: PolicyDecl,
+    /// Share of the policy's traffic on the experimental arm, in (0, 1].
+    pub fraction: f64,
+    pub max_duration_secs: u64,
+    pub guardrails: Guardrails,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExperimentError {
+    #[error("unknown policy {0:?}")]
+    UnknownPolicy(String),
+    #[error("policy {policy:?} already has an active experiment ({id})")]
+    AlreadyActive { policy: String, id: String },
+    #[error("{0}")]
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Arm {
+    Control,
+    Experimental,
+}
+
+/// The arm a run was put on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub experiment: String,
+    pub policy: String,
+    pub arm: Arm,
+}
+
+impl Assignment {
+    /// The experiment id, for a run on the experimental arm.
+    pub fn experimental_id(&self) -> Option<&str> {
+        (self.arm == Arm::Experimental).then_some(self.experiment.as_str())
+    }
+}
+
+/// `policy` as counted in the stats for a run assigned `a`.
+pub fn stats_policy(policy: &str, a: Option<&Assignment>) -> String {
+    match a.and_then(Assignment::experimental_id) {
+        Some(id) => format!("{policy}@{id}"),
+        None => policy.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ArmStats {
+    pub runs: u64,
+    pub decisions: u64,
+    pub blocked: u64,
+    pub errors: u64,
+}
+
+impl ArmStats {
+    fn block_rate_pct(&self) -> f64 {
+        pct(self.blocked, self.decisions)
+    }
+
+    fn error_rate_pct(&self) -> f64 {
+        pct(self.errors, self.runs)
+    }
+}
+
+fn pct(n: u64, of: u64) -> f64 {
+    if of == 0 {
+        0.0
+    } else {
+        n as f64 * 100.0 / of as f64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExperimentStatus {
+    Active,
+    /// Ran for `max_duration_secs`.
+    Expired,
+    /// A guardrail tripped.
+    RolledBack,
+}
+
+/// An experiment as listed by `GET /v1/acip/experiments`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExperimentView {
+    pub id: String,
+    pub policy: String,
+    pub started_by: String,
+    pub fraction: f64,
+    pub started_unix: u64,
+    pub ends_unix: u64,
+    pub definition: PolicyDecl,
+    pub guardrails: Guardrails,
+    pub status: ExperimentStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ended_unix: Option<u64>,
+    /// Why it ended.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+    pub control: ArmStats,
+    pub experimental: ArmStats,
+}
+
+/// One start or end of an experiment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalEntry {
+    pub at_unix: u64,
+    pub experiment: String,
+    pub policy: String,
+    /// `started`, `expired` or `rolled_back`.
+    pub change: String,
+    pub detail: String,
+}
+
+struct Active {
+    view: ExperimentView,
+    config: PolicyConfig,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// By policy name.
+    active: BTreeMap<String, Active>,
+    finished: VecDeque<ExperimentView>,
+    journal: VecDeque<JournalEntry>,
+    next_id: u64,
+}
+
+pub struct ExperimentRegistry {
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+impl Default for ExperimentRegistry {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+/// Whether the run keyed `key` falls in the first `fraction` of experiment `id`'s buckets.
+pub fn in_experimental_arm(id: &str, key: &str, fraction: f64) -> bool {
+    let digest = Sha256::digest(format!("{id}\n{key}"));
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    let bucket = u64::from_be_bytes(head) % BUCKETS;
+    (bucket as f64) < fraction * BUCKETS as f64
+}
+
+impl ExperimentRegistry {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    /// Start the experiment `req` on a policy of `policies`.
+    pub fn start(
+        &self,
+        req: ExperimentRequest,
+        policies: &PolicyStore,
+        started_by: &str,
+    ) -> Result<ExperimentView, ExperimentError> {
+        let base = policies
+            .get(&req.policy)
+            .ok_or_else(|| ExperimentError::UnknownPolicy(req.policy.clone()))?;
+        if !(req.fraction > 0.0 && req.fraction <= 1.0) {
+            return Err(ExperimentError::Invalid(
+                "fraction must be in (0, 1]".to_string(),
+            ));
+        }
+        if req.max_duration_secs == 0 || req.max_duration_secs > MAX_DURATION_SECS {
+            return Err(ExperimentError::Invalid(format!(
+                "max_duration_secs must be in 1..={MAX_DURATION_SECS}"
+            )));
+        }
+        let g = &req.guardrails;
+        if g.max_block_rate_delta_pct.is_none() && g.max_error_rate_pct.is_none() {
+            return Err(ExperimentError::Invalid(
+                "at least one guardrail is required".to_string(),
+            ));
+        }
+        let valid_pct = |v: Option<f64>| v.is_none_or(|v| (0.0..=100.0).contains(&v));
+        if !valid_pct(g.max_block_rate_delta_pct) || !valid_pct(g.max_error_rate_pct) {
+            return Err(ExperimentError::Invalid(
+                "guardrail percentages must be in 0..=100".to_string(),
+            ));
+        }
+        if req.definition.extends.is_some() {
+            return Err(ExperimentError::Invalid(
+                "definition is applied over the policy; `extends` is not allowed".to_string(),
+            ));
+        }
+        let config = resolve(&req.policy, base, &req.definition)?;
+        if serde_json::to_value(&config).ok() == serde_json::to_value(base).ok() {
+            return Err(ExperimentError::Invalid(
+                "definition does not change the policy".to_string(),
+            ));
+        }
+
+        let now = self.clock.now_unix();
+        let mut inner = self.inner.lock().unwrap();
+        self.expire_due(&mut inner, now);
+        if let Some(a) = inner.active.get(&req.policy) {
+            return Err(ExperimentError::AlreadyActive {
+                policy: req.policy,
+                id: a.view.id.clone(),
+            });
+        }
+        inner.next_id += 1;
+        let view = ExperimentView {
+            id: format!("exp-{now}-{}", inner.next_id),
+            policy: req.policy.clone(),
+            started_by: started_by.to_string(),
+            fraction: req.fraction,
+            started_unix: now,
+            ends_unix: now + req.max_duration_secs,
+            definition: req.definition,
+            guardrails: req.guardrails,
+            status: ExperimentStatus::Active,
+            ended_unix: None,
+            outcome: None,
+            control: ArmStats::default(),
+            experimental: ArmStats::default(),
+        };
+        let detail = format!(
+            "{:.2}% of traffic for {}s, started by {started_by}",
+            view.fraction * 100.0,
+            req.max_duration_secs
+        );
+        tracing::warn!(experiment = %view.id, policy = %view.policy, "experiment started: {detail}");
+        journal(&mut inner, now, &view, "started", detail);
+        inner.active.insert(
+            req.policy,
+            Active {
+                view: view.clone(),
+                config,
+            },
+        );
+        Ok(view)
+    }
+
+    /// The definition a run of `policy` keyed `key` uses: `base`, or the experimental one.
+    /// `paused` (draining, read-only) keeps every run on control.
+    pub fn assign(
+        &self,
+        policy: &str,
+        base: PolicyConfig,
+        key: &str,
+        paused: bool,
+    ) -> (PolicyConfig, Option<Assignment>) {
+        let mut inner = self.inner.lock().unwrap();
+        self.expire_due(&mut inner, self.clock.now_unix());
+        let Some(active) = inner.active.get(policy) else {
+            return (base, None);
+        };
+        let experimental =
+            !paused && in_experimental_arm(&active.view.id, key, active.view.fraction);
+        let assignment = Assignment {
+            experiment: active.view.id.clone(),
+            policy: policy.to_string(),
+            arm: if experimental {
+                Arm::Experimental
+            } else {
+                Arm::Control
+            },
+        };
+        let config = if experimental {
+            active.config.clone()
+        } else {
+            base
+        };
+        (config, Some(assignment))
+    }
+
+    /// Count a finished run, then check the guardrails.
+    pub fn observe(&self, a: &Assignment, decided: Option<&Action>, ok: bool) {
+        let now = self.clock.now_unix();
+        let mut inner = self.inner.lock().unwrap();
+        let Some(active) = inner.active.get_mut(&a.policy) else {
+            return;
+        };
+        if active.view.id != a.experiment {
+            return;
+        }
+        let arm = match a.arm {
+            Arm::Control => &mut active.view.control,
+            Arm::Experimental => &mut active.view.experimental,
+        };
+        arm.runs += 1;
+        if !ok {
+            arm.errors += 1;
+        }
+        if let Some(action) = decided {
+            arm.decisions += 1;
+            if *action == Action::Block {
+                arm.blocked += 1;
+            }
+        }
+        if let Some(reason) = tripped(&active.view) {
+            self.end(
+                &mut inner,
+                &a.policy,
+                now,
+                ExperimentStatus::RolledBack,
+                reason,
+            );
+        }
+    }
+
+    /// Active experiments, then finished ones (newest last).
+    pub fn list(&self) -> Vec<ExperimentView> {
+        let mut inner = self.inner.lock().unwrap();
+        self.expire_due(&mut inner, self.clock.now_unix());
+        let mut out: Vec<ExperimentView> = inner.active.values().map(|a| a.view.clone()).collect();
+        out.extend(inner.finished.iter().cloned());
+        out
+    }
+
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.inner.lock().unwrap().journal.iter().cloned().collect()
+    }
+
+    /// `/status` view: the active experiment id per policy.
+    pub fn snapshot(&self) -> BTreeMap<String, String> {
+        let mut inner = self.inner.lock().unwrap();
+        self.expire_due(&mut inner, self.clock.now_unix());
+        inner
+            .active
+            .iter()
+            .map(|(policy, a)| (policy.clone(), a.view.id.clone()))
+            .collect()
+    }
+
+    fn expire_due(&self, inner: &mut Inner, now: u64) {
+        let due: Vec<String> = inner
+            .active
+            .iter()
+            .filter(|(_, a)| now >= a.view.ends_unix)
+            .map(|(policy, _)| policy.clone())
+            .collect();
+        for policy in due {
+            self.end(
+                inner,
+                &policy,
+                now,
+                ExperimentStatus::Expired,
+                "max_duration_secs elapsed".to_string(),
+            );
+        }
+    }
+
+    fn end(
+        &self,
+        inner: &mut Inner,
+        policy: &str,
+        now: u64,
+        status: ExperimentStatus,
+        outcome: String,
+    ) {
+        let Some(Active { mut view, .. }) = inner.active.remove(policy) else {
+            return;
+        };
+        view.status = status;
+        view.ended_unix = Some(now);
+        view.outcome = Some(outcome.clone());
+        let change = match status {
+            ExperimentStatus::RolledBack => "rolled_back",
+            _ => "expired",
+        };
+        tracing::warn!(
+            experiment = %view.id,
+            policy = %view.policy,
+            control = ?view.control,
+            experimental = ?view.experimental,
+            "experiment {change}: {outcome}; all traffic back on the current policy"
+        );
+        journal(inner, now, &view, change, outcome);
+        if inner.finished.len() == JOURNAL_CAPACITY {
+            inner.finished.pop_front();
+        }
+        inner.finished.push_back(view);
+    }
+}
+
+/// The experimental definition: `decl` over `base`, as a child of it.
+fn resolve(
+    policy: &str,
+    base: &PolicyConfig,
+    decl: &PolicyDecl,
+) -> Result<PolicyConfig, ExperimentError> {
+    let name = format!("{policy}@experiment");
+    let declared = DeclaredPolicies {
+        policies: BTreeMap::from([
+            (policy.to_string(), PolicyDecl::from_resolved(base)),
+            (
+                name.clone(),
+                PolicyDecl {
+                    extends: Some(policy.to_string()),
+                    ..decl.clone()
+                },
+            ),
+        ]),
+    };
+    declared
+        .resolve(&name)
+        .map_err(|e| ExperimentError::Invalid(format!("{e:#}")))
+}
+
+/// Why the guardrails stop `v`, if they do.
+fn tripped(v: &ExperimentView) -> Option<String> {
+    let g = &v.guardrails;
+    let min = g.min_decisions.unwrap_or(DEFAULT_MIN_DECISIONS);
+    let (c, e) = (&v.control, &v.experimental);
+    if c.decisions < min || e.decisions < min {
+        return None;
+    }
+    if let Some(max) = g.max_block_rate_delta_pct {
+        let delta = e.block_rate_pct() - c.block_rate_pct();
+        if delta > max {
+            return Some(format!(
+                "block rate {:.1}% vs control {:.1}% (+{delta:.1} points > {max})",
+                e.block_rate_pct(),
+                c.block_rate_pct()
+            ));
+        }
+    }
+    if let Some(max) = g.max_error_rate_pct {
+        if e.error_rate_pct() > max {
+            return Some(format!("error rate {:.1}% > {max}%", e.error_rate_pct()));
+        }
+    }
+    None
+}
+
+fn journal(inner: &mut Inner, now: u64, v: &ExperimentView, change: &str, detail: String) {
+    if inner.journal.len() == JOURNAL_CAPACITY {
+        inner.journal.pop_front();
+    }
+    inner.journal.push_back(JournalEntry {
+        at_unix: now,
+        experiment: v.id.clone(),
+        policy: v.policy.clone(),
+        change: change.to_string(),
+        detail,
+    });
+}
+
+/// `POST /v1/acip/experiments`
+pub async fn post_experiment(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+    Json(req): Json<ExperimentRequest>,
+) -> Response {
+    if state.read_only {
+        return introspection::json_error(
+            StatusCode::FORBIDDEN,
+            crate::read_only::ERROR_CODE,
+            json!({"method": "POST", "path": "/v1/acip/experiments"}),
+        )
+        .into_response();
+    }
+    let started_by = token_auth::actor_name(actor);
+    match state.experiments.start(req, &state.policies, &started_by) {
+        Ok(view) => (StatusCode::CREATED, Json(view)).into_response(),
+        Err(e) => {
+            let (status, code, extra) = match &e {
+                ExperimentError::UnknownPolicy(p) => (
+                    StatusCode::BAD_REQUEST,
+                    "unknown policy",
+                    json!({"requested": p}),
+                ),
+                ExperimentError::AlreadyActive { policy, id } => (
+                    StatusCode::CONFLICT,
+                    "experiment_active",
+                    json!({"policy": policy, "experiment": id}),
+                ),
+                ExperimentError::Invalid(msg) => (
+                    StatusCode::BAD_REQUEST,
+                    "invalid_experiment",
+                    json!({"detail": msg}),
+                ),
+            };
+            introspection::json_error(status, code, extra).into_response()
+        }
+    }
+}
+
+/// `GET /v1/acip/experiments`
+pub async fn get_experiments(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(json!({
+        "experiments": state.experiments.list(),
+        "journal": state.experiments.journal(),
+    }))
+}
@@ -1,17 +1,23 @@
+use crate::disconnect::CancelToken;
+use crate::secrets::Secret;
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::{
+    collections::{BTreeMap, VecDeque},
     fs::{self, File, OpenOptions},
-    io::{Read, Write},
+    io::{Read, Seek, SeekFrom, Write},
     path::Path,
-    process::{Command, Stdio},
-    time::Duration,
+    process::{ChildStderr, Command, Stdio},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
 };
-use tempfile::{tempdir, Builder};
+use tempfile::Builder;
 use wait_timeout::ChildExt;
+use zeroize::Zeroizing;
 
 #[cfg(unix)]
-use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::fs::OpenOptionsExt;
 
 #[cfg(target_os = "linux")]
 mod seccomp {
@@ -104,6 +110,40 @@ pub struct ExtractRequest {
     pub dpi: Option<u32>,
     #[serde(default)]
     pub max_output_chars: Option<usize>,
+    /// PDFs: OCR the pages even when the text layer looks big enough (used to retry a
+    /// garbled text layer).
+    #[serde(default)]
+    pub force_ocr: bool,
+    /// PDFs: the caller's `document_password`, tried on an encrypted document. The request
+    /// line is the only place it is serialized.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "crate::secrets::serialize_exposed"
+    )]
+    pub password: Option<Secret>,
+}
+
+/// Whether the helper could read the document at all.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractOutcome {
+    #[default]
+    Extracted,
+    /// Encrypted, and no password was given.
+    EncryptedUnreadable,
+    /// Encrypted, and the password given does not open it.
+    DecryptionFailed,
+}
+
+impl ExtractOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Extracted => "extracted",
+            Self::EncryptedUnreadable => "encrypted_unreadable",
+            Self::DecryptionFailed => "decryption_failed",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -114,6 +154,8 @@ pub struct ExtractResponse {
     #[serde(default)]
     pub warnings: Vec<String>,
     pub stats: ExtractStats,
+    #[serde(default)]
+    pub outcome: ExtractOutcome,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -140,10 +182,30 @@ pub fn extract_pdf_hybrid(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractR
     let dpi = req.dpi.unwrap_or(250);
     let max_output_chars = req.max_output_chars.unwrap_or(2_000_000);
 
-    let dir = tempdir().context("create tempdir")?;
-    let pdf_path = dir.path().join("input.pdf");
+    // Runs inside the helper, whose TMPDIR is the tracked output dir.
+    let dir = Builder::new()
+        .prefix(&format!("{}pdf-", crate::tmpdir::TMP_PREFIX))
+        .tempdir()
+        .context("create tempdir")?;
+    let mut pdf_path = dir.path().join("input.pdf");
     std::fs::write(&pdf_path, bytes).context("write pdf")?;
 
+    let mut warnings: Vec<String> = vec![];
+
+    // 0) Encrypted documents are only read with the caller's password.
+    if crate::content_types::is_encrypted_pdf(bytes) {
+        warnings.push("pdf_encrypted".to_string());
+        let Some(password) = &req.password else {
+            return Ok(unreadable(ExtractOutcome::EncryptedUnreadable, warnings));
+        };
+        let decrypted = dir.path().join("decrypted.pdf");
+        if !decrypt_pdf(&pdf_path, &decrypted, password)? {
+            return Ok(unreadable(ExtractOutcome::DecryptionFailed, warnings));
+        }
+        warnings.push("pdf_decrypted".to_string());
+        pdf_path = decrypted;
+    }
+
     // 1) Text-layer extraction via poppler pdftotext.
     let pdftotext = Command::new("pdftotext")
         .arg("-layout")
@@ -154,8 +216,6 @@ pub fn extract_pdf_hybrid(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractR
         .output()
         .context("run pdftotext")?;
 
-    let mut warnings: Vec<String> = vec![];
-
     if !pdftotext.status.success() {
         warnings.push("pdftotext_failed".to_string());
     }
@@ -166,11 +226,15 @@ pub fn extract_pdf_hybrid(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractR
 
     // Heuristic: if text is too small, do OCR.
     let primary_chars = text_primary.chars().count();
-    let needs_ocr = primary_chars < 500;
+    let needs_ocr = req.force_ocr || primary_chars < 500;
 
     let mut ocr_text = String::new();
     if needs_ocr {
-        warnings.push("pdf_text_layer_missing_or_small".to_string());
+        warnings.push(if req.force_ocr {
+            "pdf_ocr_forced".to_string()
+        } else {
+            "pdf_text_layer_missing_or_small".to_string()
+        });
 
         // 2) Render pages to PNG via pdftoppm.
         let prefix = dir.path().join("page");
@@ -268,9 +332,56 @@ pub fn extract_pdf_hybrid(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractR
             ocr_used: needs_ocr,
             ocr_chars: ocr_text.chars().count(),
         },
+        outcome: ExtractOutcome::Extracted,
     })
 }
 
+/// An encrypted PDF the helper could not open.
+fn unreadable(outcome: ExtractOutcome, warnings: Vec<String>) -> ExtractResponse {
+    ExtractResponse {
+        ok: false,
+        kind: ExtractKind::Pdf,
+        text: String::new(),
+        warnings,
+        stats: ExtractStats::default(),
+        outcome,
+    }
+}
+
+/// Decrypt `input` into `output` with qpdf; `false` when the password is wrong. The password
+/// goes to qpdf on stdin, never on its command line.
+fn decrypt_pdf(input: &Path, output: &Path, password: &Secret) -> Result<bool> {
+    let mut child = Command::new("qpdf")
+        .arg("--password-file=-")
+        .arg("--decrypt")
+        .arg(input.as_os_str())
+        .arg(output.as_os_str())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("qpdf_not_installed: cannot decrypt PDF"),
+            _ => anyhow!("run qpdf: {e}"),
+        })?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(password.expose().as_bytes())
+            .and_then(|_| stdin.write_all(b"\n"))
+            .context("write qpdf password")?;
+    }
+    let out = child.wait_with_output().context("run qpdf")?;
+    // 3: decrypted, with warnings.
+    if matches!(out.status.code(), Some(0 | 3)) {
+        return Ok(true);
+    }
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    if stderr.contains("invalid password") {
+        return Ok(false);
+    }
+    anyhow::bail!("qpdf_failed: {}", stderr.trim())
+}
+
 pub fn extract_svg_text(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractResponse> {
     let max_output_chars = req.max_output_chars.unwrap_or(500_000);
     let raw0 = String::from_utf8(bytes.to_vec()).map_err(|_| anyhow!("svg must be utf-8"))?;
@@ -320,6 +431,7 @@ pub fn extract_svg_text(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractRes
                 ocr_used: false,
                 ocr_chars: 0,
             },
+            outcome: ExtractOutcome::Extracted,
         });
     };
 
@@ -361,6 +473,7 @@ pub fn extract_svg_text(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractRes
             ocr_used: false,
             ocr_chars: 0,
         },
+        outcome: ExtractOutcome::Extracted,
     })
 }
 
@@ -371,11 +484,19 @@ pub fn extract(req: &ExtractRequest, bytes: &[u8]) -> Result<ExtractResponse> {
     }
 }
 
+/// Why [`run_helper`] failed. `stderr` fields hold the tail of the helper's diagnostics and
+/// stderr, at most [`STDERR_TAIL_BYTES`].
 #[derive(thiserror::Error, Debug)]
 pub enum ExtractorError {
+    /// Killed by us when the timeout ran out.
     #[error("extractor timeout")]
     Timeout,
 
+    /// Killed by us because the run was cancelled (its client disconnected). Not a helper
+    /// failure: not counted in [`failure_counts`].
+    #[error("extractor cancelled")]
+    Cancelled,
+
     #[error("spawn extractor failed: {0}")]
     Spawn(String),
 
@@ -388,15 +509,337 @@ pub enum ExtractorError {
         stderr: String,
     },
 
+    /// Killed by a signal we did not send (OOM killer, `RLIMIT_CPU`, a crash).
+    #[error("extractor killed by signal {signal}: {stderr}")]
+    Signaled { signal: i32, stderr: String },
+
     #[error("extractor output exceeded limit ({bytes} bytes > {max_bytes} bytes)")]
     OutputTooLarge { bytes: u64, max_bytes: u64 },
 
-    #[error("extractor output invalid: {0}")]
-    OutputParse(String),
+    /// The output file was missing or could not be read after a clean exit.
+    #[error("extractor output file unreadable: {0}")]
+    OutputMissing(String),
+
+    #[error("extractor exited cleanly but wrote no output")]
+    EmptyOutput,
+
+    /// The JSON stopped early, as when the helper dies mid-write.
+    #[error("extractor output truncated mid-JSON after {bytes} bytes")]
+    TruncatedOutput { bytes: usize },
+
+    /// Not JSON at all, or JSON followed by something else (a stray debug print).
+    #[error("extractor output is not JSON ({bytes} bytes): {detail}")]
+    MalformedOutput { bytes: usize, detail: String },
+
+    /// Valid JSON that does not match [`ExtractResponse`].
+    #[error("extractor output field `{field}` invalid: {detail}")]
+    SchemaViolation { field: String, detail: String },
+}
+
+/// Every [`ExtractorError::kind`], in counter order.
+pub const FAILURE_KINDS: &[&str] = &[
+    "timeout",
+    "spawn_failed",
+    "io_failed",
+    "nonzero_exit",
+    "signaled",
+    "output_too_large",
+    "output_missing",
+    "empty_output",
+    "truncated_output",
+    "malformed_output",
+    "schema_violation",
+];
+
+impl ExtractorError {
+    /// Stable failure kind, used in `extract_failed` responses, logs and counters.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExtractorError::Timeout => "timeout",
+            ExtractorError::Cancelled => "cancelled",
+            ExtractorError::Spawn(_) => "spawn_failed",
+            ExtractorError::Io(_) => "io_failed",
+            ExtractorError::NonZeroExit { .. } => "nonzero_exit",
+            ExtractorError::Signaled { .. } => "signaled",
+            ExtractorError::OutputTooLarge { .. } => "output_too_large",
+            ExtractorError::OutputMissing(_) => "output_missing",
+            ExtractorError::EmptyOutput => "empty_output",
+            ExtractorError::TruncatedOutput { .. } => "truncated_output",
+            ExtractorError::MalformedOutput { .. } => "malformed_output",
+            ExtractorError::SchemaViolation { .. } => "schema_violation",
+        }
+    }
+}
+
+impl ExtractorError {
+    /// Replace `secret` wherever it appears in the error's text.
+    pub fn redact(self, secret: &str) -> Self {
+        if secret.is_empty() {
+            return self;
+        }
+        let r = |s: String| s.replace(secret, "[redacted]");
+        match self {
+            ExtractorError::Spawn(s) => ExtractorError::Spawn(r(s)),
+            ExtractorError::Io(s) => ExtractorError::Io(r(s)),
+            ExtractorError::NonZeroExit { exit_code, stderr } => ExtractorError::NonZeroExit {
+                exit_code,
+                stderr: r(stderr),
+            },
+            ExtractorError::Signaled { signal, stderr } => ExtractorError::Signaled {
+                signal,
+                stderr: r(stderr),
+            },
+            ExtractorError::OutputMissing(s) => ExtractorError::OutputMissing(r(s)),
+            ExtractorError::MalformedOutput { bytes, detail } => ExtractorError::MalformedOutput {
+                bytes,
+                detail: r(detail),
+            },
+            ExtractorError::SchemaViolation { field, detail } => ExtractorError::SchemaViolation {
+                field: r(field),
+                detail: r(detail),
+            },
+            other => other,
+        }
+    }
+}
+
+static FAILURES: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+
+/// Helper failures by kind since startup (every kind listed, zeros included).
+pub fn failure_counts() -> BTreeMap<&'static str, u64> {
+    let counts = FAILURES.lock().unwrap();
+    FAILURE_KINDS
+        .iter()
+        .map(|k| (*k, counts.get(k).copied().unwrap_or(0)))
+        .collect()
+}
+
+fn record_failure(e: &ExtractorError) {
+    if matches!(e, ExtractorError::Cancelled) {
+        return;
+    }
+    *FAILURES.lock().unwrap().entry(e.kind()).or_default() += 1;
+    // Log lines go through the redacting writer, stderr tails included.
+    tracing::warn!(kind = e.kind(), "extractor helper failed: {e}");
+}
+
+/// Bytes of helper stderr (and of its diagnostics file) kept for error reports.
+pub const STDERR_TAIL_BYTES: usize = 4096;
+
+/// How long to wait for the stderr reader after the helper exits; a grandchild still holding
+/// the pipe must not hold up the request.
+const STDERR_DRAIN_WAIT: Duration = Duration::from_millis(500);
+
+/// The last `cap` bytes written to a stream; older bytes are dropped as new ones arrive.
+#[derive(Debug)]
+pub struct TailBuffer {
+    buf: VecDeque<u8>,
+    cap: usize,
+    dropped: u64,
+}
+
+impl TailBuffer {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(cap),
+            cap,
+            dropped: 0,
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        let bytes = if bytes.len() > self.cap {
+            self.dropped += (bytes.len() - self.cap) as u64;
+            &bytes[bytes.len() - self.cap..]
+        } else {
+            bytes
+        };
+        let excess = (self.buf.len() + bytes.len()).saturating_sub(self.cap);
+        self.buf.drain(..excess);
+        self.dropped += excess as u64;
+        self.buf.extend(bytes);
+    }
+
+    /// Bytes dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// The kept bytes as text, prefixed with `...` when something was dropped.
+    pub fn text(&self) -> String {
+        let (a, b) = self.buf.as_slices();
+        let mut bytes = a.to_vec();
+        bytes.extend_from_slice(b);
+        let text = String::from_utf8_lossy(&bytes);
+        if self.dropped > 0 {
+            format!("...{}", text.trim())
+        } else {
+            text.trim().to_string()
+        }
+    }
+}
+
+/// Read `stream` to its end on a thread, keeping the last [`STDERR_TAIL_BYTES`]. The receiver
+/// fires when the stream closes.
+fn capture_stderr(mut stream: ChildStderr) -> (Arc<Mutex<TailBuffer>>, mpsc::Receiver<()>) {
+    let tail = Arc::new(Mutex::new(TailBuffer::new(STDERR_TAIL_BYTES)));
+    let (done, closed) = mpsc::channel();
+    let writer = tail.clone();
+    std::thread::spawn(move || {
+        let mut chunk = [0u8; 4096];
+        while let Ok(n) = stream.read(&mut chunk) {
+            if n == 0 {
+                break;
+            }
+            writer.lock().unwrap().push(&chunk[..n]);
+        }
+        let _ = done.send(());
+    });
+    (tail, closed)
+}
+
+/// The helper's diagnostics file tail followed by its stderr tail.
+fn stderr_tail(err_path: &Path, tail: &Mutex<TailBuffer>, closed: &mpsc::Receiver<()>) -> String {
+    let _ = closed.recv_timeout(STDERR_DRAIN_WAIT);
+    let mut diag = TailBuffer::new(STDERR_TAIL_BYTES);
+    if let Ok(mut file) = fs::File::open(err_path) {
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let skip = len.saturating_sub(STDERR_TAIL_BYTES as u64);
+        if file.seek(SeekFrom::Start(skip)).is_ok() {
+            let mut bytes = Vec::with_capacity(STDERR_TAIL_BYTES);
+            let _ = file.take(STDERR_TAIL_BYTES as u64).read_to_end(&mut bytes);
+            diag.dropped = skip;
+            diag.push(&bytes);
+        }
+    }
+    let stderr = tail.lock().unwrap().text();
+    [diag.text(), stderr]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Expected JSON type of an [`ExtractResponse`] field.
+#[derive(Clone, Copy)]
+enum Expect {
+    Bool,
+    Text,
+    Count,
+    OptionalCount,
+    Kind,
+    Outcome,
+    Texts,
+    Object,
+}
+
+impl Expect {
+    fn matches(self, v: &Value) -> bool {
+        match self {
+            Expect::Bool => v.is_boolean(),
+            Expect::Text => v.is_string(),
+            Expect::Count => v.is_u64(),
+            Expect::OptionalCount => v.is_u64() || v.is_null(),
+            Expect::Kind => matches!(v.as_str(), Some("pdf" | "svg")),
+            Expect::Outcome => matches!(
+                v.as_str(),
+                Some("extracted" | "encrypted_unreadable" | "decryption_failed")
+            ),
+            Expect::Texts => v.as_array().is_some_and(|a| a.iter().all(Value::is_string)),
+            Expect::Object => v.is_object(),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Expect::Bool => "a boolean",
+            Expect::Text => "a string",
+            Expect::Count | Expect::OptionalCount => "a non-negative integer",
+            Expect::Kind => "\"pdf\" or \"svg\"",
+            Expect::Outcome => "an extraction outcome",
+            Expect::Texts => "an array of strings",
+            Expect::Object => "an object",
+        }
+    }
+}
+
+/// `(path, type, required)` for every [`ExtractResponse`] field, parents before children.
+const RESPONSE_FIELDS: &[(&str, Expect, bool)] = &[
+    ("ok", Expect::Bool, true),
+    ("kind", Expect::Kind, true),
+    ("text", Expect::Text, true),
+    ("warnings", Expect::Texts, false),
+    ("stats", Expect::Object, true),
+    ("stats.pages", Expect::OptionalCount, false),
+    ("stats.text_chars", Expect::Count, true),
+    ("stats.ocr_used", Expect::Bool, true),
+    ("stats.ocr_chars", Expect::Count, true),
+    ("outcome", Expect::Outcome, false),
+];
+
+fn json_type(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// The first field of `v` that does not fit [`ExtractResponse`], with what is wrong with it.
+fn schema_violation(v: &Value) -> Option<(String, String)> {
+    if !v.is_object() {
+        return Some((
+            "$".to_string(),
+            format!("expected an object, got {}", json_type(v)),
+        ));
+    }
+    for &(path, expect, required) in RESPONSE_FIELDS {
+        match v.pointer(&format!("/{}", path.replace('.', "/"))) {
+            None if !required => {}
+            None => return Some((path.to_string(), "missing".to_string())),
+            Some(field) if !expect.matches(field) => {
+                return Some((
+                    path.to_string(),
+                    format!("expected {}, got {}", expect.describe(), json_type(field)),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    None
+}
+
+/// Classify and decode the helper's output file contents.
+pub fn parse_output(output: &[u8]) -> std::result::Result<ExtractResponse, ExtractorError> {
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Err(ExtractorError::EmptyOutput);
+    }
+    let value: Value = serde_json::from_slice(output).map_err(|e| {
+        if e.is_eof() {
+            ExtractorError::TruncatedOutput {
+                bytes: output.len(),
+            }
+        } else {
+            ExtractorError::MalformedOutput {
+                bytes: output.len(),
+                detail: e.to_string(),
+            }
+        }
+    })?;
+    if let Some((field, detail)) = schema_violation(&value) {
+        return Err(ExtractorError::SchemaViolation { field, detail });
+    }
+    serde_json::from_value(value).map_err(|e| ExtractorError::SchemaViolation {
+        field: "$".to_string(),
+        detail: e.to_string(),
+    })
 }
 
 fn default_max_output_chars(req: &ExtractRequest) -> usize {
-    req.max_output_chars.unwrap_or_else(|| match req.kind {
+    req.max_output_chars.unwrap_or(match req.kind {
         ExtractKind::Pdf => 2_000_000,
         ExtractKind::Svg => 500_000,
     })
@@ -430,12 +873,12 @@ fn create_secure_file(path: &Path) -> std::result::Result<(), ExtractorError> {
 }
 
 fn read_limited_file(path: &Path, max_bytes: u64) -> std::result::Result<Vec<u8>, ExtractorError> {
-    let file = File::open(path).map_err(|e| ExtractorError::OutputParse(e.to_string()))?;
+    let file = File::open(path).map_err(|e| ExtractorError::OutputMissing(e.to_string()))?;
     let mut limited = file.take(max_bytes.saturating_add(1));
     let mut buf = Vec::new();
     limited
         .read_to_end(&mut buf)
-        .map_err(|e| ExtractorError::OutputParse(e.to_string()))?;
+        .map_err(|e| ExtractorError::OutputMissing(e.to_string()))?;
     if (buf.len() as u64) > max_bytes {
         return Err(ExtractorError::OutputTooLarge {
             bytes: buf.len() as u64,
@@ -445,6 +888,12 @@ fn read_limited_file(path: &Path, max_bytes: u64) -> std::result::Result<Vec<u8>
     Ok(buf)
 }
 
+/// Helper limits when the `ACIP_EXTRACTOR_*` variable is unset.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 180;
+pub const DEFAULT_RLIMIT_AS_MB: u64 = 2048;
+pub const DEFAULT_RLIMIT_NOFILE: u64 = 64;
+pub const DEFAULT_RLIMIT_FSIZE_MB: u64 = 512;
+
 /// Spawn the external extractor helper (`acip-extract`) and return its JSON response.
 ///
 /// Linux-only v1 sandboxing (pure Rust):
@@ -452,28 +901,55 @@ fn read_limited_file(path: &Path, max_bytes: u64) -> std::result::Result<Vec<u8>
 /// - set PR_SET_NO_NEW_PRIVS
 /// - set PR_SET_PDEATHSIG=SIGKILL
 /// - nice/ionice/umask
-/// - kill helper on timeout
+/// - kill helper on timeout (or, with [`run_helper_cancellable`], on cancellation)
+///
+/// All scratch space (output files and the helper's own `TMPDIR`) lives in one directory
+/// tracked by `tmp`, removed when the call returns.
+///
+/// Failures are classified ([`ExtractorError::kind`]), counted ([`failure_counts`]) and logged.
 pub fn run_helper(
+    tmp: &crate::tmpdir::TmpDirManager,
     req: &ExtractRequest,
     bytes: &[u8],
     timeout: Duration,
 ) -> std::result::Result<ExtractResponse, ExtractorError> {
-    let bin = std::env::var("ACIP_EXTRACTOR_BIN").unwrap_or_else(|_| "acip-extract".to_string());
+    run_helper_cancellable(tmp, req, bytes, timeout, &CancelToken::default())
+}
 
-    let tmpdir_env = std::env::var("ACIP_EXTRACTOR_TMPDIR")
-        .ok()
-        .filter(|v| !v.trim().is_empty());
+/// [`run_helper`], killing the helper with [`ExtractorError::Cancelled`] within
+/// [`CANCEL_POLL`] of `cancel` being cancelled.
+pub fn run_helper_cancellable(
+    tmp: &crate::tmpdir::TmpDirManager,
+    req: &ExtractRequest,
+    bytes: &[u8],
+    timeout: Duration,
+    cancel: &CancelToken,
+) -> std::result::Result<ExtractResponse, ExtractorError> {
+    run_helper_unrecorded(tmp, req, bytes, timeout, cancel)
+        .map_err(|e| match &req.password {
+            Some(password) => e.redact(password.expose()),
+            None => e,
+        })
+        .inspect_err(record_failure)
+}
+
+/// How often a running helper checks for cancellation.
+pub const CANCEL_POLL: Duration = Duration::from_millis(50);
 
-    let mut builder = Builder::new();
-    builder.prefix("acip-extractor-");
-    let output_dir = match tmpdir_env.as_deref() {
-        Some(base) => builder.tempdir_in(base),
-        None => builder.tempdir(),
+fn run_helper_unrecorded(
+    tmp: &crate::tmpdir::TmpDirManager,
+    req: &ExtractRequest,
+    bytes: &[u8],
+    timeout: Duration,
+    cancel: &CancelToken,
+) -> std::result::Result<ExtractResponse, ExtractorError> {
+    if cancel.is_cancelled() {
+        return Err(ExtractorError::Cancelled);
     }
-    .map_err(|e| ExtractorError::Io(e.to_string()))?;
+    let bin = std::env::var("ACIP_EXTRACTOR_BIN").unwrap_or_else(|_| "acip-extract".to_string());
 
-    #[cfg(unix)]
-    fs::set_permissions(output_dir.path(), fs::Permissions::from_mode(0o700))
+    let output_dir = tmp
+        .create_dir("extractor")
         .map_err(|e| ExtractorError::Io(e.to_string()))?;
 
     let out_path = output_dir.path().join("out.json");
@@ -495,6 +971,9 @@ pub fn run_helper(
         // Test-only/debug passthrough.
         "ACIP_EXTRACTOR_SELFTEST_NET",
         "ACIP_EXTRACTOR_SELFTEST_LARGE",
+        "ACIP_EXTRACTOR_SELFTEST_FAIL",
+        "ACIP_EXTRACTOR_SELFTEST_PDF",
+        "ACIP_EXTRACTOR_SELFTEST_DELAY_MS",
     ] {
         if let Ok(v) = std::env::var(key) {
             if !v.trim().is_empty() {
@@ -503,14 +982,12 @@ pub fn run_helper(
         }
     }
 
-    if let Some(tmpdir) = tmpdir_env.as_ref() {
-        cmd.env("TMPDIR", tmpdir);
-    }
+    cmd.env("TMPDIR", output_dir.path());
     cmd.env("ACIP_EXTRACTOR_OUT", &out_path)
         .env("ACIP_EXTRACTOR_ERR", &err_path);
     cmd.stdin(Stdio::piped())
         .stdout(Stdio::null())
-        .stderr(Stdio::null());
+        .stderr(Stdio::piped());
 
     #[cfg(unix)]
     unsafe {
@@ -523,14 +1000,14 @@ pub fn run_helper(
             let as_bytes: u64 = std::env::var("ACIP_EXTRACTOR_RLIMIT_AS_MB")
                 .ok()
                 .and_then(|v| v.trim().parse::<u64>().ok())
-                .unwrap_or(2048)
+                .unwrap_or(DEFAULT_RLIMIT_AS_MB)
                 * 1024
                 * 1024;
 
             let nofile: u64 = std::env::var("ACIP_EXTRACTOR_RLIMIT_NOFILE")
                 .ok()
                 .and_then(|v| v.trim().parse::<u64>().ok())
-                .unwrap_or(64);
+                .unwrap_or(DEFAULT_RLIMIT_NOFILE);
 
             fn setrlim(
                 resource: libc::__rlimit_resource_t,
@@ -572,7 +1049,7 @@ pub fn run_helper(
             let fsize_mb: u64 = std::env::var("ACIP_EXTRACTOR_RLIMIT_FSIZE_MB")
                 .ok()
                 .and_then(|v| v.trim().parse::<u64>().ok())
-                .unwrap_or(512);
+                .unwrap_or(DEFAULT_RLIMIT_FSIZE_MB);
             setrlim(
                 libc::RLIMIT_FSIZE,
                 fsize_mb * 1024 * 1024,
@@ -642,50 +1119,75 @@ pub fn run_helper(
     let mut child = cmd
         .spawn()
         .map_err(|e| ExtractorError::Spawn(e.to_string()))?;
+    let (tail, stderr_closed) = match child.stderr.take() {
+        Some(stream) => capture_stderr(stream),
+        None => (
+            Arc::new(Mutex::new(TailBuffer::new(STDERR_TAIL_BYTES))),
+            mpsc::channel().1,
+        ),
+    };
 
     let stdin = child
         .stdin
         .as_mut()
         .ok_or_else(|| ExtractorError::Spawn("missing stdin".to_string()))?;
 
+    // May carry the document password.
     let header =
-        serde_json::to_string(req).map_err(|e| ExtractorError::OutputParse(e.to_string()))?;
-    stdin
+        Zeroizing::new(serde_json::to_string(req).map_err(|e| ExtractorError::Io(e.to_string()))?);
+    let written = stdin
         .write_all(header.as_bytes())
-        .map_err(|e| ExtractorError::Spawn(e.to_string()))?;
-    stdin
-        .write_all(b"\n")
-        .map_err(|e| ExtractorError::Spawn(e.to_string()))?;
-    stdin
-        .write_all(bytes)
-        .map_err(|e| ExtractorError::Spawn(e.to_string()))?;
+        .and_then(|()| stdin.write_all(b"\n"))
+        .and_then(|()| stdin.write_all(bytes));
     drop(child.stdin.take());
+    // A helper that died mid-write (EPIPE) must still be reaped.
+    if let Err(e) = written {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(ExtractorError::Spawn(e.to_string()));
+    }
 
-    let Some(status) = child
-        .wait_timeout(timeout)
-        .map_err(|e| ExtractorError::Spawn(e.to_string()))?
-    else {
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        let slice = deadline
+            .saturating_duration_since(Instant::now())
+            .min(CANCEL_POLL);
+        if let Some(status) = child
+            .wait_timeout(slice)
+            .map_err(|e| ExtractorError::Spawn(e.to_string()))?
+        {
+            break status;
+        }
+        let err = if cancel.is_cancelled() {
+            ExtractorError::Cancelled
+        } else if Instant::now() >= deadline {
+            ExtractorError::Timeout
+        } else {
+            continue;
+        };
         let _ = child.kill();
         let _ = child.wait();
-        return Err(ExtractorError::Timeout);
+        return Err(err);
     };
 
     if !status.success() {
-        let err = fs::read_to_string(&err_path)
-            .unwrap_or_default()
-            .trim()
-            .to_string();
+        let stderr = stderr_tail(&err_path, &tail, &stderr_closed);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            if let Some(signal) = status.signal() {
+                return Err(ExtractorError::Signaled { signal, stderr });
+            }
+        }
         return Err(ExtractorError::NonZeroExit {
             exit_code: status.code(),
-            stderr: err,
+            stderr,
         });
     }
 
     let max_bytes = output_cap_bytes(req);
     let output = read_limited_file(&out_path, max_bytes)?;
-    let resp: ExtractResponse = serde_json::from_slice(&output)
-        .map_err(|e| ExtractorError::OutputParse(e.to_string()))?;
-    Ok(resp)
+    parse_output(&output)
 }
 
 #[cfg(test)]
@@ -0,0 +1,275 @@
+//! Circuit breaker around the extractor helper.
+//!
+//! When the helper itself is broken (a bad deploy, a missing shared library) every PDF/SVG
+//! ingest would spawn it and fail. After `threshold` consecutive helper failures
+//! (`ACIP_EXTRACTOR_BREAKER_THRESHOLD`) the breaker opens: extraction is no longer attempted
+//! and those ingests get the policy's `on_extractor_unavailable` decision at once, while
+//! text, HTML and other formats that need no helper are served as usual. A single failure
+//! never opens it, and any success resets the count.
+//!
+//! A background probe runs the helper on a tiny SVG every `probe_interval`
+//! (`ACIP_EXTRACTOR_PROBE_SECS`), open or not, so a helper that is broken at startup is noticed
+//! before the first document and a repaired one is put back in service without a restart: the
+//! first successful probe closes the breaker. Both transitions are logged at `warn`, kept under
+//! `extractor.breaker` in `/v1/acip/status`, and `/health/ready` reports `degraded` while open.
+//!
+//! Failures that say something about the document rather than the helper (a timeout, output
+//! over the limit, a cancelled run) are not counted.
+
+use crate::extract::{self, ExtractKind, ExtractRequest, ExtractorError};
+use crate::reputation::{Clock, SystemClock};
+use crate::tmpdir::TmpDirManager;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub const DEFAULT_THRESHOLD: u32 = 3;
+pub const DEFAULT_PROBE_INTERVAL_SECS: u64 = 30;
+/// Transitions kept for `/v1/acip/status`.
+pub const TRANSITIONS_KEPT: usize = 16;
+
+/// Input of the probe: extracting it needs a working helper and nothing else.
+const PROBE_SVG: &[u8] =
+    br#"<svg xmlns="http://www.w3.org/2000/svg"><text x="0" y="10">acip probe</text></svg>"#;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakerSettings {
+    /// Consecutive helper failures that open the breaker (at least 1).
+    pub threshold: u32,
+    pub probe_interval: Duration,
+    pub probe_timeout: Duration,
+}
+
+impl Default for BreakerSettings {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            probe_interval: Duration::from_secs(DEFAULT_PROBE_INTERVAL_SECS),
+            probe_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl BreakerSettings {
+    pub fn from_env() -> Self {
+        let env_u64 = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_BREAKER_THRESHOLD") {
+            s.threshold = u32::try_from(v).unwrap_or(u32::MAX).max(1);
+        }
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_PROBE_SECS") {
+            s.probe_interval = Duration::from_secs(v.max(1));
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    /// The helper runs for every document that needs it.
+    Closed,
+    /// The helper is not run; documents that need it get the degraded decision.
+    Open,
+}
+
+/// One change of [`BreakerState`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub state: BreakerState,
+    pub at_unix: u64,
+    /// The failure kind that opened the breaker, or what closed it.
+    pub reason: String,
+}
+
+#[derive(Default)]
+struct Inner {
+    consecutive_failures: u32,
+    open_since_unix: Option<u64>,
+    last_failure: Option<&'static str>,
+    probes: u64,
+    transitions: VecDeque<Transition>,
+}
+
+pub struct ExtractorHealth {
+    settings: BreakerSettings,
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+impl Default for ExtractorHealth {
+    fn default() -> Self {
+        Self::new(BreakerSettings::default(), Arc::new(SystemClock))
+    }
+}
+
+impl ExtractorHealth {
+    pub fn new(settings: BreakerSettings, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn settings(&self) -> &BreakerSettings {
+        &self.settings
+    }
+
+    pub fn state(&self) -> BreakerState {
+        if self.inner.lock().unwrap().open_since_unix.is_some() {
+            BreakerState::Open
+        } else {
+            BreakerState::Closed
+        }
+    }
+
+    /// False while the breaker is open: do not run the helper.
+    pub fn is_available(&self) -> bool {
+        self.state() == BreakerState::Closed
+    }
+
+    /// Note the outcome of a helper run.
+    pub fn record<T>(&self, result: &Result<T, ExtractorError>, via: &str) {
+        match result {
+            Ok(_) => self.record_success(via),
+            Err(e) => self.record_failure(e),
+        }
+    }
+
+    /// A helper run succeeded; closes the breaker if it was open.
+    pub fn record_success(&self, via: &str) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if let Some(since) = inner.open_since_unix.take() {
+            let now = self.clock.now_unix();
+            tracing::warn!(
+                open_secs = now.saturating_sub(since),
+                "extractor helper recovered ({via}); extraction resumed"
+            );
+            push(
+                &mut inner,
+                BreakerState::Closed,
+                now,
+                format!("{via}_succeeded"),
+            );
+        }
+    }
+
+    /// A helper run failed; opens the breaker at the threshold. Failures that do not point at
+    /// the helper are ignored.
+    pub fn record_failure(&self, e: &ExtractorError) {
+        if !counts(e) {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        inner.last_failure = Some(e.kind());
+        if inner.open_since_unix.is_none() && inner.consecutive_failures >= self.settings.threshold
+        {
+            let now = self.clock.now_unix();
+            inner.open_since_unix = Some(now);
+            tracing::warn!(
+                kind = e.kind(),
+                failures = inner.consecutive_failures,
+                "extractor helper failing; documents that need it get a degraded decision until \
+                 it recovers"
+            );
+            push(&mut inner, BreakerState::Open, now, e.kind().to_string());
+        }
+    }
+
+    /// Run the helper on the probe document and record the outcome; true when it worked.
+    pub async fn probe(&self, tmp: Arc<TmpDirManager>) -> bool {
+        let timeout = self.settings.probe_timeout;
+        let run = tokio::task::spawn_blocking(move || {
+            let req = ExtractRequest {
+                kind: ExtractKind::Svg,
+                content_type: Some("image/svg+xml".to_string()),
+                max_pages: None,
+                dpi: None,
+                max_output_chars: Some(1024),
+                force_ocr: false,
+                password: None,
+            };
+            extract::run_helper(&tmp, &req, PROBE_SVG, timeout)
+        })
+        .await;
+        self.inner.lock().unwrap().probes += 1;
+        match run {
+            Ok(result) => {
+                self.record(&result, "probe");
+                result.is_ok()
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// `extractor.breaker` in `/v1/acip/status`.
+    pub fn snapshot(&self) -> BreakerSnapshot {
+        let inner = self.inner.lock().unwrap();
+        BreakerSnapshot {
+            state: if inner.open_since_unix.is_some() {
+                BreakerState::Open
+            } else {
+                BreakerState::Closed
+            },
+            open_since_unix: inner.open_since_unix,
+            consecutive_failures: inner.consecutive_failures,
+            threshold: self.settings.threshold,
+            probe_interval_secs: self.settings.probe_interval.as_secs(),
+            probes: inner.probes,
+            last_failure: inner.last_failure,
+            transitions: inner.transitions.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Helper failures that say the helper, not the document, is at fault.
+fn counts(e: &ExtractorError) -> bool {
+    !matches!(
+        e,
+        ExtractorError::Timeout | ExtractorError::Cancelled | ExtractorError::OutputTooLarge { .. }
+    )
+}
+
+fn push(inner: &mut Inner, state: BreakerState, at_unix: u64, reason: String) {
+    if inner.transitions.len() == TRANSITIONS_KEPT {
+        inner.transitions.pop_front();
+    }
+    inner.transitions.push_back(Transition {
+        state,
+        at_unix,
+        reason,
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BreakerSnapshot {
+    pub state: BreakerState,
+    pub open_since_unix: Option<u64>,
+    pub consecutive_failures: u32,
+    pub threshold: u32,
+    pub probe_interval_secs: u64,
+    pub probes: u64,
+    pub last_failure: Option<&'static str>,
+    /// Oldest first, at most [`TRANSITIONS_KEPT`].
+    pub transitions: Vec<Transition>,
+}
+
+/// Probe the helper every `probe_interval`, starting now.
+pub fn start(health: Arc<ExtractorHealth>, tmp: Arc<TmpDirManager>) {
+    tokio::spawn(async move {
+        loop {
+            health.probe(tmp.clone()).await;
+            tokio::time::sleep(health.settings.probe_interval).await;
+        }
+    });
+}
@@ -0,0 +1,874 @@
+//! External threat feeds: domain blocklists, partner allowlists and reputation seeds.
+//!
+//! Each `[[feeds]]` entry is loaded at startup and refreshed on its own interval from a local
+//! file or an HTTPS URL that passes the [`crate::egress`] checks. HTTP refreshes are conditional
+//! (`If-None-Match` / `If-Modified-Since`), do not follow redirects, and files are re-read only
+//! when their mtime changes. A new version is size-bounded, parsed and validated as a whole
+//! before it replaces the old one, so a failed or malformed refresh keeps serving the last good
+//! data. Feeds are keyed by name; their order in the config file does not matter.
+//!
+//! The URL scanner ([`FeedRegistry::assess_urls`]) flags linked hosts on a blocklist and drops
+//! the generic URL indicators when every linked host is allowlisted, by any allowlist feed or by
+//! the policy's own `url_allowlist` ([`crate::url_allowlist`]), which may name feeds too.
+//! Reputation seeds give a listed host a minimum risk score ([`FeedRegistry::apply_seeds`]).
+
+use crate::config::FeedConfig;
+use crate::egress::{self, EgressPolicy};
+use crate::introspection;
+use crate::policy_store::PolicyStore;
+use crate::reputation::{Clock, ReputationRecord, SystemClock};
+use crate::state::AppState;
+use crate::telemetry::{SpanKind, Telemetry, TraceContext};
+use crate::threat::{AttackType, DetectedPattern, ScanStage, ThreatAssessment};
+use crate::url_allowlist::{self, EffectiveAllowlist, UrlAllowlistConfig, UrlRef};
+use anyhow::{anyhow, bail};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+pub const DEFAULT_REFRESH_SECS: u64 = 3600;
+pub const DEFAULT_MAX_BYTES: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_STALE_AFTER_INTERVALS: u32 = 3;
+pub const DEFAULT_SEED_SCORE: u64 = 50;
+/// Threat score added for each linked host on a blocklist.
+pub const BLOCKLIST_SCORE: u8 = 10;
+
+/// Generic indicators the allowlist can clear when every linked host is a partner.
+const URL_INDICATORS: [&str; 2] = ["mentions_exfil:http://", "mentions_exfil:https://"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedKind {
+    DomainBlocklist,
+    DomainAllowlist,
+    ReputationSeed,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    /// One domain per line; blank lines and `#` comments are skipped.
+    #[default]
+    Plain,
+    /// One column of a CSV file.
+    Csv,
+    /// One JSON object per line; `field` selects the domain.
+    Jsonl,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Source {
+    File(PathBuf),
+    Http(String),
+}
+
+impl Source {
+    /// An [`EgressPolicy`] URL (`http://` allowed to a loopback host), or a local path.
+    fn parse(raw: &str, allowed_hosts: &HashSet<String>) -> anyhow::Result<Self> {
+        if !raw.contains("://") {
+            return Ok(Self::File(PathBuf::from(raw)));
+        }
+        EgressPolicy::new(allowed_hosts.clone())
+            .with_loopback()
+            .check(raw)
+            .map(|_| Self::Http(raw.to_string()))
+            .map_err(|reason| anyhow!("feed source {raw:?}: {reason}"))
+    }
+
+    /// For `/status`: URLs without credentials or query.
+    fn display(&self) -> String {
+        match self {
+            Self::File(p) => p.display().to_string(),
+            Self::Http(u) => url::Url::parse(u)
+                .map(|mut url| {
+                    let _ = url.set_username("");
+                    let _ = url.set_password(None);
+                    url.set_query(None);
+                    url.to_string()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selector {
+    Line,
+    Column(usize),
+    Header(String),
+    Field(Vec<String>),
+}
+
+/// A validated `[[feeds]]` entry.
+#[derive(Debug, Clone)]
+pub struct FeedSpec {
+    pub name: String,
+    pub kind: FeedKind,
+    source: Source,
+    pub format: FeedFormat,
+    selector: Selector,
+    pub refresh: Duration,
+    pub max_bytes: u64,
+    pub stale_after_intervals: u32,
+    pub seed_score: u64,
+}
+
+impl FeedSpec {
+    /// `allowed_hosts` is the egress allowlist an HTTPS source must be on.
+    pub fn from_config(cfg: &FeedConfig, allowed_hosts: &HashSet<String>) -> anyhow::Result<Self> {
+        let name = cfg.name.trim();
+        let name_ok = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !name_ok {
+            bail!("feed name {:?} must be non-empty [A-Za-z0-9_-]", cfg.name);
+        }
+        let context = |e: anyhow::Error| anyhow!("feeds {name:?}: {e}");
+
+        let selector = match cfg.format {
+            FeedFormat::Plain => Selector::Line,
+            FeedFormat::Csv => {
+                let column = cfg
+                    .column
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|c| !c.is_empty())
+                    .ok_or_else(|| context(anyhow!("csv feeds need `column`")))?;
+                match column.parse::<usize>() {
+                    Ok(i) => Selector::Column(i),
+                    Err(_) => Selector::Header(column.to_string()),
+                }
+            }
+            FeedFormat::Jsonl => {
+                let field = cfg
+                    .field
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|f| !f.is_empty())
+                    .ok_or_else(|| context(anyhow!("jsonl feeds need `field`")))?;
+                Selector::Field(field.split('.').map(str::to_string).collect())
+            }
+        };
+
+        let refresh_secs = cfg.refresh_secs.unwrap_or(DEFAULT_REFRESH_SECS);
+        if refresh_secs == 0 {
+            return Err(context(anyhow!("refresh_secs must be > 0")));
+        }
+        let stale_after_intervals = cfg
+            .stale_after_intervals
+            .unwrap_or(DEFAULT_STALE_AFTER_INTERVALS)
+            .max(1);
+
+        Ok(Self {
+            name: name.to_string(),
+            kind: cfg.kind,
+            source: Source::parse(cfg.source.trim(), allowed_hosts).map_err(context)?,
+            format: cfg.format,
+            selector,
+            refresh: Duration::from_secs(refresh_secs),
+            max_bytes: cfg.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            stale_after_intervals,
+            seed_score: cfg.seed_score.unwrap_or(DEFAULT_SEED_SCORE),
+        })
+    }
+
+    /// Validate every entry; names must be unique.
+    pub fn from_configs(
+        cfgs: &[FeedConfig],
+        allowed_hosts: &HashSet<String>,
+    ) -> anyhow::Result<Vec<Self>> {
+        let mut names = HashSet::new();
+        let mut specs = vec![];
+        for cfg in cfgs {
+            let spec = Self::from_config(cfg, allowed_hosts)?;
+            if !names.insert(spec.name.clone()) {
+                bail!("feeds: duplicate feed name {:?}", spec.name);
+            }
+            specs.push(spec);
+        }
+        Ok(specs)
+    }
+
+    /// Parse and validate a whole feed body. Any bad entry rejects the whole update.
+    pub fn parse(&self, body: &[u8]) -> Result<HashSet<String>, String> {
+        let text = std::str::from_utf8(body).map_err(|_| "feed is not UTF-8".to_string())?;
+        let mut entries = HashSet::new();
+        let mut header: Option<usize> = None;
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let value = match self.select(line, &mut header) {
+                Ok(Some(v)) => v,
+                Ok(None) => continue,
+                Err(e) => return Err(format!("line {}: {e}", i + 1)),
+            };
+            let domain = normalize_domain(&value)
+                .ok_or_else(|| format!("line {}: invalid domain {value:?}", i + 1))?;
+            entries.insert(domain);
+        }
+
+        if entries.is_empty() {
+            return Err("feed has no entries".to_string());
+        }
+        Ok(entries)
+    }
+
+    /// The domain on one non-comment line; `None` for a CSV header line.
+    fn select(&self, line: &str, header: &mut Option<usize>) -> Result<Option<String>, String> {
+        let value = match &self.selector {
+            Selector::Line => line
+                .split('#')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            Selector::Column(c) => csv_fields(line)
+                .into_iter()
+                .nth(*c)
+                .ok_or_else(|| format!("no column {c}"))?,
+            Selector::Header(name) => {
+                let fields = csv_fields(line);
+                let Some(c) = *header else {
+                    let c = fields
+                        .iter()
+                        .position(|f| f.trim() == name)
+                        .ok_or_else(|| format!("no column named {name:?}"))?;
+                    *header = Some(c);
+                    return Ok(None);
+                };
+                fields
+                    .into_iter()
+                    .nth(c)
+                    .ok_or_else(|| format!("no column {name:?}"))?
+            }
+            Selector::Field(path) => {
+                let v: Value =
+                    serde_json::from_str(line).map_err(|e| format!("invalid JSON: {e}"))?;
+                path.iter()
+                    .try_fold(&v, |v, key| v.get(key))
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("no string at {:?}", path.join(".")))?
+            }
+        };
+        Ok(Some(value))
+    }
+}
+
+/// Fields of one CSV line; double-quoted fields may contain commas and `""`.
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut cur = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                cur.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut cur)),
+            _ => cur.push(c),
+        }
+    }
+    fields.push(cur);
+    fields
+}
+
+/// Lowercased domain without a leading `*.` or trailing dot; `None` unless every label is
+/// 1-63 characters of `[a-z0-9-]` not starting or ending with `-`.
+pub fn normalize_domain(raw: &str) -> Option<String> {
+    let d = raw.trim().to_ascii_lowercase();
+    let d = d.strip_prefix("*.").unwrap_or(&d);
+    let d = d.strip_suffix('.').unwrap_or(d);
+    if d.is_empty() || d.len() > 253 {
+        return None;
+    }
+    let labels_ok = d.split('.').all(|l| {
+        !l.is_empty()
+            && l.len() <= 63
+            && !l.starts_with('-')
+            && !l.ends_with('-')
+            && l.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+    });
+    labels_ok.then(|| d.to_string())
+}
+
+/// Hosts of the `http://` and `https://` URLs in `text`, normalized (IDNs in punycode).
+pub fn url_hosts(text: &str) -> BTreeSet<String> {
+    url_hosts_checked(text).0
+}
+
+/// [`url_hosts`], and whether every URL had a domain host (IP literals and malformed hosts are
+/// left out of the set).
+pub fn url_hosts_checked(text: &str) -> (BTreeSet<String>, bool) {
+    let lower = text.to_lowercase();
+    let mut hosts = BTreeSet::new();
+    let mut complete = true;
+    for scheme in ["http://", "https://"] {
+        for (i, _) in lower.match_indices(scheme) {
+            let rest = &lower[i + scheme.len()..];
+            let authority = rest
+                .split(|c: char| {
+                    c.is_whitespace() || matches!(c, '/' | '?' | '#' | '"' | '\'' | '<' | '>' | ')')
+                })
+                .next()
+                .unwrap_or_default();
+            let host = authority.rsplit('@').next().unwrap_or_default();
+            let host = host.split(':').next().unwrap_or_default();
+            match url_allowlist::normalize_host(host) {
+                Some(h) => {
+                    hosts.insert(h);
+                }
+                None => complete = false,
+            }
+        }
+    }
+    (hosts, complete)
+}
+
+/// `host` or one of its parent domains is in `entries`.
+fn listed(entries: &HashSet<String>, host: &str) -> bool {
+    let mut h = host;
+    loop {
+        if entries.contains(h) {
+            return true;
+        }
+        match h.split_once('.') {
+            Some((_, parent)) => h = parent,
+            None => return false,
+        }
+    }
+}
+
+/// Like [`listed`], but an entry that is a public suffix never matches.
+fn listed_below_suffix(entries: &HashSet<String>, host: &str) -> bool {
+    let mut h = host;
+    while !url_allowlist::is_public_suffix(h) {
+        if entries.contains(h) {
+            return true;
+        }
+        match h.split_once('.') {
+            Some((_, parent)) => h = parent,
+            None => return false,
+        }
+    }
+    false
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeedStatus {
+    pub entries: usize,
+    pub last_attempt_unix: Option<u64>,
+    pub last_success_unix: Option<u64>,
+    /// When the data last changed (a `304` or unchanged file only counts as a success).
+    pub last_updated_unix: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// Cache validators from the last good fetch.
+#[derive(Debug, Clone, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+enum Fetched {
+    NotModified,
+    Body(Vec<u8>, Validators),
+}
+
+struct Feed {
+    spec: FeedSpec,
+    /// Swapped as a whole on each successful update.
+    data: RwLock<Arc<HashSet<String>>>,
+    status: Mutex<FeedStatus>,
+    validators: Mutex<Validators>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum RefreshOutcome {
+    Updated { entries: usize },
+    NotModified,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum FeedError {
+    #[error("unknown feed {0}")]
+    UnknownFeed(String),
+    #[error("feed {name} refresh failed: {reason}")]
+    Refresh { name: String, reason: String },
+}
+
+impl IntoResponse for FeedError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::UnknownFeed(name) => introspection::json_error(
+                StatusCode::NOT_FOUND,
+                "unknown_feed",
+                json!({"name": name}),
+            )
+            .into_response(),
+            Self::Refresh { name, reason } => introspection::json_error(
+                StatusCode::BAD_GATEWAY,
+                "feed_refresh_failed",
+                json!({"name": name, "reason": reason}),
+            )
+            .into_response(),
+        }
+    }
+}
+
+/// Every configured feed and its current data, shared by all requests.
+pub struct FeedRegistry {
+    feeds: BTreeMap<String, Feed>,
+    http: reqwest::Client,
+    clock: Arc<dyn Clock>,
+    telemetry: Telemetry,
+}
+
+impl Default for FeedRegistry {
+    fn default() -> Self {
+        Self::new(vec![], reqwest::Client::new(), Arc::new(SystemClock))
+    }
+}
+
+impl FeedRegistry {
+    /// Feeds start empty (and stale) until their first refresh.
+    pub fn new(specs: Vec<FeedSpec>, http: reqwest::Client, clock: Arc<dyn Clock>) -> Self {
+        let feeds = specs
+            .into_iter()
+            .map(|spec| {
+                let feed = Feed {
+                    spec,
+                    data: RwLock::new(Arc::new(HashSet::new())),
+                    status: Mutex::new(FeedStatus::default()),
+                    validators: Mutex::new(Validators::default()),
+                };
+                (feed.spec.name.clone(), feed)
+            })
+            .collect();
+        Self {
+            feeds,
+            http,
+            clock,
+            telemetry: Telemetry::default(),
+        }
+    }
+
+    /// Export a span per refresh.
+    pub fn with_telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// HTTPS sources are checked against `ACIP_EGRESS_HOSTS`.
+    pub fn from_config(
+        cfgs: &[FeedConfig],
+        http: reqwest::Client,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let specs = FeedSpec::from_configs(cfgs, &egress::allowed_hosts_from_env())?;
+        Ok(Self::new(specs, http, clock))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.feeds.keys().cloned().collect()
+    }
+
+    pub fn status(&self, name: &str) -> Option<FeedStatus> {
+        self.feeds
+            .get(name)
+            .map(|f| f.status.lock().unwrap().clone())
+    }
+
+    /// Fetch `name` now. On any failure the previous data keeps serving.
+    pub async fn refresh(&self, name: &str) -> Result<RefreshOutcome, FeedError> {
+        self.refresh_traced(name, None).await
+    }
+
+    /// [`Self::refresh`] as a span in `parent`'s trace, or in a new trace without one. An HTTP
+    /// feed's request carries the span's context.
+    pub async fn refresh_traced(
+        &self,
+        name: &str,
+        parent: Option<&TraceContext>,
+    ) -> Result<RefreshOutcome, FeedError> {
+        let mut span = self
+            .telemetry
+            .span(parent, "acip.feed.refresh", SpanKind::Client);
+        span.attribute("acip.feed", json!(name));
+        let result = self.refresh_in(name, span.context()).await;
+        if result.is_err() {
+            span.fail();
+        }
+        span.end(&self.telemetry);
+        result
+    }
+
+    async fn refresh_in(
+        &self,
+        name: &str,
+        trace: &TraceContext,
+    ) -> Result<RefreshOutcome, FeedError> {
+        let feed = self
+            .feeds
+            .get(name)
+            .ok_or_else(|| FeedError::UnknownFeed(name.to_string()))?;
+        let validators = feed.validators.lock().unwrap().clone();
+        let fetched = match &feed.spec.source {
+            Source::File(path) => fetch_file(path.clone(), validators, feed.spec.max_bytes).await,
+            Source::Http(url) => {
+                fetch_http(&self.http, url, &validators, feed.spec.max_bytes, trace).await
+            }
+        };
+        let parsed = fetched.and_then(|f| match f {
+            Fetched::NotModified => Ok(None),
+            Fetched::Body(body, v) => feed.spec.parse(&body).map(|e| Some((e, v))),
+        });
+
+        let now = self.clock.now_unix();
+        let mut status = feed.status.lock().unwrap();
+        status.last_attempt_unix = Some(now);
+        match parsed {
+            Ok(None) => {
+                status.last_success_unix = Some(now);
+                status.last_error = None;
+                Ok(RefreshOutcome::NotModified)
+            }
+            Ok(Some((entries, validators))) => {
+                let count = entries.len();
+                *feed.data.write().unwrap() = Arc::new(entries);
+                *feed.validators.lock().unwrap() = validators;
+                status.entries = count;
+                status.last_success_unix = Some(now);
+                status.last_updated_unix = Some(now);
+                status.last_error = None;
+                tracing::info!(feed = %name, entries = count, "feed updated");
+                Ok(RefreshOutcome::Updated { entries: count })
+            }
+            Err(reason) => {
+                tracing::warn!(feed = %name, "feed refresh failed; keeping previous data: {reason}");
+                status.last_error = Some(reason.clone());
+                Err(FeedError::Refresh {
+                    name: name.to_string(),
+                    reason,
+                })
+            }
+        }
+    }
+
+    /// Refresh every feed once (startup); failures are logged and recorded in the status.
+    pub async fn refresh_all(&self) {
+        for name in self.feeds.keys() {
+            let _ = self.refresh(name).await;
+        }
+    }
+
+    fn is_stale_at(feed: &Feed, now: u64) -> bool {
+        let max_age = feed.spec.refresh.as_secs() * feed.spec.stale_after_intervals as u64;
+        match feed.status.lock().unwrap().last_success_unix {
+            Some(t) => now.saturating_sub(t) > max_age,
+            None => true,
+        }
+    }
+
+    /// Feeds whose last successful refresh is older than `stale_after_intervals` intervals
+    /// (or that never loaded).
+    pub fn stale_feeds(&self) -> Vec<String> {
+        let now = self.clock.now_unix();
+        self.feeds
+            .iter()
+            .filter(|(_, f)| Self::is_stale_at(f, now))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Names of the `kind` feeds listing `host` (or a parent domain).
+    fn listing(&self, kind: FeedKind, host: &str) -> Vec<&Feed> {
+        self.feeds
+            .values()
+            .filter(|f| f.spec.kind == kind && listed(&f.data.read().unwrap(), host))
+            .collect()
+    }
+
+    /// URL scanner stage: flag linked hosts on a blocklist, report every linked host in
+    /// `urls`, and clear the generic URL indicators when every linked host is allowlisted
+    /// (by an allowlist feed or by `allowlist`) and none is blocked.
+    pub fn assess_urls(
+        &self,
+        text: &str,
+        allowlist: &UrlAllowlistConfig,
+        a: &mut ThreatAssessment,
+    ) {
+        let (hosts, complete) = url_hosts_checked(text);
+        if hosts.is_empty() {
+            return;
+        }
+        let mut blocked = false;
+        for host in &hosts {
+            for feed in self.listing(FeedKind::DomainBlocklist, host) {
+                let indicator = format!("feed_blocklist:{}:{host}", feed.spec.name);
+                a.detected.push(DetectedPattern {
+                    indicator: indicator.clone(),
+                    stage: ScanStage::Raw,
+                });
+                a.add(AttackType::DataExfiltration, indicator, BLOCKLIST_SCORE);
+                blocked = true;
+            }
+        }
+        let urls = self.url_refs(&hosts, allowlist);
+        let all_allowed = complete
+            && urls.iter().all(|u| {
+                u.allowlisted || !self.listing(FeedKind::DomainAllowlist, &u.host).is_empty()
+            });
+        if all_allowed && !blocked {
+            for indicator in URL_INDICATORS {
+                a.remove_phrase(indicator);
+            }
+        }
+        a.urls.extend(urls);
+        a.normalize();
+    }
+
+    /// `hosts` with their status under a policy's `allowlist`: inline domains first, then the
+    /// `domain_allowlist` feeds it names, in name order.
+    pub fn url_refs(
+        &self,
+        hosts: &BTreeSet<String>,
+        allowlist: &UrlAllowlistConfig,
+    ) -> Vec<UrlRef> {
+        hosts
+            .iter()
+            .map(|host| {
+                let inline = allowlist
+                    .domains
+                    .iter()
+                    .any(|d| url_allowlist::covers(d, host))
+                    .then(|| "inline".to_string());
+                let by = inline.or_else(|| {
+                    allowlist
+                        .feeds
+                        .iter()
+                        .filter_map(|name| self.feeds.get(name))
+                        .find(|f| {
+                            f.spec.kind == FeedKind::DomainAllowlist
+                                && listed_below_suffix(&f.data.read().unwrap(), host)
+                        })
+                        .map(|f| f.spec.name.clone())
+                });
+                UrlRef {
+                    host: host.clone(),
+                    allowlisted: by.is_some(),
+                    allowlisted_by: by,
+                }
+            })
+            .collect()
+    }
+
+    /// `allowlist` as in effect now: its domains and the entry count of each feed it names.
+    pub fn effective_allowlist(&self, allowlist: &UrlAllowlistConfig) -> EffectiveAllowlist {
+        EffectiveAllowlist {
+            domains: allowlist.domains.clone(),
+            feeds: allowlist
+                .feeds
+                .iter()
+                .map(|name| {
+                    let entries = self.feeds.get(name).map(|f| f.data.read().unwrap().len());
+                    (name.clone(), entries)
+                })
+                .collect(),
+        }
+    }
+
+    /// Refuse policies whose `url_allowlist` names a feed that is not configured or is not a
+    /// `domain_allowlist`.
+    pub fn check_policy_refs(&self, policies: &PolicyStore) -> anyhow::Result<()> {
+        for policy in policies.list() {
+            let Some(cfg) = policies.get(&policy) else {
+                continue;
+            };
+            for name in &cfg.url_allowlist.feeds {
+                match self.feeds.get(name) {
+                    None => bail!("policy '{policy}': url_allowlist names unknown feed '{name}'"),
+                    Some(f) if f.spec.kind != FeedKind::DomainAllowlist => bail!(
+                        "policy '{policy}': url_allowlist feed '{name}' is not a domain_allowlist"
+                    ),
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Highest seed score among the `reputation_seed` feeds listing `host`.
+    pub fn seed_score(&self, host: &str) -> Option<u64> {
+        self.listing(FeedKind::ReputationSeed, host)
+            .iter()
+            .map(|f| f.spec.seed_score)
+            .max()
+    }
+
+    /// Reputation seeding: `host:` records of seeded hosts start at the seed score.
+    pub fn apply_seeds(&self, recs: &mut [ReputationRecord]) {
+        for rec in recs {
+            let Some(score) = rec
+                .key
+                .strip_prefix("host:")
+                .and_then(|h| self.seed_score(h))
+            else {
+                continue;
+            };
+            rec.risk_score = rec.risk_score.max(score);
+        }
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        let now = self.clock.now_unix();
+        let feeds: serde_json::Map<String, Value> = self
+            .feeds
+            .iter()
+            .map(|(name, f)| {
+                let mut v = json!(f.status.lock().unwrap().clone());
+                v["type"] = json!(f.spec.kind);
+                v["format"] = json!(f.spec.format);
+                v["source"] = json!(f.spec.source.display());
+                v["refresh_secs"] = json!(f.spec.refresh.as_secs());
+                v["stale"] = json!(Self::is_stale_at(f, now));
+                (name.clone(), v)
+            })
+            .collect();
+        Value::Object(feeds)
+    }
+}
+
+async fn fetch_file(
+    path: PathBuf,
+    validators: Validators,
+    max_bytes: u64,
+) -> Result<Fetched, String> {
+    let read = tokio::task::spawn_blocking(move || -> Result<Fetched, String> {
+        let meta = std::fs::metadata(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mtime = meta
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos().to_string());
+        if mtime.is_some() && mtime == validators.last_modified {
+            return Ok(Fetched::NotModified);
+        }
+        if meta.len() > max_bytes {
+            return Err(format!("feed is {} bytes (max {max_bytes})", meta.len()));
+        }
+        let body = std::fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        if body.len() as u64 > max_bytes {
+            return Err(format!("feed is {} bytes (max {max_bytes})", body.len()));
+        }
+        Ok(Fetched::Body(
+            body,
+            Validators {
+                etag: None,
+                last_modified: mtime,
+            },
+        ))
+    });
+    read.await.map_err(|e| e.to_string())?
+}
+
+async fn fetch_http(
+    http: &reqwest::Client,
+    url: &str,
+    validators: &Validators,
+    max_bytes: u64,
+    trace: &TraceContext,
+) -> Result<Fetched, String> {
+    use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+    let mut req = trace.apply(http.get(url));
+    if let Some(etag) = &validators.etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    if let Some(lm) = &validators.last_modified {
+        req = req.header(IF_MODIFIED_SINCE, lm);
+    }
+    let mut resp = req.send().await.map_err(|e| e.to_string())?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+    if !resp.status().is_success() {
+        return Err(format!("HTTP {}", resp.status()));
+    }
+    if let Some(len) = resp.content_length().filter(|l| *l > max_bytes) {
+        return Err(format!("feed is {len} bytes (max {max_bytes})"));
+    }
+
+    let header = |name: reqwest::header::HeaderName| {
+        resp.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let next = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await.map_err(|e| e.to_string())? {
+        if (body.len() + chunk.len()) as u64 > max_bytes {
+            return Err(format!("feed exceeds {max_bytes} bytes"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Fetched::Body(body, next))
+}
+
+/// Refresh each feed on its own interval.
+pub fn start(feeds: Arc<FeedRegistry>) {
+    for name in feeds.names() {
+        let feeds = feeds.clone();
+        tokio::spawn(async move {
+            let interval = feeds.feeds[&name].spec.refresh;
+            loop {
+                tokio::time::sleep(interval).await;
+                let _ = feeds.refresh(&name).await;
+            }
+        });
+    }
+}
+
+/// `POST /v1/acip/feeds/{name}/refresh`
+pub async fn post_refresh(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    let caller = TraceContext::from_headers(&headers);
+    match state.feeds.refresh_traced(&name, caller.as_ref()).await {
+        Ok(outcome) => {
+            let mut v = json!(outcome);
+            v["name"] = json!(name);
+            v["status"] = json!(state.feeds.status(&name));
+            (StatusCode::OK, Json(v)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,271 @@
+//! Deployment-scoped hashing of identifiers.
+//!
+//! Two helpers, and the name says where the output may go:
+//!
+//! - [`IdHasher::export_id`]: HMAC-SHA256 keyed with this deployment's salt. Every identifier
+//!   hash that leaves the process goes through it: SIEM observables, event ids and content
+//!   hashes, the content hash in audit entries, and digests in log lines. Without the salt an
+//!   outside party cannot dictionary-reverse a guessable `source_id`, and two deployments
+//!   cannot correlate their exports.
+//! - [`internal_only_digest`]: plain SHA-256, for exact-match lookups that never leave the
+//!   process (the last-decision map key). The one exception is the `digest.sha256` of an
+//!   ingest response: it goes only to the caller that sent the content.
+//!
+//! The salt is the `ACIP_HASHING_SALT` secret. Without one, it is read from `hashing.salt_file`
+//! (default `/var/lib/acip/hashing.salt`), and generated into that file on first start. If
+//! the file cannot be written the process runs on an ephemeral salt and says so: exports stay
+//! salted but do not line up across restarts.
+//!
+//! Rotation: set `hashing.accept_previous_salt = true` with the old salt in
+//! `ACIP_HASHING_PREVIOUS_SALT` and the new one in `ACIP_HASHING_SALT`. New exports use the
+//! new salt; [`IdHasher::matches`] and the admin lookup accept both until the flag is removed.
+
+use crate::config::HashingConfig;
+use crate::introspection;
+use crate::secrets::{Secret, SecretStore};
+use crate::state::AppState;
+use anyhow::{bail, Context};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Secret holding the current salt.
+pub const SALT_SECRET: &str = "ACIP_HASHING_SALT";
+/// Secret holding the previous salt while `accept_previous_salt` is on.
+pub const PREVIOUS_SALT_SECRET: &str = "ACIP_HASHING_PREVIOUS_SALT";
+pub const DEFAULT_SALT_FILE: &str = "/var/lib/acip/hashing.salt";
+
+/// Hex HMAC-SHA256 of `message` under `salt`.
+fn salted_hex(salt: &Secret, message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt.expose().as_bytes())
+        .expect("HMAC accepts any key length");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Unsalted SHA-256 hex, for exact-match keys that stay inside the process (and the `digest`
+/// handed back to the caller that sent the content). Anything else that is serialized, logged
+/// or exported uses [`IdHasher::export_id`] instead.
+pub fn internal_only_digest(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Where the current salt came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaltSource {
+    Secret,
+    File,
+    Generated,
+    /// Random for this process only.
+    Ephemeral,
+}
+
+/// The deployment salt (and, during a rotation, the previous one).
+pub struct IdHasher {
+    salt: Secret,
+    previous: Option<Secret>,
+    source: SaltSource,
+}
+
+impl Default for IdHasher {
+    /// An ephemeral salt: never the unsalted digest, but not stable across restarts.
+    fn default() -> Self {
+        Self {
+            salt: random_salt(),
+            previous: None,
+            source: SaltSource::Ephemeral,
+        }
+    }
+}
+
+impl IdHasher {
+    pub fn new(salt: impl Into<Secret>) -> Self {
+        Self {
+            salt: salt.into(),
+            previous: None,
+            source: SaltSource::Secret,
+        }
+    }
+
+    /// Also accept `previous` in lookups (a rotation window).
+    pub fn with_previous(mut self, previous: impl Into<Secret>) -> Self {
+        self.previous = Some(previous.into());
+        self
+    }
+
+    /// The salt per the module docs. `read_only` never writes a salt file.
+    pub fn load(
+        secrets: &dyn SecretStore,
+        cfg: Option<&HashingConfig>,
+        read_only: bool,
+    ) -> anyhow::Result<Self> {
+        let accept_previous = cfg.and_then(|c| c.accept_previous_salt).unwrap_or(false);
+        let previous = match secrets.get(PREVIOUS_SALT_SECRET) {
+            Some(p) if accept_previous => Some(p),
+            None if accept_previous => {
+                bail!("hashing.accept_previous_salt is set but {PREVIOUS_SALT_SECRET} is missing")
+            }
+            _ => None,
+        };
+        let path = std::env::var("ACIP_HASHING_SALT_FILE")
+            .ok()
+            .or_else(|| cfg.and_then(|c| c.salt_file.clone()))
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SALT_FILE));
+
+        let (salt, source) = if let Some(s) = secrets.get(SALT_SECRET) {
+            (s, SaltSource::Secret)
+        } else if let Some(s) = read_salt_file(&path)? {
+            (s, SaltSource::File)
+        } else if read_only {
+            tracing::warn!(
+                path = %path.display(),
+                "no hashing salt and read-only mode: using an ephemeral salt"
+            );
+            (random_salt(), SaltSource::Ephemeral)
+        } else {
+            let salt = random_salt();
+            match write_salt_file(&path, &salt) {
+                Ok(()) => {
+                    tracing::info!(path = %path.display(), "generated the hashing salt");
+                    (salt, SaltSource::Generated)
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "cannot persist a hashing salt ({e:#}); using an ephemeral salt: \
+                         exported identifiers will not match across restarts"
+                    );
+                    (salt, SaltSource::Ephemeral)
+                }
+            }
+        };
+        if previous.as_ref().is_some_and(|p| p.ct_eq(salt.expose())) {
+            bail!("{SALT_SECRET} equals {PREVIOUS_SALT_SECRET}: nothing is being rotated");
+        }
+        Ok(Self {
+            salt,
+            previous,
+            source,
+        })
+    }
+
+    /// Salted identifier for anything that leaves the process (hex HMAC-SHA256).
+    pub fn export_id(&self, value: impl AsRef<[u8]>) -> String {
+        salted_hex(&self.salt, value.as_ref())
+    }
+
+    /// `value` as exported under the current salt, then under the previous one if accepted.
+    pub fn candidates(&self, value: impl AsRef<[u8]>) -> Vec<String> {
+        let value = value.as_ref();
+        std::iter::once(&self.salt)
+            .chain(&self.previous)
+            .map(|salt| salted_hex(salt, value))
+            .collect()
+    }
+
+    /// Whether `exported` is `value` under an accepted salt. A prefix of at least 16 hex chars
+    /// matches too (event ids are truncated).
+    pub fn matches(&self, value: impl AsRef<[u8]>, exported: &str) -> bool {
+        let exported = exported.trim().to_ascii_lowercase();
+        exported.len() >= 16
+            && self
+                .candidates(value)
+                .iter()
+                .any(|c| c.starts_with(&exported))
+    }
+
+    pub fn snapshot(&self) -> HashingSnapshot {
+        HashingSnapshot {
+            salt_source: self.source,
+            accepting_previous_salt: self.previous.is_some(),
+        }
+    }
+}
+
+/// `hashing` in `/v1/acip/status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HashingSnapshot {
+    pub salt_source: SaltSource,
+    pub accepting_previous_salt: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LookupQuery {
+    /// The plain identifier: a `source_id`, URL or title, or a content SHA-256.
+    pub value: String,
+    /// An exported id (or event id prefix) to check against `value`.
+    #[serde(default)]
+    pub exported: Option<String>,
+}
+
+/// `GET /v1/acip/admin/hashing/lookup`: `value` as exported under each accepted salt, so exports
+/// from both sides of a rotation can be searched.
+pub async fn get_lookup(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<LookupQuery>,
+) -> Response {
+    if q.value.is_empty() {
+        return introspection::json_error(StatusCode::BAD_REQUEST, "value is required", json!({}))
+            .into_response();
+    }
+    let mut candidates = state.hashing.candidates(&q.value).into_iter();
+    let mut body = json!({
+        "current": candidates.next(),
+        "previous": candidates.next(),
+    });
+    if let Some(exported) = &q.exported {
+        body["matches"] = json!(state.hashing.matches(&q.value, exported));
+    }
+    Json(body).into_response()
+}
+
+fn random_salt() -> Secret {
+    let mut bytes = zeroize::Zeroizing::new([0u8; 32]);
+    let read = std::fs::File::open("/dev/urandom").and_then(|mut f| f.read_exact(&mut bytes[..]));
+    if read.is_err() {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        *bytes = Sha256::digest(format!("{nanos}:{}:{:p}", std::process::id(), &bytes)).into();
+    }
+    Secret::new(hex::encode(&bytes[..]))
+}
+
+fn read_salt_file(path: &Path) -> anyhow::Result<Option<Secret>> {
+    match std::fs::read_to_string(path).map(Secret::new) {
+        Ok(s) if s.is_blank() => bail!("hashing salt file {} is empty", path.display()),
+        Ok(s) => Ok(Some(Secret::new(s.expose().trim()))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading hashing salt {}", path.display())),
+    }
+}
+
+fn write_salt_file(path: &Path, salt: &Secret) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut opts, 0o600);
+    let mut f = opts
+        .open(path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    writeln!(f, "{}", salt.expose())?;
+    f.sync_all()?;
+    Ok(())
+}
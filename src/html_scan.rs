@@ -15,6 +15,19 @@ pub struct HtmlScanResult {
     pub severity: u8,
 }
 
+impl HtmlScanResult {
+    /// Every `http(s)` link points at an allowlisted host: drop the external-reference flag and
+    /// its severity. A data URI keeps the flag, as it embeds content rather than linking it.
+    pub fn clear_external_ref(&mut self) {
+        self.matches.retain(|m| m != "http" && m != "https");
+        if !self.has_external_ref || self.has_data_uri {
+            return;
+        }
+        self.has_external_ref = false;
+        self.severity = self.severity.saturating_sub(1);
+    }
+}
+
 static PATTERNS: &[(&str, &str)] = &[
     ("script_tag", "<script"),
     ("javascript_uri", "javascript:"),
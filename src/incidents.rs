@@ -0,0 +1,508 @@
+//! Cross-references between the records one ingest request leaves behind.
+//!
+//! Every ingest run that gets as far as a request id (`origin.request_id`) writes one audit
+//! entry. The entry is built with all of its references and inserted in one step: the async
+//! job or resumable upload it ran for, its decision, and the reputation events it caused. A
+//! finished job records the request id in turn.
+//!
+//! `GET /v1/acip/incidents/{request_id}` joins the entry with the live job (including callback
+//! delivery), the reputation records it touched and the slow request record, if any.
+//! `GET /v1/acip/admin/incidents/check` lists references that point nowhere.
+//!
+//! Entries are kept in memory, newest [`MAX_AUDIT_ENTRIES`]. An evicted entry or a purged job
+//! therefore shows up in the check as an orphan.
+
+use crate::cache_bypass::BypassMode;
+use crate::experiments::Assignment;
+use crate::hashing::IdHasher;
+use crate::introspection;
+use crate::reputation::{Clock, ReputationRecord, SystemClock};
+use crate::reputation_policy::{self, ReputationThresholds};
+use crate::sentry::Action;
+use crate::slow_requests::Stage;
+use crate::state::AppState;
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use url::Url;
+
+/// Audit entries kept; the oldest is evicted first.
+pub const MAX_AUDIT_ENTRIES: usize = 10_000;
+
+/// What an ingest run was started for, besides a plain `ingest_source` call.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Links {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload_id: Option<String>,
+    /// Host of the run's per-request decision callback (see [`crate::notify`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback_host: Option<String>,
+    /// The callback itself; taken by the run before the audit entry is written.
+    #[serde(skip)]
+    pub callback_url: Option<Url>,
+}
+
+impl Links {
+    pub fn job(id: &str) -> Self {
+        Self {
+            job_id: Some(id.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn upload(id: &str) -> Self {
+        Self {
+            upload_id: Some(id.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn callback(url: Url) -> Self {
+        Self {
+            callback_host: url.host_str().map(str::to_lowercase),
+            callback_url: Some(url),
+            ..Self::default()
+        }
+    }
+}
+
+/// Side effects collected while the pipeline runs.
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub reputation: Vec<ReputationEvent>,
+    /// The stage at which the run noticed its client had disconnected (see
+    /// [`crate::disconnect`]).
+    pub disconnected_at: Option<Stage>,
+    /// The policy experiment arm the run was put on.
+    pub experiment: Option<Assignment>,
+    /// The action of the final decision, once made.
+    pub decided: Option<Action>,
+    /// `X-ACIP-Bypass-Cache` mode of the run.
+    pub cache_bypass: Option<BypassMode>,
+    /// Scanner indicators of the run, kept for remediation hints outside audit mode.
+    pub indicators: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReputationEventKind {
+    /// The request's threat score was added to the record's risk.
+    Bump,
+    /// The record's effective risk raised the decision's risk level (`medium_score` and up).
+    Escalation,
+    /// The record's effective risk reached `bad_actor_score`: tools are off whatever the caller
+    /// asked for.
+    HardCap,
+}
+
+/// A reputation change caused by one request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReputationEvent {
+    pub kind: ReputationEventKind,
+    pub request_id: String,
+    /// Reputation record key, e.g. `source_id:abc` or `host:example.com`.
+    pub key: String,
+    pub risk_before: u64,
+    pub risk_after: u64,
+    /// Decayed, trust-discounted score the escalation or cap was decided on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effective_risk: Option<u64>,
+}
+
+/// Events for `recs`, the records an observation with `threat_score` just updated. Escalation
+/// and hard cap follow [`reputation_policy::apply_reputation`]: only the riskiest record
+/// ([`reputation_policy::rank_records`]) counts.
+pub fn reputation_events(
+    request_id: &str,
+    threat_score: u8,
+    recs: &[ReputationRecord],
+    t: &ReputationThresholds,
+) -> Vec<ReputationEvent> {
+    reputation_events_with_clock(request_id, threat_score, recs, t, &SystemClock)
+}
+
+/// [`reputation_events`] with an explicit time source.
+pub fn reputation_events_with_clock(
+    request_id: &str,
+    threat_score: u8,
+    recs: &[ReputationRecord],
+    t: &ReputationThresholds,
+    clock: &dyn Clock,
+) -> Vec<ReputationEvent> {
+    let now_unix = clock.now_unix();
+    let mut out = vec![];
+    if threat_score > 0 {
+        for r in recs {
+            out.push(ReputationEvent {
+                kind: ReputationEventKind::Bump,
+                request_id: request_id.to_string(),
+                key: r.key.clone(),
+                risk_before: r.risk_score.saturating_sub(threat_score as u64),
+                risk_after: r.risk_score,
+                effective_risk: None,
+            });
+        }
+    }
+    let worst = reputation_policy::rank_records(now_unix, recs, t)
+        .into_iter()
+        .next();
+    if let Some((r, b)) = worst {
+        let kind = if b.effective_risk >= t.bad_actor_score {
+            Some(ReputationEventKind::HardCap)
+        } else if b.effective_risk >= t.medium_score {
+            Some(ReputationEventKind::Escalation)
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            out.push(ReputationEvent {
+                kind,
+                request_id: request_id.to_string(),
+                key: r.key.clone(),
+                risk_before: r.risk_score,
+                risk_after: r.risk_score,
+                effective_risk: Some(b.effective_risk),
+            });
+        }
+    }
+    out
+}
+
+/// The decision part of an audit entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionSummary {
+    pub action: String,
+    pub risk_level: String,
+    pub tools_allowed: bool,
+    pub threat_score: u64,
+    /// The content's SHA-256, hashed again with the deployment salt ([`IdHasher::export_id`]).
+    pub content_sha256: String,
+    #[serde(default)]
+    pub reasons: Vec<String>,
+}
+
+impl DecisionSummary {
+    /// From an `ingest_source` success body.
+    pub fn from_response(body: &Value, hasher: &IdHasher) -> Option<Self> {
+        Some(Self {
+            action: body["action"].as_str()?.to_string(),
+            risk_level: body["risk_level"].as_str()?.to_string(),
+            tools_allowed: body["tools_allowed"].as_bool()?,
+            threat_score: body["threat"]["threat_score"].as_u64().unwrap_or(0),
+            content_sha256: hasher.export_id(body["digest"]["sha256"].as_str().unwrap_or_default()),
+            reasons: body["reasons"]
+                .as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|r| r.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// One ingest run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// `audit-<n>`, unique within this process.
+    pub id: String,
+    pub request_id: String,
+    pub recorded_unix: u64,
+    pub actor: String,
+    pub policy: String,
+    pub source_id: String,
+    pub source_type: String,
+    pub http_status: u16,
+    /// For a success; errors carry `error` instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decision: Option<DecisionSummary>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten)]
+    pub links: Links,
+    #[serde(default)]
+    pub reputation_events: Vec<ReputationEvent>,
+    /// Set on the entry of a run cut short by a client disconnect (its `error` is
+    /// `client_disconnected`): the stage it stopped at.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disconnected_at: Option<Stage>,
+    /// Set when the run was decided under an experimental policy definition: the experiment id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
+    /// Set when the run bypassed the last-decision map (see [`crate::cache_bypass`]); `actor`
+    /// names the token that asked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_bypass: Option<BypassMode>,
+}
+
+/// What [`IncidentLog::record`] needs besides the response.
+pub struct RunRefs<'a> {
+    pub request_id: &'a str,
+    pub actor: &'a str,
+    pub policy: &'a str,
+    pub source_id: &'a str,
+    pub source_type: &'a str,
+    pub links: Links,
+    pub trace: Trace,
+}
+
+/// A reference the consistency check could not resolve.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Orphan {
+    /// `job_missing`, `audit_missing` or `reputation_missing`.
+    pub kind: &'static str,
+    pub request_id: String,
+    /// The id or key that points nowhere (or, for `audit_missing`, the job holding it).
+    pub reference: String,
+}
+
+#[derive(Default)]
+struct Entries {
+    order: VecDeque<String>,
+    by_request: HashMap<String, AuditEntry>,
+}
+
+pub struct IncidentLog {
+    clock: Arc<dyn Clock>,
+    entries: Mutex<Entries>,
+    next_seq: AtomicU64,
+}
+
+impl Default for IncidentLog {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl IncidentLog {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            entries: Mutex::new(Entries::default()),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    /// Write the audit entry for a finished run. Returns its id.
+    pub fn record(
+        &self,
+        refs: RunRefs<'_>,
+        hasher: &IdHasher,
+        http_status: StatusCode,
+        body: &Value,
+    ) -> String {
+        let id = format!(
+            "audit-{}",
+            self.next_seq.fetch_add(1, Ordering::Relaxed) + 1
+        );
+        let (decision, error) = if http_status.is_success() {
+            (DecisionSummary::from_response(body, hasher), None)
+        } else {
+            (None, body["error"].as_str().map(str::to_string))
+        };
+        let entry = AuditEntry {
+            id: id.clone(),
+            request_id: refs.request_id.to_string(),
+            recorded_unix: self.clock.now_unix(),
+            actor: refs.actor.to_string(),
+            policy: refs.policy.to_string(),
+            source_id: refs.source_id.to_string(),
+            source_type: refs.source_type.to_string(),
+            http_status: http_status.as_u16(),
+            decision,
+            error,
+            links: refs.links,
+            reputation_events: refs.trace.reputation,
+            disconnected_at: refs.trace.disconnected_at,
+            experiment: refs
+                .trace
+                .experiment
+                .as_ref()
+                .and_then(|a| a.experimental_id().map(str::to_string)),
+            cache_bypass: refs.trace.cache_bypass,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        if entries.order.len() == MAX_AUDIT_ENTRIES {
+            if let Some(old) = entries.order.pop_front() {
+                entries.by_request.remove(&old);
+            }
+        }
+        entries.order.push_back(entry.request_id.clone());
+        entries.by_request.insert(entry.request_id.clone(), entry);
+        id
+    }
+
+    /// Buffer `resp`, write its audit entry and hand it back unchanged.
+    pub async fn record_response(
+        &self,
+        refs: RunRefs<'_>,
+        hasher: &IdHasher,
+        resp: Response,
+    ) -> Response {
+        let (parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, crate::redact::MAX_REDACT_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "response too large to audit",
+                )
+                    .into_response()
+            }
+        };
+        let v = serde_json::from_slice::<Value>(&bytes).unwrap_or(Value::Null);
+        self.record(refs, hasher, parts.status, &v);
+        Response::from_parts(parts, Body::from(bytes))
+    }
+
+    pub fn get(&self, request_id: &str) -> Option<AuditEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .by_request
+            .get(request_id)
+            .cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every entry, oldest first.
+    pub fn list(&self) -> Vec<AuditEntry> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .order
+            .iter()
+            .filter_map(|r| entries.by_request.get(r).cloned())
+            .collect()
+    }
+}
+
+/// The incident graph for one request.
+pub fn incident(state: &AppState, request_id: &str) -> Option<Value> {
+    let audit = state.incidents.get(request_id)?;
+    let job = audit.links.job_id.as_deref().map(|id| {
+        state
+            .jobs
+            .get(id)
+            .map(|s| {
+                json!({
+                    "job_id": s.job_id,
+                    "status": s.status,
+                    "request_id": s.request_id,
+                    "http_status": s.http_status,
+                    "callback": s.callback,
+                })
+            })
+            .unwrap_or_else(|| json!({ "job_id": id, "status": "purged" }))
+    });
+    let mut keys: Vec<&str> = audit
+        .reputation_events
+        .iter()
+        .map(|e| e.key.as_str())
+        .collect();
+    keys.sort();
+    keys.dedup();
+    let reputation: Vec<Value> = keys
+        .iter()
+        .map(|k| json!({ "key": k, "record": state.reputation.get(k) }))
+        .collect();
+    let webhook = job
+        .as_ref()
+        .map(|j| j["callback"].clone())
+        .or_else(|| state.notify.callback_state(request_id).map(|s| json!(s)));
+    Some(json!({
+        "request_id": request_id,
+        "audit": audit,
+        "decision": audit.decision,
+        "reputation_events": audit.reputation_events,
+        "reputation": reputation,
+        "job": job,
+        "webhook": webhook,
+        "upload_id": audit.links.upload_id,
+        "slow_request": state.slow_requests.get(request_id),
+    }))
+}
+
+/// References that point nowhere, across the audit log, the job store and reputation.
+pub fn check(state: &AppState) -> Vec<Orphan> {
+    let entries = state.incidents.list();
+    let mut orphans = vec![];
+    for e in &entries {
+        if let Some(job) = &e.links.job_id {
+            if state.jobs.get(job).is_none() {
+                orphans.push(Orphan {
+                    kind: "job_missing",
+                    request_id: e.request_id.clone(),
+                    reference: job.clone(),
+                });
+            }
+        }
+        for ev in &e.reputation_events {
+            if state.reputation.get(&ev.key).is_none() {
+                orphans.push(Orphan {
+                    kind: "reputation_missing",
+                    request_id: e.request_id.clone(),
+                    reference: ev.key.clone(),
+                });
+            }
+        }
+    }
+    for (job_id, request_id) in state.jobs.request_ids() {
+        if state.incidents.get(&request_id).is_none() {
+            orphans.push(Orphan {
+                kind: "audit_missing",
+                request_id,
+                reference: job_id,
+            });
+        }
+    }
+    orphans.sort_by(|a, b| (a.kind, &a.request_id).cmp(&(b.kind, &b.request_id)));
+    orphans.dedup();
+    orphans
+}
+
+/// `GET /v1/acip/incidents/{request_id}`
+pub async fn get_incident(
+    State(state): State<Arc<AppState>>,
+    Path(request_id): Path<String>,
+) -> Response {
+    match incident(&state, &request_id) {
+        Some(v) => Json(v).into_response(),
+        None => introspection::json_error(
+            StatusCode::NOT_FOUND,
+            "unknown_request",
+            json!({ "request_id": request_id }),
+        )
+        .into_response(),
+    }
+}
+
+/// `GET /v1/acip/admin/incidents/check`
+pub async fn get_check(State(state): State<Arc<AppState>>) -> Response {
+    let orphans = check(&state);
+    Json(json!({
+        "audit_entries": state.incidents.len(),
+        "orphans": orphans,
+    }))
+    .into_response()
+}
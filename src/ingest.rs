@@ -1,21 +1,28 @@
+use crate::model_policy::{EncryptedHandling, ExtractorUnavailableHandling, GarbledTextHandling};
+use crate::secrets::Secret;
+use crate::slow_requests::Stage;
+use crate::url_allowlist::UrlAllowlistConfig;
 use crate::{
-    extract, html_scan, introspection, normalize, reputation, reputation_policy, routes, sentry,
-    state, threat, xml_scan,
+    acip_headers, b64, cache_bypass, chunking, content_types, decode_scan, disconnect, experiments,
+    extract, feeds, hashing, html_scan, incidents, introspection, jobs, loop_guard, multipart,
+    normalize, notify, reasons, reputation, reputation_policy, routes, sentry, siem, slow_poll,
+    slow_requests, state, stats, telemetry, text_quality, threat, token_auth, token_budget,
+    verdicts, xml_scan,
 };
+use async_trait::async_trait;
 use axum::{
-    extract::State,
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
-    Json,
+    body::Bytes,
+    extract::{FromRequest, Query, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
-use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::sync::Arc;
-use tracing::error;
+use tracing::{error, Instrument};
 use url::Url;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum SourceType {
     Html,
@@ -26,6 +33,17 @@ pub enum SourceType {
     Other,
 }
 
+impl SourceType {
+    pub const ALL: [SourceType; 6] = [
+        SourceType::Html,
+        SourceType::Pdf,
+        SourceType::Tweet,
+        SourceType::File,
+        SourceType::Clipboard,
+        SourceType::Other,
+    ];
+}
+
 #[derive(Deserialize, Debug)]
 pub struct IngestRequest {
     pub source_id: String,
@@ -43,6 +61,113 @@ pub struct IngestRequest {
     pub text: Option<String>,
     #[serde(default)]
     pub bytes_b64: Option<String>,
+
+    /// Password for an encrypted document, tried by the extractor. Never logged, stored or
+    /// hashed; see [`Secret`].
+    #[serde(default)]
+    pub document_password: Option<Secret>,
+
+    /// With `mode=async`, URL the finished job is POSTed to (see [`crate::jobs`]); otherwise a
+    /// per-request decision callback (see [`crate::notify`]).
+    #[serde(default)]
+    pub callback_url: Option<String>,
+}
+
+/// How `ingest_source` runs the pipeline.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestMode {
+    /// Run it now and answer with the decision.
+    #[default]
+    Sync,
+    /// Queue it as a job and answer `202` with the job id.
+    Async,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct IngestQuery {
+    #[serde(default)]
+    pub mode: IngestMode,
+}
+
+/// An `ingest_source` body, negotiated on its Content-Type: `multipart/form-data` parts, or
+/// JSON for anything else (rejected exactly as a plain `Json` body would be).
+pub enum IngestBody {
+    Json(IngestRequest),
+    Form(Vec<multipart::Part>),
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for IngestBody {
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Response> {
+        let content_type = req
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if !multipart::is_form_data(&content_type) {
+            let Json(req) = Json::<IngestRequest>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Self::Json(req));
+        }
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        multipart::parse(&content_type, &body)
+            .map(Self::Form)
+            .map_err(|e| {
+                introspection::json_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_multipart",
+                    serde_json::json!({"reason": e.to_string()}),
+                )
+                .into_response()
+            })
+    }
+}
+
+/// The `metadata` part of a form `ingest_source`: the JSON request without `text` and
+/// `bytes_b64`. Left out, `source_type` and `content_type` come from the `file` part.
+#[derive(Deserialize, Debug)]
+struct FormMetadata {
+    source_id: String,
+    #[serde(default)]
+    source_type: Option<SourceType>,
+    #[serde(default)]
+    content_type: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    turn_id: Option<String>,
+    #[serde(default)]
+    document_password: Option<Secret>,
+    #[serde(default)]
+    callback_url: Option<String>,
+}
+
+/// Source metadata shared by `ingest_source` and resumable upload sessions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SourceMeta {
+    pub source_id: String,
+    pub source_type: SourceType,
+    pub content_type: String,
+
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub turn_id: Option<String>,
+
+    /// `ingest_source` only; never serialized.
+    #[serde(skip)]
+    pub document_password: Option<Secret>,
 }
 
 #[derive(Serialize, Debug)]
@@ -51,7 +176,7 @@ pub struct DigestInfo {
     pub length: usize,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct PolicyInfo {
     pub head: usize,
     pub tail: usize,
@@ -78,10 +203,44 @@ pub struct IngestResponse {
     /// Threat summary safe for callers (non-oracle).
     pub threat: threat::ThreatAssessment,
 
+    /// How usable the model-facing text looks (OCR noise, mojibake, one char per line).
+    pub text_quality: text_quality::TextQuality,
+
     /// Detailed threat indicators (operator/audit only).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub threat_audit: Option<threat::ThreatAssessment>,
 
+    /// Repairs applied to the model verdict before it validated (operator/audit only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verdict_repairs: Option<Vec<sentry::Repair>>,
+
+    /// Estimated vs. allowed prompt tokens of each model call (operator/audit only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt_budgets: Option<Vec<token_budget::PromptBudget>>,
+
+    /// Name of the token the request was made with (operator/audit only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+
+    /// Models, model version and pattern pack behind a live verdict.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<verdicts::Provenance>,
+
+    /// Confidence in a live verdict, when the deciding model tier gave a signal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<sentry::Confidence>,
+
+    /// `X-ACIP-Bypass-Cache` mode the run was made with (see [`cache_bypass`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_bypass: Option<cache_bypass::BypassMode>,
+
+    /// Earlier revision whose verdict covered the unchanged chunks (see [`chunking`]).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunk_reuse: Option<chunking::ChunkReuse>,
+
+    /// Loop-protection marker for this response, and what was detected in the input.
+    pub origin: loop_guard::Origin,
+
     pub tools_allowed: bool,
     pub risk_level: sentry::RiskLevel,
     pub action: sentry::Action,
@@ -91,6 +250,261 @@ pub struct IngestResponse {
     pub detected_patterns: Vec<String>,
 }
 
+/// Feed a final decision into the tuning stats.
+#[allow(clippy::too_many_arguments)]
+async fn record_decision_stats(
+    state: &Arc<state::AppState>,
+    tenant: &str,
+    policy_name: &str,
+    source_type: &SourceType,
+    threat: &threat::ThreatAssessment,
+    quality: text_quality::QualityBucket,
+    d: &sentry::Decision,
+    escalated: bool,
+) {
+    let mut patterns = threat.indicators.clone();
+    patterns.extend(d.detected_patterns.iter().cloned());
+    let sample = stats::DecisionSample {
+        tenant: tenant.to_string(),
+        policy: policy_name.to_string(),
+        source_type: format!("{source_type:?}").to_lowercase(),
+        action: d.action.clone(),
+        risk_level: d.risk_level.clone(),
+        patterns,
+        escalated,
+        severity: threat.threat_score,
+        quality: Some(quality),
+        confidence: d.confidence.map(|c| c.bucket),
+    };
+    let st = state.clone();
+    state.blocking.run(move || st.stats.record(&sample)).await;
+}
+
+/// Feed a model verdict's parse attempts and second opinion into the tuning stats.
+async fn record_verdict_stats(state: &Arc<state::AppState>, verdict: &sentry::SentryVerdict) {
+    let attempts = verdict.attempts.clone();
+    let second = verdict.second_opinion.clone();
+    let st = state.clone();
+    state
+        .blocking
+        .run(move || {
+            st.stats.record_parse_attempts(&attempts);
+            if let Some(second) = &second {
+                st.stats.record_second_opinion(second);
+            }
+        })
+        .await;
+}
+
+/// Remember a model decision for staleness checks (see [`verdicts`]).
+async fn observe_verdict(
+    state: &Arc<state::AppState>,
+    policy_name: &str,
+    policy: &crate::model_policy::PolicyConfig,
+    digest: &str,
+    decision: &sentry::Decision,
+    model_version: Option<&str>,
+) {
+    let st = state.clone();
+    let (policy_name, policy, digest) =
+        (policy_name.to_string(), policy.clone(), digest.to_string());
+    let (decision, model_version) = (decision.clone(), model_version.map(str::to_string));
+    state
+        .blocking
+        .run(move || {
+            st.verdicts.observe(
+                &st.stats,
+                &policy_name,
+                &policy,
+                &digest,
+                &decision,
+                model_version.as_deref(),
+            );
+        })
+        .await;
+}
+
+/// Quality and threat scans of untrusted text, charged to `DecodeScan` and `FeedScan`. They run
+/// on blocking threads like the extractor: a multi-megabyte extraction takes seconds to scan.
+async fn scan_untrusted(
+    state: &Arc<state::AppState>,
+    text: String,
+    decode: decode_scan::DecodeBudget,
+    url_allowlist: UrlAllowlistConfig,
+    timing: &mut slow_requests::Timing,
+) -> (String, text_quality::TextQuality, threat::ThreatAssessment) {
+    let st = state.clone();
+    let (text, quality, mut threat) = off_runtime(move || {
+        let quality = text_quality::assess(&text);
+        let (mut threat, _) = decode_scan::assess_with_decoding(&text, &decode);
+        st.patterns.scan(&text, &mut threat);
+        (text, quality, threat)
+    })
+    .await;
+    timing.lap(Stage::DecodeScan);
+    let st = state.clone();
+    let (text, threat) = off_runtime(move || {
+        st.feeds.assess_urls(&text, &url_allowlist, &mut threat);
+        (text, threat)
+    })
+    .await;
+    timing.lap(Stage::FeedScan);
+    (text, quality, threat)
+}
+
+/// Active probes of the hosts `text` links to, when the policy opted in and the local scans
+/// escalated the document (see [`crate::probing`]). Charged to `LinkProbe`.
+async fn probe_links(
+    state: &Arc<state::AppState>,
+    policy_name: &str,
+    text: &str,
+    threat: &mut threat::ThreatAssessment,
+    timing: &mut slow_requests::Timing,
+    cancel: &disconnect::CancelToken,
+) {
+    let mode = std::env::var("ACIP_SENTRY_MODE").unwrap_or_default();
+    let heuristic_only = matches!(
+        mode.trim().to_ascii_lowercase().as_str(),
+        "stub" | "stub-open"
+    );
+    if !state.probes.applies(
+        state.policies.get(policy_name),
+        threat.threat_score,
+        heuristic_only,
+        state.read_only,
+    ) {
+        return;
+    }
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {}
+        _ = state.probes.probe_document(text, threat) => {}
+    }
+    timing.lap(Stage::LinkProbe);
+}
+
+/// `spawn_blocking` outside the [`blocking`](crate::blocking) pool, for CPU-bound work.
+async fn off_runtime<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(out) => out,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Put the origin banner on the fenced content and flag a looped input.
+fn stamp_origin(mut d: sentry::Decision, origin: &loop_guard::Origin) -> sentry::Decision {
+    if let Some(detected) = &origin.loop_detected {
+        d.reasons.push(detected.reason());
+    }
+    d.fenced_content = origin.banner(&d.fenced_content);
+    d
+}
+
+/// Answer `action` (`needs_review`, or `block` at high risk) for content that was never read,
+/// without asking a model: an unknown binary under `on_unknown_binary = "needs_review"`, an
+/// encrypted document the extractor could not open under `on_encrypted = "needs_review"`, or
+/// a document that needs the extractor while its breaker is open. `reason` says which.
+#[allow(clippy::too_many_arguments)]
+async fn unscanned_review(
+    state: &Arc<state::AppState>,
+    actor_name: &str,
+    policy_name: &str,
+    source_type: &SourceType,
+    digest: DigestInfo,
+    origin: loop_guard::Origin,
+    reason: String,
+    cache_bypass: Option<cache_bypass::BypassMode>,
+    action: sentry::Action,
+) -> Response {
+    let audit_mode = std::env::var("ACIP_AUDIT_MODE")
+        .map(|v| v.trim().eq("ENABLED"))
+        .unwrap_or(false);
+    let quality = text_quality::assess("");
+    let threat = threat::ThreatAssessment::none();
+    let mut d = sentry::Decision::fail_closed(fence_external(""), vec![reason]);
+    if action != sentry::Action::Block {
+        d.risk_level = sentry::RiskLevel::Medium;
+    }
+    d.action = action;
+    record_decision_stats(
+        state,
+        actor_name,
+        policy_name,
+        source_type,
+        &threat,
+        quality.bucket,
+        &d,
+        false,
+    )
+    .await;
+    let d = stamp_origin(d, &origin);
+
+    let resp = IngestResponse {
+        digest,
+        truncated: false,
+        policy: PolicyInfo {
+            head: state.policy.head,
+            tail: state.policy.tail,
+            full_if_lte: state.policy.full_if_lte,
+        },
+        original_length_chars: 0,
+        model_length_chars: 0,
+        normalized: false,
+        normalization_steps: vec![],
+        threat,
+        text_quality: quality,
+        threat_audit: None,
+        verdict_repairs: None,
+        prompt_budgets: None,
+        actor: audit_mode.then(|| actor_name.to_string()),
+        provenance: None,
+        confidence: None,
+        cache_bypass,
+        chunk_reuse: None,
+        origin,
+        tools_allowed: d.tools_allowed,
+        risk_level: d.risk_level,
+        action: d.action,
+        fenced_content: d.fenced_content,
+        reasons: d.reasons,
+        detected_patterns: d.detected_patterns,
+    };
+    (StatusCode::OK, Json(resp)).into_response()
+}
+
+/// A fresh verdict on an earlier revision of a large input (see [`chunking`]).
+struct PartialReuse {
+    overlap: chunking::Overlap,
+    prior: verdicts::VerdictRecord,
+    report: chunking::ChunkReuse,
+}
+
+/// The kept input of `tenant` that `chunks` mostly repeat, if its verdict is still fresh.
+fn partial_reuse(
+    state: &state::AppState,
+    tenant: &str,
+    policy_name: &str,
+    sha: &str,
+    chunks: &[chunking::Chunk],
+) -> Option<PartialReuse> {
+    let policy = state.policies.get(policy_name)?;
+    let overlap = state.chunks.best_match(tenant, policy_name, sha, chunks)?;
+    let prior = state
+        .verdicts
+        .reusable(policy_name, policy, &overlap.prior_sha)?;
+    let report = chunking::ChunkReuse {
+        prior_content_id: state.hashing.export_id(&overlap.prior_sha),
+        changed_fraction: overlap.changed_fraction(),
+        chunks: overlap.chunks,
+        chunks_reused: overlap.chunks_reused,
+    };
+    Some(PartialReuse {
+        overlap,
+        prior,
+        report,
+    })
+}
+
 fn fence_external(s: &str) -> String {
     format!("```external\n{}\n```", s)
 }
@@ -171,14 +585,6 @@ fn svg_to_text(svg: &str) -> String {
     parts.join("\n")
 }
 
-fn allow_tools_from_headers(headers: &HeaderMap) -> bool {
-    headers
-        .get("x-acip-allow-tools")
-        .and_then(|v| v.to_str().ok())
-        .map(|s| matches!(s.trim().to_lowercase().as_str(), "1" | "true" | "yes"))
-        .unwrap_or(false)
-}
-
 fn enforce_markup_tools_cap(mut decision: sentry::Decision, is_markup: bool) -> sentry::Decision {
     if is_markup && decision.tools_allowed {
         decision.tools_allowed = false;
@@ -202,6 +608,60 @@ fn enforce_tools_authorization(
     decision
 }
 
+/// Post-model enforcement (markup tool cap, caller authorization, reputation, garbled text).
+///
+/// Reasons from the model and from each stage are collected into one [`reasons::ReasonSet`] and
+/// rendered deduplicated, in stage order, so the array is identical across runs. Resolved
+/// `header_conflicts` are reported with the authorization stage.
+#[allow(clippy::too_many_arguments)]
+pub fn apply_decision_stages(
+    mut decision: sentry::Decision,
+    is_markup: bool,
+    allow_tools: bool,
+    header_conflicts: &[acip_headers::HeaderConflict],
+    recs: &[reputation::ReputationRecord],
+    rep_thresholds: &reputation_policy::ReputationThresholds,
+    quality: &text_quality::TextQuality,
+    on_garbled: GarbledTextHandling,
+) -> sentry::Decision {
+    let mut set = reasons::ReasonSet::default();
+    set.extend(reasons::ReasonStage::Sentry, decision.reasons.drain(..));
+    let decision = set.collect_stage(reasons::ReasonStage::Markup, decision, |d| {
+        enforce_markup_tools_cap(d, is_markup)
+    });
+    let decision = set.collect_stage(reasons::ReasonStage::Authorization, decision, |d| {
+        enforce_tools_authorization(d, allow_tools)
+    });
+    set.extend(
+        reasons::ReasonStage::Authorization,
+        header_conflicts
+            .iter()
+            .map(acip_headers::HeaderConflict::reason),
+    );
+    let decision = set.collect_stage(reasons::ReasonStage::Reputation, decision, |d| {
+        reputation_policy::apply_reputation(d, allow_tools, recs, rep_thresholds)
+    });
+    let mut decision = set.collect_stage(reasons::ReasonStage::TextQuality, decision, |d| {
+        text_quality::apply_garbled_handling(d, quality, on_garbled)
+    });
+    decision.reasons = set.render_limited(reasons::MAX_REASONS);
+    decision
+}
+
+/// L1 and L2 clients for `policy`, or [`state::AppState::model_override`] for both tiers.
+fn model_clients(
+    state: &state::AppState,
+    policy: &crate::model_policy::PolicyConfig,
+) -> (Box<dyn sentry::ModelClient>, Box<dyn sentry::ModelClient>) {
+    if let Some(m) = &state.model_override {
+        return (Box::new(m.clone()), Box::new(m.clone()));
+    }
+    let client = |p: &crate::model_policy::Provider| {
+        sentry::client_for(p, state.http.clone(), state.secrets.clone())
+    };
+    (client(&policy.l1.provider), client(&policy.l2.provider))
+}
+
 fn apply_head_tail(policy: &state::Policy, text: &str) -> (String, bool) {
     let len = text.chars().count();
     if len <= policy.full_if_lte {
@@ -255,27 +715,118 @@ fn maybe_window_markup_input(
 
 /// Main ingest endpoint.
 ///
-/// Note: the router wires this under `/v1/acip/ingest_source`.
+/// Note: the router wires this under `/v1/acip/ingest_source`. With `mode=async` the request is
+/// validated here and then queued; the pipeline runs later in a job worker.
 pub async fn ingest_source(
     State(state): State<Arc<state::AppState>>,
+    actor: Option<Extension<token_auth::Actor>>,
+    Query(query): Query<IngestQuery>,
     headers: HeaderMap,
-    Json(req): Json<IngestRequest>,
+    body: IngestBody,
 ) -> impl IntoResponse {
+    let actor_name = token_auth::actor_name(actor);
     // Multi-policy selection: validate policy selection early.
-    let policy_name = routes::policy_name_from_headers(&headers);
-    let allow_tools = allow_tools_from_headers(&headers);
+    let policy_name = routes::policy_name_from_headers(&headers, &state.header_rules);
+    if let Err(resp) = require_policy(&state, &policy_name) {
+        return resp;
+    }
 
-    if state.policies.require(&policy_name).is_err() {
-        let mut names = state.policies.list();
-        names.sort();
-        return introspection::json_error(
-            StatusCode::BAD_REQUEST,
-            "unknown policy",
-            serde_json::json!({"requested": policy_name, "available": names}),
-        )
-        .into_response();
+    let decoded = match body {
+        IngestBody::Json(req) => {
+            let callback_url = req.callback_url.clone();
+            decode_request(req).map(|decoded| (decoded, callback_url))
+        }
+        IngestBody::Form(parts) => decode_form(parts),
+    };
+    let ((meta, raw_text, input_bytes), callback_url) = match decoded {
+        Ok(decoded) => decoded,
+        Err(resp) => return resp,
+    };
+
+    match query.mode {
+        IngestMode::Async if headers.contains_key(notify::CALLBACK_HEADER) => {
+            introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_field",
+                serde_json::json!({
+                    "field": "X-ACIP-Callback-Url",
+                    "reason": "use callback_url with mode=async",
+                }),
+            )
+            .into_response()
+        }
+        IngestMode::Async => {
+            // Refuse now rather than when a worker picks the job up.
+            if let Err(refused) = state.content_types.screen(
+                &policy_name,
+                state.policies.get(&policy_name),
+                &meta.content_type,
+                &input_bytes,
+            ) {
+                return refused.into_response();
+            }
+            let input = jobs::JobInput {
+                meta,
+                headers,
+                raw_text,
+                bytes: input_bytes,
+            };
+            jobs::submit(&state, actor_name, input, callback_url)
+        }
+        IngestMode::Sync if callback_url.is_some() && !state.notify.callbacks_enabled() => {
+            introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_field",
+                serde_json::json!({"field": "callback_url", "reason": "requires mode=async"}),
+            )
+            .into_response()
+        }
+        IngestMode::Sync => {
+            let requested = notify::requested_callback(&headers, callback_url.as_deref());
+            let callback = requested.and_then(|url| {
+                url.map(|u| state.notify.accept_callback(&actor_name, &u))
+                    .transpose()
+            });
+            let links = match callback {
+                Ok(Some(url)) => incidents::Links::callback(url),
+                Ok(None) => incidents::Links::default(),
+                Err(refused) => return refused.into_response(),
+            };
+            // The run gets its own task so that it can still finish its side effects (per
+            // `server.on_client_disconnect`) after hyper drops this handler on a disconnect.
+            let cancel = disconnect::CancelToken::new();
+            let guard = cancel.drop_guard();
+            let run = tokio::spawn(ingest_cancellable(
+                state,
+                actor_name,
+                headers,
+                meta,
+                raw_text,
+                input_bytes,
+                slow_requests::Timing::start(),
+                links,
+                cancel,
+            ));
+            let resp = match run.await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    error!("ingest task failed: {e}");
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            };
+            guard.disarm();
+            resp
+        }
     }
+}
+
+/// Source metadata, the text view of the input (when it is UTF-8) and its raw bytes.
+pub type Decoded = (SourceMeta, Option<String>, Vec<u8>);
 
+/// Decode an `ingest_source` body into source metadata, its text view (when the input is UTF-8)
+/// and the raw bytes. Errors are the responses to send.
+#[allow(clippy::result_large_err)]
+pub fn decode_request(req: IngestRequest) -> Result<Decoded, Response> {
     let IngestRequest {
         source_id,
         source_type,
@@ -285,11 +836,10 @@ pub async fn ingest_source(
         turn_id,
         text,
         bytes_b64,
+        document_password,
+        callback_url: _,
     } = req;
 
-    // Basic DoS protection: cap base64 payload size before decoding.
-    const MAX_BYTES_B64_CHARS: usize = 1_500_000; // ~1.1MB decoded
-
     // We keep both a text view (when available) and raw bytes (for PDFs).
     let mut raw_text: Option<String> = None;
     let mut raw_bytes: Option<Vec<u8>> = None;
@@ -297,31 +847,532 @@ pub async fn ingest_source(
     if let Some(t) = text {
         raw_text = Some(t.clone());
         raw_bytes = Some(t.into_bytes());
-    } else if let Some(b64) = bytes_b64 {
-        if b64.len() > MAX_BYTES_B64_CHARS {
-            return (StatusCode::PAYLOAD_TOO_LARGE, "bytes_b64 too large").into_response();
-        }
-        match B64.decode(b64.as_bytes()) {
+    } else if let Some(encoded) = bytes_b64 {
+        // Basic DoS protection: the decoder stops at the size cap instead of allocating the
+        // full decoded buffer first.
+        match b64::decode_bounded(&encoded, b64::DEFAULT_MAX_DECODED_BYTES) {
             Ok(bytes) => {
                 raw_text = String::from_utf8(bytes.clone()).ok();
                 raw_bytes = Some(bytes);
             }
+            Err(e @ b64::B64Error::TooLarge { max_bytes }) => {
+                error!("base64 decode failed: {e}");
+                return Err(introspection::json_error(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    "bytes_b64 too large",
+                    serde_json::json!({"field": "bytes_b64", "max_bytes": max_bytes}),
+                )
+                .into_response());
+            }
             Err(e) => {
                 error!("base64 decode failed: {e}");
-                return (StatusCode::BAD_REQUEST, "invalid base64").into_response();
+                return Err(introspection::json_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request_field",
+                    serde_json::json!({
+                        "field": "bytes_b64",
+                        "reason": e.reason(),
+                        "offset": e.offset(),
+                    }),
+                )
+                .into_response());
             }
         }
     }
 
-    let Some(input_bytes) = raw_bytes.clone() else {
-        return (StatusCode::BAD_REQUEST, "must provide text or bytes_b64").into_response();
+    let Some(input_bytes) = raw_bytes else {
+        return Err((StatusCode::BAD_REQUEST, "must provide text or bytes_b64").into_response());
     };
 
+    let meta = SourceMeta {
+        source_id,
+        source_type,
+        content_type,
+        url,
+        title,
+        turn_id,
+        document_password,
+    };
+    Ok((meta, raw_text, input_bytes))
+}
+
+/// Decode a form `ingest_source` body (a `metadata` and a `file` part) like
+/// [`decode_request`], also returning its `callback_url`. The file's content type and
+/// filename stand in for a missing `content_type` and `title`; a missing `source_type`
+/// follows the content type (`pdf`, `html`, else `file`).
+#[allow(clippy::result_large_err)]
+pub fn decode_form(parts: Vec<multipart::Part>) -> Result<(Decoded, Option<String>), Response> {
+    let field_error = |field: &str, reason: &str| {
+        introspection::json_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_field",
+            serde_json::json!({"field": field, "reason": reason}),
+        )
+        .into_response()
+    };
+    let (mut metadata, mut file) = (None, None);
+    for part in parts {
+        let slot = match part.name.as_str() {
+            "metadata" => &mut metadata,
+            "file" => &mut file,
+            other => return Err(field_error(other, "unexpected part")),
+        };
+        if slot.is_some() {
+            return Err(field_error(&part.name, "repeated part"));
+        }
+        *slot = Some(part);
+    }
+    let Some(metadata) = metadata else {
+        return Err(field_error("metadata", "missing part"));
+    };
+    let Some(file) = file else {
+        return Err(field_error("file", "missing part"));
+    };
+
+    let metadata: serde_json::Value = serde_json::from_slice(&metadata.body)
+        .map_err(|e| field_error("metadata", &format!("invalid JSON: {e}")))?;
+    for content_field in ["text", "bytes_b64"] {
+        if metadata.get(content_field).is_some() {
+            return Err(field_error(
+                content_field,
+                "send the content as the file part",
+            ));
+        }
+    }
+    let metadata: FormMetadata =
+        serde_json::from_value(metadata).map_err(|e| field_error("metadata", &e.to_string()))?;
+
+    // The same cap as a decoded `bytes_b64`.
+    if file.body.len() > b64::DEFAULT_MAX_DECODED_BYTES {
+        return Err(introspection::json_error(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "file too large",
+            serde_json::json!({"field": "file", "max_bytes": b64::DEFAULT_MAX_DECODED_BYTES}),
+        )
+        .into_response());
+    }
+    let content_type = metadata
+        .content_type
+        .or(file.content_type)
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let source_type = metadata.source_type.unwrap_or_else(|| {
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/pdf" => SourceType::Pdf,
+            "text/html" | "application/xhtml+xml" => SourceType::Html,
+            _ => SourceType::File,
+        }
+    });
+    let meta = SourceMeta {
+        source_id: metadata.source_id,
+        source_type,
+        content_type,
+        url: metadata.url,
+        title: metadata.title.or(file.filename),
+        turn_id: metadata.turn_id,
+        document_password: metadata.document_password,
+    };
+    let raw_text = String::from_utf8(file.body.clone()).ok();
+    Ok(((meta, raw_text, file.body), metadata.callback_url))
+}
+
+/// The `400 unknown policy` response when `policy_name` is not loaded.
+#[allow(clippy::result_large_err)]
+pub fn require_policy(state: &state::AppState, policy_name: &str) -> Result<(), Response> {
+    if state.policies.require(policy_name).is_ok() {
+        return Ok(());
+    }
+    let mut names = state.policies.list();
+    names.sort();
+    Err(introspection::json_error(
+        StatusCode::BAD_REQUEST,
+        "unknown policy",
+        serde_json::json!({"requested": policy_name, "available": names}),
+    )
+    .into_response())
+}
+
+/// Run the ingest pipeline on already-decoded input.
+///
+/// `ingest_source` calls this after decoding the request body; completed resumable uploads
+/// call it with the assembled file and the metadata given at session creation, and async jobs
+/// when a worker picks them up. Policy and tool selection come from `headers` exactly as on
+/// `ingest_source`.
+pub async fn ingest_decoded(
+    state: Arc<state::AppState>,
+    actor_name: String,
+    headers: HeaderMap,
+    meta: SourceMeta,
+    raw_text: Option<String>,
+    input_bytes: Vec<u8>,
+) -> Response {
+    let timing = slow_requests::Timing::start();
+    ingest_timed(
+        state,
+        actor_name,
+        headers,
+        meta,
+        raw_text,
+        input_bytes,
+        timing,
+        incidents::Links::default(),
+    )
+    .await
+}
+
+/// [`ingest_decoded`] continuing `timing` (async jobs start it with their queue wait). The run
+/// is kept in the slow request log when it qualifies; see [`slow_requests`]. Its audit entry
+/// references `links` (see [`incidents`]).
+#[allow(clippy::too_many_arguments)]
+pub async fn ingest_timed(
+    state: Arc<state::AppState>,
+    actor_name: String,
+    headers: HeaderMap,
+    meta: SourceMeta,
+    raw_text: Option<String>,
+    input_bytes: Vec<u8>,
+    timing: slow_requests::Timing,
+    links: incidents::Links,
+) -> Response {
+    ingest_cancellable(
+        state,
+        actor_name,
+        headers,
+        meta,
+        raw_text,
+        input_bytes,
+        timing,
+        links,
+        disconnect::CancelToken::default(),
+    )
+    .await
+}
+
+/// [`ingest_timed`] stopping at its next stage boundary once `cancel` is cancelled; see
+/// [`disconnect`].
+#[allow(clippy::too_many_arguments)]
+pub async fn ingest_cancellable(
+    state: Arc<state::AppState>,
+    actor_name: String,
+    headers: HeaderMap,
+    meta: SourceMeta,
+    raw_text: Option<String>,
+    input_bytes: Vec<u8>,
+    timing: slow_requests::Timing,
+    links: incidents::Links,
+    cancel: disconnect::CancelToken,
+) -> Response {
+    let run = ingest_run(
+        state,
+        actor_name,
+        headers,
+        meta,
+        raw_text,
+        input_bytes,
+        timing,
+        links,
+        cancel,
+    );
+    slow_poll::watch("ingest", run).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn ingest_run(
+    state: Arc<state::AppState>,
+    actor_name: String,
+    headers: HeaderMap,
+    meta: SourceMeta,
+    raw_text: Option<String>,
+    input_bytes: Vec<u8>,
+    mut timing: slow_requests::Timing,
+    mut links: incidents::Links,
+    cancel: disconnect::CancelToken,
+) -> Response {
+    let callback = links.callback_url.take();
+    let policy_name = routes::policy_name_from_headers(&headers, &state.header_rules);
+    let source_type = format!("{:?}", meta.source_type).to_lowercase();
+    let source_id = meta.source_id.clone();
+    let input_len = input_bytes.len();
+    let forced = slow_requests::forced(&headers);
+    if let Some(mode) = cache_bypass::mode(&headers) {
+        tracing::info!(mode = mode.as_str(), actor = %actor_name, "cache bypass");
+        state.verdicts.bypasses().record(mode, &actor_name);
+    }
+    let subject = state.siem.is_enabled().then(|| siem::Subject {
+        actor: actor_name.clone(),
+        policy: policy_name.clone(),
+        source_id: meta.source_id.clone(),
+        source_type: source_type.clone(),
+        url: meta.url.clone(),
+        title: meta.title.clone(),
+    });
+
+    timing.trace = telemetry::RequestTrace::from_headers(&headers);
+    let span = tracing::info_span!("ingest", trace_id = %timing.trace.context.trace_id);
+
+    let mut trace = incidents::Trace::default();
+    let resp = run_pipeline(
+        state.clone(),
+        actor_name.clone(),
+        headers,
+        meta,
+        raw_text,
+        input_bytes,
+        &mut timing,
+        &mut trace,
+        &cancel,
+    )
+    .instrument(span)
+    .await;
+    let resp = state
+        .remediation
+        .annotate_response(state.policies.get(&policy_name), &trace.indicators, resp)
+        .await;
+    if let (Some(a), None) = (&trace.experiment, trace.disconnected_at) {
+        let ok = resp.status().is_success();
+        state.experiments.observe(a, trace.decided.as_ref(), ok);
+    }
+    // `abandon`: a disconnected run writes no audit entry, SIEM event or notification.
+    let abandoned = trace.disconnected_at.is_some()
+        && state.disconnects.mode() == disconnect::OnClientDisconnect::Abandon;
+    let resp = match timing.request_id.clone() {
+        Some(_) if abandoned => resp,
+        Some(request_id) => {
+            let refs = incidents::RunRefs {
+                request_id: &request_id,
+                actor: &actor_name,
+                policy: &policy_name,
+                source_id: &source_id,
+                source_type: &source_type,
+                links,
+                trace,
+            };
+            state
+                .incidents
+                .record_response(refs, &state.hashing, resp)
+                .await
+        }
+        None => resp,
+    };
+    let resp = match subject {
+        Some(_) if abandoned => resp,
+        Some(subject) => {
+            state
+                .siem
+                .export_response(&state.redaction, &state.hashing, subject, resp)
+                .await
+        }
+        None => resp,
+    };
+    let resp = if abandoned {
+        resp
+    } else {
+        state
+            .notify
+            .notify_response(&state.redaction, &state.hashing, &source_id, callback, resp)
+            .await
+    };
+    let resp = state.risk_headers.attach(resp).await;
+    timing.lap(Stage::Serialize);
+    let http_status = resp.status();
+    let st = state.clone();
+    state
+        .blocking
+        .run(move || {
+            let run = slow_requests::RunInfo {
+                actor: &actor_name,
+                policy: &policy_name,
+                source_type: &source_type,
+                http_status,
+                input_bytes: input_len,
+                forced,
+            };
+            st.telemetry.record_ingest(&timing, &run);
+            st.slow_requests.finish(timing, run);
+        })
+        .await;
+    resp
+}
+
+/// Whose reputation a run updates.
+struct ReputationSubject {
+    request_id: String,
+    source_id: String,
+    host: Option<String>,
+}
+
+impl ReputationSubject {
+    /// Record the run's observation (`threat: None` is a sighting with nothing scanned yet),
+    /// seed feed entries and note the reputation events in `trace`.
+    async fn record(
+        &self,
+        state: &Arc<state::AppState>,
+        trace: &mut incidents::Trace,
+        threat: Option<&threat::ThreatAssessment>,
+    ) -> Vec<reputation::ReputationRecord> {
+        let (threat_score, attack_types) = threat
+            .map(|t| {
+                let types = t.attack_types.iter().map(|a| format!("{a:?}")).collect();
+                (t.threat_score, types)
+            })
+            .unwrap_or_default();
+        let obs = reputation::observation(
+            self.source_id.clone(),
+            self.host.clone(),
+            threat_score,
+            attack_types,
+        );
+        let st = state.clone();
+        let mut recs = state.blocking.run(move || st.reputation.record(obs)).await;
+        state.feeds.apply_seeds(&mut recs);
+        trace.reputation = incidents::reputation_events(
+            &self.request_id,
+            threat_score,
+            &recs,
+            &state.reputation_thresholds,
+        );
+        recs
+    }
+}
+
+/// End a run whose client disconnected, noticed at `stage`. In `complete_side_effects` mode a
+/// reputation update the run had not made yet (`unrecorded`) is made from what it had gathered.
+async fn disconnected(
+    state: &Arc<state::AppState>,
+    trace: &mut incidents::Trace,
+    stage: Stage,
+    unrecorded: Option<(&ReputationSubject, Option<&threat::ThreatAssessment>)>,
+) -> Response {
+    state.disconnects.record(stage);
+    trace.disconnected_at = Some(stage);
+    if state.disconnects.mode() == disconnect::OnClientDisconnect::CompleteSideEffects {
+        if let Some((subject, threat)) = unrecorded {
+            subject.record(state, trace, threat).await;
+        }
+    }
+    tracing::info!(stage = stage.as_str(), "client disconnected; run stopped");
+    introspection::json_error(
+        StatusCode::from_u16(disconnect::STATUS).unwrap(),
+        disconnect::ERROR_CODE,
+        serde_json::json!({"stage": stage}),
+    )
+    .into_response()
+}
+
+/// The pipeline itself, charging its stages to `timing`.
+#[allow(clippy::too_many_arguments)]
+async fn run_pipeline(
+    state: Arc<state::AppState>,
+    actor_name: String,
+    headers: HeaderMap,
+    meta: SourceMeta,
+    raw_text: Option<String>,
+    input_bytes: Vec<u8>,
+    timing: &mut slow_requests::Timing,
+    trace: &mut incidents::Trace,
+    cancel: &disconnect::CancelToken,
+) -> Response {
+    let policy_name = routes::policy_name_from_headers(&headers, &state.header_rules);
+    let allow_tools = acip_headers::allow_tools(&headers);
+    // Repeated ACIP headers the router resolved under `use_strictest`; flagged in the reasons.
+    let header_conflicts = state.header_rules.check(&headers).unwrap_or_default();
+    trace.cache_bypass = cache_bypass::mode(&headers);
+
+    let on_garbled = state
+        .policies
+        .get(&policy_name)
+        .map(|p| p.on_garbled_text)
+        .unwrap_or_default();
+    let url_allowlist = state
+        .policies
+        .get(&policy_name)
+        .map(|p| p.url_allowlist.clone())
+        .unwrap_or_default();
+
+    let SourceMeta {
+        source_id,
+        source_type,
+        content_type,
+        url,
+        title,
+        turn_id,
+        document_password,
+    } = meta;
+
     let raw = raw_text.unwrap_or_default();
 
-    let mut hasher = Sha256::new();
-    hasher.update(&input_bytes);
-    let sha = hex::encode(hasher.finalize());
+    // Loop protection: a cheap prefix search over the input, before any scanning.
+    let origin = match state.loop_guard.inspect(&input_bytes) {
+        Ok(origin) => origin,
+        Err(detected) => {
+            return introspection::json_error(
+                StatusCode::CONFLICT,
+                "self_ingestion_detected",
+                serde_json::json!(detected),
+            )
+            .into_response();
+        }
+    };
+
+    // Unsalted: the last-decision map key, and `digest` to the caller that sent the content.
+    let sha = hashing::internal_only_digest(&input_bytes);
+    timing.request_id = Some(origin.request_id.clone());
+    let reputation_subject = ReputationSubject {
+        request_id: origin.request_id.clone(),
+        source_id: source_id.clone(),
+        host: url.as_deref().and_then(host_from_url),
+    };
+
+    match state.content_types.screen(
+        &policy_name,
+        state.policies.get(&policy_name),
+        &content_type,
+        &input_bytes,
+    ) {
+        Ok(content_types::Screening::Accepted) => {}
+        Ok(content_types::Screening::NeedsReview(unknown)) => {
+            let digest = DigestInfo {
+                sha256: sha,
+                length: input_bytes.len(),
+            };
+            let reason = format!(
+                "unknown_binary: declared {} but no recognised format and mostly non-text bytes",
+                unknown.declared
+            );
+            return unscanned_review(
+                &state,
+                &actor_name,
+                &policy_name,
+                &source_type,
+                digest,
+                origin,
+                reason,
+                trace.cache_bypass,
+                sentry::Action::NeedsReview,
+            )
+            .await;
+        }
+        Err(refused) => return refused.into_response(),
+    }
+    // Large inputs: chunk hashes, so a later revision can reuse this one's verdict.
+    let (input_bytes, chunks) = if state.chunks.applies(input_bytes.len()) {
+        let st = state.clone();
+        state
+            .blocking
+            .run(move || {
+                let chunks = st.chunks.chunk(&input_bytes);
+                (input_bytes, chunks)
+            })
+            .await
+    } else {
+        (input_bytes, vec![])
+    };
+    if !chunks.is_empty() && trace.cache_bypass != Some(cache_bypass::BypassMode::Ghost) {
+        state
+            .chunks
+            .record(&actor_name, &policy_name, &sha, &chunks);
+    }
+    timing.lap(Stage::Prepare);
 
     let ct_lower = content_type.to_lowercase();
     let is_pdf = ct_lower.contains("application/pdf") || matches!(source_type, SourceType::Pdf);
@@ -335,12 +1386,48 @@ pub async fn ingest_source(
             extract::ExtractKind::Svg
         };
 
+        // The helper keeps failing: answer now instead of running it again.
+        if !state.extractor_health.is_available() {
+            let last = state.extractor_health.snapshot().last_failure;
+            let action = match state
+                .policies
+                .get(&policy_name)
+                .map(|p| p.on_extractor_unavailable)
+                .unwrap_or_default()
+            {
+                ExtractorUnavailableHandling::NeedsReview => sentry::Action::NeedsReview,
+                ExtractorUnavailableHandling::Block => sentry::Action::Block,
+            };
+            let digest = DigestInfo {
+                sha256: sha,
+                length: raw.len(),
+            };
+            return unscanned_review(
+                &state,
+                &actor_name,
+                &policy_name,
+                &source_type,
+                digest,
+                origin,
+                format!(
+                    "helper_unavailable: extraction_failed ({}): the extractor helper is \
+                     failing; the document was not read",
+                    last.unwrap_or("unknown")
+                ),
+                trace.cache_bypass,
+                action,
+            )
+            .await;
+        }
+
         let req = extract::ExtractRequest {
             kind: kind.clone(),
             content_type: Some(content_type.clone()),
             max_pages: Some(100),
             dpi: Some(250),
             max_output_chars: Some(2_000_000),
+            force_ocr: false,
+            password: document_password.filter(|_| is_pdf),
         };
 
         // Run helper in a blocking task with a generous timeout.
@@ -348,15 +1435,89 @@ pub async fn ingest_source(
         let extractor_timeout_secs: u64 = std::env::var("ACIP_EXTRACTOR_TIMEOUT_SECS")
             .ok()
             .and_then(|v| v.trim().parse::<u64>().ok())
-            .unwrap_or(180);
+            .unwrap_or(extract::DEFAULT_TIMEOUT_SECS);
         let extractor_timeout = std::time::Duration::from_secs(extractor_timeout_secs);
 
+        // Refuse up front rather than fail midway through a large extraction.
+        let tmp = state.tmp.clone();
+        if let Err(e) = state.blocking.run(move || tmp.check_capacity()).await {
+            return introspection::json_error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "storage_exhausted",
+                serde_json::json!({
+                    "free_bytes": e.free_bytes,
+                    "min_free_bytes": e.min_free_bytes,
+                }),
+            )
+            .into_response();
+        }
+
+        // A garbled PDF text layer gets one more pass with OCR forced, when the policy asks.
+        let ocr_retry = is_pdf && on_garbled == GarbledTextHandling::OcrRetry;
+        let overall_timeout = if ocr_retry {
+            extractor_timeout * 2
+        } else {
+            extractor_timeout
+        };
+
+        let tmp = state.tmp.clone();
+        let helper_cancel = cancel.clone();
         let join = tokio::task::spawn_blocking(move || {
-            extract::run_helper(&req, &input_bytes, extractor_timeout)
+            let run = |req: &extract::ExtractRequest| {
+                extract::run_helper_cancellable(
+                    &tmp,
+                    req,
+                    &input_bytes,
+                    extractor_timeout,
+                    &helper_cancel,
+                )
+            };
+            let first = run(&req)?;
+            if !ocr_retry
+                || first.stats.ocr_used
+                || text_quality::assess(&first.text).bucket != text_quality::QualityBucket::Garbled
+            {
+                return Ok(first);
+            }
+            let retry = extract::ExtractRequest {
+                force_ocr: true,
+                ..req
+            };
+            match run(&retry) {
+                Ok(mut r) => {
+                    r.warnings.push("ocr_retry_garbled_text_layer".to_string());
+                    Ok(r)
+                }
+                Err(extract::ExtractorError::Cancelled) => Err(extract::ExtractorError::Cancelled),
+                Err(e) => {
+                    error!("OCR retry of garbled PDF text failed: {e}");
+                    let mut r = first;
+                    r.warnings.push("ocr_retry_failed".to_string());
+                    Ok(r)
+                }
+            }
         });
 
-        let resp = match tokio::time::timeout(extractor_timeout, join).await {
-            Ok(Ok(Ok(r))) => r,
+        let result = tokio::time::timeout(overall_timeout, join).await;
+        if let Ok(Ok(r)) = &result {
+            state.extractor_health.record(r, "ingest");
+        }
+        let resp = match result {
+            Ok(Ok(Ok(r))) => {
+                if !chunks.is_empty() {
+                    state.chunks.account(chunks.len(), 0);
+                }
+                r
+            }
+            Ok(Ok(Err(extract::ExtractorError::Cancelled))) => {
+                return disconnected(
+                    &state,
+                    trace,
+                    Stage::Extract,
+                    Some((&reputation_subject, None)),
+                )
+                .await;
+            }
             Ok(Ok(Err(e))) => {
                 return match e {
                     extract::ExtractorError::Timeout => (
@@ -366,7 +1527,7 @@ pub async fn ingest_source(
                         .into_response(),
                     _ => (
                         StatusCode::BAD_REQUEST,
-                        format!("extract_failed ({kind:?}): {e}"),
+                        format!("extract_failed ({kind:?}, {}): {e}", e.kind()),
                     )
                         .into_response(),
                 };
@@ -387,6 +1548,46 @@ pub async fn ingest_source(
             }
         };
 
+        timing.lap(Stage::Extract);
+
+        if resp.outcome != extract::ExtractOutcome::Extracted {
+            let policy = state.policies.get(&policy_name);
+            let on_encrypted = policy.map(|p| p.on_encrypted).unwrap_or_default();
+            if on_encrypted == EncryptedHandling::Reject {
+                return content_types::Unsupported {
+                    layer: content_types::Layer::Policy,
+                    reason: resp.outcome.as_str(),
+                    declared: content_types::essence(&content_type),
+                    sniffed: Some("application/pdf".to_string()),
+                    policy: policy_name.clone(),
+                    accepted: vec![],
+                }
+                .into_response();
+            }
+            let reason = match resp.outcome {
+                extract::ExtractOutcome::DecryptionFailed => {
+                    "decryption_failed: document_password does not open the encrypted document"
+                }
+                _ => "encrypted_unreadable: encrypted document and no document_password",
+            };
+            let digest = DigestInfo {
+                sha256: sha,
+                length: raw.len(),
+            };
+            return unscanned_review(
+                &state,
+                &actor_name,
+                &policy_name,
+                &source_type,
+                digest,
+                origin,
+                reason.to_string(),
+                trace.cache_bypass,
+                sentry::Action::NeedsReview,
+            )
+            .await;
+        }
+
         // Treat extracted text as untrusted.
         let model_text = resp.text;
         let normalized = true;
@@ -395,8 +1596,10 @@ pub async fn ingest_source(
 
         let original_length_chars = raw.chars().count();
         let model_length_chars = model_text.chars().count();
-
-        let mut threat_full = threat::assess(&model_text);
+        timing.extracted_chars = Some(model_length_chars);
+        let decode = state.normalize.decode.clone();
+        let (model_text, quality, mut threat_full) =
+            scan_untrusted(&state, model_text, decode, url_allowlist, timing).await;
         for step in normalization_steps.iter() {
             if step.starts_with("extract:") {
                 threat_full
@@ -404,6 +1607,25 @@ pub async fn ingest_source(
                     .push(step.replace("extract:", "extract_").to_string());
             }
         }
+        if threat_full
+            .indicators
+            .iter()
+            .any(|i| i == threat::ENCRYPTED_INDICATOR)
+        {
+            threat_full.threat_score = threat_full
+                .threat_score
+                .saturating_add(threat::ENCRYPTED_SCORE);
+        }
+        probe_links(
+            &state,
+            &policy_name,
+            &model_text,
+            &mut threat_full,
+            timing,
+            cancel,
+        )
+        .await;
+        trace.indicators = threat_full.indicators.clone();
 
         let audit_mode = std::env::var("ACIP_AUDIT_MODE")
             .map(|v| v.trim().eq("ENABLED"))
@@ -412,6 +1634,8 @@ pub async fn ingest_source(
         let mut threat = threat_full.clone();
         if !audit_mode {
             threat.indicators.clear();
+            threat.detected.clear();
+            threat.urls.clear();
         }
         let threat_audit = if audit_mode {
             Some(threat_full.clone())
@@ -420,19 +1644,16 @@ pub async fn ingest_source(
         };
 
         // Update reputation store.
-        let host = url.as_deref().and_then(host_from_url);
-        let recs = state.reputation.record(reputation::observation(
-            source_id.clone(),
-            host,
-            threat.threat_score,
-            threat
-                .attack_types
-                .iter()
-                .map(|t| format!("{:?}", t))
-                .collect(),
-        ));
-
-        let rep_thresholds = reputation_policy::ReputationThresholds::from_env();
+        if cancel.is_cancelled() {
+            let unrecorded = (&reputation_subject, Some(&threat));
+            return disconnected(&state, trace, Stage::Reputation, Some(unrecorded)).await;
+        }
+        let recs = reputation_subject
+            .record(&state, trace, Some(&threat))
+            .await;
+        timing.lap(Stage::Reputation);
+
+        let rep_thresholds = state.reputation_thresholds.clone();
         let (trunc_text, truncated) = apply_head_tail(&state.policy, &model_text);
 
         // Continue with the shared decision path.
@@ -445,12 +1666,34 @@ pub async fn ingest_source(
                 fence_external(&trunc_text),
                 vec!["sentry disabled (ACIP_SENTRY_MODE=stub)".to_string()],
             );
-            d = enforce_markup_tools_cap(d, is_markup);
-            d = enforce_tools_authorization(d, allow_tools);
-            d = reputation_policy::apply_reputation(d, allow_tools, &recs, &rep_thresholds);
+            d = apply_decision_stages(
+                d,
+                is_markup,
+                allow_tools,
+                &header_conflicts,
+                &recs,
+                &rep_thresholds,
+                &quality,
+                on_garbled,
+            );
             d.risk_level = sentry::RiskLevel::Medium;
             d.action = sentry::Action::Allow;
 
+            record_decision_stats(
+                &state,
+                &actor_name,
+                &policy_name,
+                &source_type,
+                &threat_full,
+                quality.bucket,
+                &d,
+                false,
+            )
+            .await;
+
+            let d = stamp_origin(d, &origin);
+            timing.lap(Stage::Decide);
+
             let resp = IngestResponse {
                 digest: DigestInfo {
                     sha256: sha,
@@ -467,7 +1710,16 @@ pub async fn ingest_source(
                 normalized,
                 normalization_steps,
                 threat,
+                text_quality: quality,
                 threat_audit,
+                verdict_repairs: None,
+                prompt_budgets: None,
+                actor: audit_mode.then(|| actor_name.clone()),
+                provenance: None,
+                confidence: None,
+                cache_bypass: trace.cache_bypass,
+                chunk_reuse: None,
+                origin,
                 tools_allowed: d.tools_allowed,
                 risk_level: d.risk_level,
                 action: d.action,
@@ -487,10 +1739,33 @@ pub async fn ingest_source(
                 fenced_content: fence_external(&trunc_text),
                 reasons: vec!["sentry disabled (ACIP_SENTRY_MODE=stub-open)".to_string()],
                 detected_patterns: vec![],
+                confidence: None,
             };
-            d = enforce_markup_tools_cap(d, is_markup);
-            d = enforce_tools_authorization(d, allow_tools);
-            d = reputation_policy::apply_reputation(d, allow_tools, &recs, &rep_thresholds);
+            d = apply_decision_stages(
+                d,
+                is_markup,
+                allow_tools,
+                &header_conflicts,
+                &recs,
+                &rep_thresholds,
+                &quality,
+                on_garbled,
+            );
+
+            record_decision_stats(
+                &state,
+                &actor_name,
+                &policy_name,
+                &source_type,
+                &threat_full,
+                quality.bucket,
+                &d,
+                false,
+            )
+            .await;
+
+            let d = stamp_origin(d, &origin);
+            timing.lap(Stage::Decide);
 
             let resp = IngestResponse {
                 digest: DigestInfo {
@@ -508,7 +1783,16 @@ pub async fn ingest_source(
                 normalized,
                 normalization_steps,
                 threat,
+                text_quality: quality,
                 threat_audit,
+                verdict_repairs: None,
+                prompt_budgets: None,
+                actor: audit_mode.then(|| actor_name.clone()),
+                provenance: None,
+                confidence: None,
+                cache_bypass: trace.cache_bypass,
+                chunk_reuse: None,
+                origin,
                 tools_allowed: d.tools_allowed,
                 risk_level: d.risk_level,
                 action: d.action,
@@ -521,7 +1805,7 @@ pub async fn ingest_source(
         }
 
         // Live mode.
-        let policy_name = routes::policy_name_from_headers(&headers);
+        let policy_name = routes::policy_name_from_headers(&headers, &state.header_rules);
         let policy = match state.policies.require(&policy_name) {
             Ok(p) => p,
             Err(_) => {
@@ -535,28 +1819,15 @@ pub async fn ingest_source(
                 .into_response();
             }
         };
+        let (policy, experiment) = state.experiments.assign(
+            &policy_name,
+            policy,
+            &format!("source_id:{source_id}"),
+            state.drain.is_draining() || state.read_only,
+        );
+        trace.experiment = experiment;
 
-        let http = state.http.clone();
-        let l1: Box<dyn sentry::ModelClient> = match policy.l1.provider {
-            crate::model_policy::Provider::Gemini => Box::new(sentry::GeminiClient::new(
-                http.clone(),
-                state.secrets.clone(),
-            )),
-            crate::model_policy::Provider::Anthropic => Box::new(sentry::AnthropicClient::new(
-                http.clone(),
-                state.secrets.clone(),
-            )),
-        };
-        let l2: Box<dyn sentry::ModelClient> = match policy.l2.provider {
-            crate::model_policy::Provider::Gemini => Box::new(sentry::GeminiClient::new(
-                http.clone(),
-                state.secrets.clone(),
-            )),
-            crate::model_policy::Provider::Anthropic => Box::new(sentry::AnthropicClient::new(
-                http.clone(),
-                state.secrets.clone(),
-            )),
-        };
+        let (l1, l2) = model_clients(&state, &policy);
         let engine = sentry::DecisionEngine::new(l1, l2);
 
         let source_meta = serde_json::json!({
@@ -573,20 +1844,80 @@ pub async fn ingest_source(
             "threat": threat,
         });
 
-        let decision = engine
-            .decide(
+        let model_headers = timing.trace.outbound_headers(&headers, Stage::Model);
+        // Dropping the call on a disconnect aborts its HTTP request.
+        let fenced = fence_external(&trunc_text);
+        let verdict = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => {
+                return disconnected(&state, trace, Stage::Model, None).await;
+            }
+            v = engine.decide_tiered(
                 &policy_name,
                 &policy,
                 &source_meta,
-                &fence_external(&trunc_text),
-                &headers,
+                &fenced,
+                &model_headers,
+            ) => v,
+        };
+        timing.model_calls(&verdict.calls);
+        timing.lap(Stage::Model);
+        record_verdict_stats(&state, &verdict).await;
+        let verdict_repairs = audit_mode.then(|| verdict.repairs.clone());
+        let prompt_budgets = audit_mode.then(|| verdict.budgets.clone());
+        let (decision, model_version) = state.model_versions.enforce_for(
+            Some(&origin),
+            &policy_name,
+            &policy,
+            verdict.tier,
+            verdict.decision,
+            verdict.model_version.as_deref(),
+        );
+        let provenance = verdicts::Provenance {
+            experiment: trace
+                .experiment
+                .as_ref()
+                .and_then(|a| a.experimental_id().map(str::to_string)),
+            ..verdicts::Provenance::observed(&policy, model_version.as_deref())
+        };
+
+        let decision = apply_decision_stages(
+            decision,
+            is_markup,
+            allow_tools,
+            &header_conflicts,
+            &recs,
+            &rep_thresholds,
+            &quality,
+            on_garbled,
+        );
+
+        trace.decided = Some(decision.action.clone());
+        record_decision_stats(
+            &state,
+            &actor_name,
+            &experiments::stats_policy(&policy_name, trace.experiment.as_ref()),
+            &source_type,
+            &threat_full,
+            quality.bucket,
+            &decision,
+            verdict.tier == sentry::ModelTier::L2,
+        )
+        .await;
+        if trace.cache_bypass != Some(cache_bypass::BypassMode::Ghost) {
+            observe_verdict(
+                &state,
+                &policy_name,
+                &policy,
+                &sha,
+                &decision,
+                model_version.as_deref(),
             )
             .await;
+        }
 
-        let decision = enforce_markup_tools_cap(decision, is_markup);
-        let decision = enforce_tools_authorization(decision, allow_tools);
-        let decision =
-            reputation_policy::apply_reputation(decision, allow_tools, &recs, &rep_thresholds);
+        let decision = stamp_origin(decision, &origin);
+        timing.lap(Stage::Decide);
 
         let resp = IngestResponse {
             digest: DigestInfo {
@@ -604,7 +1935,16 @@ pub async fn ingest_source(
             normalized,
             normalization_steps,
             threat,
+            text_quality: quality,
             threat_audit,
+            verdict_repairs,
+            prompt_budgets,
+            actor: audit_mode.then(|| actor_name.clone()),
+            provenance: Some(provenance),
+            confidence: decision.confidence,
+            cache_bypass: trace.cache_bypass,
+            chunk_reuse: None,
+            origin,
             tools_allowed: decision.tools_allowed,
             risk_level: decision.risk_level,
             action: decision.action,
@@ -624,11 +1964,19 @@ pub async fn ingest_source(
     let mut eff_norm = state.normalize.clone();
     let mut html_scan_res = html_scan::HtmlScanResult::default();
     let mut xml_scan_res = xml_scan::XmlScanResult::default();
+    let mut markup_urls = vec![];
     let mut combined_sev: u8 = 0;
     let mut tightened_for_adversarial = false;
     if is_markup {
         html_scan_res = html_scan::scan(&raw);
         xml_scan_res = xml_scan::scan(&raw);
+        // Links that all point at the policy's allowlisted domains are not external references.
+        let (hosts, complete) = feeds::url_hosts_checked(&raw);
+        markup_urls = state.feeds.url_refs(&hosts, &url_allowlist);
+        if complete && !markup_urls.is_empty() && markup_urls.iter().all(|u| u.allowlisted) {
+            html_scan_res.clear_external_ref();
+            xml_scan_res.clear_external_ref();
+        }
         combined_sev = html_scan_res.severity.saturating_add(xml_scan_res.severity);
         if combined_sev >= eff_norm.adversarial_threshold {
             let factor = eff_norm.adversarial_tighten_factor;
@@ -647,6 +1995,7 @@ pub async fn ingest_source(
             eff_norm.window_tail_chars = new_tail.max(min_cap / 2);
             tightened_for_adversarial = true;
         }
+        timing.lap(Stage::MarkupScan);
     }
 
     let (raw_for_normalization, windowed_for_normalization) =
@@ -675,10 +2024,39 @@ pub async fn ingest_source(
         normalization_steps.insert(0, format!("adversarial_tighten:sev={}", combined_sev));
     }
 
+    // A large plain-text revision of a recent input: the model reads only what changed.
+    let reuse = if chunks.is_empty()
+        || is_markup
+        || trace.cache_bypass.is_some()
+        || raw.as_bytes() != input_bytes.as_slice()
+    {
+        None
+    } else {
+        partial_reuse(&state, &actor_name, &policy_name, &sha, &chunks)
+    };
+    if !chunks.is_empty() {
+        let reused = reuse.as_ref().map_or(0, |r| r.report.chunks_reused);
+        state.chunks.account(chunks.len() - reused, reused);
+    }
+    let normalized = normalized || reuse.is_some();
+    if reuse.is_some() {
+        normalization_steps.push("chunk_reuse".to_string());
+    }
+    timing.lap(Stage::Normalize);
+
     let original_length_chars = raw.chars().count();
     let model_length_chars = model_text.chars().count();
-
-    let mut threat_full = threat::assess(&model_text);
+    timing.extracted_chars = Some(model_length_chars);
+    let (model_text, quality, mut threat_full) = scan_untrusted(
+        &state,
+        model_text,
+        eff_norm.decode.clone(),
+        url_allowlist,
+        timing,
+    )
+    .await;
+    threat_full.urls.extend(markup_urls);
+    threat_full.normalize();
 
     // Cheap XML/SVG/HTML red-flag scan (pre-parse style signals). This does not replace
     // sandboxing/rlimits; it's for scoring + audit visibility.
@@ -699,6 +2077,8 @@ pub async fn ingest_source(
             threat_full.indicators.push(format!("adversarial_tighten:sev={}", combined_sev));
         }
     }
+    probe_links(&state, &policy_name, &raw, &mut threat_full, timing, cancel).await;
+    trace.indicators = threat_full.indicators.clone();
 
     let audit_mode = std::env::var("ACIP_AUDIT_MODE")
         .map(|v| v.trim().eq("ENABLED"))
@@ -707,6 +2087,8 @@ pub async fn ingest_source(
     let mut threat = threat_full.clone();
     if !audit_mode {
         threat.indicators.clear();
+        threat.detected.clear();
+        threat.urls.clear();
     }
     let threat_audit = if audit_mode {
         Some(threat_full.clone())
@@ -715,19 +2097,16 @@ pub async fn ingest_source(
     };
 
     // Update reputation store (best-effort, does not change decision yet).
-    let host = url.as_deref().and_then(host_from_url);
-    let recs = state.reputation.record(reputation::observation(
-        source_id.clone(),
-        host,
-        threat.threat_score,
-        threat
-            .attack_types
-            .iter()
-            .map(|t| format!("{:?}", t))
-            .collect(),
-    ));
+    if cancel.is_cancelled() {
+        let unrecorded = (&reputation_subject, Some(&threat));
+        return disconnected(&state, trace, Stage::Reputation, Some(unrecorded)).await;
+    }
+    let recs = reputation_subject
+        .record(&state, trace, Some(&threat))
+        .await;
+    timing.lap(Stage::Reputation);
 
-    let rep_thresholds = reputation_policy::ReputationThresholds::from_env();
+    let rep_thresholds = state.reputation_thresholds.clone();
 
     let (trunc_text, truncated) = apply_head_tail(&state.policy, &model_text);
 
@@ -741,12 +2120,37 @@ pub async fn ingest_source(
             fence_external(&trunc_text),
             vec!["sentry disabled (ACIP_SENTRY_MODE=stub)".to_string()],
         );
-        d = enforce_markup_tools_cap(d, is_markup);
-        d = enforce_tools_authorization(d, allow_tools);
-        d = reputation_policy::apply_reputation(d, allow_tools, &recs, &rep_thresholds);
+        d = apply_decision_stages(
+            d,
+            is_markup,
+            allow_tools,
+            &header_conflicts,
+            &recs,
+            &rep_thresholds,
+            &quality,
+            on_garbled,
+        );
         // In stub mode we still allow content to be appended, but never allow tools.
         d.risk_level = sentry::RiskLevel::Medium;
         d.action = sentry::Action::Allow;
+        if let Some(r) = &reuse {
+            d = chunking::apply_reuse(d, &r.prior, &r.report, fence_external(&trunc_text));
+        }
+
+        record_decision_stats(
+            &state,
+            &actor_name,
+            &policy_name,
+            &source_type,
+            &threat_full,
+            quality.bucket,
+            &d,
+            false,
+        )
+        .await;
+
+        let d = stamp_origin(d, &origin);
+        timing.lap(Stage::Decide);
 
         let resp = IngestResponse {
             digest: DigestInfo {
@@ -764,7 +2168,16 @@ pub async fn ingest_source(
             normalized,
             normalization_steps,
             threat,
+            text_quality: quality,
             threat_audit,
+            verdict_repairs: None,
+            prompt_budgets: None,
+            actor: audit_mode.then(|| actor_name.clone()),
+            provenance: None,
+            confidence: None,
+            cache_bypass: trace.cache_bypass,
+            chunk_reuse: reuse.map(|r| r.report),
+            origin,
             tools_allowed: d.tools_allowed,
             risk_level: d.risk_level,
             action: d.action,
@@ -784,10 +2197,36 @@ pub async fn ingest_source(
             fenced_content: fence_external(&trunc_text),
             reasons: vec!["sentry disabled (ACIP_SENTRY_MODE=stub-open)".to_string()],
             detected_patterns: vec![],
+            confidence: None,
         };
-        d = enforce_markup_tools_cap(d, is_markup);
-        d = enforce_tools_authorization(d, allow_tools);
-        d = reputation_policy::apply_reputation(d, allow_tools, &recs, &rep_thresholds);
+        d = apply_decision_stages(
+            d,
+            is_markup,
+            allow_tools,
+            &header_conflicts,
+            &recs,
+            &rep_thresholds,
+            &quality,
+            on_garbled,
+        );
+        if let Some(r) = &reuse {
+            d = chunking::apply_reuse(d, &r.prior, &r.report, fence_external(&trunc_text));
+        }
+
+        record_decision_stats(
+            &state,
+            &actor_name,
+            &policy_name,
+            &source_type,
+            &threat_full,
+            quality.bucket,
+            &d,
+            false,
+        )
+        .await;
+
+        let d = stamp_origin(d, &origin);
+        timing.lap(Stage::Decide);
 
         let resp = IngestResponse {
             digest: DigestInfo {
@@ -805,7 +2244,16 @@ pub async fn ingest_source(
             normalized,
             normalization_steps,
             threat,
+            text_quality: quality,
             threat_audit,
+            verdict_repairs: None,
+            prompt_budgets: None,
+            actor: audit_mode.then(|| actor_name.clone()),
+            provenance: None,
+            confidence: None,
+            cache_bypass: trace.cache_bypass,
+            chunk_reuse: reuse.map(|r| r.report),
+            origin,
             tools_allowed: d.tools_allowed,
             risk_level: d.risk_level,
             action: d.action,
@@ -818,7 +2266,7 @@ pub async fn ingest_source(
     }
 
     // Live mode: call the sentry.
-    let policy_name = routes::policy_name_from_headers(&headers);
+    let policy_name = routes::policy_name_from_headers(&headers, &state.header_rules);
     let policy = match state.policies.require(&policy_name) {
         Ok(p) => p,
         Err(_) => {
@@ -832,29 +2280,15 @@ pub async fn ingest_source(
             .into_response();
         }
     };
-
-    let http = state.http.clone();
-
-    let l1: Box<dyn sentry::ModelClient> = match policy.l1.provider {
-        crate::model_policy::Provider::Gemini => Box::new(sentry::GeminiClient::new(
-            http.clone(),
-            state.secrets.clone(),
-        )),
-        crate::model_policy::Provider::Anthropic => Box::new(sentry::AnthropicClient::new(
-            http.clone(),
-            state.secrets.clone(),
-        )),
-    };
-    let l2: Box<dyn sentry::ModelClient> = match policy.l2.provider {
-        crate::model_policy::Provider::Gemini => Box::new(sentry::GeminiClient::new(
-            http.clone(),
-            state.secrets.clone(),
-        )),
-        crate::model_policy::Provider::Anthropic => Box::new(sentry::AnthropicClient::new(
-            http.clone(),
-            state.secrets.clone(),
-        )),
-    };
+    let (policy, experiment) = state.experiments.assign(
+        &policy_name,
+        policy,
+        &format!("source_id:{source_id}"),
+        state.drain.is_draining() || state.read_only,
+    );
+    trace.experiment = experiment;
+
+    let (l1, l2) = model_clients(&state, &policy);
     let engine = sentry::DecisionEngine::new(l1, l2);
 
     let source_meta = serde_json::json!({
@@ -871,20 +2305,92 @@ pub async fn ingest_source(
         "threat": threat,
     });
 
-    let decision = engine
-        .decide(
+    let model_headers = timing.trace.outbound_headers(&headers, Stage::Model);
+    // Dropping the call on a disconnect aborts its HTTP request.
+    let fenced = fence_external(&trunc_text);
+    let model_input = match &reuse {
+        Some(r) => {
+            let context = state.chunks.settings().context_bytes;
+            let changed = chunking::changed_text(&raw, &r.overlap.changed, context);
+            fence_external(&apply_head_tail(&state.policy, &changed).0)
+        }
+        None => fenced.clone(),
+    };
+    let verdict = tokio::select! {
+        biased;
+        _ = cancel.cancelled() => {
+            return disconnected(&state, trace, Stage::Model, None).await;
+        }
+        v = engine.decide_tiered(
             &policy_name,
             &policy,
             &source_meta,
-            &fence_external(&trunc_text),
-            &headers,
+            &model_input,
+            &model_headers,
+        ) => v,
+    };
+    timing.model_calls(&verdict.calls);
+    timing.lap(Stage::Model);
+    record_verdict_stats(&state, &verdict).await;
+    let verdict_repairs = audit_mode.then(|| verdict.repairs.clone());
+    let prompt_budgets = audit_mode.then(|| verdict.budgets.clone());
+    let (decision, model_version) = state.model_versions.enforce_for(
+        Some(&origin),
+        &policy_name,
+        &policy,
+        verdict.tier,
+        verdict.decision,
+        verdict.model_version.as_deref(),
+    );
+    let provenance = verdicts::Provenance {
+        experiment: trace
+            .experiment
+            .as_ref()
+            .and_then(|a| a.experimental_id().map(str::to_string)),
+        ..verdicts::Provenance::observed(&policy, model_version.as_deref())
+    };
+
+    let decision = apply_decision_stages(
+        decision,
+        is_markup,
+        allow_tools,
+        &header_conflicts,
+        &recs,
+        &rep_thresholds,
+        &quality,
+        on_garbled,
+    );
+    let decision = match &reuse {
+        Some(r) => chunking::apply_reuse(decision, &r.prior, &r.report, fenced),
+        None => decision,
+    };
+
+    trace.decided = Some(decision.action.clone());
+    record_decision_stats(
+        &state,
+        &actor_name,
+        &experiments::stats_policy(&policy_name, trace.experiment.as_ref()),
+        &source_type,
+        &threat_full,
+        quality.bucket,
+        &decision,
+        verdict.tier == sentry::ModelTier::L2,
+    )
+    .await;
+    if trace.cache_bypass != Some(cache_bypass::BypassMode::Ghost) {
+        observe_verdict(
+            &state,
+            &policy_name,
+            &policy,
+            &sha,
+            &decision,
+            model_version.as_deref(),
         )
         .await;
+    }
 
-    let decision = enforce_markup_tools_cap(decision, is_markup);
-    let decision = enforce_tools_authorization(decision, allow_tools);
-    let decision =
-        reputation_policy::apply_reputation(decision, allow_tools, &recs, &rep_thresholds);
+    let decision = stamp_origin(decision, &origin);
+    timing.lap(Stage::Decide);
 
     let resp = IngestResponse {
         digest: DigestInfo {
@@ -902,7 +2408,16 @@ pub async fn ingest_source(
         normalized,
         normalization_steps,
         threat,
+        text_quality: quality,
         threat_audit,
+        verdict_repairs,
+        prompt_budgets,
+        actor: audit_mode.then(|| actor_name.clone()),
+        provenance: Some(provenance),
+        confidence: decision.confidence,
+        cache_bypass: trace.cache_bypass,
+        chunk_reuse: reuse.map(|r| r.report),
+        origin,
         tools_allowed: decision.tools_allowed,
         risk_level: decision.risk_level,
         action: decision.action,
@@ -936,7 +2451,16 @@ mod tests {
             normalized: true,
             normalization_steps: vec!["x".to_string()],
             threat: threat::ThreatAssessment::none(),
+            text_quality: text_quality::assess(""),
             threat_audit: None,
+            verdict_repairs: None,
+            prompt_budgets: None,
+            actor: None,
+            provenance: None,
+            confidence: None,
+            cache_bypass: None,
+            chunk_reuse: None,
+            origin: loop_guard::LoopGuard::default().inspect(b"").unwrap(),
             tools_allowed: false,
             risk_level: sentry::RiskLevel::Low,
             action: sentry::Action::Allow,
@@ -991,6 +2515,7 @@ mod tests {
             fenced_content: "```external\nx\n```".to_string(),
             reasons: vec![],
             detected_patterns: vec![],
+            confidence: None,
         };
 
         let out = enforce_markup_tools_cap(d, true);
@@ -1007,6 +2532,7 @@ mod tests {
             fenced_content: "```external\nx\n```".to_string(),
             reasons: vec![],
             detected_patterns: vec![],
+            confidence: None,
         };
 
         let out = enforce_tools_authorization(d, false);
@@ -1022,6 +2548,7 @@ mod tests {
             window_tail_chars: 40,
             adversarial_threshold: 3,
             adversarial_tighten_factor: 0.5,
+            decode: Default::default(),
         };
         let tail_marker = "TAIL_ONLY_MARKER";
         let html = format!(
@@ -1044,6 +2571,7 @@ mod tests {
             window_tail_chars: 5,
             adversarial_threshold: 3,
             adversarial_tighten_factor: 0.5,
+            decode: Default::default(),
         };
         let text = "x".repeat(100);
 
@@ -1,6 +1,9 @@
 use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde_json::json;
 
+/// Version of [`decision_schema`]; bumped on incompatible changes.
+pub const DECISION_SCHEMA_VERSION: u32 = 1;
+
 pub fn decision_schema() -> serde_json::Value {
     // Schema for the model's decision JSON (used in v0.2 sentry).
     json!({
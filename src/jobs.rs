@@ -0,0 +1,600 @@
+//! Asynchronous ingest jobs (`POST /v1/acip/ingest_source?mode=async`).
+//!
+//! For callers that cannot hold a connection open while a large document is extracted. The
+//! request is validated and decoded exactly as in synchronous mode, then queued; submission
+//! answers `202` with a job id and the caller polls `GET /v1/acip/jobs/{id}`. Workers run the
+//! same pipeline ([`ingest::ingest_decoded`]), so every side effect (reputation, stats, verdict
+//! history) happens when the job runs, not when it is submitted.
+//!
+//! Jobs live in memory only. The queue of jobs not yet started is bounded; a full queue answers
+//! `503 job_queue_full`. Finished jobs keep their result for `result_ttl` and are then purged by
+//! a background sweep. A job is visible only to the token that submitted it. Queued and running
+//! jobs count as in flight for the maintenance drain.
+//!
+//! A job may name a `callback_url`; the finished job is POSTed there (redacted, with the origin
+//! marker header) when it passes the [`crate::egress`] checks; redirects are not followed.
+
+use crate::drain::InFlightGuard;
+use crate::egress::{self, EgressPolicy};
+use crate::incidents;
+use crate::ingest::{self, SourceMeta};
+use crate::introspection;
+use crate::loop_guard;
+use crate::reputation::{self, Clock};
+use crate::risk_headers;
+use crate::slow_requests;
+use crate::state::AppState;
+use crate::telemetry::{self, SpanKind};
+use crate::token_auth::{self, Actor};
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use url::Url;
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 32;
+pub const DEFAULT_WORKERS: usize = 2;
+pub const DEFAULT_RESULT_TTL_SECS: u64 = 3600;
+pub const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// `retry_after` hint (seconds) sent with `job_queue_full`.
+pub const QUEUE_FULL_RETRY_AFTER_SECS: u64 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobSettings {
+    /// Jobs waiting for a worker; submission beyond this is refused.
+    pub queue_capacity: usize,
+    /// Jobs run at once.
+    pub workers: usize,
+    /// How long a finished job's result stays available.
+    pub result_ttl: Duration,
+    pub sweep_interval: Duration,
+    /// What a `callback_url` may look like: the egress allowlist (`ACIP_EGRESS_HOSTS`).
+    pub callbacks: EgressPolicy,
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        Self {
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            workers: DEFAULT_WORKERS,
+            result_ttl: Duration::from_secs(DEFAULT_RESULT_TTL_SECS),
+            sweep_interval: Duration::from_secs(DEFAULT_SWEEP_INTERVAL_SECS),
+            callbacks: EgressPolicy::default(),
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl JobSettings {
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_JOB_QUEUE_CAPACITY") {
+            s.queue_capacity = v as usize;
+        }
+        if let Some(v) = env_u64("ACIP_JOB_WORKERS") {
+            s.workers = (v as usize).max(1);
+        }
+        if let Some(v) = env_u64("ACIP_JOB_RESULT_TTL_SECS") {
+            s.result_ttl = Duration::from_secs(v.max(1));
+        }
+        if let Some(v) = env_u64("ACIP_JOB_SWEEP_SECS") {
+            s.sweep_interval = Duration::from_secs(v.max(1));
+        }
+        s.callbacks = EgressPolicy::from_env();
+        s
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JobError {
+    #[error("unknown job {0}")]
+    NotFound(String),
+    #[error("job queue full (capacity {capacity})")]
+    QueueFull { capacity: usize },
+    #[error("callback_url not allowed: {reason}")]
+    CallbackNotAllowed { url: String, reason: &'static str },
+}
+
+impl IntoResponse for JobError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::NotFound(id) => introspection::json_error(
+                StatusCode::NOT_FOUND,
+                "unknown_job",
+                json!({"job_id": id}),
+            )
+            .into_response(),
+            Self::QueueFull { capacity } => {
+                let mut resp = introspection::json_error(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "job_queue_full",
+                    json!({
+                        "capacity": capacity,
+                        "retry_after": QUEUE_FULL_RETRY_AFTER_SECS,
+                    }),
+                )
+                .into_response();
+                resp.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(QUEUE_FULL_RETRY_AFTER_SECS),
+                );
+                resp
+            }
+            Self::CallbackNotAllowed { url, reason } => introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "callback_not_allowed",
+                json!({"callback_url": url, "reason": reason}),
+            )
+            .into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Running,
+    /// The pipeline produced a decision (`result`).
+    Complete,
+    /// The pipeline answered with an error (`error`, with its `http_status`).
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallbackState {
+    /// Waiting for the job to finish.
+    Pending,
+    Delivered,
+    Failed,
+    /// Not sent: the input was flagged as a loop (see [`crate::loop_guard`]).
+    Suppressed,
+}
+
+/// Job state as reported to clients (and POSTed to `callback_url`).
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JobStatus {
+    pub job_id: String,
+    pub status: JobState,
+    pub source_id: String,
+    pub created_unix: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub started_unix: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finished_unix: Option<u64>,
+    /// When a finished job is purged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_unix: Option<u64>,
+    /// Status the synchronous endpoint would have answered with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    /// The ingest response, for `complete`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// The structured error, for `failed`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<CallbackState>,
+    /// `origin.request_id` of the run, once finished (see [`crate::incidents`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// A decoded request waiting for a worker.
+pub struct JobInput {
+    pub meta: SourceMeta,
+    /// Request headers (policy and tool selection), minus credentials.
+    pub headers: HeaderMap,
+    pub raw_text: Option<String>,
+    pub bytes: Vec<u8>,
+}
+
+struct Job {
+    actor: String,
+    status: JobStatus,
+    /// Taken by the worker that runs the job.
+    input: Option<JobInput>,
+    callback_url: Option<Url>,
+    /// Keeps a queued or running job counted by the maintenance drain.
+    in_flight: Option<InFlightGuard>,
+    queued_at: Instant,
+}
+
+/// A job taken off the queue by a worker.
+pub struct Claimed {
+    pub id: String,
+    pub actor: String,
+    pub input: JobInput,
+    /// Time spent in the queue, charged to the run's `queue_wait` stage.
+    pub queue_wait: Duration,
+}
+
+pub struct JobStore {
+    settings: JobSettings,
+    clock: Arc<dyn Clock>,
+    jobs: Mutex<HashMap<String, Job>>,
+    queue: Mutex<VecDeque<String>>,
+    ready: Notify,
+    next_seq: AtomicU64,
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new(JobSettings::default(), Arc::new(reputation::SystemClock))
+    }
+}
+
+impl JobStore {
+    pub fn new(settings: JobSettings, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            jobs: Mutex::new(HashMap::new()),
+            queue: Mutex::new(VecDeque::new()),
+            ready: Notify::new(),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> &JobSettings {
+        &self.settings
+    }
+
+    fn new_id(&self) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let seed = format!("job:{nanos}:{seq}:{:p}", self);
+        hex::encode(Sha256::digest(seed.as_bytes()))[..32].to_string()
+    }
+
+    /// Check `url` against the callback [`EgressPolicy`].
+    pub fn check_callback(&self, url: &str) -> Result<Url, JobError> {
+        self.settings
+            .callbacks
+            .check(url)
+            .map_err(|reason| JobError::CallbackNotAllowed {
+                url: url.to_string(),
+                reason,
+            })
+    }
+
+    /// Queue `input` for `actor`.
+    pub fn submit(
+        &self,
+        actor: &str,
+        mut input: JobInput,
+        callback_url: Option<&str>,
+        in_flight: Option<InFlightGuard>,
+    ) -> Result<JobStatus, JobError> {
+        let callback_url = callback_url.map(|u| self.check_callback(u)).transpose()?;
+        input.headers.remove(header::AUTHORIZATION);
+        input.headers.remove("x-acip-token");
+        input.headers.remove(header::CONTENT_LENGTH);
+
+        let mut queue = self.queue.lock().unwrap();
+        if queue.len() >= self.settings.queue_capacity {
+            return Err(JobError::QueueFull {
+                capacity: self.settings.queue_capacity,
+            });
+        }
+        let id = self.new_id();
+        let status = JobStatus {
+            job_id: id.clone(),
+            status: JobState::Pending,
+            source_id: input.meta.source_id.clone(),
+            created_unix: self.clock.now_unix(),
+            started_unix: None,
+            finished_unix: None,
+            expires_unix: None,
+            http_status: None,
+            result: None,
+            error: None,
+            callback: callback_url.as_ref().map(|_| CallbackState::Pending),
+            request_id: None,
+        };
+        self.jobs.lock().unwrap().insert(
+            id.clone(),
+            Job {
+                actor: actor.to_string(),
+                status: status.clone(),
+                input: Some(input),
+                callback_url,
+                in_flight,
+                queued_at: Instant::now(),
+            },
+        );
+        queue.push_back(id);
+        drop(queue);
+        self.ready.notify_one();
+        Ok(status)
+    }
+
+    /// State of job `id`, if `actor` submitted it.
+    pub fn status(&self, id: &str, actor: &str) -> Result<JobStatus, JobError> {
+        let jobs = self.jobs.lock().unwrap();
+        match jobs.get(id) {
+            Some(job) if job.actor == actor => Ok(job.status.clone()),
+            _ => Err(JobError::NotFound(id.to_string())),
+        }
+    }
+
+    /// State of job `id`, whoever submitted it.
+    pub fn get(&self, id: &str) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(id).map(|j| j.status.clone())
+    }
+
+    /// (job id, request id) of every finished job that recorded one.
+    pub fn request_ids(&self) -> Vec<(String, String)> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(id, j)| Some((id.clone(), j.status.request_id.clone()?)))
+            .collect()
+    }
+
+    /// Take the oldest queued job, marking it running.
+    pub fn claim(&self) -> Option<Claimed> {
+        let mut queue = self.queue.lock().unwrap();
+        let mut jobs = self.jobs.lock().unwrap();
+        while let Some(id) = queue.pop_front() {
+            let Some(job) = jobs.get_mut(&id) else {
+                continue;
+            };
+            let Some(input) = job.input.take() else {
+                continue;
+            };
+            job.status.status = JobState::Running;
+            job.status.started_unix = Some(self.clock.now_unix());
+            return Some(Claimed {
+                id,
+                actor: job.actor.clone(),
+                input,
+                queue_wait: job.queued_at.elapsed(),
+            });
+        }
+        None
+    }
+
+    /// Wait for the next queued job.
+    pub async fn next(&self) -> Claimed {
+        loop {
+            let ready = self.ready.notified();
+            if let Some(claimed) = self.claim() {
+                return claimed;
+            }
+            ready.await;
+        }
+    }
+
+    /// Record the pipeline's answer for job `id`. Returns the final status and where to POST
+    /// it, when the job has a callback that may be sent.
+    pub fn finish(
+        &self,
+        id: &str,
+        http_status: StatusCode,
+        body: Value,
+    ) -> Option<(JobStatus, Option<Url>)> {
+        let now = self.clock.now_unix();
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.get_mut(id)?;
+        let s = &mut job.status;
+        s.finished_unix = Some(now);
+        s.expires_unix = Some(now + self.settings.result_ttl.as_secs());
+        s.http_status = Some(http_status.as_u16());
+        s.request_id = body["origin"]["request_id"].as_str().map(str::to_string);
+        if http_status.is_success() {
+            s.status = JobState::Complete;
+            // No notifications for content flagged as a loop.
+            if body.pointer("/origin/loop_detected").is_some() && s.callback.is_some() {
+                s.callback = Some(CallbackState::Suppressed);
+            }
+            s.result = Some(body);
+        } else {
+            s.status = JobState::Failed;
+            s.error = Some(body);
+        }
+        job.in_flight = None;
+        let url = job
+            .callback_url
+            .clone()
+            .filter(|_| s.callback == Some(CallbackState::Pending));
+        Some((s.clone(), url))
+    }
+
+    pub fn record_callback(&self, id: &str, delivered: bool) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.status.callback = Some(if delivered {
+                CallbackState::Delivered
+            } else {
+                CallbackState::Failed
+            });
+        }
+    }
+
+    /// Purge finished jobs past their `expires_unix`. Returns how many were removed.
+    pub fn sweep_expired(&self) -> usize {
+        let now = self.clock.now_unix();
+        let mut jobs = self.jobs.lock().unwrap();
+        let before = jobs.len();
+        jobs.retain(|_, j| j.status.expires_unix.is_none_or(|at| at > now));
+        before - jobs.len()
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        let queued = self.queue.lock().unwrap().len();
+        let jobs = self.jobs.lock().unwrap();
+        let running = jobs
+            .values()
+            .filter(|j| j.status.status == JobState::Running)
+            .count();
+        json!({
+            "queued": queued,
+            "running": running,
+            "retained": jobs.len() - queued - running,
+            "queue_capacity": self.settings.queue_capacity,
+            "workers": self.settings.workers,
+            "result_ttl_secs": self.settings.result_ttl.as_secs(),
+            "callbacks_enabled": !self.settings.callbacks.allowed_hosts.is_empty(),
+        })
+    }
+}
+
+/// Queue a decoded `ingest_source` request and answer `202` with the job.
+pub fn submit(
+    state: &AppState,
+    actor: String,
+    input: JobInput,
+    callback_url: Option<String>,
+) -> Response {
+    // The drain gate admitted this request; the job stays counted until it finishes.
+    let in_flight = state.drain.try_begin().ok();
+    match state
+        .jobs
+        .submit(&actor, input, callback_url.as_deref(), in_flight)
+    {
+        Ok(status) => {
+            let location = format!("/v1/acip/jobs/{}", status.job_id);
+            let mut resp = (StatusCode::ACCEPTED, Json(status)).into_response();
+            if let Ok(v) = HeaderValue::from_str(&location) {
+                resp.headers_mut().insert(header::LOCATION, v);
+            }
+            resp
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Run one claimed job through the ingest pipeline and deliver its callback.
+pub async fn run(state: Arc<AppState>, claimed: Claimed) {
+    let Claimed {
+        id,
+        actor,
+        input,
+        queue_wait,
+    } = claimed;
+    // The webhook joins the submitter's trace, like the job's own request span.
+    let caller = telemetry::TraceContext::from_headers(&input.headers);
+    let resp = ingest::ingest_timed(
+        state.clone(),
+        actor,
+        input.headers,
+        input.meta,
+        input.raw_text,
+        input.bytes,
+        slow_requests::Timing::queued(queue_wait),
+        incidents::Links::job(&id),
+    )
+    .await;
+    let http_status = resp.status();
+    let body = match axum::body::to_bytes(resp.into_body(), usize::MAX).await {
+        Ok(raw) => serde_json::from_slice(&raw)
+            .unwrap_or_else(|_| json!({"error": String::from_utf8_lossy(&raw)})),
+        Err(e) => {
+            tracing::error!(job_id = %id, "job result unreadable: {e}");
+            json!({"error": "job_result_unreadable"})
+        }
+    };
+
+    let Some((status, Some(url))) = state.jobs.finish(&id, http_status, body) else {
+        return;
+    };
+    let mut payload = serde_json::to_value(&status).unwrap_or_default();
+    state.redaction.redact_json(&mut payload);
+    let mut span = state
+        .telemetry
+        .span(caller.as_ref(), "acip.job.callback", SpanKind::Client);
+    span.attribute("acip.job_id", json!(id));
+    let mut req = span
+        .context()
+        .apply(state.egress_http.post(url.clone()))
+        .json(&payload);
+    if let Some(marker) = status
+        .result
+        .as_ref()
+        .and_then(|r| r["origin"]["marker"].as_str())
+    {
+        req = req.header(loop_guard::ORIGIN_HEADER, marker);
+    }
+    let delivered = match egress::send(req).await {
+        Ok(_) => true,
+        Err(e) => {
+            tracing::warn!(job_id = %id, url = %url, "job callback failed: {e}");
+            span.fail();
+            false
+        }
+    };
+    span.end(&state.telemetry);
+    state.jobs.record_callback(&id, delivered);
+}
+
+/// Start `workers` job workers and the result sweep in the background.
+pub fn start(state: Arc<AppState>) {
+    for _ in 0..state.jobs.settings.workers {
+        let state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                let claimed = state.jobs.next().await;
+                run(state.clone(), claimed).await;
+            }
+        });
+    }
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(state.jobs.settings.sweep_interval);
+        loop {
+            tick.tick().await;
+            let removed = state.jobs.sweep_expired();
+            if removed > 0 {
+                tracing::info!(removed, "Expired job results removed");
+            }
+        }
+    });
+}
+
+/// `GET /v1/acip/jobs/{id}`
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.jobs.status(&id, &token_auth::actor_name(actor)) {
+        Ok(status) => {
+            // A finished job carries one decision; anything else gets no summary headers.
+            let summary = status
+                .result
+                .as_ref()
+                .filter(|_| status.status == JobState::Complete)
+                .and_then(risk_headers::RiskSummary::from_body);
+            let mut resp = Json(status).into_response();
+            if let Some(summary) = summary {
+                state.risk_headers.insert(&summary, resp.headers_mut());
+            }
+            resp
+        }
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,126 @@
+//! Streamed JSON responses.
+//!
+//! Items are serialized one at a time into the response body, so the server holds one encoded
+//! item at a time instead of the whole response, and the client sees the first items while
+//! later ones are still being produced. The wire form follows the `Accept` header: NDJSON
+//! (`application/x-ndjson`, one item per line) or, by default, a single JSON array.
+
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures_util::stream::{self, FuturesOrdered, FuturesUnordered, Stream, StreamExt};
+use serde::Serialize;
+use std::future::Future;
+
+pub const NDJSON: &str = "application/x-ndjson";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    JsonArray,
+    Ndjson,
+}
+
+impl StreamFormat {
+    /// NDJSON when `Accept` names it, otherwise a JSON array.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let wants_ndjson = headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|t| t.split(';').next().unwrap_or_default().trim() == NDJSON);
+        if wants_ndjson {
+            Self::Ndjson
+        } else {
+            Self::JsonArray
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::JsonArray => "application/json",
+            Self::Ndjson => NDJSON,
+        }
+    }
+
+    /// Encode item number `n` (0-based) with its separator.
+    fn encode<T: Serialize>(self, n: usize, item: &T) -> Result<Bytes, serde_json::Error> {
+        let mut buf = Vec::new();
+        if self == Self::JsonArray && n > 0 {
+            buf.push(b',');
+        }
+        serde_json::to_writer(&mut buf, item)?;
+        if self == Self::Ndjson {
+            buf.push(b'\n');
+        }
+        Ok(Bytes::from(buf))
+    }
+}
+
+/// Stream `items` as the response body in `format`, one body frame per item.
+pub fn stream_response<S, T>(format: StreamFormat, items: S) -> Response
+where
+    S: Stream<Item = T> + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    let body = items
+        .enumerate()
+        .map(move |(n, item)| format.encode(n, &item));
+    let body: stream::BoxStream<'static, Result<Bytes, serde_json::Error>> = match format {
+        StreamFormat::JsonArray => stream::once(async { Ok(Bytes::from_static(b"[")) })
+            .chain(body)
+            .chain(stream::once(async { Ok(Bytes::from_static(b"]")) }))
+            .boxed(),
+        StreamFormat::Ndjson => body.boxed(),
+    };
+    (
+        StatusCode::OK,
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        )],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// One result of [`stream_completed`] in NDJSON form: `index` is the position of the job in the
+/// request. `T` must serialize as a JSON object.
+#[derive(Debug, Serialize)]
+pub struct Indexed<T> {
+    pub index: usize,
+    #[serde(flatten)]
+    pub item: T,
+}
+
+/// Run `jobs` concurrently and stream their results.
+///
+/// NDJSON emits each result as soon as it completes, tagged with its request `index`; the
+/// JSON array keeps request order (a result waits for the ones before it).
+pub fn stream_completed<F, T>(format: StreamFormat, jobs: Vec<F>) -> Response
+where
+    F: Future<Output = T> + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    match format {
+        StreamFormat::Ndjson => {
+            let jobs: FuturesUnordered<_> = jobs
+                .into_iter()
+                .enumerate()
+                .map(|(index, job)| async move {
+                    Indexed {
+                        index,
+                        item: job.await,
+                    }
+                })
+                .collect();
+            stream_response(format, jobs)
+        }
+        StreamFormat::JsonArray => {
+            let jobs: FuturesOrdered<_> = jobs.into_iter().collect();
+            stream_response(format, jobs)
+        }
+    }
+}
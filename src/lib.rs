@@ -1,22 +1,74 @@
+pub mod acip_headers;
 pub mod app;
 pub mod app_state_builder;
+pub mod b64;
+pub mod blocking;
+pub mod build_info;
+pub mod cache_bypass;
+pub mod capabilities;
+pub mod chunking;
+pub mod client;
+pub mod command_line;
 pub mod config;
+pub mod content_types;
+pub mod cors;
+pub mod decode_scan;
+pub mod deprecations;
+pub mod disconnect;
+pub mod drain;
+pub mod egress;
+pub mod environment;
+pub mod experiments;
 pub mod extract;
+pub mod extractor_health;
+pub mod feeds;
+pub mod hashing;
 pub mod html_scan;
+pub mod incidents;
 pub mod ingest;
 pub mod introspection;
+pub mod jobs;
+pub mod json_stream;
+pub mod loop_guard;
+pub mod model_pinning;
 pub mod model_policy;
+pub mod multipart;
 pub mod normalize;
+pub mod notify;
+pub mod pagination;
+pub mod patterns;
 pub mod policy_store;
+pub mod probing;
+pub mod read_only;
+pub mod reasons;
+pub mod redact;
+pub mod regex_guard;
+pub mod remediation;
 pub mod reputation;
+pub mod reputation_limits;
 pub mod reputation_policy;
+pub mod risk_headers;
 pub mod routes;
 pub mod secrets;
 pub mod sentry;
 pub mod server_config;
+pub mod siem;
+pub mod slow_poll;
+pub mod slow_requests;
 pub mod startup;
 pub mod state;
+pub mod stats;
+pub mod stats_aggregate;
 pub mod status;
+pub mod storage;
+pub mod telemetry;
+pub mod test_support;
+pub mod text_quality;
 pub mod threat;
+pub mod tmpdir;
 pub mod token_auth;
+pub mod token_budget;
+pub mod uploads;
+pub mod url_allowlist;
+pub mod verdicts;
 pub mod xml_scan;
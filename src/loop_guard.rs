@@ -0,0 +1,336 @@
+//! Loop protection for content the sidecar itself emitted.
+//!
+//! Every ingest response (its `origin` field and a banner on `fenced_content`) and every
+//! webhook carries an origin marker: `acip-origin:v1:<instance>:<request_id>:<hop>:<check>`.
+//! `check` is a hash of the other fields, so only markers the sidecar actually wrote are
+//! recognized; text that merely describes the format does not parse. Incoming content is
+//! searched for the marker prefix before anything else runs. Content carrying this instance's
+//! marker, or more hops than `max_hops`, is a loop: `reject` refuses it with
+//! `self_ingestion_detected`, `flag` processes it but flags the decision and sends no
+//! notifications for it, and `ignore` turns detection off.
+
+use crate::config::LoopProtectionConfig;
+use aho_corasick::AhoCorasick;
+use anyhow::{bail, Result};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const MARKER_PREFIX: &str = "acip-origin:v1:";
+/// Header carrying the marker on outgoing webhooks.
+pub const ORIGIN_HEADER: &str = "x-acip-origin";
+pub const DEFAULT_MAX_HOPS: u32 = 3;
+
+/// Longest instance id accepted in config and in markers.
+const MAX_INSTANCE_ID_LEN: usize = 64;
+const REQUEST_ID_LEN: usize = 16;
+const CHECK_LEN: usize = 12;
+
+static INSTANCE_SEQ: AtomicU64 = AtomicU64::new(0);
+
+static PREFIX_FINDER: Lazy<AhoCorasick> =
+    Lazy::new(|| AhoCorasick::new([MARKER_PREFIX]).expect("static pattern"));
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopProtection {
+    Reject,
+    #[default]
+    Flag,
+    Ignore,
+}
+
+#[derive(Debug, Clone)]
+pub struct LoopSettings {
+    pub mode: LoopProtection,
+    /// Sidecars a piece of content may pass through before it counts as a loop.
+    pub max_hops: u32,
+    /// Stable id for this instance; random per process when unset.
+    pub instance_id: Option<String>,
+}
+
+impl Default for LoopSettings {
+    fn default() -> Self {
+        Self {
+            mode: LoopProtection::default(),
+            max_hops: DEFAULT_MAX_HOPS,
+            instance_id: None,
+        }
+    }
+}
+
+impl LoopSettings {
+    pub fn from_config(cfg: Option<&LoopProtectionConfig>) -> Self {
+        let Some(cfg) = cfg else {
+            return Self::default();
+        };
+        Self {
+            mode: cfg.mode.unwrap_or_default(),
+            max_hops: cfg.max_hops.unwrap_or(DEFAULT_MAX_HOPS),
+            instance_id: cfg.instance_id.clone(),
+        }
+    }
+}
+
+/// `[A-Za-z0-9_-]`, 1 to 64 characters: anything else would not survive in a marker.
+pub fn validate_instance_id(id: &str) -> Result<()> {
+    let valid_chars = id
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if id.is_empty() || id.len() > MAX_INSTANCE_ID_LEN || !valid_chars {
+        bail!("loop_protection.instance_id {id:?} must be 1-{MAX_INSTANCE_ID_LEN} characters of [A-Za-z0-9_-]");
+    }
+    Ok(())
+}
+
+/// A parsed, verified origin marker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Marker {
+    pub instance_id: String,
+    pub request_id: String,
+    pub hop: u32,
+}
+
+impl Marker {
+    fn check(instance_id: &str, request_id: &str, hop: u32) -> String {
+        let digest = Sha256::digest(format!("{MARKER_PREFIX}{instance_id}:{request_id}:{hop}"));
+        hex::encode(digest)[..CHECK_LEN].to_string()
+    }
+
+    pub fn render(&self) -> String {
+        format!(
+            "{MARKER_PREFIX}{}:{}:{}:{}",
+            self.instance_id,
+            self.request_id,
+            self.hop,
+            Self::check(&self.instance_id, &self.request_id, self.hop)
+        )
+    }
+
+    /// Parse the marker fields that follow [`MARKER_PREFIX`]; `None` unless the check matches.
+    fn parse_fields(rest: &[u8]) -> Option<Self> {
+        let end = rest
+            .iter()
+            .position(|b| !(b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b':')))
+            .unwrap_or(rest.len());
+        let fields = std::str::from_utf8(&rest[..end]).ok()?;
+        let mut parts = fields.split(':');
+        let (instance_id, request_id, hop, check) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if parts.next().is_some() || validate_instance_id(instance_id).is_err() {
+            return None;
+        }
+        let hop: u32 = hop.parse().ok()?;
+        if check != Self::check(instance_id, request_id, hop) {
+            return None;
+        }
+        Some(Self {
+            instance_id: instance_id.to_string(),
+            request_id: request_id.to_string(),
+            hop,
+        })
+    }
+}
+
+/// Every verified marker in `input`.
+pub fn find_markers(input: &[u8]) -> Vec<Marker> {
+    // Longest possible field run after the prefix; bounds the parse at each candidate.
+    const MAX_FIELDS_LEN: usize = MAX_INSTANCE_ID_LEN + REQUEST_ID_LEN + 10 + CHECK_LEN + 3;
+    PREFIX_FINDER
+        .find_iter(input)
+        .filter_map(|m| {
+            let rest = &input[m.end()..];
+            Marker::parse_fields(&rest[..rest.len().min(MAX_FIELDS_LEN + 1)])
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopKind {
+    /// The input carries a marker written by this instance.
+    SelfIngestion,
+    /// The input has passed through more than `max_hops` sidecars.
+    HopLimit,
+}
+
+/// Why an input was treated as a loop.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LoopDetected {
+    pub kind: LoopKind,
+    /// The marker that triggered detection.
+    pub marker: Marker,
+    /// Hop this request would have been.
+    pub hop: u32,
+    pub max_hops: u32,
+}
+
+impl LoopDetected {
+    /// Reason appended to a flagged decision.
+    pub fn reason(&self) -> String {
+        match self.kind {
+            LoopKind::SelfIngestion => format!(
+                "self_ingestion_detected: input carries this sidecar's output (request {}); notifications suppressed",
+                self.marker.request_id
+            ),
+            LoopKind::HopLimit => format!(
+                "self_ingestion_detected: input has passed through {} sidecars (max {}); notifications suppressed",
+                self.hop, self.max_hops
+            ),
+        }
+    }
+}
+
+/// Loop-protection outcome for one request, and the marker its outputs carry.
+#[derive(Debug, Clone, Serialize)]
+pub struct Origin {
+    pub marker: String,
+    pub request_id: String,
+    pub hop: u32,
+    /// Set in `flag` mode when the input is a loop; no notifications are sent for it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_detected: Option<LoopDetected>,
+}
+
+impl Origin {
+    pub fn notifications_allowed(&self) -> bool {
+        self.loop_detected.is_none()
+    }
+
+    /// `fenced_content` with the origin banner on its first line.
+    pub fn banner(&self, fenced_content: &str) -> String {
+        format!("[{}]\n{fenced_content}", self.marker)
+    }
+}
+
+/// Instance identity, settings and counters, shared by all requests.
+pub struct LoopGuard {
+    settings: LoopSettings,
+    instance_id: String,
+    next_seq: AtomicU64,
+    flagged: AtomicU64,
+    rejected: AtomicU64,
+}
+
+impl Default for LoopGuard {
+    fn default() -> Self {
+        Self::new(LoopSettings::default()).expect("default settings are valid")
+    }
+}
+
+impl LoopGuard {
+    pub fn new(settings: LoopSettings) -> Result<Self> {
+        let instance_id = match &settings.instance_id {
+            Some(id) => {
+                validate_instance_id(id)?;
+                id.clone()
+            }
+            None => {
+                let seq = INSTANCE_SEQ.fetch_add(1, Ordering::Relaxed);
+                random_hex(&format!("instance:{}:{seq}", std::process::id()), 16)
+            }
+        };
+        Ok(Self {
+            settings,
+            instance_id,
+            next_seq: AtomicU64::new(0),
+            flagged: AtomicU64::new(0),
+            rejected: AtomicU64::new(0),
+        })
+    }
+
+    pub fn settings(&self) -> &LoopSettings {
+        &self.settings
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    fn new_request_id(&self) -> String {
+        let seq = self.next_seq.fetch_add(1, Ordering::Relaxed);
+        random_hex(&format!("{}:{seq}", self.instance_id), REQUEST_ID_LEN)
+    }
+
+    /// Check `input` for origin markers and assign this request its own.
+    ///
+    /// `Err` means the input is a loop and `mode` is `reject`.
+    pub fn inspect(&self, input: &[u8]) -> Result<Origin, LoopDetected> {
+        let request_id = self.new_request_id();
+        let markers = match self.settings.mode {
+            LoopProtection::Ignore => vec![],
+            _ => find_markers(input),
+        };
+        let hop = markers
+            .iter()
+            .map(|m| m.hop.saturating_add(1))
+            .max()
+            .unwrap_or(0);
+
+        let detected = markers
+            .iter()
+            .find(|m| m.instance_id == self.instance_id)
+            .map(|m| (LoopKind::SelfIngestion, m))
+            .or_else(|| {
+                (hop > self.settings.max_hops)
+                    .then(|| markers.iter().max_by_key(|m| m.hop))
+                    .flatten()
+                    .map(|m| (LoopKind::HopLimit, m))
+            })
+            .map(|(kind, m)| LoopDetected {
+                kind,
+                marker: m.clone(),
+                hop,
+                max_hops: self.settings.max_hops,
+            });
+
+        if let Some(d) = &detected {
+            tracing::warn!(
+                kind = ?d.kind,
+                origin_instance = %d.marker.instance_id,
+                origin_request = %d.marker.request_id,
+                hop,
+                "ingest loop detected"
+            );
+            if self.settings.mode == LoopProtection::Reject {
+                self.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(d.clone());
+            }
+            self.flagged.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let marker = Marker {
+            instance_id: self.instance_id.clone(),
+            request_id: request_id.clone(),
+            hop,
+        }
+        .render();
+        Ok(Origin {
+            marker,
+            request_id,
+            hop,
+            loop_detected: detected,
+        })
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "mode": self.settings.mode,
+            "max_hops": self.settings.max_hops,
+            "instance_id": self.instance_id,
+            "flagged": self.flagged.load(Ordering::Relaxed),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// `len` hex characters derived from the clock and `seed`.
+fn random_hex(seed: &str, len: usize) -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    hex::encode(Sha256::digest(format!("{nanos}:{seed}")))[..len].to_string()
+}
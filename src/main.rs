@@ -4,7 +4,9 @@ use std::{net::SocketAddr, path::PathBuf};
 use tracing::{info, warn};
 
 use acip_sidecar::{
-    app, app_state_builder, config, reputation, server_config, startup, state,
+    app, app_state_builder, config, deprecations, environment, extractor_health, feeds, hashing,
+    jobs, read_only, redact, regex_guard, reputation, reputation_limits, reputation_policy, sentry,
+    server_config, siem, slow_requests, startup, state, stats, tmpdir, uploads,
 };
 
 #[derive(Parser, Debug)]
@@ -201,11 +203,27 @@ fn gid_from_groupname(name: &str) -> anyhow::Result<libc::gid_t> {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Created before logging so every log line is redacted; rules load with the config below.
+    let redaction = std::sync::Arc::new(redact::Redaction::default());
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(redact::RedactingMakeWriter::new(
+            redaction.clone(),
+            std::io::stdout,
+        ))
         .init();
 
     let args = Args::parse();
+    let build = acip_sidecar::build_info::current();
+    info!(
+        version = %build.version,
+        git = %build.git_describe,
+        profile = %build.profile,
+        rustc = %build.rustc,
+        features = %build.features.join(","),
+        "starting acip-sidecar {}",
+        build.summary()
+    );
     let config_path = args
         .config
         .unwrap_or_else(|| PathBuf::from("/etc/acip/config.toml"));
@@ -227,6 +245,28 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     };
+    // Deprecated env vars move onto their config keys (and fail startup past their sunset).
+    let (config, env_deprecations) =
+        deprecations::Registry::builtin().apply_env(config, |name| std::env::var(name).ok())?;
+    for used in &env_deprecations {
+        deprecations::record(used);
+    }
+
+    let regex_limits =
+        regex_guard::RegexLimits::from_config(config.as_ref().and_then(|cfg| cfg.regex.as_ref()));
+    redaction.reload_with_limits(
+        config.as_ref().and_then(|cfg| cfg.redaction.as_ref()),
+        &regex_limits,
+    )?;
+    let pattern_ids: Vec<String> = config
+        .as_ref()
+        .map(|c| c.patterns.iter().map(|p| p.id.clone()).collect())
+        .unwrap_or_default();
+    let remediation = std::sync::Arc::new(acip_sidecar::remediation::Remediation::from_config(
+        config.as_ref().and_then(|c| c.remediation.as_ref()),
+        &pattern_ids,
+    )?);
+    startup::spawn_config_reloader(config_path.clone(), redaction.clone(), remediation.clone());
 
     let cfg_service = config.as_ref().and_then(|cfg| cfg.service.as_ref());
 
@@ -246,7 +286,6 @@ async fn main() -> anyhow::Result<()> {
         acip_sidecar::server_config::allow_insecure_loopback(config.as_ref());
     let require_token_setting =
         acip_sidecar::server_config::require_token_setting(config.as_ref());
-    let token_env = server_config::token_env(config.as_ref());
 
     let token_required = if eff.unix_socket.is_some() {
         // Treat Unix sockets like loopback: allow insecure loopback disables token requirement.
@@ -357,23 +396,74 @@ async fn main() -> anyhow::Result<()> {
 
     // Secrets: secrets file (optional) + env fallback.
     let secrets = startup::build_secrets_store(args.secrets_file.clone())?;
+    // Keep loaded secrets out of core dumps.
+    if !server_config::allow_core_dumps(config.as_ref()) {
+        if let Err(e) = acip_sidecar::secrets::disable_core_dumps() {
+            warn!("could not disable core dumps: {e}");
+        }
+    }
 
-    let token_opt = startup::resolve_token(token_required, &secrets, &token_env)?;
-    if token_opt.is_some() {
-        info!("auth token required");
+    // Read-only mode: stores opened without write access, writing background tasks off.
+    let read_only = server_config::read_only(config.as_ref());
+    if read_only {
+        warn!("read-only mode: mutating endpoints are refused and no store is written");
     }
 
+    // Salt for exported identifier hashes: secrets store, then the salt file (made on first start).
+    let hashing = std::sync::Arc::new(hashing::IdHasher::load(
+        secrets.as_ref(),
+        config.as_ref().and_then(|c| c.hashing.as_ref()),
+        read_only,
+    )?);
+
     // Reputation store: pluggable backend behind a stable interface.
     let reputation: std::sync::Arc<dyn reputation::ReputationStore> = {
         let store = std::env::var("ACIP_REPUTATION_STORE").unwrap_or_else(|_| "memory".to_string());
-        if let Some(path) = store.strip_prefix("file:") {
-            std::sync::Arc::new(reputation::JsonFileReputationStore::load_or_create(path)?)
+        let cfg = config.as_ref().and_then(|c| c.reputation.as_ref());
+        let limits = || {
+            reputation_limits::CardinalityGuard::new(
+                reputation_limits::CardinalitySettings::from_config(cfg),
+                reputation_policy::ReputationThresholds::from_config(cfg),
+            )
+        };
+        if read_only {
+            read_only::open_reputation_store(&store)?
+        } else if let Some(path) = store.strip_prefix("file:") {
+            std::sync::Arc::new(
+                reputation::JsonFileReputationStore::load_or_create(path)?.with_limits(limits()),
+            )
         } else {
-            std::sync::Arc::new(reputation::InMemoryReputationStore::new())
+            std::sync::Arc::new(reputation::InMemoryReputationStore::new().with_limits(limits()))
         }
     };
 
-    let http = app_state_builder::build_http_client()?;
+    // Decision stats: same backend selection scheme as the reputation store.
+    let stats: std::sync::Arc<stats::DecisionStats> = {
+        let settings = stats::StatsSettings::from_env();
+        let clock = std::sync::Arc::new(reputation::SystemClock);
+        let store = std::env::var("ACIP_STATS_STORE").unwrap_or_else(|_| "memory".to_string());
+        if read_only {
+            read_only::open_stats_store(&store, settings, clock)?
+        } else if let Some(path) = store.strip_prefix("file:") {
+            std::sync::Arc::new(stats::DecisionStats::load_or_create(path, settings, clock)?)
+        } else {
+            std::sync::Arc::new(stats::DecisionStats::in_memory(settings, clock))
+        }
+    };
+
+    // Slow request timings: same backend selection scheme, never written in read-only mode.
+    let slow_requests: std::sync::Arc<slow_requests::SlowRequestLog> = {
+        let settings = slow_requests::SlowRequestSettings::from_env();
+        let clock = std::sync::Arc::new(reputation::SystemClock);
+        let store =
+            std::env::var("ACIP_SLOW_REQUEST_STORE").unwrap_or_else(|_| "memory".to_string());
+        let log = if let Some(path) = store.strip_prefix("file:") {
+            slow_requests::SlowRequestLog::load_or_create(path, settings, clock)?
+        } else {
+            slow_requests::SlowRequestLog::in_memory(settings, clock)
+        };
+        std::sync::Arc::new(if read_only { log.into_read_only() } else { log })
+    };
 
     // Policy store: load from policies.json when provided, otherwise fall back
     // to env-configured single 'default' policy.
@@ -390,25 +480,71 @@ async fn main() -> anyhow::Result<()> {
         warn!("ANTHROPIC_API_KEY not set (ok for v0.1; required for Anthropic L2 fallback)");
     }
 
-    let normalize = state::NormalizeSettings::from_config(config.as_ref().and_then(|c| c.normalize.as_ref()));
-
-    let state = app_state_builder::build_app_state(
-        state::Policy {
+    // Extractor scratch space: sweep orphans from earlier runs, then keep usage figures fresh.
+    let tmp = std::sync::Arc::new(tmpdir::TmpDirManager::new(
+        tmpdir::TmpDirSettings::from_env(),
+    ));
+    // Refuse (strict) or warn about extractor limits the host cannot honour.
+    let environment = environment::Report::assess(config.as_ref(), tmp.settings());
+    environment.enforce()?;
+
+    // Everything else is built from the config; invariants between components are checked here.
+    let (state, tokens) = app_state_builder::AppStateBuilder::new(config.clone(), secrets)
+        .with_token_required(token_required)
+        .with_read_only(read_only)
+        .with_policy(state::Policy {
             head: effective_head,
             tail: effective_tail,
             full_if_lte: effective_full_if_lte,
-        },
-        normalize,
-        http,
-        secrets,
-        policies,
-        reputation,
-    );
+        })
+        .with_policies(policies)
+        .with_reputation(reputation)
+        .with_stats(stats)
+        .with_slow_requests(slow_requests)
+        .with_hashing(hashing)
+        .with_redaction(redaction)
+        .with_remediation(remediation)
+        .with_tmp(tmp)
+        .with_environment(std::sync::Arc::new(environment))
+        .build_with_tokens()?;
+    if tokens.is_enabled() {
+        info!("auth token required (tokens: {})", tokens.names().join(", "));
+    }
+    if state.probes.settings().enabled {
+        tracing::warn!("{}", acip_sidecar::probing::STATUS_NOTICE);
+    }
+
+    // Background work. Nothing is swept, expired or extracted in read-only mode.
+    if !read_only {
+        tmpdir::start(state.tmp.clone());
+        uploads::start(state.uploads.clone());
+        extractor_health::start(state.extractor_health.clone(), state.tmp.clone());
+    }
+
+    // Models pinned to a validated version: report drift now rather than on the first request.
+    let sentry_mode = std::env::var("ACIP_SENTRY_MODE").unwrap_or_else(|_| "live".to_string());
+    if sentry_mode.trim().eq_ignore_ascii_case("live") {
+        state
+            .model_versions
+            .self_test(&state.policies, |m| {
+                sentry::client_for(&m.provider, state.http.clone(), state.secrets.clone())
+            })
+            .await;
+    }
+
+    state.feeds.refresh_all().await;
+    feeds::start(state.feeds.clone());
+    siem::start(state.siem.clone(), state.egress_http.clone());
+
+    // Async ingest jobs run on the same pipeline; none can be submitted in read-only mode.
+    if !read_only {
+        jobs::start(state.clone());
+    }
 
     // Apply token auth and body size limits to protected routes.
     let extra_protected =
         Router::new().route("/v1/acip/ingest_source", post(crate::ingest::ingest_source));
-    let app = app::build_router(state, token_opt.clone(), extra_protected);
+    let app = app::build_router_with_tokens(state, tokens, extra_protected);
 
     if let Some(sock_path) = effective_unix_socket {
         #[cfg(unix)]
@@ -473,6 +609,11 @@ async fn main() -> anyhow::Result<()> {
     info!("listening on http://{}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    // Peer addresses are logged for suspicious requests (e.g. duplicated ACIP headers).
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
     Ok(())
 }
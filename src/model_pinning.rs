@@ -0,0 +1,413 @@
+//! Model version pinning.
+//!
+//! A policy can pin a model to a validated version (`l1.required_model_version` /
+//! `l2.required_model_version`). The version actually served is taken from the provider's
+//! response metadata, or from a startup probe for providers that expose a model lookup. When
+//! it differs from the pin (or cannot be determined), the policy's `on_version_mismatch`
+//! decides: `fail_closed` turns the decision into `needs_review` with tools off, `warn` keeps
+//! the verdict but flags it. Mismatches are counted per policy, and the first observation of
+//! each (provider, version) pair is sent to the configured notifier.
+
+use crate::egress::{self, EgressPolicy};
+use crate::loop_guard::{self, Origin};
+use crate::model_policy::{ModelRef, PolicyConfig, VersionMismatchHandling};
+use crate::policy_store::PolicyStore;
+use crate::sentry::{Action, Decision, ModelClient, ModelTier};
+use anyhow::anyhow;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+/// The model-version webhook URL variable.
+pub const WEBHOOK_URL_ENV: &str = "ACIP_MODEL_VERSION_WEBHOOK_URL";
+
+/// Stands in for the observed version when the provider reported none and no probe answered.
+pub const UNKNOWN_VERSION: &str = "unknown";
+
+/// Result of comparing a pinned model with the version that served it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionCheck {
+    /// Provider and model, as in [`ModelRef::label`].
+    pub model: String,
+    pub required: String,
+    pub observed: Option<String>,
+}
+
+impl VersionCheck {
+    pub fn matches(&self) -> bool {
+        self.observed.as_deref() == Some(self.required.as_str())
+    }
+
+    fn observed_or_unknown(&self) -> &str {
+        self.observed.as_deref().unwrap_or(UNKNOWN_VERSION)
+    }
+}
+
+/// Compare `model` with `observed`; `None` when the model is not pinned.
+pub fn check(model: &ModelRef, observed: Option<&str>) -> Option<VersionCheck> {
+    let required = model.required_model_version.as_ref()?;
+    Some(VersionCheck {
+        model: model.label(),
+        required: required.clone(),
+        observed: observed.map(str::to_string),
+    })
+}
+
+/// Apply `on_version_mismatch` to a verdict whose model failed its version pin.
+pub fn apply_version_check(
+    mut decision: Decision,
+    check: &VersionCheck,
+    handling: VersionMismatchHandling,
+) -> Decision {
+    if check.matches() {
+        return decision;
+    }
+    match handling {
+        VersionMismatchHandling::FailClosed => {
+            decision.tools_allowed = false;
+            if !matches!(decision.action, Action::Block) {
+                decision.action = Action::NeedsReview;
+            }
+            decision.reasons.push(format!(
+                "model_version_mismatch: {} served version {} (required {}); failing closed",
+                check.model,
+                check.observed_or_unknown(),
+                check.required
+            ));
+        }
+        VersionMismatchHandling::Warn => {
+            decision.reasons.push(format!(
+                "model_version_unvalidated: {} served version {} (required {})",
+                check.model,
+                check.observed_or_unknown(),
+                check.required
+            ));
+        }
+    }
+    decision
+}
+
+/// Where a mismatch was noticed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObservedBy {
+    Decision,
+    StartupProbe,
+}
+
+/// Sent the first time a provider is seen serving a given unvalidated version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VersionMismatchEvent {
+    pub policy: String,
+    pub provider: String,
+    #[serde(flatten)]
+    pub check: VersionCheck,
+    pub on_version_mismatch: VersionMismatchHandling,
+    pub observed_by: ObservedBy,
+    /// Loop-protection marker of the request that observed the mismatch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+}
+
+pub trait VersionNotifier: Send + Sync {
+    fn notify(&self, event: &VersionMismatchEvent);
+}
+
+/// Notifier used when no webhook is configured: the event is only logged.
+pub struct LogNotifier;
+
+impl VersionNotifier for LogNotifier {
+    fn notify(&self, event: &VersionMismatchEvent) {
+        tracing::warn!(
+            policy = %event.policy,
+            model = %event.check.model,
+            required = %event.check.required,
+            observed = %event.check.observed_or_unknown(),
+            "New unvalidated model version observed"
+        );
+    }
+}
+
+/// POSTs each event as JSON to `url` (best effort, in the background), after logging it.
+///
+/// The URL is an outbound destination set by the operator: it passes
+/// [`EgressPolicy::check`] with loopback allowed, and deliveries go through [`egress::send`],
+/// so a redirect is a failed delivery. `http` should be [`egress::client`].
+pub struct WebhookNotifier {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(http: reqwest::Client, url: String) -> Self {
+        Self { http, url }
+    }
+
+    /// `raw` checked against `allowed_hosts`.
+    pub fn from_url(
+        http: reqwest::Client,
+        raw: &str,
+        allowed_hosts: HashSet<String>,
+    ) -> anyhow::Result<Self> {
+        let url = EgressPolicy::new(allowed_hosts)
+            .with_loopback()
+            .check(raw)
+            .map_err(|reason| anyhow!("{WEBHOOK_URL_ENV} {:?}: {reason}", raw.trim()))?;
+        Ok(Self::new(http, url.to_string()))
+    }
+
+    /// From `ACIP_MODEL_VERSION_WEBHOOK_URL`, when set; an error when its URL is refused.
+    pub fn from_env(http: reqwest::Client) -> anyhow::Result<Option<Self>> {
+        std::env::var(WEBHOOK_URL_ENV)
+            .ok()
+            .filter(|u| !u.trim().is_empty())
+            .map(|raw| Self::from_url(http, &raw, egress::allowed_hosts_from_env()))
+            .transpose()
+    }
+}
+
+impl VersionNotifier for WebhookNotifier {
+    fn notify(&self, event: &VersionMismatchEvent) {
+        LogNotifier.notify(event);
+        let Ok(rt) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let mut req = self.http.post(&self.url).json(event);
+        if let Some(marker) = &event.origin {
+            req = req.header(loop_guard::ORIGIN_HEADER, marker);
+        }
+        let url = self.url.clone();
+        rt.spawn(async move {
+            if let Err(e) = egress::send(req).await {
+                tracing::warn!(url = %url, "model version webhook failed: {e}");
+            }
+        });
+    }
+}
+
+/// Probe results, mismatch counters and notification dedup, shared by all requests.
+pub struct ModelVersionMonitor {
+    notifier: Arc<dyn VersionNotifier>,
+    /// Model label -> version found by the startup probe.
+    probed: Mutex<HashMap<String, String>>,
+    /// (provider, version) pairs already notified.
+    notified: Mutex<HashSet<(String, String)>>,
+    /// Policy -> mismatches seen.
+    mismatches: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Default for ModelVersionMonitor {
+    fn default() -> Self {
+        Self::new(Arc::new(LogNotifier))
+    }
+}
+
+impl ModelVersionMonitor {
+    pub fn new(notifier: Arc<dyn VersionNotifier>) -> Self {
+        Self {
+            notifier,
+            probed: Mutex::new(HashMap::new()),
+            notified: Mutex::new(HashSet::new()),
+            mismatches: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_probe(&self, model: &ModelRef, version: &str) {
+        self.probed
+            .lock()
+            .unwrap()
+            .insert(model.label(), version.to_string());
+    }
+
+    pub fn probed(&self, model: &ModelRef) -> Option<String> {
+        self.probed.lock().unwrap().get(&model.label()).cloned()
+    }
+
+    pub fn mismatch_count(&self, policy_name: &str) -> u64 {
+        self.mismatches
+            .lock()
+            .unwrap()
+            .get(policy_name)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn record_mismatch(
+        &self,
+        policy_name: &str,
+        model: &ModelRef,
+        check: &VersionCheck,
+        handling: VersionMismatchHandling,
+        observed_by: ObservedBy,
+        origin: Option<&Origin>,
+    ) {
+        *self
+            .mismatches
+            .lock()
+            .unwrap()
+            .entry(policy_name.to_string())
+            .or_default() += 1;
+
+        if let Some(o) = origin.filter(|o| !o.notifications_allowed()) {
+            tracing::warn!(
+                policy = %policy_name,
+                model = %check.model,
+                request = %o.request_id,
+                "model version mismatch not notified: request is an ingest loop"
+            );
+            return;
+        }
+        let provider = format!("{:?}", model.provider);
+        let first = self
+            .notified
+            .lock()
+            .unwrap()
+            .insert((provider.clone(), check.observed_or_unknown().to_string()));
+        if first {
+            self.notifier.notify(&VersionMismatchEvent {
+                policy: policy_name.to_string(),
+                provider,
+                check: check.clone(),
+                on_version_mismatch: handling,
+                observed_by,
+                origin: origin.map(|o| o.marker.clone()),
+            });
+        }
+    }
+
+    /// Version stage for a live verdict produced by `tier` under `policy`.
+    ///
+    /// Returns the (possibly failed-closed) decision and the observed version, which belongs
+    /// in the verdict's provenance whether or not the model is pinned.
+    pub fn enforce(
+        &self,
+        policy_name: &str,
+        policy: &PolicyConfig,
+        tier: ModelTier,
+        decision: Decision,
+        reported: Option<&str>,
+    ) -> (Decision, Option<String>) {
+        self.enforce_for(None, policy_name, policy, tier, decision, reported)
+    }
+
+    /// [`Self::enforce`] for an ingest request: notifications carry the request's origin
+    /// marker, and none are sent when loop protection flagged the request.
+    pub fn enforce_for(
+        &self,
+        origin: Option<&Origin>,
+        policy_name: &str,
+        policy: &PolicyConfig,
+        tier: ModelTier,
+        decision: Decision,
+        reported: Option<&str>,
+    ) -> (Decision, Option<String>) {
+        let model = match tier {
+            ModelTier::L1 => &policy.l1,
+            ModelTier::L2 => &policy.l2,
+        };
+        let observed = reported.map(str::to_string).or_else(|| self.probed(model));
+        let Some(check) = check(model, observed.as_deref()) else {
+            return (decision, observed);
+        };
+        if check.matches() {
+            return (decision, observed);
+        }
+        self.record_mismatch(
+            policy_name,
+            model,
+            &check,
+            policy.on_version_mismatch,
+            ObservedBy::Decision,
+            origin,
+        );
+        let decision = apply_version_check(decision, &check, policy.on_version_mismatch);
+        (decision, observed)
+    }
+
+    /// Startup self-test: probe every pinned model and report mismatches right away instead of
+    /// on the first request under the policy.
+    ///
+    /// `client_for` builds the client for a model's provider. Providers without a version
+    /// lookup are skipped; their version is only known from responses.
+    pub async fn self_test<F>(&self, policies: &PolicyStore, client_for: F) -> Vec<VersionCheck>
+    where
+        F: Fn(&ModelRef) -> Box<dyn ModelClient>,
+    {
+        let mut mismatched = vec![];
+        for name in policies.list() {
+            let Some(policy) = policies.get(&name) else {
+                continue;
+            };
+            for model in [&policy.l1, &policy.l2] {
+                if model.required_model_version.is_none() {
+                    continue;
+                }
+                let version = match self.probed(model) {
+                    Some(v) => Some(v),
+                    None => match client_for(model).probe_version(&model.model).await {
+                        Ok(Some(v)) => {
+                            self.record_probe(model, &v);
+                            Some(v)
+                        }
+                        Ok(None) => continue,
+                        Err(e) => {
+                            tracing::warn!(
+                                policy = %name,
+                                model = %model.label(),
+                                "model version probe failed: {e:#}"
+                            );
+                            continue;
+                        }
+                    },
+                };
+                let Some(check) = check(model, version.as_deref()) else {
+                    continue;
+                };
+                if check.matches() {
+                    tracing::info!(
+                        policy = %name,
+                        model = %check.model,
+                        version = %check.required,
+                        "Pinned model version confirmed"
+                    );
+                    continue;
+                }
+                tracing::error!(
+                    policy = %name,
+                    model = %check.model,
+                    required = %check.required,
+                    observed = %check.observed_or_unknown(),
+                    on_version_mismatch = ?policy.on_version_mismatch,
+                    "Pinned model version mismatch at startup"
+                );
+                self.record_mismatch(
+                    &name,
+                    model,
+                    &check,
+                    policy.on_version_mismatch,
+                    ObservedBy::StartupProbe,
+                    None,
+                );
+                mismatched.push(check);
+            }
+        }
+        mismatched
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let probed: BTreeMap<String, String> = self
+            .probed
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        serde_json::json!({
+            "probed": probed,
+            "mismatches": *self.mismatches.lock().unwrap(),
+        })
+    }
+}
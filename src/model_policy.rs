@@ -1,6 +1,8 @@
+use crate::remediation::RemediationPolicy;
+use crate::url_allowlist::UrlAllowlistConfig;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Provider {
     Gemini,
@@ -17,18 +19,166 @@ impl Provider {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ModelRef {
     pub provider: Provider,
     pub model: String,
+    /// Validated model version this policy may only run against, matched against the
+    /// version the provider reports serving (see `model_pinning`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_model_version: Option<String>,
+    /// L1 only: score each verdict against a second, independently sampled output of the same
+    /// model (see `sentry::agreement`). Doubles the L1 calls.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub consistency_check: bool,
+    /// Context window in tokens, overriding the provider's window for the model family (see
+    /// `token_budget`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_tokens: Option<u64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ModelRef {
+    /// `Provider/model`, e.g. `Gemini/gemini-2.0-flash`.
+    pub fn label(&self) -> String {
+        format!("{:?}/{}", self.provider, self.model)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PolicyConfig {
     /// L1: cheap-first model
     pub l1: ModelRef,
     /// L2: fallback model
     pub l2: ModelRef,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub verdict_parsing: VerdictParsing,
+    #[serde(default)]
+    pub on_garbled_text: GarbledTextHandling,
+    #[serde(default)]
+    pub on_version_mismatch: VersionMismatchHandling,
+    #[serde(default)]
+    pub on_low_confidence: LowConfidenceHandling,
+    #[serde(default)]
+    pub on_encrypted: EncryptedHandling,
+    #[serde(default)]
+    pub on_extractor_unavailable: ExtractorUnavailableHandling,
+    /// Content types this policy accepts, a subset of the globally supported ones. Empty
+    /// accepts all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_types: Vec<String>,
+    /// Domains whose links do not count as external references (see `url_allowlist`).
+    #[serde(default, skip_serializing_if = "UrlAllowlistConfig::is_empty")]
+    pub url_allowlist: UrlAllowlistConfig,
+    /// Probe the links of escalated documents when `[probing]` is on (see `probing`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub active_probing: bool,
+    /// Locale tag for remediation hints (`de`, `pt-BR`); English when unset or untranslated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Which remediation hints, and how many, a decision gets (see `remediation`).
+    #[serde(default, skip_serializing_if = "RemediationPolicy::is_default")]
+    pub remediation: RemediationPolicy,
+}
+
+/// How model verdict JSON is checked against the decision schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerdictParsing {
+    /// Any deviation from the schema is a model failure.
+    Strict,
+    /// Apply the bounded repair set in `sentry::Repair` first; anything else is a failure.
+    #[default]
+    Repair,
+}
+
+/// What to do when extracted text scores as `garbled` (see `text_quality`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GarbledTextHandling {
+    /// Report the quality signals only.
+    #[default]
+    Ignore,
+    /// Treat like a low text yield: the decision becomes `needs_review`.
+    NeedsReview,
+    /// PDFs: re-extract with OCR forced, then `needs_review` if still garbled. Other inputs
+    /// behave like `needs_review`.
+    OcrRetry,
+}
+
+/// What to do when a pinned model reports a version other than `required_model_version`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionMismatchHandling {
+    /// The decision becomes `needs_review` with tools off.
+    #[default]
+    FailClosed,
+    /// Keep the verdict, but flag it with a reason.
+    Warn,
+}
+
+/// What to do with an L1 verdict whose confidence falls in the `low` bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LowConfidenceHandling {
+    /// Keep the verdict, but flag it with a reason.
+    #[default]
+    Accept,
+    /// Ask L2 for a second opinion; its verdict is final.
+    EscalateL2,
+    /// The decision becomes `needs_review` with tools off.
+    NeedsReview,
+}
+
+/// What to do with an encrypted document the extractor could not read: no
+/// `document_password` (`encrypted_unreadable`) or a wrong one (`decryption_failed`).
+///
+/// There is deliberately no `allow`: unscanned encrypted content never passes as clean.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptedHandling {
+    /// The decision becomes `needs_review` with tools off; nothing is sent to a model.
+    NeedsReview,
+    /// Refuse it with `unsupported_content_type`.
+    #[default]
+    Reject,
+}
+
+/// Decision for a document that needs the extractor helper while its circuit breaker is open
+/// (see `extractor_health`). Tools are off either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractorUnavailableHandling {
+    /// `needs_review`, medium risk.
+    #[default]
+    NeedsReview,
+    /// `block`, high risk.
+    Block,
+}
+
+/// Default for `cache.max_verdict_age_days`.
+pub const DEFAULT_MAX_VERDICT_AGE_DAYS: u64 = 30;
+
+/// Verdict reuse controls.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// Recorded verdicts older than this are advisory only: the pipeline re-runs and the old
+    /// verdict is only used for revalidation telemetry.
+    #[serde(default = "default_max_verdict_age_days")]
+    pub max_verdict_age_days: u64,
+}
+
+fn default_max_verdict_age_days() -> u64 {
+    DEFAULT_MAX_VERDICT_AGE_DAYS
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_verdict_age_days: DEFAULT_MAX_VERDICT_AGE_DAYS,
+        }
+    }
 }
 
 impl Default for PolicyConfig {
@@ -37,11 +187,29 @@ impl Default for PolicyConfig {
             l1: ModelRef {
                 provider: Provider::Gemini,
                 model: "gemini-2.0-flash".to_string(),
+                required_model_version: None,
+                consistency_check: false,
+                context_tokens: None,
             },
             l2: ModelRef {
                 provider: Provider::Anthropic,
                 model: "claude-3-5-haiku-latest".to_string(),
+                required_model_version: None,
+                consistency_check: false,
+                context_tokens: None,
             },
+            cache: CacheConfig::default(),
+            verdict_parsing: VerdictParsing::default(),
+            on_garbled_text: GarbledTextHandling::default(),
+            on_version_mismatch: VersionMismatchHandling::default(),
+            on_low_confidence: LowConfidenceHandling::default(),
+            on_encrypted: EncryptedHandling::default(),
+            on_extractor_unavailable: ExtractorUnavailableHandling::default(),
+            content_types: vec![],
+            url_allowlist: UrlAllowlistConfig::default(),
+            active_probing: false,
+            locale: None,
+            remediation: RemediationPolicy::default(),
         }
     }
 }
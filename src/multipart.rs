@@ -0,0 +1,211 @@
+//! `multipart/form-data` bodies (RFC 7578), buffered.
+//!
+//! Enough of the format for `ingest_source` form uploads from a browser: parts with a
+//! `Content-Disposition: form-data` name, an optional filename and an optional content type.
+//! Bodies arrive whole, already bounded by the route's body limit, so parts are slices of it
+//! rather than a stream.
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const FORM_DATA: &str = "multipart/form-data";
+
+/// Parts read from one body; more is refused rather than scanned.
+pub const MAX_PARTS: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+impl Part {
+    /// A plain form field.
+    pub fn field(name: &str, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            name: name.to_string(),
+            filename: None,
+            content_type: None,
+            body: value.into(),
+        }
+    }
+
+    /// A file field.
+    pub fn file(name: &str, filename: &str, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            name: name.to_string(),
+            filename: Some(filename.to_string()),
+            content_type: Some(content_type.to_string()),
+            body: body.into(),
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MultipartError {
+    #[error("Content-Type has no boundary parameter")]
+    MissingBoundary,
+    #[error("body does not start with the boundary")]
+    MissingFirstBoundary,
+    #[error("body ends before the closing boundary")]
+    Unterminated,
+    #[error("more than {MAX_PARTS} parts")]
+    TooManyParts,
+    #[error("part {index}: {reason}")]
+    BadPart { index: usize, reason: &'static str },
+}
+
+/// True for a `multipart/form-data` Content-Type, whatever its parameters.
+pub fn is_form_data(content_type: &str) -> bool {
+    content_type
+        .split(';')
+        .next()
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(FORM_DATA))
+}
+
+/// The `boundary` parameter of a Content-Type.
+pub fn boundary(content_type: &str) -> Option<String> {
+    params(content_type)
+        .find(|(k, _)| k.eq_ignore_ascii_case("boundary"))
+        .map(|(_, v)| v)
+        .filter(|b| !b.is_empty() && b.len() <= 70)
+}
+
+/// `key=value` parameters after the first `;`, with quotes removed. A `;` inside quotes (a
+/// filename, say) does not end the value.
+fn params(value: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    let mut quoted = false;
+    value
+        .split(move |c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ';' && !quoted
+        })
+        .skip(1)
+        .filter_map(|p| {
+            let (k, v) = p.split_once('=')?;
+            let v = v.trim();
+            let v = v
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(v);
+            Some((k.trim().to_string(), v.to_string()))
+        })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The parts of `body`, sent with `content_type`.
+pub fn parse(content_type: &str, body: &[u8]) -> Result<Vec<Part>, MultipartError> {
+    let boundary = boundary(content_type).ok_or(MultipartError::MissingBoundary)?;
+    let delimiter = format!("--{boundary}").into_bytes();
+    let separator = [b"\r\n".as_slice(), &delimiter].concat();
+
+    // A preamble before the first delimiter is allowed and ignored.
+    let mut pos = if body.starts_with(&delimiter) {
+        delimiter.len()
+    } else {
+        find(body, &separator).ok_or(MultipartError::MissingFirstBoundary)? + separator.len()
+    };
+    let mut parts = Vec::new();
+    loop {
+        let rest = &body[pos..];
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        if parts.len() == MAX_PARTS {
+            return Err(MultipartError::TooManyParts);
+        }
+        let index = parts.len();
+        let bad = |reason| MultipartError::BadPart { index, reason };
+        // Transport padding after the delimiter, then its line break.
+        let line_end = find(rest, b"\r\n").ok_or(MultipartError::Unterminated)?;
+        if rest[..line_end].iter().any(|b| !matches!(b, b' ' | b'\t')) {
+            return Err(bad("text after the boundary"));
+        }
+        let head_start = pos + line_end + 2;
+        let (head, body_start) = if body[head_start..].starts_with(b"\r\n") {
+            (&body[head_start..head_start], head_start + 2)
+        } else {
+            let end = find(&body[head_start..], b"\r\n\r\n").ok_or(MultipartError::Unterminated)?;
+            (&body[head_start..head_start + end], head_start + end + 4)
+        };
+        let body_end = body_start
+            + find(&body[body_start..], &separator).ok_or(MultipartError::Unterminated)?;
+
+        let mut part = Part::field("", Vec::new());
+        let mut disposition = false;
+        for line in String::from_utf8_lossy(head)
+            .split("\r\n")
+            .filter(|l| !l.is_empty())
+        {
+            let (name, value) = line.split_once(':').ok_or(bad("malformed header"))?;
+            let value = value.trim();
+            if name.trim().eq_ignore_ascii_case("content-disposition") {
+                let kind = value.split(';').next().unwrap_or_default().trim();
+                if !kind.eq_ignore_ascii_case("form-data") {
+                    return Err(bad("Content-Disposition is not form-data"));
+                }
+                disposition = true;
+                for (k, v) in params(value) {
+                    match k.to_ascii_lowercase().as_str() {
+                        "name" => part.name = v,
+                        "filename" => part.filename = Some(v),
+                        _ => {}
+                    }
+                }
+            } else if name.trim().eq_ignore_ascii_case("content-type") {
+                part.content_type = Some(value.to_string());
+            }
+        }
+        if !disposition || part.name.is_empty() {
+            return Err(bad("no Content-Disposition name"));
+        }
+        part.body = body[body_start..body_end].to_vec();
+        parts.push(part);
+        pos = body_end + separator.len();
+    }
+}
+
+/// `parts` as a body and the Content-Type to send it with. The boundary is derived from the
+/// part bodies, so it cannot occur in them.
+pub fn encode(parts: &[Part]) -> (String, Vec<u8>) {
+    let mut digest = Sha256::new();
+    for p in parts {
+        digest.update(&p.body);
+    }
+    let boundary = format!("acip-{}", &hex::encode(digest.finalize())[..40]);
+    let mut out = Vec::new();
+    for p in parts {
+        out.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+        let mut disposition = format!(
+            "Content-Disposition: form-data; name=\"{}\"",
+            quote(&p.name)
+        );
+        if let Some(filename) = &p.filename {
+            disposition.push_str(&format!("; filename=\"{}\"", quote(filename)));
+        }
+        out.extend_from_slice(disposition.as_bytes());
+        if let Some(ct) = &p.content_type {
+            out.extend_from_slice(format!("\r\nContent-Type: {ct}").as_bytes());
+        }
+        out.extend_from_slice(b"\r\n\r\n");
+        out.extend_from_slice(&p.body);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    (format!("{FORM_DATA}; boundary={boundary}"), out)
+}
+
+/// Percent-encode what would end a quoted parameter, as browsers do (RFC 7578, section 4.2).
+fn quote(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
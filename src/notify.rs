@@ -0,0 +1,609 @@
+//! Decision webhooks: with a `[notify]` section, every decision `ingest_source` answers with is
+//! POSTed to `webhook_url` as a small JSON event.
+//!
+//! Events carry sequence numbers assigned in decision order: `seq.global` over all events and
+//! `seq.key` per sender (the `source_id` reputation key, sent hashed as `key`), so a receiver
+//! can detect gaps and reordering whatever the delivery mode. Numbers start at 1 at startup.
+//!
+//! `ordering` decides how deliveries relate to each other:
+//! - `none` (default): each event is delivered on its own task, so a retried event can arrive
+//!   after a later one.
+//! - `per_key`: events of one sender are delivered one at a time, in order, by a worker for that
+//!   sender; different senders are delivered concurrently.
+//! - `global`: one worker delivers every event in order.
+//!
+//! A failed delivery is retried with exponential backoff. In the ordered modes it holds up only
+//! its own queue, and only for `max_blockage`: after that the event is moved to the spool of
+//! undelivered events, the skip is counted in `ordering_violations`, and the queue moves on. In
+//! `none` mode an event that has failed for as long is spooled without counting a violation.
+//! The spool is bounded and drops its oldest events when full; it is not redelivered.
+//!
+//! With `per_request_callbacks`, a synchronous `ingest_source` may also name one callback URL
+//! (`X-ACIP-Callback-Url` or `callback_url`): the same payload, without `seq`, is POSTed there
+//! on its own task with the same retry and spool rules. Callback URLs are checked when the
+//! request arrives (see [`Notifier::accept_callback`]) and capped per token per minute; their
+//! delivery state is kept by request id for the incident view.
+//!
+//! The webhook and callback URLs pass the [`crate::egress`] checks, and deliveries do not follow
+//! redirects: a `3xx` answer is a failed attempt.
+
+use crate::config::NotifyConfig;
+use crate::egress::{self, EgressPolicy};
+use crate::hashing::IdHasher;
+use crate::incidents::MAX_AUDIT_ENTRIES;
+use crate::introspection;
+use crate::jobs::CallbackState;
+use crate::loop_guard;
+use crate::redact::{self, Redaction};
+use anyhow::{anyhow, bail};
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+use url::Url;
+
+pub const DEFAULT_MAX_BLOCKAGE_SECS: u64 = 300;
+pub const DEFAULT_RETRY_INITIAL_MS: u64 = 1000;
+pub const DEFAULT_SPOOL_CAPACITY: usize = 1000;
+pub const DEFAULT_CALLBACKS_PER_MINUTE: u32 = 60;
+/// Names a per-request callback; the body field is `callback_url`.
+pub const CALLBACK_HEADER: &str = "x-acip-callback-url";
+/// Longest wait between retries of a failed delivery.
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryOrdering {
+    #[default]
+    None,
+    PerKey,
+    Global,
+}
+
+/// How per-request callback URLs are checked and capped.
+#[derive(Debug, Clone, Default)]
+pub struct CallbackSettings {
+    /// Callbacks one token may request per minute.
+    pub per_minute: u32,
+    /// What a callback URL may look like: the egress allowlist, loosened by
+    /// `callback_allow_http` and `callback_allow_ip_literals`.
+    pub egress: EgressPolicy,
+}
+
+/// A validated `[notify]` section.
+#[derive(Debug, Clone)]
+pub struct NotifySettings {
+    /// The webhook every decision goes to; `None` when only per-request callbacks are on.
+    pub url: Option<Url>,
+    pub ordering: DeliveryOrdering,
+    /// How long one event may keep failing (and, when ordered, hold up its queue).
+    pub max_blockage: Duration,
+    /// Wait before the first retry; doubled after each failure up to [`MAX_RETRY_BACKOFF`].
+    pub retry_initial: Duration,
+    pub spool_capacity: usize,
+    /// Set when `per_request_callbacks` is on.
+    pub callbacks: Option<CallbackSettings>,
+}
+
+impl NotifySettings {
+    pub fn from_config(
+        cfg: &NotifyConfig,
+        allowed_hosts: &HashSet<String>,
+    ) -> anyhow::Result<Self> {
+        let url = cfg
+            .webhook_url
+            .as_deref()
+            .map(|u| webhook_url(u, allowed_hosts))
+            .transpose()?;
+        let callbacks = cfg
+            .per_request_callbacks
+            .unwrap_or(false)
+            .then(|| CallbackSettings {
+                per_minute: cfg
+                    .callbacks_per_minute
+                    .unwrap_or(DEFAULT_CALLBACKS_PER_MINUTE),
+                egress: EgressPolicy {
+                    allow_http: cfg.callback_allow_http.unwrap_or(false),
+                    allow_ip_literals: cfg.callback_allow_ip_literals.unwrap_or(false),
+                    ..EgressPolicy::new(allowed_hosts.clone())
+                },
+            });
+        if url.is_none() && callbacks.is_none() {
+            bail!("notify needs webhook_url or per_request_callbacks = true");
+        }
+        Ok(Self {
+            url,
+            ordering: cfg.ordering.unwrap_or_default(),
+            max_blockage: Duration::from_secs(
+                cfg.max_blockage_secs.unwrap_or(DEFAULT_MAX_BLOCKAGE_SECS),
+            ),
+            retry_initial: Duration::from_millis(
+                cfg.retry_initial_ms
+                    .unwrap_or(DEFAULT_RETRY_INITIAL_MS)
+                    .max(1),
+            ),
+            spool_capacity: cfg.spool_capacity.unwrap_or(DEFAULT_SPOOL_CAPACITY).max(1),
+            callbacks,
+        })
+    }
+}
+
+fn webhook_url(raw: &str, allowed_hosts: &HashSet<String>) -> anyhow::Result<Url> {
+    EgressPolicy::new(allowed_hosts.clone())
+        .with_loopback()
+        .check(raw)
+        .map_err(|reason| anyhow!("notify webhook_url {:?}: {reason}", raw.trim()))
+}
+
+/// Why a per-request callback was refused.
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CallbackError {
+    #[error("per-request callbacks are disabled")]
+    Disabled,
+    #[error("only one callback per request")]
+    TooMany,
+    #[error("callback url not allowed: {reason}")]
+    NotAllowed { url: String, reason: &'static str },
+    #[error("more than {per_minute} callbacks per minute")]
+    RateLimited { per_minute: u32, retry_after: u64 },
+}
+
+impl IntoResponse for CallbackError {
+    fn into_response(self) -> Response {
+        let reason = self.to_string();
+        match self {
+            Self::Disabled | Self::TooMany => introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "callback_not_allowed",
+                json!({"reason": reason}),
+            )
+            .into_response(),
+            Self::NotAllowed { url, reason } => introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "callback_not_allowed",
+                json!({"callback_url": url, "reason": reason}),
+            )
+            .into_response(),
+            Self::RateLimited {
+                per_minute,
+                retry_after,
+            } => {
+                let mut resp = introspection::json_error(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "callback_rate_limited",
+                    json!({"per_minute": per_minute, "retry_after": retry_after}),
+                )
+                .into_response();
+                resp.headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                resp
+            }
+        }
+    }
+}
+
+/// The callback URL a request names in `X-ACIP-Callback-Url` and/or its `callback_url` field.
+pub fn requested_callback(
+    headers: &HeaderMap,
+    body: Option<&str>,
+) -> Result<Option<String>, CallbackError> {
+    let mut named: Vec<String> = headers
+        .get_all(CALLBACK_HEADER)
+        .iter()
+        .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+        .collect();
+    named.extend(body.map(str::to_string));
+    match named.len() {
+        0 | 1 => Ok(named.pop()),
+        _ => Err(CallbackError::TooMany),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Counters {
+    delivered: u64,
+    /// Failed attempts that were retried.
+    retries: u64,
+    /// Events moved to the spool after failing for `max_blockage`.
+    undeliverable: u64,
+    /// Per-request callbacks; not counted in `delivered` or `undeliverable`.
+    callbacks_delivered: u64,
+    callbacks_undeliverable: u64,
+    /// Callback URLs refused when the request arrived, including over the rate cap.
+    callbacks_refused: u64,
+    /// Spooled events dropped from a full spool.
+    dropped: u64,
+    /// Events an ordered queue skipped; the receiver sees a gap in `seq.key` / `seq.global`.
+    ordering_violations: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Event {
+    payload: Value,
+    /// Loop-protection marker of the decision, sent as [`loop_guard::ORIGIN_HEADER`].
+    marker: Option<String>,
+    /// A per-request callback: where it goes and the request it reports on.
+    callback: Option<(Url, String)>,
+}
+
+#[derive(Default)]
+struct Inner {
+    next_global: u64,
+    next_per_key: HashMap<String, u64>,
+    /// Ordered queues with a worker draining them; a queue is removed when its worker finds it
+    /// empty. Keyed by sender (`per_key`) or `""` (`global`).
+    queues: HashMap<String, VecDeque<Event>>,
+    spool: VecDeque<Value>,
+    counters: Counters,
+    /// Callbacks per token in the current minute: (window start, count).
+    callback_windows: HashMap<String, (Instant, u32)>,
+    /// Delivery state of the newest [`MAX_AUDIT_ENTRIES`] callbacks, by request id.
+    callback_states: HashMap<String, CallbackState>,
+    callback_order: VecDeque<String>,
+}
+
+/// The webhook sink; disabled (every call a no-op) without a `[notify]` section.
+pub struct Notifier {
+    settings: Option<NotifySettings>,
+    http: reqwest::Client,
+    inner: Mutex<Inner>,
+}
+
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new(None, reqwest::Client::new())
+    }
+}
+
+impl Notifier {
+    pub fn new(settings: Option<NotifySettings>, http: reqwest::Client) -> Self {
+        Self {
+            settings,
+            http,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn from_config(cfg: Option<&NotifyConfig>, http: reqwest::Client) -> anyhow::Result<Self> {
+        let settings = cfg
+            .map(|c| NotifySettings::from_config(c, &egress::allowed_hosts_from_env()))
+            .transpose()?;
+        Ok(Self::new(settings, http))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.is_some()
+    }
+
+    /// Whether `per_request_callbacks` is on.
+    pub fn callbacks_enabled(&self) -> bool {
+        self.settings
+            .as_ref()
+            .is_some_and(|s| s.callbacks.is_some())
+    }
+
+    fn webhook(&self) -> Option<&NotifySettings> {
+        self.settings.as_ref().filter(|s| s.url.is_some())
+    }
+
+    /// Check a per-request callback URL for `actor` and count it against the rate cap.
+    pub fn accept_callback(&self, actor: &str, raw: &str) -> Result<Url, CallbackError> {
+        let result = self.check_callback(actor, raw);
+        if result.is_err() {
+            self.inner.lock().unwrap().counters.callbacks_refused += 1;
+        }
+        result
+    }
+
+    fn check_callback(&self, actor: &str, raw: &str) -> Result<Url, CallbackError> {
+        let Some(cb) = self.settings.as_ref().and_then(|s| s.callbacks.as_ref()) else {
+            return Err(CallbackError::Disabled);
+        };
+        let url = cb
+            .egress
+            .check(raw)
+            .map_err(|reason| CallbackError::NotAllowed {
+                url: raw.to_string(),
+                reason,
+            })?;
+
+        let mut inner = self.inner.lock().unwrap();
+        let now = Instant::now();
+        let window = inner
+            .callback_windows
+            .entry(actor.to_string())
+            .or_insert((now, 0));
+        let elapsed = now.duration_since(window.0);
+        if elapsed >= Duration::from_secs(60) {
+            *window = (now, 0);
+        }
+        if window.1 >= cb.per_minute {
+            let retry_after = 60u64.saturating_sub(elapsed.as_secs()).max(1);
+            return Err(CallbackError::RateLimited {
+                per_minute: cb.per_minute,
+                retry_after,
+            });
+        }
+        window.1 += 1;
+        Ok(url)
+    }
+
+    /// Delivery state of the per-request callback of `request_id`, while it is remembered.
+    pub fn callback_state(&self, request_id: &str) -> Option<CallbackState> {
+        self.inner
+            .lock()
+            .unwrap()
+            .callback_states
+            .get(request_id)
+            .copied()
+    }
+
+    fn set_callback_state(&self, request_id: &str, state: CallbackState) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner
+            .callback_states
+            .insert(request_id.to_string(), state)
+            .is_none()
+        {
+            inner.callback_order.push_back(request_id.to_string());
+            if inner.callback_order.len() > MAX_AUDIT_ENTRIES {
+                if let Some(old) = inner.callback_order.pop_front() {
+                    inner.callback_states.remove(&old);
+                }
+            }
+        }
+    }
+
+    /// Number `payload` for `key` and start delivering it. Must be called in decision order.
+    pub fn offer(self: &Arc<Self>, key: &str, mut payload: Value, marker: Option<String>) {
+        let Some(settings) = self.webhook() else {
+            return;
+        };
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_global += 1;
+        let global = inner.next_global;
+        let per_key = inner.next_per_key.entry(key.to_string()).or_default();
+        *per_key += 1;
+        payload["seq"] = json!({ "global": global, "key": *per_key });
+        let event = Event {
+            payload,
+            marker,
+            callback: None,
+        };
+
+        let queue = match settings.ordering {
+            DeliveryOrdering::None => {
+                drop(inner);
+                let this = self.clone();
+                tokio::spawn(async move { this.deliver(event, false).await });
+                return;
+            }
+            DeliveryOrdering::PerKey => key.to_string(),
+            DeliveryOrdering::Global => String::new(),
+        };
+        if let Some(waiting) = inner.queues.get_mut(&queue) {
+            waiting.push_back(event);
+            return;
+        }
+        inner.queues.insert(queue.clone(), VecDeque::from([event]));
+        drop(inner);
+        let this = self.clone();
+        tokio::spawn(async move { this.drain(queue).await });
+    }
+
+    /// Start delivering `payload` to a per-request callback accepted for `request_id`.
+    pub fn offer_callback(
+        self: &Arc<Self>,
+        url: Url,
+        request_id: &str,
+        payload: Value,
+        marker: Option<String>,
+    ) {
+        if self.settings.is_none() {
+            return;
+        }
+        self.set_callback_state(request_id, CallbackState::Pending);
+        let event = Event {
+            payload,
+            marker,
+            callback: Some((url, request_id.to_string())),
+        };
+        let this = self.clone();
+        tokio::spawn(async move { this.deliver(event, false).await });
+    }
+
+    /// Queue a notification for the decision in a successful `ingest_source` response, and send
+    /// it to `callback` as well, and hand the response back unchanged. A callback is not sent
+    /// for an error response, nor when the content was flagged as a loop.
+    pub async fn notify_response(
+        self: &Arc<Self>,
+        redaction: &Redaction,
+        hasher: &IdHasher,
+        source_id: &str,
+        callback: Option<Url>,
+        resp: Response,
+    ) -> Response {
+        if !resp.status().is_success() || (self.webhook().is_none() && callback.is_none()) {
+            return resp;
+        }
+        let (parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, redact::MAX_REDACT_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "response too large to notify",
+                )
+                    .into_response()
+            }
+        };
+        if let Ok(body) = serde_json::from_slice::<Value>(&bytes) {
+            let key = crate::reputation::source_key(source_id);
+            let mut payload = json!({
+                "key": hasher.export_id(&key),
+                "request_id": body["origin"]["request_id"],
+                "action": body["action"],
+                "risk_level": body["risk_level"],
+                "tools_allowed": body["tools_allowed"],
+                "reasons": body["reasons"],
+            });
+            redaction.redact_json(&mut payload);
+            let marker = body["origin"]["marker"].as_str().map(str::to_string);
+            if let (Some(url), Some(request_id)) = (callback, body["origin"]["request_id"].as_str())
+            {
+                if body.pointer("/origin/loop_detected").is_some() {
+                    self.set_callback_state(request_id, CallbackState::Suppressed);
+                } else {
+                    self.offer_callback(url, request_id, payload.clone(), marker.clone());
+                }
+            }
+            self.offer(&key, payload, marker);
+        }
+        Response::from_parts(parts, Body::from(bytes))
+    }
+
+    async fn drain(self: Arc<Self>, queue: String) {
+        loop {
+            let event = {
+                let mut inner = self.inner.lock().unwrap();
+                match inner.queues.get_mut(&queue).and_then(VecDeque::pop_front) {
+                    Some(e) => e,
+                    None => {
+                        inner.queues.remove(&queue);
+                        return;
+                    }
+                }
+            };
+            self.deliver(event, true).await;
+        }
+    }
+
+    /// POST `event` until it is accepted or has failed for `max_blockage`, then spool it.
+    async fn deliver(&self, event: Event, ordered: bool) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let url = match (&event.callback, &settings.url) {
+            (Some((url, _)), _) | (None, Some(url)) => url.clone(),
+            (None, None) => return,
+        };
+        let started = Instant::now();
+        let mut backoff = settings.retry_initial;
+        loop {
+            let mut req = self.http.post(url.clone()).json(&event.payload);
+            if let Some(marker) = &event.marker {
+                req = req.header(loop_guard::ORIGIN_HEADER, marker);
+            }
+            let error = match egress::send(req).await {
+                Ok(_) => {
+                    if let Some((_, request_id)) = &event.callback {
+                        self.set_callback_state(request_id, CallbackState::Delivered);
+                        self.inner.lock().unwrap().counters.callbacks_delivered += 1;
+                    } else {
+                        self.inner.lock().unwrap().counters.delivered += 1;
+                    }
+                    return;
+                }
+                Err(e) => e,
+            };
+            let remaining = settings.max_blockage.saturating_sub(started.elapsed());
+            if remaining.is_zero() {
+                tracing::warn!(
+                    seq = %event.payload["seq"],
+                    ordered,
+                    "notification undeliverable for {:?}; spooled: {error}",
+                    settings.max_blockage
+                );
+                if let Some((_, request_id)) = &event.callback {
+                    self.set_callback_state(request_id, CallbackState::Failed);
+                }
+                self.spool(event, error, ordered, settings.spool_capacity);
+                return;
+            }
+            {
+                let mut inner = self.inner.lock().unwrap();
+                inner.counters.retries += 1;
+                inner.counters.last_error = Some(error);
+            }
+            tokio::time::sleep(backoff.min(remaining)).await;
+            backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+        }
+    }
+
+    fn spool(&self, event: Event, error: String, ordered: bool, capacity: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.counters.last_error = Some(error);
+        if event.callback.is_some() {
+            inner.counters.callbacks_undeliverable += 1;
+        } else {
+            inner.counters.undeliverable += 1;
+        }
+        if ordered {
+            inner.counters.ordering_violations += 1;
+        }
+        inner.spool.push_back(event.payload);
+        while inner.spool.len() > capacity {
+            inner.spool.pop_front();
+            inner.counters.dropped += 1;
+        }
+    }
+
+    /// Events that could not be delivered, oldest first.
+    pub fn spooled(&self) -> Vec<Value> {
+        self.inner.lock().unwrap().spool.iter().cloned().collect()
+    }
+
+    /// Events queued behind an ordered delivery in progress.
+    pub fn queued(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap()
+            .queues
+            .values()
+            .map(VecDeque::len)
+            .sum()
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        let Some(settings) = &self.settings else {
+            return json!({"enabled": false});
+        };
+        let url = settings.url.clone().map(|mut url| {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.to_string()
+        });
+        let inner = self.inner.lock().unwrap();
+        let mut v = json!({
+            "enabled": true,
+            "webhook_url": url,
+            "per_request_callbacks": settings.callbacks.is_some(),
+            "callbacks_per_minute": settings.callbacks.as_ref().map(|c| c.per_minute),
+            "ordering": settings.ordering,
+            "max_blockage_secs": settings.max_blockage.as_secs(),
+            "active_queues": inner.queues.len(),
+            "queued": inner.queues.values().map(VecDeque::len).sum::<usize>(),
+            "spooled": inner.spool.len(),
+        });
+        if let (Some(obj), Value::Object(counters)) = (
+            v.as_object_mut(),
+            serde_json::to_value(&inner.counters).unwrap_or_default(),
+        ) {
+            obj.extend(counters);
+        }
+        v
+    }
+}
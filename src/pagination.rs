@@ -0,0 +1,171 @@
+//! Shared pagination for list endpoints.
+//!
+//! Every list endpoint takes `?limit=&cursor=` and answers with the same envelope:
+//! `{"items": [...], "next_cursor": "...", "total_estimate": N}`. Each listing has one documented
+//! sort key that is unique per item (ties are broken by an id inside the key), and the cursor
+//! encodes the key of the last item returned. The next page starts strictly after that key, so
+//! items added or removed between requests never cause earlier items to be skipped or repeated.
+//!
+//! Cursors are opaque to clients (base64url JSON) and bound to the listing that issued them.
+//! `?offset=` still works for one release; responses that used it carry a `deprecation` field.
+
+use crate::introspection;
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::json;
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 500;
+
+pub const OFFSET_DEPRECATION: &str =
+    "offset pagination is deprecated and will be removed in the next release; use cursor";
+
+const CURSOR_VERSION: u8 = 1;
+
+/// Query parameters shared by all list endpoints.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PageParams {
+    /// Page size; defaults to [`DEFAULT_PAGE_SIZE`], at most [`MAX_PAGE_SIZE`].
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// `next_cursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<String>,
+    /// Deprecated: number of items to skip.
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// `null` on the last page.
+    pub next_cursor: Option<String>,
+    /// Items in the whole listing when this page was built; may change between pages.
+    pub total_estimate: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecation: Option<&'static str>,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum PageError {
+    #[error("limit must be between 1 and {max}")]
+    InvalidLimit { limit: usize, max: usize },
+
+    #[error("cursor is malformed or was issued by a different listing")]
+    InvalidCursor,
+
+    #[error("cursor and offset cannot be combined")]
+    CursorWithOffset,
+}
+
+impl IntoResponse for PageError {
+    fn into_response(self) -> Response {
+        let field = match self {
+            PageError::InvalidLimit { .. } => "limit",
+            PageError::InvalidCursor | PageError::CursorWithOffset => "cursor",
+        };
+        let mut extra = json!({ "field": field, "reason": self.to_string() });
+        if let PageError::InvalidLimit { limit, max } = self {
+            extra["limit"] = json!(limit);
+            extra["max"] = json!(max);
+        }
+        introspection::json_error(StatusCode::BAD_REQUEST, "invalid_request_field", extra)
+            .into_response()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CursorBody<K> {
+    v: u8,
+    /// Listing the cursor belongs to.
+    l: String,
+    /// Sort key of the last item returned.
+    k: K,
+}
+
+fn encode_cursor<K: Serialize>(listing: &str, key: &K) -> String {
+    let body = CursorBody {
+        v: CURSOR_VERSION,
+        l: listing.to_string(),
+        k: key,
+    };
+    let bytes = serde_json::to_vec(&body).unwrap_or_default();
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn decode_cursor<K: DeserializeOwned>(listing: &str, cursor: &str) -> Result<K, PageError> {
+    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor.trim())
+        .map_err(|_| PageError::InvalidCursor)?;
+    let body: CursorBody<K> =
+        serde_json::from_slice(&bytes).map_err(|_| PageError::InvalidCursor)?;
+    if body.v != CURSOR_VERSION || body.l != listing {
+        return Err(PageError::InvalidCursor);
+    }
+    Ok(body.k)
+}
+
+impl PageParams {
+    /// Effective page size, rejecting `0` and anything over [`MAX_PAGE_SIZE`].
+    pub fn page_size(&self) -> Result<usize, PageError> {
+        match self.limit {
+            None => Ok(DEFAULT_PAGE_SIZE),
+            Some(l) if (1..=MAX_PAGE_SIZE).contains(&l) => Ok(l),
+            Some(l) => Err(PageError::InvalidLimit {
+                limit: l,
+                max: MAX_PAGE_SIZE,
+            }),
+        }
+    }
+}
+
+/// Cut one page out of a full listing.
+///
+/// `listing` names the endpoint's ordering (cursors from another listing are rejected) and
+/// `key` must be unique per item; items are returned in ascending key order.
+pub fn paginate<T, K>(
+    mut items: Vec<T>,
+    listing: &str,
+    key: impl Fn(&T) -> K,
+    params: &PageParams,
+) -> Result<Page<T>, PageError>
+where
+    K: Ord + Serialize + DeserializeOwned,
+{
+    let limit = params.page_size()?;
+    if params.cursor.is_some() && params.offset.is_some() {
+        return Err(PageError::CursorWithOffset);
+    }
+
+    items.sort_by_key(&key);
+    let total_estimate = items.len();
+
+    let start = match (&params.cursor, params.offset) {
+        (Some(c), _) => {
+            let after: K = decode_cursor(listing, c)?;
+            items.partition_point(|item| key(item) <= after)
+        }
+        (None, Some(offset)) => offset.min(items.len()),
+        (None, None) => 0,
+    };
+
+    let mut page: Vec<T> = items.into_iter().skip(start).collect();
+    let has_more = page.len() > limit;
+    page.truncate(limit);
+    let next_cursor = match page.last() {
+        Some(last) if has_more => Some(encode_cursor(listing, &key(last))),
+        _ => None,
+    };
+
+    Ok(Page {
+        items: page,
+        next_cursor,
+        total_estimate,
+        deprecation: params.offset.map(|_| OFFSET_DEPRECATION),
+    })
+}
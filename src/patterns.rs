@@ -0,0 +1,365 @@
+//! User detection patterns (`[[patterns]]`) and their runtime scan budgets.
+//!
+//! Each pattern compiles under the `[regex]` limits (see [`crate::regex_guard`]) when the config
+//! is validated. On every ingest each enabled pattern runs once over the model-facing text and a
+//! match adds `user_pattern:<id>` with the pattern's attack type and score.
+//!
+//! Every scan is timed. A scan slower than `pattern_scan_budget` is a strike; after
+//! `demote_after_strikes` strikes the pattern is disabled until restart, with an entry in the
+//! change journal and a warning on `/status`. Once the whole pack has used `pack_scan_budget` on
+//! one request, the remaining patterns are skipped and
+//! [`PACK_BUDGET_INDICATOR`] is recorded instead.
+//!
+//! [`lint`] runs the same patterns against [`adversarial_inputs`] for `acipctl patterns lint`.
+
+use crate::config::PatternConfig;
+use crate::regex_guard::{self, RegexLimits};
+use crate::reputation::{Clock, SystemClock};
+use crate::threat::{AttackType, DetectedPattern, ScanStage, ThreatAssessment};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{HashSet, VecDeque};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
+
+/// Indicator prefix for a pattern match: `user_pattern:<id>`.
+pub const INDICATOR_PREFIX: &str = "user_pattern";
+
+/// Indicator added when the pack ran out of time and skipped patterns.
+pub const PACK_BUDGET_INDICATOR: &str = "user_patterns:scan_budget_exceeded";
+
+pub const DEFAULT_SCORE: u8 = 3;
+
+/// Change journal entries kept (oldest dropped first).
+pub const JOURNAL_CAPACITY: usize = 256;
+
+/// Size of each [`adversarial_inputs`] text.
+pub const LINT_INPUT_BYTES: usize = 256 * 1024;
+
+/// Runs per lint input; the fastest is reported, which keeps the figure stable.
+pub const LINT_RUNS: usize = 3;
+
+struct UserPattern {
+    id: String,
+    ty: AttackType,
+    score: u8,
+    regex: Regex,
+    compiled_bytes: usize,
+    strikes: AtomicU32,
+    disabled: AtomicBool,
+    scans: AtomicU64,
+    slowest_us: AtomicU64,
+}
+
+/// One change to the pack made at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct JournalEntry {
+    pub at_unix: u64,
+    pub pattern: String,
+    /// Currently always `demoted`.
+    pub change: String,
+    pub detail: String,
+}
+
+pub struct PatternPack {
+    patterns: Vec<UserPattern>,
+    limits: RegexLimits,
+    clock: Arc<dyn Clock>,
+    journal: Mutex<VecDeque<JournalEntry>>,
+    pack_overruns: AtomicU64,
+}
+
+impl Default for PatternPack {
+    fn default() -> Self {
+        Self {
+            patterns: vec![],
+            limits: RegexLimits::default(),
+            clock: Arc::new(SystemClock),
+            journal: Mutex::new(VecDeque::new()),
+            pack_overruns: AtomicU64::new(0),
+        }
+    }
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+impl PatternPack {
+    pub fn new(
+        cfgs: &[PatternConfig],
+        limits: &RegexLimits,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        let mut seen = HashSet::new();
+        let mut patterns = Vec::with_capacity(cfgs.len());
+        for c in cfgs {
+            if !valid_id(&c.id) {
+                return Err(anyhow!(
+                    "patterns: invalid id {:?} (letters, digits, `_`, `-`, `.`; at most 64)",
+                    c.id
+                ));
+            }
+            if !seen.insert(c.id.as_str()) {
+                return Err(anyhow!("patterns: duplicate id {:?}", c.id));
+            }
+            let compiled = regex_guard::compile(&format!("patterns.{}", c.id), &c.regex, limits)?;
+            if compiled.regex.is_match("") {
+                return Err(anyhow!(
+                    "patterns.{}: regex must not match the empty string",
+                    c.id
+                ));
+            }
+            patterns.push(UserPattern {
+                id: c.id.clone(),
+                ty: c.attack_type.clone(),
+                score: c.score.unwrap_or(DEFAULT_SCORE),
+                regex: compiled.regex,
+                compiled_bytes: compiled.compiled_bytes,
+                strikes: AtomicU32::new(0),
+                disabled: AtomicBool::new(false),
+                scans: AtomicU64::new(0),
+                slowest_us: AtomicU64::new(0),
+            });
+        }
+        Ok(Self {
+            patterns,
+            limits: limits.clone(),
+            clock,
+            journal: Mutex::new(VecDeque::new()),
+            pack_overruns: AtomicU64::new(0),
+        })
+    }
+
+    /// Validation only: compile `cfgs` as [`Self::new`] would.
+    pub fn compile(cfgs: &[PatternConfig], limits: &RegexLimits) -> Result<Self> {
+        Self::new(cfgs, limits, Arc::new(SystemClock))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// `None` for an unknown id.
+    pub fn is_enabled(&self, id: &str) -> Option<bool> {
+        self.patterns
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| !p.disabled.load(Ordering::Relaxed))
+    }
+
+    pub fn strikes(&self, id: &str) -> Option<u32> {
+        self.patterns
+            .iter()
+            .find(|p| p.id == id)
+            .map(|p| p.strikes.load(Ordering::Relaxed))
+    }
+
+    pub fn journal(&self) -> Vec<JournalEntry> {
+        self.journal.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Run every enabled pattern over `text`, within the pack budget.
+    pub fn scan(&self, text: &str, a: &mut ThreatAssessment) {
+        if self.patterns.is_empty() {
+            return;
+        }
+        let started = Instant::now();
+        for p in &self.patterns {
+            if p.disabled.load(Ordering::Relaxed) {
+                continue;
+            }
+            if started.elapsed() >= self.limits.pack_scan_budget {
+                self.pack_overruns.fetch_add(1, Ordering::Relaxed);
+                a.indicators.push(PACK_BUDGET_INDICATOR.to_string());
+                break;
+            }
+            let t = Instant::now();
+            let hit = p.regex.is_match(text);
+            self.record(p, t.elapsed());
+            if hit {
+                let indicator = format!("{INDICATOR_PREFIX}:{}", p.id);
+                a.detected.push(DetectedPattern {
+                    indicator: indicator.clone(),
+                    stage: ScanStage::Raw,
+                });
+                a.add(p.ty.clone(), indicator, p.score);
+            }
+        }
+        a.normalize();
+    }
+
+    fn record(&self, p: &UserPattern, took: Duration) {
+        let us = took.as_micros() as u64;
+        p.scans.fetch_add(1, Ordering::Relaxed);
+        p.slowest_us.fetch_max(us, Ordering::Relaxed);
+        if took <= self.limits.pattern_scan_budget {
+            return;
+        }
+        let strikes = p.strikes.fetch_add(1, Ordering::Relaxed) + 1;
+        if strikes < self.limits.demote_after_strikes || p.disabled.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        let detail = format!(
+            "{strikes} scans over the {}us budget; last took {us}us",
+            self.limits.pattern_scan_budget.as_micros()
+        );
+        tracing::warn!(pattern = %p.id, "user pattern disabled: {detail}");
+        let mut journal = self.journal.lock().unwrap();
+        if journal.len() == JOURNAL_CAPACITY {
+            journal.pop_front();
+        }
+        journal.push_back(JournalEntry {
+            at_unix: self.clock.now_unix(),
+            pattern: p.id.clone(),
+            change: "demoted".to_string(),
+            detail,
+        });
+    }
+
+    /// `/status` view.
+    pub fn snapshot(&self) -> Value {
+        let patterns: Vec<Value> = self
+            .patterns
+            .iter()
+            .map(|p| {
+                json!({
+                    "id": p.id,
+                    "attack_type": p.ty,
+                    "compiled_bytes": p.compiled_bytes,
+                    "enabled": !p.disabled.load(Ordering::Relaxed),
+                    "strikes": p.strikes.load(Ordering::Relaxed),
+                    "scans": p.scans.load(Ordering::Relaxed),
+                    "slowest_us": p.slowest_us.load(Ordering::Relaxed),
+                })
+            })
+            .collect();
+        let warnings: Vec<String> = self
+            .patterns
+            .iter()
+            .filter(|p| p.disabled.load(Ordering::Relaxed))
+            .map(|p| {
+                format!(
+                    "pattern {} disabled after {} scans over budget",
+                    p.id,
+                    p.strikes.load(Ordering::Relaxed)
+                )
+            })
+            .collect();
+        json!({
+            "count": self.patterns.len(),
+            "enabled": self.patterns.iter().filter(|p| !p.disabled.load(Ordering::Relaxed)).count(),
+            "pattern_scan_budget_us": self.limits.pattern_scan_budget.as_micros() as u64,
+            "pack_scan_budget_us": self.limits.pack_scan_budget.as_micros() as u64,
+            "demote_after_strikes": self.limits.demote_after_strikes,
+            "pack_budget_overruns": self.pack_overruns.load(Ordering::Relaxed),
+            "patterns": patterns,
+            "warnings": warnings,
+            "journal": self.journal(),
+        })
+    }
+}
+
+/// Long inputs that push regex engines off their fast paths: long runs of one class, non-ASCII
+/// words (Unicode word boundaries), and near-misses for common URL and number patterns.
+pub fn adversarial_inputs() -> Vec<(&'static str, String)> {
+    let fill = |unit: &str| {
+        let mut s = unit.repeat(LINT_INPUT_BYTES / unit.len() + 1);
+        let mut end = LINT_INPUT_BYTES;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s
+    };
+    vec![
+        ("ascii_run", fill("a")),
+        ("ascii_words", fill("lorem ipsum ")),
+        ("whitespace", fill(" \t\n")),
+        ("unicode_words", fill("слово données 単語 ")),
+        ("punctuation", fill("!-_.,;:/")),
+        ("near_urls", fill("http://x.")),
+        ("digits", fill("0123456789")),
+        ("base64_like", fill("QUJDRA==")),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LintStatus {
+    Ok,
+    /// Slowest input took longer than `pattern_scan_budget`.
+    Slow,
+    /// Does not compile under the limits.
+    Rejected,
+}
+
+/// One line of `acipctl patterns lint`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LintReport {
+    pub label: String,
+    pub status: LintStatus,
+    pub compiled_bytes: Option<usize>,
+    pub slowest_us: Option<u64>,
+    pub slowest_input: Option<&'static str>,
+    pub error: Option<String>,
+}
+
+/// Compile each `(label, regex)` under `limits` and time it on every adversarial input.
+pub fn lint(entries: &[(String, String)], limits: &RegexLimits) -> Vec<LintReport> {
+    let inputs = adversarial_inputs();
+    entries
+        .iter()
+        .map(|(label, pattern)| {
+            let compiled = match regex_guard::compile(label, pattern, limits) {
+                Ok(c) => c,
+                Err(e) => {
+                    return LintReport {
+                        label: label.clone(),
+                        status: LintStatus::Rejected,
+                        compiled_bytes: None,
+                        slowest_us: None,
+                        slowest_input: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            };
+            let (slowest, input) = inputs
+                .iter()
+                .map(|(name, text)| {
+                    let best = (0..LINT_RUNS)
+                        .map(|_| {
+                            let t = Instant::now();
+                            std::hint::black_box(compiled.regex.is_match(text));
+                            t.elapsed()
+                        })
+                        .min()
+                        .unwrap_or_default();
+                    (best, *name)
+                })
+                .max()
+                .unwrap_or_default();
+            LintReport {
+                label: label.clone(),
+                status: if slowest > limits.pattern_scan_budget {
+                    LintStatus::Slow
+                } else {
+                    LintStatus::Ok
+                },
+                compiled_bytes: Some(compiled.compiled_bytes),
+                slowest_us: Some(slowest.as_micros() as u64),
+                slowest_input: Some(input),
+                error: None,
+            }
+        })
+        .collect()
+}
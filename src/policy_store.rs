@@ -1,42 +1,356 @@
-use crate::model_policy::{PolicyConfig, Provider};
+use crate::model_policy::{
+    CacheConfig, EncryptedHandling, ExtractorUnavailableHandling, GarbledTextHandling,
+    LowConfidenceHandling, ModelRef, PolicyConfig, Provider, VerdictParsing,
+    VersionMismatchHandling,
+};
+use crate::remediation::RemediationPolicy;
+use crate::url_allowlist::UrlAllowlistConfig;
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{collections::BTreeMap, fs, path::Path};
 
+/// Longest allowed `extends` chain (child -> parent -> ...), counting the child.
+pub const MAX_EXTENDS_DEPTH: usize = 4;
+
+/// Keys that may not appear inside a policy body. The policy name is its map key, so it is
+/// never inherited or overridden.
+const RESERVED_POLICY_KEYS: &[&str] = &["name"];
+
 /// On-disk policy configuration.
 ///
 /// Policies are intentionally *non-secret*. Secrets live in `/etc/acip/secrets.env`.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PoliciesFile {
     pub policies: BTreeMap<String, PolicyConfig>,
 }
 
+/// Sparse model reference as declared in the policies file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelRefDecl {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider: Option<Provider>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_model_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consistency_check: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_tokens: Option<u64>,
+}
+
+/// Sparse verdict cache section as declared in the policies file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheDecl {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_verdict_age_days: Option<u64>,
+}
+
+/// Sparse policy as declared in the policies file.
+///
+/// Merge rules when `extends` is set (resolved at load time):
+/// - scalar fields (`l1.provider`, `l1.model`, `l1.required_model_version`,
+///   `l1.consistency_check`, `l1.context_tokens`, the same for `l2`,
+///   `cache.max_verdict_age_days`, `verdict_parsing`, `on_garbled_text`, `on_version_mismatch`, `on_low_confidence`, `on_encrypted`, `on_extractor_unavailable`, `active_probing`, `locale`) are taken from the child when present, otherwise from the parent, field by field.
+/// - `content_types`, `url_allowlist` and `remediation` are taken whole from the child when
+///   present (lists are not merged).
+/// - `extends` itself is never inherited.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PolicyDecl {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l1: Option<ModelRefDecl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub l2: Option<ModelRefDecl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheDecl>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verdict_parsing: Option<VerdictParsing>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_garbled_text: Option<GarbledTextHandling>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_version_mismatch: Option<VersionMismatchHandling>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_low_confidence: Option<LowConfidenceHandling>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_encrypted: Option<EncryptedHandling>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_extractor_unavailable: Option<ExtractorUnavailableHandling>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_types: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url_allowlist: Option<UrlAllowlistConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_probing: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<RemediationPolicy>,
+}
+
+impl PolicyDecl {
+    /// Fully-specified declaration for an already-resolved policy.
+    pub fn from_resolved(p: &PolicyConfig) -> Self {
+        let model = |m: &ModelRef| ModelRefDecl {
+            provider: Some(m.provider.clone()),
+            model: Some(m.model.clone()),
+            required_model_version: m.required_model_version.clone(),
+            consistency_check: m.consistency_check.then_some(true),
+            context_tokens: m.context_tokens,
+        };
+        Self {
+            extends: None,
+            l1: Some(model(&p.l1)),
+            l2: Some(model(&p.l2)),
+            cache: Some(CacheDecl {
+                max_verdict_age_days: Some(p.cache.max_verdict_age_days),
+            }),
+            verdict_parsing: Some(p.verdict_parsing),
+            on_garbled_text: Some(p.on_garbled_text),
+            on_version_mismatch: Some(p.on_version_mismatch),
+            on_low_confidence: Some(p.on_low_confidence),
+            on_encrypted: Some(p.on_encrypted),
+            on_extractor_unavailable: Some(p.on_extractor_unavailable),
+            content_types: (!p.content_types.is_empty()).then(|| p.content_types.clone()),
+            url_allowlist: (!p.url_allowlist.is_empty()).then(|| p.url_allowlist.clone()),
+            active_probing: p.active_probing.then_some(true),
+            locale: p.locale.clone(),
+            remediation: (!p.remediation.is_default()).then(|| p.remediation.clone()),
+        }
+    }
+}
+
+fn merge_model_ref(
+    child: Option<&ModelRefDecl>,
+    parent: Option<&ModelRefDecl>,
+) -> Option<ModelRefDecl> {
+    match (child, parent) {
+        (None, None) => None,
+        (Some(c), None) => Some(c.clone()),
+        (None, Some(p)) => Some(p.clone()),
+        (Some(c), Some(p)) => Some(ModelRefDecl {
+            provider: c.provider.clone().or_else(|| p.provider.clone()),
+            model: c.model.clone().or_else(|| p.model.clone()),
+            required_model_version: c
+                .required_model_version
+                .clone()
+                .or_else(|| p.required_model_version.clone()),
+            consistency_check: c.consistency_check.or(p.consistency_check),
+            context_tokens: c.context_tokens.or(p.context_tokens),
+        }),
+    }
+}
+
+fn merge_cache(child: Option<&CacheDecl>, parent: Option<&CacheDecl>) -> Option<CacheDecl> {
+    match (child, parent) {
+        (None, None) => None,
+        (Some(c), None) => Some(c.clone()),
+        (None, Some(p)) => Some(p.clone()),
+        (Some(c), Some(p)) => Some(CacheDecl {
+            max_verdict_age_days: c.max_verdict_age_days.or(p.max_verdict_age_days),
+        }),
+    }
+}
+
+fn finish_model_ref(policy: &str, field: &str, m: Option<ModelRefDecl>) -> Result<ModelRef> {
+    let m = m.unwrap_or_default();
+    let provider = m.provider.ok_or_else(|| {
+        anyhow!("policy '{policy}' has no {field}.provider (declared or inherited)")
+    })?;
+    let model = m
+        .model
+        .ok_or_else(|| anyhow!("policy '{policy}' has no {field}.model (declared or inherited)"))?;
+    Ok(ModelRef {
+        provider,
+        model,
+        required_model_version: m.required_model_version,
+        consistency_check: m.consistency_check.unwrap_or(false),
+        context_tokens: m.context_tokens,
+    })
+}
+
+/// Policies as declared on disk, before inheritance is resolved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeclaredPolicies {
+    pub policies: BTreeMap<String, PolicyDecl>,
+}
+
+impl DeclaredPolicies {
+    pub fn parse(raw: &str) -> Result<Self> {
+        let v: serde_json::Value = serde_json::from_str(raw)?;
+        if let Some(policies) = v.get("policies").and_then(|p| p.as_object()) {
+            for (name, body) in policies {
+                for key in RESERVED_POLICY_KEYS {
+                    if body.get(key).is_some() {
+                        return Err(anyhow!(
+                            "policy '{name}': field '{key}' cannot be declared or inherited"
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(serde_json::from_value(v)?)
+    }
+
+    /// The `extends` chain for `name`, starting with `name` itself.
+    ///
+    /// Fails on unknown parents, cycles, and chains longer than [`MAX_EXTENDS_DEPTH`].
+    pub fn chain(&self, name: &str) -> Result<Vec<String>> {
+        let mut chain: Vec<String> = vec![];
+        let mut cur = name.to_string();
+        loop {
+            if chain.contains(&cur) {
+                chain.push(cur);
+                return Err(anyhow!("policy inheritance cycle: {}", chain.join(" -> ")));
+            }
+            let decl = self.policies.get(&cur).ok_or_else(|| match chain.last() {
+                Some(child) => anyhow!("policy '{child}' extends unknown policy '{cur}'"),
+                None => anyhow!("unknown policy: {cur}"),
+            })?;
+            chain.push(cur);
+            if chain.len() > MAX_EXTENDS_DEPTH {
+                return Err(anyhow!(
+                    "policy '{name}' inheritance chain exceeds {MAX_EXTENDS_DEPTH} levels: {}",
+                    chain.join(" -> ")
+                ));
+            }
+            match &decl.extends {
+                Some(parent) => cur = parent.clone(),
+                None => return Ok(chain),
+            }
+        }
+    }
+
+    /// Resolve one policy by folding its chain from the root down.
+    pub fn resolve(&self, name: &str) -> Result<PolicyConfig> {
+        let chain = self.chain(name)?;
+        let mut l1: Option<ModelRefDecl> = None;
+        let mut l2: Option<ModelRefDecl> = None;
+        let mut cache: Option<CacheDecl> = None;
+        let mut verdict_parsing: Option<VerdictParsing> = None;
+        let mut on_garbled_text: Option<GarbledTextHandling> = None;
+        let mut on_version_mismatch: Option<VersionMismatchHandling> = None;
+        let mut on_low_confidence: Option<LowConfidenceHandling> = None;
+        let mut on_encrypted: Option<EncryptedHandling> = None;
+        let mut on_extractor_unavailable: Option<ExtractorUnavailableHandling> = None;
+        let mut content_types: Option<Vec<String>> = None;
+        let mut url_allowlist: Option<UrlAllowlistConfig> = None;
+        let mut active_probing: Option<bool> = None;
+        let mut locale: Option<String> = None;
+        let mut remediation: Option<RemediationPolicy> = None;
+        for ancestor in chain.iter().rev() {
+            let decl = &self.policies[ancestor];
+            l1 = merge_model_ref(decl.l1.as_ref(), l1.as_ref());
+            l2 = merge_model_ref(decl.l2.as_ref(), l2.as_ref());
+            cache = merge_cache(decl.cache.as_ref(), cache.as_ref());
+            verdict_parsing = decl.verdict_parsing.or(verdict_parsing);
+            on_garbled_text = decl.on_garbled_text.or(on_garbled_text);
+            on_version_mismatch = decl.on_version_mismatch.or(on_version_mismatch);
+            on_low_confidence = decl.on_low_confidence.or(on_low_confidence);
+            on_encrypted = decl.on_encrypted.or(on_encrypted);
+            on_extractor_unavailable = decl.on_extractor_unavailable.or(on_extractor_unavailable);
+            content_types = decl.content_types.clone().or(content_types);
+            url_allowlist = decl.url_allowlist.clone().or(url_allowlist);
+            active_probing = decl.active_probing.or(active_probing);
+            locale = decl.locale.clone().or(locale);
+            remediation = decl.remediation.clone().or(remediation);
+        }
+        let content_types = content_types.unwrap_or_default();
+        crate::content_types::validate_policy_list(name, &content_types)?;
+        let url_allowlist = url_allowlist.unwrap_or_default().normalized(name)?;
+        if let Some(tag) = locale.as_deref() {
+            if !crate::remediation::valid_locale(tag) {
+                return Err(anyhow!(
+                    "policy '{name}': locale {tag:?} is not a locale tag"
+                ));
+            }
+        }
+        let mut cache_config = CacheConfig::default();
+        if let Some(days) = cache.and_then(|c| c.max_verdict_age_days) {
+            cache_config.max_verdict_age_days = days;
+        }
+        Ok(PolicyConfig {
+            l1: finish_model_ref(name, "l1", l1)?,
+            l2: finish_model_ref(name, "l2", l2)?,
+            cache: cache_config,
+            verdict_parsing: verdict_parsing.unwrap_or_default(),
+            on_garbled_text: on_garbled_text.unwrap_or_default(),
+            on_version_mismatch: on_version_mismatch.unwrap_or_default(),
+            on_low_confidence: on_low_confidence.unwrap_or_default(),
+            on_encrypted: on_encrypted.unwrap_or_default(),
+            on_extractor_unavailable: on_extractor_unavailable.unwrap_or_default(),
+            content_types,
+            url_allowlist,
+            active_probing: active_probing.unwrap_or_default(),
+            locale,
+            remediation: remediation.unwrap_or_default(),
+        })
+    }
+
+    pub fn resolve_all(&self) -> Result<BTreeMap<String, PolicyConfig>> {
+        self.policies
+            .keys()
+            .map(|name| Ok((name.clone(), self.resolve(name)?)))
+            .collect()
+    }
+}
+
 impl PoliciesFile {
     pub fn load(path: &Path) -> Result<Self> {
-        let raw = fs::read_to_string(path)
-            .with_context(|| format!("failed reading policies file: {}", path.display()))?;
-        let pf: PoliciesFile = serde_json::from_str(&raw)
-            .with_context(|| format!("invalid JSON in policies file: {}", path.display()))?;
-        if !pf.policies.contains_key("default") {
-            return Err(anyhow!("policies file must include a 'default' policy"));
-        }
-        Ok(pf)
+        let declared = load_declared(path)?;
+        Ok(Self {
+            policies: declared.resolve_all()?,
+        })
+    }
+}
+
+fn load_declared(path: &Path) -> Result<DeclaredPolicies> {
+    let raw = fs::read_to_string(path)
+        .with_context(|| format!("failed reading policies file: {}", path.display()))?;
+    let declared = DeclaredPolicies::parse(&raw)
+        .with_context(|| format!("invalid policies file: {}", path.display()))?;
+    if !declared.policies.contains_key("default") {
+        return Err(anyhow!("policies file must include a 'default' policy"));
     }
+    Ok(declared)
 }
 
 /// In-memory policy store.
 #[derive(Debug, Clone)]
 pub struct PolicyStore {
     policies: BTreeMap<String, PolicyConfig>,
+    declared: BTreeMap<String, PolicyDecl>,
 }
 
 impl PolicyStore {
     pub fn from_file(pf: PoliciesFile) -> Self {
+        let declared = pf
+            .policies
+            .iter()
+            .map(|(k, v)| (k.clone(), PolicyDecl::from_resolved(v)))
+            .collect();
         Self {
             policies: pf.policies,
+            declared,
         }
     }
 
+    /// Build from declared (sparse) policies, resolving inheritance.
+    pub fn from_declared(declared: DeclaredPolicies) -> Result<Self> {
+        let policies = declared.resolve_all()?;
+        Ok(Self {
+            policies,
+            declared: declared.policies,
+        })
+    }
+
+    /// Load a policies file, keeping both the declared and resolved forms.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_declared(load_declared(path)?)
+    }
+
     pub fn default_from_env(
         l1_provider: Provider,
         l1_model: String,
@@ -47,27 +361,82 @@ impl PolicyStore {
         policies.insert(
             "default".to_string(),
             PolicyConfig {
-                l1: crate::model_policy::ModelRef {
+                l1: ModelRef {
                     provider: l1_provider,
                     model: l1_model,
+                    required_model_version: None,
+                    consistency_check: false,
+                    context_tokens: None,
                 },
-                l2: crate::model_policy::ModelRef {
+                l2: ModelRef {
                     provider: l2_provider,
                     model: l2_model,
+                    required_model_version: None,
+                    consistency_check: false,
+                    context_tokens: None,
                 },
+                cache: CacheConfig::default(),
+                verdict_parsing: VerdictParsing::default(),
+                on_garbled_text: GarbledTextHandling::default(),
+                on_version_mismatch: VersionMismatchHandling::default(),
+                on_low_confidence: LowConfidenceHandling::default(),
+                on_encrypted: EncryptedHandling::default(),
+                on_extractor_unavailable: ExtractorUnavailableHandling::default(),
+                content_types: vec![],
+                url_allowlist: UrlAllowlistConfig::default(),
+                active_probing: false,
+                locale: None,
+                remediation: RemediationPolicy::default(),
             },
         );
-        Self { policies }
+        Self::from_file(PoliciesFile { policies })
     }
 
     pub fn list(&self) -> Vec<String> {
         self.policies.keys().cloned().collect()
     }
 
+    /// Resolved (effective) policy.
     pub fn get(&self, name: &str) -> Option<&PolicyConfig> {
         self.policies.get(name)
     }
 
+    /// Policy as declared on disk (sparse when it uses `extends`).
+    pub fn declared(&self, name: &str) -> Option<&PolicyDecl> {
+        self.declared.get(name)
+    }
+
+    /// `extends` chain for `name`, starting with `name` itself.
+    pub fn chain(&self, name: &str) -> Vec<String> {
+        let mut chain = vec![];
+        let mut cur = Some(name.to_string());
+        while let Some(n) = cur {
+            let Some(decl) = self.declared.get(&n) else {
+                break;
+            };
+            cur = decl.extends.clone();
+            chain.push(n);
+        }
+        chain
+    }
+
+    /// Cache-invalidation revision for a policy.
+    ///
+    /// Derived from the declared form of the policy *and every ancestor*, so editing a parent
+    /// changes the revision of all policies that extend it.
+    pub fn revision(&self, name: &str) -> Option<String> {
+        if !self.policies.contains_key(name) {
+            return None;
+        }
+        let decls: Vec<(&String, &PolicyDecl)> = self
+            .chain(name)
+            .iter()
+            .filter_map(|n| self.declared.get_key_value(n))
+            .collect();
+        let raw = serde_json::to_vec(&decls).ok()?;
+        Some(hex::encode(&Sha256::digest(&raw)[..8]))
+    }
+
     /// Require a policy to exist; returns a cloned PolicyConfig.
     pub fn require(&self, name: &str) -> Result<PolicyConfig> {
         self.get(name)
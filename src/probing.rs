@@ -0,0 +1,1116 @@
+//! Active probes of the hosts an escalated document links to (opt-in).
+//!
+//! The URL scanner is lexical by design ([`crate::feeds::FeedRegistry::assess_urls`]). With
+//! `[probing] enabled = true`, a policy with `active_probing = true` also gets live metadata
+//! about the linked hosts of a document the local scanners already escalated (`threat_score`
+//! at least `min_threat_score`). For each host, at most once per `cache_ttl_secs`:
+//!
+//! - `dns`: an A query to `dns_server` (default: the first `nameserver` of `/etc/resolv.conf`);
+//! - `rdap`: `GET <rdap_endpoint>/domain/<registrable domain>` for the registration date;
+//! - `tls`: a handshake on the link's port (443 for `http` links), checked against the web PKI
+//!   roots and `tls_ca_file`;
+//! - `head`: a `HEAD` of the link keeping only status, `content-type` and `content-length`.
+//!   Redirects are followed by hand, at most `max_redirects` of them, and stop at the first
+//!   hop to another registrable domain.
+//!
+//! No probe sends a body, and none reads one apart from the RDAP answer. Every probe goes
+//! through [`Egress`], which allows mechanisms rather than destinations: the mechanism must be
+//! in `mechanisms`, the global `probes_per_minute` budget must have room, the host must resolve
+//! to a public address (unless `allow_private_addresses`), and each step is bounded by
+//! `timeout_ms`. At most `max_urls_per_document` hosts are probed per document, in order of
+//! appearance; allowlisted hosts never are.
+//!
+//! Findings become detected patterns `probe:<finding>:<host>` (stage `probe`) with a threat
+//! score: a domain that does not exist, one registered within `recent_registration_days`, a
+//! certificate that is not valid for the host, and a redirect to another registrable domain.
+//! Probing never runs in heuristic-only (`ACIP_SENTRY_MODE=stub` or `stub-open`) or read-only
+//! mode. `/v1/acip/status` shows `probing.enabled`, with a notice while it is on.
+
+use crate::config::ProbingConfig;
+use crate::model_policy::PolicyConfig;
+use crate::reputation::{Clock, SystemClock};
+use crate::threat::{AttackType, DetectedPattern, ScanStage, ThreatAssessment};
+use crate::url_allowlist;
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    future::Future,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::Duration,
+};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{pem::PemObject, CertificateDer, ServerName},
+};
+use url::{Host, Url};
+
+pub const DEFAULT_MIN_THREAT_SCORE: u8 = 10;
+pub const DEFAULT_MAX_URLS_PER_DOCUMENT: usize = 5;
+pub const DEFAULT_PROBES_PER_MINUTE: u32 = 60;
+pub const DEFAULT_TIMEOUT_MS: u64 = 2000;
+pub const DEFAULT_CACHE_TTL_SECS: u64 = 3600;
+pub const DEFAULT_MAX_REDIRECTS: usize = 3;
+pub const DEFAULT_RECENT_REGISTRATION_DAYS: u32 = 30;
+/// Hosts kept in the result cache; expired entries are dropped first, then the oldest.
+pub const MAX_CACHED_HOSTS: usize = 10_000;
+/// Largest RDAP answer read.
+pub const MAX_RDAP_BYTES: usize = 256 * 1024;
+/// Shown under `probing.notice` in `/v1/acip/status` while probing is on.
+pub const STATUS_NOTICE: &str = "active probing is on: hosts linked from escalated documents \
+                                 are contacted (DNS, RDAP, TLS handshake, HEAD)";
+
+const DAY_SECS: u64 = 86_400;
+/// Julian day number of 1970-01-01.
+const UNIX_EPOCH_JULIAN_DAY: i64 = 2_440_588;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Mechanism {
+    Dns,
+    Rdap,
+    Tls,
+    Head,
+}
+
+impl Mechanism {
+    pub const ALL: [Mechanism; 4] = [
+        Mechanism::Dns,
+        Mechanism::Rdap,
+        Mechanism::Tls,
+        Mechanism::Head,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Finding {
+    /// DNS answered NXDOMAIN, or RDAP knows no such registration.
+    NonexistentDomain,
+    /// Registered within `recent_registration_days`.
+    RecentlyRegistered,
+    /// The host's certificate chains to a trusted root but does not name the host.
+    CertMismatch,
+    /// A `head` probe was redirected to another registrable domain.
+    CrossDomainRedirect,
+}
+
+impl Finding {
+    pub const ALL: [Finding; 4] = [
+        Finding::NonexistentDomain,
+        Finding::RecentlyRegistered,
+        Finding::CertMismatch,
+        Finding::CrossDomainRedirect,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Finding::NonexistentDomain => "nonexistent_domain",
+            Finding::RecentlyRegistered => "recently_registered",
+            Finding::CertMismatch => "cert_mismatch",
+            Finding::CrossDomainRedirect => "cross_domain_redirect",
+        }
+    }
+
+    /// Threat score the finding adds.
+    pub fn score(self) -> u8 {
+        match self {
+            Finding::NonexistentDomain => 8,
+            Finding::RecentlyRegistered | Finding::CertMismatch => 6,
+            Finding::CrossDomainRedirect => 4,
+        }
+    }
+
+    /// `probe:<finding>:<host>`.
+    pub fn indicator(self, host: &str) -> String {
+        format!("probe:{}:{host}", self.as_str())
+    }
+}
+
+/// Why a probe step did not run or did not finish.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Refusal {
+    /// Not in `mechanisms`, or nothing configured to ask (`rdap_endpoint`, `dns_server`).
+    NotAllowed,
+    /// `probes_per_minute` is used up.
+    RateLimited,
+    /// The host resolved to a loopback, private or link-local address.
+    PrivateAddress,
+    Timeout,
+    Failed(String),
+}
+
+impl Refusal {
+    fn key(&self) -> &'static str {
+        match self {
+            Refusal::NotAllowed => "not_allowed",
+            Refusal::RateLimited => "rate_limited",
+            Refusal::PrivateAddress => "private_address",
+            Refusal::Timeout => "timeout",
+            Refusal::Failed(_) => "failed",
+        }
+    }
+}
+
+fn failed(e: impl Display) -> Refusal {
+    Refusal::Failed(e.to_string())
+}
+
+/// A validated `[probing]` section.
+#[derive(Debug, Clone)]
+pub struct ProbeSettings {
+    pub enabled: bool,
+    pub mechanisms: BTreeSet<Mechanism>,
+    /// Ends in `/`.
+    pub rdap_endpoint: Option<Url>,
+    pub dns_server: Option<SocketAddr>,
+    pub min_threat_score: u8,
+    pub max_urls_per_document: usize,
+    pub probes_per_minute: u32,
+    pub timeout: Duration,
+    pub cache_ttl_secs: u64,
+    pub max_redirects: usize,
+    pub recent_registration_days: u32,
+    /// Extra trust roots from `tls_ca_file`.
+    pub extra_roots: Vec<CertificateDer<'static>>,
+    pub allow_private_addresses: bool,
+}
+
+impl Default for ProbeSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mechanisms: Mechanism::ALL.into_iter().collect(),
+            rdap_endpoint: None,
+            dns_server: None,
+            min_threat_score: DEFAULT_MIN_THREAT_SCORE,
+            max_urls_per_document: DEFAULT_MAX_URLS_PER_DOCUMENT,
+            probes_per_minute: DEFAULT_PROBES_PER_MINUTE,
+            timeout: Duration::from_millis(DEFAULT_TIMEOUT_MS),
+            cache_ttl_secs: DEFAULT_CACHE_TTL_SECS,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            recent_registration_days: DEFAULT_RECENT_REGISTRATION_DAYS,
+            extra_roots: vec![],
+            allow_private_addresses: false,
+        }
+    }
+}
+
+impl ProbeSettings {
+    pub fn from_config(cfg: Option<&ProbingConfig>) -> Result<Self> {
+        let d = Self::default();
+        let Some(cfg) = cfg else {
+            return Ok(d);
+        };
+        let enabled = cfg.enabled.unwrap_or(false);
+        let mechanisms = match &cfg.mechanisms {
+            Some(m) => m.iter().copied().collect(),
+            None => d.mechanisms,
+        };
+        let rdap_endpoint = cfg
+            .rdap_endpoint
+            .as_deref()
+            .map(parse_rdap_endpoint)
+            .transpose()?;
+        let dns_server = match cfg.dns_server.as_deref() {
+            Some(raw) => Some(parse_dns_server(raw)?),
+            None if enabled && mechanisms.contains(&Mechanism::Dns) => {
+                let conf = std::fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+                let Some(server) = resolv_conf_nameserver(&conf) else {
+                    bail!("probing.dns_server is not set and /etc/resolv.conf names no nameserver");
+                };
+                Some(server)
+            }
+            None => None,
+        };
+        let extra_roots = match cfg.tls_ca_file.as_deref() {
+            Some(path) => read_roots(path)?,
+            None => vec![],
+        };
+        Ok(Self {
+            enabled,
+            mechanisms,
+            rdap_endpoint,
+            dns_server,
+            min_threat_score: cfg.min_threat_score.unwrap_or(d.min_threat_score),
+            max_urls_per_document: cfg.max_urls_per_document.unwrap_or(d.max_urls_per_document),
+            probes_per_minute: cfg.probes_per_minute.unwrap_or(d.probes_per_minute),
+            timeout: cfg
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(d.timeout),
+            cache_ttl_secs: cfg.cache_ttl_secs.unwrap_or(d.cache_ttl_secs),
+            max_redirects: cfg.max_redirects.unwrap_or(d.max_redirects),
+            recent_registration_days: cfg
+                .recent_registration_days
+                .unwrap_or(d.recent_registration_days),
+            extra_roots,
+            allow_private_addresses: cfg.allow_private_addresses.unwrap_or(false),
+        })
+    }
+}
+
+/// `https://`, or `http://` to a loopback host, with a trailing `/` so paths join under it.
+fn parse_rdap_endpoint(raw: &str) -> Result<Url> {
+    let mut url = Url::parse(raw).with_context(|| format!("probing.rdap_endpoint {raw:?}"))?;
+    let loopback = match url.host() {
+        Some(Host::Domain(d)) => d.eq_ignore_ascii_case("localhost"),
+        Some(Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    };
+    match url.scheme() {
+        "https" => {}
+        "http" if loopback => {}
+        _ => {
+            bail!("probing.rdap_endpoint {raw:?} must be https:// (or http:// to a loopback host)")
+        }
+    }
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+/// `ip:port`, or an address alone for port 53.
+fn parse_dns_server(raw: &str) -> Result<SocketAddr> {
+    let raw = raw.trim();
+    if let Ok(addr) = raw.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    match raw.parse::<IpAddr>() {
+        Ok(ip) => Ok(SocketAddr::new(ip, 53)),
+        Err(_) => bail!("probing.dns_server {raw:?} is not an ip:port"),
+    }
+}
+
+/// The first `nameserver` line of a `resolv.conf`.
+fn resolv_conf_nameserver(conf: &str) -> Option<SocketAddr> {
+    conf.lines()
+        .filter_map(|l| l.trim().strip_prefix("nameserver"))
+        .filter_map(|rest| rest.trim().split('%').next()?.parse::<IpAddr>().ok())
+        .map(|ip| SocketAddr::new(ip, 53))
+        .next()
+}
+
+fn read_roots(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("probing.tls_ca_file {path}"))?;
+    let roots = CertificateDer::pem_slice_iter(&pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("probing.tls_ca_file {path}: {e:?}"))?;
+    if roots.is_empty() {
+        bail!("probing.tls_ca_file {path} holds no certificate");
+    }
+    let mut store = rustls::RootCertStore::empty();
+    for root in &roots {
+        store
+            .add(root.clone())
+            .map_err(|e| anyhow::anyhow!("probing.tls_ca_file {path}: {e}"))?;
+    }
+    Ok(roots)
+}
+
+/// A loopback, private, link-local, shared or otherwise non-global address.
+///
+/// IPv6 addresses that embed an IPv4 one (IPv4-mapped, NAT64 `64:ff9b::/96`, 6to4
+/// `2002::/16`) are judged by the embedded address.
+pub fn is_private_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, c, _] = v4.octets();
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                // 0.0.0.0/8, "this network".
+                || a == 0
+                // 100.64.0.0/10, carrier-grade NAT.
+                || (a == 100 && (b & 0xc0) == 64)
+                // 192.0.0.0/24, IETF protocol assignments.
+                || (a == 192 && b == 0 && c == 0)
+                // 198.18.0.0/15, benchmarking.
+                || (a == 198 && (b & 0xfe) == 18)
+                // 240.0.0.0/4, reserved.
+                || a >= 240
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_ipv4(v6) {
+                return is_private_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // fc00::/7 unique local, fe80::/10 link-local.
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// The IPv4 address carried by an IPv4-mapped, NAT64 (`64:ff9b::/96`) or 6to4 (`2002::/16`)
+/// address.
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return Some(v4);
+    }
+    let s = v6.segments();
+    let from = |hi: u16, lo: u16| Ipv4Addr::from((u32::from(hi) << 16) | u32::from(lo));
+    match s {
+        [0x64, 0xff9b, 0, 0, 0, 0, hi, lo] => Some(from(hi, lo)),
+        [0x2002, hi, lo, ..] => Some(from(hi, lo)),
+        _ => None,
+    }
+}
+
+/// What a DNS server said about a name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// NXDOMAIN.
+    NoSuchDomain,
+    /// The A records; empty when the name exists without one.
+    Addresses(Vec<Ipv4Addr>),
+}
+
+/// What RDAP said about a registrable domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Registration {
+    NotFound,
+    /// Days since the `registration` event, when there is one.
+    Registered {
+        days_ago: Option<i64>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "result")]
+pub enum TlsOutcome {
+    Valid,
+    /// Chains to a trusted root, but names other hosts.
+    NameMismatch {
+        presented: Vec<String>,
+    },
+    /// Untrusted, expired or otherwise unusable certificate.
+    InvalidCertificate {
+        error: String,
+    },
+    /// No TLS answer on the port.
+    NoHandshake {
+        error: String,
+    },
+}
+
+/// One `HEAD` request of a `head` probe.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeadHop {
+    pub host: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_length: Option<u64>,
+    /// `Location` of a redirect.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+}
+
+/// Everything probed about one host.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct HostReport {
+    pub host: String,
+    /// `false` on NXDOMAIN.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exists: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub addresses: Vec<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registered_days_ago: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsOutcome>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub head: Vec<HeadHop>,
+    /// The redirect chain went on past `max_redirects`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub redirects_truncated: bool,
+    pub findings: BTreeSet<Finding>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub refused: BTreeMap<Mechanism, Refusal>,
+}
+
+impl HostReport {
+    fn refuse(&mut self, m: Mechanism, r: Refusal) {
+        self.refused.entry(m).or_insert(r);
+    }
+}
+
+/// The one way out for probes: a mechanism allowlist, the global per-minute budget, the
+/// address policy and the per-step timeout.
+pub struct Egress {
+    mechanisms: BTreeSet<Mechanism>,
+    rdap_endpoint: Option<Url>,
+    dns_server: Option<SocketAddr>,
+    timeout: Duration,
+    per_minute: u32,
+    allow_private: bool,
+    tls: Arc<rustls::ClientConfig>,
+    http_roots: Vec<reqwest::Certificate>,
+    clock: Arc<dyn Clock>,
+    /// Start of the current minute and the probes spent in it.
+    window: Mutex<(u64, u32)>,
+    query_id: AtomicU16,
+}
+
+impl Egress {
+    fn new(settings: &ProbeSettings, clock: Arc<dyn Clock>) -> Self {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        for root in &settings.extra_roots {
+            // Checked by `read_roots`.
+            let _ = roots.add(root.clone());
+        }
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let tls = rustls::ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .expect("ring supports the default protocol versions")
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let http_roots = settings
+            .extra_roots
+            .iter()
+            .filter_map(|c| reqwest::Certificate::from_der(c.as_ref()).ok())
+            .collect();
+        let seed = SystemClock.now_unix() as u16;
+        Self {
+            mechanisms: settings.mechanisms.clone(),
+            rdap_endpoint: settings.rdap_endpoint.clone(),
+            dns_server: settings.dns_server,
+            timeout: settings.timeout,
+            per_minute: settings.probes_per_minute,
+            allow_private: settings.allow_private_addresses,
+            tls: Arc::new(tls),
+            http_roots,
+            clock,
+            window: Mutex::new((0, 0)),
+            query_id: AtomicU16::new(seed),
+        }
+    }
+
+    /// Spend one probe of `m` from the budget.
+    fn admit(&self, m: Mechanism) -> Result<(), Refusal> {
+        if !self.mechanisms.contains(&m) {
+            return Err(Refusal::NotAllowed);
+        }
+        let now = self.clock.now_unix();
+        let mut window = self.window.lock().unwrap();
+        if now >= window.0 + 60 {
+            *window = (now, 0);
+        }
+        if window.1 >= self.per_minute {
+            return Err(Refusal::RateLimited);
+        }
+        window.1 += 1;
+        Ok(())
+    }
+
+    fn check_address(&self, ip: IpAddr) -> Result<(), Refusal> {
+        if !self.allow_private && is_private_address(ip) {
+            return Err(Refusal::PrivateAddress);
+        }
+        Ok(())
+    }
+
+    async fn bounded<T>(&self, f: impl Future<Output = Result<T, Refusal>>) -> Result<T, Refusal> {
+        tokio::time::timeout(self.timeout, f)
+            .await
+            .unwrap_or(Err(Refusal::Timeout))
+    }
+
+    pub async fn dns(&self, host: &str) -> Result<Resolution, Refusal> {
+        let server = self.dns_server.ok_or(Refusal::NotAllowed)?;
+        self.admit(Mechanism::Dns)?;
+        let id = self.query_id.fetch_add(1, Ordering::Relaxed);
+        let query = dns_query(id, host).ok_or_else(|| failed("not a DNS name"))?;
+        self.bounded(async move {
+            let bind: SocketAddr = match server {
+                SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+            };
+            let socket = UdpSocket::bind(bind).await.map_err(failed)?;
+            socket.connect(server).await.map_err(failed)?;
+            socket.send(&query).await.map_err(failed)?;
+            let mut buf = [0u8; 1500];
+            loop {
+                let n = socket.recv(&mut buf).await.map_err(failed)?;
+                if let Some(answer) = dns_answer(id, &buf[..n]) {
+                    return answer;
+                }
+            }
+        })
+        .await
+    }
+
+    pub async fn rdap(&self, domain: &str, today: i64) -> Result<Registration, Refusal> {
+        let endpoint = self.rdap_endpoint.as_ref().ok_or(Refusal::NotAllowed)?;
+        self.admit(Mechanism::Rdap)?;
+        let url = endpoint.join(&format!("domain/{domain}")).map_err(failed)?;
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(failed)?;
+        self.bounded(async move {
+            let mut resp = client
+                .get(url)
+                .header(reqwest::header::ACCEPT, "application/rdap+json")
+                .send()
+                .await
+                .map_err(failed)?;
+            if resp.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(Registration::NotFound);
+            }
+            if !resp.status().is_success() {
+                return Err(failed(format!("rdap answered {}", resp.status())));
+            }
+            let mut body = Vec::new();
+            while let Some(chunk) = resp.chunk().await.map_err(failed)? {
+                body.extend_from_slice(&chunk);
+                if body.len() > MAX_RDAP_BYTES {
+                    return Err(failed("rdap answer too large"));
+                }
+            }
+            let v: Value = serde_json::from_slice(&body).map_err(failed)?;
+            Ok(Registration::Registered {
+                days_ago: registration_day(&v).map(|day| today - day),
+            })
+        })
+        .await
+    }
+
+    pub async fn tls(&self, host: &str, addr: IpAddr, port: u16) -> Result<TlsOutcome, Refusal> {
+        self.check_address(addr)?;
+        self.admit(Mechanism::Tls)?;
+        let name = ServerName::try_from(host.to_string()).map_err(failed)?;
+        let connector = tokio_rustls::TlsConnector::from(self.tls.clone());
+        self.bounded(async move {
+            let tcp = match TcpStream::connect((addr, port)).await {
+                Ok(tcp) => tcp,
+                Err(e) => {
+                    return Ok(TlsOutcome::NoHandshake {
+                        error: e.to_string(),
+                    })
+                }
+            };
+            Ok(match connector.connect(name, tcp).await {
+                Ok(_) => TlsOutcome::Valid,
+                Err(e) => tls_failure(e),
+            })
+        })
+        .await
+    }
+
+    /// One `HEAD` of `url` at `addr`, without following redirects.
+    pub async fn head(&self, url: &Url, addr: IpAddr) -> Result<HeadHop, Refusal> {
+        self.check_address(addr)?;
+        self.admit(Mechanism::Head)?;
+        let host = url.host_str().ok_or_else(|| failed("no host"))?.to_string();
+        let mut builder = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy();
+        if let Some(Host::Domain(domain)) = url.host() {
+            builder = builder.resolve(domain, SocketAddr::new(addr, 0));
+        }
+        for root in &self.http_roots {
+            builder = builder.add_root_certificate(root.clone());
+        }
+        let client = builder.build().map_err(failed)?;
+        let resp = self
+            .bounded(async { client.head(url.clone()).send().await.map_err(failed) })
+            .await?;
+        let header = |name: reqwest::header::HeaderName| {
+            resp.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Ok(HeadHop {
+            host,
+            status: resp.status().as_u16(),
+            content_type: header(reqwest::header::CONTENT_TYPE),
+            content_length: header(reqwest::header::CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+            location: resp
+                .status()
+                .is_redirection()
+                .then(|| header(reqwest::header::LOCATION))
+                .flatten(),
+        })
+    }
+}
+
+/// A recursive A query for `host`.
+fn dns_query(id: u16, host: &str) -> Option<Vec<u8>> {
+    let mut q = Vec::with_capacity(host.len() + 18);
+    q.extend_from_slice(&id.to_be_bytes());
+    // Recursion desired; one question.
+    q.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in host.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return None;
+        }
+        q.push(label.len() as u8);
+        q.extend_from_slice(label.as_bytes());
+    }
+    q.push(0);
+    // QTYPE A, QCLASS IN.
+    q.extend_from_slice(&[0, 1, 0, 1]);
+    Some(q)
+}
+
+/// The answer to query `id` in `msg`; `None` for anything else.
+fn dns_answer(id: u16, msg: &[u8]) -> Option<Result<Resolution, Refusal>> {
+    let u16_at = |pos: usize| -> Option<u16> {
+        Some(u16::from_be_bytes([*msg.get(pos)?, *msg.get(pos + 1)?]))
+    };
+    if u16_at(0)? != id || msg.len() < 12 {
+        return None;
+    }
+    let flags = u16_at(2)?;
+    if flags & 0x8000 == 0 {
+        return None;
+    }
+    match flags & 0x000f {
+        0 => {}
+        3 => return Some(Ok(Resolution::NoSuchDomain)),
+        rcode => return Some(Err(failed(format!("dns rcode {rcode}")))),
+    }
+    let malformed = || Some(Err(failed("malformed dns answer")));
+    let (questions, answers) = (u16_at(4)?, u16_at(6)?);
+    let mut pos = 12;
+    for _ in 0..questions {
+        let Some(end) = skip_name(msg, pos) else {
+            return malformed();
+        };
+        pos = end + 4;
+    }
+    let mut addresses = vec![];
+    for _ in 0..answers {
+        let Some(end) = skip_name(msg, pos) else {
+            return malformed();
+        };
+        let (Some(ty), Some(len)) = (u16_at(end), u16_at(end + 8)) else {
+            return malformed();
+        };
+        let data = end + 10;
+        let Some(rdata) = msg.get(data..data + len as usize) else {
+            return malformed();
+        };
+        if ty == 1 && rdata.len() == 4 {
+            addresses.push(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]));
+        }
+        pos = data + len as usize;
+    }
+    Some(Ok(Resolution::Addresses(addresses)))
+}
+
+/// Offset just past the (possibly compressed) name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)?;
+        if len == 0 {
+            return Some(pos + 1);
+        }
+        if len & 0xc0 == 0xc0 {
+            return Some(pos + 2);
+        }
+        pos += 1 + len as usize;
+    }
+}
+
+/// Day number (days since 1970-01-01) of the `registration` event of an RDAP domain object.
+fn registration_day(v: &Value) -> Option<i64> {
+    let date = v["events"]
+        .as_array()?
+        .iter()
+        .find(|e| e["eventAction"] == "registration")?["eventDate"]
+        .as_str()?;
+    let mut parts = date.get(..10)?.split('-');
+    let year: i32 = parts.next()?.parse().ok()?;
+    let month: u8 = parts.next()?.parse().ok()?;
+    let day: u8 = parts.next()?.parse().ok()?;
+    let date =
+        time::Date::from_calendar_date(year, time::Month::try_from(month).ok()?, day).ok()?;
+    Some(i64::from(date.to_julian_day()) - UNIX_EPOCH_JULIAN_DAY)
+}
+
+fn tls_failure(e: std::io::Error) -> TlsOutcome {
+    use rustls::{CertificateError, Error};
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+        Some(Error::InvalidCertificate(CertificateError::NotValidForNameContext {
+            presented,
+            ..
+        })) => TlsOutcome::NameMismatch {
+            presented: presented.iter().map(|n| san_value(n)).collect(),
+        },
+        Some(Error::InvalidCertificate(CertificateError::NotValidForName)) => {
+            TlsOutcome::NameMismatch { presented: vec![] }
+        }
+        Some(Error::InvalidCertificate(c)) => TlsOutcome::InvalidCertificate {
+            error: format!("{c:?}"),
+        },
+        _ => TlsOutcome::NoHandshake {
+            error: e.to_string(),
+        },
+    }
+}
+
+/// `good.test` of rustls' `DnsName("good.test")`.
+fn san_value(presented: &str) -> String {
+    presented
+        .split_once("(\"")
+        .and_then(|(_, rest)| rest.strip_suffix("\")"))
+        .unwrap_or(presented)
+        .to_string()
+}
+
+/// `http(s)://` links of `text`, the first one for each host, in order.
+pub fn links(text: &str) -> Vec<(String, Url)> {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    let re = LINK.get_or_init(|| Regex::new(r#"(?i)\bhttps?://[^\s"'<>()\[\]{}]+"#).unwrap());
+    let mut seen = BTreeSet::new();
+    re.find_iter(text)
+        .filter_map(|m| Url::parse(m.as_str()).ok())
+        .filter_map(|url| {
+            let host = host_key(&url)?;
+            seen.insert(host.clone()).then_some((host, url))
+        })
+        .collect()
+}
+
+/// The host of `url` as probes and the cache name it.
+fn host_key(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Domain(d) => url_allowlist::normalize_host(d),
+        Host::Ipv4(ip) => Some(ip.to_string()),
+        Host::Ipv6(ip) => Some(ip.to_string()),
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+struct Counters {
+    documents: u64,
+    hosts_probed: u64,
+    cache_hits: u64,
+    /// Hosts left out by `max_urls_per_document`.
+    hosts_over_cap: u64,
+    refusals: BTreeMap<&'static str, u64>,
+    findings: BTreeMap<&'static str, u64>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Host -> (expiry, report).
+    cache: HashMap<String, (u64, HostReport)>,
+    counters: Counters,
+}
+
+/// Active link probing: settings, [`Egress`] and the per-host result cache.
+pub struct Prober {
+    settings: ProbeSettings,
+    egress: Egress,
+    clock: Arc<dyn Clock>,
+    inner: Mutex<Inner>,
+}
+
+impl Default for Prober {
+    /// Disabled.
+    fn default() -> Self {
+        Self::new(ProbeSettings::default())
+    }
+}
+
+impl Prober {
+    pub fn new(settings: ProbeSettings) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Self {
+            egress: Egress::new(&settings, clock.clone()),
+            settings,
+            clock,
+            inner: Mutex::new(Inner::default()),
+        }
+    }
+
+    pub fn from_config(cfg: Option<&ProbingConfig>) -> Result<Self> {
+        Ok(Self::new(ProbeSettings::from_config(cfg)?))
+    }
+
+    /// Time source for the cache, the rate budget and registration ages.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.egress = Egress::new(&self.settings, clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    pub fn settings(&self) -> &ProbeSettings {
+        &self.settings
+    }
+
+    pub fn egress(&self) -> &Egress {
+        &self.egress
+    }
+
+    /// Whether a document under `policy` with local `threat_score` gets probed.
+    pub fn applies(
+        &self,
+        policy: Option<&PolicyConfig>,
+        threat_score: u8,
+        heuristic_only: bool,
+        read_only: bool,
+    ) -> bool {
+        self.settings.enabled
+            && !heuristic_only
+            && !read_only
+            && policy.is_some_and(|p| p.active_probing)
+            && threat_score >= self.settings.min_threat_score
+    }
+
+    /// Probe the hosts `text` links to, except those `threat.urls` marks allowlisted, and add
+    /// the findings to `threat`.
+    pub async fn probe_document(
+        &self,
+        text: &str,
+        threat: &mut ThreatAssessment,
+    ) -> Vec<HostReport> {
+        let allowlisted: BTreeSet<&str> = threat
+            .urls
+            .iter()
+            .filter(|u| u.allowlisted)
+            .map(|u| u.host.as_str())
+            .collect();
+        let mut candidates: Vec<(String, Url)> = links(text)
+            .into_iter()
+            .filter(|(host, _)| !allowlisted.contains(host.as_str()))
+            .collect();
+        let over_cap = candidates
+            .len()
+            .saturating_sub(self.settings.max_urls_per_document);
+        candidates.truncate(self.settings.max_urls_per_document);
+
+        let now = self.clock.now_unix();
+        let mut cached = vec![];
+        let mut fresh = vec![];
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.counters.documents += 1;
+            inner.counters.hosts_over_cap += over_cap as u64;
+            for (host, url) in candidates {
+                match inner.cache.get(&host) {
+                    Some((expires, report)) if *expires > now => cached.push(report.clone()),
+                    _ => fresh.push((host, url)),
+                }
+            }
+            inner.counters.cache_hits += cached.len() as u64;
+        }
+
+        let probed = futures_util::future::join_all(
+            fresh.iter().map(|(host, url)| self.probe_host(host, url)),
+        )
+        .await;
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let expires = now + self.settings.cache_ttl_secs;
+            for report in &probed {
+                inner.counters.hosts_probed += 1;
+                for r in report.refused.values() {
+                    *inner.counters.refusals.entry(r.key()).or_default() += 1;
+                }
+                for f in &report.findings {
+                    *inner.counters.findings.entry(f.as_str()).or_default() += 1;
+                }
+                // A rate-limited probe is retried by the next document instead of cached.
+                if report.refused.values().any(|r| *r == Refusal::RateLimited) {
+                    continue;
+                }
+                inner
+                    .cache
+                    .insert(report.host.clone(), (expires, report.clone()));
+            }
+            if inner.cache.len() > MAX_CACHED_HOSTS {
+                inner.cache.retain(|_, (expires, _)| *expires > now);
+            }
+            while inner.cache.len() > MAX_CACHED_HOSTS {
+                let oldest = inner
+                    .cache
+                    .iter()
+                    .min_by_key(|(host, (expires, _))| (*expires, (*host).clone()))
+                    .map(|(host, _)| host.clone());
+                match oldest {
+                    Some(host) => inner.cache.remove(&host),
+                    None => break,
+                };
+            }
+        }
+
+        let reports: Vec<HostReport> = cached.into_iter().chain(probed).collect();
+        for report in &reports {
+            for f in &report.findings {
+                let indicator = f.indicator(&report.host);
+                threat.detected.push(DetectedPattern {
+                    indicator: indicator.clone(),
+                    stage: ScanStage::Probe,
+                });
+                threat.add(AttackType::SocialEngineering, indicator, f.score());
+            }
+        }
+        threat.normalize();
+        reports
+    }
+
+    async fn probe_host(&self, host: &str, url: &Url) -> HostReport {
+        let mut r = HostReport {
+            host: host.to_string(),
+            ..HostReport::default()
+        };
+        let literal = host.parse::<IpAddr>().ok();
+        let addr = match literal {
+            Some(ip) => Some(ip),
+            None => match self.egress.dns(host).await {
+                Ok(Resolution::NoSuchDomain) => {
+                    r.exists = Some(false);
+                    r.findings.insert(Finding::NonexistentDomain);
+                    None
+                }
+                Ok(Resolution::Addresses(addrs)) => {
+                    r.exists = Some(true);
+                    r.addresses = addrs.iter().copied().map(IpAddr::V4).collect();
+                    r.addresses.first().copied()
+                }
+                Err(e) => {
+                    r.refuse(Mechanism::Dns, e);
+                    None
+                }
+            },
+        };
+
+        if literal.is_none() && r.exists != Some(false) {
+            let today = (self.clock.now_unix() / DAY_SECS) as i64;
+            let domain = url_allowlist::registrable_domain(host);
+            match self.egress.rdap(domain, today).await {
+                Ok(Registration::NotFound) => {
+                    r.findings.insert(Finding::NonexistentDomain);
+                }
+                Ok(Registration::Registered { days_ago }) => {
+                    r.registered_days_ago = days_ago;
+                    if days_ago
+                        .is_some_and(|d| d <= i64::from(self.settings.recent_registration_days))
+                    {
+                        r.findings.insert(Finding::RecentlyRegistered);
+                    }
+                }
+                Err(e) => r.refuse(Mechanism::Rdap, e),
+            }
+        }
+
+        let Some(addr) = addr else {
+            return r;
+        };
+        let port = match url.scheme() {
+            "https" => url.port_or_known_default().unwrap_or(443),
+            _ => 443,
+        };
+        match self.egress.tls(host, addr, port).await {
+            Ok(outcome) => {
+                if matches!(outcome, TlsOutcome::NameMismatch { .. }) {
+                    r.findings.insert(Finding::CertMismatch);
+                }
+                r.tls = Some(outcome);
+            }
+            Err(e) => r.refuse(Mechanism::Tls, e),
+        }
+        self.follow(url.clone(), addr, &mut r).await;
+        r
+    }
+
+    /// `HEAD` along the redirect chain from `url`, at most `max_redirects` hops.
+    async fn follow(&self, mut url: Url, mut addr: IpAddr, r: &mut HostReport) {
+        let origin = url_allowlist::registrable_domain(&r.host).to_string();
+        for hop in 0..=self.settings.max_redirects {
+            let location = match self.egress.head(&url, addr).await {
+                Ok(h) => {
+                    let location = h.location.clone();
+                    r.head.push(h);
+                    location
+                }
+                Err(e) => {
+                    r.refuse(Mechanism::Head, e);
+                    return;
+                }
+            };
+            let Some(next) = location.and_then(|l| url.join(&l).ok()) else {
+                return;
+            };
+            if hop == self.settings.max_redirects {
+                r.redirects_truncated = true;
+                return;
+            }
+            let Some(next_host) = host_key(&next) else {
+                return;
+            };
+            if url_allowlist::registrable_domain(&next_host) != origin {
+                r.findings.insert(Finding::CrossDomainRedirect);
+                return;
+            }
+            if host_key(&url).as_deref() != Some(next_host.as_str()) {
+                match self.egress.dns(&next_host).await {
+                    Ok(Resolution::Addresses(addrs)) if !addrs.is_empty() => {
+                        addr = IpAddr::V4(addrs[0]);
+                    }
+                    Ok(_) => return,
+                    Err(e) => {
+                        r.refuse(Mechanism::Dns, e);
+                        return;
+                    }
+                }
+            }
+            url = next;
+        }
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        if !self.settings.enabled {
+            return json!({ "enabled": false });
+        }
+        let inner = self.inner.lock().unwrap();
+        let s = &self.settings;
+        let mut v = json!({
+            "enabled": true,
+            "notice": STATUS_NOTICE,
+            "mechanisms": s.mechanisms,
+            "rdap": s.rdap_endpoint.as_ref().and_then(|u| u.host_str()),
+            "min_threat_score": s.min_threat_score,
+            "max_urls_per_document": s.max_urls_per_document,
+            "probes_per_minute": s.probes_per_minute,
+            "timeout_ms": s.timeout.as_millis() as u64,
+            "cache_ttl_secs": s.cache_ttl_secs,
+            "max_redirects": s.max_redirects,
+            "allow_private_addresses": s.allow_private_addresses,
+            "cached_hosts": inner.cache.len(),
+        });
+        if let (Some(obj), Value::Object(counters)) = (
+            v.as_object_mut(),
+            serde_json::to_value(&inner.counters).unwrap_or_default(),
+        ) {
+            obj.extend(counters);
+        }
+        v
+    }
+}
@@ -0,0 +1,83 @@
+//! Read-only mode (`server.read_only = true`) for investigation instances.
+//!
+//! Such an instance serves the query API over a copy of production data and must not change
+//! it. Routes that change state answer `403 read_only_mode` ([`reject_writes`]); the stores are
+//! opened through [`open_reputation_store`] and [`open_stats_store`], which refuse to start on a
+//! backend they cannot open without write access; and the background tasks that write (temp
+//! dir sweeping, upload expiry) are not started.
+
+use crate::introspection;
+use crate::reputation::{self, Clock, ReputationStore};
+use crate::stats::{DecisionStats, StatsSettings};
+use anyhow::{bail, Result};
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{from_fn, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+/// `error` code of the `403` answered to a mutating request.
+pub const ERROR_CODE: &str = "read_only_mode";
+
+/// Refuse every request to `router` (403 `read_only_mode`) when `read_only` is set.
+pub fn reject_writes<S>(router: Router<S>, read_only: bool) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if !read_only {
+        return router;
+    }
+    router.layer(from_fn(read_only_middleware))
+}
+
+async fn read_only_middleware(req: Request, _next: Next) -> Response {
+    introspection::json_error(
+        StatusCode::FORBIDDEN,
+        ERROR_CODE,
+        json!({
+            "method": req.method().as_str(),
+            "path": req.uri().path(),
+        }),
+    )
+    .into_response()
+}
+
+/// The reputation backend named by `spec` (`ACIP_REPUTATION_STORE`), opened without write
+/// access. The file must already exist; unknown backends are refused.
+pub fn open_reputation_store(spec: &str) -> Result<Arc<dyn ReputationStore>> {
+    if let Some(path) = spec.strip_prefix("file:") {
+        return Ok(Arc::new(
+            reputation::JsonFileReputationStore::open_read_only(path)?,
+        ));
+    }
+    if spec == "memory" {
+        return Ok(Arc::new(reputation::ReadOnlyReputationStore(
+            reputation::InMemoryReputationStore::new(),
+        )));
+    }
+    bail!("reputation store {spec:?} cannot be opened read-only")
+}
+
+/// The stats backend named by `spec` (`ACIP_STATS_STORE`), opened without write access. The
+/// file must already exist; unknown backends are refused.
+pub fn open_stats_store(
+    spec: &str,
+    settings: StatsSettings,
+    clock: Arc<dyn Clock>,
+) -> Result<Arc<DecisionStats>> {
+    if let Some(path) = spec.strip_prefix("file:") {
+        return Ok(Arc::new(DecisionStats::open_read_only(
+            path, settings, clock,
+        )?));
+    }
+    if spec == "memory" {
+        return Ok(Arc::new(
+            DecisionStats::in_memory(settings, clock).into_read_only(),
+        ));
+    }
+    bail!("stats store {spec:?} cannot be opened read-only")
+}
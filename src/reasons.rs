@@ -0,0 +1,238 @@
+//! Deterministic assembly of `decision.reasons`.
+//!
+//! Every stage that explains a decision contributes [`Reason`]s to one [`ReasonSet`]. Rendering
+//! deduplicates by id and orders by stage, then id, so the final array does not depend on the
+//! order in which stages (or model providers) completed.
+
+use crate::sentry::Decision;
+use serde::Serialize;
+
+/// Upper bound on rendered reasons; pinned reasons are never evicted.
+pub const MAX_REASONS: usize = 32;
+
+/// Pipeline stages, in render order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonStage {
+    /// Model verdict (L1/L2), stub, or fail-closed explanation.
+    Sentry,
+    /// Tool cap for markup content.
+    Markup,
+    /// Caller tool authorization.
+    Authorization,
+    /// Source reputation.
+    Reputation,
+    /// Extracted text quality (garbled routing).
+    TextQuality,
+}
+
+impl ReasonStage {
+    /// Which instance survives when several stages report the same id: sidecar enforcement
+    /// outranks model prose.
+    fn priority(self) -> u8 {
+        match self {
+            ReasonStage::Sentry => 0,
+            ReasonStage::Reputation => 1,
+            ReasonStage::Markup | ReasonStage::Authorization | ReasonStage::TextQuality => 2,
+        }
+    }
+}
+
+/// Stable ids for reasons the sidecar emits itself, matched by message prefix.
+/// `pinned` reasons explain a tool cap or fail-closed verdict and survive [`MAX_REASONS`].
+const KNOWN_REASONS: &[(&str, &str, bool)] = &[
+    (
+        "tools hard-capped for markup content",
+        "tools.markup_cap",
+        true,
+    ),
+    (
+        "tools not authorized by caller",
+        "tools.not_authorized",
+        true,
+    ),
+    (
+        "tools hard-capped: source classified as bad actor",
+        "tools.bad_actor_cap",
+        true,
+    ),
+    (
+        "header_conflict: x-acip-allow-tools",
+        "headers.conflict.allow_tools",
+        true,
+    ),
+    (
+        "header_conflict: x-acip-policy",
+        "headers.conflict.policy",
+        true,
+    ),
+    ("source reputation:", "reputation.context", false),
+    ("sentry disabled", "sentry.disabled", true),
+    ("extracted text garbled", "text_quality.garbled", true),
+    ("L1 failed;", "sentry.fail_closed", true),
+    ("L1 low confidence;", "sentry.fail_closed", true),
+    ("low_confidence:", "sentry.low_confidence", true),
+    (
+        "model_version_mismatch:",
+        "sentry.model_version_mismatch",
+        true,
+    ),
+    (
+        "model_version_unvalidated:",
+        "sentry.model_version_unvalidated",
+        false,
+    ),
+];
+
+/// Sidecar reasons reported once per subject: the id is the fixed id, `:`, then the subject
+/// (the message up to the first space after the prefix).
+const KEYED_REASONS: &[(&str, &str)] = &[("reputation flagged: key=", "reputation.flagged")];
+
+/// Ids of the reasons the sidecar emits itself (keyed ones without their subject).
+pub fn known_ids() -> impl Iterator<Item = &'static str> {
+    KNOWN_REASONS
+        .iter()
+        .map(|(_, id, _)| *id)
+        .chain(KEYED_REASONS.iter().map(|(_, id)| *id))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reason {
+    pub stage: ReasonStage,
+    pub id: String,
+    pub message: String,
+    pub pinned: bool,
+    /// How many times this id was reported (across all stages).
+    pub count: u32,
+}
+
+impl Reason {
+    pub fn new(stage: ReasonStage, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let keyed = KEYED_REASONS.iter().find_map(|(prefix, id)| {
+            let rest = message.strip_prefix(prefix)?;
+            let subject = rest.split_whitespace().next().unwrap_or_default();
+            Some((format!("{id}:{subject}"), false))
+        });
+        let (id, pinned) = keyed
+            .or_else(|| {
+                KNOWN_REASONS
+                    .iter()
+                    .find(|(prefix, _, _)| message.starts_with(prefix))
+                    .map(|(_, id, pinned)| (id.to_string(), *pinned))
+            })
+            .unwrap_or_else(|| (normalize_id(&message), false));
+        Self {
+            stage,
+            id,
+            message,
+            pinned,
+            count: 1,
+        }
+    }
+
+    fn render(&self) -> String {
+        if self.count > 1 {
+            format!("{} (x{})", self.message, self.count)
+        } else {
+            self.message.clone()
+        }
+    }
+}
+
+/// Free-text id: lowercase, whitespace collapsed, trailing punctuation dropped.
+fn normalize_id(message: &str) -> String {
+    let collapsed = message
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    collapsed
+        .trim_end_matches(|c: char| c.is_ascii_punctuation())
+        .to_string()
+}
+
+/// Reasons collected across stages, deduplicated by id.
+#[derive(Debug, Clone, Default)]
+pub struct ReasonSet {
+    items: Vec<Reason>,
+}
+
+impl ReasonSet {
+    pub fn push(&mut self, reason: Reason) {
+        if let Some(existing) = self.items.iter_mut().find(|r| r.id == reason.id) {
+            existing.count += reason.count;
+            existing.pinned |= reason.pinned;
+            // Ties keep the lexicographically smaller message so completion order never matters.
+            let ours = (
+                existing.stage.priority(),
+                std::cmp::Reverse(&existing.message),
+            );
+            let theirs = (reason.stage.priority(), std::cmp::Reverse(&reason.message));
+            if theirs > ours {
+                existing.stage = reason.stage;
+                existing.message = reason.message;
+            }
+            return;
+        }
+        self.items.push(reason);
+    }
+
+    pub fn extend<I, S>(&mut self, stage: ReasonStage, messages: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        for m in messages {
+            self.push(Reason::new(stage, m));
+        }
+    }
+
+    /// Run a stage that appends plain strings to `decision.reasons`, attributing them to `stage`.
+    pub fn collect_stage(
+        &mut self,
+        stage: ReasonStage,
+        decision: Decision,
+        f: impl FnOnce(Decision) -> Decision,
+    ) -> Decision {
+        let before = decision.reasons.len();
+        let mut decision = f(decision);
+        let start = before.min(decision.reasons.len());
+        let added: Vec<String> = decision.reasons.drain(start..).collect();
+        self.extend(stage, added);
+        decision
+    }
+
+    /// Structured view in render order (stage, then id).
+    pub fn sorted(&self) -> Vec<Reason> {
+        let mut items = self.items.clone();
+        items.sort_by(|a, b| (a.stage, &a.id).cmp(&(b.stage, &b.id)));
+        items
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        self.sorted().iter().map(Reason::render).collect()
+    }
+
+    /// Render at most `max` reasons (plus any pinned ones beyond that), dropping the latest
+    /// unpinned reasons in render order first.
+    pub fn render_limited(&self, max: usize) -> Vec<String> {
+        let sorted = self.sorted();
+        let pinned = sorted.iter().filter(|r| r.pinned).count();
+        let mut budget = max.saturating_sub(pinned);
+        sorted
+            .iter()
+            .filter(|r| {
+                if r.pinned {
+                    return true;
+                }
+                if budget == 0 {
+                    return false;
+                }
+                budget -= 1;
+                true
+            })
+            .map(Reason::render)
+            .collect()
+    }
+}
@@ -0,0 +1,410 @@
+//! Output redaction for configured sensitive strings.
+//!
+//! Redaction is an output-only pass: scanners and models always see the original content. Every
+//! HTTP response goes through [`redact_response`] and every log line through
+//! [`RedactingMakeWriter`], so no endpoint or response shape can skip it. Matches are replaced
+//! with `[REDACTED:<label>]`.
+//!
+//! Literals share one Aho-Corasick automaton (ASCII case-insensitive) and regexes one
+//! `RegexSet`; only regexes the set reports as matching are run again to find positions. Regexes
+//! compile under the `[regex]` limits (see [`crate::regex_guard`]); unlike `[[patterns]]`, a slow
+//! redaction rule is never disabled at runtime, since that would let the string through.
+
+use crate::config::RedactionConfig;
+use crate::regex_guard::{self, RegexLimits};
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{anyhow, Context, Result};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::StreamExt;
+use regex::{Regex, RegexSet};
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    io::{self, Write},
+    sync::{Arc, Mutex, RwLock},
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+/// Largest response body the middleware will buffer; bigger bodies fail closed.
+pub const MAX_REDACT_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+pub fn placeholder(label: &str) -> String {
+    format!("[REDACTED:{label}]")
+}
+
+/// Compiled rule set; immutable, swapped as a whole on reload.
+#[derive(Default)]
+struct Redactor {
+    /// Per rule index.
+    labels: Vec<String>,
+    literals: Option<AhoCorasick>,
+    /// Aho-Corasick pattern id -> rule index.
+    literal_rules: Vec<usize>,
+    regex_set: Option<RegexSet>,
+    /// Aligned with `regex_set` indices: (rule index, regex).
+    regexes: Vec<(usize, Regex)>,
+}
+
+impl Redactor {
+    fn compile(cfg: Option<&RedactionConfig>, limits: &RegexLimits) -> Result<Self> {
+        let mut r = Redactor::default();
+        let mut literals: Vec<&str> = vec![];
+        let mut patterns: Vec<&str> = vec![];
+
+        let rules = cfg.map(|c| c.rules.as_slice()).unwrap_or(&[]);
+        for (i, rule) in rules.iter().enumerate() {
+            let label = rule.label.trim();
+            if label.is_empty() || label.contains(['[', ']']) || label.contains(char::is_whitespace)
+            {
+                return Err(anyhow!(
+                    "redaction rule {i}: invalid label {:?}",
+                    rule.label
+                ));
+            }
+            match (&rule.literal, &rule.regex) {
+                (Some(lit), None) if !lit.is_empty() => {
+                    r.literal_rules.push(i);
+                    literals.push(lit);
+                }
+                (None, Some(pat)) => {
+                    let re = regex_guard::compile(
+                        &format!("redaction rule {i} ({label})"),
+                        pat,
+                        limits,
+                    )?
+                    .regex;
+                    if re.is_match("") {
+                        return Err(anyhow!(
+                            "redaction rule {i} ({label}): regex must not match the empty string"
+                        ));
+                    }
+                    r.regexes.push((i, re));
+                    patterns.push(pat);
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "redaction rule {i} ({label}): set exactly one of `literal` or `regex`"
+                    ))
+                }
+            }
+            r.labels.push(label.to_string());
+        }
+
+        if !literals.is_empty() {
+            r.literals = Some(
+                AhoCorasick::builder()
+                    .ascii_case_insensitive(true)
+                    .match_kind(MatchKind::LeftmostLongest)
+                    .build(&literals)
+                    .context("redaction literals")?,
+            );
+        }
+        if !patterns.is_empty() {
+            r.regex_set = Some(regex_guard::compile_set(
+                "redaction regexes",
+                &patterns,
+                limits,
+            )?);
+        }
+        Ok(r)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Non-overlapping matches as (start, end, rule index), leftmost first, longest on ties.
+    fn find(&self, s: &str) -> Vec<(usize, usize, usize)> {
+        let mut hits: Vec<(usize, usize, usize)> = vec![];
+        if let Some(ac) = &self.literals {
+            for m in ac.find_iter(s) {
+                hits.push((
+                    m.start(),
+                    m.end(),
+                    self.literal_rules[m.pattern().as_usize()],
+                ));
+            }
+        }
+        if let Some(set) = &self.regex_set {
+            for idx in set.matches(s).iter() {
+                let (rule, re) = &self.regexes[idx];
+                for m in re.find_iter(s).filter(|m| !m.is_empty()) {
+                    hits.push((m.start(), m.end(), *rule));
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+
+        let mut out: Vec<(usize, usize, usize)> = vec![];
+        let mut last_end = 0;
+        for h in hits {
+            if h.0 >= last_end {
+                last_end = h.1;
+                out.push(h);
+            }
+        }
+        out
+    }
+}
+
+/// Hot-reloadable redaction layer with per-label match counters.
+#[derive(Default)]
+pub struct Redaction {
+    current: RwLock<Arc<Redactor>>,
+    counts: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Redaction {
+    pub fn from_config(cfg: Option<&RedactionConfig>) -> Result<Self> {
+        let r = Self::default();
+        r.reload(cfg)?;
+        Ok(r)
+    }
+
+    /// Swap in a new rule set. On error the previous rules stay active.
+    ///
+    /// Counters are kept across reloads; labels that are new start at zero.
+    pub fn reload(&self, cfg: Option<&RedactionConfig>) -> Result<()> {
+        self.reload_with_limits(cfg, &RegexLimits::default())
+    }
+
+    /// [`Self::reload`] with the `[regex]` compile limits.
+    pub fn reload_with_limits(
+        &self,
+        cfg: Option<&RedactionConfig>,
+        limits: &RegexLimits,
+    ) -> Result<()> {
+        let compiled = Redactor::compile(cfg, limits)?;
+        {
+            let mut counts = self.counts.lock().unwrap();
+            for label in &compiled.labels {
+                counts.entry(label.clone()).or_insert(0);
+            }
+        }
+        *self.current.write().unwrap() = Arc::new(compiled);
+        Ok(())
+    }
+
+    fn redactor(&self) -> Arc<Redactor> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.redactor().is_empty()
+    }
+
+    pub fn rule_count(&self) -> usize {
+        self.redactor().labels.len()
+    }
+
+    /// Matches replaced so far, per label (all surfaces, since startup).
+    pub fn counts(&self) -> BTreeMap<String, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+
+    pub fn redact_str<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        let r = self.redactor();
+        redact_with(&r, &self.counts, s)
+    }
+
+    /// Redact every string (and object key) in a JSON value in place.
+    pub fn redact_json(&self, v: &mut Value) {
+        let r = self.redactor();
+        if !r.is_empty() {
+            redact_value(&r, &self.counts, v);
+        }
+    }
+}
+
+fn redact_with<'a>(
+    r: &Redactor,
+    counts: &Mutex<BTreeMap<String, u64>>,
+    s: &'a str,
+) -> Cow<'a, str> {
+    if r.is_empty() {
+        return Cow::Borrowed(s);
+    }
+    let hits = r.find(s);
+    if hits.is_empty() {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len());
+    let mut pos = 0;
+    let mut counts = counts.lock().unwrap();
+    for (start, end, rule) in hits {
+        let label = &r.labels[rule];
+        out.push_str(&s[pos..start]);
+        out.push_str(&placeholder(label));
+        *counts.entry(label.clone()).or_default() += 1;
+        pos = end;
+    }
+    out.push_str(&s[pos..]);
+    Cow::Owned(out)
+}
+
+fn redact_value(r: &Redactor, counts: &Mutex<BTreeMap<String, u64>>, v: &mut Value) {
+    match v {
+        Value::String(s) => {
+            if let Cow::Owned(red) = redact_with(r, counts, s) {
+                *s = red;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redact_value(r, counts, item);
+            }
+        }
+        Value::Object(map) => {
+            let entries = std::mem::take(map);
+            for (k, mut item) in entries {
+                redact_value(r, counts, &mut item);
+                map.insert(redact_with(r, counts, &k).into_owned(), item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Final pass over every HTTP response body.
+///
+/// JSON bodies are redacted string by string (so escaping stays valid); other UTF-8 bodies as
+/// text. Bodies over [`MAX_REDACT_BODY_BYTES`] are replaced with a 500 rather than passed through.
+/// NDJSON bodies are redacted frame by frame and stay streamed.
+pub async fn redact_response(
+    State(redaction): State<Arc<Redaction>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let resp = next.run(req).await;
+    if !redaction.is_enabled() {
+        return resp;
+    }
+
+    let (mut parts, body) = resp.into_parts();
+    crate::risk_headers::redact(&redaction, &mut parts.headers);
+    let is_ndjson = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with(crate::json_stream::NDJSON));
+    if is_ndjson {
+        // Streamed responses stay streamed: `json_stream` sends whole lines per frame.
+        parts.headers.remove(header::CONTENT_LENGTH);
+        let lines = body
+            .into_data_stream()
+            .map(move |chunk| chunk.map(|bytes| redact_lines(&redaction, &bytes)));
+        return Response::from_parts(parts, Body::from_stream(lines));
+    }
+
+    let bytes = match axum::body::to_bytes(body, MAX_REDACT_BODY_BYTES).await {
+        Ok(b) => b,
+        Err(_) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "response too large to redact",
+            )
+                .into_response()
+        }
+    };
+
+    let is_json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| ct.contains("json"))
+        .unwrap_or(false);
+
+    let parsed = if is_json {
+        serde_json::from_slice::<Value>(&bytes).ok()
+    } else {
+        None
+    };
+
+    let out: Vec<u8> = match parsed {
+        Some(mut v) => {
+            redaction.redact_json(&mut v);
+            serde_json::to_vec(&v).unwrap_or_default()
+        }
+        None => match std::str::from_utf8(&bytes) {
+            Ok(text) => redaction.redact_str(text).into_owned().into_bytes(),
+            // Binary bodies: nothing to redact as text.
+            Err(_) => bytes.to_vec(),
+        },
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(out))
+}
+
+/// Redact one NDJSON frame line by line, as JSON where a line parses and as text otherwise.
+fn redact_lines(redaction: &Redaction, bytes: &[u8]) -> axum::body::Bytes {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return axum::body::Bytes::copy_from_slice(bytes);
+    };
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(c) => (c, "\n"),
+            None => (line, ""),
+        };
+        match serde_json::from_str::<Value>(content) {
+            Ok(mut v) => {
+                redaction.redact_json(&mut v);
+                out.push_str(&serde_json::to_string(&v).unwrap_or_default());
+            }
+            Err(_) => out.push_str(&redaction.redact_str(content)),
+        }
+        out.push_str(newline);
+    }
+    axum::body::Bytes::from(out)
+}
+
+/// `tracing_subscriber` writer that redacts each formatted log line before it is written.
+pub struct RedactingMakeWriter<M> {
+    redaction: Arc<Redaction>,
+    inner: M,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(redaction: Arc<Redaction>, inner: M) -> Self {
+        Self { redaction, inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            redaction: self.redaction.clone(),
+            inner: self.inner.make_writer(),
+        }
+    }
+}
+
+pub struct RedactingWriter<W> {
+    redaction: Arc<Redaction>,
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    /// The fmt layer writes one whole formatted event per call.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        self.inner
+            .write_all(self.redaction.redact_str(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
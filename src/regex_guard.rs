@@ -0,0 +1,192 @@
+//! Compile limits for user-supplied regular expressions (redaction rules, `[[patterns]]`).
+//!
+//! The `regex` crate matches in time linear in the input, so a pattern cannot backtrack
+//! catastrophically; what it can do is compile to a very large automaton (`\w{1000}` with Unicode
+//! classes) and make every scan slow. Every user regex is therefore built with explicit limits:
+//!
+//! - `size_limit`: hard cap on the compiled program, enforced by the `regex` crate itself;
+//! - `dfa_size_limit`: cache for the lazy DFA; a pattern that thrashes it falls back to a slower
+//!   engine rather than using more memory;
+//! - `nest_limit`: deepest allowed nesting of groups and repetitions;
+//! - `compiled_budget`: the largest measured compiled size accepted at validation time. This is
+//!   the limit operators normally hit; the error carries the measured size.
+//!
+//! Scan-time budgets for the pattern pack live in [`crate::patterns`].
+
+use crate::config::RegexConfig;
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use regex_automata::{nfa::thompson, util::syntax};
+use std::time::Duration;
+
+pub const DEFAULT_SIZE_LIMIT: usize = 4 * 1024 * 1024;
+pub const DEFAULT_DFA_SIZE_LIMIT: usize = 2 * 1024 * 1024;
+pub const DEFAULT_NEST_LIMIT: u32 = 64;
+pub const DEFAULT_COMPILED_BUDGET: usize = 1024 * 1024;
+pub const DEFAULT_PATTERN_SCAN_BUDGET: Duration = Duration::from_millis(5);
+pub const DEFAULT_PACK_SCAN_BUDGET: Duration = Duration::from_millis(25);
+pub const DEFAULT_DEMOTE_AFTER_STRIKES: u32 = 5;
+
+/// Effective `[regex]` settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegexLimits {
+    pub size_limit: usize,
+    pub dfa_size_limit: usize,
+    pub nest_limit: u32,
+    pub compiled_budget: usize,
+    pub pattern_scan_budget: Duration,
+    pub pack_scan_budget: Duration,
+    pub demote_after_strikes: u32,
+}
+
+impl Default for RegexLimits {
+    fn default() -> Self {
+        Self {
+            size_limit: DEFAULT_SIZE_LIMIT,
+            dfa_size_limit: DEFAULT_DFA_SIZE_LIMIT,
+            nest_limit: DEFAULT_NEST_LIMIT,
+            compiled_budget: DEFAULT_COMPILED_BUDGET,
+            pattern_scan_budget: DEFAULT_PATTERN_SCAN_BUDGET,
+            pack_scan_budget: DEFAULT_PACK_SCAN_BUDGET,
+            demote_after_strikes: DEFAULT_DEMOTE_AFTER_STRIKES,
+        }
+    }
+}
+
+impl RegexLimits {
+    pub fn from_config(cfg: Option<&RegexConfig>) -> Self {
+        let d = Self::default();
+        let Some(c) = cfg else {
+            return d;
+        };
+        Self {
+            size_limit: c.size_limit_bytes.unwrap_or(d.size_limit),
+            dfa_size_limit: c.dfa_size_limit_bytes.unwrap_or(d.dfa_size_limit),
+            nest_limit: c.nest_limit.unwrap_or(d.nest_limit),
+            compiled_budget: c.compiled_budget_bytes.unwrap_or(d.compiled_budget),
+            pattern_scan_budget: c
+                .pattern_scan_budget_us
+                .map(Duration::from_micros)
+                .unwrap_or(d.pattern_scan_budget),
+            pack_scan_budget: c
+                .pack_scan_budget_us
+                .map(Duration::from_micros)
+                .unwrap_or(d.pack_scan_budget),
+            demote_after_strikes: c
+                .demote_after_strikes
+                .unwrap_or(d.demote_after_strikes)
+                .max(1),
+        }
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.compiled_budget > self.size_limit {
+            anyhow::bail!(
+                "regex.compiled_budget_bytes ({}) must not exceed regex.size_limit_bytes ({})",
+                self.compiled_budget,
+                self.size_limit
+            );
+        }
+        if self.pattern_scan_budget.is_zero() || self.pack_scan_budget.is_zero() {
+            anyhow::bail!("regex scan budgets must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Why a user regex was refused; `label` names the rule or pattern.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum RegexRejected {
+    #[error("{label}: invalid regex: {detail}")]
+    Invalid { label: String, detail: String },
+    #[error("{label}: compiled size exceeds the size limit of {limit} bytes")]
+    OverLimit { label: String, limit: usize },
+    #[error("{label}: compiled size {size} bytes exceeds the budget of {budget} bytes")]
+    OverBudget {
+        label: String,
+        size: usize,
+        budget: usize,
+    },
+}
+
+/// A regex built under [`RegexLimits`], with its measured compiled size.
+#[derive(Debug, Clone)]
+pub struct CompiledRegex {
+    pub regex: Regex,
+    pub compiled_bytes: usize,
+}
+
+/// Measured size of the compiled program (the Thompson NFA `size_limit` applies to).
+pub fn compiled_size(
+    label: &str,
+    pattern: &str,
+    limits: &RegexLimits,
+) -> Result<usize, RegexRejected> {
+    let nfa = thompson::Compiler::new()
+        .syntax(syntax::Config::new().nest_limit(limits.nest_limit))
+        .configure(thompson::Config::new().nfa_size_limit(Some(limits.size_limit)))
+        .build(pattern)
+        .map_err(|e| match e.size_limit() {
+            Some(limit) => RegexRejected::OverLimit {
+                label: label.to_string(),
+                limit,
+            },
+            None => RegexRejected::Invalid {
+                label: label.to_string(),
+                detail: e.to_string(),
+            },
+        })?;
+    Ok(nfa.memory_usage())
+}
+
+/// Compile `pattern` under `limits`, refusing it when the measured size exceeds the budget.
+pub fn compile(
+    label: &str,
+    pattern: &str,
+    limits: &RegexLimits,
+) -> Result<CompiledRegex, RegexRejected> {
+    let size = compiled_size(label, pattern, limits)?;
+    if size > limits.compiled_budget {
+        return Err(RegexRejected::OverBudget {
+            label: label.to_string(),
+            size,
+            budget: limits.compiled_budget,
+        });
+    }
+    let regex = RegexBuilder::new(pattern)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .nest_limit(limits.nest_limit)
+        .build()
+        .map_err(|e| reject(label, limits, e))?;
+    Ok(CompiledRegex {
+        regex,
+        compiled_bytes: size,
+    })
+}
+
+/// A `RegexSet` over patterns already accepted by [`compile`], under the same limits.
+pub fn compile_set(
+    label: &str,
+    patterns: &[&str],
+    limits: &RegexLimits,
+) -> Result<RegexSet, RegexRejected> {
+    RegexSetBuilder::new(patterns)
+        .size_limit(limits.size_limit)
+        .dfa_size_limit(limits.dfa_size_limit)
+        .nest_limit(limits.nest_limit)
+        .build()
+        .map_err(|e| reject(label, limits, e))
+}
+
+fn reject(label: &str, limits: &RegexLimits, e: regex::Error) -> RegexRejected {
+    match e {
+        regex::Error::CompiledTooBig(_) => RegexRejected::OverLimit {
+            label: label.to_string(),
+            limit: limits.size_limit,
+        },
+        other => RegexRejected::Invalid {
+            label: label.to_string(),
+            detail: other.to_string(),
+        },
+    }
+}
@@ -0,0 +1,539 @@
+//! Remediation hints: what a reviewer should do about a decision that is not a plain allow.
+//!
+//! Hints are data. `remediation_hints.toml` is built in and `[remediation] hints_file` names a
+//! file of `[[hint]]` entries merged over it by id: an entry with a built-in id replaces it, any
+//! other is added. A hint has a stable `id`, a `priority`, English `text`, `translations` by
+//! locale, and selectors: detected pattern ids or their families (`patterns`), `attack_types`,
+//! and `outcomes` (an action or a reason id, see [`crate::reasons`]). It applies when any
+//! selector matches.
+//!
+//! A decision whose action is `sanitize`, `needs_review` or `block` gets the applicable hints
+//! under `remediation`, highest priority first (ties by id), at most the policy's
+//! `remediation.max_hints`, and only those the policy selects (`remediation.hints`, empty for
+//! all). The policy's `locale` picks the translation: the exact tag, then its language, then
+//! the English text. Hints are added to the response once the decision is made, so they never
+//! reach a model.
+//!
+//! Loading refuses duplicate ids within one file, bad ids and locale tags, empty texts and hints
+//! without selectors. Pattern ids and outcomes that nothing produces are only warned about, with
+//! the closest known id suggested. The file is re-read on SIGHUP; a file that fails to load
+//! keeps the current hints.
+
+use crate::config::RemediationConfig;
+use crate::model_policy::PolicyConfig;
+use crate::policy_store::PolicyStore;
+use crate::reasons::{self, Reason, ReasonStage};
+use crate::threat::AttackType;
+use anyhow::{bail, Context, Result};
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::{Arc, RwLock},
+};
+
+/// The built-in hints.
+pub const DEFAULT_HINTS: &str = include_str!("remediation_hints.toml");
+pub const DEFAULT_MAX_HINTS: usize = 3;
+pub const DEFAULT_PRIORITY: u8 = 50;
+/// Locale of a hint's `text`.
+pub const DEFAULT_LOCALE: &str = "en";
+/// Actions that get hints.
+pub const ACTIONS: [&str; 3] = ["sanitize", "needs_review", "block"];
+
+/// Indicator families whose ids end in a value (a host, a markup token) rather than a fixed set.
+const OPEN_FAMILIES: &[&str] = &[
+    "html_scan",
+    "xml_scan",
+    "feed_blocklist",
+    "adversarial_tighten",
+];
+
+/// `remediation` of a policy. Taken whole from the nearest declaration, like `url_allowlist`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemediationPolicy {
+    /// Hint ids this policy uses; empty for all of them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<String>,
+    /// Hints per decision (default 3); 0 turns them off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_hints: Option<usize>,
+}
+
+impl RemediationPolicy {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn max_hints(&self) -> usize {
+        self.max_hints.unwrap_or(DEFAULT_MAX_HINTS)
+    }
+}
+
+/// One `[[hint]]` entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HintDecl {
+    pub id: String,
+    pub text: String,
+    #[serde(default = "default_priority")]
+    pub priority: u8,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attack_types: Vec<AttackType>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub outcomes: Vec<String>,
+    /// Locale tag -> text.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub translations: BTreeMap<String, String>,
+}
+
+fn default_priority() -> u8 {
+    DEFAULT_PRIORITY
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HintsFile {
+    #[serde(default)]
+    hint: Vec<HintDecl>,
+}
+
+/// A hint as attached to a decision.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hint {
+    pub id: String,
+    pub text: String,
+    /// Locale of `text`.
+    pub locale: String,
+}
+
+/// What a decision found, for matching hints.
+#[derive(Debug, Clone, Default)]
+pub struct Evidence {
+    pub action: String,
+    /// Scanner indicators and the model's detected patterns.
+    pub patterns: Vec<String>,
+    pub attack_types: Vec<AttackType>,
+    /// Reason ids (see [`crate::reasons`]).
+    pub reasons: Vec<String>,
+}
+
+impl Evidence {
+    /// From a rendered ingest response and the run's scanner indicators (which the response
+    /// only carries in audit mode).
+    pub fn from_response(body: &Value, indicators: &[String]) -> Self {
+        let strings = |v: &Value| -> Vec<String> {
+            v.as_array()
+                .map(|a| {
+                    a.iter()
+                        .filter_map(|s| s.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let mut patterns = indicators.to_vec();
+        patterns.extend(strings(&body["detected_patterns"]));
+        Self {
+            action: body["action"].as_str().unwrap_or_default().to_string(),
+            patterns,
+            attack_types: serde_json::from_value(body["threat"]["attack_types"].clone())
+                .unwrap_or_default(),
+            reasons: strings(&body["reasons"])
+                .iter()
+                .map(|m| Reason::new(ReasonStage::Sentry, m.as_str()).id)
+                .collect(),
+        }
+    }
+}
+
+/// `selector` names `id`: the same id, or a family of it (`probe:cert_mismatch` of
+/// `probe:cert_mismatch:evil.test`).
+pub fn selects(selector: &str, id: &str) -> bool {
+    id.strip_prefix(selector)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= 64
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// A BCP 47-shaped tag: `de`, `pt-BR`, `zh-Hant-TW`.
+pub fn valid_locale(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let lang_ok = parts
+        .next()
+        .is_some_and(|l| (2..=3).contains(&l.len()) && l.chars().all(|c| c.is_ascii_alphabetic()));
+    lang_ok
+        && parts.all(|p| (1..=8).contains(&p.len()) && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Pattern ids and outcomes the sidecar can produce, for validating selectors.
+#[derive(Debug, Clone)]
+pub struct KnownIds {
+    patterns: BTreeSet<String>,
+    open_families: BTreeSet<String>,
+    outcomes: BTreeSet<String>,
+}
+
+impl KnownIds {
+    /// The built-in detectors plus the configured `[[patterns]]` ids.
+    pub fn new(user_patterns: &[String]) -> Self {
+        let mut patterns: BTreeSet<String> =
+            crate::threat::known_indicators().into_iter().collect();
+        patterns.insert(crate::patterns::PACK_BUDGET_INDICATOR.to_string());
+        for id in user_patterns {
+            patterns.insert(format!("{}:{id}", crate::patterns::INDICATOR_PREFIX));
+        }
+        let mut open_families: BTreeSet<String> =
+            OPEN_FAMILIES.iter().map(|f| f.to_string()).collect();
+        for f in crate::probing::Finding::ALL {
+            open_families.insert(format!("probe:{}", f.as_str()));
+        }
+        let outcomes = ACTIONS
+            .into_iter()
+            .chain(reasons::known_ids())
+            .map(str::to_string)
+            .collect();
+        Self {
+            patterns,
+            open_families,
+            outcomes,
+        }
+    }
+
+    fn knows_pattern(&self, selector: &str) -> bool {
+        self.patterns.iter().any(|p| selects(selector, p))
+            || self
+                .open_families
+                .iter()
+                .any(|f| selects(selector, f) || selects(f, selector))
+    }
+
+    fn knows_outcome(&self, selector: &str) -> bool {
+        self.outcomes
+            .iter()
+            .any(|o| selects(selector, o) || selects(o, selector))
+    }
+
+    /// Known ids and their families, for suggestions.
+    fn pattern_names(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        for id in self.patterns.iter().chain(&self.open_families) {
+            names.insert(id.as_str());
+            let mut end = 0;
+            while let Some(i) = id[end..].find(':') {
+                end += i;
+                names.insert(&id[..end]);
+                end += 1;
+            }
+        }
+        names
+    }
+}
+
+impl Default for KnownIds {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+/// Edit distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cur = row[j + 1];
+            row[j + 1] = (prev + usize::from(ca != *cb)).min(row[j] + 1).min(cur + 1);
+            prev = cur;
+        }
+    }
+    row[b.len()]
+}
+
+/// The candidate closest to `wanted`, if it is close enough to be a typo.
+fn suggest<'a>(wanted: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (wanted.chars().count() / 3).clamp(1, 4);
+    candidates
+        .into_iter()
+        .map(|c| (distance(wanted, c), c))
+        .filter(|(d, _)| *d <= limit)
+        .min()
+        .map(|(_, c)| c)
+}
+
+fn did_you_mean(suggestion: Option<&str>) -> String {
+    suggestion
+        .map(|s| format!(" (did you mean '{s}'?)"))
+        .unwrap_or_default()
+}
+
+/// Loaded hints, highest priority first.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    hints: Vec<HintDecl>,
+}
+
+impl Catalog {
+    /// The built-in hints with `overrides` (TOML) merged over them by id. Also returns warnings
+    /// about selectors nothing produces.
+    pub fn load(overrides: Option<&str>, known: &KnownIds) -> Result<(Self, Vec<String>)> {
+        let mut hints = parse_file(DEFAULT_HINTS).context("built-in remediation hints")?;
+        if let Some(raw) = overrides {
+            for hint in parse_file(raw).context("remediation hints file")? {
+                match hints.iter_mut().find(|h| h.id == hint.id) {
+                    Some(existing) => *existing = hint,
+                    None => hints.push(hint),
+                }
+            }
+        }
+        let mut warnings = vec![];
+        for hint in &hints {
+            for p in &hint.patterns {
+                if !known.knows_pattern(p) {
+                    let close = suggest(p, known.pattern_names());
+                    warnings.push(format!(
+                        "hint '{}': no detector produces pattern '{p}'{}",
+                        hint.id,
+                        did_you_mean(close)
+                    ));
+                }
+            }
+            for o in &hint.outcomes {
+                if !known.knows_outcome(o) {
+                    let close = suggest(o, known.outcomes.iter().map(String::as_str));
+                    warnings.push(format!(
+                        "hint '{}': unknown outcome '{o}'{}",
+                        hint.id,
+                        did_you_mean(close)
+                    ));
+                }
+            }
+        }
+        hints.sort_by(|a, b| (b.priority, &a.id).cmp(&(a.priority, &b.id)));
+        Ok((Self { hints }, warnings))
+    }
+
+    pub fn len(&self) -> usize {
+        self.hints.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hints.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.hints.iter().map(|h| h.id.as_str())
+    }
+
+    /// The hints for `evidence` under `policy`, rendered in `policy.locale`.
+    pub fn resolve(&self, policy: &PolicyConfig, evidence: &Evidence) -> Vec<Hint> {
+        if !ACTIONS.contains(&evidence.action.as_str()) {
+            return vec![];
+        }
+        let selected = &policy.remediation.hints;
+        self.hints
+            .iter()
+            .filter(|h| selected.is_empty() || selected.contains(&h.id))
+            .filter(|h| applies(h, evidence))
+            .take(policy.remediation.max_hints())
+            .map(|h| render(h, policy.locale.as_deref()))
+            .collect()
+    }
+}
+
+fn parse_file(raw: &str) -> Result<Vec<HintDecl>> {
+    let file: HintsFile = toml::from_str(raw)?;
+    let mut seen = HashSet::new();
+    for h in &file.hint {
+        if !valid_id(&h.id) {
+            bail!(
+                "hint id {:?}: letters, digits, `_`, `-`, `.`; at most 64",
+                h.id
+            );
+        }
+        if !seen.insert(h.id.as_str()) {
+            bail!("duplicate hint id '{}'", h.id);
+        }
+        if h.text.trim().is_empty() {
+            bail!("hint '{}': empty text", h.id);
+        }
+        if h.patterns.is_empty() && h.attack_types.is_empty() && h.outcomes.is_empty() {
+            bail!(
+                "hint '{}': needs at least one of patterns, attack_types, outcomes",
+                h.id
+            );
+        }
+        for (locale, text) in &h.translations {
+            if !valid_locale(locale) {
+                bail!("hint '{}': {locale:?} is not a locale tag", h.id);
+            }
+            if text.trim().is_empty() {
+                bail!("hint '{}': empty {locale} translation", h.id);
+            }
+        }
+    }
+    Ok(file.hint)
+}
+
+fn applies(hint: &HintDecl, e: &Evidence) -> bool {
+    hint.attack_types.iter().any(|t| e.attack_types.contains(t))
+        || hint
+            .patterns
+            .iter()
+            .any(|s| e.patterns.iter().any(|p| selects(s, p)))
+        || hint
+            .outcomes
+            .iter()
+            .any(|s| selects(s, &e.action) || e.reasons.iter().any(|r| selects(s, r)))
+}
+
+fn render(hint: &HintDecl, locale: Option<&str>) -> Hint {
+    let translated = locale.and_then(|tag| {
+        let language = tag.split('-').next().unwrap_or(tag);
+        [tag, language].into_iter().find_map(|t| {
+            hint.translations
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(t))
+                .map(|(k, text)| (k.clone(), text.clone()))
+        })
+    });
+    let (locale, text) =
+        translated.unwrap_or_else(|| (DEFAULT_LOCALE.to_string(), hint.text.clone()));
+    Hint {
+        id: hint.id.clone(),
+        text,
+        locale,
+    }
+}
+
+/// The loaded catalog, swapped on reload.
+pub struct Remediation {
+    catalog: RwLock<Arc<Catalog>>,
+    known: KnownIds,
+}
+
+impl Default for Remediation {
+    /// The built-in hints.
+    fn default() -> Self {
+        let known = KnownIds::default();
+        let (catalog, _) =
+            Catalog::load(None, &known).expect("built-in remediation hints are valid");
+        Self {
+            catalog: RwLock::new(Arc::new(catalog)),
+            known,
+        }
+    }
+}
+
+impl Remediation {
+    /// Built-in hints plus `hints_file`, validated against the built-in detectors and
+    /// `user_patterns`. Warnings are logged.
+    pub fn from_config(cfg: Option<&RemediationConfig>, user_patterns: &[String]) -> Result<Self> {
+        let this = Self {
+            catalog: RwLock::new(Arc::new(Catalog::default())),
+            known: KnownIds::new(user_patterns),
+        };
+        this.reload(cfg)?;
+        Ok(this)
+    }
+
+    /// Re-read `hints_file`; on error the current hints stay. Returns the number of hints.
+    pub fn reload(&self, cfg: Option<&RemediationConfig>) -> Result<usize> {
+        let overrides = cfg
+            .and_then(|c| c.hints_file.as_deref())
+            .map(|p| {
+                std::fs::read_to_string(p).with_context(|| format!("remediation hints_file {p:?}"))
+            })
+            .transpose()?;
+        let (catalog, warnings) = Catalog::load(overrides.as_deref(), &self.known)?;
+        for w in &warnings {
+            tracing::warn!("remediation: {w}");
+        }
+        let n = catalog.len();
+        *self.catalog.write().unwrap() = Arc::new(catalog);
+        Ok(n)
+    }
+
+    pub fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.read().unwrap().clone()
+    }
+
+    /// Refuse policies whose `remediation.hints` names a hint that does not exist.
+    pub fn check_policy_refs(&self, policies: &PolicyStore) -> Result<()> {
+        let catalog = self.catalog();
+        for name in policies.list() {
+            let Some(policy) = policies.get(&name) else {
+                continue;
+            };
+            for id in &policy.remediation.hints {
+                if !catalog.ids().any(|h| h == id) {
+                    bail!(
+                        "policy '{name}': remediation.hints names unknown hint '{id}'{}",
+                        did_you_mean(suggest(id, catalog.ids()))
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add `remediation` to a successful ingest response whose action calls for it.
+    /// `indicators` are the run's scanner indicators.
+    pub async fn annotate_response(
+        &self,
+        policy: Option<&PolicyConfig>,
+        indicators: &[String],
+        resp: Response,
+    ) -> Response {
+        let Some(policy) = policy else {
+            return resp;
+        };
+        if !resp.status().is_success() || policy.remediation.max_hints() == 0 {
+            return resp;
+        }
+        let (parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, crate::redact::MAX_REDACT_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "response too large to annotate",
+                )
+                    .into_response()
+            }
+        };
+        let Ok(mut v) = serde_json::from_slice::<Value>(&bytes) else {
+            return Response::from_parts(parts, Body::from(bytes));
+        };
+        let hints = self
+            .catalog()
+            .resolve(policy, &Evidence::from_response(&v, indicators));
+        if hints.is_empty() {
+            return Response::from_parts(parts, Body::from(bytes));
+        }
+        let Some(obj) = v.as_object_mut() else {
+            return Response::from_parts(parts, Body::from(bytes));
+        };
+        obj.insert(
+            "remediation".to_string(),
+            serde_json::to_value(hints).unwrap_or_default(),
+        );
+        let mut parts = parts;
+        parts.headers.remove(axum::http::header::CONTENT_LENGTH);
+        Response::from_parts(parts, Body::from(v.to_string()))
+    }
+}
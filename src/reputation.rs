@@ -1,10 +1,17 @@
+use crate::reputation_limits::{CardinalityGuard, CardinalitySnapshot};
+use crate::storage::{self, StorageError, StoreFile};
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
     collections::HashMap,
     fs,
     io::Write,
     path::{Path, PathBuf},
-    sync::Mutex,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -20,6 +27,26 @@ pub struct ReputationRecord {
     #[serde(default)]
     pub last_attack_types: Vec<String>,
     pub risk_score: u64,
+    /// First observation time. Records persisted before this field existed get their
+    /// `last_seen_unix` when the file is migrated (so their age is underestimated, never
+    /// overestimated); `0` only for records that never reached a migrated file.
+    #[serde(default)]
+    pub first_seen_unix: u64,
+}
+
+impl ReputationRecord {
+    /// Total ingests observed for this key, clean or not: `seen_count`, which every ingest
+    /// increments.
+    pub fn total_ingests(&self) -> u64 {
+        self.seen_count
+    }
+
+    /// Ingests that did not register any threat score: every ingest with a non-zero threat
+    /// score is counted in `suspected_attack_count`.
+    pub fn clean_ingests(&self) -> u64 {
+        self.total_ingests()
+            .saturating_sub(self.suspected_attack_count)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,14 +65,77 @@ fn now_unix() -> u64 {
         .as_secs()
 }
 
+/// Time source for reputation scoring, so tests can pin "now".
+pub trait Clock: Send + Sync {
+    fn now_unix(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_unix(&self) -> u64 {
+        now_unix()
+    }
+}
+
+/// Manually driven clock for tests.
+#[derive(Debug, Default)]
+pub struct MockClock {
+    now: AtomicU64,
+}
+
+impl MockClock {
+    pub fn new(now_unix: u64) -> Self {
+        Self {
+            now: AtomicU64::new(now_unix),
+        }
+    }
+
+    pub fn set(&self, now_unix: u64) {
+        self.now.store(now_unix, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for MockClock {
+    fn now_unix(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
 pub trait ReputationStore: Send + Sync {
     fn get(&self, key: &str) -> Option<ReputationRecord>;
     fn record(&self, obs: Observation) -> Vec<ReputationRecord>;
+    /// Snapshot of every record, in no particular order.
+    fn list(&self) -> Vec<ReputationRecord>;
+    /// Every key, in no particular order; cheaper than `list` for walking a large store.
+    fn keys(&self) -> Vec<String> {
+        self.list().into_iter().map(|r| r.key).collect()
+    }
+    /// Record count and cap/eviction gauges.
+    fn cardinality(&self) -> CardinalitySnapshot {
+        CardinalitySnapshot {
+            records: self.list().len(),
+            ..Default::default()
+        }
+    }
+    /// The backing file and its format version, for file-backed stores.
+    fn storage(&self) -> Option<storage::StoreInfo> {
+        None
+    }
+    /// Never written (`server.read_only`).
+    fn is_read_only(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Default)]
 pub struct InMemoryReputationStore {
     inner: Mutex<HashMap<String, ReputationRecord>>,
+    limits: CardinalityGuard,
 }
 
 impl InMemoryReputationStore {
@@ -53,14 +143,18 @@ impl InMemoryReputationStore {
         Self::default()
     }
 
-    fn upsert_locked(map: &mut HashMap<String, ReputationRecord>, key: String, obs: &Observation) {
-        let rec = map.entry(key.clone()).or_insert_with(|| ReputationRecord {
-            key,
-            ..Default::default()
-        });
+    /// Bound the store's key cardinality (see [`crate::reputation_limits`]).
+    pub fn with_limits(mut self, limits: CardinalityGuard) -> Self {
+        self.limits = limits;
+        self
+    }
 
+    fn upsert(rec: &mut ReputationRecord, obs: &Observation) {
         rec.seen_count += 1;
         rec.last_seen_unix = obs.now_unix;
+        if rec.first_seen_unix == 0 {
+            rec.first_seen_unix = obs.now_unix;
+        }
 
         if obs.threat_score > 0 {
             rec.suspected_attack_count += 1;
@@ -70,21 +164,40 @@ impl InMemoryReputationStore {
         }
     }
 
-    fn record_inner(&self, obs: Observation) -> Vec<ReputationRecord> {
-        let mut out: Vec<ReputationRecord> = vec![];
-        let mut map = self.inner.lock().unwrap();
-
-        let src_key = format!("source_id:{}", obs.source_id);
-        Self::upsert_locked(&mut map, src_key.clone(), &obs);
-        out.push(map.get(&src_key).cloned().unwrap());
-
-        if let Some(host) = &obs.host {
-            let host_key = format!("host:{}", host);
-            Self::upsert_locked(&mut map, host_key.clone(), &obs);
-            out.push(map.get(&host_key).cloned().unwrap());
+    /// Apply `obs` to `map` under `limits`. Keys the pre-filter does not admit are reported
+    /// as they would read after this one sighting but not stored. Returns the records and
+    /// whether `map` changed.
+    fn record_locked(
+        map: &mut HashMap<String, ReputationRecord>,
+        limits: &CardinalityGuard,
+        obs: &Observation,
+    ) -> (Vec<ReputationRecord>, bool) {
+        let keys = observed_keys(obs);
+        let mut changed = false;
+        let out = keys
+            .iter()
+            .map(|key| {
+                if !map.contains_key(key) && !limits.admit(key, obs) {
+                    let mut rec = ReputationRecord {
+                        key: key.clone(),
+                        ..Default::default()
+                    };
+                    Self::upsert(&mut rec, obs);
+                    return rec;
+                }
+                let rec = map.entry(key.clone()).or_insert_with(|| ReputationRecord {
+                    key: key.clone(),
+                    ..Default::default()
+                });
+                Self::upsert(rec, obs);
+                changed = true;
+                rec.clone()
+            })
+            .collect();
+        if changed {
+            limits.enforce(map, obs.now_unix, &keys);
         }
-
-        out
+        (out, changed)
     }
 }
 
@@ -93,8 +206,21 @@ impl ReputationStore for InMemoryReputationStore {
         self.inner.lock().unwrap().get(key).cloned()
     }
 
+    fn list(&self) -> Vec<ReputationRecord> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.inner.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn cardinality(&self) -> CardinalitySnapshot {
+        self.limits.snapshot(self.inner.lock().unwrap().len())
+    }
+
     fn record(&self, obs: Observation) -> Vec<ReputationRecord> {
-        self.record_inner(obs)
+        let mut map = self.inner.lock().unwrap();
+        Self::record_locked(&mut map, &self.limits, &obs).0
     }
 }
 
@@ -105,90 +231,145 @@ struct JsonStoreFile {
 }
 
 pub struct JsonFileReputationStore {
-    path: PathBuf,
+    file: StoreFile,
     inner: Mutex<HashMap<String, ReputationRecord>>,
+    limits: CardinalityGuard,
+    /// Opened with [`JsonFileReputationStore::open_read_only`]: never written.
+    read_only: bool,
 }
 
 impl JsonFileReputationStore {
     pub fn load_or_create(path: impl AsRef<Path>) -> anyhow::Result<Self> {
         let path = path.as_ref().to_path_buf();
-        let map = if path.exists() {
-            let raw = fs::read_to_string(&path)?;
-            match serde_json::from_str::<JsonStoreFile>(&raw) {
-                Ok(parsed) => parsed.records,
-                Err(err) => {
-                    let quarantine = quarantine_path(&path);
-                    if let Err(quarantine_err) = quarantine_corrupt_file(&path, &quarantine) {
-                        tracing::warn!(
-                            error = %quarantine_err,
-                            quarantine_path = %quarantine.display(),
-                            "Failed to quarantine corrupt reputation file"
-                        );
-                    } else {
-                        tracing::warn!(
-                            error = %err,
-                            quarantine_path = %quarantine.display(),
-                            "Quarantined corrupt reputation file after JSON parse failure"
-                        );
-                    }
-                    HashMap::new()
+        let (file, map) = match StoreFile::open::<JsonStoreFile>(&path, &storage::REPUTATION, false)
+        {
+            Ok(opened) => (opened.file, opened.data.unwrap_or_default().records),
+            Err(StorageError::Corrupt { reason, .. }) => {
+                let quarantine = quarantine_path(&path);
+                if let Err(quarantine_err) = quarantine_corrupt_file(&path, &quarantine) {
+                    tracing::warn!(
+                        error = %quarantine_err,
+                        quarantine_path = %quarantine.display(),
+                        "Failed to quarantine corrupt reputation file"
+                    );
+                } else {
+                    tracing::warn!(
+                        error = %reason,
+                        quarantine_path = %quarantine.display(),
+                        "Quarantined corrupt reputation file after JSON parse failure"
+                    );
                 }
+                (StoreFile::new(path, &storage::REPUTATION), HashMap::new())
             }
-        } else {
-            HashMap::new()
+            // Newer or unmigratable files are left alone and refuse startup.
+            Err(err) => return Err(err.into()),
         };
 
         Ok(Self {
-            path,
+            file,
             inner: Mutex::new(map),
+            limits: CardinalityGuard::default(),
+            read_only: false,
+        })
+    }
+
+    /// Open an existing store without ever writing to it (`server.read_only`).
+    ///
+    /// Unlike [`Self::load_or_create`], a missing or corrupt file is an error rather than an
+    /// empty store (a corrupt file is left where it is), and `record` changes nothing.
+    pub fn open_read_only(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let opened = StoreFile::open::<JsonStoreFile>(&path, &storage::REPUTATION, true)
+            .with_context(|| format!("open reputation store {}", path.display()))?;
+        let parsed = opened
+            .data
+            .with_context(|| format!("read reputation store {}: no such file", path.display()))?;
+        Ok(Self {
+            file: opened.file,
+            inner: Mutex::new(parsed.records),
+            limits: CardinalityGuard::default(),
+            read_only: true,
         })
     }
 
+    /// Bound the store's key cardinality (see [`crate::reputation_limits`]). A loaded file
+    /// already past the cap is trimmed in memory and on the next write.
+    pub fn with_limits(mut self, limits: CardinalityGuard) -> Self {
+        limits.enforce(self.inner.get_mut().unwrap(), now_unix(), &[]);
+        self.limits = limits;
+        self
+    }
+
     pub fn default_path() -> PathBuf {
         // Best-effort default. Operators can override via env/config.
         PathBuf::from("/var/lib/acip/reputation.json")
     }
 
     fn persist(&self, map: &HashMap<String, ReputationRecord>) -> anyhow::Result<()> {
-        let parent = self.path.parent().unwrap_or_else(|| Path::new("."));
-        if !parent.as_os_str().is_empty() {
-            fs::create_dir_all(parent)?;
-        }
-        let file = JsonStoreFile {
+        self.file.write(&JsonStoreFile {
             records: map.clone(),
-        };
-        let raw = serde_json::to_string_pretty(&file)?;
-        let temp_path = temp_path_for(&self.path, parent);
-        let mut options = fs::OpenOptions::new();
-        options.write(true).create_new(true);
-        #[cfg(unix)]
-        {
-            options.mode(0o600);
-        }
+        })
+    }
+}
 
-        let mut temp_file = options.open(&temp_path)?;
-        #[cfg(unix)]
-        {
-            let _ = temp_file.set_permissions(fs::Permissions::from_mode(0o600));
-        }
-        temp_file.write_all(raw.as_bytes())?;
-        if let Err(err) = temp_file.sync_all() {
-            tracing::warn!(
-                error = %err,
-                path = %temp_path.display(),
-                "Failed to fsync reputation temp file"
-            );
+/// [`storage::REPUTATION`] version 1 to 2: records written before `first_seen_unix` existed
+/// get their `last_seen_unix`.
+pub fn migrate_v1_to_v2(mut data: Value) -> anyhow::Result<Value> {
+    let Some(records) = data.get_mut("records") else {
+        return Ok(data);
+    };
+    let records = records
+        .as_object_mut()
+        .context("records is not an object")?;
+    for (key, rec) in records.iter_mut() {
+        let rec = rec
+            .as_object_mut()
+            .with_context(|| format!("record {key:?} is not an object"))?;
+        let field = |rec: &Map<String, Value>, name: &str| rec.get(name).and_then(Value::as_u64);
+        if field(rec, "first_seen_unix").unwrap_or(0) == 0 {
+            let last_seen = field(rec, "last_seen_unix").unwrap_or(0);
+            rec.insert("first_seen_unix".to_string(), Value::from(last_seen));
         }
-        drop(temp_file);
+    }
+    Ok(data)
+}
 
-        if let Err(err) = fs::rename(&temp_path, &self.path) {
-            let _ = fs::remove_file(&temp_path);
-            return Err(err.into());
-        }
+/// Write `raw` to `path` via a 0600 temp file + rename, fsyncing best-effort.
+pub(crate) fn write_private_atomic(path: &Path, raw: &[u8]) -> anyhow::Result<()> {
+    let parent = path.parent().unwrap_or_else(|| Path::new("."));
+    if !parent.as_os_str().is_empty() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp_path = temp_path_for(path, parent);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        options.mode(0o600);
+    }
+
+    let mut temp_file = options.open(&temp_path)?;
+    #[cfg(unix)]
+    {
+        let _ = temp_file.set_permissions(fs::Permissions::from_mode(0o600));
+    }
+    temp_file.write_all(raw)?;
+    if let Err(err) = temp_file.sync_all() {
+        tracing::warn!(
+            error = %err,
+            path = %temp_path.display(),
+            "Failed to fsync temp file"
+        );
+    }
+    drop(temp_file);
 
-        fsync_dir_best_effort(parent);
-        Ok(())
+    if let Err(err) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
     }
+
+    fsync_dir_best_effort(parent);
+    Ok(())
 }
 
 fn quarantine_corrupt_file(path: &Path, quarantine: &Path) -> anyhow::Result<()> {
@@ -212,7 +393,7 @@ fn fsync_dir_best_effort(path: &Path) {
                 tracing::warn!(
                     error = %err,
                     path = %path.display(),
-                    "Failed to fsync reputation directory"
+                    "Failed to fsync store directory"
                 );
             }
         }
@@ -220,7 +401,7 @@ fn fsync_dir_best_effort(path: &Path) {
             tracing::warn!(
                 error = %err,
                 path = %path.display(),
-                "Failed to open reputation directory for fsync"
+                "Failed to open store directory for fsync"
             );
         }
     }
@@ -253,30 +434,109 @@ impl ReputationStore for JsonFileReputationStore {
         self.inner.lock().unwrap().get(key).cloned()
     }
 
+    fn list(&self) -> Vec<ReputationRecord> {
+        self.inner.lock().unwrap().values().cloned().collect()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.inner.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn cardinality(&self) -> CardinalitySnapshot {
+        self.limits.snapshot(self.inner.lock().unwrap().len())
+    }
+
+    fn storage(&self) -> Option<storage::StoreInfo> {
+        Some(self.file.info())
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     fn record(&self, mut obs: Observation) -> Vec<ReputationRecord> {
         if obs.now_unix == 0 {
             obs.now_unix = now_unix();
         }
 
-        let mut out: Vec<ReputationRecord> = vec![];
         let mut map = self.inner.lock().unwrap();
-
-        let src_key = format!("source_id:{}", obs.source_id);
-        InMemoryReputationStore::upsert_locked(&mut map, src_key.clone(), &obs);
-        out.push(map.get(&src_key).cloned().unwrap());
-
-        if let Some(host) = &obs.host {
-            let host_key = format!("host:{}", host);
-            InMemoryReputationStore::upsert_locked(&mut map, host_key.clone(), &obs);
-            out.push(map.get(&host_key).cloned().unwrap());
+        if self.read_only {
+            return current_records(|key| map.get(key).cloned(), &obs);
         }
 
-        // Persist best-effort.
-        let _ = self.persist(&map);
+        let (out, changed) = InMemoryReputationStore::record_locked(&mut map, &self.limits, &obs);
+        if changed {
+            // Persist best-effort.
+            let _ = self.persist(&map);
+        }
         out
     }
 }
 
+/// Write-rejecting layer for backends that cannot be opened read-only (the in-memory store):
+/// `record` reports the current records and changes nothing.
+pub struct ReadOnlyReputationStore<S>(pub S);
+
+impl<S: ReputationStore> ReputationStore for ReadOnlyReputationStore<S> {
+    fn get(&self, key: &str) -> Option<ReputationRecord> {
+        self.0.get(key)
+    }
+
+    fn list(&self) -> Vec<ReputationRecord> {
+        self.0.list()
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.0.keys()
+    }
+
+    fn cardinality(&self) -> CardinalitySnapshot {
+        self.0.cardinality()
+    }
+
+    fn storage(&self) -> Option<storage::StoreInfo> {
+        self.0.storage()
+    }
+
+    fn is_read_only(&self) -> bool {
+        true
+    }
+
+    fn record(&self, obs: Observation) -> Vec<ReputationRecord> {
+        current_records(|key| self.0.get(key), &obs)
+    }
+}
+
+/// The records `obs` would update, as they stand (zeroed for keys never seen).
+fn current_records(
+    get: impl Fn(&str) -> Option<ReputationRecord>,
+    obs: &Observation,
+) -> Vec<ReputationRecord> {
+    observed_keys(obs)
+        .into_iter()
+        .map(|key| {
+            get(&key).unwrap_or_else(|| ReputationRecord {
+                key,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Reputation key of a sender (`source_id`).
+pub fn source_key(source_id: &str) -> String {
+    format!("source_id:{source_id}")
+}
+
+/// The keys `obs` updates: its source, then its host if any.
+fn observed_keys(obs: &Observation) -> Vec<String> {
+    let mut keys = vec![source_key(&obs.source_id)];
+    if let Some(host) = &obs.host {
+        keys.push(format!("host:{host}"));
+    }
+    keys
+}
+
 /// Helper for building an observation from request metadata.
 pub fn observation(
     source_id: String,
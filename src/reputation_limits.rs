@@ -0,0 +1,238 @@
+//! Cardinality governance for the reputation stores.
+//!
+//! `max_records` caps how many keys a store holds. Past the cap the records with the lowest
+//! effective score go first, oldest `last_seen_unix` first among equals; records at or above
+//! `high_score` are never evicted and leave only once decay takes them below it. Each overflow
+//! evicts 1% of the cap at once so a full store does not rescan on every new key.
+//!
+//! With `prefilter` on, a key seen for the first time in a clean observation gets no record:
+//! only its second sighting within the recent-keys window (or a nonzero threat score) allocates
+//! one, so one-off keys never reach the store. The window is a two-generation Bloom filter of
+//! about `prefilter_keys` keys; a false positive only means a record is allocated early.
+
+use crate::config::ReputationConfig;
+use crate::reputation::{Observation, ReputationRecord};
+use crate::reputation_policy::{risk_breakdown, ReputationThresholds};
+use serde::Serialize;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+const DEFAULT_PREFILTER_KEYS: usize = 100_000;
+/// Bits per key and probes per lookup: about 1% false positives at capacity.
+const BITS_PER_KEY: usize = 10;
+const PROBES: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardinalitySettings {
+    /// Most records kept; `0` is unbounded.
+    pub max_records: usize,
+    /// Allocate a record only on a key's second clean sighting (or first risky one).
+    pub prefilter: bool,
+    /// Keys per generation of the recent-keys filter.
+    pub prefilter_keys: usize,
+}
+
+impl Default for CardinalitySettings {
+    fn default() -> Self {
+        Self {
+            max_records: 0,
+            prefilter: false,
+            prefilter_keys: DEFAULT_PREFILTER_KEYS,
+        }
+    }
+}
+
+impl CardinalitySettings {
+    /// Config values first, then `ACIP_REP_MAX_RECORDS`, `ACIP_REP_PREFILTER` and
+    /// `ACIP_REP_PREFILTER_KEYS` overrides.
+    pub fn from_config(cfg: Option<&ReputationConfig>) -> Self {
+        fn env<T: std::str::FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok()?.trim().parse().ok()
+        }
+        let d = Self::default();
+        Self {
+            max_records: env("ACIP_REP_MAX_RECORDS")
+                .or(cfg.and_then(|c| c.max_records))
+                .unwrap_or(d.max_records),
+            prefilter: env("ACIP_REP_PREFILTER")
+                .or(cfg.and_then(|c| c.prefilter))
+                .unwrap_or(d.prefilter),
+            prefilter_keys: env("ACIP_REP_PREFILTER_KEYS")
+                .or(cfg.and_then(|c| c.prefilter_keys))
+                .unwrap_or(d.prefilter_keys)
+                .max(1),
+        }
+    }
+}
+
+/// Keys seen recently: two generations of a Bloom filter. A key is recent if either holds it;
+/// the older one is dropped once the newer has taken `capacity` keys.
+struct RecentKeys {
+    capacity: usize,
+    current: Vec<u64>,
+    previous: Vec<u64>,
+    inserted: usize,
+}
+
+impl RecentKeys {
+    fn new(capacity: usize) -> Self {
+        let words = (capacity * BITS_PER_KEY).div_ceil(64).max(16);
+        Self {
+            capacity,
+            current: vec![0; words],
+            previous: vec![0; words],
+            inserted: 0,
+        }
+    }
+
+    fn bits(&self, key: &str) -> impl Iterator<Item = usize> {
+        let hash = |seed: u8| {
+            let mut h = DefaultHasher::new();
+            seed.hash(&mut h);
+            key.hash(&mut h);
+            h.finish()
+        };
+        let (h1, h2) = (hash(0), hash(1) | 1);
+        let n = (self.current.len() * 64) as u64;
+        (0..PROBES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % n) as usize)
+    }
+
+    fn holds(words: &[u64], bits: &[usize]) -> bool {
+        bits.iter().all(|b| words[b / 64] & (1 << (b % 64)) != 0)
+    }
+
+    /// Add `key`; whether it was already recent.
+    fn check_and_insert(&mut self, key: &str) -> bool {
+        let bits: Vec<usize> = self.bits(key).collect();
+        if Self::holds(&self.current, &bits) {
+            return true;
+        }
+        let seen = Self::holds(&self.previous, &bits);
+        if self.inserted >= self.capacity {
+            self.previous = std::mem::replace(&mut self.current, vec![0; self.previous.len()]);
+            self.inserted = 0;
+        }
+        for b in bits {
+            self.current[b / 64] |= 1 << (b % 64);
+        }
+        self.inserted += 1;
+        seen
+    }
+}
+
+/// Cap, eviction and pre-filter state shared by a store's writes.
+pub struct CardinalityGuard {
+    settings: CardinalitySettings,
+    thresholds: ReputationThresholds,
+    recent: Mutex<RecentKeys>,
+    evictions: AtomicU64,
+    suppressed: AtomicU64,
+}
+
+impl Default for CardinalityGuard {
+    /// Unbounded, no pre-filter.
+    fn default() -> Self {
+        Self::new(
+            CardinalitySettings::default(),
+            ReputationThresholds::from_env(),
+        )
+    }
+}
+
+impl CardinalityGuard {
+    pub fn new(settings: CardinalitySettings, thresholds: ReputationThresholds) -> Self {
+        let recent = RecentKeys::new(if settings.prefilter {
+            settings.prefilter_keys
+        } else {
+            0
+        });
+        Self {
+            settings,
+            thresholds,
+            recent: Mutex::new(recent),
+            evictions: AtomicU64::new(0),
+            suppressed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> &CardinalitySettings {
+        &self.settings
+    }
+
+    /// Whether `key`, which has no record yet, gets one for `obs`.
+    pub fn admit(&self, key: &str, obs: &Observation) -> bool {
+        if !self.settings.prefilter || obs.threat_score > 0 {
+            return true;
+        }
+        if self.recent.lock().unwrap().check_and_insert(key) {
+            return true;
+        }
+        self.suppressed.fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Evict records past `max_records`, scored at `now_unix`. Keys in `keep` (the ones just
+    /// written) and records in the protected band stay. Returns how many were evicted.
+    pub fn enforce(
+        &self,
+        map: &mut HashMap<String, ReputationRecord>,
+        now_unix: u64,
+        keep: &[String],
+    ) -> usize {
+        let max = self.settings.max_records;
+        if max == 0 || map.len() <= max {
+            return 0;
+        }
+        let excess = map.len() - max + max / 100;
+        let high = self.thresholds.high_score;
+        let mut candidates: Vec<(u64, u64, &String)> = map
+            .iter()
+            .filter(|(key, _)| !keep.contains(key))
+            .map(|(key, r)| {
+                let score = risk_breakdown(now_unix, r, &self.thresholds).effective_risk;
+                (score, r.last_seen_unix, key)
+            })
+            .filter(|(score, _, _)| *score < high)
+            .collect();
+        if candidates.len() > excess {
+            candidates.select_nth_unstable(excess);
+            candidates.truncate(excess);
+        }
+        let victims: Vec<String> = candidates.into_iter().map(|(_, _, k)| k.clone()).collect();
+        for key in &victims {
+            map.remove(key);
+        }
+        self.evictions
+            .fetch_add(victims.len() as u64, Ordering::Relaxed);
+        victims.len()
+    }
+
+    /// Gauges for `/status`, given the store's current record count.
+    pub fn snapshot(&self, records: usize) -> CardinalitySnapshot {
+        CardinalitySnapshot {
+            records,
+            max_records: (self.settings.max_records > 0).then_some(self.settings.max_records),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            prefilter: self.settings.prefilter,
+            prefilter_suppressed: self.suppressed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Reputation store cardinality as reported by `/v1/acip/status`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CardinalitySnapshot {
+    pub records: usize,
+    /// `None` when unbounded. `records` may exceed it by protected records.
+    pub max_records: Option<usize>,
+    pub evictions: u64,
+    pub prefilter: bool,
+    /// First clean sightings that got no record.
+    pub prefilter_suppressed: u64,
+}
@@ -1,5 +1,7 @@
-use crate::reputation::ReputationRecord;
+use crate::config;
+use crate::reputation::{Clock, ReputationRecord, SystemClock};
 use crate::sentry::{Action, Decision, RiskLevel};
+use serde::Serialize;
 
 #[derive(Debug, Clone)]
 pub struct ReputationThresholds {
@@ -10,33 +12,82 @@ pub struct ReputationThresholds {
     // Adaptive decay knobs
     pub half_life_base_days: f64,
     pub half_life_k: f64,
+
+    // Trust discount knobs (see `trust_discount`)
+    pub trust_max_discount: f64,
+    pub trust_full_age_days: f64,
+    pub trust_full_clean_ingests: u64,
 }
 
 impl ReputationThresholds {
     pub fn from_env() -> Self {
-        fn get(name: &str, default: u64) -> u64 {
+        Self::from_config(None)
+    }
+
+    /// Config values first, then `ACIP_REP_*` env overrides.
+    pub fn from_config(cfg: Option<&config::ReputationConfig>) -> Self {
+        fn get_u64(name: &str, cfg: Option<u64>, default: u64) -> u64 {
             std::env::var(name)
                 .ok()
                 .and_then(|v| v.trim().parse::<u64>().ok())
+                .or(cfg)
                 .unwrap_or(default)
         }
-
-        Self {
-            medium_score: get("ACIP_REP_MED", 20),
-            high_score: get("ACIP_REP_HIGH", 50),
-            bad_actor_score: get("ACIP_REP_BAD", 150),
-            half_life_base_days: std::env::var("ACIP_REP_HALFLIFE_BASE_DAYS")
-                .ok()
-                .and_then(|v| v.trim().parse::<f64>().ok())
-                .unwrap_or(2.0),
-            half_life_k: std::env::var("ACIP_REP_HALFLIFE_K")
+        fn get_f64(name: &str, cfg: Option<f64>, default: f64) -> f64 {
+            std::env::var(name)
                 .ok()
                 .and_then(|v| v.trim().parse::<f64>().ok())
-                .unwrap_or(0.5),
+                .or(cfg)
+                .unwrap_or(default)
+        }
+
+        let mut trust_max_discount = get_f64(
+            "ACIP_REP_TRUST_MAX_DISCOUNT",
+            cfg.and_then(|c| c.trust_max_discount),
+            0.5,
+        );
+        // Defensive clamp: a discount outside 0..=1 would invert or zero out scoring.
+        if !(0.0..=1.0).contains(&trust_max_discount) {
+            trust_max_discount = 0.5;
+        }
+
+        Self {
+            medium_score: get_u64("ACIP_REP_MED", cfg.and_then(|c| c.medium_score), 20),
+            high_score: get_u64("ACIP_REP_HIGH", cfg.and_then(|c| c.high_score), 50),
+            bad_actor_score: get_u64("ACIP_REP_BAD", cfg.and_then(|c| c.bad_actor_score), 150),
+            half_life_base_days: get_f64(
+                "ACIP_REP_HALFLIFE_BASE_DAYS",
+                cfg.and_then(|c| c.half_life_base_days),
+                2.0,
+            ),
+            half_life_k: get_f64("ACIP_REP_HALFLIFE_K", cfg.and_then(|c| c.half_life_k), 0.5),
+            trust_max_discount,
+            trust_full_age_days: get_f64(
+                "ACIP_REP_TRUST_FULL_AGE_DAYS",
+                cfg.and_then(|c| c.trust_full_age_days),
+                365.0,
+            ),
+            trust_full_clean_ingests: get_u64(
+                "ACIP_REP_TRUST_FULL_CLEAN_INGESTS",
+                cfg.and_then(|c| c.trust_full_clean_ingests),
+                1000,
+            ),
         }
     }
 }
 
+/// How a record's raw risk turned into the score used for thresholds.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct RiskBreakdown {
+    pub raw_risk: u64,
+    /// After time decay.
+    pub decayed_risk: u64,
+    /// Fraction (0.0..=1.0) removed for account age and clean traffic.
+    pub trust_discount: f64,
+    /// Score compared against thresholds.
+    pub effective_risk: u64,
+}
+
 fn bump_risk_level(level: RiskLevel) -> RiskLevel {
     match level {
         RiskLevel::Low => RiskLevel::Medium,
@@ -45,7 +96,7 @@ fn bump_risk_level(level: RiskLevel) -> RiskLevel {
     }
 }
 
-fn effective_risk_score(now_unix: u64, r: &ReputationRecord, t: &ReputationThresholds) -> u64 {
+fn decayed_risk_score(now_unix: u64, r: &ReputationRecord, t: &ReputationThresholds) -> u64 {
     if r.risk_score == 0 {
         return 0;
     }
@@ -64,47 +115,143 @@ fn effective_risk_score(now_unix: u64, r: &ReputationRecord, t: &ReputationThres
         .round()
         .clamp(0.0, u64::MAX as f64) as u64
 }
+
+/// Fraction of risk forgiven for a long-lived source with mostly clean traffic.
+///
+/// Half the discount is earned by age (`first_seen_unix` vs `trust_full_age_days`) and half by
+/// clean ingests (vs `trust_full_clean_ingests`); the sum is scaled by `trust_max_discount`.
+fn trust_discount(now_unix: u64, r: &ReputationRecord, t: &ReputationThresholds) -> f64 {
+    if t.trust_max_discount <= 0.0 || r.first_seen_unix == 0 {
+        return 0.0;
+    }
+
+    let age_days = (now_unix.saturating_sub(r.first_seen_unix) as f64) / 86_400.0;
+    let age_factor = if t.trust_full_age_days > 0.0 {
+        (age_days / t.trust_full_age_days).min(1.0)
+    } else {
+        1.0
+    };
+    let volume_factor = if t.trust_full_clean_ingests > 0 {
+        ((r.clean_ingests() as f64) / (t.trust_full_clean_ingests as f64)).min(1.0)
+    } else {
+        1.0
+    };
+
+    (t.trust_max_discount * (age_factor + volume_factor) / 2.0).clamp(0.0, 1.0)
+}
+
+/// Score a record: time decay, then trust discount.
+///
+/// The discount only applies while the decayed score is below `high_score`, so established
+/// sources can still be escalated and the `bad_actor_score` cutoff stays absolute.
+pub fn risk_breakdown(
+    now_unix: u64,
+    r: &ReputationRecord,
+    t: &ReputationThresholds,
+) -> RiskBreakdown {
+    let decayed_risk = decayed_risk_score(now_unix, r, t);
+    let discount = if decayed_risk < t.high_score {
+        trust_discount(now_unix, r, t)
+    } else {
+        0.0
+    };
+    let effective_risk = ((decayed_risk as f64) * (1.0 - discount)).round() as u64;
+
+    RiskBreakdown {
+        raw_risk: r.risk_score,
+        decayed_risk,
+        trust_discount: discount,
+        effective_risk,
+    }
+}
+
+/// How risky `r` is for record selection: compared field by field, a greater key is riskier.
+/// Ties on effective risk go, in turn, to more suspected attacks, the higher raw risk, the more
+/// recent `last_seen_unix`, and finally the lexicographically smaller key, so the order is total
+/// and never depends on the order the store returned the records in.
+#[allow(clippy::type_complexity)]
+fn selection_key<'a>(
+    r: &'a ReputationRecord,
+    b: &RiskBreakdown,
+) -> (u64, u64, u64, u64, std::cmp::Reverse<&'a str>) {
+    (
+        b.effective_risk,
+        r.suspected_attack_count,
+        r.risk_score,
+        r.last_seen_unix,
+        std::cmp::Reverse(r.key.as_str()),
+    )
+}
+
+/// `records` with their scores, riskiest first (see [`selection_key`] for the tie-breakers).
+pub fn rank_records<'a>(
+    now_unix: u64,
+    records: &'a [ReputationRecord],
+    t: &ReputationThresholds,
+) -> Vec<(&'a ReputationRecord, RiskBreakdown)> {
+    let mut ranked: Vec<_> = records
+        .iter()
+        .map(|r| (r, risk_breakdown(now_unix, r, t)))
+        .collect();
+    ranked.sort_by(|(a, ab), (b, bb)| selection_key(b, bb).cmp(&selection_key(a, ab)));
+    ranked
+}
+
 /// Apply reputation-based escalation.
 ///
+/// The riskiest record ([`rank_records`]) decides the escalation and is named in the
+/// `source reputation:` reason; every other record at or above `medium_score` is listed with its
+/// own `reputation flagged:` reason, so a request matching several bad keys reports all of them.
+///
 /// Policy:
 /// - Explicit tool authorization may override bad reputation up to `bad_actor_score`.
 /// - At/above `bad_actor_score`, tools are always hard-capped off.
 pub fn apply_reputation(
+    decision: Decision,
+    allow_tools: bool,
+    records: &[ReputationRecord],
+    t: &ReputationThresholds,
+) -> Decision {
+    apply_reputation_with_clock(decision, allow_tools, records, t, &SystemClock)
+}
+
+/// [`apply_reputation`] with an explicit time source.
+pub fn apply_reputation_with_clock(
     mut decision: Decision,
     allow_tools: bool,
     records: &[ReputationRecord],
     t: &ReputationThresholds,
+    clock: &dyn Clock,
 ) -> Decision {
     if records.is_empty() {
         return decision;
     }
 
-    let now_unix = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
-
-    let mut worst_eff: Option<(ReputationRecord, u64)> = None;
-    for r in records {
-        let eff = effective_risk_score(now_unix, r, t);
-        match &worst_eff {
-            None => worst_eff = Some((r.clone(), eff)),
-            Some((best, best_eff)) => {
-                if (eff, r.suspected_attack_count) > (*best_eff, best.suspected_attack_count) {
-                    worst_eff = Some((r.clone(), eff));
-                }
-            }
-        }
-    }
-    let Some((worst, effective_risk)) = worst_eff else {
+    let ranked = rank_records(clock.now_unix(), records, t);
+    let Some((worst, breakdown)) = ranked.first() else {
         return decision;
     };
+    let effective_risk = breakdown.effective_risk;
 
     // Always add context reason (non-sensitive).
     decision.reasons.push(format!(
-        "source reputation: key={} effective_risk={} raw_risk={} suspected_attacks={}",
-        worst.key, effective_risk, worst.risk_score, worst.suspected_attack_count
+        "source reputation: key={} effective_risk={} raw_risk={} suspected_attacks={} trust_discount={:.2}",
+        worst.key,
+        effective_risk,
+        worst.risk_score,
+        worst.suspected_attack_count,
+        breakdown.trust_discount
     ));
+    for (r, b) in ranked
+        .iter()
+        .skip(1)
+        .filter(|(_, b)| b.effective_risk >= t.medium_score)
+    {
+        decision.reasons.push(format!(
+            "reputation flagged: key={} effective_risk={} raw_risk={} suspected_attacks={}",
+            r.key, b.effective_risk, r.risk_score, r.suspected_attack_count
+        ));
+    }
 
     if effective_risk >= t.medium_score {
         decision.risk_level = bump_risk_level(decision.risk_level);
@@ -0,0 +1,167 @@
+//! Decision summary headers on ingest responses, for proxies that route on the verdict
+//! without parsing the body.
+//!
+//! A response that carries one decision gets `X-ACIP-Action`, `X-ACIP-Risk-Level`,
+//! `X-ACIP-Tools-Allowed` and `X-ACIP-Request-Id` (`origin.request_id`). With
+//! `[response_headers] patterns = true` it also gets `X-ACIP-Patterns`: the first
+//! `max_patterns` of `detected_patterns`, comma-separated. That one is off by default, since
+//! headers are size-constrained and pattern ids can name hosts.
+//!
+//! The headers are read back from the finished body ([`RiskSummary::from_body`]), so they
+//! cannot disagree with it; responses without a decision (errors, `202` job submissions) get
+//! none. Output redaction applies to their values as to the body ([`redact`]).
+
+use crate::config::ResponseHeadersConfig;
+use crate::redact::Redaction;
+use crate::sentry::{Action, RiskLevel};
+use anyhow::{bail, Result};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const ACTION: &str = "x-acip-action";
+pub const RISK_LEVEL: &str = "x-acip-risk-level";
+pub const TOOLS_ALLOWED: &str = "x-acip-tools-allowed";
+pub const REQUEST_ID: &str = "x-acip-request-id";
+pub const PATTERNS: &str = "x-acip-patterns";
+
+/// Every header this module may set.
+pub const NAMES: [&str; 5] = [ACTION, RISK_LEVEL, TOOLS_ALLOWED, REQUEST_ID, PATTERNS];
+
+pub const DEFAULT_MAX_PATTERNS: usize = 5;
+pub const MAX_MAX_PATTERNS: usize = 32;
+
+/// The decision fields of an ingest response body that the headers mirror.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RiskSummary {
+    pub action: Action,
+    pub risk_level: RiskLevel,
+    pub tools_allowed: bool,
+    origin: OriginRef,
+    #[serde(default)]
+    pub detected_patterns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct OriginRef {
+    request_id: String,
+}
+
+impl RiskSummary {
+    /// `None` unless `body` is a decision.
+    pub fn from_body(body: &Value) -> Option<Self> {
+        Self::deserialize(body).ok()
+    }
+
+    pub fn request_id(&self) -> &str {
+        &self.origin.request_id
+    }
+}
+
+/// Which summary headers to set (`[response_headers]`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RiskHeaders {
+    /// Pattern ids in `X-ACIP-Patterns`; `0` leaves the header out.
+    pub max_patterns: usize,
+}
+
+impl RiskHeaders {
+    pub fn from_config(cfg: Option<&ResponseHeadersConfig>) -> Result<Self> {
+        let Some(cfg) = cfg else {
+            return Ok(Self::default());
+        };
+        if !cfg.patterns.unwrap_or(false) {
+            return Ok(Self::default());
+        }
+        let max_patterns = cfg.max_patterns.unwrap_or(DEFAULT_MAX_PATTERNS);
+        if !(1..=MAX_MAX_PATTERNS).contains(&max_patterns) {
+            bail!("response_headers.max_patterns must be between 1 and {MAX_MAX_PATTERNS}");
+        }
+        Ok(Self { max_patterns })
+    }
+
+    /// Set the headers of `summary`, replacing any already there.
+    pub fn insert(&self, summary: &RiskSummary, headers: &mut HeaderMap) {
+        let mut set = |name: &'static str, value: &str| {
+            if let Ok(v) = HeaderValue::from_str(value) {
+                headers.insert(HeaderName::from_static(name), v);
+            }
+        };
+        set(ACTION, &wire_name(&summary.action));
+        set(RISK_LEVEL, &wire_name(&summary.risk_level));
+        set(
+            TOOLS_ALLOWED,
+            if summary.tools_allowed {
+                "true"
+            } else {
+                "false"
+            },
+        );
+        set(REQUEST_ID, summary.request_id());
+        if self.max_patterns > 0 && !summary.detected_patterns.is_empty() {
+            let top: Vec<&str> = summary
+                .detected_patterns
+                .iter()
+                .take(self.max_patterns)
+                .map(String::as_str)
+                .collect();
+            set(PATTERNS, &top.join(","));
+        }
+    }
+
+    /// Set the headers on a successful response whose JSON body is a decision.
+    pub async fn attach(&self, resp: Response) -> Response {
+        if !resp.status().is_success() {
+            return resp;
+        }
+        let (mut parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, crate::redact::MAX_REDACT_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "response too large to summarize",
+                )
+                    .into_response()
+            }
+        };
+        if let Some(summary) = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .as_ref()
+            .and_then(RiskSummary::from_body)
+        {
+            self.insert(&summary, &mut parts.headers);
+        }
+        Response::from_parts(parts, Body::from(bytes))
+    }
+}
+
+/// Apply output redaction to the summary headers of `headers`, one pattern id at a time as in
+/// the body.
+pub fn redact(redaction: &Redaction, headers: &mut HeaderMap) {
+    for name in NAMES {
+        let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) else {
+            continue;
+        };
+        let redacted: Vec<_> = value.split(',').map(|v| redaction.redact_str(v)).collect();
+        let redacted = redacted.join(",");
+        if redacted != value {
+            match HeaderValue::from_str(&redacted) {
+                Ok(v) => headers.insert(name, v),
+                Err(_) => headers.remove(name),
+            };
+        }
+    }
+}
+
+/// How `value` is spelled in the body.
+fn wire_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(s)) => s,
+        _ => String::new(),
+    }
+}
@@ -1,34 +1,47 @@
+use crate::acip_headers::HeaderRules;
 use crate::introspection;
+use crate::json_stream;
+use crate::pagination::{self, PageParams};
+use crate::reputation::{Clock, SystemClock};
+use crate::reputation_policy;
 use crate::state::AppState;
+use crate::stats;
 use axum::{
-    extract::State,
+    extract::{Query, State},
     http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
+use futures_util::StreamExt;
+use serde::Deserialize;
 use serde_json::json;
 use std::sync::Arc;
 
-pub fn policy_name_from_headers(headers: &HeaderMap) -> String {
-    headers
-        .get("x-acip-policy")
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.trim().to_string())
-        .filter(|s| !s.is_empty())
-        .unwrap_or_else(|| "default".to_string())
+/// Policy selected by `X-ACIP-Policy` (see [`crate::acip_headers::HeaderRules::policy_name`]).
+pub fn policy_name_from_headers(headers: &HeaderMap, rules: &HeaderRules) -> String {
+    rules.policy_name(headers)
 }
 
-pub async fn list_policies(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    let mut names = state.policies.list();
-    names.sort();
-    (StatusCode::OK, Json(json!({ "policies": names })))
+/// Policy names, ordered by name.
+pub async fn list_policies(
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<PageParams>,
+) -> impl IntoResponse {
+    let page = match pagination::paginate(state.policies.list(), "policies", String::clone, &page) {
+        Ok(p) => p,
+        Err(e) => return e.into_response(),
+    };
+    let mut v = json!(page);
+    // Deprecated alias of `items`, kept for one release.
+    v["policies"] = v["items"].clone();
+    (StatusCode::OK, Json(v)).into_response()
 }
 
 pub async fn get_policy(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
 ) -> impl IntoResponse {
-    let name = policy_name_from_headers(&headers);
+    let name = policy_name_from_headers(&headers, &state.header_rules);
     let Some(p) = state.policies.get(&name) else {
         let mut names = state.policies.list();
         names.sort();
@@ -40,9 +53,114 @@ pub async fn get_policy(
         .into_response();
     };
 
-    (StatusCode::OK, Json(json!({ "name": name, "policy": p }))).into_response()
+    (
+        StatusCode::OK,
+        Json(json!({
+            "name": name,
+            "policy": p,
+            "declared": state.policies.declared(&name),
+            "extends_chain": state.policies.chain(&name),
+            "revision": state.policies.revision(&name),
+            "url_allowlist": state.feeds.effective_allowlist(&p.url_allowlist),
+        })),
+    )
+        .into_response()
 }
 
 pub async fn get_schema() -> impl IntoResponse {
     (StatusCode::OK, Json(introspection::decision_schema()))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ReputationQuery {
+    /// Record key, e.g. `source_id:abc` or `host:example.com`.
+    pub key: String,
+}
+
+/// Inspect one reputation record with its raw, decayed, and trust-discounted scores.
+pub async fn get_reputation(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<ReputationQuery>,
+) -> impl IntoResponse {
+    let Some(rec) = state.reputation.get(&q.key) else {
+        return introspection::json_error(
+            StatusCode::NOT_FOUND,
+            "unknown reputation key",
+            json!({ "requested": q.key }),
+        )
+        .into_response();
+    };
+
+    let score = reputation_policy::risk_breakdown(
+        SystemClock.now_unix(),
+        &rec,
+        &state.reputation_thresholds,
+    );
+    (
+        StatusCode::OK,
+        Json(json!({ "record": rec, "score": score })),
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReputationListQuery {
+    /// Only keys starting with this, e.g. `host:`.
+    #[serde(default)]
+    pub prefix: Option<String>,
+}
+
+/// Reputation records, ordered by key.
+///
+/// With `Accept: application/x-ndjson` every matching record is streamed, one per line, and
+/// the pagination parameters are ignored (export for audits).
+pub async fn list_reputation(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<ReputationListQuery>,
+    Query(page): Query<PageParams>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if json_stream::StreamFormat::from_headers(&headers) == json_stream::StreamFormat::Ndjson {
+        // Export: only the keys are copied up front; each record is read as it is sent, and
+        // keys evicted in the meantime are skipped.
+        let mut keys = state.reputation.keys();
+        if let Some(prefix) = q.prefix.as_deref() {
+            keys.retain(|k| k.starts_with(prefix));
+        }
+        keys.sort();
+        let store = state.reputation.clone();
+        return json_stream::stream_response(
+            json_stream::StreamFormat::Ndjson,
+            futures_util::stream::iter(keys)
+                .filter_map(move |key| futures_util::future::ready(store.get(&key))),
+        );
+    }
+    let mut records = state.reputation.list();
+    if let Some(prefix) = q.prefix.as_deref() {
+        records.retain(|r| r.key.starts_with(prefix));
+    }
+    match pagination::paginate(records, "reputation", |r| r.key.clone(), &page) {
+        Ok(p) => (StatusCode::OK, Json(p)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    #[serde(default = "default_stats_days")]
+    pub days: u64,
+    #[serde(default)]
+    pub group_by: stats::GroupBy,
+}
+
+fn default_stats_days() -> u64 {
+    7
+}
+
+/// Rolling decision statistics for tuning reviews.
+pub async fn get_stats(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<StatsQuery>,
+) -> impl IntoResponse {
+    (StatusCode::OK, Json(state.stats.report(q.days, q.group_by)))
+}
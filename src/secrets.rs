@@ -1,22 +1,146 @@
+//! Secret material and where it comes from.
+//!
+//! Every secret (API tokens, provider API keys, the hashing salt, document passwords) is held
+//! as a [`Secret`]: the buffer is zeroed on drop, `Debug` and `Display` print `[REDACTED]`, and
+//! it has no `Serialize` impl, so a struct holding one must skip the field or opt in with
+//! [`serialize_exposed`]. Reading the value takes an explicit [`Secret::expose`].
+
 use anyhow::{anyhow, Context, Result};
+use reqwest::header::HeaderValue;
+use serde::{Deserialize, Deserializer, Serializer};
 use std::{
     collections::HashMap,
-    fs,
+    fmt, fs,
     path::{Path, PathBuf},
 };
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 
+/// A secret value. See the module docs.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(Zeroizing::new(value.into()))
+    }
+
+    /// The value itself. Every use is a place the secret leaves the wrapper.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.0.trim().is_empty()
+    }
+
+    /// Compare with `other` in time that depends only on the lengths.
+    pub fn ct_eq(&self, other: &str) -> bool {
+        let (a, b) = (self.0.as_bytes(), other.as_bytes());
+        let mut diff = a.len() ^ b.len();
+        for i in 0..a.len().max(b.len()) {
+            let av = *a.get(i).unwrap_or(&0);
+            let bv = *b.get(i).unwrap_or(&0);
+            diff |= (av ^ bv) as usize;
+        }
+        diff == 0
+    }
+
+    /// As a header value marked sensitive, so `http` never prints it.
+    pub fn header_value(&self) -> Result<HeaderValue> {
+        let mut v = HeaderValue::from_str(&self.0)
+            .map_err(|_| anyhow!("secret is not a valid header value"))?;
+        v.set_sensitive(true);
+        Ok(v)
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Self::new(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Self::new(value)
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl Zeroize for Secret {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for Secret {}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        String::deserialize(d).map(Self::new)
+    }
+}
+
+/// `serialize_with` for the rare field that must carry a secret on the wire (the extractor
+/// helper's request line).
+pub fn serialize_exposed<S: Serializer>(
+    secret: &Option<Secret>,
+    s: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    match secret {
+        Some(v) => s.serialize_some(v.expose()),
+        None => s.serialize_none(),
+    }
+}
+
+/// Make the process non-dumpable: no core dumps, and other same-user processes cannot read its
+/// memory through `/proc`. Called once secrets are loaded unless `security.allow_core_dumps`.
+#[cfg(target_os = "linux")]
+pub fn disable_core_dumps() -> std::io::Result<()> {
+    let no_core = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &no_core) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if unsafe { libc::prctl(libc::PR_SET_DUMPABLE, 0, 0, 0, 0) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn disable_core_dumps() -> std::io::Result<()> {
+    Ok(())
+}
+
 pub trait SecretStore: Send + Sync {
-    fn get(&self, key: &str) -> Option<String>;
+    fn get(&self, key: &str) -> Option<Secret>;
 }
 
 pub struct EnvStore;
 
 impl SecretStore for EnvStore {
-    fn get(&self, key: &str) -> Option<String> {
-        std::env::var(key).ok().filter(|v| !v.is_empty())
+    fn get(&self, key: &str) -> Option<Secret> {
+        std::env::var(key)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .map(Secret::new)
     }
 }
 
@@ -24,7 +148,7 @@ impl SecretStore for EnvStore {
 ///
 /// Intended default path for system installs: `/etc/acip/secrets.env`.
 pub struct EnvFileStore {
-    map: HashMap<String, String>,
+    map: HashMap<String, Secret>,
 }
 
 impl EnvFileStore {
@@ -32,8 +156,10 @@ impl EnvFileStore {
         let path = path.into();
         ensure_secure_dotenv(&path)?;
 
-        let contents = fs::read_to_string(&path)
-            .with_context(|| format!("failed reading dotenv file: {}", path.display()))?;
+        let contents = Zeroizing::new(
+            fs::read_to_string(&path)
+                .with_context(|| format!("failed reading dotenv file: {}", path.display()))?,
+        );
 
         let mut map = HashMap::new();
         for line in contents.lines() {
@@ -47,7 +173,7 @@ impl EnvFileStore {
                 let k = k.trim();
                 let v = v.trim();
                 if !k.is_empty() && !v.is_empty() {
-                    map.insert(k.to_string(), v.to_string());
+                    map.insert(k.to_string(), Secret::new(v));
                 }
             }
         }
@@ -57,8 +183,8 @@ impl EnvFileStore {
 }
 
 impl SecretStore for EnvFileStore {
-    fn get(&self, key: &str) -> Option<String> {
-        self.map.get(key).cloned().filter(|v| !v.is_empty())
+    fn get(&self, key: &str) -> Option<Secret> {
+        self.map.get(key).cloned()
     }
 }
 
@@ -73,7 +199,7 @@ impl CompositeStore {
 }
 
 impl SecretStore for CompositeStore {
-    fn get(&self, key: &str) -> Option<String> {
+    fn get(&self, key: &str) -> Option<Secret> {
         for s in &self.stores {
             if let Some(v) = s.get(key) {
                 return Some(v);
@@ -1,4 +1,6 @@
-use crate::{introspection, model_policy, secrets};
+use crate::model_policy::{LowConfidenceHandling, VerdictParsing};
+use crate::token_budget::PromptBudget;
+use crate::{introspection, model_policy, secrets, telemetry, token_budget};
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use axum::http::HeaderMap;
@@ -6,6 +8,7 @@ use once_cell::sync::Lazy;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::Instant;
 use tracing::{info, warn};
 
 static DECISION_SCHEMA: Lazy<jsonschema::JSONSchema> = Lazy::new(|| {
@@ -16,7 +19,7 @@ static DECISION_SCHEMA: Lazy<jsonschema::JSONSchema> = Lazy::new(|| {
 static DECISION_SCHEMA_TEXT: Lazy<String> =
     Lazy::new(|| introspection::decision_schema().to_string());
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum RiskLevel {
     Low,
@@ -24,7 +27,7 @@ pub enum RiskLevel {
     High,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 pub enum Action {
     Allow,
@@ -44,6 +47,10 @@ pub struct Decision {
     pub reasons: Vec<String>,
     #[serde(default)]
     pub detected_patterns: Vec<String>,
+    /// How sure the sentry is of this verdict, when the tier that produced it gave a signal.
+    /// Never read from model output.
+    #[serde(skip)]
+    pub confidence: Option<Confidence>,
 }
 
 impl Decision {
@@ -55,8 +62,105 @@ impl Decision {
             fenced_content,
             reasons,
             detected_patterns: vec![],
+            confidence: None,
+        }
+    }
+}
+
+/// Coarse confidence level; the thresholds are [`Confidence::HIGH`] and [`Confidence::MEDIUM`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceBucket {
+    Low,
+    Medium,
+    High,
+}
+
+impl ConfidenceBucket {
+    pub fn from_value(value: f64) -> Self {
+        if value >= Confidence::HIGH {
+            ConfidenceBucket::High
+        } else if value >= Confidence::MEDIUM {
+            ConfidenceBucket::Medium
+        } else {
+            ConfidenceBucket::Low
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfidenceBucket::Low => "low",
+            ConfidenceBucket::Medium => "medium",
+            ConfidenceBucket::High => "high",
+        }
+    }
+}
+
+/// Where a confidence value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfidenceMethod {
+    /// The provider's own signal for the output (e.g. Gemini's average token log-probability).
+    Provider,
+    /// Agreement between the verdict and an independent second sample ([`agreement`]).
+    Consistency,
+}
+
+impl ConfidenceMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfidenceMethod::Provider => "provider",
+            ConfidenceMethod::Consistency => "consistency",
+        }
+    }
+}
+
+/// Confidence in a verdict, in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Confidence {
+    pub value: f64,
+    pub bucket: ConfidenceBucket,
+    pub method: ConfidenceMethod,
+}
+
+impl Confidence {
+    /// Lowest value in the `high` bucket.
+    pub const HIGH: f64 = 0.8;
+    /// Lowest value in the `medium` bucket; anything below is `low`.
+    pub const MEDIUM: f64 = 0.5;
+
+    pub fn new(value: f64, method: ConfidenceMethod) -> Self {
+        let value = if value.is_finite() {
+            value.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        Self {
+            value,
+            bucket: ConfidenceBucket::from_value(value),
+            method,
         }
     }
+
+    fn note(&self, outcome: &str) -> String {
+        format!(
+            "low_confidence: L1 verdict confidence {:.2} ({}); {outcome}",
+            self.value,
+            self.method.as_str()
+        )
+    }
+}
+
+/// Agreement of two verdicts on the same content: `1.0` when action, tool gate and risk level
+/// all match, `0.75` when only the risk level differs, `0.25` otherwise.
+pub fn agreement(a: &Decision, b: &Decision) -> f64 {
+    if a.action != b.action || a.tools_allowed != b.tools_allowed {
+        0.25
+    } else if a.risk_level != b.risk_level {
+        0.75
+    } else {
+        1.0
+    }
 }
 
 fn extract_json_only(s: &str) -> &str {
@@ -100,6 +204,15 @@ fn strict_json_enabled() -> bool {
         .unwrap_or(false)
 }
 
+/// Parsing mode for `policy`; `ACIP_SENTRY_JSON_STRICT=1` forces strict for every policy.
+pub fn effective_verdict_parsing(policy: &model_policy::PolicyConfig) -> VerdictParsing {
+    if strict_json_enabled() {
+        VerdictParsing::Strict
+    } else {
+        policy.verdict_parsing
+    }
+}
+
 fn parse_json_strict(raw: &str) -> Result<Value> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
@@ -112,15 +225,103 @@ fn parse_json_strict(raw: &str) -> Result<Value> {
     Ok(v)
 }
 
-pub fn parse_and_validate_decision(raw: &str) -> Result<Decision> {
-    let v = if strict_json_enabled() {
-        parse_json_strict(raw)?
-    } else {
-        parse_json_strict(raw).or_else(|_| parse_json_strict(extract_json_only(raw)))?
+/// Top-level fields of the decision schema; anything else is dropped in repair mode.
+const DECISION_FIELDS: &[&str] = &[
+    "tools_allowed",
+    "risk_level",
+    "action",
+    "fenced_content",
+    "reasons",
+    "detected_patterns",
+];
+
+/// The complete repair set of [`VerdictParsing::Repair`]. Any other deviation from the schema
+/// (trailing commas, wrong types, unknown enum values, missing scalars) is a model failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Repair {
+    /// Removed a markdown code fence (```` ```json ... ``` ````) around the whole output.
+    StripCodeFences,
+    /// Took the JSON object out of surrounding prose.
+    ExtractEmbeddedJson,
+    /// Lowercased `risk_level` / `action` (e.g. `"LOW"`).
+    LowercaseEnums,
+    /// Dropped top-level fields the schema does not define (e.g. `confidence`).
+    DropUnknownFields,
+    /// Filled a missing `reasons` / `detected_patterns` with `[]`.
+    DefaultMissingArrays,
+}
+
+impl Repair {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Repair::StripCodeFences => "strip_code_fences",
+            Repair::ExtractEmbeddedJson => "extract_embedded_json",
+            Repair::LowercaseEnums => "lowercase_enums",
+            Repair::DropUnknownFields => "drop_unknown_fields",
+            Repair::DefaultMissingArrays => "default_missing_arrays",
+        }
+    }
+}
+
+/// A schema-valid decision and the repairs (in application order) it needed.
+#[derive(Debug, Clone)]
+pub struct ParsedDecision {
+    pub decision: Decision,
+    pub repairs: Vec<Repair>,
+}
+
+/// Body of a markdown code fence spanning the whole (trimmed) output, if it is one.
+fn strip_code_fence(s: &str) -> Option<&str> {
+    let rest = s.strip_prefix("```")?.strip_suffix("```")?;
+    // Drop the info string (`json`, `JSON`, ...) on the opening line.
+    let (info, body) = rest.split_once('\n')?;
+    if info.trim().chars().any(|c| !c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    Some(body)
+}
+
+fn repair_fields(v: &mut Value, repairs: &mut Vec<Repair>) {
+    let Value::Object(map) = v else {
+        return;
     };
 
+    let mut lowered = false;
+    for key in ["risk_level", "action"] {
+        if let Some(Value::String(s)) = map.get_mut(key) {
+            let lower = s.to_lowercase();
+            if *s != lower {
+                *s = lower;
+                lowered = true;
+            }
+        }
+    }
+    if lowered {
+        repairs.push(Repair::LowercaseEnums);
+    }
+
+    let before = map.len();
+    map.retain(|k, _| DECISION_FIELDS.contains(&k.as_str()));
+    if map.len() != before {
+        repairs.push(Repair::DropUnknownFields);
+    }
+
+    let mut defaulted = false;
+    for key in ["reasons", "detected_patterns"] {
+        if !map.contains_key(key) {
+            map.insert(key.to_string(), Value::Array(vec![]));
+            defaulted = true;
+        }
+    }
+    if defaulted {
+        repairs.push(Repair::DefaultMissingArrays);
+    }
+}
+
+fn validate_schema(v: &Value) -> Result<()> {
     let compiled = &*DECISION_SCHEMA;
-    if let Err(mut errs) = compiled.validate(&v) {
+    if let Err(mut errs) = compiled.validate(v) {
         // collect a few errors
         let mut msgs: Vec<String> = vec![];
         if let Some(e) = errs.next() {
@@ -134,81 +335,336 @@ pub fn parse_and_validate_decision(raw: &str) -> Result<Decision> {
             msgs.join("; ")
         ));
     }
+    Ok(())
+}
+
+/// Parse model output into a decision.
+///
+/// `Strict` accepts only a bare JSON document (surrounding whitespace aside) that validates
+/// against the decision schema. `Repair` first applies the [`Repair`] set, recording each repair
+/// used; the result must then validate exactly as in strict mode.
+pub fn parse_decision(raw: &str, mode: VerdictParsing) -> Result<ParsedDecision> {
+    let mut repairs: Vec<Repair> = vec![];
+    let mut v = match mode {
+        VerdictParsing::Strict => parse_json_strict(raw)?,
+        VerdictParsing::Repair => {
+            let mut text = raw.trim();
+            if let Some(body) = strip_code_fence(text) {
+                repairs.push(Repair::StripCodeFences);
+                text = body.trim();
+            }
+            match parse_json_strict(text) {
+                Ok(v) => v,
+                Err(e) => {
+                    let embedded = extract_json_only(text);
+                    if embedded.len() == text.len() {
+                        return Err(e);
+                    }
+                    repairs.push(Repair::ExtractEmbeddedJson);
+                    parse_json_strict(embedded)?
+                }
+            }
+        }
+    };
+
+    if mode == VerdictParsing::Repair {
+        repair_fields(&mut v, &mut repairs);
+    }
+    validate_schema(&v)?;
+
+    let decision: Decision =
+        serde_json::from_value(v).context("decision JSON did not match struct")?;
+    Ok(ParsedDecision { decision, repairs })
+}
+
+/// [`parse_decision`] in repair mode, or strict mode when `ACIP_SENTRY_JSON_STRICT=1`.
+pub fn parse_and_validate_decision(raw: &str) -> Result<Decision> {
+    let mode = if strict_json_enabled() {
+        VerdictParsing::Strict
+    } else {
+        VerdictParsing::Repair
+    };
+    Ok(parse_decision(raw, mode)?.decision)
+}
 
-    let d: Decision = serde_json::from_value(v).context("decision JSON did not match struct")?;
-    Ok(d)
+/// Model output together with the version the provider reports having served it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub text: String,
+    pub model_version: Option<String>,
+    /// The provider's own confidence in the output (`0.0..=1.0`), for providers that report one.
+    pub confidence: Option<f64>,
+    /// Prompt tokens the provider billed, for providers that report usage.
+    pub prompt_tokens: Option<u64>,
 }
 
+/// Sampling temperature of [`ModelClient::generate_sample`]; verdicts are generated at 0.
+const SAMPLE_TEMPERATURE: f64 = 0.7;
+
 #[async_trait]
 pub trait ModelClient: Send + Sync {
     async fn generate(&self, model: &str, prompt: &str, headers: &HeaderMap) -> Result<String>;
+
+    /// [`Self::generate`], also returning the model version from the response metadata, for
+    /// providers that report one.
+    async fn generate_reporting(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        Ok(Generation {
+            text: self.generate(model, prompt, headers).await?,
+            model_version: None,
+            confidence: None,
+            prompt_tokens: None,
+        })
+    }
+
+    /// A second, independently sampled output for `prompt`, for `l1.consistency_check`.
+    /// Providers that cannot sample fall back to [`Self::generate_reporting`].
+    async fn generate_sample(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        self.generate_reporting(model, prompt, headers).await
+    }
+
+    /// The version currently served under `model`, for providers that expose a lookup.
+    async fn probe_version(&self, _model: &str) -> Result<Option<String>> {
+        Ok(None)
+    }
 }
 
+/// One client shared by both tiers, e.g. [`crate::state::AppState::model_override`].
+#[async_trait]
+impl ModelClient for std::sync::Arc<dyn ModelClient> {
+    async fn generate(&self, model: &str, prompt: &str, headers: &HeaderMap) -> Result<String> {
+        (**self).generate(model, prompt, headers).await
+    }
+
+    async fn generate_reporting(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        (**self).generate_reporting(model, prompt, headers).await
+    }
+
+    async fn generate_sample(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        (**self).generate_sample(model, prompt, headers).await
+    }
+
+    async fn probe_version(&self, model: &str) -> Result<Option<String>> {
+        (**self).probe_version(model).await
+    }
+}
+
+/// A provider refused the prompt as longer than the model's context window. Unlike other call
+/// failures the same prompt would fail again, so [`DecisionEngine::decide_tiered`] retries the
+/// level once with half the content budget instead.
+#[derive(Debug, thiserror::Error)]
+#[error("{provider} context length exceeded: {message}")]
+pub struct ContextExceeded {
+    pub provider: &'static str,
+    /// The provider's error message (its first 200 characters).
+    pub message: String,
+}
+
+/// Whether a provider error body says the prompt is over the context window, e.g. Anthropic's
+/// `prompt is too long: 210000 tokens > 200000 maximum` or Gemini's `The input token count
+/// (1100000) exceeds the maximum number of tokens allowed (1048576).`
+pub fn is_context_exceeded(body: &str) -> bool {
+    let body = body.to_ascii_lowercase();
+    [
+        "prompt is too long",
+        "input token count",
+        "exceeds the maximum number of tokens",
+        "context length",
+        "context window",
+        "too many tokens",
+    ]
+    .iter()
+    .any(|p| body.contains(p))
+}
+
+/// The JSON body of a successful provider response. A 400 or 413 whose body
+/// [`is_context_exceeded`] is a [`ContextExceeded`].
+async fn response_json(resp: reqwest::Response, provider: &'static str) -> Result<Value> {
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        if matches!(status.as_u16(), 400 | 413) && is_context_exceeded(&body) {
+            let message = serde_json::from_str::<Value>(&body)
+                .ok()
+                .and_then(|v| v.pointer("/error/message")?.as_str().map(str::to_string))
+                .unwrap_or(body);
+            return Err(ContextExceeded {
+                provider,
+                message: message.chars().take(200).collect(),
+            }
+            .into());
+        }
+        return Err(anyhow!("{provider} non-2xx: {status}"));
+    }
+    resp.json()
+        .await
+        .with_context(|| format!("{provider} response not json"))
+}
+
+/// Client for `provider`.
+pub fn client_for(
+    provider: &model_policy::Provider,
+    http: Client,
+    secrets: std::sync::Arc<dyn secrets::SecretStore>,
+) -> Box<dyn ModelClient> {
+    match provider {
+        model_policy::Provider::Gemini => Box::new(GeminiClient::new(http, secrets)),
+        model_policy::Provider::Anthropic => Box::new(AnthropicClient::new(http, secrets)),
+    }
+}
+
+pub const GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+pub const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com";
+
 pub struct GeminiClient {
     http: Client,
     secrets: std::sync::Arc<dyn secrets::SecretStore>,
+    base_url: String,
 }
 
 impl GeminiClient {
     pub fn new(http: Client, secrets: std::sync::Arc<dyn secrets::SecretStore>) -> Self {
-        Self { http, secrets }
+        Self {
+            http,
+            secrets,
+            base_url: GEMINI_BASE_URL.to_string(),
+        }
     }
-}
 
-#[async_trait]
-impl ModelClient for GeminiClient {
-    async fn generate(&self, model: &str, prompt: &str, _headers: &HeaderMap) -> Result<String> {
+    /// Send requests to `base_url` instead (a proxy, or a stub in tests).
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// `headers` carry the model stage's trace context, which is forwarded to the provider.
+    async fn call(
+        &self,
+        model: &str,
+        prompt: &str,
+        temperature: f64,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
         let key = self
             .secrets
             .get("GEMINI_API_KEY")
             .ok_or_else(|| anyhow!("GEMINI_API_KEY not set"))?;
 
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent?key={}",
-            model, key
-        );
+        // The key goes in a header: a query string ends up in reqwest's error messages.
+        let url = format!("{}/v1beta/models/{}:generateContent", self.base_url, model);
 
         let body = serde_json::json!({
           "contents": [{"role": "user", "parts": [{"text": prompt}]}],
-          "generationConfig": {"temperature": 0, "maxOutputTokens": 1024}
+          "generationConfig": {
+            "temperature": temperature,
+            "maxOutputTokens": token_budget::COMPLETION_TOKENS
+          }
         });
 
-        let resp: Value = self
-            .http
-            .post(url)
+        let resp = telemetry::forward(self.http.post(url), headers)
+            .header("x-goog-api-key", key.header_value()?)
             .json(&body)
             .send()
             .await
-            .context("gemini request failed")?
-            .error_for_status()
-            .context("gemini non-2xx")?
-            .json()
-            .await
-            .context("gemini response not json")?;
+            .context("gemini request failed")?;
+        let resp = response_json(resp, "gemini").await?;
 
         // candidates[0].content.parts[0].text
         let text = resp
             .pointer("/candidates/0/content/parts/0/text")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("gemini response missing text"))?;
-        Ok(text.to_string())
+        Ok(Generation {
+            text: text.to_string(),
+            model_version: resp
+                .get("modelVersion")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            // Mean log-probability per output token, as a per-token probability.
+            confidence: resp
+                .pointer("/candidates/0/avgLogprobs")
+                .and_then(|v| v.as_f64())
+                .map(f64::exp),
+            prompt_tokens: resp
+                .pointer("/usageMetadata/promptTokenCount")
+                .and_then(|v| v.as_u64()),
+        })
+    }
+}
+
+#[async_trait]
+impl ModelClient for GeminiClient {
+    async fn generate(&self, model: &str, prompt: &str, headers: &HeaderMap) -> Result<String> {
+        Ok(self.generate_reporting(model, prompt, headers).await?.text)
+    }
+
+    async fn generate_reporting(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        self.call(model, prompt, 0.0, headers).await
+    }
+
+    async fn generate_sample(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        self.call(model, prompt, SAMPLE_TEMPERATURE, headers).await
     }
 }
 
 pub struct AnthropicClient {
     http: Client,
     secrets: std::sync::Arc<dyn secrets::SecretStore>,
+    base_url: String,
 }
 
 impl AnthropicClient {
     pub fn new(http: Client, secrets: std::sync::Arc<dyn secrets::SecretStore>) -> Self {
-        Self { http, secrets }
+        Self {
+            http,
+            secrets,
+            base_url: ANTHROPIC_BASE_URL.to_string(),
+        }
     }
-}
 
-#[async_trait]
-impl ModelClient for AnthropicClient {
-    async fn generate(&self, model: &str, prompt: &str, _headers: &HeaderMap) -> Result<String> {
+    /// Send requests to `base_url` instead (a proxy, or a stub in tests).
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// `headers` carry the model stage's trace context, which is forwarded to the provider.
+    async fn call(
+        &self,
+        model: &str,
+        prompt: &str,
+        temperature: f64,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
         let key = self
             .secrets
             .get("ANTHROPIC_API_KEY")
@@ -216,35 +672,211 @@ impl ModelClient for AnthropicClient {
 
         let body = serde_json::json!({
           "model": model,
-          "max_tokens": 1024,
-          "temperature": 0,
+          "max_tokens": token_budget::COMPLETION_TOKENS,
+          "temperature": temperature,
           "messages": [{"role": "user", "content": prompt}]
         });
 
-        let resp: Value = self
-            .http
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", key)
+        let request = self.http.post(format!("{}/v1/messages", self.base_url));
+        let resp = telemetry::forward(request, headers)
+            .header("x-api-key", key.header_value()?)
             .header("anthropic-version", "2023-06-01")
             .json(&body)
             .send()
             .await
-            .context("anthropic request failed")?
-            .error_for_status()
-            .context("anthropic non-2xx")?
-            .json()
-            .await
-            .context("anthropic response not json")?;
+            .context("anthropic request failed")?;
+        let resp = response_json(resp, "anthropic").await?;
 
         // content[0].text
         let text = resp
             .pointer("/content/0/text")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("anthropic response missing text"))?;
-        Ok(text.to_string())
+        // `model` is the concrete model that answered (aliases resolve to a dated id).
+        Ok(Generation {
+            text: text.to_string(),
+            model_version: resp
+                .get("model")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            // The Messages API reports no per-output confidence.
+            confidence: None,
+            prompt_tokens: resp.pointer("/usage/input_tokens").and_then(|v| v.as_u64()),
+        })
     }
 }
 
+#[async_trait]
+impl ModelClient for AnthropicClient {
+    async fn generate(&self, model: &str, prompt: &str, headers: &HeaderMap) -> Result<String> {
+        Ok(self.generate_reporting(model, prompt, headers).await?.text)
+    }
+
+    async fn generate_reporting(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        self.call(model, prompt, 0.0, headers).await
+    }
+
+    async fn generate_sample(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> Result<Generation> {
+        self.call(model, prompt, SAMPLE_TEMPERATURE, headers).await
+    }
+
+    async fn probe_version(&self, model: &str) -> Result<Option<String>> {
+        let key = self
+            .secrets
+            .get("ANTHROPIC_API_KEY")
+            .ok_or_else(|| anyhow!("ANTHROPIC_API_KEY not set"))?;
+
+        let resp: Value = self
+            .http
+            .get(format!("{}/v1/models/{model}", self.base_url))
+            .header("x-api-key", key.header_value()?)
+            .header("anthropic-version", "2023-06-01")
+            .send()
+            .await
+            .context("anthropic model lookup failed")?
+            .error_for_status()
+            .context("anthropic model lookup non-2xx")?
+            .json()
+            .await
+            .context("anthropic model lookup not json")?;
+        Ok(resp.get("id").and_then(|v| v.as_str()).map(str::to_string))
+    }
+}
+
+/// Model tier that produced a verdict. `L2` includes fail-closed after an L2 attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelTier {
+    L1,
+    L2,
+}
+
+/// How one model output parsed (calls that returned no output are not attempts).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseAttempt {
+    pub tier: ModelTier,
+    /// Provider and model, as in [`model_policy::ModelRef::label`].
+    pub model: String,
+    /// The output became a schema-valid decision (possibly after repairs).
+    pub valid: bool,
+    pub repairs: Vec<Repair>,
+    /// The output was the second sample of `l1.consistency_check`, not a verdict candidate.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub consistency_check: bool,
+}
+
+impl ParseAttempt {
+    fn ok(tier: ModelTier, model: &model_policy::ModelRef, p: &ParsedDecision) -> Self {
+        Self {
+            tier,
+            model: model.label(),
+            valid: true,
+            repairs: p.repairs.clone(),
+            consistency_check: false,
+        }
+    }
+
+    fn invalid(tier: ModelTier, model: &model_policy::ModelRef) -> Self {
+        Self {
+            tier,
+            model: model.label(),
+            valid: false,
+            repairs: vec![],
+            consistency_check: false,
+        }
+    }
+
+    fn consistency_check(self) -> Self {
+        Self {
+            consistency_check: true,
+            ..self
+        }
+    }
+}
+
+/// One model request made for a verdict and how long it took, for slow-request forensics.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelCall {
+    pub tier: ModelTier,
+    /// Provider and model, as in [`model_policy::ModelRef::label`].
+    pub model: String,
+    pub ms: f64,
+    /// The provider answered (whether or not the output parsed).
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub consistency_check: bool,
+    /// Prompt tokens: the provider's usage when it reported one, else the estimate the prompt
+    /// was budgeted with.
+    #[serde(default)]
+    pub prompt_tokens: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub prompt_tokens_estimated: bool,
+    /// The retry with half the content budget after the provider reported the context exceeded.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub context_retry: bool,
+}
+
+impl ModelCall {
+    fn timed(
+        tier: ModelTier,
+        model: &model_policy::ModelRef,
+        started: Instant,
+        out: &Result<Generation>,
+        budget: &PromptBudget,
+    ) -> Self {
+        let reported = out.as_ref().ok().and_then(|g| g.prompt_tokens);
+        Self {
+            tier,
+            model: model.label(),
+            ms: started.elapsed().as_secs_f64() * 1000.0,
+            ok: out.is_ok(),
+            consistency_check: false,
+            prompt_tokens: reported.unwrap_or(budget.prompt_tokens),
+            prompt_tokens_estimated: reported.is_none(),
+            context_retry: budget.context_retry,
+        }
+    }
+}
+
+/// A low-confidence L1 verdict that `on_low_confidence = "escalate_l2"` sent to L2.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SecondOpinion {
+    pub l1_confidence: Confidence,
+    pub l1_action: Action,
+    /// L2 (or fail-closed after L2) reached a different action than L1.
+    pub overturned: bool,
+}
+
+/// Final verdict of [`DecisionEngine::decide_tiered`].
+#[derive(Debug, Clone)]
+pub struct SentryVerdict {
+    pub decision: Decision,
+    /// Tier that produced the verdict; `L2` includes fail-closed after an L2 attempt.
+    pub tier: ModelTier,
+    /// Repairs applied to the output that became `decision` (empty for fail-closed).
+    pub repairs: Vec<Repair>,
+    pub attempts: Vec<ParseAttempt>,
+    /// Model version reported by the provider of `tier`, when it answered and reported one.
+    pub model_version: Option<String>,
+    /// Set when a low-confidence L1 verdict was escalated to L2.
+    pub second_opinion: Option<SecondOpinion>,
+    /// Every model request made, in order.
+    pub calls: Vec<ModelCall>,
+    /// How the prompt was fitted to each verdict request, in order (consistency samples reuse
+    /// the L1 prompt).
+    pub budgets: Vec<PromptBudget>,
+}
+
 pub struct DecisionEngine {
     pub l1: Box<dyn ModelClient>,
     pub l2: Box<dyn ModelClient>,
@@ -283,17 +915,85 @@ impl DecisionEngine {
         fenced_external: &str,
         headers: &HeaderMap,
     ) -> Decision {
-        let prompt = Self::build_prompt(policy_name, policy, source_meta, fenced_external);
+        self.decide_tiered(policy_name, policy, source_meta, fenced_external, headers)
+            .await
+            .decision
+    }
+
+    /// [`Self::decide`], also reporting which model tier produced the verdict and how each
+    /// model output parsed.
+    pub async fn decide_tiered(
+        &self,
+        policy_name: &str,
+        policy: &model_policy::PolicyConfig,
+        source_meta: &Value,
+        fenced_external: &str,
+        headers: &HeaderMap,
+    ) -> SentryVerdict {
+        let render = |content: &str| Self::build_prompt(policy_name, policy, source_meta, content);
+        let mode = effective_verdict_parsing(policy);
+        let mut attempts: Vec<ParseAttempt> = vec![];
+        let mut calls: Vec<ModelCall> = vec![];
+        let mut budgets: Vec<PromptBudget> = vec![];
+        // L1's confidence and action, when a low-confidence L1 verdict goes to L2.
+        let mut low_confidence: Option<(Confidence, Action)> = None;
 
         // L1
-        match self.l1.generate(&policy.l1.model, &prompt, headers).await {
-            Ok(out) => match parse_and_validate_decision(&out) {
-                Ok(d) => {
+        let (l1_out, prompt, l1_budget) = self
+            .generate_budgeted(
+                ModelTier::L1,
+                &policy.l1,
+                &render,
+                fenced_external,
+                headers,
+                &mut calls,
+                &mut budgets,
+            )
+            .await;
+        match l1_out {
+            Ok(out) => match parse_decision(&out.text, mode) {
+                Ok(p) => {
                     info!("sentry: L1 decision ok");
-                    return d;
+                    attempts.push(ParseAttempt::ok(ModelTier::L1, &policy.l1, &p));
+                    let mut decision = p.decision;
+                    decision.confidence = self
+                        .l1_confidence(
+                            policy,
+                            (&prompt, &l1_budget),
+                            headers,
+                            &decision,
+                            out.confidence,
+                            &mut attempts,
+                            &mut calls,
+                        )
+                        .await;
+                    let low = decision
+                        .confidence
+                        .filter(|c| c.bucket == ConfidenceBucket::Low);
+                    match low {
+                        Some(c)
+                            if apply_low_confidence(&mut decision, c, policy.on_low_confidence) =>
+                        {
+                            info!("sentry: L1 confidence {:.2}; escalating to L2", c.value);
+                            low_confidence = Some((c, decision.action.clone()));
+                        }
+                        _ => {
+                            return SentryVerdict {
+                                decision,
+                                tier: ModelTier::L1,
+                                repairs: p.repairs,
+                                attempts,
+                                model_version: out.model_version,
+                                second_opinion: None,
+                                calls,
+                                budgets,
+                            };
+                        }
+                    }
                 }
                 Err(e) => {
                     warn!("sentry: L1 output invalid: {e:#}");
+                    attempts.push(ParseAttempt::invalid(ModelTier::L1, &policy.l1));
                 }
             },
             Err(e) => {
@@ -302,27 +1002,194 @@ impl DecisionEngine {
         }
 
         // L2
-        match self.l2.generate(&policy.l2.model, &prompt, headers).await {
-            Ok(out) => match parse_and_validate_decision(&out) {
-                Ok(d) => {
+        let (l2_out, _, _) = self
+            .generate_budgeted(
+                ModelTier::L2,
+                &policy.l2,
+                &render,
+                fenced_external,
+                headers,
+                &mut calls,
+                &mut budgets,
+            )
+            .await;
+        let model_version = l2_out.as_ref().ok().and_then(|g| g.model_version.clone());
+        let l1_outcome = if low_confidence.is_some() {
+            "L1 low confidence"
+        } else {
+            "L1 failed"
+        };
+        let (mut decision, repairs) = match l2_out {
+            Ok(out) => match parse_decision(&out.text, mode) {
+                Ok(p) => {
                     info!("sentry: L2 decision ok");
-                    d
+                    attempts.push(ParseAttempt::ok(ModelTier::L2, &policy.l2, &p));
+                    let mut d = p.decision;
+                    d.confidence = out
+                        .confidence
+                        .map(|v| Confidence::new(v, ConfidenceMethod::Provider));
+                    (d, p.repairs)
                 }
                 Err(e) => {
                     warn!("sentry: L2 output invalid: {e:#}");
-                    Decision::fail_closed(
+                    attempts.push(ParseAttempt::invalid(ModelTier::L2, &policy.l2));
+                    let d = Decision::fail_closed(
                         fenced_external.to_string(),
-                        vec![format!("L1 failed; L2 invalid: {e:#}")],
-                    )
+                        vec![format!("{l1_outcome}; L2 invalid: {e:#}")],
+                    );
+                    (d, vec![])
                 }
             },
             Err(e) => {
                 warn!("sentry: L2 call failed: {e:#}");
-                Decision::fail_closed(
+                let d = Decision::fail_closed(
                     fenced_external.to_string(),
-                    vec![format!("L1 failed; L2 failed: {e:#}")],
-                )
+                    vec![format!("{l1_outcome}; L2 failed: {e:#}")],
+                );
+                (d, vec![])
+            }
+        };
+        let second_opinion = low_confidence.map(|(c, l1_action)| {
+            decision.reasons.push(c.note("escalated to L2"));
+            SecondOpinion {
+                l1_confidence: c,
+                overturned: decision.action != l1_action,
+                l1_action,
+            }
+        });
+        SentryVerdict {
+            decision,
+            tier: ModelTier::L2,
+            repairs,
+            attempts,
+            model_version,
+            second_opinion,
+            calls,
+            budgets,
+        }
+    }
+
+    /// Ask `tier`'s model about `content` rendered into a prompt that fits its context window.
+    /// When the provider still reports the context exceeded, the call is retried once with
+    /// half the content tokens that were sent; any other failure is returned as is. Also returns the prompt
+    /// and budget of the final call.
+    #[allow(clippy::too_many_arguments)]
+    async fn generate_budgeted(
+        &self,
+        tier: ModelTier,
+        model: &model_policy::ModelRef,
+        render: &(dyn Fn(&str) -> String + Sync),
+        content: &str,
+        headers: &HeaderMap,
+        calls: &mut Vec<ModelCall>,
+        budgets: &mut Vec<PromptBudget>,
+    ) -> (Result<Generation>, String, PromptBudget) {
+        let client = match tier {
+            ModelTier::L1 => &self.l1,
+            ModelTier::L2 => &self.l2,
+        };
+        let mut max_content_tokens = None;
+        loop {
+            let (prompt, budget) =
+                token_budget::fit_prompt(model, content, max_content_tokens, render);
+            if budget.trimmed {
+                info!(
+                    estimated = budget.estimated_content_tokens,
+                    allowed = budget.max_content_tokens,
+                    "sentry: {tier:?} content trimmed to the token budget"
+                );
+            }
+            let started = Instant::now();
+            let out = client
+                .generate_reporting(&model.model, &prompt, headers)
+                .await;
+            calls.push(ModelCall::timed(tier, model, started, &out, &budget));
+            budgets.push(budget.clone());
+            match &out {
+                Err(e) if !budget.context_retry && e.is::<ContextExceeded>() => {
+                    warn!("sentry: {tier:?} {e:#}; retrying with half the content budget");
+                    let sent = budget
+                        .max_content_tokens
+                        .min(budget.estimated_content_tokens);
+                    max_content_tokens = Some(sent / 2);
+                }
+                _ => return (out, prompt, budget),
+            }
+        }
+    }
+
+    /// Confidence in the L1 verdict `first`: its agreement with a second sample when
+    /// `l1.consistency_check` is on, else the provider's own signal (`native`).
+    ///
+    /// A second sample that does not parse counts as full disagreement; one that could not be
+    /// fetched leaves the provider's signal in place.
+    #[allow(clippy::too_many_arguments)]
+    async fn l1_confidence(
+        &self,
+        policy: &model_policy::PolicyConfig,
+        (prompt, budget): (&str, &PromptBudget),
+        headers: &HeaderMap,
+        first: &Decision,
+        native: Option<f64>,
+        attempts: &mut Vec<ParseAttempt>,
+        calls: &mut Vec<ModelCall>,
+    ) -> Option<Confidence> {
+        if policy.l1.consistency_check {
+            let started = Instant::now();
+            let sample = self
+                .l1
+                .generate_sample(&policy.l1.model, prompt, headers)
+                .await;
+            calls.push(ModelCall {
+                consistency_check: true,
+                ..ModelCall::timed(ModelTier::L1, &policy.l1, started, &sample, budget)
+            });
+            match sample {
+                Ok(out) => {
+                    let value = match parse_decision(&out.text, effective_verdict_parsing(policy)) {
+                        Ok(p) => {
+                            attempts.push(
+                                ParseAttempt::ok(ModelTier::L1, &policy.l1, &p).consistency_check(),
+                            );
+                            agreement(first, &p.decision)
+                        }
+                        Err(e) => {
+                            warn!("sentry: L1 consistency sample invalid: {e:#}");
+                            attempts.push(
+                                ParseAttempt::invalid(ModelTier::L1, &policy.l1)
+                                    .consistency_check(),
+                            );
+                            0.0
+                        }
+                    };
+                    return Some(Confidence::new(value, ConfidenceMethod::Consistency));
+                }
+                Err(e) => warn!("sentry: L1 consistency sample failed: {e:#}"),
+            }
+        }
+        native.map(|v| Confidence::new(v, ConfidenceMethod::Provider))
+    }
+}
+
+/// Apply `on_low_confidence` to a low-confidence L1 verdict; `true` means ask L2 instead.
+fn apply_low_confidence(
+    decision: &mut Decision,
+    confidence: Confidence,
+    handling: LowConfidenceHandling,
+) -> bool {
+    match handling {
+        LowConfidenceHandling::Accept => {
+            decision.reasons.push(confidence.note("accepted"));
+            false
+        }
+        LowConfidenceHandling::NeedsReview => {
+            if decision.action != Action::Block {
+                decision.action = Action::NeedsReview;
             }
+            decision.tools_allowed = false;
+            decision.reasons.push(confidence.note("needs review"));
+            false
         }
+        LowConfidenceHandling::EscalateL2 => true,
     }
 }
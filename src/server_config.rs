@@ -103,6 +103,41 @@ pub fn allow_insecure_loopback(cfg: Option<&config::Config>) -> bool {
         .unwrap_or(true)
 }
 
+/// `security.allow_core_dumps`; off unless set.
+pub fn allow_core_dumps(cfg: Option<&config::Config>) -> bool {
+    cfg.and_then(|c| c.security.as_ref())
+        .and_then(|s| s.allow_core_dumps)
+        .unwrap_or(false)
+}
+
+/// `server.read_only`; off unless set.
+pub fn read_only(cfg: Option<&config::Config>) -> bool {
+    cfg.and_then(|c| c.server.as_ref())
+        .and_then(|s| s.read_only)
+        .unwrap_or(false)
+}
+
+/// `security.duplicate_headers` and `security.policy_ranking`.
+pub fn header_rules(cfg: Option<&config::Config>) -> crate::acip_headers::HeaderRules {
+    let security = cfg.and_then(|c| c.security.as_ref());
+    crate::acip_headers::HeaderRules {
+        duplicates: security
+            .and_then(|s| s.duplicate_headers)
+            .unwrap_or_default(),
+        policy_ranking: security
+            .map(|s| s.policy_ranking.clone())
+            .unwrap_or_default(),
+        deprecations: crate::deprecations::Registry::builtin(),
+    }
+}
+
+/// Named, scoped tokens from `[[security.tokens]]`.
+pub fn named_tokens(cfg: Option<&config::Config>) -> Vec<config::TokenConfig> {
+    cfg.and_then(|c| c.security.as_ref())
+        .map(|s| s.tokens.clone())
+        .unwrap_or_default()
+}
+
 pub fn require_token_setting(cfg: Option<&config::Config>) -> bool {
     cfg.and_then(|c| c.security.as_ref())
         .and_then(|s| s.require_token)
@@ -0,0 +1,811 @@
+//! SIEM export: completed ingest decisions as OCSF or ECS-style events.
+//!
+//! With a `[siem]` section, every decision `ingest_source` answers with (sync, async job or
+//! completed upload) is rendered into the configured schema and queued on an in-memory spool. A
+//! background task ships the spool in batches to a local file (one JSON event per line) or an
+//! HTTPS endpoint (a JSON array per batch) every `flush_interval_secs`, or as soon as
+//! `batch_size` events are waiting. A failed batch stays at the head of the spool and is retried
+//! with exponential backoff; ingest never waits on the export. The spool is bounded and drops its
+//! oldest events when full.
+//!
+//! Field mappings are data: `siem_mappings.toml` is built in and an override file (`mappings`)
+//! is merged over it key by key, so a SOC can adjust a field without a rebuild. Source
+//! identifiers (`source_id`, `url`, `title`) are hashed with the deployment salt unless
+//! `hash_observables = false`; `event_id` and `content_sha256` always are (see
+//! [`crate::hashing`]). The rendered event goes through output redaction before it is spooled.
+//! An HTTPS destination must pass the [`crate::egress`] checks.
+
+use crate::config::SiemConfig;
+use crate::egress::{self, EgressPolicy};
+use crate::hashing::IdHasher;
+use crate::redact::{self, Redaction};
+use crate::reputation::{Clock, SystemClock};
+use crate::verdicts::Provenance;
+use anyhow::{anyhow, bail, Context};
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Notify;
+
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+pub const DEFAULT_FLUSH_INTERVAL_SECS: u64 = 10;
+pub const DEFAULT_SPOOL_CAPACITY: usize = 10_000;
+/// Longest wait between retries of a failed batch.
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(300);
+
+/// The built-in field mappings.
+pub const DEFAULT_MAPPINGS: &str = include_str!("siem_mappings.toml");
+
+/// Decision attributes a mapping can place (the values of a `fields` table).
+pub const ATTRIBUTES: &[&str] = &[
+    "time_ms",
+    "time_unix",
+    "timestamp",
+    "event_id",
+    "actor",
+    "policy",
+    "source_id",
+    "source_type",
+    "url",
+    "title",
+    "content_sha256",
+    "content_length",
+    "action",
+    "risk_level",
+    "tools_allowed",
+    "threat_score",
+    "attack_types",
+    "reasons",
+    "reasons_text",
+    "patterns",
+    "product_version",
+    "pattern_pack",
+    "l1_model",
+    "l2_model",
+    "model_version",
+];
+
+const RISK_LEVELS: [&str; 3] = ["low", "medium", "high"];
+const ACTIONS: [&str; 4] = ["allow", "sanitize", "block", "needs_review"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiemFormat {
+    /// OCSF Detection Finding (class 2004).
+    Ocsf,
+    /// Flat ECS-style alert document.
+    Ecs,
+}
+
+impl SiemFormat {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ocsf => "ocsf",
+            Self::Ecs => "ecs",
+        }
+    }
+}
+
+/// Output fields: dotted path -> value.
+pub type Fields = BTreeMap<String, Value>;
+
+/// How one format lays out a decision; see `siem_mappings.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FormatMapping {
+    #[serde(default)]
+    pub constants: Fields,
+    /// Dotted path -> decision attribute (one of [`ATTRIBUTES`]).
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// Risk level -> fields.
+    #[serde(default)]
+    pub severity: BTreeMap<String, Fields>,
+    /// Action -> fields.
+    #[serde(default)]
+    pub disposition: BTreeMap<String, Fields>,
+    #[serde(default)]
+    pub observables: Option<ObservableMapping>,
+    #[serde(default)]
+    pub findings: Option<FindingMapping>,
+}
+
+/// An array of source observables, one entry per attribute present on the decision.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObservableMapping {
+    pub path: String,
+    /// Where the attribute value goes inside each entry.
+    #[serde(default = "default_value_field")]
+    pub value_field: String,
+    /// Attribute -> fields of its entry.
+    #[serde(default)]
+    pub attributes: BTreeMap<String, Fields>,
+}
+
+fn default_value_field() -> String {
+    "value".to_string()
+}
+
+/// One entry per detected pattern id.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FindingMapping {
+    pub path: String,
+    /// Put each field's values in an array under `path` instead of an array of objects.
+    #[serde(default)]
+    pub flatten: bool,
+    #[serde(default)]
+    pub default: Fields,
+    /// Pattern id, or its family (the part before the first `:`) -> fields over `default`.
+    #[serde(default)]
+    pub patterns: BTreeMap<String, Fields>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Mappings {
+    pub ocsf: FormatMapping,
+    pub ecs: FormatMapping,
+}
+
+impl Mappings {
+    /// The built-in mappings with `overrides` (TOML) merged over them key by key.
+    pub fn load(overrides: Option<&str>) -> anyhow::Result<Self> {
+        let mut table: toml::Table = DEFAULT_MAPPINGS.parse().context("built-in SIEM mappings")?;
+        if let Some(raw) = overrides {
+            let over: toml::Table = raw.parse().context("SIEM mapping overrides")?;
+            merge(&mut table, over);
+        }
+        let mappings: Self = toml::Value::Table(table).try_into()?;
+        for format in [SiemFormat::Ocsf, SiemFormat::Ecs] {
+            mappings
+                .format(format)
+                .validate()
+                .map_err(|e| anyhow!("siem mappings [{}]: {e}", format.as_str()))?;
+        }
+        Ok(mappings)
+    }
+
+    pub fn format(&self, format: SiemFormat) -> &FormatMapping {
+        match format {
+            SiemFormat::Ocsf => &self.ocsf,
+            SiemFormat::Ecs => &self.ecs,
+        }
+    }
+}
+
+/// Merge `over` into `base`: tables recursively, anything else replaced.
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(b)), toml::Value::Table(o)) => merge(b, o),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+impl FormatMapping {
+    fn validate(&self) -> anyhow::Result<()> {
+        let observables = self.observables.iter().flat_map(|o| o.attributes.keys());
+        for attr in self.fields.values().chain(observables) {
+            if !ATTRIBUTES.contains(&attr.as_str()) {
+                bail!("unknown attribute {attr:?}");
+            }
+        }
+        if let Some(level) = self
+            .severity
+            .keys()
+            .find(|k| !RISK_LEVELS.contains(&k.as_str()))
+        {
+            bail!("severity: unknown risk level {level:?}");
+        }
+        if let Some(action) = self
+            .disposition
+            .keys()
+            .find(|k| !ACTIONS.contains(&k.as_str()))
+        {
+            bail!("disposition: unknown action {action:?}");
+        }
+        Ok(())
+    }
+}
+
+/// Request details an ingest response body does not carry.
+#[derive(Debug, Clone)]
+pub struct Subject {
+    pub actor: String,
+    pub policy: String,
+    pub source_id: String,
+    pub source_type: String,
+    pub url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// What the export knows about one completed decision.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DecisionEvent {
+    pub time_unix: u64,
+    pub product_version: String,
+    pub actor: String,
+    pub policy: String,
+    pub source_id: String,
+    pub source_type: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub content_sha256: String,
+    pub content_length: u64,
+    pub action: String,
+    pub risk_level: String,
+    pub tools_allowed: bool,
+    pub threat_score: u64,
+    #[serde(default)]
+    pub attack_types: Vec<String>,
+    #[serde(default)]
+    pub reasons: Vec<String>,
+    /// Scanner indicators, then model-reported patterns, without repeats.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+fn strings(v: &Value) -> Vec<String> {
+    v.as_array()
+        .map(|a| {
+            a.iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl DecisionEvent {
+    /// From an `ingest_source` response body; `None` when it holds no decision.
+    pub fn from_response(subject: Subject, time_unix: u64, body: &Value) -> Option<Self> {
+        let mut patterns = strings(&body["threat"]["indicators"]);
+        for p in strings(&body["detected_patterns"]) {
+            if !patterns.contains(&p) {
+                patterns.push(p);
+            }
+        }
+        Some(Self {
+            time_unix,
+            product_version: env!("CARGO_PKG_VERSION").to_string(),
+            actor: subject.actor,
+            policy: subject.policy,
+            source_id: subject.source_id,
+            source_type: subject.source_type,
+            url: subject.url,
+            title: subject.title,
+            content_sha256: body["digest"]["sha256"].as_str()?.to_string(),
+            content_length: body["digest"]["length"].as_u64().unwrap_or(0),
+            action: body["action"].as_str()?.to_string(),
+            risk_level: body["risk_level"].as_str()?.to_string(),
+            tools_allowed: body["tools_allowed"].as_bool()?,
+            threat_score: body["threat"]["threat_score"].as_u64().unwrap_or(0),
+            attack_types: strings(&body["threat"]["attack_types"]),
+            reasons: strings(&body["reasons"]),
+            patterns,
+            provenance: serde_json::from_value(body["provenance"].clone()).ok(),
+        })
+    }
+
+    /// Value of `attr`; `None` when the decision has none (the field is left out).
+    pub fn attribute(
+        &self,
+        attr: &str,
+        hasher: &IdHasher,
+        hash_observables: bool,
+    ) -> Option<Value> {
+        let observable = |v: &str| {
+            Value::from(if hash_observables {
+                hasher.export_id(v)
+            } else {
+                v.to_string()
+            })
+        };
+        let list = |v: &[String]| (!v.is_empty()).then(|| json!(v));
+        let provenance = self.provenance.as_ref();
+        Some(match attr {
+            "time_ms" => json!(self.time_unix * 1000),
+            "time_unix" => json!(self.time_unix),
+            "timestamp" => {
+                let t = time::OffsetDateTime::from_unix_timestamp(self.time_unix as i64).ok()?;
+                json!(t
+                    .format(&time::format_description::well_known::Rfc3339)
+                    .ok()?)
+            }
+            "event_id" => {
+                let seed = format!(
+                    "{}|{}|{}|{}",
+                    self.time_unix, self.policy, self.source_id, self.content_sha256
+                );
+                json!(hasher.export_id(&seed)[..32])
+            }
+            "actor" => json!(self.actor),
+            "policy" => json!(self.policy),
+            "source_id" => observable(&self.source_id),
+            "source_type" => json!(self.source_type),
+            "url" => observable(self.url.as_deref()?),
+            "title" => observable(self.title.as_deref()?),
+            "content_sha256" => json!(hasher.export_id(&self.content_sha256)),
+            "content_length" => json!(self.content_length),
+            "action" => json!(self.action),
+            "risk_level" => json!(self.risk_level),
+            "tools_allowed" => json!(self.tools_allowed),
+            "threat_score" => json!(self.threat_score),
+            "attack_types" => list(&self.attack_types)?,
+            "reasons" => list(&self.reasons)?,
+            "reasons_text" => json!((!self.reasons.is_empty()).then(|| self.reasons.join("; "))?),
+            "patterns" => list(&self.patterns)?,
+            "product_version" => json!(self.product_version),
+            "pattern_pack" => json!(provenance?.pattern_pack),
+            "l1_model" => json!(provenance?.l1_model),
+            "l2_model" => json!(provenance?.l2_model),
+            "model_version" => json!(provenance?.model_version.as_ref()?),
+            _ => return None,
+        })
+    }
+
+    /// The event as `mapping` lays it out.
+    pub fn render(
+        &self,
+        mapping: &FormatMapping,
+        hasher: &IdHasher,
+        hash_observables: bool,
+    ) -> Value {
+        let mut out = Value::Object(Map::new());
+        set_fields(&mut out, &mapping.constants);
+        for (path, attr) in &mapping.fields {
+            if let Some(v) = self.attribute(attr, hasher, hash_observables) {
+                set_path(&mut out, path, v);
+            }
+        }
+        if let Some(fields) = mapping.severity.get(&self.risk_level) {
+            set_fields(&mut out, fields);
+        }
+        if let Some(fields) = mapping.disposition.get(&self.action) {
+            set_fields(&mut out, fields);
+        }
+
+        if let Some(obs) = &mapping.observables {
+            let entries: Vec<Value> = obs
+                .attributes
+                .iter()
+                .filter_map(|(attr, fields)| {
+                    let value = self.attribute(attr, hasher, hash_observables)?;
+                    let mut entry = Value::Object(Map::new());
+                    set_fields(&mut entry, fields);
+                    set_path(&mut entry, &obs.value_field, value);
+                    Some(entry)
+                })
+                .collect();
+            if !entries.is_empty() {
+                set_path(&mut out, &obs.path, Value::Array(entries));
+            }
+        }
+
+        if let Some(findings) = &mapping.findings {
+            if !self.patterns.is_empty() {
+                render_findings(&mut out, findings, &self.patterns);
+            }
+        }
+        out
+    }
+}
+
+fn render_findings(out: &mut Value, mapping: &FindingMapping, patterns: &[String]) {
+    let mut entries = vec![];
+    for pattern in patterns {
+        let family = pattern.split(':').next().unwrap_or_default();
+        let mut fields = mapping.default.clone();
+        if let Some(specific) = mapping
+            .patterns
+            .get(pattern)
+            .or_else(|| mapping.patterns.get(family))
+        {
+            fields.extend(specific.clone());
+        }
+        for v in fields.values_mut() {
+            if let Value::String(s) = v {
+                *s = s.replace("{pattern}", pattern);
+            }
+        }
+        entries.push(fields);
+    }
+
+    if mapping.flatten {
+        let mut columns: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+        for fields in entries {
+            for (k, v) in fields {
+                columns.entry(k).or_default().push(v);
+            }
+        }
+        for (k, values) in columns {
+            set_path(out, &format!("{}.{k}", mapping.path), Value::Array(values));
+        }
+    } else {
+        let entries = entries
+            .iter()
+            .map(|fields| {
+                let mut entry = Value::Object(Map::new());
+                set_fields(&mut entry, fields);
+                entry
+            })
+            .collect();
+        set_path(out, &mapping.path, Value::Array(entries));
+    }
+}
+
+fn set_fields(out: &mut Value, fields: &Fields) {
+    for (path, v) in fields {
+        set_path(out, path, v.clone());
+    }
+}
+
+/// Set dotted `path` in `out`, creating (or replacing non-object) parents as needed.
+fn set_path(out: &mut Value, path: &str, v: Value) {
+    let mut cur = out;
+    let mut keys = path.split('.').peekable();
+    while let Some(key) = keys.next() {
+        if !cur.is_object() {
+            *cur = Value::Object(Map::new());
+        }
+        let Value::Object(map) = cur else {
+            unreachable!()
+        };
+        if keys.peek().is_none() {
+            map.insert(key.to_string(), v);
+            return;
+        }
+        cur = map
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Destination {
+    /// JSON lines appended to a local file.
+    File(PathBuf),
+    /// A JSON array POSTed per batch.
+    Http(url::Url),
+}
+
+impl Destination {
+    /// An [`EgressPolicy`] URL (`http://` allowed to a loopback host), or a local path.
+    fn parse(raw: &str, allowed_hosts: &HashSet<String>) -> anyhow::Result<Self> {
+        if !raw.contains("://") {
+            return Ok(Self::File(PathBuf::from(raw)));
+        }
+        EgressPolicy::new(allowed_hosts.clone())
+            .with_loopback()
+            .check(raw)
+            .map(Self::Http)
+            .map_err(|reason| anyhow!("{reason}"))
+    }
+
+    /// For `/status`: URLs without credentials or query.
+    fn display(&self) -> String {
+        match self {
+            Self::File(p) => p.display().to_string(),
+            Self::Http(u) => {
+                let mut u = u.clone();
+                let _ = u.set_username("");
+                let _ = u.set_password(None);
+                u.set_query(None);
+                u.to_string()
+            }
+        }
+    }
+}
+
+/// A validated `[siem]` section.
+#[derive(Debug, Clone)]
+pub struct SiemSettings {
+    pub format: SiemFormat,
+    destination: Destination,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub spool_capacity: usize,
+    pub hash_observables: bool,
+    pub mapping: FormatMapping,
+}
+
+impl SiemSettings {
+    pub fn from_config(cfg: &SiemConfig, allowed_hosts: &HashSet<String>) -> anyhow::Result<Self> {
+        let overrides = cfg
+            .mappings
+            .as_deref()
+            .map(|p| std::fs::read_to_string(p).with_context(|| format!("siem mappings {p:?}")))
+            .transpose()?;
+        let mappings = Mappings::load(overrides.as_deref())?;
+        let flush_secs = cfg
+            .flush_interval_secs
+            .unwrap_or(DEFAULT_FLUSH_INTERVAL_SECS);
+        if flush_secs == 0 {
+            bail!("siem: flush_interval_secs must be > 0");
+        }
+        Ok(Self {
+            format: cfg.format,
+            destination: Destination::parse(cfg.destination.trim(), allowed_hosts)
+                .map_err(|e| anyhow!("siem destination: {e}"))?,
+            batch_size: cfg.batch_size.unwrap_or(DEFAULT_BATCH_SIZE).max(1),
+            flush_interval: Duration::from_secs(flush_secs),
+            spool_capacity: cfg.spool_capacity.unwrap_or(DEFAULT_SPOOL_CAPACITY).max(1),
+            hash_observables: cfg.hash_observables.unwrap_or(true),
+            mapping: mappings.format(cfg.format).clone(),
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Counters {
+    exported: u64,
+    /// Dropped from a full spool.
+    dropped: u64,
+    failed_batches: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_export_unix: Option<u64>,
+}
+
+#[derive(Default)]
+struct Spool {
+    /// (sequence number, rendered event)
+    events: VecDeque<(u64, Value)>,
+    next_seq: u64,
+}
+
+/// The export pipeline; disabled (every call a no-op) without a `[siem]` section.
+pub struct SiemExport {
+    settings: Option<SiemSettings>,
+    clock: Arc<dyn Clock>,
+    spool: Mutex<Spool>,
+    counters: Mutex<Counters>,
+    ready: Notify,
+}
+
+impl Default for SiemExport {
+    fn default() -> Self {
+        Self::new(None, Arc::new(SystemClock))
+    }
+}
+
+impl SiemExport {
+    pub fn new(settings: Option<SiemSettings>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            spool: Mutex::new(Spool::default()),
+            counters: Mutex::new(Counters::default()),
+            ready: Notify::new(),
+        }
+    }
+
+    pub fn from_config(cfg: Option<&SiemConfig>) -> anyhow::Result<Self> {
+        let settings = cfg
+            .map(|c| SiemSettings::from_config(c, &egress::allowed_hosts_from_env()))
+            .transpose()?;
+        Ok(Self::new(settings, Arc::new(SystemClock)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.is_some()
+    }
+
+    pub fn settings(&self) -> Option<&SiemSettings> {
+        self.settings.as_ref()
+    }
+
+    /// Render `event`, redact it and queue it for the next batch.
+    pub fn offer(&self, redaction: &Redaction, hasher: &IdHasher, event: &DecisionEvent) {
+        let Some(settings) = &self.settings else {
+            return;
+        };
+        let mut rendered = event.render(&settings.mapping, hasher, settings.hash_observables);
+        redaction.redact_json(&mut rendered);
+
+        let mut spool = self.spool.lock().unwrap();
+        let seq = spool.next_seq;
+        spool.next_seq += 1;
+        spool.events.push_back((seq, rendered));
+        let mut dropped = 0;
+        while spool.events.len() > settings.spool_capacity {
+            spool.events.pop_front();
+            dropped += 1;
+        }
+        let waiting = spool.events.len();
+        drop(spool);
+        if dropped > 0 {
+            self.counters.lock().unwrap().dropped += dropped;
+        }
+        if waiting >= settings.batch_size {
+            self.ready.notify_one();
+        }
+    }
+
+    /// Queue the decision in a successful `ingest_source` response and hand the response back
+    /// unchanged.
+    pub async fn export_response(
+        &self,
+        redaction: &Redaction,
+        hasher: &IdHasher,
+        subject: Subject,
+        resp: Response,
+    ) -> Response {
+        if !self.is_enabled() || !resp.status().is_success() {
+            return resp;
+        }
+        let (parts, body) = resp.into_parts();
+        let bytes = match axum::body::to_bytes(body, redact::MAX_REDACT_BODY_BYTES).await {
+            Ok(b) => b,
+            Err(_) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "response too large to export",
+                )
+                    .into_response()
+            }
+        };
+        let event = serde_json::from_slice::<Value>(&bytes)
+            .ok()
+            .and_then(|body| DecisionEvent::from_response(subject, self.clock.now_unix(), &body));
+        if let Some(event) = event {
+            self.offer(redaction, hasher, &event);
+        }
+        Response::from_parts(parts, Body::from(bytes))
+    }
+
+    /// Events waiting to be shipped.
+    pub fn spooled(&self) -> usize {
+        self.spool.lock().unwrap().events.len()
+    }
+
+    /// Ship one batch from the head of the spool. Returns how many events were shipped; a
+    /// failed batch stays queued.
+    pub async fn flush(&self, http: &reqwest::Client) -> Result<usize, String> {
+        let Some(settings) = &self.settings else {
+            return Ok(0);
+        };
+        let batch: Vec<(u64, Value)> = {
+            let spool = self.spool.lock().unwrap();
+            spool
+                .events
+                .iter()
+                .take(settings.batch_size)
+                .cloned()
+                .collect()
+        };
+        let Some(&(last_seq, _)) = batch.last() else {
+            return Ok(0);
+        };
+        let events: Vec<Value> = batch.into_iter().map(|(_, v)| v).collect();
+
+        let result = ship(&settings.destination, http, &events).await;
+        let mut counters = self.counters.lock().unwrap();
+        match result {
+            Ok(()) => {
+                // Events dropped from a full spool meanwhile are simply gone.
+                self.spool
+                    .lock()
+                    .unwrap()
+                    .events
+                    .retain(|(seq, _)| *seq > last_seq);
+                counters.exported += events.len() as u64;
+                counters.last_export_unix = Some(self.clock.now_unix());
+                counters.last_error = None;
+                Ok(events.len())
+            }
+            Err(e) => {
+                counters.failed_batches += 1;
+                counters.last_error = Some(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    /// Ship batches until the spool is empty or one fails.
+    pub async fn flush_all(&self, http: &reqwest::Client) -> Result<(), String> {
+        while self.flush(http).await? > 0 {}
+        Ok(())
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        let Some(settings) = &self.settings else {
+            return json!({"enabled": false});
+        };
+        let mut v = json!({
+            "enabled": true,
+            "format": settings.format,
+            "destination": settings.destination.display(),
+            "batch_size": settings.batch_size,
+            "flush_interval_secs": settings.flush_interval.as_secs(),
+            "spool_capacity": settings.spool_capacity,
+            "spooled": self.spooled(),
+        });
+        if let (Value::Object(out), Ok(Value::Object(counters))) = (
+            &mut v,
+            serde_json::to_value(&*self.counters.lock().unwrap()),
+        ) {
+            out.extend(counters);
+        }
+        v
+    }
+}
+
+async fn ship(
+    destination: &Destination,
+    http: &reqwest::Client,
+    events: &[Value],
+) -> Result<(), String> {
+    match destination {
+        Destination::File(path) => {
+            let mut lines = Vec::new();
+            for event in events {
+                serde_json::to_writer(&mut lines, event).map_err(|e| e.to_string())?;
+                lines.push(b'\n');
+            }
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || {
+                let mut f = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)?;
+                f.write_all(&lines)?;
+                f.flush()
+            })
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+        }
+        Destination::Http(url) => egress::send(http.post(url.clone()).json(events))
+            .await
+            .map(|_| ()),
+    }
+}
+
+/// Ship the spool in the background: every flush interval, or once a batch is waiting. After a
+/// failure the wait doubles (up to [`MAX_RETRY_BACKOFF`]) until a batch goes through.
+pub fn start(export: Arc<SiemExport>, http: reqwest::Client) {
+    let Some(interval) = export.settings.as_ref().map(|s| s.flush_interval) else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut backoff: Option<Duration> = None;
+        loop {
+            match backoff {
+                Some(wait) => tokio::time::sleep(wait).await,
+                None => {
+                    let _ = tokio::time::timeout(interval, export.ready.notified()).await;
+                }
+            }
+            backoff = match export.flush_all(&http).await {
+                Ok(()) => None,
+                Err(e) => {
+                    tracing::warn!("SIEM export failed, will retry: {e}");
+                    Some(backoff.map_or(interval, |b| (b * 2).min(MAX_RETRY_BACKOFF)))
+                }
+            };
+        }
+    });
+}
@@ -0,0 +1,107 @@
+//! Debug-build detector for futures that block a runtime worker.
+//!
+//! [`watch`] wraps a future and times each of its polls. A poll longer than the threshold
+//! (`ACIP_SLOW_POLL_MS`, default 50) means the future did blocking work on a runtime worker:
+//! it is counted, the worst one is kept, and a warning names the watched future. The ingest
+//! pipeline is watched; the stress test in `tests/blocking_pool_tests.rs` fails on any hit.
+//!
+//! Release builds compile [`watch`] to the future itself and [`snapshot`] to zeros.
+
+use serde::Serialize;
+
+pub const DEFAULT_THRESHOLD_MS: u64 = 50;
+
+/// Polls over the threshold since start (or the last [`reset`]).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SlowPolls {
+    pub count: u64,
+    pub worst_ms: u64,
+    pub worst_label: Option<&'static str>,
+}
+
+#[cfg(debug_assertions)]
+mod imp {
+    use super::{SlowPolls, DEFAULT_THRESHOLD_MS};
+    use std::{
+        future::Future,
+        pin::Pin,
+        sync::{Mutex, OnceLock},
+        task::{Context, Poll},
+        time::{Duration, Instant},
+    };
+
+    static SEEN: Mutex<SlowPolls> = Mutex::new(SlowPolls {
+        count: 0,
+        worst_ms: 0,
+        worst_label: None,
+    });
+
+    fn threshold() -> Duration {
+        static THRESHOLD: OnceLock<Duration> = OnceLock::new();
+        *THRESHOLD.get_or_init(|| {
+            let ms = std::env::var("ACIP_SLOW_POLL_MS")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_THRESHOLD_MS);
+            Duration::from_millis(ms)
+        })
+    }
+
+    pub struct Watched<F> {
+        label: &'static str,
+        inner: Pin<Box<F>>,
+    }
+
+    impl<F: Future> Future for Watched<F> {
+        type Output = F::Output;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+            let start = Instant::now();
+            let out = self.inner.as_mut().poll(cx);
+            let took = start.elapsed();
+            if took > threshold() {
+                let ms = took.as_millis() as u64;
+                tracing::warn!(future = self.label, poll_ms = ms, "slow poll");
+                let mut seen = SEEN.lock().unwrap();
+                seen.count += 1;
+                if ms >= seen.worst_ms {
+                    seen.worst_ms = ms;
+                    seen.worst_label = Some(self.label);
+                }
+            }
+            out
+        }
+    }
+
+    pub fn watch<F: Future>(label: &'static str, fut: F) -> Watched<F> {
+        Watched {
+            label,
+            inner: Box::pin(fut),
+        }
+    }
+
+    pub fn snapshot() -> SlowPolls {
+        SEEN.lock().unwrap().clone()
+    }
+
+    pub fn reset() {
+        *SEEN.lock().unwrap() = SlowPolls::default();
+    }
+}
+
+#[cfg(not(debug_assertions))]
+mod imp {
+    use super::SlowPolls;
+
+    pub fn watch<F>(_label: &'static str, fut: F) -> F {
+        fut
+    }
+
+    pub fn snapshot() -> SlowPolls {
+        SlowPolls::default()
+    }
+
+    pub fn reset() {}
+}
+
+pub use imp::{reset, snapshot, watch};
@@ -0,0 +1,639 @@
+//! Per-request pipeline timing for slow-request forensics.
+//!
+//! Every ingest run is timed stage by stage ([`Timing`]). A run is kept as a
+//! [`SlowRequestRecord`] when it took at least `threshold`, when it falls in the `sample_rate`
+//! share of requests, or when the caller sent `X-ACIP-Force-Timing: true` (which needs the
+//! `support` scope). Records hold timings, sizes and the pool each stage ran on, never content.
+//!
+//! Records are keyed by the request id also reported as `origin.request_id` in the ingest
+//! response, so a record can be matched to the response and to log lines. They are kept for
+//! `retention` and at most `max_records` of them; with a file backend (`file:<path>` in
+//! `ACIP_SLOW_REQUEST_STORE`) they survive restarts.
+//!
+//! Sampling is decided from a hash of the request id, so it needs no random source and the
+//! same request is always either sampled or not.
+
+use crate::acip_headers;
+use crate::introspection;
+use crate::reputation::{self, Clock};
+use crate::sentry::ModelCall;
+use crate::state::AppState;
+use crate::storage::{self, StorageError, StoreFile};
+use crate::telemetry::RequestTrace;
+use crate::token_auth::{Actor, Scope};
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::{from_fn, Next},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Request header asking for this request's timing to be recorded whatever its duration.
+pub const FORCE_HEADER: &str = "x-acip-force-timing";
+
+pub const DEFAULT_THRESHOLD_MS: u64 = 5_000;
+pub const DEFAULT_RETENTION_SECS: u64 = 7 * 86_400;
+pub const DEFAULT_MAX_RECORDS: usize = 1_000;
+
+/// Records answered by one `GET /v1/acip/slow_requests` when `limit` is not given.
+pub const DEFAULT_LIST_LIMIT: usize = 100;
+pub const MAX_LIST_LIMIT: usize = 1_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowRequestSettings {
+    /// Runs at least this long are recorded.
+    pub threshold: Duration,
+    /// Share of all other runs recorded anyway, `0.0..=1.0`.
+    pub sample_rate: f64,
+    pub retention: Duration,
+    pub max_records: usize,
+}
+
+impl Default for SlowRequestSettings {
+    fn default() -> Self {
+        Self {
+            threshold: Duration::from_millis(DEFAULT_THRESHOLD_MS),
+            sample_rate: 0.0,
+            retention: Duration::from_secs(DEFAULT_RETENTION_SECS),
+            max_records: DEFAULT_MAX_RECORDS,
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl SlowRequestSettings {
+    /// Defaults, then `ACIP_SLOW_REQUEST_MS`, `ACIP_SLOW_REQUEST_SAMPLE_RATE`,
+    /// `ACIP_SLOW_REQUEST_RETENTION_SECS` and `ACIP_SLOW_REQUEST_MAX_RECORDS` overrides.
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_SLOW_REQUEST_MS") {
+            s.threshold = Duration::from_millis(v);
+        }
+        if let Some(v) = std::env::var("ACIP_SLOW_REQUEST_SAMPLE_RATE")
+            .ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+        {
+            s.sample_rate = v.clamp(0.0, 1.0);
+        }
+        if let Some(v) = env_u64("ACIP_SLOW_REQUEST_RETENTION_SECS") {
+            s.retention = Duration::from_secs(v.max(1));
+        }
+        if let Some(v) = env_u64("ACIP_SLOW_REQUEST_MAX_RECORDS") {
+            s.max_records = v as usize;
+        }
+        s
+    }
+}
+
+/// A timed step of the ingest pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Waiting in the async job queue for a worker.
+    QueueWait,
+    /// Loop protection and hashing the input.
+    Prepare,
+    /// Out-of-process PDF/SVG extraction, OCR retry included.
+    Extract,
+    /// HTML/XML red-flag scans.
+    MarkupScan,
+    /// Markup to text.
+    Normalize,
+    /// Pattern and decoded-payload scanning.
+    DecodeScan,
+    /// Threat feed URL lookups.
+    FeedScan,
+    /// Active probes of linked hosts (see [`crate::probing`]).
+    LinkProbe,
+    /// Reputation update and feed seeding.
+    Reputation,
+    /// Sentry model calls; see [`SlowRequestRecord::model_calls`] for each call.
+    Model,
+    /// Decision stages, stats and verdict history.
+    Decide,
+    /// Building and serializing the response.
+    Serialize,
+}
+
+impl Stage {
+    /// Where the stage's time is spent.
+    pub fn pool(self) -> Pool {
+        match self {
+            Stage::QueueWait => Pool::JobQueue,
+            Stage::Extract => Pool::Blocking,
+            Stage::Model | Stage::LinkProbe => Pool::Upstream,
+            _ => Pool::Runtime,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::QueueWait => "queue_wait",
+            Stage::Prepare => "prepare",
+            Stage::Extract => "extract",
+            Stage::MarkupScan => "markup_scan",
+            Stage::Normalize => "normalize",
+            Stage::DecodeScan => "decode_scan",
+            Stage::FeedScan => "feed_scan",
+            Stage::LinkProbe => "link_probe",
+            Stage::Reputation => "reputation",
+            Stage::Model => "model",
+            Stage::Decide => "decide",
+            Stage::Serialize => "serialize",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pool {
+    /// Queued async job, not yet on a worker.
+    JobQueue,
+    /// The request's own task on the async runtime.
+    Runtime,
+    /// Tokio's blocking pool (the extractor helper runs there).
+    Blocking,
+    /// Waiting on a model provider, or on linked hosts during link probes.
+    Upstream,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub pool: Pool,
+    pub ms: f64,
+}
+
+/// Why a run was recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureReason {
+    Threshold,
+    Sampled,
+    Forced,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlowRequestRecord {
+    pub request_id: String,
+    pub recorded_unix: u64,
+    pub reason: CaptureReason,
+    /// Token name behind the request.
+    pub actor: String,
+    pub policy: String,
+    pub source_type: String,
+    pub http_status: u16,
+    pub total_ms: f64,
+    /// The stage that took longest.
+    pub dominant_stage: Stage,
+    pub stages: Vec<StageTiming>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub model_calls: Vec<ModelCall>,
+    pub input_bytes: usize,
+    /// Characters of text the pipeline scanned (extracted or normalized).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted_chars: Option<usize>,
+}
+
+/// One [`Timing::lap`], for tracing: where in the run it started and how long it took.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lap {
+    pub stage: Stage,
+    /// From the start of the run, queue wait included.
+    pub offset: Duration,
+    pub duration: Duration,
+}
+
+/// Stage timer for one ingest run. Each [`Self::lap`] charges the time since the previous lap
+/// to a stage.
+#[derive(Debug)]
+pub struct Timing {
+    started: Instant,
+    started_wall: SystemTime,
+    last: Instant,
+    /// Time spent before `started`, e.g. in the job queue.
+    before: Duration,
+    stages: Vec<StageTiming>,
+    laps: Vec<Lap>,
+    model_calls: Vec<ModelCall>,
+    /// Set once loop protection assigned the request its origin.
+    pub request_id: Option<String>,
+    pub extracted_chars: Option<usize>,
+    /// The run's distributed trace (see [`crate::telemetry`]).
+    pub trace: RequestTrace,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::start()
+    }
+}
+
+fn ms(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+impl Timing {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            started_wall: SystemTime::now(),
+            last: now,
+            before: Duration::ZERO,
+            stages: Vec::new(),
+            laps: Vec::new(),
+            model_calls: Vec::new(),
+            request_id: None,
+            extracted_chars: None,
+            trace: RequestTrace::default(),
+        }
+    }
+
+    /// A run that first waited `wait` in the job queue.
+    pub fn queued(wait: Duration) -> Self {
+        let mut t = Self::start();
+        t.before = wait;
+        t.stages.push(StageTiming {
+            stage: Stage::QueueWait,
+            pool: Stage::QueueWait.pool(),
+            ms: ms(wait),
+        });
+        t.laps.push(Lap {
+            stage: Stage::QueueWait,
+            offset: Duration::ZERO,
+            duration: wait,
+        });
+        t
+    }
+
+    /// Charge the time since the previous lap to `stage`. Repeated stages add up.
+    pub fn lap(&mut self, stage: Stage) {
+        let now = Instant::now();
+        self.laps.push(Lap {
+            stage,
+            offset: self.before + (self.last - self.started),
+            duration: now - self.last,
+        });
+        let elapsed = ms(now - self.last);
+        self.last = now;
+        match self.stages.iter_mut().find(|s| s.stage == stage) {
+            Some(s) => s.ms += elapsed,
+            None => self.stages.push(StageTiming {
+                stage,
+                pool: stage.pool(),
+                ms: elapsed,
+            }),
+        }
+    }
+
+    pub fn model_calls(&mut self, calls: &[ModelCall]) {
+        self.model_calls.extend_from_slice(calls);
+    }
+
+    pub fn total(&self) -> Duration {
+        self.before + self.last.duration_since(self.started)
+    }
+
+    /// Wall-clock start of the run, queue wait included.
+    pub fn started_at(&self) -> SystemTime {
+        self.started_wall
+            .checked_sub(self.before)
+            .unwrap_or(self.started_wall)
+    }
+
+    /// Every lap in order; a repeated stage has one lap per repetition.
+    pub fn laps(&self) -> &[Lap] {
+        &self.laps
+    }
+
+    pub fn recorded_model_calls(&self) -> &[ModelCall] {
+        &self.model_calls
+    }
+}
+
+/// What the pipeline knows about a run besides its timing.
+pub struct RunInfo<'a> {
+    pub actor: &'a str,
+    pub policy: &'a str,
+    pub source_type: &'a str,
+    pub http_status: StatusCode,
+    pub input_bytes: usize,
+    pub forced: bool,
+}
+
+/// `X-ACIP-Force-Timing` is set to a true value.
+pub fn forced(headers: &axum::http::HeaderMap) -> bool {
+    acip_headers::values(headers, FORCE_HEADER)
+        .iter()
+        .any(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
+}
+
+/// Whether `request_id` falls in the sampled share `rate`.
+pub fn sampled(request_id: &str, rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    let digest = Sha256::digest(request_id.as_bytes());
+    let mut head = [0u8; 8];
+    head.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(head) as f64 / u64::MAX as f64) < rate
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SlowRequestFile {
+    records: Vec<SlowRequestRecord>,
+}
+
+/// Recorded slow requests, oldest first.
+pub struct SlowRequestLog {
+    settings: SlowRequestSettings,
+    clock: Arc<dyn Clock>,
+    file: Option<StoreFile>,
+    records: Mutex<VecDeque<SlowRequestRecord>>,
+    /// `server.read_only`: nothing is recorded.
+    read_only: bool,
+}
+
+impl Default for SlowRequestLog {
+    /// In-memory, default settings, wall clock.
+    fn default() -> Self {
+        Self::in_memory(
+            SlowRequestSettings::default(),
+            Arc::new(reputation::SystemClock),
+        )
+    }
+}
+
+impl SlowRequestLog {
+    pub fn in_memory(settings: SlowRequestSettings, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            file: None,
+            records: Mutex::new(VecDeque::new()),
+            read_only: false,
+        }
+    }
+
+    /// File-backed log. A corrupt file is logged and replaced rather than failing startup.
+    pub fn load_or_create(
+        path: impl AsRef<Path>,
+        settings: SlowRequestSettings,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (file, records) =
+            match StoreFile::open::<SlowRequestFile>(&path, &storage::SLOW_REQUESTS, false) {
+                Ok(opened) => (opened.file, opened.data.unwrap_or_default().records.into()),
+                Err(StorageError::Corrupt { reason, .. }) => {
+                    tracing::warn!(
+                        error = %reason,
+                        path = %path.display(),
+                        "Ignoring unreadable slow request file"
+                    );
+                    (
+                        StoreFile::new(path, &storage::SLOW_REQUESTS),
+                        VecDeque::new(),
+                    )
+                }
+                Err(err) => return Err(err.into()),
+            };
+        Ok(Self {
+            settings,
+            clock,
+            file: Some(file),
+            records: Mutex::new(records),
+            read_only: false,
+        })
+    }
+
+    /// The same log with recording turned off.
+    pub fn into_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn settings(&self) -> &SlowRequestSettings {
+        &self.settings
+    }
+
+    /// The backing file and its format version, for the file backend.
+    pub fn storage(&self) -> Option<storage::StoreInfo> {
+        self.file.as_ref().map(StoreFile::info)
+    }
+
+    /// Why a run of `total` with `request_id` should be recorded, if at all.
+    pub fn should_capture(
+        &self,
+        total: Duration,
+        request_id: &str,
+        forced: bool,
+    ) -> Option<CaptureReason> {
+        if forced {
+            Some(CaptureReason::Forced)
+        } else if total >= self.settings.threshold {
+            Some(CaptureReason::Threshold)
+        } else if sampled(request_id, self.settings.sample_rate) {
+            Some(CaptureReason::Sampled)
+        } else {
+            None
+        }
+    }
+
+    /// Record `timing` if the run qualifies. Runs refused before loop protection assigned a
+    /// request id are not recorded.
+    pub fn finish(&self, timing: Timing, run: RunInfo<'_>) -> Option<SlowRequestRecord> {
+        if self.read_only {
+            return None;
+        }
+        let request_id = timing.request_id.clone()?;
+        let total = timing.total();
+        let reason = self.should_capture(total, &request_id, run.forced)?;
+        let dominant_stage = timing
+            .stages
+            .iter()
+            .max_by(|a, b| a.ms.total_cmp(&b.ms))
+            .map_or(Stage::Serialize, |s| s.stage);
+        let record = SlowRequestRecord {
+            request_id,
+            recorded_unix: self.clock.now_unix(),
+            reason,
+            actor: run.actor.to_string(),
+            policy: run.policy.to_string(),
+            source_type: run.source_type.to_string(),
+            http_status: run.http_status.as_u16(),
+            total_ms: ms(total),
+            dominant_stage,
+            stages: timing.stages,
+            model_calls: timing.model_calls,
+            input_bytes: run.input_bytes,
+            extracted_chars: timing.extracted_chars,
+        };
+        tracing::info!(
+            request_id = %record.request_id,
+            reason = ?record.reason,
+            total_ms = record.total_ms,
+            dominant_stage = record.dominant_stage.as_str(),
+            "slow request recorded"
+        );
+        self.insert(record.clone());
+        Some(record)
+    }
+
+    /// Add a record, then apply retention.
+    pub fn insert(&self, record: SlowRequestRecord) {
+        if self.read_only {
+            return;
+        }
+        let mut records = self.records.lock().unwrap();
+        records.push_back(record);
+        self.prune_locked(&mut records);
+        self.persist(&records);
+    }
+
+    /// Drop records past `retention` or beyond `max_records`. Returns how many were removed.
+    pub fn prune(&self) -> usize {
+        let mut records = self.records.lock().unwrap();
+        let removed = self.prune_locked(&mut records);
+        if removed > 0 && !self.read_only {
+            self.persist(&records);
+        }
+        removed
+    }
+
+    fn prune_locked(&self, records: &mut VecDeque<SlowRequestRecord>) -> usize {
+        let before = records.len();
+        let oldest = self
+            .clock
+            .now_unix()
+            .saturating_sub(self.settings.retention.as_secs());
+        records.retain(|r| r.recorded_unix >= oldest);
+        while records.len() > self.settings.max_records {
+            records.pop_front();
+        }
+        before - records.len()
+    }
+
+    fn persist(&self, records: &VecDeque<SlowRequestRecord>) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let res = file.write(&SlowRequestFile {
+            records: records.iter().cloned().collect(),
+        });
+        if let Err(err) = res {
+            tracing::warn!(
+                error = %err,
+                path = %file.path().display(),
+                "Failed to persist slow requests"
+            );
+        }
+    }
+
+    /// Records made at or after `since_unix`, newest first.
+    pub fn list(&self, since_unix: u64, limit: usize) -> Vec<SlowRequestRecord> {
+        let mut records = self.records.lock().unwrap();
+        self.prune_locked(&mut records);
+        records
+            .iter()
+            .rev()
+            .filter(|r| r.recorded_unix >= since_unix)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, request_id: &str) -> Option<SlowRequestRecord> {
+        let records = self.records.lock().unwrap();
+        records.iter().find(|r| r.request_id == request_id).cloned()
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> Value {
+        json!({
+            "recorded": self.records.lock().unwrap().len(),
+            "threshold_ms": self.settings.threshold.as_millis() as u64,
+            "sample_rate": self.settings.sample_rate,
+            "retention_secs": self.settings.retention.as_secs(),
+            "max_records": self.settings.max_records,
+        })
+    }
+}
+
+/// Refuse `X-ACIP-Force-Timing` from actors without the `support` scope
+/// (403 `insufficient_scope`). Must sit inside token auth.
+pub fn require_force_scope<S>(router: Router<S>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(from_fn(force_timing_middleware))
+}
+
+async fn force_timing_middleware(req: Request, next: Next) -> Response {
+    if !forced(req.headers()) {
+        return next.run(req).await;
+    }
+    let actor = req
+        .extensions()
+        .get::<Actor>()
+        .cloned()
+        .unwrap_or_else(Actor::anonymous);
+    if actor.has(Scope::Support) {
+        return next.run(req).await;
+    }
+    introspection::json_error(
+        StatusCode::FORBIDDEN,
+        "insufficient_scope",
+        json!({
+            "required": Scope::Support.as_str(),
+            "token": actor.name,
+            "header": FORCE_HEADER,
+        }),
+    )
+    .into_response()
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SlowRequestQuery {
+    /// Unix seconds; only records made at or after this time.
+    #[serde(default)]
+    pub since: u64,
+    pub limit: Option<usize>,
+}
+
+/// `GET /v1/acip/slow_requests`
+pub async fn get_slow_requests(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<SlowRequestQuery>,
+) -> Response {
+    let limit = q
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let records = state.slow_requests.list(q.since, limit);
+    let settings = state.slow_requests.settings();
+    Json(json!({
+        "threshold_ms": settings.threshold.as_millis() as u64,
+        "sample_rate": settings.sample_rate,
+        "records": records,
+    }))
+    .into_response()
+}
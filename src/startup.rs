@@ -1,4 +1,4 @@
-use crate::{model_policy, policy_store, secrets};
+use crate::{config, model_policy, policy_store, secrets, token_auth};
 use anyhow::Result;
 use std::{path::PathBuf, sync::Arc};
 use tracing::{info, warn};
@@ -25,13 +25,13 @@ pub fn resolve_token(
     token_required: bool,
     secrets: &Arc<dyn secrets::SecretStore>,
     token_env: &str,
-) -> Result<Option<String>> {
+) -> Result<Option<secrets::Secret>> {
     if !token_required {
         return Ok(None);
     }
 
     match secrets.get(token_env) {
-        Some(token) if !token.trim().is_empty() => Ok(Some(token)),
+        Some(token) if !token.is_blank() => Ok(Some(token)),
         _ => anyhow::bail!(
             "auth token required but missing or empty in secrets store ({})",
             token_env
@@ -39,6 +39,45 @@ pub fn resolve_token(
     }
 }
 
+/// Resolve the token set: the `token_env` token (all scopes) plus named, scoped tokens.
+///
+/// With named tokens configured, the `token_env` token becomes optional. Every named token's
+/// secret must be present.
+pub fn resolve_tokens(
+    token_required: bool,
+    secrets: &Arc<dyn secrets::SecretStore>,
+    token_env: &str,
+    named: &[config::TokenConfig],
+) -> Result<token_auth::TokenSet> {
+    if !token_required {
+        return Ok(token_auth::TokenSet::default());
+    }
+    if named.is_empty() {
+        return Ok(token_auth::TokenSet::legacy(resolve_token(
+            token_required,
+            secrets,
+            token_env,
+        )?));
+    }
+
+    let legacy = secrets.get(token_env).filter(|t| !t.is_blank());
+    let mut set = token_auth::TokenSet::legacy(legacy);
+    for t in named {
+        let scopes = token_auth::parse_scopes(&t.scopes)
+            .map_err(|e| anyhow::anyhow!("token {:?}: {e}", t.name))?;
+        let value = match secrets.get(&t.secret) {
+            Some(v) if !v.is_blank() => v,
+            _ => anyhow::bail!(
+                "token {:?}: secret missing or empty in secrets store ({})",
+                t.name,
+                t.secret
+            ),
+        };
+        set.add(&t.name, value.expose(), scopes)?;
+    }
+    Ok(set)
+}
+
 /// Build policy store.
 ///
 /// If `policies_file` provided, load it. Otherwise derive default policy from env-configured model
@@ -48,32 +87,33 @@ pub fn build_policy_store(
     policies_file: Option<PathBuf>,
 ) -> Result<policy_store::PolicyStore> {
     if let Some(policies_path) = &policies_file {
-        let pf = policy_store::PoliciesFile::load(policies_path)?;
-        return Ok(policy_store::PolicyStore::from_file(pf));
+        return policy_store::PolicyStore::load(policies_path);
     }
 
     // Back-compat: derive the default policy from env.
     let mut mp = model_policy::PolicyConfig::default();
 
-    if let Some(p) = secrets.get("ACIP_L1_PROVIDER") {
+    // Model settings share the store with the secrets, but are not secret themselves.
+    let setting = |key: &str| secrets.get(key).map(|v| v.expose().to_string());
+    if let Some(p) = setting("ACIP_L1_PROVIDER") {
         if let Some(parsed) = model_policy::Provider::parse(&p) {
             mp.l1.provider = parsed;
         } else {
             warn!("Unknown ACIP_L1_PROVIDER={}; using default", p);
         }
     }
-    if let Some(m) = secrets.get("ACIP_L1_MODEL") {
+    if let Some(m) = setting("ACIP_L1_MODEL") {
         mp.l1.model = m;
     }
 
-    if let Some(p) = secrets.get("ACIP_L2_PROVIDER") {
+    if let Some(p) = setting("ACIP_L2_PROVIDER") {
         if let Some(parsed) = model_policy::Provider::parse(&p) {
             mp.l2.provider = parsed;
         } else {
             warn!("Unknown ACIP_L2_PROVIDER={}; using default", p);
         }
     }
-    if let Some(m) = secrets.get("ACIP_L2_MODEL") {
+    if let Some(m) = setting("ACIP_L2_MODEL") {
         mp.l2.model = m;
     }
 
@@ -89,3 +129,55 @@ pub fn build_policy_store(
         mp.l2.model.clone(),
     ))
 }
+
+/// Re-read `[redaction]` and the remediation hints file on SIGHUP; whatever fails to load
+/// keeps its current rules.
+///
+/// The handler is installed before this returns, so a SIGHUP sent afterwards is never lost.
+/// Must be called from within a Tokio runtime.
+#[cfg(unix)]
+pub fn spawn_config_reloader(
+    config_path: PathBuf,
+    redaction: Arc<crate::redact::Redaction>,
+    remediation: Arc<crate::remediation::Remediation>,
+) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hup = match signal(SignalKind::hangup()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("cannot install SIGHUP handler; redaction rules will not hot-reload: {e}");
+            return;
+        }
+    };
+    tokio::spawn(async move {
+        while hup.recv().await.is_some() {
+            let cfg = match config::Config::load(&config_path) {
+                Ok(cfg) => cfg,
+                Err(e) => {
+                    warn!("config reload failed; keeping previous rules: {e:#}");
+                    continue;
+                }
+            };
+            let res = redaction.reload_with_limits(
+                cfg.redaction.as_ref(),
+                &crate::regex_guard::RegexLimits::from_config(cfg.regex.as_ref()),
+            );
+            match res {
+                Ok(()) => info!("reloaded {} redaction rules", redaction.rule_count()),
+                Err(e) => warn!("redaction reload failed; keeping previous rules: {e:#}"),
+            }
+            match remediation.reload(cfg.remediation.as_ref()) {
+                Ok(n) => info!("reloaded {n} remediation hints"),
+                Err(e) => warn!("remediation hints reload failed; keeping previous hints: {e:#}"),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn spawn_config_reloader(
+    _config_path: PathBuf,
+    _redaction: Arc<crate::redact::Redaction>,
+    _remediation: Arc<crate::remediation::Remediation>,
+) {
+}
@@ -17,6 +17,8 @@ pub struct NormalizeSettings {
 
     pub adversarial_threshold: u8,
     pub adversarial_tighten_factor: f64,
+
+    pub decode: crate::decode_scan::DecodeBudget,
 }
 
 impl NormalizeSettings {
@@ -36,6 +38,14 @@ impl NormalizeSettings {
         let mut adversarial_tighten_factor = cfg
             .map(|c| c.adversarial_tighten_factor)
             .unwrap_or(config::DEFAULT_NORMALIZE_ADVERSARIAL_TIGHTEN_FACTOR);
+        let mut decode = crate::decode_scan::DecodeBudget {
+            max_regions: cfg
+                .map(|c| c.decode_max_regions)
+                .unwrap_or(config::DEFAULT_NORMALIZE_DECODE_MAX_REGIONS),
+            max_decoded_bytes: cfg
+                .map(|c| c.decode_max_bytes)
+                .unwrap_or(config::DEFAULT_NORMALIZE_DECODE_MAX_BYTES),
+        };
 
         if let Some(v) = env_usize("ACIP_NORMALIZE_MAX_INPUT_CHARS") {
             max_input_chars = v;
@@ -53,6 +63,12 @@ impl NormalizeSettings {
         if let Some(v) = env_f64("ACIP_NORMALIZE_ADVERSARIAL_TIGHTEN_FACTOR") {
             adversarial_tighten_factor = v;
         }
+        if let Some(v) = env_usize("ACIP_NORMALIZE_DECODE_MAX_REGIONS") {
+            decode.max_regions = v;
+        }
+        if let Some(v) = env_usize("ACIP_NORMALIZE_DECODE_MAX_BYTES") {
+            decode.max_decoded_bytes = v;
+        }
 
         // Defensive clamp for factor.
         if !(0.0..=1.0).contains(&adversarial_tighten_factor) {
@@ -65,6 +81,7 @@ impl NormalizeSettings {
             window_tail_chars,
             adversarial_threshold,
             adversarial_tighten_factor,
+            decode,
         }
     }
 }
@@ -74,9 +91,65 @@ pub struct AppState {
     pub policy: Policy,
     pub normalize: NormalizeSettings,
     pub http: Client,
+    /// Deliveries and fetches to [`crate::egress`] destinations; does not follow redirects.
+    pub egress_http: Client,
     pub secrets: Arc<dyn secrets::SecretStore>,
     pub policies: PolicyStore,
     pub reputation: Arc<dyn crate::reputation::ReputationStore>,
+    pub reputation_thresholds: crate::reputation_policy::ReputationThresholds,
+    pub stats: Arc<crate::stats::DecisionStats>,
+    pub verdicts: Arc<crate::verdicts::VerdictHistory>,
+    pub redaction: Arc<crate::redact::Redaction>,
+    pub drain: Arc<crate::drain::DrainControl>,
+    pub tmp: Arc<crate::tmpdir::TmpDirManager>,
+    pub uploads: Arc<crate::uploads::UploadStore>,
+    pub model_versions: Arc<crate::model_pinning::ModelVersionMonitor>,
+    pub loop_guard: Arc<crate::loop_guard::LoopGuard>,
+    pub feeds: Arc<crate::feeds::FeedRegistry>,
+    /// `server.read_only`: mutating routes are refused (see [`crate::read_only`]).
+    pub read_only: bool,
+    pub jobs: Arc<crate::jobs::JobStore>,
+    /// Handling of repeated `X-ACIP-*` headers (see [`crate::acip_headers`]).
+    pub header_rules: Arc<crate::acip_headers::HeaderRules>,
+    /// Stage timings of slow ingest runs (see [`crate::slow_requests`]).
+    pub slow_requests: Arc<crate::slow_requests::SlowRequestLog>,
+    /// Content types refused before the pipeline runs (see [`crate::content_types`]).
+    pub content_types: Arc<crate::content_types::ContentTypeRules>,
+    /// Decision export to a SIEM (see [`crate::siem`]).
+    pub siem: Arc<crate::siem::SiemExport>,
+    /// User `[[patterns]]` with their scan budgets (see [`crate::patterns`]).
+    pub patterns: Arc<crate::patterns::PatternPack>,
+    /// Per-request audit entries and their cross-references (see [`crate::incidents`]).
+    pub incidents: Arc<crate::incidents::IncidentLog>,
+    /// Answers every model request in place of the policy's providers (golden runs and tests;
+    /// see [`crate::test_support`]). Always `None` in the server.
+    pub model_override: Option<Arc<dyn crate::sentry::ModelClient>>,
+    /// Span export for distributed tracing (see [`crate::telemetry`]).
+    pub telemetry: Arc<crate::telemetry::Telemetry>,
+    /// `server.on_client_disconnect` and cancelled-run counters (see [`crate::disconnect`]).
+    pub disconnects: Arc<crate::disconnect::Disconnects>,
+    /// Salt for identifier hashes that leave the process (see [`crate::hashing`]).
+    pub hashing: Arc<crate::hashing::IdHasher>,
+    /// Bounded pool for blocking store and disk work (see [`crate::blocking`]).
+    pub blocking: Arc<crate::blocking::BlockingPool>,
+    /// Active policy experiments (see [`crate::experiments`]).
+    pub experiments: Arc<crate::experiments::ExperimentRegistry>,
+    /// Circuit breaker around the extractor helper (see [`crate::extractor_health`]).
+    pub extractor_health: Arc<crate::extractor_health::ExtractorHealth>,
+    /// Decision webhooks (see [`crate::notify`]).
+    pub notify: Arc<crate::notify::Notifier>,
+    /// Origins browser clients may call from (see [`crate::cors`]).
+    pub cors: Arc<crate::cors::CorsPolicy>,
+    /// Host limits found at startup (see [`crate::environment`]).
+    pub environment: Arc<crate::environment::Report>,
+    /// Chunk lists of recent large inputs (see [`crate::chunking`]).
+    pub chunks: Arc<crate::chunking::ChunkIndex>,
+    /// Active link probing of escalated documents (see [`crate::probing`]).
+    pub probes: Arc<crate::probing::Prober>,
+    /// Remediation hints for non-allow decisions (see [`crate::remediation`]).
+    pub remediation: Arc<crate::remediation::Remediation>,
+    /// Decision summary headers on ingest responses (see [`crate::risk_headers`]).
+    pub risk_headers: crate::risk_headers::RiskHeaders,
 }
 
 fn env_usize(key: &str) -> Option<usize> {
@@ -0,0 +1,858 @@
+//! In-process decision statistics for tuning reviews.
+//!
+//! Counters are bucketed per UTC day and kept for `retain_days`; with a file backend the
+//! buckets survive restarts. Pattern cardinality is bounded twice: per day (new ids past
+//! [`MAX_PATTERNS_PER_DAY`] fold into [`OTHER_BUCKET`]) and per report (top-K plus "other").
+//! Counts are also kept per tenant (the token behind the decision) for the cross-tenant view in
+//! [`crate::stats_aggregate`].
+
+use crate::reputation::{self, Clock};
+use crate::sentry::{Action, ConfidenceBucket, ParseAttempt, RiskLevel, SecondOpinion};
+use crate::stats_aggregate::{AggregateSettings, Snapshot};
+use crate::storage::{self, StorageError, StoreFile};
+use crate::text_quality::QualityBucket;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+pub const DAY_SECS: u64 = 86_400;
+
+/// Bucket for patterns outside the tracked/top-K set.
+pub const OTHER_BUCKET: &str = "other";
+
+/// Distinct pattern ids tracked per day before new ones fold into [`OTHER_BUCKET`].
+pub const MAX_PATTERNS_PER_DAY: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct StatsSettings {
+    pub retain_days: u64,
+    pub top_k: usize,
+    pub aggregate: AggregateSettings,
+}
+
+impl Default for StatsSettings {
+    fn default() -> Self {
+        Self {
+            retain_days: 14,
+            top_k: 20,
+            aggregate: AggregateSettings::default(),
+        }
+    }
+}
+
+impl StatsSettings {
+    /// Defaults, then `ACIP_STATS_RETAIN_DAYS` / `ACIP_STATS_TOP_K` overrides.
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_STATS_RETAIN_DAYS").filter(|v| *v > 0) {
+            s.retain_days = v;
+        }
+        if let Some(v) = env_u64("ACIP_STATS_TOP_K").filter(|v| *v > 0) {
+            s.top_k = v as usize;
+        }
+        s.aggregate = AggregateSettings::from_env();
+        s
+    }
+}
+
+pub(crate) fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.trim().parse::<u64>().ok())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    #[default]
+    Policy,
+    Pattern,
+    SourceType,
+    Model,
+    Confidence,
+}
+
+/// Key of decisions without a confidence signal in per-confidence counters.
+pub const NO_CONFIDENCE: &str = "none";
+
+fn confidence_key(c: Option<ConfidenceBucket>) -> &'static str {
+    c.map_or(NO_CONFIDENCE, |c| c.as_str())
+}
+
+/// One final ingest decision, as seen by the aggregator.
+#[derive(Debug, Clone)]
+pub struct DecisionSample {
+    /// Token name the decision was made for; the tenant in aggregate stats.
+    pub tenant: String,
+    pub policy: String,
+    pub source_type: String,
+    pub action: Action,
+    pub risk_level: RiskLevel,
+    /// Local detector indicators and model-reported patterns.
+    pub patterns: Vec<String>,
+    /// The L1 verdict was unusable and L2 (or fail-closed) decided.
+    pub escalated: bool,
+    pub severity: u8,
+    /// Text quality bucket of the model-facing text, when assessed.
+    pub quality: Option<QualityBucket>,
+    /// Confidence bucket of the verdict, when the deciding tier gave a signal.
+    pub confidence: Option<ConfidenceBucket>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PolicyCounters {
+    decisions: u64,
+    by_action: BTreeMap<String, u64>,
+    by_risk_level: BTreeMap<String, u64>,
+    escalations: u64,
+    /// Stale verdicts re-checked by a full pipeline run.
+    #[serde(default)]
+    revalidations: u64,
+    /// Re-checks whose new verdict differed from the stale one (rules drift).
+    #[serde(default)]
+    revalidation_divergences: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PatternCounters {
+    hits: u64,
+    /// Hits whose decision ended in block or needs_review.
+    escalated_outcomes: u64,
+    false_positives: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SourceTypeCounters {
+    decisions: u64,
+    severity_sum: u64,
+    /// Decisions per text quality bucket.
+    #[serde(default)]
+    by_quality: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ModelCounters {
+    /// Model outputs parsed (L1 and L2 separately).
+    parses: u64,
+    /// Outputs that became valid only after repairs.
+    repaired: u64,
+    /// Outputs that could not become a valid decision.
+    invalid: u64,
+    /// Per repair kind.
+    by_repair: BTreeMap<String, u64>,
+    /// Outputs that were second samples for `l1.consistency_check`.
+    #[serde(default)]
+    consistency_checks: u64,
+}
+
+/// Outcomes per confidence bucket, for calibrating the thresholds.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ConfidenceCounters {
+    decisions: u64,
+    /// Low-confidence L1 verdicts sent to L2, keyed by the L1 bucket.
+    #[serde(default)]
+    second_opinions: u64,
+    /// Second opinions where L2 reached a different action.
+    #[serde(default)]
+    second_opinion_overturns: u64,
+    /// Stale verdicts re-checked, keyed by the bucket of the stale verdict.
+    #[serde(default)]
+    revalidations: u64,
+    #[serde(default)]
+    revalidation_divergences: u64,
+}
+
+/// One tenant's decisions per (policy, source type, action) and pattern hits per (policy,
+/// source type, action, pattern), keyed by [`Cell::key`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct TenantCounters {
+    #[serde(default)]
+    decisions: BTreeMap<String, u64>,
+    #[serde(default)]
+    patterns: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DayBucket {
+    #[serde(default)]
+    policies: BTreeMap<String, PolicyCounters>,
+    #[serde(default)]
+    patterns: BTreeMap<String, PatternCounters>,
+    #[serde(default)]
+    source_types: BTreeMap<String, SourceTypeCounters>,
+    #[serde(default)]
+    models: BTreeMap<String, ModelCounters>,
+    #[serde(default)]
+    tenants: BTreeMap<String, TenantCounters>,
+    #[serde(default)]
+    confidence: BTreeMap<String, ConfidenceCounters>,
+}
+
+impl DayBucket {
+    /// Counter for `id`, folding into [`OTHER_BUCKET`] once the day is full.
+    fn pattern_mut(&mut self, id: &str) -> &mut PatternCounters {
+        let key = if self.patterns.contains_key(id) || self.patterns.len() < MAX_PATTERNS_PER_DAY {
+            id
+        } else {
+            OTHER_BUCKET
+        };
+        self.patterns.entry(key.to_string()).or_default()
+    }
+}
+
+/// Separates [`Cell`] fields in stored keys.
+const CELL_SEP: char = '\u{1f}';
+
+/// The finest breakdown of the per-tenant counters.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Cell {
+    pub policy: String,
+    pub source_type: String,
+    pub action: String,
+    /// Set for pattern-hit cells.
+    pub pattern: Option<String>,
+}
+
+impl Cell {
+    fn key(&self) -> String {
+        let mut fields = vec![
+            self.policy.as_str(),
+            self.source_type.as_str(),
+            self.action.as_str(),
+        ];
+        fields.extend(self.pattern.as_deref());
+        fields.join(&CELL_SEP.to_string())
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        let mut parts = key.split(CELL_SEP);
+        let (policy, source_type, action) = (parts.next()?, parts.next()?, parts.next()?);
+        Some(Self {
+            policy: policy.to_string(),
+            source_type: source_type.to_string(),
+            action: action.to_string(),
+            pattern: parts.next().map(str::to_string),
+        })
+    }
+}
+
+/// Per-tenant counts over a window, the input of [`crate::stats_aggregate::Snapshot`].
+#[derive(Debug, Clone, Default)]
+pub struct TenantCells {
+    /// Tenant -> decision cell -> decisions.
+    pub decisions: BTreeMap<String, BTreeMap<Cell, u64>>,
+    /// Tenant -> pattern cell -> hits.
+    pub patterns: BTreeMap<String, BTreeMap<Cell, u64>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StatsFile {
+    /// Keyed by day index (`unix / 86400`).
+    #[serde(default)]
+    days: BTreeMap<u64, DayBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PolicyRow {
+    pub policy: String,
+    pub decisions: u64,
+    pub by_action: BTreeMap<String, u64>,
+    pub by_risk_level: BTreeMap<String, u64>,
+    pub escalations: u64,
+    pub escalation_rate: f64,
+    pub revalidations: u64,
+    pub revalidation_divergences: u64,
+    /// Share of stale re-checks that reproduced the old verdict; high values mean
+    /// `cache.max_verdict_age_days` could be lengthened.
+    pub revalidation_agreement_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PatternRow {
+    pub pattern: String,
+    pub hits: u64,
+    pub escalated_outcomes: u64,
+    pub escalated_share: f64,
+    pub false_positives: u64,
+    pub overturn_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SourceTypeRow {
+    pub source_type: String,
+    pub decisions: u64,
+    pub avg_severity: f64,
+    /// Decisions per text quality bucket (`good`, `degraded`, `garbled`).
+    pub by_quality: BTreeMap<String, u64>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ModelRow {
+    /// `Provider/model`.
+    pub model: String,
+    pub parses: u64,
+    pub repaired: u64,
+    pub invalid: u64,
+    /// Share of parses that needed at least one repair; a rising rate is an early sign the
+    /// provider changed its output.
+    pub repair_rate: f64,
+    pub invalid_rate: f64,
+    pub by_repair: BTreeMap<String, u64>,
+    /// Parses that were consistency-check samples, i.e. the extra cost of that check.
+    pub consistency_checks: u64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConfidenceRow {
+    /// `high`, `medium`, `low`, or [`NO_CONFIDENCE`].
+    pub confidence: String,
+    pub decisions: u64,
+    pub second_opinions: u64,
+    pub second_opinion_overturns: u64,
+    /// Share of second opinions where L2 disagreed with L1.
+    pub overturn_rate: f64,
+    pub revalidations: u64,
+    pub revalidation_divergences: u64,
+    /// Share of stale re-checks that did not reproduce the old verdict. Well-calibrated
+    /// confidence keeps this low for `high` and higher for `low`.
+    pub divergence_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum StatsRow {
+    Policy(PolicyRow),
+    Pattern(PatternRow),
+    SourceType(SourceTypeRow),
+    Model(ModelRow),
+    Confidence(ConfidenceRow),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatsReport {
+    pub days: u64,
+    pub group_by: GroupBy,
+    pub rows: Vec<StatsRow>,
+}
+
+fn label<T: Serialize>(v: &T) -> String {
+    serde_json::to_value(v)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn ratio(num: u64, den: u64) -> f64 {
+    if den == 0 {
+        0.0
+    } else {
+        num as f64 / den as f64
+    }
+}
+
+/// Rolling per-day decision counters.
+pub struct DecisionStats {
+    settings: StatsSettings,
+    clock: Arc<dyn Clock>,
+    file: Option<StoreFile>,
+    days: Mutex<BTreeMap<u64, DayBucket>>,
+    /// Aggregate snapshots per window length, reused for `aggregate.snapshot_secs`.
+    snapshots: Mutex<BTreeMap<u64, Arc<Snapshot>>>,
+    /// `server.read_only`: the `record*` methods change nothing.
+    read_only: bool,
+}
+
+impl Default for DecisionStats {
+    /// In-memory, default settings, wall clock.
+    fn default() -> Self {
+        Self::in_memory(StatsSettings::default(), Arc::new(reputation::SystemClock))
+    }
+}
+
+impl DecisionStats {
+    pub fn in_memory(settings: StatsSettings, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            clock,
+            file: None,
+            days: Mutex::new(BTreeMap::new()),
+            snapshots: Mutex::new(BTreeMap::new()),
+            read_only: false,
+        }
+    }
+
+    /// File-backed stats. A corrupt file is logged and replaced rather than failing startup.
+    pub fn load_or_create(
+        path: impl AsRef<Path>,
+        settings: StatsSettings,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (file, days) = match StoreFile::open::<StatsFile>(&path, &storage::STATS, false) {
+            Ok(opened) => (opened.file, opened.data.unwrap_or_default().days),
+            Err(StorageError::Corrupt { reason, .. }) => {
+                tracing::warn!(
+                    error = %reason,
+                    path = %path.display(),
+                    "Ignoring unreadable stats file"
+                );
+                (StoreFile::new(path, &storage::STATS), BTreeMap::new())
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            settings,
+            clock,
+            file: Some(file),
+            days: Mutex::new(days),
+            snapshots: Mutex::new(BTreeMap::new()),
+            read_only: false,
+        })
+    }
+
+    /// Open an existing stats file without ever writing to it (`server.read_only`). A missing
+    /// or unreadable file is an error rather than an empty store.
+    pub fn open_read_only(
+        path: impl AsRef<Path>,
+        settings: StatsSettings,
+        clock: Arc<dyn Clock>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let opened = StoreFile::open::<StatsFile>(&path, &storage::STATS, true)
+            .with_context(|| format!("open stats store {}", path.display()))?;
+        let parsed = opened
+            .data
+            .with_context(|| format!("read stats store {}: no such file", path.display()))?;
+        Ok(Self {
+            settings,
+            clock,
+            file: Some(opened.file),
+            days: Mutex::new(parsed.days),
+            snapshots: Mutex::new(BTreeMap::new()),
+            read_only: true,
+        })
+    }
+
+    /// The same stats with recording turned off.
+    pub fn into_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn settings(&self) -> &StatsSettings {
+        &self.settings
+    }
+
+    /// The backing file and its format version, for the file backend.
+    pub fn storage(&self) -> Option<storage::StoreInfo> {
+        self.file.as_ref().map(StoreFile::info)
+    }
+
+    fn today(&self) -> u64 {
+        self.clock.now_unix() / DAY_SECS
+    }
+
+    fn prune(&self, days: &mut BTreeMap<u64, DayBucket>, today: u64) {
+        let oldest = today.saturating_sub(self.settings.retain_days.saturating_sub(1));
+        days.retain(|day, _| *day >= oldest);
+    }
+
+    fn persist(&self, days: &BTreeMap<u64, DayBucket>) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if let Err(err) = file.write(&StatsFile { days: days.clone() }) {
+            tracing::warn!(error = %err, path = %file.path().display(), "Failed to persist stats");
+        }
+    }
+
+    pub fn record(&self, s: &DecisionSample) {
+        if self.read_only {
+            return;
+        }
+        let today = self.today();
+        let mut days = self.days.lock().unwrap();
+        self.prune(&mut days, today);
+        let bucket = days.entry(today).or_default();
+
+        let p = bucket.policies.entry(s.policy.clone()).or_default();
+        p.decisions += 1;
+        *p.by_action.entry(label(&s.action)).or_default() += 1;
+        *p.by_risk_level.entry(label(&s.risk_level)).or_default() += 1;
+        if s.escalated {
+            p.escalations += 1;
+        }
+
+        let escalated_outcome = matches!(s.action, Action::Block | Action::NeedsReview);
+        let mut seen: Vec<&str> = vec![];
+        for id in &s.patterns {
+            if seen.contains(&id.as_str()) {
+                continue;
+            }
+            seen.push(id);
+            let c = bucket.pattern_mut(id);
+            c.hits += 1;
+            if escalated_outcome {
+                c.escalated_outcomes += 1;
+            }
+        }
+
+        let st = bucket
+            .source_types
+            .entry(s.source_type.clone())
+            .or_default();
+        st.decisions += 1;
+        st.severity_sum += s.severity as u64;
+        if let Some(q) = s.quality {
+            *st.by_quality.entry(q.as_str().to_string()).or_default() += 1;
+        }
+
+        bucket
+            .confidence
+            .entry(confidence_key(s.confidence).to_string())
+            .or_default()
+            .decisions += 1;
+
+        let t = bucket.tenants.entry(s.tenant.clone()).or_default();
+        let mut cell = Cell {
+            policy: s.policy.clone(),
+            source_type: s.source_type.clone(),
+            action: label(&s.action),
+            pattern: None,
+        };
+        *t.decisions.entry(cell.key()).or_default() += 1;
+        for id in seen {
+            cell.pattern = Some(id.to_string());
+            let key = cell.key();
+            if !t.patterns.contains_key(&key) && t.patterns.len() >= MAX_PATTERNS_PER_DAY {
+                cell.pattern = Some(OTHER_BUCKET.to_string());
+            }
+            *t.patterns.entry(cell.key()).or_default() += 1;
+        }
+
+        self.persist(&days);
+    }
+
+    /// Count a full re-check of a stale verdict for `policy`; `agreed` is false on drift.
+    /// `confidence` is the bucket of the stale verdict.
+    pub fn record_revalidation(
+        &self,
+        policy: &str,
+        confidence: Option<ConfidenceBucket>,
+        agreed: bool,
+    ) {
+        if self.read_only {
+            return;
+        }
+        let today = self.today();
+        let mut days = self.days.lock().unwrap();
+        self.prune(&mut days, today);
+        let bucket = days.entry(today).or_default();
+        let p = bucket.policies.entry(policy.to_string()).or_default();
+        p.revalidations += 1;
+        if !agreed {
+            p.revalidation_divergences += 1;
+        }
+        let c = bucket
+            .confidence
+            .entry(confidence_key(confidence).to_string())
+            .or_default();
+        c.revalidations += 1;
+        if !agreed {
+            c.revalidation_divergences += 1;
+        }
+        self.persist(&days);
+    }
+
+    /// Count a low-confidence L1 verdict that went to L2 for a second opinion.
+    pub fn record_second_opinion(&self, second: &SecondOpinion) {
+        if self.read_only {
+            return;
+        }
+        let today = self.today();
+        let mut days = self.days.lock().unwrap();
+        self.prune(&mut days, today);
+        let bucket = days.entry(today).or_default();
+        let c = bucket
+            .confidence
+            .entry(second.l1_confidence.bucket.as_str().to_string())
+            .or_default();
+        c.second_opinions += 1;
+        if second.overturned {
+            c.second_opinion_overturns += 1;
+        }
+        self.persist(&days);
+    }
+
+    /// Count how each model output of one decision parsed.
+    pub fn record_parse_attempts(&self, attempts: &[ParseAttempt]) {
+        if self.read_only {
+            return;
+        }
+        if attempts.is_empty() {
+            return;
+        }
+        let today = self.today();
+        let mut days = self.days.lock().unwrap();
+        self.prune(&mut days, today);
+        let bucket = days.entry(today).or_default();
+        for a in attempts {
+            let m = bucket.models.entry(a.model.clone()).or_default();
+            m.parses += 1;
+            if !a.valid {
+                m.invalid += 1;
+            } else if !a.repairs.is_empty() {
+                m.repaired += 1;
+            }
+            for r in &a.repairs {
+                *m.by_repair.entry(r.as_str().to_string()).or_default() += 1;
+            }
+            if a.consistency_check {
+                m.consistency_checks += 1;
+            }
+        }
+        self.persist(&days);
+    }
+
+    /// Count a reviewer overturning a decision driven by `pattern` (joinable by pattern id).
+    pub fn record_false_positive(&self, pattern: &str) {
+        if self.read_only {
+            return;
+        }
+        let today = self.today();
+        let mut days = self.days.lock().unwrap();
+        self.prune(&mut days, today);
+        days.entry(today)
+            .or_default()
+            .pattern_mut(pattern)
+            .false_positives += 1;
+        self.persist(&days);
+    }
+
+    /// Per-tenant counts over the last `days` days (clamped to `1..=retain_days`).
+    pub fn tenant_cells(&self, days: u64) -> TenantCells {
+        let days = days.clamp(1, self.settings.retain_days);
+        let today = self.today();
+        let oldest = today.saturating_sub(days - 1);
+
+        let guard = self.days.lock().unwrap();
+        let mut out = TenantCells::default();
+        for b in guard.range(oldest..=today).map(|(_, b)| b) {
+            for (tenant, t) in &b.tenants {
+                let decisions = out.decisions.entry(tenant.clone()).or_default();
+                for (key, n) in &t.decisions {
+                    if let Some(cell) = Cell::from_key(key) {
+                        *decisions.entry(cell).or_default() += n;
+                    }
+                }
+                let patterns = out.patterns.entry(tenant.clone()).or_default();
+                for (key, n) in &t.patterns {
+                    if let Some(cell) = Cell::from_key(key) {
+                        *patterns.entry(cell).or_default() += n;
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// The cross-tenant snapshot for the last `days` days. Every query within
+    /// `aggregate.snapshot_secs` of the first one is answered from the same snapshot.
+    pub fn aggregate_snapshot(&self, days: u64) -> Arc<Snapshot> {
+        let days = days.clamp(1, self.settings.retain_days);
+        let now = self.clock.now_unix();
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if let Some(s) = snapshots.get(&days) {
+            if now.saturating_sub(s.built_unix) < self.settings.aggregate.snapshot_secs {
+                return s.clone();
+            }
+        }
+        let snapshot = Arc::new(Snapshot::build(
+            &self.tenant_cells(days),
+            &self.settings.aggregate,
+            days,
+            now,
+        ));
+        snapshots.insert(days, snapshot.clone());
+        snapshot
+    }
+
+    /// Aggregate the last `days` days (clamped to `1..=retain_days`), today included.
+    pub fn report(&self, days: u64, group_by: GroupBy) -> StatsReport {
+        let days = days.clamp(1, self.settings.retain_days);
+        let today = self.today();
+        let oldest = today.saturating_sub(days - 1);
+
+        let guard = self.days.lock().unwrap();
+        let window = guard.range(oldest..=today).map(|(_, b)| b);
+
+        let rows = match group_by {
+            GroupBy::Policy => {
+                let mut agg: BTreeMap<&str, PolicyCounters> = BTreeMap::new();
+                for b in window {
+                    for (name, c) in &b.policies {
+                        let a = agg.entry(name).or_default();
+                        a.decisions += c.decisions;
+                        a.escalations += c.escalations;
+                        a.revalidations += c.revalidations;
+                        a.revalidation_divergences += c.revalidation_divergences;
+                        for (k, v) in &c.by_action {
+                            *a.by_action.entry(k.clone()).or_default() += v;
+                        }
+                        for (k, v) in &c.by_risk_level {
+                            *a.by_risk_level.entry(k.clone()).or_default() += v;
+                        }
+                    }
+                }
+                agg.into_iter()
+                    .map(|(name, c)| {
+                        StatsRow::Policy(PolicyRow {
+                            policy: name.to_string(),
+                            decisions: c.decisions,
+                            escalation_rate: ratio(c.escalations, c.decisions),
+                            escalations: c.escalations,
+                            revalidation_agreement_rate: ratio(
+                                c.revalidations - c.revalidation_divergences,
+                                c.revalidations,
+                            ),
+                            revalidations: c.revalidations,
+                            revalidation_divergences: c.revalidation_divergences,
+                            by_action: c.by_action,
+                            by_risk_level: c.by_risk_level,
+                        })
+                    })
+                    .collect()
+            }
+            GroupBy::Pattern => {
+                let mut agg: BTreeMap<&str, PatternCounters> = BTreeMap::new();
+                for b in window {
+                    for (id, c) in &b.patterns {
+                        let a = agg.entry(id).or_default();
+                        a.hits += c.hits;
+                        a.escalated_outcomes += c.escalated_outcomes;
+                        a.false_positives += c.false_positives;
+                    }
+                }
+                let mut other = agg.remove(OTHER_BUCKET).unwrap_or_default();
+                let mut ranked: Vec<(&str, PatternCounters)> = agg.into_iter().collect();
+                ranked.sort_by(|a, b| b.1.hits.cmp(&a.1.hits).then(a.0.cmp(b.0)));
+                let keep = self.settings.top_k.min(ranked.len());
+                for (_, c) in ranked.drain(keep..) {
+                    other.hits += c.hits;
+                    other.escalated_outcomes += c.escalated_outcomes;
+                    other.false_positives += c.false_positives;
+                }
+                if other.hits > 0 || other.false_positives > 0 {
+                    ranked.push((OTHER_BUCKET, other));
+                }
+                ranked
+                    .into_iter()
+                    .map(|(id, c)| {
+                        StatsRow::Pattern(PatternRow {
+                            pattern: id.to_string(),
+                            hits: c.hits,
+                            escalated_outcomes: c.escalated_outcomes,
+                            escalated_share: ratio(c.escalated_outcomes, c.hits),
+                            false_positives: c.false_positives,
+                            overturn_rate: ratio(c.false_positives, c.hits),
+                        })
+                    })
+                    .collect()
+            }
+            GroupBy::SourceType => {
+                let mut agg: BTreeMap<&str, SourceTypeCounters> = BTreeMap::new();
+                for b in window {
+                    for (name, c) in &b.source_types {
+                        let a = agg.entry(name).or_default();
+                        a.decisions += c.decisions;
+                        a.severity_sum += c.severity_sum;
+                        for (k, v) in &c.by_quality {
+                            *a.by_quality.entry(k.clone()).or_default() += v;
+                        }
+                    }
+                }
+                agg.into_iter()
+                    .map(|(name, c)| {
+                        StatsRow::SourceType(SourceTypeRow {
+                            source_type: name.to_string(),
+                            decisions: c.decisions,
+                            avg_severity: ratio(c.severity_sum, c.decisions),
+                            by_quality: c.by_quality,
+                        })
+                    })
+                    .collect()
+            }
+            GroupBy::Model => {
+                let mut agg: BTreeMap<&str, ModelCounters> = BTreeMap::new();
+                for b in window {
+                    for (name, c) in &b.models {
+                        let a = agg.entry(name).or_default();
+                        a.parses += c.parses;
+                        a.repaired += c.repaired;
+                        a.invalid += c.invalid;
+                        a.consistency_checks += c.consistency_checks;
+                        for (k, v) in &c.by_repair {
+                            *a.by_repair.entry(k.clone()).or_default() += v;
+                        }
+                    }
+                }
+                agg.into_iter()
+                    .map(|(name, c)| {
+                        StatsRow::Model(ModelRow {
+                            model: name.to_string(),
+                            parses: c.parses,
+                            repaired: c.repaired,
+                            invalid: c.invalid,
+                            repair_rate: ratio(c.repaired, c.parses),
+                            invalid_rate: ratio(c.invalid, c.parses),
+                            by_repair: c.by_repair,
+                            consistency_checks: c.consistency_checks,
+                        })
+                    })
+                    .collect()
+            }
+            GroupBy::Confidence => {
+                let mut agg: BTreeMap<&str, ConfidenceCounters> = BTreeMap::new();
+                for b in window {
+                    for (name, c) in &b.confidence {
+                        let a = agg.entry(name).or_default();
+                        a.decisions += c.decisions;
+                        a.second_opinions += c.second_opinions;
+                        a.second_opinion_overturns += c.second_opinion_overturns;
+                        a.revalidations += c.revalidations;
+                        a.revalidation_divergences += c.revalidation_divergences;
+                    }
+                }
+                agg.into_iter()
+                    .map(|(name, c)| {
+                        StatsRow::Confidence(ConfidenceRow {
+                            confidence: name.to_string(),
+                            decisions: c.decisions,
+                            second_opinions: c.second_opinions,
+                            second_opinion_overturns: c.second_opinion_overturns,
+                            overturn_rate: ratio(c.second_opinion_overturns, c.second_opinions),
+                            revalidations: c.revalidations,
+                            revalidation_divergences: c.revalidation_divergences,
+                            divergence_rate: ratio(c.revalidation_divergences, c.revalidations),
+                        })
+                    })
+                    .collect()
+            }
+        };
+
+        StatsReport {
+            days,
+            group_by,
+            rows,
+        }
+    }
+}
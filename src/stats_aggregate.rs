@@ -0,0 +1,438 @@
+//! Cross-tenant aggregate stats for platform dashboards.
+//!
+//! Tenants are the token names behind decisions. A [`Snapshot`] sums the per-tenant counters of
+//! one window once and suppresses, at the finest breakdown (policy x source type x action, plus
+//! pattern for hit counts), every cell contributed by fewer than `min_tenants` tenants or holding
+//! fewer than `min_events` events. Every grouping is then a sum of the surviving cells, with the
+//! suppressed ones always rolled into `other`, so no pair of groupings can be differenced to
+//! recover a suppressed cell. Snapshots are reused for `snapshot_secs`, so concurrent queries
+//! see the same numbers.
+//!
+//! The `external` variant adds bounded Laplace noise to every published count. Noise is drawn
+//! once per snapshot and row, so repeating a query does not average it away; it is labeled
+//! `approximate`.
+
+use crate::introspection;
+use crate::state::AppState;
+use crate::stats::{env_u64, Cell, TenantCells, OTHER_BUCKET};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+pub const DEFAULT_MIN_TENANTS: usize = 3;
+pub const DEFAULT_MIN_EVENTS: u64 = 10;
+pub const DEFAULT_NOISE_BOUND: u64 = 20;
+pub const DEFAULT_SNAPSHOT_SECS: u64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct AggregateSettings {
+    /// Minimum distinct tenants behind a published cell (K).
+    pub min_tenants: usize,
+    /// Minimum events in a published cell (N).
+    pub min_events: u64,
+    /// Laplace privacy parameter for the `external` variant; unset disables that variant.
+    pub epsilon: Option<f64>,
+    /// Largest absolute noise added to one count.
+    pub noise_bound: u64,
+    /// How long one snapshot answers queries.
+    pub snapshot_secs: u64,
+}
+
+impl Default for AggregateSettings {
+    fn default() -> Self {
+        Self {
+            min_tenants: DEFAULT_MIN_TENANTS,
+            min_events: DEFAULT_MIN_EVENTS,
+            epsilon: None,
+            noise_bound: DEFAULT_NOISE_BOUND,
+            snapshot_secs: DEFAULT_SNAPSHOT_SECS,
+        }
+    }
+}
+
+impl AggregateSettings {
+    /// Defaults, then `ACIP_STATS_AGG_MIN_TENANTS` / `_MIN_EVENTS` / `_EPSILON` / `_NOISE_BOUND`
+    /// / `_SNAPSHOT_SECS` overrides.
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_STATS_AGG_MIN_TENANTS").filter(|v| *v > 0) {
+            s.min_tenants = v as usize;
+        }
+        if let Some(v) = env_u64("ACIP_STATS_AGG_MIN_EVENTS").filter(|v| *v > 0) {
+            s.min_events = v;
+        }
+        s.epsilon = std::env::var("ACIP_STATS_AGG_EPSILON")
+            .ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|e| e.is_finite() && *e > 0.0);
+        if let Some(v) = env_u64("ACIP_STATS_AGG_NOISE_BOUND") {
+            s.noise_bound = v;
+        }
+        if let Some(v) = env_u64("ACIP_STATS_AGG_SNAPSHOT_SECS") {
+            s.snapshot_secs = v;
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    Policy,
+    SourceType,
+    Action,
+    Pattern,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 4] = [
+        Dimension::Policy,
+        Dimension::SourceType,
+        Dimension::Action,
+        Dimension::Pattern,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dimension::Policy => "policy",
+            Dimension::SourceType => "source_type",
+            Dimension::Action => "action",
+            Dimension::Pattern => "pattern",
+        }
+    }
+
+    /// Comma-separated dimension names, deduplicated into canonical order.
+    pub fn parse_list(s: &str) -> Result<Vec<Dimension>, AggregateError> {
+        let dims = s
+            .split(',')
+            .map(str::trim)
+            .filter(|d| !d.is_empty())
+            .map(|d| {
+                Dimension::ALL
+                    .into_iter()
+                    .find(|dim| dim.as_str() == d)
+                    .ok_or_else(|| AggregateError::InvalidGroupBy(d.to_string()))
+            })
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        if dims.is_empty() {
+            return Err(AggregateError::InvalidGroupBy(s.to_string()));
+        }
+        Ok(dims.into_iter().collect())
+    }
+
+    fn of(self, cell: &Cell) -> &str {
+        match self {
+            Dimension::Policy => &cell.policy,
+            Dimension::SourceType => &cell.source_type,
+            Dimension::Action => &cell.action,
+            Dimension::Pattern => cell.pattern.as_deref().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Variant {
+    /// Exact counts, after suppression.
+    #[default]
+    Internal,
+    /// Suppressed and noised, for sharing outside the platform team.
+    External,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum AggregateError {
+    #[error("invalid group_by {0:?}")]
+    InvalidGroupBy(String),
+    #[error("the external variant needs ACIP_STATS_AGG_EPSILON")]
+    NoiseDisabled,
+}
+
+impl IntoResponse for AggregateError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::InvalidGroupBy(value) => {
+                let known: Vec<&str> = Dimension::ALL.iter().map(|d| d.as_str()).collect();
+                introspection::json_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_group_by",
+                    json!({"value": value, "known": known}),
+                )
+                .into_response()
+            }
+            Self::NoiseDisabled => introspection::json_error(
+                StatusCode::BAD_REQUEST,
+                "aggregate_noise_disabled",
+                json!({"setting": "ACIP_STATS_AGG_EPSILON"}),
+            )
+            .into_response(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AggregateRow {
+    /// Dimension name -> value.
+    #[serde(flatten)]
+    pub key: BTreeMap<String, String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Totals {
+    pub decisions: u64,
+    /// Blocks in published cells; blocks in suppressed cells are part of `other`.
+    pub blocked: u64,
+    pub block_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct NoiseInfo {
+    pub epsilon: f64,
+    pub bound: u64,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct AggregateReport {
+    pub snapshot_id: String,
+    pub built_unix: u64,
+    pub days: u64,
+    pub group_by: Vec<Dimension>,
+    /// `decisions`, or `hits` when grouping by pattern.
+    pub metric: &'static str,
+    pub variant: Variant,
+    pub min_tenants: usize,
+    pub min_events: u64,
+    /// Counts carry noise (`external` variant).
+    pub approximate: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise: Option<NoiseInfo>,
+    /// Fleet-wide totals; absent when the whole window is below the thresholds.
+    pub totals: Option<Totals>,
+    /// Largest first.
+    pub rows: Vec<AggregateRow>,
+    /// Everything suppressed.
+    pub other: u64,
+}
+
+/// Suppressed cross-tenant counts for one window, fixed at build time.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub id: String,
+    pub built_unix: u64,
+    pub days: u64,
+    settings: AggregateSettings,
+    /// Published decision cells.
+    decisions: BTreeMap<Cell, u64>,
+    suppressed_decisions: u64,
+    /// Published pattern-hit cells.
+    patterns: BTreeMap<Cell, u64>,
+    suppressed_hits: u64,
+    totals: Option<(u64, u64)>,
+    /// Keys the noise of this snapshot; never published.
+    seed: String,
+}
+
+/// Sum per-tenant cells and split them into published cells and the suppressed total.
+fn suppress(
+    per_tenant: &BTreeMap<String, BTreeMap<Cell, u64>>,
+    settings: &AggregateSettings,
+) -> (BTreeMap<Cell, u64>, u64) {
+    let mut sums: BTreeMap<&Cell, (u64, usize)> = BTreeMap::new();
+    for cells in per_tenant.values() {
+        for (cell, n) in cells.iter().filter(|(_, n)| **n > 0) {
+            let e = sums.entry(cell).or_default();
+            e.0 += n;
+            e.1 += 1;
+        }
+    }
+    let mut published = BTreeMap::new();
+    let mut suppressed = 0;
+    for (cell, (count, tenants)) in sums {
+        let folded = cell.pattern.as_deref() == Some(OTHER_BUCKET);
+        if folded || tenants < settings.min_tenants || count < settings.min_events {
+            suppressed += count;
+        } else {
+            published.insert(cell.clone(), count);
+        }
+    }
+    (published, suppressed)
+}
+
+impl Snapshot {
+    pub fn build(cells: &TenantCells, settings: &AggregateSettings, days: u64, now: u64) -> Self {
+        let (decisions, suppressed_decisions) = suppress(&cells.decisions, settings);
+        let (patterns, suppressed_hits) = suppress(&cells.patterns, settings);
+
+        let tenants = cells
+            .decisions
+            .values()
+            .filter(|c| c.values().any(|n| *n > 0))
+            .count();
+        let total: u64 = decisions.values().sum::<u64>() + suppressed_decisions;
+        let totals = (tenants >= settings.min_tenants && total >= settings.min_events).then(|| {
+            let blocked = decisions
+                .iter()
+                .filter(|(c, _)| c.action == "block")
+                .map(|(_, n)| n)
+                .sum();
+            (total, blocked)
+        });
+
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let seed = hex::encode(Sha256::digest(format!(
+            "{nanos}:{}:{now}:{days}",
+            std::process::id()
+        )));
+        let id = hex::encode(Sha256::digest(format!("id:{seed}")))[..16].to_string();
+
+        Self {
+            id,
+            built_unix: now,
+            days,
+            settings: settings.clone(),
+            decisions,
+            suppressed_decisions,
+            patterns,
+            suppressed_hits,
+            totals,
+            seed,
+        }
+    }
+
+    /// Bounded Laplace noise for `label`, fixed for the life of this snapshot.
+    fn noise(&self, epsilon: f64, label: &str) -> i64 {
+        let digest = Sha256::digest(format!("{}:{label}", self.seed));
+        let bits = u64::from_be_bytes(digest[..8].try_into().expect("32-byte digest"));
+        // Uniform in (-0.5, 0.5).
+        let u = ((bits >> 11) as f64 + 0.5) / (1u64 << 53) as f64 - 0.5;
+        let scale = 1.0 / epsilon;
+        let x = -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln();
+        let bound = self.settings.noise_bound as f64;
+        x.clamp(-bound, bound).round() as i64
+    }
+
+    /// Group the published cells by `group_by` (from [`Dimension::parse_list`]).
+    pub fn query(
+        &self,
+        group_by: &[Dimension],
+        variant: Variant,
+    ) -> Result<AggregateReport, AggregateError> {
+        let epsilon = match variant {
+            Variant::Internal => None,
+            Variant::External => Some(self.settings.epsilon.ok_or(AggregateError::NoiseDisabled)?),
+        };
+        let by_pattern = group_by.contains(&Dimension::Pattern);
+        let (cells, suppressed, metric) = if by_pattern {
+            (&self.patterns, self.suppressed_hits, "hits")
+        } else {
+            (&self.decisions, self.suppressed_decisions, "decisions")
+        };
+
+        let mut groups: BTreeMap<Vec<&str>, u64> = BTreeMap::new();
+        for (cell, n) in cells {
+            let key = group_by.iter().map(|d| d.of(cell)).collect();
+            *groups.entry(key).or_default() += n;
+        }
+
+        let dims: Vec<&str> = group_by.iter().map(|d| d.as_str()).collect();
+        let noised = |count: u64, label: String| match epsilon {
+            Some(e) => count.saturating_add_signed(self.noise(e, &format!("{metric}|{label}"))),
+            None => count,
+        };
+
+        let mut rows: Vec<AggregateRow> = groups
+            .into_iter()
+            .map(|(values, count)| {
+                let label = format!("{}={}", dims.join(","), values.join(","));
+                AggregateRow {
+                    key: dims
+                        .iter()
+                        .zip(&values)
+                        .map(|(d, v)| (d.to_string(), v.to_string()))
+                        .collect(),
+                    count: noised(count, label),
+                }
+            })
+            .collect();
+        rows.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+
+        let totals = self.totals.map(|(decisions, blocked)| {
+            let decisions = noised(decisions, "total:decisions".to_string());
+            let blocked = noised(blocked, "total:blocked".to_string()).min(decisions);
+            Totals {
+                decisions,
+                blocked,
+                block_rate: if decisions == 0 {
+                    0.0
+                } else {
+                    blocked as f64 / decisions as f64
+                },
+            }
+        });
+
+        Ok(AggregateReport {
+            snapshot_id: self.id.clone(),
+            built_unix: self.built_unix,
+            days: self.days,
+            group_by: group_by.to_vec(),
+            metric,
+            variant,
+            min_tenants: self.settings.min_tenants,
+            min_events: self.settings.min_events,
+            approximate: epsilon.is_some(),
+            noise: epsilon.map(|epsilon| NoiseInfo {
+                epsilon,
+                bound: self.settings.noise_bound,
+            }),
+            totals,
+            rows,
+            other: noised(suppressed, "other".to_string()),
+        })
+    }
+}
+
+fn default_aggregate_days() -> u64 {
+    7
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateQuery {
+    #[serde(default = "default_aggregate_days")]
+    pub days: u64,
+    /// Comma-separated dimensions; defaults to `policy`.
+    pub group_by: Option<String>,
+    #[serde(default)]
+    pub variant: Variant,
+}
+
+/// `GET /v1/acip/stats/aggregate`
+pub async fn get_aggregate(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<AggregateQuery>,
+) -> Response {
+    let report =
+        Dimension::parse_list(q.group_by.as_deref().unwrap_or("policy")).and_then(|group_by| {
+            state
+                .stats
+                .aggregate_snapshot(q.days)
+                .query(&group_by, q.variant)
+        });
+    match report {
+        Ok(report) => (StatusCode::OK, Json(report)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
@@ -18,11 +18,24 @@ pub async fn get_status(State(state): State<Arc<AppState>>) -> impl IntoResponse
         "tmpdir": std::env::var("ACIP_EXTRACTOR_TMPDIR").ok(),
         // Path is not a secret but could be sensitive; include only if explicitly set.
         "bin": std::env::var("ACIP_EXTRACTOR_BIN").ok(),
+        "failures": crate::extract::failure_counts(),
+        "breaker": state.extractor_health.snapshot(),
     });
 
+    // File-backed stores and the format version of each file.
+    let storage: Vec<_> = [
+        state.reputation.storage(),
+        state.stats.storage(),
+        state.slow_requests.storage(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+
     let v = json!({
         "ok": true,
-        "version": env!("CARGO_PKG_VERSION"),
+        "version": crate::build_info::current(),
+        "read_only": state.read_only,
         "sentry_mode": std::env::var("ACIP_SENTRY_MODE").unwrap_or_else(|_| "live".to_string()),
         "policy": {
             "head": state.policy.head,
@@ -31,6 +44,32 @@ pub async fn get_status(State(state): State<Arc<AppState>>) -> impl IntoResponse
         },
         "policies": policies,
         "extractor": extractor,
+        "redaction": {
+            "rules": state.redaction.rule_count(),
+            "counts": state.redaction.counts(),
+        },
+        "drain": state.drain.snapshot(),
+        "client_disconnects": state.disconnects.snapshot(),
+        "tmpdir": state.tmp.snapshot(),
+        "environment": &*state.environment,
+        "uploads": state.uploads.snapshot(),
+        "model_versions": state.model_versions.snapshot(),
+        "loop_protection": state.loop_guard.snapshot(),
+        "feeds": state.feeds.snapshot(),
+        "jobs": state.jobs.snapshot(),
+        "slow_requests": state.slow_requests.snapshot(),
+        "reputation": state.reputation.cardinality(),
+        "siem": state.siem.snapshot(),
+        "notify": state.notify.snapshot(),
+        "chunking": state.chunks.snapshot(),
+        "probing": state.probes.snapshot(),
+        "hashing": state.hashing.snapshot(),
+        "blocking_pool": state.blocking.snapshot(),
+        "experiments": state.experiments.snapshot(),
+        "cache_bypass": state.verdicts.bypasses().snapshot(),
+        "patterns": state.patterns.snapshot(),
+        "deprecations_in_use": crate::deprecations::in_use(),
+        "storage": storage,
     });
 
     (StatusCode::OK, Json(v)).into_response()
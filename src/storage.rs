@@ -0,0 +1,487 @@
+//! Versioned on-disk formats for the file-backed stores, and the migrations between versions.
+//!
+//! Every store file is a JSON object carrying its format name and version next to the store's
+//! own fields (`"format": "reputation", "format_version": 2, "min_release": "0.1.0", ...`). A
+//! file without `format_version` predates versioning and is version 1.
+//!
+//! Opening a store file ([`StoreFile::open`]) runs the registered [`MIGRATIONS`] for its format,
+//! one version at a time, before the store sees the data. The file on disk is left as it was and
+//! a byte-exact copy is kept next to it (`<file>.v<N>.bak`) until the store's first successful
+//! write in the new format, which removes it. A file from a newer version than this build reads
+//! is refused with the version and the release needed to read it; nothing is changed.
+//!
+//! `acipctl storage check` runs [`check`] over the configured store files: versions and pending
+//! migrations, without applying anything.
+
+use crate::reputation;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+};
+
+/// One on-disk format.
+#[derive(Debug)]
+pub struct Format {
+    pub name: &'static str,
+    /// Environment variable selecting the backend (`file:<path>` for this format).
+    pub env: &'static str,
+    /// The version this build writes.
+    pub current: u32,
+    /// Written indented (hand-inspected files) rather than compact.
+    pub pretty: bool,
+}
+
+impl Format {
+    /// The file named by this format's backend variable, when it selects `file:<path>`.
+    pub fn configured_path(&self) -> Option<PathBuf> {
+        let spec = std::env::var(self.env).ok()?;
+        spec.strip_prefix("file:").map(PathBuf::from)
+    }
+}
+
+/// Reputation records. Version 2 fills in `first_seen_unix`.
+pub const REPUTATION: Format = Format {
+    name: "reputation",
+    env: "ACIP_REPUTATION_STORE",
+    current: 2,
+    pretty: true,
+};
+
+/// Per-day decision counters.
+pub const STATS: Format = Format {
+    name: "stats",
+    env: "ACIP_STATS_STORE",
+    current: 1,
+    pretty: false,
+};
+
+/// Slow request timings.
+pub const SLOW_REQUESTS: Format = Format {
+    name: "slow_requests",
+    env: "ACIP_SLOW_REQUEST_STORE",
+    current: 1,
+    pretty: false,
+};
+
+pub const FORMATS: [&Format; 3] = [&REPUTATION, &STATS, &SLOW_REQUESTS];
+
+/// Turns a store file's data (envelope fields removed) from version `from` into `from + 1`.
+pub struct Migration {
+    pub format: &'static str,
+    pub from: u32,
+    pub summary: &'static str,
+    pub apply: fn(Value) -> anyhow::Result<Value>,
+}
+
+/// Every migration, by format and starting version.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    format: "reputation",
+    from: 1,
+    summary: "fill in first_seen_unix on every record",
+    apply: reputation::migrate_v1_to_v2,
+}];
+
+fn migration(format: &str, from: u32) -> Option<&'static Migration> {
+    MIGRATIONS
+        .iter()
+        .find(|m| m.format == format && m.from == from)
+}
+
+const FORMAT_KEY: &str = "format";
+const VERSION_KEY: &str = "format_version";
+/// The release that wrote the file, which reads its version; an older build names it when it
+/// refuses the file.
+const RELEASE_KEY: &str = "min_release";
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("{path}: unreadable {format} store: {reason}")]
+    Corrupt {
+        path: String,
+        format: &'static str,
+        reason: String,
+    },
+    #[error("{path}: holds a {found:?} store, expected {expected:?}")]
+    WrongFormat {
+        path: String,
+        found: String,
+        expected: &'static str,
+    },
+    #[error(
+        "{path}: {format} format version {version} is newer than this build reads (up to \
+         {supported}); it needs acip-sidecar {min_release} or later"
+    )]
+    TooNew {
+        path: String,
+        format: &'static str,
+        version: u32,
+        supported: u32,
+        min_release: String,
+    },
+    #[error("{path}: no migration for {format} format version {from}")]
+    NoMigration {
+        path: String,
+        format: &'static str,
+        from: u32,
+    },
+    #[error("{path}: migrating {format} from version {from}: {reason}")]
+    Migration {
+        path: String,
+        format: &'static str,
+        from: u32,
+        reason: String,
+    },
+    #[error("{path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+/// The store's data and version as found on disk.
+struct Found {
+    version: u32,
+    data: Value,
+}
+
+fn read(path: &Path, format: &'static Format) -> Result<Option<(Vec<u8>, Found)>, StorageError> {
+    let display = path.display().to_string();
+    let raw = match fs::read(path) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(StorageError::Io {
+                path: display,
+                source,
+            })
+        }
+    };
+    let corrupt = |reason: String| StorageError::Corrupt {
+        path: display.clone(),
+        format: format.name,
+        reason,
+    };
+    let mut obj: Map<String, Value> = match serde_json::from_slice(&raw) {
+        Ok(Value::Object(obj)) => obj,
+        Ok(_) => return Err(corrupt("not a JSON object".to_string())),
+        Err(e) => return Err(corrupt(e.to_string())),
+    };
+    let version = match obj.remove(VERSION_KEY) {
+        None => 1,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| corrupt(format!("bad {VERSION_KEY} {v}")))?,
+    };
+    if let Some(found) = obj.remove(FORMAT_KEY) {
+        if found.as_str() != Some(format.name) {
+            return Err(StorageError::WrongFormat {
+                path: display,
+                found: found
+                    .as_str()
+                    .map_or_else(|| found.to_string(), str::to_string),
+                expected: format.name,
+            });
+        }
+    }
+    let min_release = obj.remove(RELEASE_KEY);
+    if version > format.current {
+        return Err(StorageError::TooNew {
+            path: display,
+            format: format.name,
+            version,
+            supported: format.current,
+            min_release: min_release
+                .as_ref()
+                .and_then(Value::as_str)
+                .unwrap_or("(a newer release)")
+                .to_string(),
+        });
+    }
+    Ok(Some((
+        raw,
+        Found {
+            version,
+            data: Value::Object(obj),
+        },
+    )))
+}
+
+/// Where the pre-migration copy of a version-`from` file is kept.
+pub fn backup_path(path: &Path, from: u32) -> PathBuf {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("store.json");
+    path.with_file_name(format!("{name}.v{from}.bak"))
+}
+
+/// `data` as a store file of `format`'s current version.
+pub fn encode<T: Serialize>(format: &Format, data: &T) -> anyhow::Result<Vec<u8>> {
+    let mut obj = match serde_json::to_value(data)? {
+        Value::Object(obj) => obj,
+        other => anyhow::bail!("{} store data is not an object: {other}", format.name),
+    };
+    obj.insert(FORMAT_KEY.to_string(), Value::from(format.name));
+    obj.insert(VERSION_KEY.to_string(), Value::from(format.current));
+    obj.insert(
+        RELEASE_KEY.to_string(),
+        Value::from(env!("CARGO_PKG_VERSION")),
+    );
+    let obj = Value::Object(obj);
+    Ok(if format.pretty {
+        serde_json::to_vec_pretty(&obj)?
+    } else {
+        serde_json::to_vec(&obj)?
+    })
+}
+
+/// A store's file: where it is, the version found there and any pending backup.
+#[derive(Debug)]
+pub struct StoreFile {
+    path: PathBuf,
+    format: &'static Format,
+    /// Version of the file on disk; the current version once the store has written it.
+    on_disk: AtomicU32,
+    migrated_from: Option<u32>,
+    backup: Mutex<Option<PathBuf>>,
+}
+
+/// What [`StoreFile::open`] found.
+pub struct Opened<T> {
+    pub file: StoreFile,
+    /// `None` when there was no file yet.
+    pub data: Option<T>,
+}
+
+impl StoreFile {
+    /// A file that does not exist yet (or is being replaced): it is written in the current
+    /// version.
+    pub fn new(path: impl Into<PathBuf>, format: &'static Format) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            on_disk: AtomicU32::new(format.current),
+            migrated_from: None,
+            backup: Mutex::new(None),
+        }
+    }
+
+    /// Read `path`, migrating older versions to the current one. With `read_only` no backup is
+    /// made (nothing will be written). An unparsable file is [`StorageError::Corrupt`]; a newer
+    /// one is [`StorageError::TooNew`].
+    pub fn open<T: DeserializeOwned>(
+        path: impl Into<PathBuf>,
+        format: &'static Format,
+        read_only: bool,
+    ) -> Result<Opened<T>, StorageError> {
+        let path = path.into();
+        let Some((raw, found)) = read(&path, format)? else {
+            return Ok(Opened {
+                file: Self::new(path, format),
+                data: None,
+            });
+        };
+        let display = path.display().to_string();
+        let mut data = found.data;
+        let mut backup = None;
+        if found.version < format.current {
+            if !read_only {
+                let bak = backup_path(&path, found.version);
+                // An earlier interrupted run's copy is the older one; keep it.
+                if !bak.exists() {
+                    reputation::write_private_atomic(&bak, &raw).map_err(|e| {
+                        StorageError::Migration {
+                            path: display.clone(),
+                            format: format.name,
+                            from: found.version,
+                            reason: format!("backup to {}: {e:#}", bak.display()),
+                        }
+                    })?;
+                }
+                backup = Some(bak);
+            }
+            for from in found.version..format.current {
+                let m = migration(format.name, from).ok_or_else(|| StorageError::NoMigration {
+                    path: display.clone(),
+                    format: format.name,
+                    from,
+                })?;
+                data = (m.apply)(data).map_err(|e| StorageError::Migration {
+                    path: display.clone(),
+                    format: format.name,
+                    from,
+                    reason: format!("{e:#}"),
+                })?;
+            }
+            tracing::info!(
+                path = %path.display(),
+                format = format.name,
+                from = found.version,
+                to = format.current,
+                "Migrated store file; written in the new format on the next change"
+            );
+        }
+        let data = serde_json::from_value(data).map_err(|e| StorageError::Corrupt {
+            path: display,
+            format: format.name,
+            reason: e.to_string(),
+        })?;
+        Ok(Opened {
+            file: Self {
+                path,
+                format,
+                on_disk: AtomicU32::new(found.version),
+                migrated_from: (found.version < format.current).then_some(found.version),
+                backup: Mutex::new(backup),
+            },
+            data: Some(data),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `data` in the current version, then drop the pre-migration backup.
+    pub fn write<T: Serialize>(&self, data: &T) -> anyhow::Result<()> {
+        let raw = encode(self.format, data)?;
+        reputation::write_private_atomic(&self.path, &raw)?;
+        self.on_disk.store(self.format.current, Ordering::Relaxed);
+        if let Some(bak) = self.backup.lock().unwrap().take() {
+            if let Err(err) = fs::remove_file(&bak) {
+                tracing::warn!(error = %err, path = %bak.display(), "Failed to remove store backup");
+            }
+        }
+        Ok(())
+    }
+
+    /// For `/v1/acip/status`.
+    pub fn info(&self) -> StoreInfo {
+        StoreInfo {
+            format: self.format.name,
+            path: self.path.display().to_string(),
+            version: self.on_disk.load(Ordering::Relaxed),
+            supported: self.format.current,
+            migrated_from: self.migrated_from,
+            backup: self
+                .backup
+                .lock()
+                .unwrap()
+                .as_ref()
+                .map(|p| p.display().to_string()),
+        }
+    }
+}
+
+/// A store file as reported by `/v1/acip/status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoreInfo {
+    pub format: &'static str,
+    pub path: String,
+    /// Version of the file on disk.
+    pub version: u32,
+    /// Version this build writes.
+    pub supported: u32,
+    /// Version the file was migrated from at startup.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub migrated_from: Option<u32>,
+    /// Pre-migration copy, until the first write in the new version.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// No file yet; it will be created in the current version.
+    Missing,
+    Current,
+    /// Opening it will migrate it.
+    MigrationPending,
+    /// Written by a newer release; this build refuses to open it.
+    TooNew,
+    /// Unparsable, of another format, or with a missing migration.
+    Unreadable,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingMigration {
+    pub from: u32,
+    pub to: u32,
+    pub summary: &'static str,
+}
+
+/// One store file as seen by `acipctl storage check`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckReport {
+    pub format: &'static str,
+    pub path: String,
+    pub status: CheckStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+    pub supported: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub pending: Vec<PendingMigration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The version of `path` and the migrations opening it would run. Reads only.
+pub fn check(path: &Path, format: &'static Format) -> CheckReport {
+    let mut report = CheckReport {
+        format: format.name,
+        path: path.display().to_string(),
+        status: CheckStatus::Missing,
+        version: None,
+        supported: format.current,
+        pending: vec![],
+        error: None,
+    };
+    match read(path, format) {
+        Ok(None) => {}
+        Ok(Some((_, found))) => {
+            report.version = Some(found.version);
+            report.status = CheckStatus::Current;
+            for from in found.version..format.current {
+                report.status = CheckStatus::MigrationPending;
+                match migration(format.name, from) {
+                    Some(m) => report.pending.push(PendingMigration {
+                        from,
+                        to: from + 1,
+                        summary: m.summary,
+                    }),
+                    None => {
+                        report.status = CheckStatus::Unreadable;
+                        report.error = Some(
+                            StorageError::NoMigration {
+                                path: report.path.clone(),
+                                format: format.name,
+                                from,
+                            }
+                            .to_string(),
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            if let StorageError::TooNew { version, .. } = &e {
+                report.version = Some(*version);
+                report.status = CheckStatus::TooNew;
+            } else {
+                report.status = CheckStatus::Unreadable;
+            }
+            report.error = Some(e.to_string());
+        }
+    }
+    report
+}
@@ -0,0 +1,686 @@
+//! W3C trace context: join the caller's distributed trace and carry it to outbound calls.
+//!
+//! An incoming `traceparent` (and `tracestate`) header makes the sidecar's request span a child
+//! of the caller's span; without one, or with a malformed one, the request starts a new root
+//! trace. The request span and one child span per pipeline stage (see
+//! [`crate::slow_requests::Stage`]) are built from the run's [`Timing`] once the response is
+//! ready, with the `request_id` as the `acip.request_id` attribute so a trace can be matched to
+//! the ingest response, slow-request records and incidents.
+//!
+//! The context is propagated as `traceparent`/`tracestate` on the requests the sidecar makes
+//! itself: model provider calls (as children of the `model` stage span), async job webhooks and
+//! threat feed refreshes.
+//!
+//! Spans go to a [`SpanExporter`]. With the `otel` feature and `[telemetry] otlp_endpoint`
+//! configured they are sent as OTLP/HTTP JSON; otherwise export is a no-op. Propagation does not
+//! depend on export: downstream services see the caller's trace either way.
+
+use crate::config::TelemetryConfig;
+use crate::sentry::ModelCall;
+use crate::slow_requests::{RunInfo, Stage, Timing};
+use axum::http::{HeaderMap, HeaderValue};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+pub const TRACEPARENT: &str = "traceparent";
+pub const TRACESTATE: &str = "tracestate";
+
+/// Longest `tracestate` passed on; longer values are dropped (the spec allows 512 characters).
+pub const MAX_TRACESTATE_LEN: usize = 512;
+
+pub const DEFAULT_SERVICE_NAME: &str = "acip-sidecar";
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId(pub [u8; 16]);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId(pub [u8; 8]);
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceId({self})")
+    }
+}
+
+impl fmt::Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl fmt::Debug for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpanId({self})")
+    }
+}
+
+/// Not cryptographic: ids only need to be unique, not unguessable.
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut h = std::collections::hash_map::RandomState::new().build_hasher();
+    h.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    h.write_u128(unix_nanos(SystemTime::now()));
+    h.finish()
+}
+
+impl TraceId {
+    pub fn random() -> Self {
+        loop {
+            let mut id = [0u8; 16];
+            id[..8].copy_from_slice(&random_u64().to_be_bytes());
+            id[8..].copy_from_slice(&random_u64().to_be_bytes());
+            if id != [0; 16] {
+                return Self(id);
+            }
+        }
+    }
+}
+
+impl SpanId {
+    pub fn random() -> Self {
+        loop {
+            let id = random_u64().to_be_bytes();
+            if id != [0; 8] {
+                return Self(id);
+            }
+        }
+    }
+}
+
+/// Lowercase hex of exactly `N` bytes, not all zero.
+fn parse_id<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != 2 * N || !is_lower_hex(s) {
+        return None;
+    }
+    let mut out = [0u8; N];
+    hex::decode_to_slice(s, &mut out).ok()?;
+    (out != [0; N]).then_some(out)
+}
+
+/// One span's position in a trace, as carried by `traceparent`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    /// `trace-flags`; bit 0 is "sampled".
+    pub flags: u8,
+    /// Vendor state, passed on unchanged.
+    pub tracestate: Option<String>,
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` value. Returns `None` for anything the W3C spec says to ignore:
+    /// wrong lengths, uppercase hex, version `ff`, all-zero ids, or a version-00 header with
+    /// trailing data. Later versions may append fields after a `-`.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let s = traceparent.trim();
+        if s.len() < 55 || !s.is_ascii() {
+            return None;
+        }
+        let version = parse_hex_byte(&s[..2])?;
+        if version == 0xff || (version == 0 && s.len() != 55) {
+            return None;
+        }
+        if s.len() > 55 && s.as_bytes()[55] != b'-' {
+            return None;
+        }
+        let b = s.as_bytes();
+        if b[2] != b'-' || b[35] != b'-' || b[52] != b'-' {
+            return None;
+        }
+        Some(Self {
+            trace_id: TraceId(parse_id(&s[3..35])?),
+            span_id: SpanId(parse_id(&s[36..52])?),
+            flags: parse_hex_byte(&s[53..55])?,
+            tracestate: tracestate
+                .map(str::trim)
+                .filter(|t| !t.is_empty() && t.len() <= MAX_TRACESTATE_LEN)
+                .map(str::to_string),
+        })
+    }
+
+    /// The caller's context. A missing, repeated or malformed `traceparent` yields `None`;
+    /// several `tracestate` headers are joined as one list.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut parents = headers.get_all(TRACEPARENT).iter();
+        let parent = parents.next()?.to_str().ok()?;
+        if parents.next().is_some() {
+            return None;
+        }
+        let states: Vec<&str> = headers
+            .get_all(TRACESTATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        let state = (!states.is_empty()).then(|| states.join(","));
+        Self::parse(parent, state.as_deref())
+    }
+
+    /// A new sampled trace.
+    pub fn root() -> Self {
+        Self {
+            trace_id: TraceId::random(),
+            span_id: SpanId::random(),
+            flags: 0x01,
+            tracestate: None,
+        }
+    }
+
+    /// A new span in the same trace.
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: SpanId::random(),
+            ..self.clone()
+        }
+    }
+
+    pub fn sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+
+    /// Set `traceparent` (and `tracestate`) on `headers`, replacing any already there.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        if let Ok(v) = HeaderValue::from_str(&self.traceparent()) {
+            headers.insert(TRACEPARENT, v);
+        }
+        headers.remove(TRACESTATE);
+        if let Some(v) = self
+            .tracestate
+            .as_deref()
+            .and_then(|s| HeaderValue::from_str(s).ok())
+        {
+            headers.insert(TRACESTATE, v);
+        }
+    }
+
+    /// `request` with this context's headers.
+    pub fn apply(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let mut headers = HeaderMap::new();
+        self.inject(&mut headers);
+        request.headers(headers)
+    }
+}
+
+fn is_lower_hex(s: &str) -> bool {
+    s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn parse_hex_byte(s: &str) -> Option<u8> {
+    if s.len() != 2 || !is_lower_hex(s) {
+        return None;
+    }
+    u8::from_str_radix(s, 16).ok()
+}
+
+/// Copy `traceparent`/`tracestate` from `headers` onto an outbound `request`. Used by the model
+/// provider clients, whose headers already carry the model stage's context.
+pub fn forward(request: reqwest::RequestBuilder, headers: &HeaderMap) -> reqwest::RequestBuilder {
+    match TraceContext::from_headers(headers) {
+        Some(ctx) => ctx.apply(request),
+        None => request,
+    }
+}
+
+/// The trace of one ingest run: the request span and the span ids its stages will get.
+#[derive(Debug, Clone)]
+pub struct RequestTrace {
+    /// The sidecar's request span.
+    pub context: TraceContext,
+    /// The caller's span, when the request came with a valid `traceparent`.
+    pub parent: Option<SpanId>,
+    stages: Vec<(Stage, SpanId)>,
+}
+
+impl Default for RequestTrace {
+    /// A new root trace.
+    fn default() -> Self {
+        Self {
+            context: TraceContext::root(),
+            parent: None,
+            stages: Vec::new(),
+        }
+    }
+}
+
+impl RequestTrace {
+    /// A child of the caller's context, or a new root trace when there is none.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        match TraceContext::from_headers(headers) {
+            Some(parent) => Self {
+                context: parent.child(),
+                parent: Some(parent.span_id),
+                stages: Vec::new(),
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// The context of `stage`'s span, for calls made during that stage. The same stage always
+    /// gets the same span.
+    pub fn stage_context(&mut self, stage: Stage) -> TraceContext {
+        let span_id = match self.stages.iter().find(|(s, _)| *s == stage) {
+            Some((_, id)) => *id,
+            None => {
+                let id = SpanId::random();
+                self.stages.push((stage, id));
+                id
+            }
+        };
+        TraceContext {
+            span_id,
+            ..self.context.clone()
+        }
+    }
+
+    /// `headers` with `stage`'s context in place of the caller's.
+    pub fn outbound_headers(&mut self, headers: &HeaderMap, stage: Stage) -> HeaderMap {
+        let mut out = headers.clone();
+        self.stage_context(stage).inject(&mut out);
+        out
+    }
+
+    fn stage_span_id(&self, stage: Stage) -> Option<SpanId> {
+        self.stages
+            .iter()
+            .find(|(s, _)| *s == stage)
+            .map(|(_, id)| *id)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanKind {
+    /// Handling a request from a caller.
+    Server,
+    /// A request the sidecar made.
+    Client,
+    Internal,
+}
+
+/// A finished span.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpanRecord {
+    pub trace_id: String,
+    pub span_id: String,
+    pub parent_span_id: Option<String>,
+    pub name: String,
+    pub kind: SpanKind,
+    pub start_unix_nanos: u128,
+    pub end_unix_nanos: u128,
+    pub attributes: BTreeMap<String, Value>,
+    /// The operation failed.
+    pub error: bool,
+}
+
+fn unix_nanos(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()
+}
+
+/// Where finished spans go.
+pub trait SpanExporter: Send + Sync {
+    fn export(&self, spans: Vec<SpanRecord>);
+}
+
+/// Keeps every span, for tests.
+#[derive(Default)]
+pub struct InMemoryExporter {
+    spans: Mutex<Vec<SpanRecord>>,
+}
+
+impl InMemoryExporter {
+    pub fn spans(&self) -> Vec<SpanRecord> {
+        self.spans.lock().unwrap().clone()
+    }
+}
+
+impl SpanExporter for InMemoryExporter {
+    fn export(&self, spans: Vec<SpanRecord>) {
+        self.spans.lock().unwrap().extend(spans);
+    }
+}
+
+/// Span export. The default exports nothing.
+#[derive(Default, Clone)]
+pub struct Telemetry {
+    exporter: Option<Arc<dyn SpanExporter>>,
+}
+
+impl Telemetry {
+    pub fn new(exporter: Arc<dyn SpanExporter>) -> Self {
+        Self {
+            exporter: Some(exporter),
+        }
+    }
+
+    /// OTLP export to `[telemetry] otlp_endpoint` when the `otel` feature is built in; a no-op
+    /// otherwise. The endpoint must be an `http(s)://` URL either way.
+    pub fn from_config(
+        cfg: Option<&TelemetryConfig>,
+        http: reqwest::Client,
+    ) -> anyhow::Result<Self> {
+        let Some(endpoint) = cfg.and_then(|c| c.otlp_endpoint.as_deref()) else {
+            return Ok(Self::default());
+        };
+        let endpoint = endpoint.trim();
+        match url::Url::parse(endpoint) {
+            Ok(u) if matches!(u.scheme(), "http" | "https") && u.host().is_some() => {}
+            _ => anyhow::bail!("telemetry.otlp_endpoint {endpoint:?} must be an http(s) URL"),
+        }
+        #[cfg(feature = "otel")]
+        {
+            let service = cfg
+                .and_then(|c| c.service_name.clone())
+                .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_string());
+            tracing::info!(endpoint, service = %service, "exporting spans over OTLP");
+            Ok(Self::new(Arc::new(otlp::OtlpExporter::new(
+                http, endpoint, service,
+            ))))
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            let _ = http;
+            tracing::warn!(
+                endpoint,
+                "telemetry.otlp_endpoint is set but this build lacks the `otel` feature; spans are not exported"
+            );
+            Ok(Self::default())
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.exporter.is_some()
+    }
+
+    pub fn export(&self, spans: Vec<SpanRecord>) {
+        if let Some(e) = &self.exporter {
+            if !spans.is_empty() {
+                e.export(spans);
+            }
+        }
+    }
+
+    /// A span for an outbound call or background task, a child of `parent` or a new root.
+    pub fn span(&self, parent: Option<&TraceContext>, name: &str, kind: SpanKind) -> ActiveSpan {
+        ActiveSpan {
+            context: parent.map_or_else(TraceContext::root, TraceContext::child),
+            parent: parent.map(|p| p.span_id),
+            name: name.to_string(),
+            kind,
+            started: SystemTime::now(),
+            attributes: BTreeMap::new(),
+            error: false,
+        }
+    }
+
+    /// Export the request span of a finished ingest run, its stage spans and one span per
+    /// model call (children of the `model` stage span).
+    pub fn record_ingest(&self, timing: &Timing, run: &RunInfo<'_>) {
+        if self.is_enabled() {
+            self.export(ingest_spans(timing, run));
+        }
+    }
+}
+
+/// The spans of a finished ingest run; see [`Telemetry::record_ingest`].
+pub fn ingest_spans(timing: &Timing, run: &RunInfo<'_>) -> Vec<SpanRecord> {
+    let trace = &timing.trace;
+    let trace_id = trace.context.trace_id.to_string();
+    let request_span = trace.context.span_id.to_string();
+    let start = unix_nanos(timing.started_at());
+    let at = |offset: Duration| start + offset.as_nanos();
+
+    let mut attributes = BTreeMap::from([
+        ("acip.actor".to_string(), json!(run.actor)),
+        ("acip.policy".to_string(), json!(run.policy)),
+        ("acip.source_type".to_string(), json!(run.source_type)),
+        ("acip.input_bytes".to_string(), json!(run.input_bytes)),
+        (
+            "http.response.status_code".to_string(),
+            json!(run.http_status.as_u16()),
+        ),
+    ]);
+    if let Some(id) = &timing.request_id {
+        attributes.insert("acip.request_id".to_string(), json!(id));
+    }
+    if let Some(chars) = timing.extracted_chars {
+        attributes.insert("acip.extracted_chars".to_string(), json!(chars));
+    }
+    let mut spans = vec![SpanRecord {
+        trace_id: trace_id.clone(),
+        span_id: request_span.clone(),
+        parent_span_id: trace.parent.map(|p| p.to_string()),
+        name: "acip.ingest".to_string(),
+        kind: SpanKind::Server,
+        start_unix_nanos: start,
+        end_unix_nanos: at(timing.total()),
+        attributes,
+        error: run.http_status.is_server_error(),
+    }];
+
+    let mut used = Vec::new();
+    for lap in timing.laps() {
+        // A stage's first lap takes the span id handed out to its outbound calls.
+        let id = match trace.stage_span_id(lap.stage) {
+            Some(id) if !used.contains(&lap.stage) => id,
+            _ => SpanId::random(),
+        };
+        used.push(lap.stage);
+        spans.push(SpanRecord {
+            trace_id: trace_id.clone(),
+            span_id: id.to_string(),
+            parent_span_id: Some(request_span.clone()),
+            name: format!("acip.stage.{}", lap.stage.as_str()),
+            kind: SpanKind::Internal,
+            start_unix_nanos: at(lap.offset),
+            end_unix_nanos: at(lap.offset + lap.duration),
+            attributes: BTreeMap::from([
+                ("acip.stage".to_string(), json!(lap.stage.as_str())),
+                ("acip.pool".to_string(), json!(lap.stage.pool())),
+                (
+                    "acip.ms".to_string(),
+                    json!(lap.duration.as_secs_f64() * 1000.0),
+                ),
+            ]),
+            error: false,
+        });
+        if lap.stage == Stage::Model {
+            let mut call_start = at(lap.offset);
+            for call in timing.recorded_model_calls() {
+                let span = model_call_span(&trace_id, &id, call, call_start);
+                call_start = span.end_unix_nanos;
+                spans.push(span);
+            }
+        }
+    }
+    spans
+}
+
+/// Model calls are timed but not timestamped; they are laid out one after another from the
+/// start of the model stage.
+fn model_call_span(trace_id: &str, parent: &SpanId, call: &ModelCall, start: u128) -> SpanRecord {
+    SpanRecord {
+        trace_id: trace_id.to_string(),
+        span_id: SpanId::random().to_string(),
+        parent_span_id: Some(parent.to_string()),
+        name: "acip.model_call".to_string(),
+        kind: SpanKind::Client,
+        start_unix_nanos: start,
+        end_unix_nanos: start + (call.ms * 1_000_000.0) as u128,
+        attributes: BTreeMap::from([
+            ("acip.model".to_string(), json!(call.model)),
+            ("acip.ms".to_string(), json!(call.ms)),
+            ("acip.model.tier".to_string(), json!(call.tier)),
+            (
+                "acip.model.consistency_check".to_string(),
+                json!(call.consistency_check),
+            ),
+            (
+                "acip.model.prompt_tokens".to_string(),
+                json!(call.prompt_tokens),
+            ),
+            (
+                "acip.model.prompt_tokens_estimated".to_string(),
+                json!(call.prompt_tokens_estimated),
+            ),
+            (
+                "acip.model.context_retry".to_string(),
+                json!(call.context_retry),
+            ),
+        ]),
+        error: !call.ok,
+    }
+}
+
+/// A span in progress; see [`Telemetry::span`].
+pub struct ActiveSpan {
+    context: TraceContext,
+    parent: Option<SpanId>,
+    name: String,
+    kind: SpanKind,
+    started: SystemTime,
+    attributes: BTreeMap<String, Value>,
+    error: bool,
+}
+
+impl ActiveSpan {
+    /// The context to propagate to calls made under this span.
+    pub fn context(&self) -> &TraceContext {
+        &self.context
+    }
+
+    pub fn attribute(&mut self, key: &str, value: Value) {
+        self.attributes.insert(key.to_string(), value);
+    }
+
+    pub fn fail(&mut self) {
+        self.error = true;
+    }
+
+    pub fn end(self, telemetry: &Telemetry) {
+        telemetry.export(vec![SpanRecord {
+            trace_id: self.context.trace_id.to_string(),
+            span_id: self.context.span_id.to_string(),
+            parent_span_id: self.parent.map(|p| p.to_string()),
+            name: self.name,
+            kind: self.kind,
+            start_unix_nanos: unix_nanos(self.started),
+            end_unix_nanos: unix_nanos(SystemTime::now()),
+            attributes: self.attributes,
+            error: self.error,
+        }]);
+    }
+}
+
+#[cfg(feature = "otel")]
+pub mod otlp {
+    //! OTLP/HTTP export with the JSON encoding (`POST {endpoint}/v1/traces`).
+
+    use super::{SpanExporter, SpanKind, SpanRecord};
+    use serde_json::{json, Value};
+
+    pub struct OtlpExporter {
+        http: reqwest::Client,
+        url: String,
+        service_name: String,
+    }
+
+    impl OtlpExporter {
+        pub fn new(http: reqwest::Client, endpoint: &str, service_name: String) -> Self {
+            Self {
+                http,
+                url: format!("{}/v1/traces", endpoint.trim_end_matches('/')),
+                service_name,
+            }
+        }
+    }
+
+    fn attribute_value(v: &Value) -> Value {
+        match v {
+            Value::Bool(b) => json!({ "boolValue": b }),
+            Value::Number(n) if n.is_f64() => json!({ "doubleValue": n }),
+            Value::Number(n) => json!({ "intValue": n.to_string() }),
+            Value::String(s) => json!({ "stringValue": s }),
+            other => json!({ "stringValue": other.to_string() }),
+        }
+    }
+
+    /// An `ExportTraceServiceRequest` for `spans`.
+    pub fn request_body(service_name: &str, spans: &[SpanRecord]) -> Value {
+        let spans: Vec<Value> = spans
+            .iter()
+            .map(|s| {
+                let kind = match s.kind {
+                    SpanKind::Internal => 1,
+                    SpanKind::Server => 2,
+                    SpanKind::Client => 3,
+                };
+                let attributes: Vec<Value> = s
+                    .attributes
+                    .iter()
+                    .map(|(k, v)| json!({ "key": k, "value": attribute_value(v) }))
+                    .collect();
+                let mut span = json!({
+                    "traceId": s.trace_id,
+                    "spanId": s.span_id,
+                    "name": s.name,
+                    "kind": kind,
+                    "startTimeUnixNano": s.start_unix_nanos.to_string(),
+                    "endTimeUnixNano": s.end_unix_nanos.to_string(),
+                    "attributes": attributes,
+                    "status": { "code": if s.error { 2 } else { 0 } },
+                });
+                if let Some(p) = &s.parent_span_id {
+                    span["parentSpanId"] = json!(p);
+                }
+                span
+            })
+            .collect();
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [{ "key": "service.name", "value": { "stringValue": service_name } }],
+                },
+                "scopeSpans": [{ "scope": { "name": "acip-sidecar" }, "spans": spans }],
+            }],
+        })
+    }
+
+    impl SpanExporter for OtlpExporter {
+        /// Posted in the background; a failed export is logged and dropped.
+        fn export(&self, spans: Vec<SpanRecord>) {
+            let Ok(rt) = tokio::runtime::Handle::try_current() else {
+                return;
+            };
+            let request = self
+                .http
+                .post(&self.url)
+                .json(&request_body(&self.service_name, &spans));
+            rt.spawn(async move {
+                match request.send().await.and_then(|r| r.error_for_status()) {
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!(error = %e, "OTLP span export failed"),
+                }
+            });
+        }
+    }
+}
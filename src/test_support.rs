@@ -0,0 +1,565 @@
+//! Building blocks for golden-decision runs, here and in downstream crates.
+//!
+//! - [`load_fixtures`] reads a fixtures directory: a `cases.toml` manifest plus any input files
+//!   it names.
+//! - [`ScriptedModel`] answers model requests with canned verdicts, so the pipeline can run end
+//!   to end without a provider (set it as [`AppState::model_override`]).
+//! - [`normalize_decision`] strips what changes from run to run (request ids, timestamps, the
+//!   actor) and puts unordered sets in a fixed order, so a decision can be compared byte for
+//!   byte.
+//! - [`check_golden`] compares a normalized decision with its checked-in golden file and
+//!   renders a field-by-field diff. With `ACIP_UPDATE_GOLDENS=1` it rewrites the file instead.
+//!
+//! Every golden carries the [`rule_state`] it was produced under, so a changed golden says
+//! whether the local pattern pack moved too.
+//!
+//! [`leak_scan`] is a tracing layer for tests that checks every log record, and any API
+//! response handed to it, for the secret values a test watches.
+
+use crate::loop_guard::MARKER_PREFIX;
+use crate::model_policy::PolicyConfig;
+use crate::sentry::ModelClient;
+use crate::state::AppState;
+use crate::{threat, verdicts};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use base64::Engine as _;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, OnceLock,
+};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context as LayerContext, Layer, SubscriberExt};
+
+/// Set to `1` to rewrite golden files from the current decisions.
+pub const UPDATE_GOLDENS_ENV: &str = "ACIP_UPDATE_GOLDENS";
+
+/// Name of the manifest inside a fixtures directory.
+pub const MANIFEST: &str = "cases.toml";
+
+/// How the decision for a fixture is produced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoldenMode {
+    /// No model calls (`ACIP_SENTRY_MODE=stub`): local scanners, reputation and the fixed
+    /// fail-safe verdict.
+    Heuristic,
+    /// The live pipeline with a [`ScriptedModel`] answering for both tiers.
+    Scripted,
+}
+
+impl GoldenMode {
+    pub const ALL: [GoldenMode; 2] = [GoldenMode::Heuristic, GoldenMode::Scripted];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GoldenMode::Heuristic => "heuristic",
+            GoldenMode::Scripted => "scripted",
+        }
+    }
+
+    /// Value for `ACIP_SENTRY_MODE`.
+    pub fn sentry_mode(self) -> &'static str {
+        match self {
+            GoldenMode::Heuristic => "stub",
+            GoldenMode::Scripted => "live",
+        }
+    }
+}
+
+/// Where a fixture's content comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FixtureInput {
+    Text(String),
+    /// A file next to the manifest, sent as `bytes_b64`.
+    File(PathBuf),
+    /// `unit` repeated `repeat` times: large inputs without checking them in.
+    Synthetic {
+        unit: String,
+        repeat: usize,
+    },
+}
+
+/// One entry of `cases.toml`.
+#[derive(Debug, Clone)]
+pub struct Fixture {
+    pub name: String,
+    pub source_type: String,
+    pub content_type: String,
+    pub input: FixtureInput,
+    /// What the scripted provider answers: a verdict object, or a string sent verbatim (to
+    /// exercise repair and fail-closed paths). `None` means [`ScriptedModel::benign`].
+    pub verdict: Option<Value>,
+    /// Programs the case needs on `PATH` (e.g. Poppler for PDFs); see [`Fixture::missing_tools`].
+    pub requires: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "case")]
+    cases: Vec<RawFixture>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFixture {
+    name: String,
+    source_type: String,
+    content_type: String,
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    file: Option<String>,
+    #[serde(default)]
+    repeat: Option<RawRepeat>,
+    #[serde(default)]
+    verdict: Option<toml::Value>,
+    #[serde(default)]
+    requires: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRepeat {
+    unit: String,
+    times: usize,
+}
+
+/// Load `dir/cases.toml`. Names must be unique and usable as file names; each case has exactly
+/// one of `text`, `file` or `repeat`, and named files must exist.
+pub fn load_fixtures(dir: &Path) -> Result<Vec<Fixture>> {
+    let path = dir.join(MANIFEST);
+    let raw = std::fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    let manifest: Manifest =
+        toml::from_str(&raw).with_context(|| format!("parse {}", path.display()))?;
+
+    let mut seen = std::collections::HashSet::new();
+    let mut out = vec![];
+    for c in manifest.cases {
+        let valid_name = !c.name.is_empty()
+            && c.name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || ch == '_' || ch == '-');
+        if !valid_name {
+            bail!("case name {:?}: use letters, digits, '_' and '-'", c.name);
+        }
+        if !seen.insert(c.name.clone()) {
+            bail!("case {}: duplicate name", c.name);
+        }
+        let input = match (c.text, c.file, c.repeat) {
+            (Some(t), None, None) => FixtureInput::Text(t),
+            (None, Some(f), None) => {
+                let p = dir.join(&f);
+                if !p.is_file() {
+                    bail!("case {}: file {} not found", c.name, p.display());
+                }
+                FixtureInput::File(p)
+            }
+            (None, None, Some(r)) => FixtureInput::Synthetic {
+                unit: r.unit,
+                repeat: r.times,
+            },
+            _ => bail!("case {}: set exactly one of text, file or repeat", c.name),
+        };
+        let verdict = c
+            .verdict
+            .map(serde_json::to_value)
+            .transpose()
+            .with_context(|| format!("case {}: verdict", c.name))?;
+        out.push(Fixture {
+            name: c.name,
+            source_type: c.source_type,
+            content_type: c.content_type,
+            input,
+            verdict,
+            requires: c.requires,
+        });
+    }
+    Ok(out)
+}
+
+impl Fixture {
+    /// The `ingest_source` request body. The source id is the fixture name, so reputation
+    /// records never carry over between fixtures.
+    pub fn request(&self) -> Result<Value> {
+        let mut body = json!({
+            "source_id": format!("golden-{}", self.name),
+            "source_type": self.source_type,
+            "content_type": self.content_type,
+        });
+        match &self.input {
+            FixtureInput::Text(t) => body["text"] = json!(t),
+            FixtureInput::Synthetic { unit, repeat } => body["text"] = json!(unit.repeat(*repeat)),
+            FixtureInput::File(p) => {
+                let bytes = std::fs::read(p).with_context(|| format!("read {}", p.display()))?;
+                body["bytes_b64"] = json!(base64::engine::general_purpose::STANDARD.encode(bytes));
+            }
+        }
+        Ok(body)
+    }
+
+    /// The provider for [`GoldenMode::Scripted`] runs of this fixture.
+    pub fn scripted_model(&self) -> ScriptedModel {
+        match &self.verdict {
+            Some(v) => ScriptedModel::new(v.clone()),
+            None => ScriptedModel::benign(),
+        }
+    }
+
+    /// Entries of `requires` not found on `PATH`; a harness skips the case when any are.
+    pub fn missing_tools(&self) -> Vec<String> {
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        self.requires
+            .iter()
+            .filter(|t| !std::env::split_paths(&path).any(|d| d.join(t.as_str()).is_file()))
+            .cloned()
+            .collect()
+    }
+
+    /// `<dir>/<name>.<mode>.json`
+    pub fn golden_path(&self, dir: &Path, mode: GoldenMode) -> PathBuf {
+        dir.join(format!("{}.{}.json", self.name, mode.as_str()))
+    }
+}
+
+/// A [`ModelClient`] that answers from a script instead of a provider.
+///
+/// The first rule whose needle occurs in the prompt wins, else the default reply. Object
+/// replies get `fenced_content`, `reasons` and `detected_patterns` filled in when missing, so a
+/// script only has to state the verdict; string replies are returned verbatim.
+pub struct ScriptedModel {
+    default: Value,
+    rules: Vec<(String, Value)>,
+    calls: AtomicUsize,
+}
+
+impl ScriptedModel {
+    pub fn new(default: Value) -> Self {
+        Self {
+            default,
+            rules: vec![],
+            calls: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allows tools at low risk.
+    pub fn benign() -> Self {
+        Self::new(json!({
+            "tools_allowed": true,
+            "risk_level": "low",
+            "action": "allow",
+            "reasons": ["scripted: benign"],
+        }))
+    }
+
+    /// Answer `reply` when the prompt contains `needle`.
+    pub fn on(mut self, needle: &str, reply: Value) -> Self {
+        self.rules.push((needle.to_string(), reply));
+        self
+    }
+
+    /// Model requests answered so far.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    fn reply_for(&self, prompt: &str) -> String {
+        let reply = self
+            .rules
+            .iter()
+            .find(|(needle, _)| prompt.contains(needle.as_str()))
+            .map(|(_, r)| r)
+            .unwrap_or(&self.default);
+        match reply {
+            Value::String(raw) => raw.clone(),
+            Value::Object(o) => {
+                let mut o = o.clone();
+                o.entry("fenced_content")
+                    .or_insert_with(|| json!("```external\n[scripted]\n```"));
+                o.entry("reasons").or_insert_with(|| json!([]));
+                o.entry("detected_patterns").or_insert_with(|| json!([]));
+                Value::Object(o).to_string()
+            }
+            other => other.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ModelClient for ScriptedModel {
+    async fn generate(&self, _model: &str, prompt: &str, _headers: &HeaderMap) -> Result<String> {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        Ok(self.reply_for(prompt))
+    }
+}
+
+/// A fresh state for one golden run: `default` policy, in-memory stores, default reputation
+/// thresholds (`ACIP_REP_*` still apply), and `model` answering for every model tier.
+pub fn golden_state(model: Option<Arc<dyn ModelClient>>) -> Result<Arc<AppState>> {
+    let mut builder = crate::app_state_builder::AppStateBuilder::for_tests();
+    if let Some(model) = model {
+        builder = builder.with_model(model);
+    }
+    Ok(builder.build()?)
+}
+
+/// What a golden was produced under: the local pattern pack and the models of the `default`
+/// policy (scripted runs never reach them, but a changed default shows up here).
+pub fn rule_state() -> Value {
+    let p = verdicts::Provenance::current(&PolicyConfig::default());
+    json!({
+        "pattern_pack": threat::pattern_pack_hash(),
+        "l1_model": p.l1_model,
+        "l2_model": p.l2_model,
+    })
+}
+
+/// Response fields dropped before comparison: they differ on every run or repeat others.
+pub const VOLATILE_FIELDS: &[&str] = &["origin", "actor", "threat_audit", "verdict_repairs"];
+
+/// The comparable form of an `ingest_source` response.
+///
+/// Volatile fields go (as does `provenance.build`, which changes with every commit),
+/// `fenced_content` becomes the SHA-256 of everything after its origin banner (the banner names
+/// the run; the content can be large), and unordered sets (`detected_patterns`, threat indicators, attack types, detections) are sorted.
+/// `reasons` keep their order: the reason renderer already makes it stable.
+pub fn normalize_decision(http_status: u16, body: &Value) -> Value {
+    let mut v = body.clone();
+    if let Value::Object(o) = &mut v {
+        for f in VOLATILE_FIELDS {
+            o.remove(*f);
+        }
+        if let Some(Value::String(fenced)) = o.remove("fenced_content") {
+            let fenced = match fenced.split_once('\n') {
+                Some((banner, rest)) if banner.starts_with(&format!("[{MARKER_PREFIX}")) => rest,
+                _ => fenced.as_str(),
+            };
+            o.insert(
+                "fenced_content_sha256".to_string(),
+                json!(hex::encode(Sha256::digest(fenced.as_bytes()))),
+            );
+        }
+        if let Some(Value::Object(p)) = o.get_mut("provenance") {
+            p.remove("build");
+        }
+        sort_array(o.get_mut("detected_patterns"));
+        if let Some(Value::Object(t)) = o.get_mut("threat") {
+            for k in ["indicators", "attack_types", "detected"] {
+                sort_array(t.get_mut(k));
+            }
+        }
+    }
+    json!({ "http_status": http_status, "response": v })
+}
+
+fn sort_array(v: Option<&mut Value>) {
+    if let Some(Value::Array(a)) = v {
+        a.sort_by_key(|x| x.to_string());
+    }
+}
+
+/// The golden document for one fixture and mode.
+pub fn golden(fixture: &Fixture, mode: GoldenMode, normalized: Value) -> Value {
+    json!({
+        "fixture": fixture.name,
+        "mode": mode.as_str(),
+        "rule_state": rule_state(),
+        "decision": normalized,
+    })
+}
+
+/// Field-by-field differences, one line per changed path (`$.decision.response.action: ...`).
+pub fn diff(expected: &Value, actual: &Value) -> Vec<String> {
+    let mut out = vec![];
+    diff_at("$", expected, actual, &mut out);
+    out
+}
+
+fn diff_at(path: &str, expected: &Value, actual: &Value, out: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => {
+            let keys: std::collections::BTreeSet<&String> = e.keys().chain(a.keys()).collect();
+            for k in keys {
+                let p = format!("{path}.{k}");
+                match (e.get(k), a.get(k)) {
+                    (Some(x), Some(y)) => diff_at(&p, x, y, out),
+                    (Some(x), None) => out.push(format!("{p}: removed (was {x})")),
+                    (None, Some(y)) => out.push(format!("{p}: added {y}")),
+                    (None, None) => {}
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(a)) if e.len() == a.len() => {
+            for (i, (x, y)) in e.iter().zip(a).enumerate() {
+                diff_at(&format!("{path}[{i}]"), x, y, out);
+            }
+        }
+        _ if expected != actual => out.push(format!("{path}: expected {expected}, got {actual}")),
+        _ => {}
+    }
+}
+
+/// Outcome of [`check_golden`] when nothing failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GoldenOutcome {
+    Matched,
+    /// `ACIP_UPDATE_GOLDENS=1` and no golden existed yet: it was written.
+    Created,
+    /// `ACIP_UPDATE_GOLDENS=1`: the golden was rewritten.
+    Updated,
+}
+
+/// Whether `ACIP_UPDATE_GOLDENS=1` is set.
+pub fn update_requested() -> bool {
+    std::env::var(UPDATE_GOLDENS_ENV).is_ok_and(|v| v.trim() == "1")
+}
+
+/// Compare `actual` with the golden at `path`; with `update` (see [`update_requested`]),
+/// write it instead. A missing golden is an error unless `update` is set, so a deleted or
+/// never-committed golden cannot pass. A mismatch is an error listing every changed field.
+pub fn check_golden(path: &Path, actual: &Value, update: bool) -> Result<GoldenOutcome> {
+    let rendered = format!("{}\n", serde_json::to_string_pretty(actual)?);
+    let existing = match std::fs::read_to_string(path) {
+        Ok(s) => Some(s),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e).with_context(|| format!("read {}", path.display())),
+    };
+    let Some(existing) = existing else {
+        if !update {
+            bail!(
+                "{} is missing ({UPDATE_GOLDENS_ENV}=1 writes it)",
+                path.display()
+            );
+        }
+        std::fs::write(path, rendered).with_context(|| format!("write {}", path.display()))?;
+        return Ok(GoldenOutcome::Created);
+    };
+    if update {
+        if existing != rendered {
+            std::fs::write(path, rendered).with_context(|| format!("write {}", path.display()))?;
+        }
+        return Ok(GoldenOutcome::Updated);
+    }
+    let expected: Value = serde_json::from_str(&existing)
+        .with_context(|| format!("parse golden {}", path.display()))?;
+    let changes = diff(&expected, actual);
+    if changes.is_empty() {
+        return Ok(GoldenOutcome::Matched);
+    }
+    Err(anyhow!(
+        "{} changed ({UPDATE_GOLDENS_ENV}=1 accepts the new decision):\n  {}",
+        path.display(),
+        changes.join("\n  ")
+    ))
+}
+
+/// Records that contained a watched secret. See [`leak_scan`].
+#[derive(Default)]
+pub struct LeakScan {
+    watched: Mutex<Vec<String>>,
+    leaks: Mutex<Vec<String>>,
+}
+
+/// The leak scanner, installed as the global subscriber on first use. Every span and event of
+/// every level is rendered with all its fields, so a secret that reaches a record through
+/// `Debug`, `Display` or an error chain is caught. Panics if another global subscriber is
+/// already set, since the scan would then see nothing.
+pub fn leak_scan() -> &'static LeakScan {
+    static SCAN: OnceLock<&'static LeakScan> = OnceLock::new();
+    SCAN.get_or_init(|| {
+        let scan: &'static LeakScan = Box::leak(Box::default());
+        let subscriber = tracing_subscriber::registry().with(LeakLayer(scan));
+        tracing::subscriber::set_global_default(subscriber)
+            .expect("leak_scan needs to be the global subscriber");
+        scan
+    })
+}
+
+impl LeakScan {
+    /// Flag every record containing `secret` from now on.
+    pub fn watch(&self, secret: &str) {
+        self.watched.lock().unwrap().push(secret.to_string());
+    }
+
+    /// Flag `text` (a response body, say) if it contains a watched secret.
+    pub fn check(&self, what: &str, text: &str) {
+        self.inspect(what, text);
+    }
+
+    /// Flagged records and texts containing `secret`.
+    pub fn leaks_of(&self, secret: &str) -> Vec<String> {
+        let leaks = self.leaks.lock().unwrap();
+        leaks
+            .iter()
+            .filter(|l| l.contains(secret))
+            .cloned()
+            .collect()
+    }
+
+    /// Panic if `secret` was flagged anywhere.
+    pub fn assert_not_leaked(&self, secret: &str) {
+        let leaks = self.leaks_of(secret);
+        assert!(leaks.is_empty(), "secret leaked:\n  {}", leaks.join("\n  "));
+    }
+
+    fn inspect(&self, what: &str, text: &str) {
+        let hit = {
+            let watched = self.watched.lock().unwrap();
+            watched.iter().any(|w| text.contains(w.as_str()))
+        };
+        if hit {
+            self.leaks.lock().unwrap().push(format!("{what}: {text}"));
+        }
+    }
+}
+
+struct LeakLayer(&'static LeakScan);
+
+/// A record's fields as `name=value`, strings unquoted so nothing is escaped away.
+#[derive(Default)]
+struct Rendered(String);
+
+impl Visit for Rendered {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.push_str(&format!(" {}={value}", field.name()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0.push_str(&format!(" {}={value:?}", field.name()));
+    }
+}
+
+impl<S: tracing::Subscriber> Layer<S> for LeakLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: LayerContext<'_, S>) {
+        let mut r = Rendered::default();
+        event.record(&mut r);
+        self.0.inspect(event.metadata().target(), &r.0);
+    }
+
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        _id: &tracing::span::Id,
+        _ctx: LayerContext<'_, S>,
+    ) {
+        let mut r = Rendered::default();
+        attrs.record(&mut r);
+        self.0.inspect(attrs.metadata().name(), &r.0);
+    }
+
+    fn on_record(
+        &self,
+        _id: &tracing::span::Id,
+        values: &tracing::span::Record<'_>,
+        _ctx: LayerContext<'_, S>,
+    ) {
+        let mut r = Rendered::default();
+        values.record(&mut r);
+        self.0.inspect("span", &r.0);
+    }
+}
@@ -0,0 +1,333 @@
+//! Cheap quality signals for extracted text.
+//!
+//! OCR gibberish, mojibake from a wrong encoding guess and PDFs that extract one character per
+//! line all look like ordinary text to the scanners. [`assess`] measures a handful of signals
+//! and folds them into a 0..1 score bucketed as good / degraded / garbled, so callers can skip
+//! or review unusable text and policies can route `garbled` like a low text yield.
+//!
+//! Cost is bounded: texts over [`SAMPLE_THRESHOLD_BYTES`] are judged on evenly spaced windows
+//! and the result says so (`sampled`, `analyzed_chars`).
+
+use crate::model_policy::GarbledTextHandling;
+use crate::sentry::{Action, Decision};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Texts larger than this are sampled instead of scanned whole.
+pub const SAMPLE_THRESHOLD_BYTES: usize = 256 * 1024;
+const SAMPLE_WINDOWS: usize = 8;
+const SAMPLE_WINDOW_BYTES: usize = 16 * 1024;
+
+/// Below this many words the dictionary ratio is too noisy to count.
+const MIN_WORDS_FOR_DICTIONARY: usize = 8;
+/// Dictionary ratio at which the word signal is fully satisfied. Clean prose lands around 0.4
+/// to 0.5 against the short lists below.
+const DICTIONARY_RATIO_FULL: f64 = 0.25;
+
+const GOOD_SCORE: f64 = 0.75;
+const DEGRADED_SCORE: f64 = 0.4;
+
+/// Most frequent words per supported language. Small on purpose: the ratio only has to
+/// separate language from noise, not spell-check.
+const WORDLISTS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "of", "and", "to", "a", "in", "is", "it", "you", "that", "he", "was", "for",
+            "on", "are", "with", "as", "i", "his", "they", "be", "at", "one", "have", "this",
+            "from", "or", "had", "by", "not", "word", "but", "what", "some", "we", "can", "out",
+            "other", "were", "all", "there", "when", "up", "use", "your", "how", "said", "an",
+            "each", "she", "which", "do", "their", "time", "if", "will", "way", "about", "many",
+            "then", "them", "write", "would", "like", "so", "these", "her", "long", "make",
+            "thing", "see", "him", "two", "has", "look", "more", "day", "could", "go", "come",
+            "did", "number", "sound", "no", "most", "people", "my", "over", "know", "water",
+            "than", "call", "first", "who", "may", "down", "side", "been", "now", "find", "any",
+            "new", "work", "part", "take", "get", "place", "made", "live", "where", "after",
+            "back", "little", "only", "round", "man", "year", "came", "show", "every", "good",
+            "me", "give", "our", "under", "name", "very", "through", "just", "form", "sentence",
+            "great", "think", "say", "help", "low", "line", "differ", "turn", "cause", "much",
+            "mean", "before", "move", "right", "boy", "old", "too", "same", "tell", "does", "set",
+            "three", "want", "air", "well", "also", "play", "small", "end", "put", "home", "read",
+            "hand", "port", "large", "spell", "add", "even", "land", "here", "must", "big", "high",
+            "such", "follow", "act", "why", "ask", "men", "change", "went", "light", "kind", "off",
+            "need", "house", "picture", "try", "us", "again", "animal", "point", "mother", "world",
+            "near", "build", "self", "earth", "father",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "de", "la", "que", "el", "en", "y", "a", "los", "se", "del", "las", "un", "por", "con",
+            "no", "una", "su", "para", "es", "al", "lo", "como", "más", "o", "pero", "sus", "le",
+            "ha", "me", "si", "sin", "sobre", "este", "ya", "entre", "cuando", "todo", "esta",
+            "ser", "son", "dos", "también", "fue", "había", "era", "muy", "años", "hasta", "desde",
+            "está", "mi", "porque", "qué", "sólo", "han", "yo", "hay", "vez", "puede", "todos",
+            "así", "nos", "ni", "parte", "tiene", "él", "uno", "donde", "bien", "tiempo", "mismo",
+            "ese", "ahora", "cada", "e", "vida", "otro", "después", "te", "otros", "aunque", "esa",
+            "eso", "hace", "otra", "gobierno", "tan", "durante", "siempre", "día", "tanto", "ella",
+            "tres", "sí", "dijo", "sido", "gran", "país", "según", "menos",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "de", "la", "le", "et", "les", "des", "en", "un", "du", "une", "que", "est", "pour",
+            "qui", "dans", "a", "par", "plus", "pas", "au", "sur", "ne", "se", "il", "sont", "ce",
+            "avec", "ou", "mais", "comme", "on", "tout", "nous", "sa", "ses", "aux", "cette",
+            "été", "leur", "fait", "elle", "deux", "aussi", "même", "ont", "ces", "lui", "dont",
+            "bien", "sans", "peut", "entre", "avant", "tous", "autres", "où", "après", "était",
+            "être", "très", "fois", "vers", "chaque", "nouveau", "pendant", "trois", "afin",
+            "aucune", "moins", "avons",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "und", "in", "den", "von", "zu", "das", "mit", "sich", "des", "auf",
+            "für", "ist", "im", "dem", "nicht", "ein", "eine", "als", "auch", "es", "an", "werden",
+            "aus", "er", "hat", "dass", "sie", "nach", "wird", "bei", "einer", "um", "am", "sind",
+            "noch", "wie", "einem", "über", "einen", "so", "zum", "war", "haben", "nur", "oder",
+            "aber", "vor", "zur", "bis", "mehr", "durch", "man", "sein", "wurde", "sei", "ich",
+            "wir", "ihr",
+        ],
+    ),
+];
+
+/// Characters that follow a UTF-8 lead byte once the bytes are misread as Latin-1/CP1252
+/// (the `é` -> `Ã©` pattern).
+const CP1252_CONTINUATIONS: &[char] = &[
+    '€', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', 'Ž', '‘', '’', '“', '”', '•', '–',
+    '—', '˜', '™', 'š', '›', 'œ', 'ž', 'Ÿ',
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityBucket {
+    Good,
+    Degraded,
+    Garbled,
+}
+
+impl QualityBucket {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QualityBucket::Good => "good",
+            QualityBucket::Degraded => "degraded",
+            QualityBucket::Garbled => "garbled",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TextQuality {
+    pub bucket: QualityBucket,
+    /// 0 (unusable) to 1 (clean).
+    pub score: f64,
+    /// Share of words found in the best-matching wordlist.
+    pub dictionary_word_ratio: f64,
+    /// Language of that wordlist, if any word matched.
+    pub language: Option<&'static str>,
+    /// Share of non-whitespace characters that are not letters or digits.
+    pub non_alnum_ratio: f64,
+    /// Mean length in characters of non-blank lines.
+    pub avg_line_length: f64,
+    /// Share of non-blank lines holding a single character.
+    pub single_char_line_ratio: f64,
+    /// U+FFFD characters (bytes that did not decode).
+    pub replacement_chars: usize,
+    /// `Ã©`-style sequences left by decoding UTF-8 as Latin-1.
+    pub mojibake_sequences: usize,
+    pub words: usize,
+    /// True when only windows of the text were analyzed; counts then cover those windows.
+    pub sampled: bool,
+    pub analyzed_chars: usize,
+}
+
+/// Measure `text`. Bounded: large texts are sampled (see module docs).
+pub fn assess(text: &str) -> TextQuality {
+    let (sample, sampled) = sample(text);
+
+    let index = word_index();
+    let mut words = 0usize;
+    let mut hits = [0usize; WORDLISTS.len()];
+    for w in sample
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+    {
+        words += 1;
+        // Listed words are short; longer ones cannot match.
+        if w.len() > 16 {
+            continue;
+        }
+        let Some(langs) = index.get(w.to_lowercase().as_str()) else {
+            continue;
+        };
+        for (i, h) in hits.iter_mut().enumerate() {
+            if langs & (1 << i) != 0 {
+                *h += 1;
+            }
+        }
+    }
+    let (best, best_hits) = hits
+        .iter()
+        .enumerate()
+        .max_by_key(|(i, h)| (**h, std::cmp::Reverse(*i)))
+        .map(|(i, h)| (i, *h))
+        .unwrap_or((0, 0));
+
+    let mut analyzed_chars = 0usize;
+    let mut non_ws = 0usize;
+    let mut non_alnum = 0usize;
+    let mut replacement_chars = 0usize;
+    let mut mojibake_sequences = 0usize;
+    let mut prev: Option<char> = None;
+    for c in sample.chars() {
+        analyzed_chars += 1;
+        if !c.is_whitespace() {
+            non_ws += 1;
+            if !c.is_alphanumeric() {
+                non_alnum += 1;
+            }
+        }
+        if c == '\u{FFFD}' {
+            replacement_chars += 1;
+        }
+        if prev.is_some_and(|p| ('\u{C2}'..='\u{EF}').contains(&p)) && is_continuation(c) {
+            mojibake_sequences += 1;
+        }
+        prev = Some(c);
+    }
+
+    let mut lines = 0usize;
+    let mut line_chars = 0usize;
+    let mut single_char_lines = 0usize;
+    for line in sample.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let n = line.chars().count();
+        lines += 1;
+        line_chars += n;
+        if n == 1 {
+            single_char_lines += 1;
+        }
+    }
+
+    let dictionary_word_ratio = ratio(best_hits, words);
+    let non_alnum_ratio = ratio(non_alnum, non_ws);
+    let single_char_line_ratio = ratio(single_char_lines, lines);
+
+    let word_score = if words < MIN_WORDS_FOR_DICTIONARY {
+        1.0
+    } else {
+        (dictionary_word_ratio / DICTIONARY_RATIO_FULL).min(1.0)
+    };
+    let noise_penalty = ((non_alnum_ratio - 0.15) / 0.25).clamp(0.0, 1.0);
+    let line_penalty = ((single_char_line_ratio - 0.2) / 0.4).clamp(0.0, 1.0);
+    let encoding_penalty =
+        (ratio(replacement_chars + mojibake_sequences, words.max(1)) * 1.5).min(1.0);
+    let score = round3(
+        word_score * (1.0 - noise_penalty) * (1.0 - line_penalty) * (1.0 - encoding_penalty),
+    );
+
+    let bucket = if score >= GOOD_SCORE {
+        QualityBucket::Good
+    } else if score >= DEGRADED_SCORE {
+        QualityBucket::Degraded
+    } else {
+        QualityBucket::Garbled
+    };
+
+    TextQuality {
+        bucket,
+        score,
+        dictionary_word_ratio: round3(dictionary_word_ratio),
+        language: (best_hits > 0).then_some(WORDLISTS[best].0),
+        non_alnum_ratio: round3(non_alnum_ratio),
+        avg_line_length: round3(ratio(line_chars, lines)),
+        single_char_line_ratio: round3(single_char_line_ratio),
+        replacement_chars,
+        mojibake_sequences,
+        words,
+        sampled,
+        analyzed_chars,
+    }
+}
+
+/// Decision stage for the policy's `on_garbled_text`: garbled text goes to review (unless
+/// already blocked) with tools off, like a low text yield.
+pub fn apply_garbled_handling(
+    mut decision: Decision,
+    quality: &TextQuality,
+    handling: GarbledTextHandling,
+) -> Decision {
+    if handling == GarbledTextHandling::Ignore || quality.bucket != QualityBucket::Garbled {
+        return decision;
+    }
+    decision.tools_allowed = false;
+    if !matches!(decision.action, Action::Block) {
+        decision.action = Action::NeedsReview;
+    }
+    decision.reasons.push(format!(
+        "extracted text garbled (quality score {:.2}); routed to review",
+        quality.score
+    ));
+    decision
+}
+
+/// Every listed word with a bit per language it belongs to.
+fn word_index() -> &'static HashMap<&'static str, u8> {
+    static INDEX: OnceLock<HashMap<&'static str, u8>> = OnceLock::new();
+    INDEX.get_or_init(|| {
+        let mut m: HashMap<&'static str, u8> = HashMap::new();
+        for (i, (_, list)) in WORDLISTS.iter().enumerate() {
+            for w in list.iter() {
+                *m.entry(*w).or_default() |= 1 << i;
+            }
+        }
+        m
+    })
+}
+
+fn is_continuation(c: char) -> bool {
+    ('\u{80}'..='\u{BF}').contains(&c) || CP1252_CONTINUATIONS.contains(&c)
+}
+
+/// The whole text when small; otherwise evenly spaced windows joined by newlines, each
+/// starting at a line boundary where one is close.
+fn sample(text: &str) -> (std::borrow::Cow<'_, str>, bool) {
+    if text.len() <= SAMPLE_THRESHOLD_BYTES {
+        return (std::borrow::Cow::Borrowed(text), false);
+    }
+    let stride = text.len() / SAMPLE_WINDOWS;
+    let mut out = String::with_capacity(SAMPLE_WINDOWS * (SAMPLE_WINDOW_BYTES + 1));
+    for i in 0..SAMPLE_WINDOWS {
+        let mut start = floor_boundary(text, i * stride);
+        let end = floor_boundary(text, (start + SAMPLE_WINDOW_BYTES).min(text.len()));
+        if start > 0 {
+            if let Some(nl) = text[start..end].find('\n') {
+                start += nl + 1;
+            }
+        }
+        out.push_str(&text[start..end]);
+        out.push('\n');
+    }
+    (std::borrow::Cow::Owned(out), true)
+}
+
+fn floor_boundary(s: &str, mut i: usize) -> usize {
+    while !s.is_char_boundary(i) {
+        i -= 1;
+    }
+    i
+}
+
+fn ratio(n: usize, d: usize) -> f64 {
+    if d == 0 {
+        0.0
+    } else {
+        n as f64 / d as f64
+    }
+}
+
+fn round3(x: f64) -> f64 {
+    (x * 1000.0).round() / 1000.0
+}
@@ -1,4 +1,7 @@
+use crate::url_allowlist::UrlRef;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 #[serde(rename_all = "snake_case")]
@@ -9,6 +12,43 @@ pub enum AttackType {
     CredentialTheft,
     Jailbreak,
     SocialEngineering,
+    /// Trigger phrases hidden behind HTML-entity or percent encoding.
+    Obfuscation,
+}
+
+/// Which view of the content a detector matched in.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanStage {
+    /// The extracted/normalized text as sent to the model.
+    Raw,
+    /// Entity/percent-decoded regions of that text (see `decode_scan`).
+    DecodedContent,
+    /// Live metadata about a linked host (see `probing`).
+    Probe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DetectedPattern {
+    pub indicator: String,
+    pub stage: ScanStage,
+}
+
+/// Indicator added when a phrase only matched in decoded content.
+pub const OBFUSCATION_INDICATOR: &str = "obfuscation:encoded_trigger";
+pub const OBFUSCATION_SCORE: u8 = 4;
+
+/// Indicator for a document that came encrypted (the extractor's `pdf_encrypted` warning); a
+/// mild signal on its own.
+pub const ENCRYPTED_INDICATOR: &str = "extract_pdf_encrypted";
+pub const ENCRYPTED_SCORE: u8 = 1;
+
+/// One phrase match from [`scan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhraseHit {
+    pub ty: AttackType,
+    pub indicator: String,
+    pub score: u8,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -18,6 +58,12 @@ pub struct ThreatAssessment {
     #[serde(default)]
     pub indicators: Vec<String>,
     pub threat_score: u8,
+    /// Scanner indicators with the stage they matched in (operator/audit only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub detected: Vec<DetectedPattern>,
+    /// Hosts the content links to, with their allowlist status (operator/audit only).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<UrlRef>,
 }
 
 impl ThreatAssessment {
@@ -26,6 +72,8 @@ impl ThreatAssessment {
             attack_types: vec![],
             indicators: vec![],
             threat_score: 0,
+            detected: vec![],
+            urls: vec![],
         }
     }
 
@@ -35,131 +83,202 @@ impl ThreatAssessment {
         self.threat_score = self.threat_score.saturating_add(score);
     }
 
+    /// Remove phrase indicator `indicator` (as produced by [`scan`]) and the score it added. Its
+    /// attack type goes too unless another phrase of that type remains.
+    pub fn remove_phrase(&mut self, indicator: &str) {
+        let Some(rule) = rule_for(indicator) else {
+            return;
+        };
+        if !self.indicators.iter().any(|i| i == indicator) {
+            return;
+        }
+        self.indicators.retain(|i| i != indicator);
+        self.detected.retain(|d| d.indicator != indicator);
+        self.threat_score = self.threat_score.saturating_sub(rule.score);
+        let type_remains = self
+            .indicators
+            .iter()
+            .filter_map(|i| rule_for(i))
+            .any(|r| r.ty == rule.ty);
+        if !type_remains {
+            self.attack_types.retain(|t| *t != rule.ty);
+        }
+    }
+
     pub fn normalize(&mut self) {
         self.attack_types.sort();
         self.attack_types.dedup();
         self.indicators.sort();
         self.indicators.dedup();
+        self.detected.sort();
+        self.detected.dedup();
+        self.urls.sort();
+        self.urls.dedup_by(|a, b| a.host == b.host);
     }
 }
 
-pub fn assess(text: &str) -> ThreatAssessment {
-    let mut a = ThreatAssessment::none();
-    let lower = text.to_lowercase();
+/// One group of local detector phrases: every phrase found adds `prefix:<phrase>` and `score`.
+struct PhraseRule {
+    ty: AttackType,
+    prefix: &'static str,
+    score: u8,
+    phrases: &'static [&'static str],
+}
 
+/// The local pattern pack. Editing it changes [`pattern_pack_hash`], which invalidates
+/// previously recorded verdicts.
+const RULES: &[PhraseRule] = &[
     // Prompt injection / tool coercion patterns
-    let pi_phrases = [
-        "ignore previous",
-        "ignore all previous",
-        "disregard above",
-        "system prompt",
-        "developer message",
-        "follow these instructions",
-        "you must",
-        "do not follow",
-        "tool",
-        "function call",
-        "call the tool",
-    ];
-    for p in pi_phrases {
-        if lower.contains(p) {
-            a.add(
-                AttackType::PromptInjection,
-                format!("contains_phrase:{p}"),
-                8,
-            );
-        }
-    }
-
+    PhraseRule {
+        ty: AttackType::PromptInjection,
+        prefix: "contains_phrase",
+        score: 8,
+        phrases: &[
+            "ignore previous",
+            "ignore all previous",
+            "disregard above",
+            "system prompt",
+            "developer message",
+            "follow these instructions",
+            "you must",
+            "do not follow",
+            "tool",
+            "function call",
+            "call the tool",
+        ],
+    },
     // Exfil / credential theft signals
-    let cred_phrases = [
-        "api key",
-        "secret",
-        "token",
-        "password",
-        "private key",
-        "ssh key",
-        "wallet seed",
-        "mnemonic",
-    ];
-    for p in cred_phrases {
-        if lower.contains(p) {
-            a.add(
-                AttackType::CredentialTheft,
-                format!("mentions_sensitive:{p}"),
-                10,
-            );
-        }
-    }
+    PhraseRule {
+        ty: AttackType::CredentialTheft,
+        prefix: "mentions_sensitive",
+        score: 10,
+        phrases: &[
+            "api key",
+            "secret",
+            "token",
+            "password",
+            "private key",
+            "ssh key",
+            "wallet seed",
+            "mnemonic",
+        ],
+    },
+    PhraseRule {
+        ty: AttackType::DataExfiltration,
+        prefix: "mentions_exfil",
+        score: 6,
+        phrases: &[
+            "send to",
+            "exfiltrate",
+            "upload",
+            "pastebin",
+            "webhook",
+            "http://",
+            "https://",
+        ],
+    },
+    // Jailbreak-ish language
+    PhraseRule {
+        ty: AttackType::Jailbreak,
+        prefix: "mentions",
+        score: 8,
+        phrases: &[
+            "jailbreak",
+            "dan mode",
+            "no restrictions",
+            "bypass",
+            "override",
+            "you are free",
+        ],
+    },
+    // Social engineering
+    PhraseRule {
+        ty: AttackType::SocialEngineering,
+        prefix: "social_pressure",
+        score: 4,
+        phrases: &[
+            "urgent",
+            "immediately",
+            "asap",
+            "do this now",
+            "time sensitive",
+            "do not tell",
+        ],
+    },
+    // Tool coercion: requesting tool usage explicitly
+    PhraseRule {
+        ty: AttackType::ToolCoercion,
+        prefix: "tool_request",
+        score: 10,
+        phrases: &[
+            "run curl",
+            "execute",
+            "shell",
+            "terminal",
+            "powershell",
+            "cmd.exe",
+        ],
+    },
+];
 
-    let exfil_phrases = [
-        "send to",
-        "exfiltrate",
-        "upload",
-        "pastebin",
-        "webhook",
-        "http://",
-        "https://",
-    ];
-    for p in exfil_phrases {
-        if lower.contains(p) {
-            a.add(
-                AttackType::DataExfiltration,
-                format!("mentions_exfil:{p}"),
-                6,
-            );
-        }
-    }
+/// The rule that produces `indicator` (`prefix:phrase`).
+fn rule_for(indicator: &str) -> Option<&'static PhraseRule> {
+    let (prefix, phrase) = indicator.split_once(':')?;
+    RULES
+        .iter()
+        .find(|r| r.prefix == prefix && r.phrases.contains(&phrase))
+}
 
-    // Jailbreak-ish language
-    let jailbreak_phrases = [
-        "jailbreak",
-        "dan mode",
-        "no restrictions",
-        "bypass",
-        "override",
-        "you are free",
-    ];
-    for p in jailbreak_phrases {
-        if lower.contains(p) {
-            a.add(AttackType::Jailbreak, format!("mentions:{p}"), 8);
+/// Every phrase rule matching `text`.
+pub fn scan(text: &str) -> Vec<PhraseHit> {
+    let lower = text.to_lowercase();
+    let mut hits = vec![];
+    for rule in RULES {
+        for p in rule.phrases {
+            if lower.contains(p) {
+                hits.push(PhraseHit {
+                    ty: rule.ty.clone(),
+                    indicator: format!("{}:{p}", rule.prefix),
+                    score: rule.score,
+                });
+            }
         }
     }
+    hits
+}
 
-    // Social engineering
-    let se_phrases = [
-        "urgent",
-        "immediately",
-        "asap",
-        "do this now",
-        "time sensitive",
-        "do not tell",
-    ];
-    for p in se_phrases {
-        if lower.contains(p) {
-            a.add(
-                AttackType::SocialEngineering,
-                format!("social_pressure:{p}"),
-                4,
-            );
-        }
-    }
+/// Every indicator the built-in phrase rules can produce, and the fixed ones.
+pub fn known_indicators() -> Vec<String> {
+    RULES
+        .iter()
+        .flat_map(|r| r.phrases.iter().map(move |p| format!("{}:{p}", r.prefix)))
+        .chain([OBFUSCATION_INDICATOR, ENCRYPTED_INDICATOR].map(str::to_string))
+        .collect()
+}
 
-    // Tool coercion: requesting tool usage explicitly
-    let tool_phrases = [
-        "run curl",
-        "execute",
-        "shell",
-        "terminal",
-        "powershell",
-        "cmd.exe",
-    ];
-    for p in tool_phrases {
-        if lower.contains(p) {
-            a.add(AttackType::ToolCoercion, format!("tool_request:{p}"), 10);
-        }
+pub fn assess(text: &str) -> ThreatAssessment {
+    let mut a = ThreatAssessment::none();
+    for hit in scan(text) {
+        a.add(hit.ty, hit.indicator, hit.score);
     }
-
     a.normalize();
     a
 }
+
+/// Short stable hash of the local pattern pack (rules, prefixes, scores, phrases).
+pub fn pattern_pack_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| {
+        let mut hasher = Sha256::new();
+        for rule in RULES {
+            hasher.update(format!("{:?}|{}|{}", rule.ty, rule.prefix, rule.score));
+            for p in rule.phrases {
+                hasher.update([0u8]);
+                hasher.update(p.as_bytes());
+            }
+            hasher.update([0xffu8]);
+        }
+        hasher.update(format!("{OBFUSCATION_INDICATOR}|{OBFUSCATION_SCORE}"));
+        hex::encode(&hasher.finalize()[..8])
+    })
+}
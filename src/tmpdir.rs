@@ -0,0 +1,395 @@
+//! Extractor temp directory management.
+//!
+//! Every temp file or directory the sidecar (or the extractor helper, via `TMPDIR`) creates lives
+//! under a [`TMP_PREFIX`]-named entry in the configured base directory and stays in the registry
+//! while it is owned. That makes leftovers from killed helpers or crashes recognizable: on
+//! startup, prefixed entries older than the orphan age that nobody owns are removed. A monitor
+//! keeps usage and free-space figures for `/status`, and new extraction work is refused with
+//! `storage_exhausted` when free space drops below the floor instead of failing midway.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// Name prefix for every sidecar-created temp entry. Cleanup never touches anything else.
+pub const TMP_PREFIX: &str = "acip-tmp-";
+
+pub const DEFAULT_ORPHAN_MAX_AGE_SECS: u64 = 3600;
+pub const DEFAULT_MIN_FREE_MB: u64 = 256;
+pub const DEFAULT_SOFT_LIMIT_MB: u64 = 1024;
+pub const DEFAULT_MONITOR_INTERVAL_SECS: u64 = 30;
+
+const MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TmpDirSettings {
+    /// Directory the prefixed entries are created in.
+    pub base: PathBuf,
+    /// Unowned prefixed entries older than this are removed at startup.
+    pub orphan_max_age: Duration,
+    /// New extraction work is refused below this much free space.
+    pub min_free_bytes: u64,
+    /// A warning is logged when prefixed entries use more than this.
+    pub soft_limit_bytes: u64,
+    pub monitor_interval: Duration,
+}
+
+impl Default for TmpDirSettings {
+    fn default() -> Self {
+        Self {
+            base: std::env::temp_dir(),
+            orphan_max_age: Duration::from_secs(DEFAULT_ORPHAN_MAX_AGE_SECS),
+            min_free_bytes: DEFAULT_MIN_FREE_MB * MB,
+            soft_limit_bytes: DEFAULT_SOFT_LIMIT_MB * MB,
+            monitor_interval: Duration::from_secs(DEFAULT_MONITOR_INTERVAL_SECS),
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl TmpDirSettings {
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(base) = std::env::var("ACIP_EXTRACTOR_TMPDIR")
+            .ok()
+            .filter(|v| !v.trim().is_empty())
+        {
+            s.base = PathBuf::from(base);
+        }
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_TMP_ORPHAN_AGE_SECS") {
+            s.orphan_max_age = Duration::from_secs(v);
+        }
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_TMP_MIN_FREE_MB") {
+            s.min_free_bytes = v * MB;
+        }
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_TMP_SOFT_LIMIT_MB") {
+            s.soft_limit_bytes = v * MB;
+        }
+        if let Some(v) = env_u64("ACIP_EXTRACTOR_TMP_MONITOR_SECS") {
+            s.monitor_interval = Duration::from_secs(v.max(1));
+        }
+        s
+    }
+}
+
+/// Result of one orphan sweep.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CleanupReport {
+    pub removed: usize,
+    pub removed_bytes: u64,
+    /// Prefixed entries kept because they are owned right now.
+    pub skipped_in_use: usize,
+    /// Prefixed entries kept because they are younger than the orphan age.
+    pub skipped_recent: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TmpUsage {
+    /// Bytes under prefixed entries in the base directory.
+    pub used_bytes: u64,
+    /// Free space on the base directory's filesystem, when the platform reports it.
+    pub free_bytes: Option<u64>,
+    pub owned_entries: usize,
+    pub checked_unix: u64,
+}
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("temp storage exhausted ({free_bytes} bytes free < {min_free_bytes} bytes floor)")]
+pub struct StorageExhausted {
+    pub free_bytes: u64,
+    pub min_free_bytes: u64,
+}
+
+#[derive(Default)]
+pub struct TmpDirManager {
+    settings: TmpDirSettings,
+    owned: Mutex<HashSet<PathBuf>>,
+    last: Mutex<TmpUsage>,
+    over_soft_limit: AtomicBool,
+}
+
+impl TmpDirManager {
+    pub fn new(settings: TmpDirSettings) -> Self {
+        Self {
+            settings,
+            ..Self::default()
+        }
+    }
+
+    pub fn settings(&self) -> &TmpDirSettings {
+        &self.settings
+    }
+
+    /// Create a private (0700) prefixed directory, owned until the guard is dropped.
+    pub fn create_dir(&self, purpose: &str) -> io::Result<TrackedDir<'_>> {
+        let dir = self.register_dir(purpose)?;
+        Ok(TrackedDir { dir, manager: self })
+    }
+
+    /// Like [`create_dir`](Self::create_dir), but the guard keeps the manager alive so it can
+    /// be held across requests (resumable upload sessions).
+    pub fn create_shared_dir(self: &Arc<Self>, purpose: &str) -> io::Result<SharedTrackedDir> {
+        let dir = self.register_dir(purpose)?;
+        Ok(SharedTrackedDir {
+            dir,
+            manager: self.clone(),
+        })
+    }
+
+    fn register_dir(&self, purpose: &str) -> io::Result<tempfile::TempDir> {
+        let dir = tempfile::Builder::new()
+            .prefix(&format!("{TMP_PREFIX}{purpose}-"))
+            .tempdir_in(&self.settings.base)?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o700))?;
+        }
+
+        self.owned.lock().unwrap().insert(dir.path().to_path_buf());
+        Ok(dir)
+    }
+
+    pub fn is_owned(&self, path: &Path) -> bool {
+        self.owned.lock().unwrap().contains(path)
+    }
+
+    pub fn owned_count(&self) -> usize {
+        self.owned.lock().unwrap().len()
+    }
+
+    /// Prefixed entries directly under the base directory.
+    fn prefixed_entries(&self) -> io::Result<Vec<fs::DirEntry>> {
+        let mut out = vec![];
+        for entry in fs::read_dir(&self.settings.base)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with(TMP_PREFIX) {
+                out.push(entry);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Remove unowned prefixed entries older than the orphan age.
+    pub fn cleanup_orphans(&self) -> io::Result<CleanupReport> {
+        let mut report = CleanupReport::default();
+        let now = SystemTime::now();
+
+        for entry in self.prefixed_entries()? {
+            let path = entry.path();
+            if self.is_owned(&path) {
+                report.skipped_in_use += 1;
+                continue;
+            }
+            let Ok(meta) = fs::symlink_metadata(&path) else {
+                continue;
+            };
+            let age = meta
+                .modified()
+                .ok()
+                .and_then(|m| now.duration_since(m).ok())
+                .unwrap_or_default();
+            if age < self.settings.orphan_max_age {
+                report.skipped_recent += 1;
+                continue;
+            }
+
+            let bytes = disk_usage(&path);
+            let res = if meta.is_dir() {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            match res {
+                Ok(()) => {
+                    report.removed += 1;
+                    report.removed_bytes += bytes;
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), "cannot remove orphaned temp entry: {e}");
+                    report.failed += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Measure usage and free space now, and log once when usage crosses the soft limit.
+    pub fn refresh(&self) -> TmpUsage {
+        let used_bytes = self
+            .prefixed_entries()
+            .map(|entries| entries.iter().map(|e| disk_usage(&e.path())).sum())
+            .unwrap_or(0);
+        let usage = TmpUsage {
+            used_bytes,
+            free_bytes: free_space(&self.settings.base),
+            owned_entries: self.owned_count(),
+            checked_unix: SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        };
+
+        let over = usage.used_bytes > self.settings.soft_limit_bytes;
+        if over && !self.over_soft_limit.swap(true, Ordering::SeqCst) {
+            tracing::warn!(
+                tmpdir = %self.settings.base.display(),
+                used_bytes = usage.used_bytes,
+                soft_limit_bytes = self.settings.soft_limit_bytes,
+                "Extractor temp usage crossed the soft limit"
+            );
+        } else if !over {
+            self.over_soft_limit.store(false, Ordering::SeqCst);
+        }
+
+        *self.last.lock().unwrap() = usage.clone();
+        usage
+    }
+
+    /// Last figures recorded by [`refresh`](Self::refresh).
+    pub fn last_usage(&self) -> TmpUsage {
+        self.last.lock().unwrap().clone()
+    }
+
+    /// Refuse new extraction work when free space is below the floor.
+    ///
+    /// Checked live (one `statvfs`) rather than from the monitor's last sample, so a burst of
+    /// uploads cannot outrun the monitor interval. Platforms without free-space figures pass.
+    pub fn check_capacity(&self) -> Result<(), StorageExhausted> {
+        match free_space(&self.settings.base) {
+            Some(free) if free < self.settings.min_free_bytes => Err(StorageExhausted {
+                free_bytes: free,
+                min_free_bytes: self.settings.min_free_bytes,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        let u = self.last_usage();
+        serde_json::json!({
+            "path": self.settings.base.display().to_string(),
+            "used_bytes": u.used_bytes,
+            "free_bytes": u.free_bytes,
+            "owned_entries": self.owned_count(),
+            "checked_unix": u.checked_unix,
+            "min_free_bytes": self.settings.min_free_bytes,
+            "soft_limit_bytes": self.settings.soft_limit_bytes,
+            "over_soft_limit": self.over_soft_limit.load(Ordering::SeqCst),
+        })
+    }
+}
+
+/// A registered temp directory; removed and unregistered on drop.
+pub struct TrackedDir<'a> {
+    dir: tempfile::TempDir,
+    manager: &'a TmpDirManager,
+}
+
+impl TrackedDir<'_> {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for TrackedDir<'_> {
+    fn drop(&mut self) {
+        // `self.dir` removes the directory when it drops right after this.
+        self.manager.owned.lock().unwrap().remove(self.dir.path());
+    }
+}
+
+/// A registered temp directory that owns a handle on its manager; removed on drop.
+pub struct SharedTrackedDir {
+    dir: tempfile::TempDir,
+    manager: Arc<TmpDirManager>,
+}
+
+impl SharedTrackedDir {
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+}
+
+impl Drop for SharedTrackedDir {
+    fn drop(&mut self) {
+        self.manager.owned.lock().unwrap().remove(self.dir.path());
+    }
+}
+
+/// Bytes used by `path` (recursively for directories; symlinks are not followed).
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| disk_usage(&e.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // statvfs field widths differ between targets.
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    let rc = unsafe { libc::statvfs(c.as_ptr(), &mut st) };
+    if rc != 0 {
+        return None;
+    }
+    Some((st.f_bavail as u64).saturating_mul(st.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Sweep orphans once, then refresh usage every `monitor_interval` in the background.
+pub fn start(tmp: Arc<TmpDirManager>) {
+    match tmp.cleanup_orphans() {
+        Ok(r) => tracing::info!(
+            tmpdir = %tmp.settings.base.display(),
+            removed = r.removed,
+            removed_bytes = r.removed_bytes,
+            skipped_recent = r.skipped_recent,
+            failed = r.failed,
+            "Extractor temp orphan cleanup finished"
+        ),
+        Err(e) => tracing::warn!(
+            tmpdir = %tmp.settings.base.display(),
+            "Extractor temp orphan cleanup skipped: {e}"
+        ),
+    }
+
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(tmp.settings.monitor_interval);
+        loop {
+            tick.tick().await;
+            let t = tmp.clone();
+            let _ = tokio::task::spawn_blocking(move || t.refresh()).await;
+        }
+    });
+}
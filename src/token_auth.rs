@@ -1,84 +1,290 @@
+//! `X-ACIP-Token` authentication with per-token scopes.
+//!
+//! A [`TokenSet`] holds named tokens, each granting a set of [`Scope`]s. The auth layer resolves
+//! the presented token (comparing against every token, in constant time per token) and attaches
+//! the matching [`Actor`] to the request; route groups then declare the scope they need with
+//! [`require_scope`]. A bad or missing token is a 401; a valid token without the scope is a 403
+//! naming the missing scope. Token names (never values) identify the actor in logs and audits.
+
 use crate::introspection;
+use crate::secrets::Secret;
+use anyhow::{anyhow, Result};
 use axum::{
-    extract::State,
+    extract::{Request, State},
     http::{HeaderMap, StatusCode},
-    middleware::{from_fn_with_state, Next},
-    response::IntoResponse,
-    Router,
+    middleware::{from_fn, from_fn_with_state, Next},
+    response::{IntoResponse, Response},
+    Extension, Router,
 };
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeSet, sync::Arc};
+
+/// Permission granted to a token. Names are the snake_case forms used in config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// Read-only API routes (schema, policies, status, reputation, stats).
+    Read,
+    /// Submitting content to `/v1/acip/ingest_source`.
+    Ingest,
+    PolicyAdmin,
+    ReputationAdmin,
+    QuarantineRead,
+    Purge,
+    /// Maintenance drain/resume.
+    Drain,
+    /// Slow request forensics, including `X-ACIP-Force-Timing`.
+    Support,
+    /// Cross-tenant aggregate stats.
+    PlatformAdmin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 9] = [
+        Scope::Read,
+        Scope::Ingest,
+        Scope::PolicyAdmin,
+        Scope::ReputationAdmin,
+        Scope::QuarantineRead,
+        Scope::Purge,
+        Scope::Drain,
+        Scope::Support,
+        Scope::PlatformAdmin,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Ingest => "ingest",
+            Scope::PolicyAdmin => "policy_admin",
+            Scope::ReputationAdmin => "reputation_admin",
+            Scope::QuarantineRead => "quarantine_read",
+            Scope::Purge => "purge",
+            Scope::Drain => "drain",
+            Scope::Support => "support",
+            Scope::PlatformAdmin => "platform_admin",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Scope> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s.trim())
+    }
+}
+
+/// Parse config scope names, naming the first unknown one.
+pub fn parse_scopes(names: &[String]) -> Result<BTreeSet<Scope>> {
+    names
+        .iter()
+        .map(|n| {
+            Scope::parse(n).ok_or_else(|| {
+                let known: Vec<&str> = Scope::ALL.iter().map(|s| s.as_str()).collect();
+                anyhow!("unknown scope {n:?} (known: {})", known.join(", "))
+            })
+        })
+        .collect()
+}
+
+/// Who made a request: the name of the token presented and what it may do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor {
+    pub name: String,
+    pub scopes: BTreeSet<Scope>,
+}
+
+impl Actor {
+    /// Name used when authentication is disabled.
+    pub const ANONYMOUS: &'static str = "anonymous";
+
+    /// Requests on a server without tokens; everything is allowed, as before scopes existed.
+    pub fn anonymous() -> Self {
+        Self {
+            name: Self::ANONYMOUS.to_string(),
+            scopes: Scope::ALL.into_iter().collect(),
+        }
+    }
+
+    pub fn has(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+/// Token name behind a request, for logs and audit fields.
+pub fn actor_name(actor: Option<Extension<Actor>>) -> String {
+    actor
+        .map(|Extension(a)| a.name)
+        .unwrap_or_else(|| Actor::ANONYMOUS.to_string())
+}
+
+struct NamedToken {
+    name: String,
+    value: Secret,
+    scopes: BTreeSet<Scope>,
+}
+
+#[derive(Default)]
+pub struct TokenSet {
+    tokens: Vec<NamedToken>,
+}
+
+impl TokenSet {
+    /// Name given to the single `security.token_env` token.
+    pub const LEGACY_NAME: &'static str = "legacy";
+
+    /// The single-token setup: one token (if any) with every scope.
+    pub fn legacy(token: Option<Secret>) -> Self {
+        let mut set = Self::default();
+        if let Some(value) = token {
+            set.tokens.push(NamedToken {
+                name: Self::LEGACY_NAME.to_string(),
+                value,
+                scopes: Scope::ALL.into_iter().collect(),
+            });
+        }
+        set
+    }
+
+    /// Add a named token. Names and values must be unique and non-empty.
+    pub fn add(&mut self, name: &str, value: &str, scopes: BTreeSet<Scope>) -> Result<()> {
+        let (name, value) = (name.trim(), value.trim());
+        if name.is_empty() || value.is_empty() {
+            return Err(anyhow!("token name and value must be non-empty"));
+        }
+        if self.tokens.iter().any(|t| t.name == name) {
+            return Err(anyhow!("duplicate token name {name:?}"));
+        }
+        if self.tokens.iter().any(|t| t.value.ct_eq(value)) {
+            return Err(anyhow!(
+                "token {name:?} has the same value as another token"
+            ));
+        }
+        self.tokens.push(NamedToken {
+            name: name.to_string(),
+            value: Secret::new(value),
+            scopes,
+        });
+        Ok(())
+    }
+
+    /// Whether any token is configured (otherwise authentication is off).
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.tokens.iter().map(|t| t.name.clone()).collect()
+    }
+
+    /// The actor for a presented token value.
+    ///
+    /// Every token is compared, whatever matched earlier, so timing does not reveal which
+    /// token (or how much of one) matched.
+    pub fn resolve(&self, presented: &str) -> Option<Actor> {
+        let mut found: Option<&NamedToken> = None;
+        for t in &self.tokens {
+            if t.value.ct_eq(presented) && found.is_none() {
+                found = Some(t);
+            }
+        }
+        found.map(|t| Actor {
+            name: t.name.clone(),
+            scopes: t.scopes.clone(),
+        })
+    }
+}
 
 /// Apply X-ACIP-Token authentication to a router.
 ///
-/// If `token` is None, authentication is disabled and all requests are allowed.
-pub fn with_token_auth<S>(router: Router<S>, token: Option<String>) -> Router<S>
+/// If `tokens` is empty, authentication is disabled and requests run as [`Actor::anonymous`].
+pub fn with_token_auth<S>(router: Router<S>, tokens: Arc<TokenSet>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router.layer(from_fn_with_state(tokens, token_auth_middleware))
+}
+
+/// Like [`with_token_auth`], but for admin routes: if no token is configured they are refused
+/// (403) instead of left open. Paths that match no admin route still answer 404.
+pub fn with_admin_token_auth<S>(router: Router<S>, tokens: Arc<TokenSet>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    if tokens.is_enabled() {
+        with_token_auth(router, tokens)
+    } else {
+        router.route_layer(from_fn(admin_disabled_middleware))
+    }
+}
+
+/// Refuse requests whose actor lacks `scope` (403 `insufficient_scope`).
+///
+/// Must sit inside [`with_token_auth`]; routes without an auth layer run as anonymous.
+pub fn require_scope<S>(router: Router<S>, scope: Scope) -> Router<S>
 where
     S: Clone + Send + Sync + 'static,
 {
-    router.layer(from_fn_with_state(token, token_auth_middleware))
+    router.layer(from_fn_with_state(scope, scope_middleware))
+}
+
+async fn admin_disabled_middleware(_req: Request, _next: Next) -> Response {
+    introspection::json_error(
+        StatusCode::FORBIDDEN,
+        "admin_disabled",
+        serde_json::json!({"reason": "no auth token configured"}),
+    )
+    .into_response()
+}
+
+async fn scope_middleware(State(scope): State<Scope>, req: Request, next: Next) -> Response {
+    let actor = req
+        .extensions()
+        .get::<Actor>()
+        .cloned()
+        .unwrap_or_else(Actor::anonymous);
+    if actor.has(scope) {
+        return next.run(req).await;
+    }
+    introspection::json_error(
+        StatusCode::FORBIDDEN,
+        "insufficient_scope",
+        serde_json::json!({"required": scope.as_str(), "token": actor.name}),
+    )
+    .into_response()
+}
+
+fn unauthorized(extra: serde_json::Value) -> Response {
+    introspection::json_error(StatusCode::UNAUTHORIZED, "unauthorized", extra).into_response()
 }
 
 async fn token_auth_middleware(
-    State(token): State<Option<String>>,
+    State(tokens): State<Arc<TokenSet>>,
     headers: HeaderMap,
-    req: axum::http::Request<axum::body::Body>,
+    mut req: Request,
     next: Next,
-) -> axum::response::Response {
-    let Some(expected) = token else {
+) -> Response {
+    if !tokens.is_enabled() {
+        req.extensions_mut().insert(Actor::anonymous());
         return next.run(req).await;
-    };
+    }
 
     let mut values = headers.get_all("x-acip-token").iter();
     let Some(value) = values.next() else {
-        return introspection::json_error(
-            StatusCode::UNAUTHORIZED,
-            "unauthorized",
-            serde_json::json!({"missing": true}),
-        )
-        .into_response();
+        return unauthorized(serde_json::json!({"missing": true}));
     };
 
     if values.next().is_some() {
-        return introspection::json_error(
-            StatusCode::UNAUTHORIZED,
-            "unauthorized",
-            serde_json::json!({"invalid": true}),
-        )
-        .into_response();
+        return unauthorized(serde_json::json!({"invalid": true}));
     }
 
-    let got = match value.to_str() {
-        Ok(value) => value.trim(),
-        Err(_) => {
-            return introspection::json_error(
-                StatusCode::UNAUTHORIZED,
-                "unauthorized",
-                serde_json::json!({"invalid": true}),
-            )
-            .into_response();
-        }
+    let Ok(got) = value.to_str() else {
+        return unauthorized(serde_json::json!({"invalid": true}));
     };
 
-    if !constant_time_eq(got, &expected) {
-        return introspection::json_error(
-            StatusCode::UNAUTHORIZED,
-            "unauthorized",
-            serde_json::json!({"invalid": true}),
-        )
-        .into_response();
-    }
+    let Some(actor) = tokens.resolve(got.trim()) else {
+        return unauthorized(serde_json::json!({"invalid": true}));
+    };
 
+    req.extensions_mut().insert(actor);
     next.run(req).await
 }
-
-fn constant_time_eq(a: &str, b: &str) -> bool {
-    let a_bytes = a.as_bytes();
-    let b_bytes = b.as_bytes();
-    let mut diff = a_bytes.len() ^ b_bytes.len();
-    let max = a_bytes.len().max(b_bytes.len());
-    for i in 0..max {
-        let av = *a_bytes.get(i).unwrap_or(&0);
-        let bv = *b_bytes.get(i).unwrap_or(&0);
-        diff |= (av ^ bv) as usize;
-    }
-    diff == 0
-}
@@ -0,0 +1,319 @@
+//! Token-aware budget for the model-bound prompt.
+//!
+//! The head/tail policy limits content by characters, but providers limit prompts by tokens,
+//! and a CJK or emoji-heavy document within the character limits can still be over a model's
+//! context window. Each model level therefore gets a content allowance of its context window
+//! ([`context_tokens`]) minus the prompt template and the completion budget, and content over
+//! it is trimmed from the middle with the same marker as the head/tail truncation
+//! ([`trim_to_budget`]).
+//!
+//! Tokens are counted with a [`TokenEstimator`]: the calibrated per-script [`Heuristic`] by
+//! default, or, in builds with the `bpe` feature, a byte-pair tokenizer for any model whose
+//! ranks file (`<model>.tiktoken`) is in `ACIP_TOKENIZER_DIR`.
+
+use crate::model_policy::{ModelRef, Provider};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Output tokens requested from every provider (`maxOutputTokens` / `max_tokens`).
+pub const COMPLETION_TOKENS: u64 = 1024;
+
+/// Marks the omitted middle of trimmed content; the same marker as head/tail truncation.
+pub const TRUNCATION_MARKER: &str = "\n\n[...TRUNCATED...]\n\n";
+
+/// Counts the tokens a model will see for some text.
+pub trait TokenEstimator: Send + Sync {
+    /// `heuristic`, or `bpe:<model>`.
+    fn name(&self) -> String;
+    fn estimate(&self, text: &str) -> u64;
+}
+
+/// Characters-per-token estimate with per-script weights.
+///
+/// Calibrated to over- rather than under-count: ASCII text at the provider's characters per
+/// token, other alphabets at two characters per token, Indic and Thai at one, CJK ideographs
+/// and Hangul at 1.25 tokens per character, and emoji (anything outside the BMP) at 3.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heuristic {
+    /// Characters per token of plain ASCII prose.
+    pub ascii_chars_per_token: f64,
+}
+
+impl Heuristic {
+    pub fn for_provider(provider: &Provider) -> Self {
+        let ascii_chars_per_token = match provider {
+            Provider::Gemini => 4.0,
+            Provider::Anthropic => 3.5,
+        };
+        Self {
+            ascii_chars_per_token,
+        }
+    }
+
+    /// Tokens per character of `c`.
+    fn weight(&self, c: char) -> f64 {
+        match c as u32 {
+            _ if c.is_ascii_alphanumeric() || c == ' ' => 1.0 / self.ascii_chars_per_token,
+            0x0A | 0x0D | 0x09 => 0.5,
+            0x00..=0x7F => 0.5,
+            // Latin-1, Latin Extended, IPA, combining marks, Greek, Cyrillic, Armenian,
+            // Hebrew, Arabic.
+            0x80..=0x06FF => 0.5,
+            // Indic scripts, Sinhala, Thai, Lao.
+            0x0900..=0x0EFF => 1.0,
+            // Zero-width joiner and variation selectors inside emoji sequences.
+            0x200D | 0xFE00..=0xFE0F => 1.0,
+            // Miscellaneous symbols and dingbats (many render as emoji).
+            0x2600..=0x27BF => 2.0,
+            // CJK punctuation, kana, CJK ideographs, Hangul, compatibility ideographs,
+            // full-width forms.
+            0x3000..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF => 1.25,
+            0xAC00..=0xD7AF | 0xF900..=0xFAFF | 0xFF00..=0xFFEF => 1.25,
+            0x10000.. => 3.0,
+            _ => 1.0,
+        }
+    }
+}
+
+impl TokenEstimator for Heuristic {
+    fn name(&self) -> String {
+        "heuristic".to_string()
+    }
+
+    fn estimate(&self, text: &str) -> u64 {
+        text.chars().map(|c| self.weight(c)).sum::<f64>().ceil() as u64
+    }
+}
+
+/// Context window of `model`: `context_tokens` from the policy, else the provider's window for
+/// that model family.
+pub fn context_tokens(model: &ModelRef) -> u64 {
+    if let Some(n) = model.context_tokens {
+        return n;
+    }
+    match model.provider {
+        Provider::Gemini if model.model.starts_with("gemini-1.5-pro") => 2_097_152,
+        Provider::Gemini => 1_048_576,
+        Provider::Anthropic => 200_000,
+    }
+}
+
+/// The estimator for `model` (see the module docs).
+pub fn estimator_for(model: &ModelRef) -> Arc<dyn TokenEstimator> {
+    #[cfg(feature = "bpe")]
+    if let Some(bpe) = bpe::for_model(&model.model) {
+        return bpe;
+    }
+    Arc::new(Heuristic::for_provider(&model.provider))
+}
+
+/// `content` cut to at most `max_tokens` by `est`: the middle is replaced with
+/// [`TRUNCATION_MARKER`], keeping as much of the head and tail (the head gets the odd
+/// character) as fits. True when anything was cut. The result depends only on the inputs.
+pub fn trim_to_budget(content: &str, max_tokens: u64, est: &dyn TokenEstimator) -> (String, bool) {
+    if est.estimate(content) <= max_tokens {
+        return (content.to_string(), false);
+    }
+    let bounds: Vec<usize> = content
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([content.len()])
+        .collect();
+    let chars = bounds.len() - 1;
+    let keep = |k: usize| {
+        let head = k.div_ceil(2);
+        let tail = k - head;
+        format!(
+            "{}{TRUNCATION_MARKER}{}",
+            &content[..bounds[head]],
+            &content[bounds[chars - tail]..]
+        )
+    };
+    // Largest number of kept characters that fits.
+    let (mut lo, mut hi) = (0, chars);
+    while lo < hi {
+        let mid = (lo + hi).div_ceil(2);
+        if est.estimate(&keep(mid)) <= max_tokens {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    (keep(lo), true)
+}
+
+/// How the prompt of one model call was fitted to its model.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptBudget {
+    pub model: String,
+    pub estimator: String,
+    pub context_tokens: u64,
+    pub completion_tokens: u64,
+    /// Context window minus the completion budget.
+    pub max_prompt_tokens: u64,
+    /// The prompt without its content.
+    pub template_tokens: u64,
+    /// What the content may use: `max_prompt_tokens` minus `template_tokens`; on a retry after
+    /// the provider reported the context exceeded, half of what the first call sent.
+    pub max_content_tokens: u64,
+    /// The content before trimming.
+    pub estimated_content_tokens: u64,
+    /// The prompt as sent.
+    pub prompt_tokens: u64,
+    pub trimmed: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub context_retry: bool,
+}
+
+/// The prompt `render(content)` for `model`, with `content` trimmed to its allowance.
+/// `max_content_tokens` overrides the allowance (the halved budget of a retry).
+pub fn fit_prompt(
+    model: &ModelRef,
+    content: &str,
+    max_content_tokens: Option<u64>,
+    render: impl Fn(&str) -> String,
+) -> (String, PromptBudget) {
+    let est = estimator_for(model);
+    let context = context_tokens(model);
+    let max_prompt_tokens = context.saturating_sub(COMPLETION_TOKENS);
+    let template_tokens = est.estimate(&render(""));
+    let allowance = max_prompt_tokens.saturating_sub(template_tokens);
+    let max_content = max_content_tokens.map_or(allowance, |m| m.min(allowance));
+    let estimated_content_tokens = est.estimate(content);
+    let (content, trimmed) = trim_to_budget(content, max_content, est.as_ref());
+    let prompt = render(&content);
+    let budget = PromptBudget {
+        model: model.label(),
+        estimator: est.name(),
+        context_tokens: context,
+        completion_tokens: COMPLETION_TOKENS,
+        max_prompt_tokens,
+        template_tokens,
+        max_content_tokens: max_content,
+        estimated_content_tokens,
+        prompt_tokens: est.estimate(&prompt),
+        trimmed,
+        context_retry: max_content_tokens.is_some(),
+    };
+    (prompt, budget)
+}
+
+#[cfg(feature = "bpe")]
+pub mod bpe {
+    //! Byte-pair tokenizer over a tiktoken ranks file (one `<base64 token> <rank>` per line).
+    //!
+    //! Text is pre-split like `cl100k_base` except for its `\s+(?!\S)` rule (lookahead is not
+    //! available), which only moves where runs of spaces before a word are split.
+
+    use super::TokenEstimator;
+    use anyhow::{anyhow, Context, Result};
+    use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    const SPLIT: &str = r"(?i:'s|'t|'re|'ve|'m|'ll|'d)|[^\r\n\p{L}\p{N}]?\p{L}+|\p{N}{1,3}| ?[^\s\p{L}\p{N}]+[\r\n]*|\s*[\r\n]+|\s+";
+
+    pub struct BpeTokenizer {
+        name: String,
+        ranks: HashMap<Vec<u8>, u32>,
+        split: regex::Regex,
+    }
+
+    impl BpeTokenizer {
+        pub fn from_ranks(name: &str, ranks: HashMap<Vec<u8>, u32>) -> Self {
+            Self {
+                name: name.to_string(),
+                ranks,
+                split: regex::Regex::new(SPLIT).expect("split pattern compiles"),
+            }
+        }
+
+        pub fn load(name: &str, path: &Path) -> Result<Self> {
+            let raw = std::fs::read_to_string(path).with_context(|| format!("read {path:?}"))?;
+            let mut ranks = HashMap::new();
+            for (n, line) in raw
+                .lines()
+                .enumerate()
+                .filter(|(_, l)| !l.trim().is_empty())
+            {
+                let (token, rank) = line
+                    .split_once(' ')
+                    .ok_or_else(|| anyhow!("{path:?}:{}: expected `<token> <rank>`", n + 1))?;
+                let token = B64
+                    .decode(token)
+                    .with_context(|| format!("{path:?}:{}: token is not base64", n + 1))?;
+                let rank = rank
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("{path:?}:{}: rank is not a number", n + 1))?;
+                ranks.insert(token, rank);
+            }
+            Ok(Self::from_ranks(name, ranks))
+        }
+
+        /// Tokens of one pre-split piece: merge the lowest-ranked adjacent pair until none
+        /// is in the vocabulary.
+        fn piece_tokens(&self, piece: &[u8]) -> u64 {
+            if self.ranks.contains_key(piece) {
+                return 1;
+            }
+            let mut parts: Vec<usize> = (0..=piece.len()).collect();
+            loop {
+                let best = (0..parts.len().saturating_sub(2))
+                    .filter_map(|i| {
+                        self.ranks
+                            .get(&piece[parts[i]..parts[i + 2]])
+                            .map(|r| (*r, i))
+                    })
+                    .min();
+                match best {
+                    Some((_, i)) => {
+                        parts.remove(i + 1);
+                    }
+                    None => return (parts.len() - 1) as u64,
+                }
+            }
+        }
+    }
+
+    impl TokenEstimator for BpeTokenizer {
+        fn name(&self) -> String {
+            format!("bpe:{}", self.name)
+        }
+
+        fn estimate(&self, text: &str) -> u64 {
+            self.split
+                .find_iter(text)
+                .map(|m| self.piece_tokens(m.as_str().as_bytes()))
+                .sum()
+        }
+    }
+
+    type Loaded = HashMap<String, Option<Arc<BpeTokenizer>>>;
+    static LOADED: Mutex<Option<Loaded>> = Mutex::new(None);
+
+    /// The tokenizer in `$ACIP_TOKENIZER_DIR/<model>.tiktoken`, loaded once; `None` when
+    /// there is none (or it does not load, which is logged once).
+    pub fn for_model(model: &str) -> Option<Arc<dyn TokenEstimator>> {
+        let dir = std::env::var("ACIP_TOKENIZER_DIR").ok()?;
+        let mut loaded = LOADED.lock().unwrap();
+        let entry = loaded
+            .get_or_insert_with(HashMap::new)
+            .entry(model.to_string())
+            .or_insert_with(|| {
+                let path = Path::new(&dir).join(format!("{model}.tiktoken"));
+                if !path.exists() {
+                    return None;
+                }
+                match BpeTokenizer::load(model, &path) {
+                    Ok(t) => Some(Arc::new(t)),
+                    Err(e) => {
+                        tracing::warn!("tokenizer for {model} not loaded; estimating: {e:#}");
+                        None
+                    }
+                }
+            });
+        entry.clone().map(|t| t as Arc<dyn TokenEstimator>)
+    }
+}
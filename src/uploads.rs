@@ -0,0 +1,736 @@
+//! Resumable uploads for large sources sent over unreliable links.
+//!
+//! A client creates a session with the source metadata and total size, then PUTs fixed-size
+//! chunks in any order. Each chunk carries its SHA-256, so re-sending a chunk that is already
+//! stored is a verified no-op. After a dropped connection the client asks which chunks are
+//! present and sends only the rest. Completing with the whole-file SHA-256 assembles the chunks
+//! inside the session's tracked temp directory and runs the normal ingest pipeline with the
+//! metadata and policy headers given at creation.
+//!
+//! Sessions live in memory only. Each accepted chunk pushes the expiry out by the TTL; a
+//! background sweep removes expired sessions together with their directories. The number of open
+//! sessions and the size of each are bounded.
+
+use crate::incidents;
+use crate::ingest::{self, SourceMeta};
+use crate::introspection;
+use crate::reputation::{self, Clock};
+use crate::slow_requests;
+use crate::state::AppState;
+use crate::tmpdir::{SharedTrackedDir, StorageExhausted, TmpDirManager};
+use crate::token_auth::{self, Actor};
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Read, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Header carrying the hex SHA-256 of a chunk body.
+pub const CHUNK_SHA256_HEADER: &str = "x-acip-chunk-sha256";
+
+pub const DEFAULT_CHUNK_BYTES: u64 = 4 * MB;
+pub const DEFAULT_MAX_UPLOAD_BYTES: u64 = 256 * MB;
+pub const DEFAULT_MAX_SESSIONS: usize = 8;
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 3600;
+pub const DEFAULT_SWEEP_INTERVAL_SECS: u64 = 60;
+
+const MB: u64 = 1024 * 1024;
+
+/// Name of the assembled file inside a session directory.
+const ASSEMBLED_FILE: &str = "assembled";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadSettings {
+    pub chunk_bytes: u64,
+    /// Largest `total_bytes` a session may declare.
+    pub max_upload_bytes: u64,
+    /// Open sessions allowed at once; creation beyond this is refused.
+    pub max_sessions: usize,
+    /// Idle time after which a session expires.
+    pub session_ttl: Duration,
+    pub sweep_interval: Duration,
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        Self {
+            chunk_bytes: DEFAULT_CHUNK_BYTES,
+            max_upload_bytes: DEFAULT_MAX_UPLOAD_BYTES,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+            session_ttl: Duration::from_secs(DEFAULT_SESSION_TTL_SECS),
+            sweep_interval: Duration::from_secs(DEFAULT_SWEEP_INTERVAL_SECS),
+        }
+    }
+}
+
+fn env_u64(key: &str) -> Option<u64> {
+    std::env::var(key).ok().and_then(|v| v.trim().parse().ok())
+}
+
+impl UploadSettings {
+    pub fn from_env() -> Self {
+        let mut s = Self::default();
+        if let Some(v) = env_u64("ACIP_UPLOAD_CHUNK_KB") {
+            s.chunk_bytes = v.max(1) * 1024;
+        }
+        if let Some(v) = env_u64("ACIP_UPLOAD_MAX_MB") {
+            s.max_upload_bytes = v * MB;
+        }
+        if let Some(v) = env_u64("ACIP_UPLOAD_MAX_SESSIONS") {
+            s.max_sessions = v as usize;
+        }
+        if let Some(v) = env_u64("ACIP_UPLOAD_TTL_SECS") {
+            s.session_ttl = Duration::from_secs(v.max(1));
+        }
+        if let Some(v) = env_u64("ACIP_UPLOAD_SWEEP_SECS") {
+            s.sweep_interval = Duration::from_secs(v.max(1));
+        }
+        s
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum UploadError {
+    #[error("unknown upload session {0}")]
+    NotFound(String),
+    #[error("total_bytes must be between 1 and {max_bytes}")]
+    InvalidSize { total_bytes: u64, max_bytes: u64 },
+    #[error("too many open upload sessions (max {max_sessions})")]
+    TooManySessions { max_sessions: usize },
+    #[error("chunk {index} out of range (chunk_count {chunk_count})")]
+    ChunkOutOfRange { index: u64, chunk_count: u64 },
+    #[error("chunk {index} has {actual} bytes, expected {expected}")]
+    ChunkLength {
+        index: u64,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("chunk {index} checksum mismatch")]
+    ChunkChecksum {
+        index: u64,
+        expected: String,
+        actual: String,
+    },
+    #[error("chunk {index} already stored with a different checksum")]
+    ChunkConflict { index: u64, stored: String },
+    #[error("upload incomplete ({} chunks missing)", .missing.len())]
+    Incomplete { missing: Vec<u64> },
+    #[error("assembled upload hash mismatch")]
+    HashMismatch { expected: String, actual: String },
+    #[error("upload is being completed")]
+    Completing,
+    #[error(transparent)]
+    Storage(#[from] StorageExhausted),
+    #[error("upload storage error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl IntoResponse for UploadError {
+    fn into_response(self) -> Response {
+        let (status, msg, extra) = match &self {
+            Self::NotFound(id) => (
+                StatusCode::NOT_FOUND,
+                "unknown_upload",
+                json!({"upload_id": id}),
+            ),
+            Self::InvalidSize {
+                total_bytes,
+                max_bytes,
+            } => {
+                let status = if *total_bytes > *max_bytes {
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    StatusCode::BAD_REQUEST
+                };
+                (
+                    status,
+                    "invalid_upload_size",
+                    json!({"total_bytes": total_bytes, "max_bytes": max_bytes}),
+                )
+            }
+            Self::TooManySessions { max_sessions } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too_many_uploads",
+                json!({"max_sessions": max_sessions}),
+            ),
+            Self::ChunkOutOfRange { index, chunk_count } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_chunk_index",
+                json!({"index": index, "chunk_count": chunk_count}),
+            ),
+            Self::ChunkLength {
+                index,
+                expected,
+                actual,
+            } => (
+                StatusCode::BAD_REQUEST,
+                "invalid_chunk_length",
+                json!({"index": index, "expected": expected, "actual": actual}),
+            ),
+            Self::ChunkChecksum {
+                index,
+                expected,
+                actual,
+            } => (
+                StatusCode::BAD_REQUEST,
+                "chunk_checksum_mismatch",
+                json!({"index": index, "expected": expected, "actual": actual}),
+            ),
+            Self::ChunkConflict { index, stored } => (
+                StatusCode::CONFLICT,
+                "chunk_conflict",
+                json!({"index": index, "stored_sha256": stored}),
+            ),
+            Self::Incomplete { missing } => (
+                StatusCode::CONFLICT,
+                "upload_incomplete",
+                json!({"missing": missing}),
+            ),
+            Self::HashMismatch { expected, actual } => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "upload_hash_mismatch",
+                json!({"expected": expected, "actual": actual}),
+            ),
+            Self::Completing => (StatusCode::CONFLICT, "upload_completing", json!({})),
+            Self::Storage(e) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "storage_exhausted",
+                json!({"free_bytes": e.free_bytes, "min_free_bytes": e.min_free_bytes}),
+            ),
+            Self::Io(e) => {
+                tracing::error!("upload storage error: {e}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "upload_storage_error",
+                    json!({}),
+                )
+            }
+        };
+        introspection::json_error(status, msg, extra).into_response()
+    }
+}
+
+/// Session state as reported to clients.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UploadStatus {
+    pub upload_id: String,
+    pub source_id: String,
+    pub total_bytes: u64,
+    pub chunk_bytes: u64,
+    pub chunk_count: u64,
+    /// Chunk indexes already stored, ascending.
+    pub received: Vec<u64>,
+    /// Chunk indexes still to send, ascending.
+    pub missing: Vec<u64>,
+    pub created_unix: u64,
+    pub expires_unix: u64,
+}
+
+struct Session {
+    meta: SourceMeta,
+    /// Creation request headers (policy and tool selection), minus credentials.
+    headers: HeaderMap,
+    total_bytes: u64,
+    chunk_bytes: u64,
+    chunk_count: u64,
+    /// Chunk index -> hex SHA-256 of the stored chunk.
+    chunks: BTreeMap<u64, String>,
+    created_unix: u64,
+    expires_unix: u64,
+    completing: bool,
+    dir: SharedTrackedDir,
+}
+
+impl Session {
+    fn status(&self, id: &str) -> UploadStatus {
+        UploadStatus {
+            upload_id: id.to_string(),
+            source_id: self.meta.source_id.clone(),
+            total_bytes: self.total_bytes,
+            chunk_bytes: self.chunk_bytes,
+            chunk_count: self.chunk_count,
+            received: self.chunks.keys().copied().collect(),
+            missing: self.missing(),
+            created_unix: self.created_unix,
+            expires_unix: self.expires_unix,
+        }
+    }
+
+    fn missing(&self) -> Vec<u64> {
+        (0..self.chunk_count)
+            .filter(|n| !self.chunks.contains_key(n))
+            .collect()
+    }
+
+    fn chunk_len(&self, index: u64) -> u64 {
+        if index + 1 == self.chunk_count {
+            self.total_bytes - index * self.chunk_bytes
+        } else {
+            self.chunk_bytes
+        }
+    }
+
+    fn chunk_path(&self, index: u64) -> PathBuf {
+        self.dir.path().join(format!("chunk-{index}"))
+    }
+}
+
+/// Result of a chunk PUT.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct ChunkAccepted {
+    pub index: u64,
+    /// The chunk was already stored with the same checksum; nothing was written.
+    pub duplicate: bool,
+    pub remaining: usize,
+}
+
+/// An assembled upload, ready for the ingest pipeline.
+pub struct Assembled {
+    pub meta: SourceMeta,
+    pub headers: HeaderMap,
+    pub bytes: Vec<u8>,
+}
+
+pub struct UploadStore {
+    settings: UploadSettings,
+    tmp: Arc<TmpDirManager>,
+    clock: Arc<dyn Clock>,
+    sessions: Mutex<HashMap<String, Session>>,
+    next_seq: AtomicU64,
+}
+
+impl Default for UploadStore {
+    fn default() -> Self {
+        Self::new(
+            UploadSettings::default(),
+            Arc::new(TmpDirManager::default()),
+            Arc::new(reputation::SystemClock),
+        )
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+impl UploadStore {
+    pub fn new(settings: UploadSettings, tmp: Arc<TmpDirManager>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            settings,
+            tmp,
+            clock,
+            sessions: Mutex::new(HashMap::new()),
+            next_seq: AtomicU64::new(0),
+        }
+    }
+
+    pub fn settings(&self) -> &UploadSettings {
+        &self.settings
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn new_id(&self, dir: &SharedTrackedDir) -> String {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        let seed = format!("{nanos}:{seq}:{}", dir.path().display());
+        sha256_hex(seed.as_bytes())[..32].to_string()
+    }
+
+    /// JSON view for `/status`.
+    pub fn snapshot(&self) -> serde_json::Value {
+        json!({
+            "open_sessions": self.len(),
+            "max_sessions": self.settings.max_sessions,
+            "chunk_bytes": self.settings.chunk_bytes,
+            "max_upload_bytes": self.settings.max_upload_bytes,
+            "session_ttl_secs": self.settings.session_ttl.as_secs(),
+        })
+    }
+
+    /// Open a session for `total_bytes` of input described by `meta`.
+    pub fn create(
+        &self,
+        meta: SourceMeta,
+        headers: &HeaderMap,
+        total_bytes: u64,
+    ) -> Result<UploadStatus, UploadError> {
+        if total_bytes == 0 || total_bytes > self.settings.max_upload_bytes {
+            return Err(UploadError::InvalidSize {
+                total_bytes,
+                max_bytes: self.settings.max_upload_bytes,
+            });
+        }
+        self.tmp.check_capacity()?;
+
+        let mut headers = headers.clone();
+        headers.remove(header::AUTHORIZATION);
+        headers.remove("x-acip-token");
+        headers.remove(header::CONTENT_LENGTH);
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= self.settings.max_sessions {
+            return Err(UploadError::TooManySessions {
+                max_sessions: self.settings.max_sessions,
+            });
+        }
+
+        let dir = self.tmp.create_shared_dir("upload")?;
+        let id = self.new_id(&dir);
+        let now = self.clock.now_unix();
+        let chunk_bytes = self.settings.chunk_bytes;
+        let session = Session {
+            meta,
+            headers,
+            total_bytes,
+            chunk_bytes,
+            chunk_count: total_bytes.div_ceil(chunk_bytes),
+            chunks: BTreeMap::new(),
+            created_unix: now,
+            expires_unix: now + self.settings.session_ttl.as_secs(),
+            completing: false,
+            dir,
+        };
+        let status = session.status(&id);
+        sessions.insert(id, session);
+        Ok(status)
+    }
+
+    pub fn status(&self, id: &str) -> Result<UploadStatus, UploadError> {
+        let sessions = self.sessions.lock().unwrap();
+        let s = sessions
+            .get(id)
+            .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+        Ok(s.status(id))
+    }
+
+    /// Store chunk `index`. Idempotent: the same chunk again is accepted without rewriting.
+    pub fn put_chunk(
+        &self,
+        id: &str,
+        index: u64,
+        sha256: &str,
+        body: &[u8],
+    ) -> Result<ChunkAccepted, UploadError> {
+        let expected = sha256.trim().to_ascii_lowercase();
+        let actual = sha256_hex(body);
+
+        let path = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let s = sessions
+                .get_mut(id)
+                .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+            if s.completing {
+                return Err(UploadError::Completing);
+            }
+            if index >= s.chunk_count {
+                return Err(UploadError::ChunkOutOfRange {
+                    index,
+                    chunk_count: s.chunk_count,
+                });
+            }
+            let len = s.chunk_len(index);
+            if body.len() as u64 != len {
+                return Err(UploadError::ChunkLength {
+                    index,
+                    expected: len,
+                    actual: body.len() as u64,
+                });
+            }
+            if actual != expected {
+                return Err(UploadError::ChunkChecksum {
+                    index,
+                    expected,
+                    actual,
+                });
+            }
+            s.expires_unix = self.clock.now_unix() + self.settings.session_ttl.as_secs();
+            match s.chunks.get(&index) {
+                Some(stored) if *stored == actual => {
+                    return Ok(ChunkAccepted {
+                        index,
+                        duplicate: true,
+                        remaining: s.missing().len(),
+                    });
+                }
+                Some(stored) => {
+                    return Err(UploadError::ChunkConflict {
+                        index,
+                        stored: stored.clone(),
+                    });
+                }
+                None => s.chunk_path(index),
+            }
+        };
+
+        // Write outside the lock, then publish with a rename so a concurrent PUT of the same
+        // chunk or an expiry never sees a partial file recorded as present.
+        let part = path.with_extension(format!(
+            "part-{}",
+            self.next_seq.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::write(&part, body)?;
+        fs::rename(&part, &path)?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        let s = sessions
+            .get_mut(id)
+            .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+        let duplicate = s.chunks.insert(index, actual).is_some();
+        Ok(ChunkAccepted {
+            index,
+            duplicate,
+            remaining: s.missing().len(),
+        })
+    }
+
+    /// Assemble a complete session and check it against `expected_sha256`.
+    ///
+    /// The session is marked as completing until [`finish`](Self::finish) is called; a hash
+    /// mismatch leaves it open so the client can retry.
+    pub fn assemble(&self, id: &str, expected_sha256: &str) -> Result<Assembled, UploadError> {
+        let (chunk_paths, dir, meta, headers) = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let s = sessions
+                .get_mut(id)
+                .ok_or_else(|| UploadError::NotFound(id.to_string()))?;
+            if s.completing {
+                return Err(UploadError::Completing);
+            }
+            let missing = s.missing();
+            if !missing.is_empty() {
+                return Err(UploadError::Incomplete { missing });
+            }
+            s.completing = true;
+            let paths: Vec<PathBuf> = (0..s.chunk_count).map(|n| s.chunk_path(n)).collect();
+            (
+                paths,
+                s.dir.path().to_path_buf(),
+                s.meta.clone(),
+                s.headers.clone(),
+            )
+        };
+
+        let res = assemble_file(&chunk_paths, &dir.join(ASSEMBLED_FILE)).and_then(|actual| {
+            let expected = expected_sha256.trim().to_ascii_lowercase();
+            if actual != expected {
+                return Err(UploadError::HashMismatch { expected, actual });
+            }
+            Ok(fs::read(dir.join(ASSEMBLED_FILE))?)
+        });
+        match res {
+            Ok(bytes) => Ok(Assembled {
+                meta,
+                headers,
+                bytes,
+            }),
+            Err(e) => {
+                self.finish(id, false);
+                Err(e)
+            }
+        }
+    }
+
+    /// End a completion attempt: drop the session on success, reopen it otherwise.
+    pub fn finish(&self, id: &str, ingested: bool) {
+        let mut sessions = self.sessions.lock().unwrap();
+        if ingested {
+            sessions.remove(id);
+        } else if let Some(s) = sessions.get_mut(id) {
+            s.completing = false;
+        }
+    }
+
+    /// Remove expired sessions (and their directories). Returns how many were removed.
+    pub fn sweep_expired(&self) -> usize {
+        let now = self.clock.now_unix();
+        let expired: Vec<Session> = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let ids: Vec<String> = sessions
+                .iter()
+                .filter(|(_, s)| !s.completing && s.expires_unix <= now)
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| sessions.remove(id)).collect()
+        };
+        // Directories are removed as the sessions drop, outside the lock.
+        expired.len()
+    }
+}
+
+/// Concatenate `chunks` into `out`, returning the hex SHA-256 of the result.
+fn assemble_file(chunks: &[PathBuf], out: &std::path::Path) -> Result<String, UploadError> {
+    let mut file = fs::File::create(out)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    for path in chunks {
+        let mut chunk = fs::File::open(path)?;
+        loop {
+            let n = chunk.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            file.write_all(&buf[..n])?;
+        }
+    }
+    file.flush()?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Sweep expired sessions every `sweep_interval` in the background.
+pub fn start(uploads: Arc<UploadStore>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(uploads.settings.sweep_interval);
+        loop {
+            tick.tick().await;
+            let u = uploads.clone();
+            if let Ok(removed) = tokio::task::spawn_blocking(move || u.sweep_expired()).await {
+                if removed > 0 {
+                    tracing::info!(removed, "Expired upload sessions removed");
+                }
+            }
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUploadRequest {
+    #[serde(flatten)]
+    pub source: SourceMeta,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteUploadRequest {
+    /// Hex SHA-256 of the whole file.
+    pub sha256: String,
+}
+
+/// `POST /v1/acip/uploads`
+pub async fn post_upload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(req): Json<CreateUploadRequest>,
+) -> Response {
+    // Refuse an unknown policy now rather than after the whole file has been sent.
+    let policy_name = crate::routes::policy_name_from_headers(&headers, &state.header_rules);
+    if let Err(resp) = ingest::require_policy(&state, &policy_name) {
+        return resp;
+    }
+    let uploads = state.uploads.clone();
+    match state
+        .blocking
+        .run(move || uploads.create(req.source, &headers, req.total_bytes))
+        .await
+    {
+        Ok(status) => (StatusCode::CREATED, Json(status)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `GET /v1/acip/uploads/{id}`
+pub async fn get_upload(State(state): State<Arc<AppState>>, Path(id): Path<String>) -> Response {
+    match state.uploads.status(&id) {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `PUT /v1/acip/uploads/{id}/chunks/{n}`
+pub async fn put_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((id, index)): Path<(String, u64)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(sha) = headers
+        .get(CHUNK_SHA256_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+    else {
+        return introspection::json_error(
+            StatusCode::BAD_REQUEST,
+            "missing_chunk_checksum",
+            json!({"header": CHUNK_SHA256_HEADER}),
+        )
+        .into_response();
+    };
+    let uploads = state.uploads.clone();
+    match state
+        .blocking
+        .run(move || uploads.put_chunk(&id, index, &sha, &body))
+        .await
+    {
+        Ok(accepted) => Json(accepted).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+/// `POST /v1/acip/uploads/{id}/complete`
+///
+/// Returns the ingest decision. The session is removed once ingest succeeds; any failure leaves
+/// it open (until it expires) so the client can retry the completion.
+pub async fn post_complete(
+    State(state): State<Arc<AppState>>,
+    actor: Option<Extension<Actor>>,
+    Path(id): Path<String>,
+    Json(req): Json<CompleteUploadRequest>,
+) -> Response {
+    let uploads = state.uploads.clone();
+    let upload_id = id.clone();
+    let assembled = match state
+        .blocking
+        .run(move || uploads.assemble(&upload_id, &req.sha256))
+        .await
+    {
+        Ok(a) => a,
+        Err(e) => return e.into_response(),
+    };
+
+    let Assembled {
+        meta,
+        headers,
+        bytes,
+    } = assembled;
+    let raw_text = std::str::from_utf8(&bytes).ok().map(str::to_string);
+    let resp = ingest::ingest_timed(
+        state.clone(),
+        token_auth::actor_name(actor),
+        headers,
+        meta,
+        raw_text,
+        bytes,
+        slow_requests::Timing::start(),
+        incidents::Links::upload(&id),
+    )
+    .await;
+
+    state.uploads.finish(&id, resp.status().is_success());
+    resp
+}
@@ -0,0 +1,227 @@
+//! Per-policy URL allowlists: links to known-good domains do not count as external references.
+//!
+//! A policy lists domains inline (`url_allowlist.domains`) and/or names `domain_allowlist`
+//! feeds (`url_allowlist.feeds`). Hosts and entries are compared in their IDNA ASCII form, so a
+//! homograph of an allowlisted name (`exаmple.com` with a Cyrillic `а`) is a different host. An
+//! entry covers itself and its subdomains: `example.com` covers `docs.example.com`, not
+//! `notexample.com`. An entry that is a public suffix (`com`, `co.uk`, `github.io`) would cover
+//! unrelated sites, so it is refused inline and never matches from a feed.
+//!
+//! The sidecar does not carry the Public Suffix List. [`PUBLIC_SUFFIXES`] holds the multi-label
+//! suffixes such lists realistically contain; beyond those, any two-label name under a
+//! country-code TLD whose first label is a common registry category ([`CC_SECOND_LEVEL`]:
+//! `com.pe`, `gov.ng`, `ac.ke`) is treated as a suffix too. Other multi-label suffixes
+//! (`kommune.no`, private ones like `myshopify.com`) are not known, and an entry naming one is
+//! accepted; keep such lists to registrable domains.
+//!
+//! Allowlisted links are still reported in `threat.urls` (audit) with `allowlisted: true`, but
+//! add no severity: the markup scanners' external-reference flag and the generic URL phrase
+//! indicators are dropped when every linked host is allowlisted (see
+//! [`crate::feeds::FeedRegistry::assess_urls`]). A single other link keeps all of them.
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Multi-label public suffixes; every single label (a TLD) is one too.
+pub const PUBLIC_SUFFIXES: &[&str] = &[
+    "ac.il",
+    "ac.in",
+    "ac.jp",
+    "ac.nz",
+    "ac.uk",
+    "ac.za",
+    "appspot.com",
+    "asn.au",
+    "azurewebsites.net",
+    "blogspot.com",
+    "cloudfront.net",
+    "co.at",
+    "co.id",
+    "co.il",
+    "co.in",
+    "co.jp",
+    "co.kr",
+    "co.nz",
+    "co.uk",
+    "co.za",
+    "com.ar",
+    "com.au",
+    "com.br",
+    "com.cn",
+    "com.es",
+    "com.hk",
+    "com.mx",
+    "com.my",
+    "com.ph",
+    "com.pl",
+    "com.sg",
+    "com.tr",
+    "com.tw",
+    "com.ua",
+    "com.vn",
+    "edu.au",
+    "edu.cn",
+    "firebaseapp.com",
+    "github.io",
+    "gitlab.io",
+    "go.jp",
+    "gov.au",
+    "gov.br",
+    "gov.cn",
+    "gov.in",
+    "gov.uk",
+    "gov.za",
+    "govt.nz",
+    "herokuapp.com",
+    "id.au",
+    "ltd.uk",
+    "me.uk",
+    "ne.jp",
+    "net.au",
+    "net.br",
+    "net.cn",
+    "net.in",
+    "net.nz",
+    "net.uk",
+    "netlify.app",
+    "nhs.uk",
+    "or.at",
+    "or.jp",
+    "or.kr",
+    "org.au",
+    "org.br",
+    "org.cn",
+    "org.in",
+    "org.nz",
+    "org.uk",
+    "org.za",
+    "pages.dev",
+    "plc.uk",
+    "s3.amazonaws.com",
+    "sch.uk",
+    "vercel.app",
+    "web.app",
+    "workers.dev",
+];
+
+/// Second-level labels ccTLD registries use as categories (`com.pe`, `org.ng`); see
+/// [`is_public_suffix`].
+pub const CC_SECOND_LEVEL: &[&str] = &[
+    "ac", "co", "com", "edu", "go", "gob", "gov", "govt", "ltd", "mil", "ne", "net", "or", "org",
+    "plc", "sch",
+];
+
+/// `url_allowlist` of a policy. Lists are not merged across `extends`: a child's allowlist
+/// replaces its parent's.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UrlAllowlistConfig {
+    /// Domains, each covering its subdomains (IDNs in Unicode or punycode).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    /// Names of `domain_allowlist` feeds.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub feeds: Vec<String>,
+}
+
+impl UrlAllowlistConfig {
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty() && self.feeds.is_empty()
+    }
+
+    /// Domains in normalized form, sorted; refuses entries that are not domains or are public
+    /// suffixes.
+    pub fn normalized(&self, policy: &str) -> Result<Self> {
+        let mut domains = Vec::with_capacity(self.domains.len());
+        for raw in &self.domains {
+            let Some(d) = normalize_host(raw) else {
+                bail!("policy '{policy}': url_allowlist.domains entry {raw:?} is not a domain");
+            };
+            if is_public_suffix(&d) {
+                bail!(
+                    "policy '{policy}': url_allowlist.domains entry {raw:?} is a public suffix \
+                     and would allowlist unrelated sites"
+                );
+            }
+            domains.push(d);
+        }
+        domains.sort();
+        domains.dedup();
+        let mut feeds = self.feeds.clone();
+        feeds.sort();
+        feeds.dedup();
+        Ok(Self { domains, feeds })
+    }
+}
+
+/// `raw` as a lowercased IDNA ASCII domain without a leading `*.` or trailing dot; `None` for
+/// anything [`crate::feeds::normalize_domain`] would not accept.
+pub fn normalize_host(raw: &str) -> Option<String> {
+    let t = raw.trim();
+    let t = t.strip_prefix("*.").unwrap_or(t);
+    let t = t.strip_suffix('.').unwrap_or(t);
+    if t.is_ascii() {
+        return crate::feeds::normalize_domain(t);
+    }
+    match url::Host::parse(t).ok()? {
+        url::Host::Domain(d) => crate::feeds::normalize_domain(&d),
+        _ => None,
+    }
+}
+
+/// `domain` is a TLD, one of [`PUBLIC_SUFFIXES`], or a [`CC_SECOND_LEVEL`] label under a
+/// two-letter TLD.
+pub fn is_public_suffix(domain: &str) -> bool {
+    let Some((label, tld)) = domain.split_once('.') else {
+        return true;
+    };
+    PUBLIC_SUFFIXES.contains(&domain)
+        || (tld.len() == 2
+            && tld.bytes().all(|b| b.is_ascii_lowercase())
+            && CC_SECOND_LEVEL.contains(&label))
+}
+
+/// The registrable domain of `host`: one label under its public suffix (as far as
+/// [`is_public_suffix`] knows them), or `host` itself when it is a suffix.
+pub fn registrable_domain(host: &str) -> &str {
+    let mut rest = host;
+    while let Some((_, parent)) = rest.split_once('.') {
+        if is_public_suffix(parent) {
+            return rest;
+        }
+        rest = parent;
+    }
+    host
+}
+
+/// `entry` allowlists `host`: the same domain or a subdomain, on a label boundary, and
+/// `entry` is not a public suffix. Both in [`normalize_host`] form.
+pub fn covers(entry: &str, host: &str) -> bool {
+    if is_public_suffix(entry) {
+        return false;
+    }
+    host == entry
+        || host
+            .strip_suffix(entry)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// One linked host, as reported in `threat.urls`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct UrlRef {
+    pub host: String,
+    pub allowlisted: bool,
+    /// `inline`, or the name of the feed that listed the host.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowlisted_by: Option<String>,
+}
+
+/// A policy's allowlist as in effect: its inline domains and the current size of each feed it
+/// names (`null` for a feed that is not configured).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EffectiveAllowlist {
+    #[serde(default)]
+    pub domains: Vec<String>,
+    #[serde(default)]
+    pub feeds: BTreeMap<String, Option<usize>>,
+}
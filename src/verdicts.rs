@@ -0,0 +1,266 @@
+//! Last-decision map: the most recent verdict per (policy, content digest), together with the
+//! provenance it was produced under.
+//!
+//! A recorded verdict is only trusted while it is younger than the policy's
+//! `cache.max_verdict_age_days` *and* was produced by the current pattern pack and models.
+//! Anything else is advisory: the pipeline re-runs in full, and comparing the stale verdict
+//! with the new one feeds revalidation telemetry (agreement rate, rules drift).
+//!
+//! Keys are unsalted content digests and never leave the process; the rules drift log names
+//! the content by its salted id ([`IdHasher::export_id`]).
+
+use crate::build_info::{self, BuildInfo};
+use crate::cache_bypass::BypassCounts;
+use crate::hashing::IdHasher;
+use crate::model_policy::PolicyConfig;
+use crate::reputation::{self, Clock};
+use crate::sentry::{Action, ConfidenceBucket, Decision, RiskLevel};
+use crate::stats::{DecisionStats, DAY_SECS};
+use crate::threat;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Upper bound on tracked (policy, digest) pairs; the oldest verdict is evicted first.
+pub const MAX_TRACKED_VERDICTS: usize = 10_000;
+
+/// Inputs a verdict depends on besides the content itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub pattern_pack: String,
+    pub l1_model: String,
+    pub l2_model: String,
+    /// Version the answering provider reported (or a startup probe found), when known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_version: Option<String>,
+    /// The policy experiment whose definition decided (see [`crate::experiments`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub experiment: Option<String>,
+    /// The sidecar build that decided.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<BuildInfo>,
+}
+
+impl Provenance {
+    /// Provenance of a verdict produced now under `policy`.
+    pub fn current(policy: &PolicyConfig) -> Self {
+        Self {
+            pattern_pack: threat::pattern_pack_hash().to_string(),
+            l1_model: policy.l1.label(),
+            l2_model: policy.l2.label(),
+            model_version: None,
+            experiment: None,
+            build: Some(build_info::current().clone()),
+        }
+    }
+
+    /// [`Self::current`] for a verdict from a model that reported `model_version`.
+    pub fn observed(policy: &PolicyConfig, model_version: Option<&str>) -> Self {
+        Self {
+            model_version: model_version.map(str::to_string),
+            ..Self::current(policy)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VerdictRecord {
+    pub tools_allowed: bool,
+    pub risk_level: RiskLevel,
+    pub action: Action,
+    pub decided_unix: u64,
+    pub provenance: Provenance,
+    /// Confidence bucket of the verdict, when it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<ConfidenceBucket>,
+}
+
+impl VerdictRecord {
+    /// Same outcome for the caller (tool gate, risk level, action).
+    pub fn agrees_with(&self, d: &Decision) -> bool {
+        self.tools_allowed == d.tools_allowed
+            && self.risk_level == d.risk_level
+            && self.action == d.action
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Staleness {
+    Fresh,
+    /// Older than `cache.max_verdict_age_days`.
+    Aged,
+    /// Produced by a different pattern pack or model.
+    ProvenanceChanged,
+}
+
+/// Outcome of re-checking a stale verdict.
+#[derive(Debug, Clone)]
+pub struct Revalidation {
+    pub staleness: Staleness,
+    pub agreed: bool,
+    pub previous: VerdictRecord,
+}
+
+pub struct VerdictHistory {
+    clock: Arc<dyn Clock>,
+    max_entries: usize,
+    entries: Mutex<HashMap<(String, String), VerdictRecord>>,
+    hasher: Arc<IdHasher>,
+    bypasses: BypassCounts,
+}
+
+impl Default for VerdictHistory {
+    /// Wall clock, [`MAX_TRACKED_VERDICTS`] entries.
+    fn default() -> Self {
+        Self::new(MAX_TRACKED_VERDICTS, Arc::new(reputation::SystemClock))
+    }
+}
+
+impl VerdictHistory {
+    pub fn new(max_entries: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            max_entries: max_entries.max(1),
+            entries: Mutex::new(HashMap::new()),
+            hasher: Arc::new(IdHasher::default()),
+            bypasses: BypassCounts::default(),
+        }
+    }
+
+    /// Salt for the content ids in log lines.
+    pub fn with_hashing(mut self, hasher: Arc<IdHasher>) -> Self {
+        self.hasher = hasher;
+        self
+    }
+
+    /// Runs that skipped this map with `X-ACIP-Bypass-Cache` (see [`crate::cache_bypass`]).
+    pub fn bypasses(&self) -> &BypassCounts {
+        &self.bypasses
+    }
+
+    /// Provenance changes win over age: they invalidate immediately.
+    pub fn staleness(
+        &self,
+        rec: &VerdictRecord,
+        max_age_days: u64,
+        provenance: &Provenance,
+    ) -> Staleness {
+        if &rec.provenance != provenance {
+            return Staleness::ProvenanceChanged;
+        }
+        let age = self.clock.now_unix().saturating_sub(rec.decided_unix);
+        if age > max_age_days.saturating_mul(DAY_SECS) {
+            return Staleness::Aged;
+        }
+        Staleness::Fresh
+    }
+
+    /// Last verdict for `digest` under `policy`, with its staleness right now.
+    pub fn lookup(
+        &self,
+        policy: &str,
+        digest: &str,
+        max_age_days: u64,
+        provenance: &Provenance,
+    ) -> Option<(VerdictRecord, Staleness)> {
+        let entries = self.entries.lock().unwrap();
+        let rec = entries
+            .get(&(policy.to_string(), digest.to_string()))?
+            .clone();
+        let staleness = self.staleness(&rec, max_age_days, provenance);
+        Some((rec, staleness))
+    }
+
+    /// Last verdict for `digest` under `policy_name` if it is still fresh, judged against the
+    /// model version it was recorded with. Partial reuse of large inputs builds on it.
+    pub fn reusable(
+        &self,
+        policy_name: &str,
+        policy: &PolicyConfig,
+        digest: &str,
+    ) -> Option<VerdictRecord> {
+        let rec = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(&(policy_name.to_string(), digest.to_string()))?
+            .clone();
+        let provenance = Provenance::observed(policy, rec.provenance.model_version.as_deref());
+        let staleness = self.staleness(&rec, policy.cache.max_verdict_age_days, &provenance);
+        (staleness == Staleness::Fresh).then_some(rec)
+    }
+
+    /// Record `decision` as the latest verdict for `digest` under `policy`.
+    ///
+    /// If the previous verdict was stale, the decision is its full re-check: the outcome is
+    /// counted in `stats` and divergence is logged as rules drift. A different reported
+    /// `model_version` makes the previous verdict stale, like a model change.
+    pub fn observe(
+        &self,
+        stats: &DecisionStats,
+        policy_name: &str,
+        policy: &PolicyConfig,
+        digest: &str,
+        decision: &Decision,
+        model_version: Option<&str>,
+    ) -> Option<Revalidation> {
+        let provenance = Provenance::observed(policy, model_version);
+        let max_age_days = policy.cache.max_verdict_age_days;
+        let key = (policy_name.to_string(), digest.to_string());
+        let now = self.clock.now_unix();
+
+        let previous = {
+            let mut entries = self.entries.lock().unwrap();
+            let previous = entries.insert(
+                key,
+                VerdictRecord {
+                    tools_allowed: decision.tools_allowed,
+                    risk_level: decision.risk_level.clone(),
+                    action: decision.action.clone(),
+                    decided_unix: now,
+                    provenance: provenance.clone(),
+                    confidence: decision.confidence.map(|c| c.bucket),
+                },
+            );
+            if entries.len() > self.max_entries {
+                let oldest = entries
+                    .iter()
+                    .min_by(|a, b| (a.1.decided_unix, a.0).cmp(&(b.1.decided_unix, b.0)))
+                    .map(|(k, _)| k.clone());
+                if let Some(k) = oldest {
+                    entries.remove(&k);
+                }
+            }
+            previous?
+        };
+
+        let staleness = self.staleness(&previous, max_age_days, &provenance);
+        if staleness == Staleness::Fresh {
+            return None;
+        }
+
+        let agreed = previous.agrees_with(decision);
+        stats.record_revalidation(policy_name, previous.confidence, agreed);
+        if !agreed {
+            tracing::warn!(
+                policy = %policy_name,
+                content_id = %self.hasher.export_id(digest),
+                staleness = ?staleness,
+                previous_action = ?previous.action,
+                action = ?decision.action,
+                previous_risk_level = ?previous.risk_level,
+                risk_level = ?decision.risk_level,
+                "Rules drift: stale verdict diverged from re-check"
+            );
+        }
+
+        Some(Revalidation {
+            staleness,
+            agreed,
+            previous,
+        })
+    }
+}
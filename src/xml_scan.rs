@@ -13,6 +13,18 @@ pub struct XmlScanResult {
     pub severity: u8,
 }
 
+impl XmlScanResult {
+    /// Every `http(s)` reference points at an allowlisted host: drop the external-reference
+    /// flag and its severity.
+    pub fn clear_external_ref(&mut self) {
+        self.matches.retain(|m| m != "http" && m != "https");
+        if self.has_external_ref {
+            self.has_external_ref = false;
+            self.severity = self.severity.saturating_sub(1);
+        }
+    }
+}
+
 static PATTERNS: &[(&str, &str)] = &[
     // DTD / entity expansion / XXE markers
     ("doctype", "<!doctype"),
@@ -0,0 +1,276 @@
+use acip_sidecar::acip_headers::{self, DuplicateHeader, DuplicateHeaders, HeaderRules};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, policy_store, state};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn test_state(rules: HeaderRules) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    let mut policies = std::collections::BTreeMap::new();
+    for name in ["default", "strict", "lenient"] {
+        policies.insert(
+            name.to_string(),
+            acip_sidecar::model_policy::PolicyConfig::default(),
+        );
+    }
+
+    AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .with_header_rules(Arc::new(rules))
+        .build()
+        .unwrap()
+}
+
+fn strictest() -> HeaderRules {
+    HeaderRules {
+        duplicates: DuplicateHeaders::UseStrictest,
+        policy_ranking: vec!["strict".to_string(), "default".to_string()],
+        deprecations: Default::default(),
+    }
+}
+
+fn router(rules: HeaderRules) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(test_state(rules), None, extra)
+}
+
+async fn ingest(app: &Router, headers: &[(&str, &str)]) -> (StatusCode, Value) {
+    let mut b = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json");
+    for (k, v) in headers {
+        b = b.header(*k, *v);
+    }
+    let body = json!({
+        "source_id": "s1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Meeting moved to Thursday at 10am.",
+    });
+    let resp = app
+        .clone()
+        .oneshot(b.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn assert_duplicate(status: StatusCode, v: &Value, header: &str, count: usize) {
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{v}");
+    assert_eq!(v["error"], "duplicate_header");
+    assert_eq!(v["extra"]["header"], header);
+    assert_eq!(v["extra"]["count"], count);
+}
+
+#[tokio::test]
+async fn repeated_allow_tools_is_rejected() {
+    let app = router(HeaderRules::default());
+    let (status, v) = ingest(
+        &app,
+        &[
+            ("X-ACIP-Allow-Tools", "false"),
+            ("X-ACIP-Allow-Tools", "true"),
+        ],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-allow-tools", 2);
+}
+
+#[tokio::test]
+async fn repeated_policy_is_rejected() {
+    let app = router(HeaderRules::default());
+    let (status, v) = ingest(
+        &app,
+        &[("X-ACIP-Policy", "strict"), ("X-ACIP-Policy", "lenient")],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-policy", 2);
+}
+
+#[tokio::test]
+async fn comma_joined_values_count_as_repeats() {
+    let app = router(HeaderRules::default());
+    let (status, v) = ingest(&app, &[("X-ACIP-Allow-Tools", "false, true")]).await;
+    assert_duplicate(status, &v, "x-acip-allow-tools", 2);
+
+    let (status, v) = ingest(&app, &[("X-ACIP-Policy", "strict,lenient")]).await;
+    assert_duplicate(status, &v, "x-acip-policy", 2);
+}
+
+#[test]
+fn single_valued_headers_are_not_split_on_commas() {
+    let url = "https://hooks.example.com/acip?tags=a,b";
+    let mut headers = HeaderMap::new();
+    headers.append("x-acip-callback-url", HeaderValue::from_static(url));
+    headers.append("x-acip-origin", HeaderValue::from_static(" m1,m2 "));
+
+    assert_eq!(
+        acip_headers::values(&headers, "x-acip-callback-url"),
+        vec![url]
+    );
+    assert_eq!(
+        acip_headers::values(&headers, "x-acip-origin"),
+        vec!["m1,m2"]
+    );
+    assert_eq!(HeaderRules::default().check(&headers), Ok(vec![]));
+
+    headers.append("x-acip-callback-url", HeaderValue::from_static(url));
+    assert_eq!(
+        HeaderRules::default().check(&headers),
+        Err(DuplicateHeader {
+            header: "x-acip-callback-url".to_string(),
+            count: 2
+        })
+    );
+}
+
+#[tokio::test]
+async fn identical_repeats_and_other_acip_headers_are_rejected_too() {
+    let app = router(HeaderRules::default());
+    let (status, v) = ingest(
+        &app,
+        &[("X-ACIP-Policy", "default"), ("X-ACIP-Policy", "default")],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-policy", 2);
+
+    let (status, v) = ingest(
+        &app,
+        &[("X-ACIP-Chunk-Sha256", "aa"), ("X-ACIP-Chunk-Sha256", "bb")],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-chunk-sha256", 2);
+}
+
+#[tokio::test]
+async fn strictest_mode_denies_tools_and_flags_the_conflict() {
+    let app = router(strictest());
+    let (status, v) = ingest(
+        &app,
+        &[
+            ("X-ACIP-Allow-Tools", "true"),
+            ("X-ACIP-Allow-Tools", "false"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["tools_allowed"], false);
+    let reasons: Vec<&str> = v["reasons"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(Value::as_str)
+        .collect();
+    assert!(
+        reasons.contains(
+            &"header_conflict: x-acip-allow-tools sent 2 values (true, false); used false"
+        ),
+        "{reasons:?}"
+    );
+    assert!(reasons
+        .iter()
+        .any(|r| r.starts_with("tools not authorized")));
+}
+
+#[tokio::test]
+async fn strictest_mode_picks_the_higher_ranked_policy() {
+    let app = router(strictest());
+    let (status, v) = ingest(&app, &[("X-ACIP-Policy", "default, strict")]).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert!(v["reasons"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|r| r.as_str().unwrap().ends_with("used strict")));
+
+    // `lenient` is not ranked, so the conflict cannot be settled.
+    let (status, v) = ingest(
+        &app,
+        &[("X-ACIP-Policy", "strict"), ("X-ACIP-Policy", "lenient")],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-policy", 2);
+}
+
+#[tokio::test]
+async fn strictest_mode_still_rejects_headers_without_an_order() {
+    let app = router(strictest());
+    let (status, v) = ingest(
+        &app,
+        &[("X-ACIP-Chunk-Sha256", "aa"), ("X-ACIP-Chunk-Sha256", "bb")],
+    )
+    .await;
+    assert_duplicate(status, &v, "x-acip-chunk-sha256", 2);
+}
+
+#[tokio::test]
+async fn non_acip_headers_are_unaffected() {
+    let app = router(HeaderRules::default());
+    let (status, v) = ingest(
+        &app,
+        &[
+            ("X-Request-Tag", "a"),
+            ("X-Request-Tag", "b"),
+            ("Accept", "application/json, text/plain"),
+            ("X-ACIP-Policy", "strict"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert!(!v["reasons"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|r| r.as_str().unwrap().starts_with("header_conflict")));
+}
+
+#[test]
+fn check_reports_each_resolved_conflict() {
+    let mut headers = HeaderMap::new();
+    headers.append("x-acip-allow-tools", HeaderValue::from_static("yes"));
+    headers.append("x-acip-allow-tools", HeaderValue::from_static("1"));
+    headers.append(
+        "x-acip-policy",
+        HeaderValue::from_static(" strict , default "),
+    );
+
+    assert_eq!(
+        acip_headers::values(&headers, "x-acip-policy"),
+        vec!["strict", "default"]
+    );
+    assert!(acip_headers::allow_tools(&headers));
+
+    let conflicts = strictest().check(&headers).unwrap();
+    assert_eq!(conflicts.len(), 2);
+    assert_eq!(conflicts[0].header, "x-acip-allow-tools");
+    assert_eq!(conflicts[0].resolved, "true");
+    assert_eq!(conflicts[1].resolved, "strict");
+    assert_eq!(strictest().policy_name(&headers), "strict");
+
+    let err = HeaderRules::default().check(&headers).unwrap_err();
+    assert_eq!(err.header, "x-acip-allow-tools");
+    // Unranked repeats select no policy rather than an arbitrary one.
+    assert_eq!(
+        HeaderRules::default().policy_name(&headers),
+        "strict, default"
+    );
+}
@@ -0,0 +1,232 @@
+//! Every acipctl command that calls the sidecar, against a mock server answering each
+//! failure class: the rendered error (text on stderr, `{"error": ...}` on stdout with
+//! `--output json`) and the exit code must be the same for all of them.
+
+mod util;
+
+use assert_cmd::{cargo::cargo_bin_cmd, Command};
+use axum::{
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use util::serve;
+
+/// A loopback URL nothing listens on.
+fn refused_url() -> String {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    format!("http://{addr}")
+}
+
+/// Answers every path with a structured `503 store_unavailable`.
+fn structured_error() -> Router {
+    Router::new().fallback(|| async {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({"error": "store_unavailable", "extra": {"store": "reputation"}})),
+        )
+    })
+}
+
+/// Answers every path with a long HTML error page, as a proxy in front of the sidecar would.
+fn raw_error() -> Router {
+    Router::new().fallback(|| async {
+        let page = format!("<html><body>{}</body></html>", "bad gateway ".repeat(200));
+        (
+            StatusCode::BAD_GATEWAY,
+            [(header::CONTENT_TYPE, "text/html")],
+            page,
+        )
+            .into_response()
+    })
+}
+
+/// Answers every path with `200` and a body that is not JSON.
+fn garbage() -> Router {
+    Router::new().fallback(|| async { (StatusCode::OK, "<<not json>>") })
+}
+
+/// Every command that calls the sidecar, with its arguments and stdin.
+fn commands(file: &str) -> Vec<(Vec<&str>, &'static str)> {
+    vec![
+        (vec!["stats"], ""),
+        (vec!["slow-requests"], ""),
+        (vec!["incident", "0123456789abcdef"], ""),
+        (vec!["drain", "--initiated-by", "ops"], ""),
+        (vec!["resume"], ""),
+        (vec!["reputation"], ""),
+        (vec!["reputation", "--stream"], ""),
+        (vec!["ingest-text", "--source-id", "s1"], "hello"),
+        (vec!["ingest-text", "--source-id", "s1", "--async"], "hello"),
+        (vec!["ingest-file", "--source-id", "s1", file], ""),
+    ]
+}
+
+fn run(url: &str, args: &[&str], stdin: &str, json_output: bool) -> assert_cmd::assert::Assert {
+    let mut cmd: Command = cargo_bin_cmd!("acipctl");
+    cmd.args(["--url", url]);
+    if json_output {
+        cmd.args(["--output", "json"]);
+    }
+    cmd.args(args).write_stdin(stdin).assert()
+}
+
+/// The `error` object a `--output json` run printed.
+fn json_error(out: &assert_cmd::assert::Assert) -> Value {
+    let stdout = &out.get_output().stdout;
+    let v: Value = serde_json::from_slice(stdout)
+        .unwrap_or_else(|e| panic!("{e}: {}", String::from_utf8_lossy(stdout)));
+    v["error"].clone()
+}
+
+/// Run every command against `url` in both output formats and check the exit code, the text
+/// rendering (`stderr` must contain each of `text`) and the JSON `kind`.
+fn check_all(url: &str, code: i32, text: &[&str], kind: &str) -> Vec<Value> {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "hello").unwrap();
+    let file = file.to_str().unwrap();
+
+    let mut errors = vec![];
+    for (args, stdin) in commands(file) {
+        let mut out = run(url, &args, stdin, false).code(code).stdout("");
+        for t in text {
+            out = out.stderr(predicates::str::contains(*t));
+        }
+
+        let out = run(url, &args, stdin, true).code(code).stderr("");
+        let err = json_error(&out);
+        assert_eq!(err["kind"], kind, "{args:?}: {err}");
+        assert!(err["message"].as_str().is_some_and(|m| !m.is_empty()));
+        errors.push(err);
+    }
+    errors
+}
+
+#[test]
+fn unreachable_sidecar_exits_two_with_the_url_and_a_hint() {
+    let url = refused_url();
+    let errors = check_all(
+        &url,
+        2,
+        &[
+            &format!(" {url}/v1/acip/"),
+            "no response from the sidecar",
+            &format!("hint: is the sidecar running and listening on {url}?"),
+            "--url",
+        ],
+        "connect",
+    );
+    for err in errors {
+        assert!(err["request"].as_str().unwrap().contains(&url), "{err}");
+        assert!(err["hint"].as_str().unwrap().contains("--url"), "{err}");
+    }
+}
+
+#[test]
+fn structured_error_status_exits_three_with_code_and_message() {
+    let addr = serve(structured_error());
+    let errors = check_all(
+        &format!("http://{addr}"),
+        3,
+        &[
+            "failed: 503 Service Unavailable store_unavailable: {\"store\":\"reputation\"}",
+            "error: ",
+        ],
+        "status",
+    );
+    for err in errors {
+        assert_eq!(err["status"], 503, "{err}");
+        assert_eq!(err["code"], "store_unavailable", "{err}");
+        assert_eq!(err["body"]["extra"]["store"], "reputation", "{err}");
+        assert!(err.get("hint").is_none());
+    }
+}
+
+#[test]
+fn unstructured_error_status_exits_three_with_the_body_cut_short() {
+    let addr = serve(raw_error());
+    let errors = check_all(
+        &format!("http://{addr}"),
+        3,
+        &["failed: 502 Bad Gateway: <html><body>bad gateway", "… ("],
+        "status",
+    );
+    for err in errors {
+        assert_eq!(err["status"], 502, "{err}");
+        assert!(err["code"].is_null(), "{err}");
+        assert!(err["body"].is_null(), "{err}");
+        let message = err["message"].as_str().unwrap();
+        assert!(message.len() < 600, "{message}");
+    }
+}
+
+#[test]
+fn unreadable_answer_exits_four_with_an_excerpt() {
+    let addr = serve(garbage());
+    let errors = check_all(
+        &format!("http://{addr}"),
+        4,
+        &["unreadable 200 OK response", "<<not json>>"],
+        "parse",
+    );
+    for err in errors {
+        assert_eq!(err["excerpt"], "<<not json>>", "{err}");
+    }
+}
+
+#[test]
+fn health_renders_unreachable_and_degraded_in_both_formats() {
+    let url = refused_url();
+    cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "health", "--timeout", "1s"])
+        .assert()
+        .code(2)
+        .stderr(predicates::str::contains(format!("GET {url}/health")))
+        .stderr(predicates::str::contains("hint: "));
+    let out = cargo_bin_cmd!("acipctl")
+        .args([
+            "--url",
+            &url,
+            "--output",
+            "json",
+            "health",
+            "--timeout",
+            "1s",
+        ])
+        .assert()
+        .code(2);
+    assert_eq!(json_error(&out)["kind"], "connect");
+
+    let addr = serve(structured_error());
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}"), "--output", "json"])
+        .args(["health", "--timeout", "2s"])
+        .assert()
+        .code(1)
+        .stderr("");
+    let v: Value = serde_json::from_slice(&out.get_output().stdout).unwrap();
+    assert_eq!(v["status"], "degraded");
+    assert_eq!(v["failing"], json!(["http_503"]));
+    assert_eq!(v["body"]["error"], "store_unavailable");
+}
+
+#[test]
+fn local_failures_exit_one_and_are_json_too() {
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--output", "json", "ingest-file", "--source-id", "s1"])
+        .arg("/nonexistent/acipctl-test.pdf")
+        .assert()
+        .code(1)
+        .stderr("");
+    let err = json_error(&out);
+    assert_eq!(err["kind"], "local");
+    assert!(err["message"]
+        .as_str()
+        .unwrap()
+        .contains("acipctl-test.pdf"));
+}
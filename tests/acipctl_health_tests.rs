@@ -0,0 +1,85 @@
+mod util;
+
+use assert_cmd::{cargo::cargo_bin_cmd, Command};
+use axum::{http::StatusCode, routing::get, Json, Router};
+use predicates::prelude::*;
+use serde_json::json;
+use util::serve;
+
+fn acipctl(addr: &str) -> Command {
+    let mut cmd = cargo_bin_cmd!("acipctl");
+    cmd.args(["--url", addr]);
+    cmd
+}
+
+#[test]
+fn health_exits_zero_when_healthy() {
+    let addr = serve(Router::new().route("/health", get(|| async { "ok" })));
+
+    acipctl(&format!("http://{addr}"))
+        .args(["health", "--timeout", "2s"])
+        .assert()
+        .code(0);
+}
+
+#[test]
+fn health_exits_one_and_names_failing_checks_when_degraded() {
+    let addr = serve(Router::new().route(
+        "/health",
+        get(|| async {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "degraded",
+                    "checks": {"extractor": false, "reputation_store": true}
+                })),
+            )
+        }),
+    ));
+
+    acipctl(&format!("http://{addr}"))
+        .args(["health", "--timeout", "2s"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("extractor"))
+        .stderr(predicates::str::contains("reputation_store").not());
+}
+
+#[test]
+fn health_exits_two_when_unreachable() {
+    // Bind then drop to get a port that refuses connections.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+
+    acipctl(&format!("http://{addr}"))
+        .args([
+            "health",
+            "--timeout",
+            "1s",
+            "--retries",
+            "1",
+            "--interval",
+            "10ms",
+        ])
+        .assert()
+        .code(2);
+}
+
+#[test]
+fn health_quiet_suppresses_output() {
+    let addr = serve(Router::new().route(
+        "/ready",
+        get(|| async {
+            Json(json!({"ready": false, "checks": [{"name": "policies", "ok": false}]}))
+        }),
+    ));
+
+    acipctl(&format!("http://{addr}"))
+        .args(["health", "--ready", "--quiet", "--timeout", "2s"])
+        .assert()
+        .code(1)
+        .stdout("")
+        .stderr("");
+}
@@ -0,0 +1,84 @@
+mod util;
+
+use acip_sidecar::reputation::SystemClock;
+use acip_sidecar::sentry::{Action, RiskLevel};
+use acip_sidecar::stats::{DecisionSample, DecisionStats, StatsSettings};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder};
+use assert_cmd::cargo::cargo_bin_cmd;
+use axum::Router;
+use std::sync::Arc;
+use util::serve;
+
+fn router_with_stats(stats: DecisionStats) -> Router {
+    let st = AppStateBuilder::for_tests()
+        .with_stats(Arc::new(stats))
+        .build()
+        .unwrap();
+
+    app::build_router(st, None, Router::new())
+}
+
+#[test]
+fn stats_renders_pattern_table() {
+    let stats = DecisionStats::in_memory(StatsSettings::default(), Arc::new(SystemClock));
+    for action in [Action::Block, Action::Allow] {
+        stats.record(&DecisionSample {
+            tenant: "anonymous".to_string(),
+            policy: "default".to_string(),
+            source_type: "html".to_string(),
+            action,
+            risk_level: RiskLevel::Low,
+            patterns: vec!["contains_phrase:ignore previous".to_string()],
+            escalated: false,
+            severity: 8,
+            quality: None,
+            confidence: None,
+        });
+    }
+    stats.record_false_positive("contains_phrase:ignore previous");
+
+    let addr = serve(router_with_stats(stats));
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}")])
+        .args(["stats", "--days", "7", "--group-by", "pattern"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let out = String::from_utf8(out).unwrap();
+
+    let mut lines = out.lines();
+    let header: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+    assert_eq!(
+        header,
+        vec![
+            "pattern",
+            "hits",
+            "escalated_share",
+            "false_positives",
+            "overturn_rate"
+        ]
+    );
+    let row: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+    assert_eq!(
+        row,
+        vec![
+            "contains_phrase:ignore",
+            "previous",
+            "2",
+            "50.0%",
+            "1",
+            "50.0%"
+        ],
+        "{out}"
+    );
+}
+
+#[test]
+fn stats_rejects_unknown_group_by() {
+    cargo_bin_cmd!("acipctl")
+        .args(["stats", "--group-by", "tenant"])
+        .assert()
+        .code(2);
+}
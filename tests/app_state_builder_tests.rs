@@ -1,5 +1,55 @@
-use acip_sidecar::{app_state_builder, policy_store, reputation, secrets, state};
-use std::sync::Arc;
+use acip_sidecar::app_state_builder::{self, AppStateBuilder, BuildError};
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::secrets::{Secret, SecretStore};
+use acip_sidecar::{app, config::Config, reputation, state, stats};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{collections::HashMap, sync::Arc};
+use tower::ServiceExt;
+
+/// Secrets from a fixed map, so tests do not depend on the environment.
+struct Secrets(HashMap<&'static str, &'static str>);
+
+impl SecretStore for Secrets {
+    fn get(&self, key: &str) -> Option<Secret> {
+        self.0.get(key).map(|v| Secret::new(*v))
+    }
+}
+
+fn no_secrets() -> Arc<dyn SecretStore> {
+    Arc::new(Secrets(HashMap::new()))
+}
+
+fn config(raw: &str) -> Option<Config> {
+    Some(Config::parse(raw).unwrap())
+}
+
+fn violations(builder: AppStateBuilder) -> Vec<String> {
+    match builder.build() {
+        Ok(_) => panic!("expected a build error"),
+        Err(BuildError { violations }) => violations,
+    }
+}
+
+/// A store with one `default` policy, extended by `fields`.
+fn policies(fields: Value) -> PolicyStore {
+    let mut default = json!({
+        "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+        "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" },
+    });
+    default
+        .as_object_mut()
+        .unwrap()
+        .extend(fields.as_object().unwrap().clone());
+    let raw = json!({ "policies": { "default": default } });
+    PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap()
+}
 
 #[test]
 fn build_http_client_works() {
@@ -7,30 +57,219 @@ fn build_http_client_works() {
 }
 
 #[test]
-fn build_app_state_wires_fields() {
-    let http = app_state_builder::build_http_client().unwrap();
-
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = app_state_builder::build_app_state(
-        state::Policy {
+fn overrides_replace_defaults() {
+    let st = AppStateBuilder::for_tests()
+        .with_policy(state::Policy {
             head: 1,
             tail: 2,
             full_if_lte: 3,
-        },
-        state::NormalizeSettings::from_config(None),
-        http,
-        Arc::new(secrets::EnvStore),
-        policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        Arc::new(reputation::InMemoryReputationStore::new()),
-    );
-
+        })
+        .with_read_only(true)
+        .build()
+        .unwrap();
     assert_eq!(st.policy.head, 1);
     assert_eq!(st.policy.tail, 2);
     assert_eq!(st.policy.full_if_lte, 3);
     assert!(st.policies.require("default").is_ok());
+    // The in-memory defaults follow `read_only`.
+    assert!(st.read_only);
+    assert!(st.reputation.is_read_only());
+    assert!(st.stats.is_read_only());
+    assert!(st.slow_requests.is_read_only());
+
+    // Components nobody overrode come from the config.
+    let st = AppStateBuilder::new(
+        config("[policy]\nhead = 10\n[server]\nread_only = true\n"),
+        no_secrets(),
+    )
+    .with_policies(
+        AppStateBuilder::for_tests()
+            .build()
+            .unwrap()
+            .policies
+            .clone(),
+    )
+    .build()
+    .unwrap();
+    assert_eq!(st.policy.head, 10);
+    assert!(st.read_only && st.reputation.is_read_only());
+}
+
+#[test]
+fn required_tokens_must_resolve() {
+    let errs = violations(
+        AppStateBuilder::for_tests()
+            .with_token_required(true)
+            .with_policies(policies(json!({}))),
+    );
+    assert!(
+        errs.len() == 1 && errs[0].starts_with("tokens:"),
+        "{errs:?}"
+    );
+
+    let named = "[security]\n[[security.tokens]]\nname = \"ops\"\nsecret = \"OPS_TOKEN\"\nscopes = [\"read\"]\n";
+    let errs = violations(
+        AppStateBuilder::new(config(named), no_secrets())
+            .with_token_required(true)
+            .with_policies(policies(json!({}))),
+    );
+    assert!(errs[0].contains("OPS_TOKEN"), "{errs:?}");
+
+    let (_, tokens) = AppStateBuilder::new(
+        config(named),
+        Arc::new(Secrets(HashMap::from([("OPS_TOKEN", "s3cret")]))),
+    )
+    .with_token_required(true)
+    .with_policies(policies(json!({})))
+    .build_with_tokens()
+    .unwrap();
+    assert_eq!(tokens.names(), ["ops"]);
+}
+
+#[test]
+fn stores_must_match_read_only_mode() {
+    let errs = violations(
+        AppStateBuilder::for_tests()
+            .with_read_only(true)
+            .with_reputation(Arc::new(reputation::InMemoryReputationStore::new()))
+            .with_stats(Arc::new(stats::DecisionStats::default())),
+    );
+    assert_eq!(
+        errs,
+        [
+            "server.read_only is set but the reputation store accepts writes",
+            "server.read_only is set but the stats store accepts writes",
+        ]
+    );
+
+    // A persistent store opened read-only would silently drop every decision.
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reputation.json");
+    std::fs::write(&path, "{}").unwrap();
+    let store = reputation::JsonFileReputationStore::open_read_only(&path).unwrap();
+    let errs = violations(AppStateBuilder::for_tests().with_reputation(Arc::new(store)));
+    assert_eq!(errs.len(), 1, "{errs:?}");
+    assert!(
+        errs[0].starts_with("the reputation store persists to")
+            && errs[0].ends_with("but was opened read-only"),
+        "{errs:?}"
+    );
+}
+
+#[test]
+fn configured_exporters_must_be_wired() {
+    let raw = r#"
+[siem]
+format = "ocsf"
+destination = "/var/log/acip/decisions.jsonl"
+
+[notify]
+webhook_url = "https://hooks.example/acip"
+"#;
+    let errs = violations(
+        AppStateBuilder::new(config(raw), no_secrets())
+            .with_policies(policies(json!({})))
+            .with_siem(Arc::new(acip_sidecar::siem::SiemExport::default()))
+            .with_notify(Arc::new(acip_sidecar::notify::Notifier::default())),
+    );
+    assert_eq!(
+        errs,
+        [
+            "[siem] is configured but the SIEM export has no destination",
+            "[notify] is configured but the notifier has no webhook",
+        ]
+    );
+}
+
+#[cfg(feature = "otel")]
+#[test]
+fn configured_span_export_must_be_wired() {
+    let errs = violations(
+        AppStateBuilder::new(
+            config("[telemetry]\notlp_endpoint = \"http://collector:4318\"\n"),
+            no_secrets(),
+        )
+        .with_policies(policies(json!({})))
+        .with_telemetry(Arc::new(acip_sidecar::telemetry::Telemetry::default())),
+    );
+    assert_eq!(
+        errs,
+        ["telemetry.otlp_endpoint is set but the span exporter is disabled"]
+    );
+}
+
+#[test]
+fn every_violation_is_reported_at_once() {
+    let err = AppStateBuilder::new(
+        config("[cors]\nallowed_origins = [\"not a url\"]\n"),
+        no_secrets(),
+    )
+    .with_token_required(true)
+    .with_read_only(true)
+    .with_stats(Arc::new(stats::DecisionStats::default()))
+    .with_policies(policies(json!({
+        "url_allowlist": { "feeds": ["partners"] },
+        "remediation": { "hints": ["call_back"] },
+    })))
+    .build()
+    .err()
+    .expect("expected a build error");
+    let v = &err.violations;
+    assert_eq!(v.len(), 5, "{v:?}");
+    assert!(v[0].starts_with("tokens:"), "{v:?}");
+    assert!(v[1].starts_with("cors:"), "{v:?}");
+    assert_eq!(
+        v[2],
+        "server.read_only is set but the stats store accepts writes"
+    );
+    assert!(v[3].contains("unknown feed 'partners'"), "{v:?}");
+    assert!(v[4].contains("unknown hint 'call_back'"), "{v:?}");
+
+    let rendered = err.to_string();
+    assert!(
+        rendered.starts_with("invalid app state (5 problems)"),
+        "{rendered}"
+    );
+    for violation in v {
+        assert!(rendered.contains(violation.as_str()), "{rendered}");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn test_state_serves_the_full_router() {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    let st = AppStateBuilder::for_tests().build().unwrap();
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let app = app::build_router(st, None, extra);
+
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({
+                "source_id": "doc-1",
+                "source_type": "other",
+                "content_type": "text/plain",
+                "text": "Quarterly numbers are attached.",
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let v: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(v["action"], "allow");
+
+    for path in ["/v1/acip/status", "/v1/acip/policies", "/health"] {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK, "{path}");
+    }
+    std::env::remove_var("ACIP_SENTRY_MODE");
 }
@@ -0,0 +1,394 @@
+use acip_sidecar::egress::EgressPolicy;
+use acip_sidecar::jobs::{self, CallbackState, JobSettings, JobState, JobStatus, JobStore};
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, client, loop_guard, state};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+
+const TOKEN: &str = "t0ken";
+
+fn test_state(jobs: JobStore) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    AppStateBuilder::for_tests()
+        .with_jobs(Arc::new(jobs))
+        .build()
+        .unwrap()
+}
+
+fn router(st: Arc<state::AppState>) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, Some(TOKEN.to_string()), extra)
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let mut b = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-ACIP-Token", TOKEN);
+    if body.is_some() {
+        b = b.header("content-type", "application/json");
+    }
+    let req = b
+        .body(body.map(|v| Body::from(v.to_string())).unwrap_or_default())
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn request(source_id: &str) -> Value {
+    json!({
+        "source_id": source_id,
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Quarterly report: revenue grew 4% on stronger retail demand.",
+    })
+}
+
+/// Poll `GET /v1/acip/jobs/{id}` until the job has finished.
+async fn wait_finished(app: &Router, id: &str) -> JobStatus {
+    for _ in 0..200 {
+        let (code, v) = send(app, "GET", &format!("/v1/acip/jobs/{id}"), None).await;
+        assert_eq!(code, StatusCode::OK, "{v}");
+        let job: JobStatus = serde_json::from_value(v).unwrap();
+        if matches!(job.status, JobState::Complete | JobState::Failed) {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job {id} did not finish");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_submission_is_accepted_and_polled_to_the_same_decision() {
+    let st = test_state(JobStore::default());
+    jobs::start(st.clone());
+    let app = router(st.clone());
+
+    let (code, sync) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source",
+        Some(request("doc-1")),
+    )
+    .await;
+    assert_eq!(code, StatusCode::OK, "{sync}");
+
+    let (code, v) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source?mode=async",
+        Some(request("doc-1")),
+    )
+    .await;
+    assert_eq!(code, StatusCode::ACCEPTED, "{v}");
+    let submitted: JobStatus = serde_json::from_value(v).unwrap();
+    assert_eq!(submitted.status, JobState::Pending);
+    assert_eq!(submitted.source_id, "doc-1");
+    assert!(submitted.result.is_none());
+
+    let job = wait_finished(&app, &submitted.job_id).await;
+    assert_eq!(job.status, JobState::Complete);
+    assert_eq!(job.http_status, Some(200));
+    assert!(job.expires_unix.is_some());
+
+    let mut result = job.result.unwrap();
+    let mut sync = sync;
+    // Each run has its own request id, hence its own origin marker.
+    for v in [&mut result, &mut sync] {
+        let obj = v.as_object_mut().unwrap();
+        obj.remove("origin");
+        let fenced = obj["fenced_content"].as_str().unwrap();
+        let body = fenced.split_once('\n').map_or(fenced, |(_, body)| body);
+        obj["fenced_content"] = Value::from(body);
+    }
+    assert_eq!(result, sync);
+}
+
+#[tokio::test]
+async fn jobs_are_visible_only_to_the_submitting_token() {
+    let st = test_state(JobStore::default());
+    let status = st
+        .jobs
+        .submit(
+            "someone-else",
+            jobs::JobInput {
+                meta: serde_json::from_value(request("doc-2")).unwrap(),
+                headers: HeaderMap::new(),
+                raw_text: Some("hello".to_string()),
+                bytes: vec![],
+            },
+            None,
+            None,
+        )
+        .unwrap();
+    let app = router(st);
+
+    let (code, v) = send(
+        &app,
+        "GET",
+        &format!("/v1/acip/jobs/{}", status.job_id),
+        None,
+    )
+    .await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+    assert_eq!(v["error"], "unknown_job");
+}
+
+#[tokio::test]
+async fn full_queue_is_refused_with_retry_after() {
+    let st = test_state(JobStore::new(
+        JobSettings {
+            queue_capacity: 1,
+            ..JobSettings::default()
+        },
+        Arc::new(MockClock::new(1_000)),
+    ));
+    // No workers: the first job stays queued.
+    let app = router(st.clone());
+
+    let uri = "/v1/acip/ingest_source?mode=async";
+    let (code, _) = send(&app, "POST", uri, Some(request("a"))).await;
+    assert_eq!(code, StatusCode::ACCEPTED);
+    assert_eq!(st.drain.in_flight(), 1);
+
+    let (code, v) = send(&app, "POST", uri, Some(request("b"))).await;
+    assert_eq!(code, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(v["error"], "job_queue_full");
+    assert_eq!(v["extra"]["capacity"], 1);
+    assert_eq!(v["extra"]["retry_after"], jobs::QUEUE_FULL_RETRY_AFTER_SECS);
+
+    let snap = st.jobs.snapshot();
+    assert_eq!(snap["queued"], 1);
+    assert_eq!(snap["running"], 0);
+}
+
+#[tokio::test]
+async fn callback_requires_async_mode_and_an_allowed_host() {
+    let st = test_state(JobStore::default());
+    let app = router(st);
+
+    let mut body = request("doc-3");
+    body["callback_url"] = json!("http://127.0.0.1:9/hook");
+    let (code, v) = send(&app, "POST", "/v1/acip/ingest_source", Some(body.clone())).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    assert_eq!(v["extra"]["field"], "callback_url");
+
+    let (code, v) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source?mode=async",
+        Some(body),
+    )
+    .await;
+    assert_eq!(code, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "callback_not_allowed");
+}
+
+#[test]
+fn callback_urls_must_be_https_to_an_allowed_host_name() {
+    let store = JobStore::new(
+        JobSettings {
+            callbacks: EgressPolicy::new(HashSet::from([
+                "hooks.example.com".to_string(),
+                "10.0.0.5".to_string(),
+            ])),
+            ..JobSettings::default()
+        },
+        Arc::new(MockClock::new(1_000)),
+    );
+    let reason = |url: &str| match store.check_callback(url) {
+        Err(jobs::JobError::CallbackNotAllowed { reason, .. }) => reason,
+        other => panic!("{url}: {other:?}"),
+    };
+    assert!(store
+        .check_callback("https://hooks.example.com/acip")
+        .is_ok());
+    assert_eq!(
+        reason("http://hooks.example.com/acip"),
+        "scheme must be https"
+    );
+    assert_eq!(
+        reason("https://user:pw@hooks.example.com/acip"),
+        "credentials in the URL"
+    );
+    assert_eq!(
+        reason("https://10.0.0.5/acip"),
+        "IP address hosts are not allowed"
+    );
+    assert_eq!(
+        reason("https://evil.example.net/acip"),
+        "host not in ACIP_EGRESS_HOSTS"
+    );
+}
+
+#[tokio::test]
+async fn finished_results_expire_after_ttl() {
+    let clock = Arc::new(MockClock::new(1_000));
+    let store = JobStore::new(
+        JobSettings {
+            result_ttl: Duration::from_secs(60),
+            ..JobSettings::default()
+        },
+        clock.clone(),
+    );
+    let st = test_state(store);
+    let app = router(st.clone());
+
+    let (_, v) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source?mode=async",
+        Some(request("doc-4")),
+    )
+    .await;
+    let id = v["job_id"].as_str().unwrap().to_string();
+    let claimed = st.jobs.claim().unwrap();
+    assert_eq!(claimed.id, id);
+    jobs::run(st.clone(), claimed).await;
+    assert_eq!(st.drain.in_flight(), 0);
+
+    let job = wait_finished(&app, &id).await;
+    assert_eq!(job.expires_unix, Some(1_060));
+
+    clock.advance(59);
+    assert_eq!(st.jobs.sweep_expired(), 0);
+    clock.advance(1);
+    assert_eq!(st.jobs.sweep_expired(), 1);
+
+    let (code, _) = send(&app, "GET", &format!("/v1/acip/jobs/{id}"), None).await;
+    assert_eq!(code, StatusCode::NOT_FOUND);
+}
+
+/// Callback deliveries seen by the test hook: origin marker and body.
+type Received = Arc<Mutex<Vec<(Option<String>, Value)>>>;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn callback_receives_the_finished_job_with_origin_marker() {
+    let received: Received = Arc::default();
+    let sink = received.clone();
+    let hook = Router::new().route(
+        "/hook",
+        post(move |headers: HeaderMap, Json(body): Json<Value>| {
+            let sink = sink.clone();
+            async move {
+                let marker = headers
+                    .get(loop_guard::ORIGIN_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string);
+                sink.lock().unwrap().push((marker, body));
+                StatusCode::NO_CONTENT
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, hook).await.unwrap() });
+
+    let st = test_state(JobStore::new(
+        JobSettings {
+            callbacks: EgressPolicy {
+                allow_http: true,
+                allow_ip_literals: true,
+                ..EgressPolicy::new(HashSet::from(["127.0.0.1".to_string()]))
+            },
+            ..JobSettings::default()
+        },
+        Arc::new(acip_sidecar::reputation::SystemClock),
+    ));
+    jobs::start(st.clone());
+    let app = router(st);
+
+    let mut body = request("doc-5");
+    body["callback_url"] = json!(format!("http://{addr}/hook"));
+    let (code, v) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source?mode=async",
+        Some(body),
+    )
+    .await;
+    assert_eq!(code, StatusCode::ACCEPTED, "{v}");
+    assert_eq!(v["callback"], "pending");
+    let id = v["job_id"].as_str().unwrap().to_string();
+
+    let mut job = wait_finished(&app, &id).await;
+    for _ in 0..200 {
+        if job.callback != Some(CallbackState::Pending) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        job = wait_finished(&app, &id).await;
+    }
+    assert_eq!(job.callback, Some(CallbackState::Delivered));
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    let (marker, payload) = &received[0];
+    assert_eq!(payload["job_id"], id.as_str());
+    assert_eq!(payload["status"], "complete");
+    assert_eq!(
+        marker.as_deref(),
+        job.result.as_ref().unwrap()["origin"]["marker"].as_str()
+    );
+}
+
+fn serve_with_workers(st: Arc<state::AppState>) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            jobs::start(st.clone());
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, router(st)).await.unwrap();
+        });
+    });
+
+    addr
+}
+
+#[test]
+fn client_submits_and_waits_for_a_job() {
+    let addr = serve_with_workers(test_state(JobStore::default()));
+    let c = client::Client::new(&format!("http://{addr}"), Some(TOKEN));
+
+    let job = c.ingest_async(&request("doc-6"), &[]).unwrap();
+    assert_eq!(job.status, JobState::Pending);
+    let done = c
+        .wait_for_job(
+            &job.job_id,
+            Duration::from_millis(10),
+            Duration::from_millis(50),
+        )
+        .unwrap();
+    assert_eq!(done.status, JobState::Complete);
+    assert_eq!(done.result.unwrap()["action"], "allow");
+}
@@ -0,0 +1,182 @@
+use acip_sidecar::b64::{decode_bounded, B64Error};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD},
+    Engine as _,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// Small deterministic PRNG (xorshift64*) so failures are reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+fn inject_whitespace(rng: &mut Rng, s: &str) -> String {
+    const WS: &[char] = &[' ', '\n', '\r', '\t'];
+    let mut out = String::with_capacity(s.len() * 2);
+    for c in s.chars() {
+        if rng.below(8) == 0 {
+            out.push(WS[rng.below(WS.len())]);
+        }
+        out.push(c);
+    }
+    if rng.below(2) == 0 {
+        out.push('\n');
+    }
+    out
+}
+
+#[test]
+fn round_trips_random_bytes_through_encoder_variants() {
+    let mut rng = Rng(0x5EED_ACE5_0000_0001);
+
+    for case in 0..2_000 {
+        let len = rng.below(300);
+        let bytes: Vec<u8> = (0..len).map(|_| rng.next_u64() as u8).collect();
+
+        let variants = [
+            STANDARD.encode(&bytes),
+            STANDARD_NO_PAD.encode(&bytes),
+            URL_SAFE.encode(&bytes),
+            URL_SAFE_NO_PAD.encode(&bytes),
+        ];
+        for encoded in variants {
+            let mangled = inject_whitespace(&mut rng, &encoded);
+            let decoded = decode_bounded(&mangled, usize::MAX)
+                .unwrap_or_else(|e| panic!("case {case}: {e} for {mangled:?}"));
+            assert_eq!(decoded, bytes, "case {case}: {mangled:?}");
+        }
+    }
+}
+
+#[test]
+fn invalid_character_reports_byte_offset() {
+    assert_eq!(
+        decode_bounded("QUJD\nRE*G", 1024),
+        Err(B64Error::InvalidChar {
+            offset: 7,
+            byte: b'*'
+        })
+    );
+}
+
+#[test]
+fn mixed_alphabets_are_rejected_not_misdecoded() {
+    // '+' (standard) then '_' (urlsafe).
+    let err = decode_bounded("ab+c de_f", 1024).unwrap_err();
+    assert_eq!(err, B64Error::MixedAlphabet { offset: 7 });
+}
+
+#[test]
+fn truncated_input_is_rejected() {
+    // A single dangling sextet cannot encode a byte.
+    assert_eq!(
+        decode_bounded("QUJDR", 1024),
+        Err(B64Error::Truncated { offset: 4 })
+    );
+    // Padding that does not complete the quad.
+    assert!(matches!(
+        decode_bounded("QQ=", 1024),
+        Err(B64Error::Truncated { .. })
+    ));
+}
+
+#[test]
+fn misplaced_padding_is_rejected() {
+    assert!(matches!(
+        decode_bounded("Q===", 1024),
+        Err(B64Error::InvalidChar { offset: 1, .. })
+    ));
+    assert!(matches!(
+        decode_bounded("QQ==QUJD", 1024),
+        Err(B64Error::InvalidChar { offset: 4, .. })
+    ));
+}
+
+#[test]
+fn size_limit_is_enforced_while_decoding() {
+    let encoded = STANDARD.encode(vec![7u8; 100]);
+
+    assert_eq!(decode_bounded(&encoded, 100).unwrap().len(), 100);
+    assert_eq!(
+        decode_bounded(&encoded, 99),
+        Err(B64Error::TooLarge { max_bytes: 99 })
+    );
+}
+
+fn router() -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub");
+
+    let st = AppStateBuilder::for_tests().build().unwrap();
+
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn post_ingest(bytes_b64: &str) -> (StatusCode, Value) {
+    let body = serde_json::json!({
+        "source_id": "b64-test",
+        "source_type": "file",
+        "content_type": "text/plain",
+        "bytes_b64": bytes_b64,
+    });
+    let resp = router()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/v1/acip/ingest_source")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn ingest_accepts_wrapped_urlsafe_unpadded_base64() {
+    let encoded = URL_SAFE_NO_PAD.encode("hello from a line-wrapping encoder ~~~???");
+    let wrapped = format!("{}\n{}", &encoded[..20], &encoded[20..]);
+
+    let (status, v) = post_ingest(&wrapped).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+}
+
+#[tokio::test]
+async fn ingest_reports_structured_field_error() {
+    let (status, v) = post_ingest("aGVsbG8=!").await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "invalid_request_field");
+    assert_eq!(v["extra"]["field"], "bytes_b64");
+    assert_eq!(v["extra"]["reason"], "invalid_character");
+    assert_eq!(v["extra"]["offset"], 8);
+}
@@ -0,0 +1,177 @@
+use acip_sidecar::blocking::BlockingPool;
+use acip_sidecar::reputation::{
+    InMemoryReputationStore, Observation, ReputationRecord, ReputationStore,
+};
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, slow_poll, state};
+use axum::{body::Body, http::StatusCode, routing::post, Router};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::json;
+use serial_test::serial;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tower::ServiceExt;
+
+const INGESTS: u32 = 4;
+const WRITE_LATENCY: Duration = Duration::from_millis(400);
+
+/// A reputation store on a very slow disk: every write takes `WRITE_LATENCY`.
+#[derive(Default)]
+struct SlowStore {
+    inner: InMemoryReputationStore,
+    writes: AtomicU64,
+}
+
+impl ReputationStore for SlowStore {
+    fn get(&self, key: &str) -> Option<ReputationRecord> {
+        self.inner.get(key)
+    }
+
+    fn record(&self, obs: Observation) -> Vec<ReputationRecord> {
+        std::thread::sleep(WRITE_LATENCY);
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        self.inner.record(obs)
+    }
+
+    fn list(&self) -> Vec<ReputationRecord> {
+        self.inner.list()
+    }
+}
+
+fn app_state(store: Arc<SlowStore>) -> Arc<state::AppState> {
+    AppStateBuilder::for_tests()
+        .with_reputation(store)
+        .with_model(Arc::new(ScriptedModel::benign()))
+        .with_blocking(Arc::new(BlockingPool::new(INGESTS as usize)))
+        .build()
+        .unwrap()
+}
+
+fn large_pdf(i: u32) -> axum::http::Request<Body> {
+    let body = json!({
+        "source_id": format!("doc-{i}"),
+        "source_type": "pdf",
+        "content_type": "application/pdf",
+        "bytes_b64": B64.encode(b"%PDF-1.4\n"),
+    });
+    axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn large_extractions_and_slow_writes_do_not_stall_the_runtime() {
+    std::env::set_var("ACIP_EXTRACTOR_BIN", env!("CARGO_BIN_EXE_acip-extract"));
+    std::env::set_var("ACIP_EXTRACTOR_SELFTEST_LARGE", "1");
+    std::env::remove_var("ACIP_SENTRY_MODE");
+
+    let store = Arc::new(SlowStore::default());
+    let st = app_state(store.clone());
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    let router = app::build_router(st.clone(), None, extra);
+
+    // Health checks keep being answered while the ingests run.
+    let done = Arc::new(AtomicBool::new(false));
+    let probe = {
+        let (router, done) = (router.clone(), done.clone());
+        tokio::spawn(async move {
+            let (mut answered, mut worst) = (0u32, Duration::ZERO);
+            while !done.load(Ordering::SeqCst) {
+                let start = Instant::now();
+                let req = axum::http::Request::get("/health")
+                    .body(Body::empty())
+                    .unwrap();
+                let resp = router.clone().oneshot(req).await.unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                answered += 1;
+                worst = worst.max(start.elapsed());
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            (answered, worst)
+        })
+    };
+
+    slow_poll::reset();
+    let start = Instant::now();
+    let runs: Vec<_> = (0..INGESTS)
+        .map(|i| tokio::spawn(router.clone().oneshot(large_pdf(i))))
+        .collect();
+    for run in runs {
+        let resp = run.await.unwrap().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let elapsed = start.elapsed();
+    done.store(true, Ordering::SeqCst);
+    let (answered, worst) = probe.await.unwrap();
+
+    std::env::remove_var("ACIP_EXTRACTOR_SELFTEST_LARGE");
+    std::env::remove_var("ACIP_EXTRACTOR_BIN");
+
+    assert_eq!(store.writes.load(Ordering::SeqCst), INGESTS as u64);
+    // With the writes parked on the two runtime workers, a health check waits out a write.
+    assert!(
+        worst < WRITE_LATENCY / 2,
+        "a health check took {worst:?} ({answered} answered in {elapsed:?})"
+    );
+    assert!(
+        answered as u128 >= elapsed.as_millis() / 100,
+        "{answered} in {elapsed:?}"
+    );
+    if cfg!(debug_assertions) {
+        let slow = slow_poll::snapshot();
+        assert_eq!(slow.count, 0, "{slow:?}");
+    }
+    assert!(st.blocking.snapshot().completed >= INGESTS as u64);
+}
+
+#[tokio::test]
+#[serial]
+async fn detector_counts_a_blocking_poll() {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let before = slow_poll::snapshot().count;
+    slow_poll::watch("blocking_test", async {
+        std::thread::sleep(Duration::from_millis(slow_poll::DEFAULT_THRESHOLD_MS * 2));
+    })
+    .await;
+    let after = slow_poll::snapshot();
+    assert!(after.count > before, "{after:?}");
+    assert!(after.worst_ms >= slow_poll::DEFAULT_THRESHOLD_MS * 2);
+}
+
+#[tokio::test]
+async fn pool_runs_at_most_size_jobs_at_once() {
+    let pool = Arc::new(BlockingPool::new(2));
+    let running = Arc::new(AtomicU64::new(0));
+    let peak = Arc::new(AtomicU64::new(0));
+    let jobs: Vec<_> = (0..6)
+        .map(|_| {
+            let (pool, running, peak) = (pool.clone(), running.clone(), peak.clone());
+            tokio::spawn(async move {
+                pool.run(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    std::thread::sleep(Duration::from_millis(30));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await
+            })
+        })
+        .collect();
+    for job in jobs {
+        job.await.unwrap();
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    let snap = pool.snapshot();
+    assert_eq!((snap.size, snap.busy, snap.completed), (2, 0, 6));
+}
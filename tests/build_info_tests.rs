@@ -0,0 +1,199 @@
+mod util;
+
+use acip_sidecar::build_info::{self, BuildInfo, UNKNOWN};
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::{app, client, ingest};
+use assert_cmd::cargo::cargo_bin_cmd;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+use util::serve;
+
+fn router() -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "live");
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn call(app: &Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// A sidecar that only answers `/v1/acip/status`, reporting `version`.
+fn mock_status(version: &'static str) -> String {
+    let router = Router::new().route(
+        "/v1/acip/status",
+        get(move || async move { Json(json!({ "ok": true, "version": { "version": version } })) }),
+    );
+    format!("http://{}", serve(router))
+}
+
+#[tokio::test]
+async fn status_and_provenance_report_the_build() {
+    let app = router();
+    let (status, v) = call(
+        &app,
+        Request::get("/v1/acip/status").body(Body::empty()).unwrap(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let version = &v["version"];
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    let keys: Vec<&str> = version
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "build_timestamp",
+            "features",
+            "git_describe",
+            "git_dirty",
+            "profile",
+            "rustc",
+            "version"
+        ]
+    );
+    assert_eq!(version["profile"], "debug");
+    assert!(version["rustc"].as_str().unwrap().starts_with("rustc "));
+
+    let body = json!({
+        "source_id": "s1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Meeting moved to Thursday at 10am.",
+    });
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, v) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(&v["provenance"]["build"], version, "{v}");
+}
+
+#[test]
+fn a_build_without_git_reports_unknown_fields() {
+    // What `build.rs` leaves behind for a source tarball without `.git` or a `git` binary.
+    let info = BuildInfo::from_lookup("0.1.0", |key| {
+        (key == "ACIP_BUILD_PROFILE").then(|| "release".to_string())
+    });
+    assert_eq!(
+        info,
+        BuildInfo {
+            version: "0.1.0".to_string(),
+            git_describe: UNKNOWN.to_string(),
+            git_dirty: None,
+            build_timestamp: None,
+            rustc: UNKNOWN.to_string(),
+            profile: "release".to_string(),
+            features: vec![],
+        }
+    );
+    assert_eq!(
+        info.summary(),
+        "0.1.0 (git unknown, release, unknown, features: none)"
+    );
+
+    let info = BuildInfo::from_lookup("0.1.0", |key| match key {
+        "ACIP_BUILD_GIT_DESCRIBE" => Some("v0.1.0-3-g0123456789ab-dirty".to_string()),
+        "ACIP_BUILD_TIMESTAMP" => Some("1760000000".to_string()),
+        "ACIP_BUILD_FEATURES" => Some("otel".to_string()),
+        _ => Some(String::new()),
+    });
+    assert_eq!(info.git_dirty, Some(true));
+    assert_eq!(info.build_timestamp, Some(1_760_000_000));
+    assert_eq!(info.features, ["otel"]);
+    assert_eq!(info.profile, UNKNOWN);
+}
+
+#[test]
+fn only_incompatible_versions_are_skew() {
+    assert!(build_info::compatible("1.2.0", "1.9.3"));
+    assert!(build_info::compatible("0.1.0", "0.1.7-rc.1"));
+    assert!(!build_info::compatible("0.1.0", "0.2.0"));
+    assert!(!build_info::compatible("1.0.0", "2.0.0"));
+    assert_eq!(build_info::skew_warning("1.0.0", "1.4.0"), None);
+}
+
+#[test]
+fn client_and_acipctl_warn_on_version_skew() {
+    let url = mock_status("99.0.0");
+    let c = client::Client::new(&url, None);
+    assert_eq!(c.server_version().unwrap(), "99.0.0");
+    let warning = c.version_skew().unwrap().unwrap();
+    assert_eq!(
+        warning,
+        format!(
+            "client version {} does not match server version 99.0.0; upgrade the older one",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+
+    // The command itself fails (the mock has no stats), but the warning comes first.
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "stats"])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&out.get_output().stderr).to_string();
+    assert!(
+        stderr.starts_with(&format!(
+            "warning: {warning} (--no-version-check to silence)\n"
+        )),
+        "{stderr}"
+    );
+
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "--no-version-check", "stats"])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&out.get_output().stderr).to_string();
+    assert!(!stderr.contains("warning:"), "{stderr}");
+
+    // A compatible server gets no warning.
+    let url = mock_status(env!("CARGO_PKG_VERSION"));
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "stats"])
+        .assert()
+        .failure();
+    let stderr = String::from_utf8_lossy(&out.get_output().stderr).to_string();
+    assert!(!stderr.contains("warning:"), "{stderr}");
+}
+
+#[test]
+fn acipctl_version_prints_the_build() {
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--version", "--output", "json"])
+        .assert()
+        .success();
+    let v: Value = serde_json::from_slice(&out.get_output().stdout).unwrap();
+    assert_eq!(v, json!(build_info::current()));
+
+    cargo_bin_cmd!("acipctl")
+        .arg("--version")
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with(format!(
+            "acipctl {} (git ",
+            env!("CARGO_PKG_VERSION")
+        )));
+}
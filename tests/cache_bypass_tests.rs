@@ -0,0 +1,189 @@
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::token_auth::{Scope, TokenSet};
+use acip_sidecar::verdicts::{Provenance, VerdictHistory};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const SUPPORT: &str = "support-t0ken";
+const INGEST_ONLY: &str = "ingest-t0ken";
+
+fn test_state(model: Arc<ScriptedModel>, clock: Arc<MockClock>) -> Arc<state::AppState> {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    AppStateBuilder::for_tests()
+        .with_verdicts(Arc::new(VerdictHistory::new(100, clock)))
+        .with_model(model)
+        .build()
+        .unwrap()
+}
+
+fn router(st: Arc<state::AppState>) -> Router {
+    let mut tokens = TokenSet::default();
+    let scopes = |s: &[Scope]| s.iter().copied().collect::<BTreeSet<_>>();
+    tokens
+        .add(
+            "oncall",
+            SUPPORT,
+            scopes(&[Scope::Ingest, Scope::Support, Scope::Read]),
+        )
+        .unwrap();
+    tokens
+        .add("ingest-bot", INGEST_ONLY, scopes(&[Scope::Ingest]))
+        .unwrap();
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router_with_tokens(st, tokens, extra)
+}
+
+async fn send(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    token: &str,
+    bypass: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut b = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-ACIP-Token", token)
+        .header("content-type", "application/json");
+    if let Some(mode) = bypass {
+        b = b.header("X-ACIP-Bypass-Cache", mode);
+    }
+    let req = b
+        .body(body.map(|v| Body::from(v.to_string())).unwrap_or_default())
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn ingest(app: &Router, token: &str, bypass: Option<&str>) -> (StatusCode, Value) {
+    let body = json!({
+        "source_id": "mail-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": "Minutes of the Tuesday planning meeting.",
+    });
+    send(
+        app,
+        "POST",
+        "/v1/acip/ingest_source",
+        token,
+        bypass,
+        Some(body),
+    )
+    .await
+}
+
+/// The remembered verdict for the fixture, serialized.
+fn remembered(st: &state::AppState, sha: &str) -> String {
+    let provenance = Provenance::current(st.policies.get("default").unwrap());
+    let (rec, _) = st.verdicts.lookup("default", sha, 30, &provenance).unwrap();
+    serde_json::to_string(&rec).unwrap()
+}
+
+#[tokio::test]
+async fn refresh_reruns_the_pipeline_and_replaces_the_remembered_verdict() {
+    let model = Arc::new(ScriptedModel::benign());
+    let clock = Arc::new(MockClock::new(1_000));
+    let st = test_state(model.clone(), clock.clone());
+    let app = router(st.clone());
+
+    let (code, v) = ingest(&app, SUPPORT, None).await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    assert!(v.get("cache_bypass").is_none());
+    let sha = v["digest"]["sha256"].as_str().unwrap().to_string();
+    let warm = remembered(&st, &sha);
+    let calls = model.calls();
+
+    clock.advance(60);
+    let (code, v) = ingest(&app, SUPPORT, Some("refresh")).await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    assert_eq!(v["cache_bypass"], "refresh");
+    assert!(model.calls() > calls, "the models were not asked again");
+    let fresh = remembered(&st, &sha);
+    assert_ne!(fresh, warm);
+    assert!(fresh.contains("\"decided_unix\":1060"), "{fresh}");
+}
+
+#[tokio::test]
+async fn ghost_reruns_the_pipeline_and_leaves_the_remembered_verdict_alone() {
+    let model = Arc::new(ScriptedModel::benign());
+    let clock = Arc::new(MockClock::new(1_000));
+    let st = test_state(model.clone(), clock.clone());
+    let app = router(st.clone());
+
+    let (_, v) = ingest(&app, SUPPORT, None).await;
+    let sha = v["digest"]["sha256"].as_str().unwrap().to_string();
+    let warm = remembered(&st, &sha);
+    let calls = model.calls();
+
+    clock.advance(60);
+    let (code, v) = ingest(&app, SUPPORT, Some("ghost")).await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    assert_eq!(v["cache_bypass"], "ghost");
+    assert!(model.calls() > calls, "the models were not asked again");
+    assert_eq!(remembered(&st, &sha), warm);
+
+    let request_id = v["origin"]["request_id"].as_str().unwrap();
+    let (code, inc) = send(
+        &app,
+        "GET",
+        &format!("/v1/acip/incidents/{request_id}"),
+        SUPPORT,
+        None,
+        None,
+    )
+    .await;
+    assert_eq!(code, StatusCode::OK, "{inc}");
+    assert_eq!(inc["audit"]["cache_bypass"], "ghost");
+    assert_eq!(inc["audit"]["actor"], "oncall");
+
+    let (_, status) = send(&app, "GET", "/v1/acip/status", SUPPORT, None, None).await;
+    assert_eq!(status["cache_bypass"]["ghost"]["total"], 1);
+    assert_eq!(status["cache_bypass"]["ghost"]["by_token"]["oncall"], 1);
+    assert_eq!(status["cache_bypass"]["refresh"]["total"], 0);
+}
+
+#[tokio::test]
+async fn bypass_needs_the_support_scope_and_a_known_mode() {
+    let model = Arc::new(ScriptedModel::benign());
+    let st = test_state(model.clone(), Arc::new(MockClock::new(1_000)));
+    let app = router(st);
+
+    let (code, v) = ingest(&app, INGEST_ONLY, Some("true")).await;
+    assert_eq!(code, StatusCode::FORBIDDEN, "{v}");
+    assert_eq!(v["error"], "insufficient_scope");
+    assert_eq!(v["extra"]["required"], "support");
+    assert_eq!(v["extra"]["header"], "x-acip-bypass-cache");
+    assert_eq!(model.calls(), 0);
+
+    let (code, v) = ingest(&app, SUPPORT, Some("sometimes")).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST, "{v}");
+    assert_eq!(v["error"], "invalid_header");
+
+    // `false` is no bypass at all, so any ingest token may send it.
+    let (code, v) = ingest(&app, INGEST_ONLY, Some("false")).await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    assert!(v.get("cache_bypass").is_none());
+}
@@ -0,0 +1,257 @@
+mod util;
+
+use acip_sidecar::capabilities::{Capabilities, Rejection};
+use acip_sidecar::token_auth::{Scope, TokenSet};
+use acip_sidecar::uploads::{UploadSettings, UploadStore};
+use acip_sidecar::{
+    app, app_state_builder::AppStateBuilder, client, policy_store, reputation, state,
+};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tower::ServiceExt;
+use util::serve;
+
+fn test_state(policies: &[(&str, u64)], upload_sessions: usize) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let mut map = BTreeMap::new();
+    for (name, max_age) in policies {
+        let mut p = acip_sidecar::model_policy::PolicyConfig::default();
+        p.cache.max_verdict_age_days = *max_age;
+        map.insert(name.to_string(), p);
+    }
+
+    AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies: map },
+        ))
+        .with_uploads(Arc::new(UploadStore::new(
+            UploadSettings {
+                max_sessions: upload_sessions,
+                ..UploadSettings::default()
+            },
+            Arc::new(acip_sidecar::tmpdir::TmpDirManager::default()),
+            Arc::new(reputation::SystemClock),
+        )))
+        .build()
+        .unwrap()
+}
+
+fn ingest_route() -> Router<Arc<state::AppState>> {
+    Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    )
+}
+
+async fn get_caps(
+    app: Router,
+    token: Option<&str>,
+    etag: Option<&str>,
+) -> (StatusCode, Option<String>, Value) {
+    let mut b = axum::http::Request::builder().uri("/v1/acip/capabilities");
+    if let Some(t) = token {
+        b = b.header("X-ACIP-Token", t);
+    }
+    if let Some(e) = etag {
+        b = b.header("If-None-Match", e);
+    }
+    let resp = app.oneshot(b.body(Body::empty()).unwrap()).await.unwrap();
+    let status = resp.status();
+    let etag = resp
+        .headers()
+        .get("etag")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        etag,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn endpoint<'a>(caps: &'a Capabilities, path: &str) -> &'a acip_sidecar::capabilities::Endpoint {
+    caps.endpoints.iter().find(|e| e.path == path).unwrap()
+}
+
+#[tokio::test]
+async fn document_reflects_live_configuration() {
+    let app = app::build_router(
+        test_state(&[("default", 30), ("strict", 7)], 8),
+        None,
+        ingest_route(),
+    );
+    let (status, etag, v) = get_caps(app, None, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(etag.is_some());
+
+    let caps: Capabilities = serde_json::from_value(v).unwrap();
+    assert!(caps.source_types.iter().any(|t| t == "pdf"));
+    assert_eq!(caps.source_types.len(), 6);
+    assert_eq!(
+        caps.limits.max_request_body_bytes,
+        app::MAX_REQUEST_BODY_BYTES as u64
+    );
+    assert_eq!(caps.schema_versions.decision, vec![1]);
+    assert_eq!(caps.sentry_mode, "stub-open");
+    assert!(caps.features.resumable_uploads);
+    assert!(!caps.features.streaming && !caps.features.batch && !caps.features.grpc);
+
+    // No tokens: anonymous, no token header, admin routes not served.
+    assert_eq!(caps.token.name, "anonymous");
+    assert!(!caps.features.admin);
+    assert!(!endpoint(&caps, "/v1/acip/admin/drain").available);
+
+    let strict = caps.policy("strict").unwrap();
+    assert_eq!(strict.required_headers, vec!["x-acip-policy"]);
+    assert_eq!(strict.truncation.full_if_lte, 9000);
+    assert!(strict.revision.is_some());
+    assert!(caps.policy("default").unwrap().required_headers.is_empty());
+}
+
+#[tokio::test]
+async fn disabled_uploads_are_reported_and_not_routed() {
+    let app = app::build_router(test_state(&[("default", 30)], 0), None, ingest_route());
+    let (_, _, v) = get_caps(app.clone(), None, None).await;
+    let caps: Capabilities = serde_json::from_value(v).unwrap();
+
+    assert!(!caps.features.resumable_uploads);
+    assert_eq!(caps.limits.max_upload_sessions, 0);
+    assert!(!endpoint(&caps, "/v1/acip/uploads").available);
+    assert!(endpoint(&caps, "/v1/acip/ingest_source").available);
+
+    let resp = app
+        .oneshot(
+            axum::http::Request::builder()
+                .method("POST")
+                .uri("/v1/acip/uploads")
+                .header("content-type", "application/json")
+                .body(Body::from("{}"))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn document_is_scoped_to_the_presenting_token() {
+    let mut tokens = TokenSet::default();
+    tokens
+        .add(
+            "reader",
+            "secret-reader",
+            [Scope::Read].into_iter().collect(),
+        )
+        .unwrap();
+    let app =
+        app::build_router_with_tokens(test_state(&[("default", 30)], 8), tokens, ingest_route());
+
+    let (_, _, v) = get_caps(app, Some("secret-reader"), None).await;
+    let caps: Capabilities = serde_json::from_value(v).unwrap();
+
+    assert_eq!(caps.token.name, "reader");
+    assert_eq!(caps.token.scopes, vec![Scope::Read]);
+    assert!(caps.features.admin);
+    assert!(!endpoint(&caps, "/v1/acip/ingest_source").allowed);
+    assert!(endpoint(&caps, "/v1/acip/capabilities").allowed);
+    assert_eq!(
+        caps.policy("default").unwrap().required_headers,
+        vec!["x-acip-token"]
+    );
+    assert_eq!(
+        caps.check_ingest(10, "other", "default"),
+        Err(Rejection::NotAllowed {
+            scope: Scope::Ingest
+        })
+    );
+}
+
+#[tokio::test]
+async fn etag_revalidates_and_changes_with_policies() {
+    let app = app::build_router(test_state(&[("default", 30)], 8), None, ingest_route());
+    let (_, etag, _) = get_caps(app.clone(), None, None).await;
+    let etag = etag.unwrap();
+
+    let (status, again, _) = get_caps(app, None, Some(&etag)).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(again.as_deref(), Some(etag.as_str()));
+
+    // Same policy names, one edited: the policy revision and with it the ETag change.
+    let reloaded = app::build_router(test_state(&[("default", 7)], 8), None, ingest_route());
+    let (status, new_etag, _) = get_caps(reloaded, None, Some(&etag)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(new_etag.unwrap(), etag);
+}
+
+#[test]
+fn client_caches_capabilities_and_prevalidates_ingest() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let router = app::build_router(test_state(&[("default", 30)], 8), None, ingest_route()).layer(
+        middleware::from_fn(move |req: Request, next: Next| {
+            let counter = counter.clone();
+            async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                next.run(req).await
+            }
+        }),
+    );
+    let addr = serve(router);
+    let c = client::Client::new(&format!("http://{addr}"), None);
+
+    let caps = c.capabilities().unwrap();
+    // Revalidation is a 304 answered from the cache.
+    assert_eq!(c.capabilities().unwrap(), caps);
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    let big = "x".repeat(caps.limits.max_request_body_bytes as usize + 1);
+    let err = c
+        .ingest(
+            &json!({"source_id": "s1", "source_type": "other", "content_type": "text/plain", "text": big}),
+            &[],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Rejection>(),
+        Some(Rejection::PayloadTooLarge { .. })
+    ));
+    let err = c
+        .ingest(
+            &json!({"source_id": "s1", "source_type": "other", "content_type": "text/plain", "text": "hi"}),
+            &[("X-ACIP-Policy", "missing".to_string())],
+        )
+        .unwrap_err();
+    assert!(matches!(
+        err.downcast_ref::<Rejection>(),
+        Some(Rejection::UnknownPolicy(_))
+    ));
+    // Neither refused request reached the server.
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+    let v = c
+        .ingest(
+            &json!({"source_id": "s1", "source_type": "other", "content_type": "text/plain", "text": "hello"}),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(v["action"], "allow");
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,292 @@
+use acip_sidecar::chunking::{self, ChunkIndex, ChunkingMode, ChunkingSettings, OMITTED_MARKER};
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::{app, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const AVG: usize = 4096;
+
+fn settings() -> ChunkingSettings {
+    ChunkingSettings {
+        mode: ChunkingMode::ContentDefined,
+        min_bytes: 256 * 1024,
+        avg_chunk_bytes: AVG,
+        ..ChunkingSettings::default()
+    }
+}
+
+/// About `len` bytes of word salad, the same for the same `seed`.
+fn document(seed: u64, len: usize) -> String {
+    let words: Vec<&str> = "invoice quarterly meeting summary budget the of and review planning \
+         customer report draft shipping ledger notes"
+        .split(' ')
+        .collect();
+    let mut x = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1;
+    let mut out = String::with_capacity(len + 16);
+    while out.len() < len {
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        out.push_str(words[(x % words.len() as u64) as usize]);
+        out.push(if x.is_multiple_of(11) { '\n' } else { ' ' });
+    }
+    out
+}
+
+fn appended(base: &str) -> String {
+    format!("{base}\n\nAppendix Z. Late addition to the minutes: one more page of notes.\n")
+}
+
+fn test_state(model: ScriptedModel) -> Arc<state::AppState> {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    let st = test_support::golden_state(Some(Arc::new(model))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    st.chunks = Arc::new(ChunkIndex::new(settings()));
+    Arc::new(st)
+}
+
+fn router(st: Arc<state::AppState>) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, None, extra)
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn ingest(app: &Router, text: &str) -> Value {
+    let body = json!({
+        "source_id": "bundle-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": text,
+    });
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let (status, v) = send(app, req).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    v
+}
+
+async fn chunking_status(app: &Router) -> Value {
+    let req = Request::get("/v1/acip/status").body(Body::empty()).unwrap();
+    let (status, v) = send(app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    v["chunking"].clone()
+}
+
+fn reasons(v: &Value) -> Vec<String> {
+    v["reasons"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r.as_str().unwrap().to_string())
+        .collect()
+}
+
+#[test]
+fn cut_points_survive_an_append_and_an_edit_only_touches_its_chunks() {
+    let base = document(7, 400 * 1024);
+    let a = chunking::chunk(base.as_bytes(), AVG);
+    assert!(a.len() > 40, "{} chunks", a.len());
+    assert!(a.iter().all(|c| c.len <= AVG * 8));
+    assert_eq!(a.iter().map(|c| c.len).sum::<usize>(), base.len());
+
+    let b = chunking::chunk(appended(&base).as_bytes(), AVG);
+    let o = chunking::overlap("a", &index_of(&a), &b);
+    assert!(o.chunks - o.chunks_reused <= 2, "{o:?}");
+    assert_eq!(o.changed.last().unwrap().end, appended(&base).len());
+
+    // Overwrite a few bytes in the middle: the change stays local.
+    let mut edited = base.clone().into_bytes();
+    edited[200_000..200_010].copy_from_slice(b"XXXXXXXXXX");
+    let o = chunking::overlap("a", &index_of(&a), &chunking::chunk(&edited, AVG));
+    assert_eq!(o.changed.len(), 1);
+    assert!(o.changed[0].contains(&200_000));
+    assert!(o.changed_fraction() < 0.05, "{o:?}");
+}
+
+fn index_of(chunks: &[chunking::Chunk]) -> Vec<(String, usize)> {
+    chunks.iter().map(|c| (c.hash.clone(), c.len)).collect()
+}
+
+#[test]
+fn best_match_honours_the_similarity_threshold_and_the_tenant() {
+    let base = document(3, 300 * 1024);
+    let next = appended(&base);
+    let index = ChunkIndex::new(settings());
+    let old = index.chunk(base.as_bytes());
+    let new = index.chunk(next.as_bytes());
+    index.record("team-a", "default", "sha-old", &old);
+
+    let o = index
+        .best_match("team-a", "default", "sha-new", &new)
+        .unwrap();
+    assert_eq!(o.prior_sha, "sha-old");
+    // Other tenants, other policies and the same content never match.
+    assert!(index
+        .best_match("team-b", "default", "sha-new", &new)
+        .is_none());
+    assert!(index
+        .best_match("team-a", "strict", "sha-new", &new)
+        .is_none());
+    assert!(index
+        .best_match("team-a", "default", "sha-old", &new)
+        .is_none());
+
+    let at = |similarity: f64| {
+        let index = ChunkIndex::new(ChunkingSettings {
+            similarity,
+            ..settings()
+        });
+        index.record("team-a", "default", "sha-old", &old);
+        index.best_match("team-a", "default", "sha-new", &new)
+    };
+    assert!(at(o.shared_fraction).is_some());
+    assert!(at(o.shared_fraction + 1e-9).is_none());
+}
+
+#[test]
+fn changed_text_keeps_context_and_marks_what_it_left_out() {
+    let text = "a".repeat(100) + "CHANGED" + &"b".repeat(100);
+    let out = chunking::changed_text(&text, &[20..22, 100..107], 5);
+    let m = OMITTED_MARKER;
+    assert_eq!(out, format!("{m}aaaaaaaaaaaa{m}aaaaaCHANGEDbbbbb{m}"));
+    // A region at the end has no trailing marker; overlapping windows merge.
+    let out = chunking::changed_text(&text, &[0..3, 6..8, 200..207], 2);
+    assert_eq!(out, format!("aaaaaaaaaa{OMITTED_MARKER}bbbbbbbbb"));
+}
+
+#[test]
+fn settings_validate_and_default_to_whole_file_hashing() {
+    let d = ChunkingSettings::from_config(None).unwrap();
+    assert_eq!(d.mode, ChunkingMode::Whole);
+    assert!(!ChunkIndex::new(d).applies(usize::MAX));
+
+    let cfg = |avg: usize, similarity: f64| acip_sidecar::config::ChunkingConfig {
+        mode: Some(ChunkingMode::ContentDefined),
+        avg_chunk_bytes: Some(avg),
+        similarity: Some(similarity),
+        ..Default::default()
+    };
+    assert!(ChunkingSettings::from_config(Some(&cfg(4096, 0.8))).is_ok());
+    assert!(ChunkingSettings::from_config(Some(&cfg(16, 0.8))).is_err());
+    assert!(ChunkingSettings::from_config(Some(&cfg(4096, 0.0))).is_err());
+    assert!(ChunkingSettings::from_config(Some(&cfg(4096, 1.5))).is_err());
+}
+
+#[tokio::test]
+async fn an_appended_page_reuses_the_prior_verdict_for_the_unchanged_chunks() {
+    let model = ScriptedModel::benign().on(
+        OMITTED_MARKER.trim(),
+        json!({
+            "tools_allowed": true,
+            "risk_level": "low",
+            "action": "allow",
+            "reasons": ["scripted: changed regions only"],
+        }),
+    );
+    let app = router(test_state(model));
+    let base = document(11, 1_000_000);
+
+    let first = ingest(&app, &base).await;
+    assert!(first.get("chunk_reuse").is_none(), "{first}");
+    let status = chunking_status(&app).await;
+    let total = status["chunks_extracted"].as_u64().unwrap();
+    assert!(total > 100, "{status}");
+    assert_eq!(status["chunks_reused"], 0);
+    assert_eq!(status["indexed"], 1);
+
+    let second = ingest(&app, &appended(&base)).await;
+    let reuse = &second["chunk_reuse"];
+    assert!(
+        reuse["changed_fraction"].as_f64().unwrap() < 0.02,
+        "{reuse}"
+    );
+    let reused = reuse["chunks_reused"].as_u64().unwrap();
+    assert!(reuse["chunks"].as_u64().unwrap() - reused <= 2, "{reuse}");
+    assert!(!reuse["prior_content_id"].as_str().unwrap().is_empty());
+    let r = reasons(&second);
+    assert!(
+        r.iter().any(|r| r == "scripted: changed regions only"),
+        "{r:?}"
+    );
+    assert!(r.iter().any(|r| r.starts_with("partial_reuse: ")), "{r:?}");
+    assert!(second["normalization_steps"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("chunk_reuse")));
+    // The caller still gets the whole input fenced.
+    let fenced = second["fenced_content"].as_str().unwrap();
+    assert!(fenced.contains("Appendix Z."));
+    assert!(!fenced.contains(OMITTED_MARKER.trim()));
+
+    let status = chunking_status(&app).await;
+    assert_eq!(status["partial_reuses"], 1);
+    assert_eq!(status["chunks_reused"], reused);
+    assert!(status["chunks_extracted"].as_u64().unwrap() - total <= 2);
+}
+
+#[tokio::test]
+async fn the_stricter_of_the_prior_verdict_and_the_changed_regions_wins() {
+    let model = ScriptedModel::new(json!({
+        "tools_allowed": false,
+        "risk_level": "medium",
+        "action": "needs_review",
+        "reasons": ["scripted: unsure"],
+    }))
+    .on(
+        OMITTED_MARKER.trim(),
+        json!({"tools_allowed": true, "risk_level": "low", "action": "allow"}),
+    );
+    let app = router(test_state(model));
+    let base = document(5, 1_000_000);
+
+    assert_eq!(ingest(&app, &base).await["action"], "needs_review");
+    let v = ingest(&app, &appended(&base)).await;
+    assert!(v.get("chunk_reuse").is_some(), "{v}");
+    assert_eq!(v["action"], "needs_review");
+    assert_eq!(v["risk_level"], "medium");
+    assert_eq!(v["tools_allowed"], false);
+}
+
+#[tokio::test]
+async fn unrelated_and_small_inputs_run_in_full() {
+    let app = router(test_state(ScriptedModel::benign()));
+    ingest(&app, &document(1, 1_000_000)).await;
+
+    // Same size, different content.
+    let v = ingest(&app, &document(2, 1_000_000)).await;
+    assert!(v.get("chunk_reuse").is_none(), "{v}");
+
+    // Below `min_bytes`: not chunked at all.
+    let small = document(1, 100 * 1024);
+    ingest(&app, &small).await;
+    let v = ingest(&app, &appended(&small)).await;
+    assert!(v.get("chunk_reuse").is_none(), "{v}");
+
+    let status = chunking_status(&app).await;
+    assert_eq!(status["indexed"], 2);
+    assert_eq!(status["partial_reuses"], 0);
+    assert_eq!(status["mode"], "content_defined");
+}
@@ -0,0 +1,223 @@
+use acip_sidecar::disconnect::{Disconnects, OnClientDisconnect};
+use acip_sidecar::sentry::ModelClient;
+use acip_sidecar::slow_requests::Stage;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, state};
+use async_trait::async_trait;
+use axum::{http::HeaderMap, routing::post, Router};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::io::AsyncWriteExt;
+
+/// Counts calls; each call hangs until dropped, and marks `aborted` when it is.
+#[derive(Default)]
+struct HangingModel {
+    calls: AtomicUsize,
+    aborted: Arc<AtomicBool>,
+}
+
+struct SetOnDrop(Arc<AtomicBool>);
+
+impl Drop for SetOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+#[async_trait]
+impl ModelClient for HangingModel {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        let _guard = SetOnDrop(self.aborted.clone());
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        anyhow::bail!("model call was not aborted")
+    }
+}
+
+fn app_state(mode: OnClientDisconnect, model: Arc<HangingModel>) -> Arc<state::AppState> {
+    AppStateBuilder::for_tests()
+        .with_model(model)
+        .with_disconnects(Arc::new(Disconnects::new(mode)))
+        .build()
+        .unwrap()
+}
+
+/// Serve `st` on a loopback port.
+async fn serve(st: Arc<state::AppState>) -> std::net::SocketAddr {
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    let router = app::build_router(st, None, extra);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    addr
+}
+
+/// Send an ingest and return the open connection without reading the response.
+async fn send_ingest(addr: std::net::SocketAddr, body: Value) -> tokio::net::TcpStream {
+    let body = body.to_string();
+    let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let head = format!(
+        "POST /v1/acip/ingest_source HTTP/1.1\r\nHost: localhost\r\n\
+         Content-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await.unwrap();
+    stream.write_all(body.as_bytes()).await.unwrap();
+    stream
+}
+
+/// An extractor that records its pid in `pid_file` and then hangs.
+fn slow_extractor(dir: &Path) -> PathBuf {
+    let pid_file = dir.join("helper.pid");
+    let script = dir.join("slow-extract");
+    fs::write(
+        &script,
+        format!(
+            "#!/bin/sh\ncat >/dev/null\necho $$ > {}\nexec sleep 60\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("ACIP_EXTRACTOR_BIN", &script);
+    std::env::set_var("ACIP_EXTRACTOR_TIMEOUT_SECS", "120");
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    pid_file
+}
+
+async fn wait_for(what: &str, mut done: impl FnMut() -> bool) -> Duration {
+    let start = Instant::now();
+    while !done() {
+        assert!(
+            start.elapsed() < Duration::from_secs(10),
+            "timed out: {what}"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    start.elapsed()
+}
+
+fn pdf_body(source_id: &str) -> Value {
+    json!({
+        "source_id": source_id,
+        "source_type": "pdf",
+        "content_type": "application/pdf",
+        "bytes_b64": B64.encode(include_bytes!("fixtures/acip_known_text.pdf")),
+    })
+}
+
+/// Disconnect while the helper runs; returns how long the helper outlived the connection.
+async fn disconnect_during_extraction(addr: std::net::SocketAddr, pid_file: &Path) -> Duration {
+    let stream = send_ingest(addr, pdf_body("dropper")).await;
+    wait_for("helper started", || {
+        fs::read_to_string(pid_file).is_ok_and(|p| p.ends_with('\n'))
+    })
+    .await;
+    let pid = fs::read_to_string(pid_file).unwrap().trim().to_string();
+    let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+    assert!(proc_dir.exists());
+
+    drop(stream);
+    wait_for("helper killed", || !proc_dir.exists()).await
+}
+
+#[tokio::test]
+#[serial]
+async fn complete_mode_kills_the_helper_and_keeps_reputation_and_audit() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = slow_extractor(dir.path());
+    let model = Arc::new(HangingModel::default());
+    let st = app_state(OnClientDisconnect::CompleteSideEffects, model.clone());
+    let addr = serve(st.clone()).await;
+
+    let lag = disconnect_during_extraction(addr, &pid_file).await;
+    assert!(
+        lag < Duration::from_secs(2),
+        "helper outlived the client by {lag:?}"
+    );
+
+    wait_for("audit entry", || st.incidents.len() == 1).await;
+    let entry = st.incidents.list().remove(0);
+    assert_eq!(entry.http_status, 499);
+    assert_eq!(entry.error.as_deref(), Some("client_disconnected"));
+    assert_eq!(entry.disconnected_at, Some(Stage::Extract));
+
+    let rec = st.reputation.get("source_id:dropper").unwrap();
+    assert_eq!(rec.seen_count, 1);
+    assert_eq!(model.calls.load(Ordering::SeqCst), 0);
+
+    let snap = st.disconnects.snapshot();
+    assert_eq!(snap.cancelled, 1);
+    assert_eq!(snap.by_stage.get("extract"), Some(&1));
+}
+
+#[tokio::test]
+#[serial]
+async fn abandon_mode_makes_no_model_call_and_writes_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let pid_file = slow_extractor(dir.path());
+    let model = Arc::new(HangingModel::default());
+    let st = app_state(OnClientDisconnect::Abandon, model.clone());
+    let addr = serve(st.clone()).await;
+
+    let lag = disconnect_during_extraction(addr, &pid_file).await;
+    assert!(
+        lag < Duration::from_secs(2),
+        "helper outlived the client by {lag:?}"
+    );
+
+    wait_for("run cancelled", || st.disconnects.snapshot().cancelled == 1).await;
+    assert_eq!(model.calls.load(Ordering::SeqCst), 0);
+    assert!(st.reputation.list().is_empty());
+    assert!(st.incidents.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn in_flight_model_call_is_aborted() {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    let model = Arc::new(HangingModel::default());
+    let st = app_state(OnClientDisconnect::CompleteSideEffects, model.clone());
+    let addr = serve(st.clone()).await;
+
+    let stream = send_ingest(
+        addr,
+        json!({
+            "source_id": "impatient",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "text": "plain text that goes to the model",
+        }),
+    )
+    .await;
+    wait_for("model called", || model.calls.load(Ordering::SeqCst) == 1).await;
+    drop(stream);
+    wait_for("model call aborted", || {
+        model.aborted.load(Ordering::SeqCst)
+    })
+    .await;
+
+    wait_for("audit entry", || st.incidents.len() == 1).await;
+    let entry = st.incidents.list().remove(0);
+    assert_eq!(entry.disconnected_at, Some(Stage::Model));
+    // Reputation was recorded before the model stage, once.
+    assert_eq!(
+        st.reputation.get("source_id:impatient").unwrap().seen_count,
+        1
+    );
+}
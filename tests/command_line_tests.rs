@@ -0,0 +1,128 @@
+use acip_sidecar::command_line::{quote, CommandLine};
+use proptest::prelude::*;
+
+fn reparse(s: &str) -> Vec<String> {
+    shell_words::split(s).expect("display string parses")
+}
+
+#[test]
+fn quote_leaves_safe_words_alone() {
+    assert_eq!(quote("acip-sidecar"), "acip-sidecar");
+    assert_eq!(
+        quote("/srv/acip/docker-compose.yml"),
+        "/srv/acip/docker-compose.yml"
+    );
+    assert_eq!(quote("user@host:8080"), "user@host:8080");
+    assert_eq!(quote("--env-file=prod.env"), "--env-file=prod.env");
+    assert_eq!(quote("a,b+c%d"), "a,b+c%d");
+}
+
+#[test]
+fn quote_empty_string_keeps_an_argument() {
+    assert_eq!(quote(""), "''");
+    assert_eq!(reparse(&format!("x {} y", quote(""))), ["x", "", "y"]);
+}
+
+#[test]
+fn quote_embedded_single_quotes() {
+    assert_eq!(quote("it's"), r"'it'\''s'");
+    assert_eq!(reparse(&quote("'")), ["'"]);
+    assert_eq!(reparse(&quote("''a''")), ["''a''"]);
+}
+
+#[test]
+fn quote_whitespace_newlines_and_metacharacters() {
+    for s in [
+        "my compose.yml",
+        "line1\nline2",
+        "tab\there",
+        "$HOME",
+        "`id`",
+        "a;b",
+        "a|b",
+        "*.yml",
+        "~/x",
+        "back\\slash",
+        "\"dq\"",
+        "!bang",
+    ] {
+        let q = quote(s);
+        assert!(q.starts_with('\''), "{s:?} should be quoted, got {q}");
+        assert_eq!(reparse(&q), [s]);
+    }
+}
+
+#[test]
+fn quote_unicode() {
+    assert_eq!(quote("café"), "'café'");
+    assert_eq!(reparse(&quote("données/📄.yml")), ["données/📄.yml"]);
+}
+
+#[test]
+fn command_line_renders_argv_and_display() {
+    let cmd = CommandLine::new("docker").args([
+        "compose",
+        "-f",
+        "/srv/my stack/compose.yml",
+        "restart",
+        "acip",
+    ]);
+    assert_eq!(
+        cmd.argv(),
+        [
+            "docker",
+            "compose",
+            "-f",
+            "/srv/my stack/compose.yml",
+            "restart",
+            "acip"
+        ]
+    );
+    assert_eq!(
+        cmd.display(),
+        "docker compose -f '/srv/my stack/compose.yml' restart acip"
+    );
+}
+
+#[test]
+fn wrapped_prefixes_the_program() {
+    let cmd = CommandLine::new("systemctl")
+        .args(["restart", "acip-sidecar"])
+        .wrapped("sudo");
+    assert_eq!(cmd.program(), "sudo");
+    assert_eq!(cmd.argv(), ["sudo", "systemctl", "restart", "acip-sidecar"]);
+}
+
+#[test]
+fn program_that_looks_like_an_assignment_is_quoted() {
+    let cmd = CommandLine::new("FOO=bar").arg("x");
+    assert_eq!(cmd.display(), "'FOO=bar' x");
+    assert_eq!(reparse(&cmd.display()), cmd.argv());
+}
+
+#[cfg(unix)]
+#[test]
+fn to_command_runs_argv_without_a_shell() {
+    let out = CommandLine::new("printf")
+        .args(["%s|", "$HOME", "a b", ""])
+        .to_command()
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "$HOME|a b||");
+}
+
+proptest! {
+    #[test]
+    fn display_round_trips_through_a_shell_parser(
+        program in "\\PC{1,12}",
+        args in proptest::collection::vec("(\\PC|\\s)*", 0..6),
+    ) {
+        let cmd = CommandLine::new(program).args(args);
+        prop_assert_eq!(reparse(&cmd.display()), cmd.argv());
+    }
+
+    #[test]
+    fn quote_round_trips_any_string(s in any::<String>()) {
+        prop_assert_eq!(reparse(&quote(&s)), vec![s]);
+    }
+}
@@ -0,0 +1,286 @@
+use acip_sidecar::model_policy::{LowConfidenceHandling, PolicyConfig};
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::reputation::SystemClock;
+use acip_sidecar::sentry::{
+    Action, ConfidenceBucket, ConfidenceMethod, DecisionEngine, Generation, ModelClient, ModelTier,
+    SentryVerdict,
+};
+use acip_sidecar::stats::{DecisionStats, GroupBy, StatsRow, StatsSettings};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+/// Answers `generate_reporting` with `first` and `generate_sample` with `sample`.
+struct ScriptedClient {
+    first: String,
+    sample: String,
+    native: Option<f64>,
+    samples: Arc<Mutex<u32>>,
+}
+
+impl ScriptedClient {
+    fn new(first: String, sample: String) -> Self {
+        Self {
+            first,
+            sample,
+            native: None,
+            samples: Arc::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl ModelClient for ScriptedClient {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        Ok(self.first.clone())
+    }
+
+    async fn generate_reporting(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<Generation> {
+        Ok(Generation {
+            text: self.first.clone(),
+            model_version: None,
+            confidence: self.native,
+            prompt_tokens: None,
+        })
+    }
+
+    async fn generate_sample(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<Generation> {
+        *self.samples.lock().unwrap() += 1;
+        Ok(Generation {
+            text: self.sample.clone(),
+            model_version: None,
+            confidence: None,
+            prompt_tokens: None,
+        })
+    }
+}
+
+fn verdict_json(action: &str, tools_allowed: bool, risk_level: &str) -> String {
+    json!({
+        "tools_allowed": tools_allowed,
+        "risk_level": risk_level,
+        "action": action,
+        "fenced_content": "```external\nX\n```",
+        "reasons": [],
+        "detected_patterns": []
+    })
+    .to_string()
+}
+
+fn allow() -> String {
+    verdict_json("allow", true, "low")
+}
+
+fn block() -> String {
+    verdict_json("block", false, "high")
+}
+
+fn policy(consistency_check: bool, on_low: LowConfidenceHandling) -> PolicyConfig {
+    let mut p = PolicyConfig::default();
+    p.l1.consistency_check = consistency_check;
+    p.on_low_confidence = on_low;
+    p
+}
+
+async fn decide(l1: ScriptedClient, l2: ScriptedClient, policy: &PolicyConfig) -> SentryVerdict {
+    DecisionEngine::new(Box::new(l1), Box::new(l2))
+        .decide_tiered(
+            "default",
+            policy,
+            &json!({}),
+            "```external\nX\n```",
+            &HeaderMap::new(),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn agreeing_sample_gives_high_consistency_confidence() {
+    let l1 = ScriptedClient::new(allow(), allow());
+    let samples = l1.samples.clone();
+    let v = decide(
+        l1,
+        ScriptedClient::new(block(), block()),
+        &policy(true, LowConfidenceHandling::EscalateL2),
+    )
+    .await;
+
+    assert_eq!(*samples.lock().unwrap(), 1);
+    assert_eq!(v.tier, ModelTier::L1);
+    let c = v.decision.confidence.unwrap();
+    assert_eq!(c.value, 1.0);
+    assert_eq!(c.bucket, ConfidenceBucket::High);
+    assert_eq!(c.method, ConfidenceMethod::Consistency);
+    assert!(v.second_opinion.is_none());
+
+    // The second sample is attributed to the check, not counted as a verdict.
+    assert_eq!(v.attempts.len(), 2);
+    assert!(!v.attempts[0].consistency_check);
+    assert!(v.attempts[1].consistency_check);
+    // Both requests are timed, in order.
+    assert_eq!(v.calls.len(), 2);
+    assert!(v.calls.iter().all(|c| c.ok && c.tier == ModelTier::L1));
+    assert!(v.calls[1].consistency_check);
+}
+
+#[tokio::test]
+async fn risk_level_disagreement_is_medium() {
+    let v = decide(
+        ScriptedClient::new(allow(), verdict_json("allow", true, "medium")),
+        ScriptedClient::new(block(), block()),
+        &policy(true, LowConfidenceHandling::EscalateL2),
+    )
+    .await;
+    let c = v.decision.confidence.unwrap();
+    assert_eq!((c.value, c.bucket), (0.75, ConfidenceBucket::Medium));
+    assert_eq!(v.tier, ModelTier::L1);
+}
+
+#[tokio::test]
+async fn no_check_and_no_provider_signal_means_no_confidence() {
+    let l1 = ScriptedClient::new(allow(), block());
+    let samples = l1.samples.clone();
+    let v = decide(
+        l1,
+        ScriptedClient::new(block(), block()),
+        &policy(false, LowConfidenceHandling::EscalateL2),
+    )
+    .await;
+    assert_eq!(*samples.lock().unwrap(), 0);
+    assert!(v.decision.confidence.is_none());
+    assert_eq!(v.decision.action, Action::Allow);
+}
+
+#[tokio::test]
+async fn provider_confidence_is_used_without_check() {
+    let mut l1 = ScriptedClient::new(allow(), allow());
+    l1.native = Some(0.3);
+    let v = decide(
+        l1,
+        ScriptedClient::new(block(), block()),
+        &policy(false, LowConfidenceHandling::NeedsReview),
+    )
+    .await;
+    let c = v.decision.confidence.unwrap();
+    assert_eq!(c.method, ConfidenceMethod::Provider);
+    assert_eq!(c.bucket, ConfidenceBucket::Low);
+    assert_eq!(v.decision.action, Action::NeedsReview);
+    assert!(!v.decision.tools_allowed);
+}
+
+#[tokio::test]
+async fn low_confidence_is_accepted_by_default_with_reason() {
+    let v = decide(
+        ScriptedClient::new(allow(), block()),
+        ScriptedClient::new(block(), block()),
+        &policy(true, LowConfidenceHandling::default()),
+    )
+    .await;
+    assert_eq!(v.tier, ModelTier::L1);
+    assert_eq!(v.decision.action, Action::Allow);
+    assert_eq!(v.decision.confidence.unwrap().value, 0.25);
+    assert!(v
+        .decision
+        .reasons
+        .iter()
+        .any(|r| r.starts_with("low_confidence:") && r.ends_with("accepted")));
+}
+
+#[tokio::test]
+async fn low_confidence_escalates_to_l2_and_records_overturn() {
+    let v = decide(
+        ScriptedClient::new(allow(), block()),
+        ScriptedClient::new(block(), block()),
+        &policy(true, LowConfidenceHandling::EscalateL2),
+    )
+    .await;
+    assert_eq!(v.tier, ModelTier::L2);
+    assert_eq!(v.decision.action, Action::Block);
+    assert!(v
+        .decision
+        .reasons
+        .iter()
+        .any(|r| r.contains("escalated to L2")));
+    let second = v.second_opinion.clone().unwrap();
+    assert_eq!(second.l1_action, Action::Allow);
+    assert_eq!(second.l1_confidence.bucket, ConfidenceBucket::Low);
+    assert!(second.overturned);
+
+    let stats = DecisionStats::in_memory(StatsSettings::default(), Arc::new(SystemClock));
+    stats.record_parse_attempts(&v.attempts);
+    stats.record_second_opinion(&second);
+    let rows = stats.report(1, GroupBy::Confidence).rows;
+    let low = rows
+        .iter()
+        .find_map(|r| match r {
+            StatsRow::Confidence(c) if c.confidence == "low" => Some(c.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!((low.second_opinions, low.second_opinion_overturns), (1, 1));
+    assert_eq!(low.overturn_rate, 1.0);
+
+    let models = stats.report(1, GroupBy::Model).rows;
+    let l1 = models
+        .iter()
+        .find_map(|r| match r {
+            StatsRow::Model(m) if m.model == "Gemini/gemini-2.0-flash" => Some(m.clone()),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!((l1.parses, l1.consistency_checks), (2, 1));
+}
+
+#[tokio::test]
+async fn unparseable_sample_counts_as_disagreement() {
+    let v = decide(
+        ScriptedClient::new(allow(), "not json".to_string()),
+        ScriptedClient::new(allow(), allow()),
+        &policy(true, LowConfidenceHandling::EscalateL2),
+    )
+    .await;
+    assert_eq!(v.decision.action, Action::Allow);
+    assert_eq!(v.tier, ModelTier::L2);
+    assert!(!v.second_opinion.unwrap().overturned);
+    assert!(v.attempts.iter().any(|a| a.consistency_check && !a.valid));
+}
+
+#[test]
+fn consistency_settings_are_inherited() {
+    let store = PolicyStore::from_declared(
+        DeclaredPolicies::parse(
+            r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash", "consistency_check": true},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"},
+      "on_low_confidence": "escalate_l2"
+    },
+    "child": {"extends": "default", "l1": {"model": "gemini-2.5-flash"}}
+  }
+}"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let child = store.require("child").unwrap();
+    assert!(child.l1.consistency_check);
+    assert!(!child.l2.consistency_check);
+    assert_eq!(child.on_low_confidence, LowConfidenceHandling::EscalateL2);
+}
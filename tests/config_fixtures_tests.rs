@@ -0,0 +1,131 @@
+//! Every config and policies file shipped in the repo is loaded through the real loaders,
+//! re-serialized, re-loaded and compared, so example drift and serde default/skip
+//! asymmetries fail here instead of in a release.
+//!
+//! - `config.example.toml` and `tests/fixtures/config/sections/*.toml` (one optional section
+//!   each) must round-trip alone and all together.
+//! - `tests/fixtures/config/invalid/*.toml` must fail to load; the first line,
+//!   `# error: <key path>`, names the key the error has to mention.
+//! - `tests/fixtures/policies/*.json` must round-trip in declared and resolved form.
+
+use acip_sidecar::config::Config;
+use acip_sidecar::policy_store::{DeclaredPolicies, PoliciesFile};
+use std::path::{Path, PathBuf};
+
+fn repo_path(rel: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(rel)
+}
+
+/// Files with extension `ext` directly in `dir`, sorted; fails on an empty directory.
+fn fixtures(dir: &str, ext: &str) -> Vec<PathBuf> {
+    let mut out: Vec<PathBuf> = std::fs::read_dir(repo_path(dir))
+        .unwrap_or_else(|e| panic!("{dir}: {e}"))
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|x| x == ext))
+        .collect();
+    out.sort();
+    assert!(!out.is_empty(), "no *.{ext} fixtures in {dir}");
+    out
+}
+
+fn load_config(path: &Path) -> Config {
+    Config::load(path).unwrap_or_else(|e| panic!("{}: {e:#}", path.display()))
+}
+
+/// Load, write back out and load again: both loads must agree.
+fn assert_config_round_trips(path: &Path) -> Config {
+    let cfg = load_config(path);
+    let raw = toml::to_string(&cfg).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+    let dir = tempfile::tempdir().unwrap();
+    let copy = dir.path().join("config.toml");
+    std::fs::write(&copy, &raw).unwrap();
+    let again = Config::load(&copy)
+        .unwrap_or_else(|e| panic!("{} re-serialized: {e:#}\n{raw}", path.display()));
+    assert_eq!(cfg, again, "{} re-serialized as\n{raw}", path.display());
+    cfg
+}
+
+#[test]
+fn example_config_round_trips() {
+    let cfg = assert_config_round_trips(&repo_path("config.example.toml"));
+    // Sanity: the example really is the documented one, not an empty file.
+    assert_eq!(cfg.server.unwrap().port, Some(18795));
+    assert!(cfg.reputation.is_some());
+}
+
+#[test]
+fn each_section_fixture_round_trips_alone() {
+    for path in fixtures("tests/fixtures/config/sections", "toml") {
+        let cfg = assert_config_round_trips(&path);
+        let section = path.file_stem().unwrap().to_str().unwrap();
+        let set = serde_json::to_value(&cfg).unwrap();
+        let present: Vec<&String> = set
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(_, v)| !v.is_null() && *v != &serde_json::json!([]))
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(present, [section], "{} sets other sections", path.display());
+    }
+}
+
+#[test]
+fn all_section_fixtures_round_trip_together() {
+    let combined: String = fixtures("tests/fixtures/config/sections", "toml")
+        .iter()
+        .map(|p| std::fs::read_to_string(p).unwrap() + "\n")
+        .collect();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("combined.toml");
+    std::fs::write(&path, combined).unwrap();
+    let cfg = assert_config_round_trips(&path);
+    assert!(cfg.service.is_some() && cfg.hashing.is_some());
+    assert_eq!(cfg.patterns.len(), 1);
+    assert_eq!(cfg.feeds.len(), 2);
+}
+
+#[test]
+fn invalid_fixtures_name_the_offending_key() {
+    for path in fixtures("tests/fixtures/config/invalid", "toml") {
+        let raw = std::fs::read_to_string(&path).unwrap();
+        let key = raw
+            .lines()
+            .next()
+            .and_then(|l| l.strip_prefix("# error: "))
+            .unwrap_or_else(|| panic!("{}: first line must be `# error: <key>`", path.display()));
+        let err = match Config::load(&path) {
+            Ok(_) => panic!("{} loaded", path.display()),
+            Err(e) => format!("{e:#}"),
+        };
+        assert!(
+            err.contains(key),
+            "{}: error does not name {key}:\n{err}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn policies_fixtures_round_trip() {
+    for path in fixtures("tests/fixtures/policies", "json") {
+        let name = path.display();
+        let raw = std::fs::read_to_string(&path).unwrap();
+        let declared = DeclaredPolicies::parse(&raw).unwrap_or_else(|e| panic!("{name}: {e:#}"));
+        let again = DeclaredPolicies::parse(&serde_json::to_string(&declared).unwrap()).unwrap();
+        assert_eq!(declared, again, "{name}: declared form");
+
+        let resolved = PoliciesFile::load(&path).unwrap_or_else(|e| panic!("{name}: {e:#}"));
+        let json = serde_json::to_string_pretty(&resolved).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let copy = dir.path().join("policies.json");
+        std::fs::write(&copy, &json).unwrap();
+        let again = PoliciesFile::load(&copy)
+            .unwrap_or_else(|e| panic!("{name} re-serialized: {e:#}\n{json}"));
+        assert_eq!(resolved, again, "{name} re-serialized as\n{json}");
+        assert_eq!(
+            resolved.policies.keys().collect::<Vec<_>>(),
+            declared.policies.keys().collect::<Vec<_>>()
+        );
+    }
+}
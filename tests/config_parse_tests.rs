@@ -9,7 +9,6 @@ fn config_load_parses_toml() {
     let mut f = std::fs::File::create(&path).unwrap();
     write!(
         f,
-        "{}",
         r#"
 [server]
 host = "127.0.0.1"
@@ -0,0 +1,338 @@
+use acip_sidecar::capabilities::{Capabilities, Rejection};
+use acip_sidecar::content_types::{ContentTypeRules, Screening, UnknownBinaryHandling};
+use acip_sidecar::model_policy::PolicyConfig;
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::token_auth::Actor;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, policy_store, state};
+use axum::{body::Body, http::StatusCode, Router};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn app_state(rules: ContentTypeRules) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let mut policies = std::collections::BTreeMap::new();
+    policies.insert("default".to_string(), PolicyConfig::default());
+    policies.insert(
+        "text-only".to_string(),
+        PolicyConfig {
+            content_types: vec!["text/plain".to_string()],
+            ..PolicyConfig::default()
+        },
+    );
+
+    AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .with_content_types(Arc::new(rules))
+        .build()
+        .unwrap()
+}
+
+fn sidecar(rules: ContentTypeRules) -> Router {
+    let ingest = Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(app_state(rules), None, ingest)
+}
+
+async fn ingest(
+    app: &Router,
+    uri: &str,
+    policy: &str,
+    content_type: &str,
+    bytes: &[u8],
+) -> (StatusCode, Value) {
+    let body = json!({
+        "source_id": "upload-1",
+        "source_type": "file",
+        "content_type": content_type,
+        "bytes_b64": B64.encode(bytes),
+    });
+    let resp = app
+        .clone()
+        .oneshot(
+            axum::http::Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("x-acip-policy", policy)
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn elf() -> Vec<u8> {
+    let mut b = b"\x7fELF\x02\x01\x01\x00".to_vec();
+    b.resize(512, 0);
+    b
+}
+
+fn pe() -> Vec<u8> {
+    let mut b = b"MZ".to_vec();
+    b.resize(0x40, 0);
+    b[0x3c] = 0x40;
+    b.extend_from_slice(b"PE\0\0");
+    b.resize(512, 0);
+    b
+}
+
+/// No magic number, and most bytes are control characters or invalid UTF-8.
+fn unknown_binary() -> Vec<u8> {
+    (0..4096u32).map(|i| (i * 37 % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn executables_declared_as_text_are_rejected_by_sniffing() {
+    let app = sidecar(ContentTypeRules::default());
+
+    for (bytes, sniffed) in [
+        (elf(), "application/x-elf"),
+        (pe(), "application/vnd.microsoft.portable-executable"),
+    ] {
+        let (status, v) = ingest(
+            &app,
+            "/v1/acip/ingest_source",
+            "default",
+            "text/plain; charset=utf-8",
+            &bytes,
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{v}");
+        assert_eq!(v["error"], "unsupported_content_type");
+        assert_eq!(v["extra"]["layer"], "reject");
+        assert_eq!(v["extra"]["reason"], "executable");
+        assert_eq!(v["extra"]["declared"], "text/plain");
+        assert_eq!(v["extra"]["sniffed"], sniffed);
+    }
+
+    // Async submissions are refused up front, not when a worker picks them up.
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source?mode=async",
+        "default",
+        "text/plain",
+        &elf(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{v}");
+    assert_eq!(v["extra"]["layer"], "reject");
+
+    // Declared executable types are refused whatever the bytes are.
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "application/x-msdownload",
+        b"harmless",
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(v["extra"]["reason"], "rejected_type");
+}
+
+#[tokio::test]
+async fn global_layer_refuses_unsupported_types_and_formats() {
+    let app = sidecar(ContentTypeRules::default());
+    let zip = b"PK\x03\x04\x14\x00\x00\x00payload".to_vec();
+
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "application/zip",
+        &zip,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(v["extra"]["layer"], "global");
+    assert_eq!(v["extra"]["reason"], "not_supported");
+    assert!(v["extra"]["accepted"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("text/plain")));
+
+    // An archive declared as text is recognised and refused at the same layer.
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "text/plain",
+        &zip,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(v["extra"]["layer"], "global");
+    assert_eq!(v["extra"]["reason"], "unsupported_format");
+    assert_eq!(v["extra"]["sniffed"], "application/zip");
+
+    // Encrypted PDFs go on to the extractor, which tries the caller's password.
+    let pdf = b"%PDF-1.7\ntrailer << /Encrypt 5 0 R >>\n%%EOF".to_vec();
+    let screened = ContentTypeRules::default().screen("default", None, "application/pdf", &pdf);
+    assert_eq!(screened, Ok(Screening::Accepted));
+}
+
+#[tokio::test]
+async fn policies_narrow_the_global_list() {
+    let app = sidecar(ContentTypeRules::default());
+    let html = b"<p>hello</p>";
+
+    let (status, v) = ingest(&app, "/v1/acip/ingest_source", "default", "text/html", html).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "text-only",
+        "text/html",
+        html,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(v["extra"]["layer"], "policy");
+    assert_eq!(v["extra"]["reason"], "not_allowed_by_policy");
+    assert_eq!(v["extra"]["policy"], "text-only");
+    assert_eq!(v["extra"]["accepted"], json!(["text/plain"]));
+
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "text-only",
+        "text/plain",
+        b"hello",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+
+    // Capabilities report the effective list per policy, and clients check against it.
+    let caps = Capabilities::build(&app_state(ContentTypeRules::default()), &Actor::anonymous());
+    assert_eq!(
+        caps.policy("text-only").unwrap().content_types,
+        vec!["text/plain"]
+    );
+    assert!(caps
+        .policy("default")
+        .unwrap()
+        .content_types
+        .contains(&"text/html".to_string()));
+    assert_eq!(
+        caps.check_content_type("text-only", "text/html"),
+        Err(Rejection::UnsupportedContentType {
+            content_type: "text/html".to_string(),
+            policy: "text-only".to_string(),
+        })
+    );
+    assert!(caps.check_content_type("default", "text/html").is_ok());
+}
+
+#[tokio::test]
+async fn unknown_binaries_follow_on_unknown_binary() {
+    let app = sidecar(ContentTypeRules::default());
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "text/plain",
+        &unknown_binary(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{v}");
+    assert_eq!(v["extra"]["layer"], "global");
+    assert_eq!(v["extra"]["reason"], "unknown_binary");
+    assert_eq!(v["extra"]["sniffed"], "application/octet-stream");
+    assert_eq!(v["extra"]["declared"], "text/plain");
+
+    let app = sidecar(ContentTypeRules {
+        on_unknown_binary: UnknownBinaryHandling::NeedsReview,
+        ..ContentTypeRules::default()
+    });
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "text/plain",
+        &unknown_binary(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["action"], "needs_review");
+    assert_eq!(v["tools_allowed"], false);
+    assert_eq!(v["model_length_chars"], 0);
+    assert!(v["reasons"][0]
+        .as_str()
+        .unwrap()
+        .starts_with("unknown_binary:"));
+
+    // Ordinary text with a few control characters is not an unknown binary.
+    let (status, v) = ingest(
+        &app,
+        "/v1/acip/ingest_source",
+        "default",
+        "text/plain",
+        "plain text\u{7}with a bell and ünïcode".as_bytes(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["action"], "allow");
+}
+
+#[test]
+fn policy_content_types_are_inherited_whole_and_validated() {
+    let store = PolicyStore::from_declared(
+        DeclaredPolicies::parse(
+            r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash"},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"},
+      "content_types": ["text/plain", "application/pdf"]
+    },
+    "child": {"extends": "default"},
+    "html": {"extends": "default", "content_types": ["text/html"]}
+  }
+}"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        store.require("child").unwrap().content_types,
+        vec!["text/plain", "application/pdf"]
+    );
+    assert_eq!(
+        store.require("html").unwrap().content_types,
+        vec!["text/html"]
+    );
+
+    let err = PolicyStore::from_declared(
+        DeclaredPolicies::parse(
+            r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash"},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"},
+      "content_types": ["application/zip"]
+    }
+  }
+}"#,
+        )
+        .unwrap(),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("application/zip"), "{err:#}");
+}
@@ -0,0 +1,266 @@
+use acip_sidecar::acip_headers::{DuplicateHeaders, HeaderRules};
+use acip_sidecar::config::Config;
+use acip_sidecar::deprecations::{self, Deprecation, Form, Registry};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, state};
+use assert_cmd::cargo::cargo_bin_cmd;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn renamed(form: Form, old: &'static str, replacement: &'static str) -> Deprecation {
+    Deprecation {
+        form,
+        old,
+        replacement,
+        sunset: "0.4.0",
+        translate: None,
+    }
+}
+
+fn test_state(rules: HeaderRules) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    AppStateBuilder::for_tests()
+        .with_header_rules(Arc::new(rules))
+        .build()
+        .unwrap()
+}
+
+/// A router where `X-ACIP-Tools` is a deprecated name of `X-ACIP-Allow-Tools`, judged as
+/// release `version`.
+fn router(version: &str) -> Router {
+    let rules = HeaderRules {
+        duplicates: DuplicateHeaders::UseStrictest,
+        policy_ranking: vec![],
+        deprecations: Registry::builtin()
+            .with_entry(renamed(Form::Header, "x-acip-tools", "x-acip-allow-tools"))
+            .with_version(version),
+    };
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(test_state(rules), None, extra)
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Vec<String>, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let marked = resp
+        .headers()
+        .get_all(deprecations::RESPONSE_HEADER)
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let v = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, marked, v)
+}
+
+fn ingest(headers: &[(&str, &str)]) -> Request<Body> {
+    let mut b = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json");
+    for (k, v) in headers {
+        b = b.header(*k, *v);
+    }
+    let body = json!({
+        "source_id": "s1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Meeting moved to Thursday at 10am.",
+    });
+    b.body(Body::from(body.to_string())).unwrap()
+}
+
+#[tokio::test]
+async fn deprecated_header_reaches_the_handler_under_its_new_name() {
+    let app = router("0.1.0");
+    let (status, marked, v) = send(
+        &app,
+        ingest(&[("X-ACIP-Tools", "true"), ("X-ACIP-Tools", "false")]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(marked, vec!["x-acip-tools=x-acip-allow-tools"]);
+    // Both values were moved, so the duplicate rules see them under the new name.
+    let reasons = v["reasons"].to_string();
+    assert!(
+        reasons.contains("header_conflict: x-acip-allow-tools sent 2 values"),
+        "{reasons}"
+    );
+
+    let req = Request::builder()
+        .uri("/v1/acip/status")
+        .body(Body::empty())
+        .unwrap();
+    let (status, marked, v) = send(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(marked.is_empty());
+    let in_use = v["deprecations_in_use"].as_array().unwrap();
+    assert!(
+        in_use.contains(&json!({
+            "form": "header",
+            "old": "x-acip-tools",
+            "replacement": "x-acip-allow-tools",
+            "sunset": "0.4.0",
+        })),
+        "{in_use:?}"
+    );
+}
+
+#[tokio::test]
+async fn replacement_header_wins_over_the_deprecated_one() {
+    let app = router("0.1.0");
+    let (status, marked, v) = send(
+        &app,
+        ingest(&[("X-ACIP-Tools", "true"), ("X-ACIP-Allow-Tools", "false")]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(marked, vec!["x-acip-tools=x-acip-allow-tools"]);
+    assert_eq!(v["tools_allowed"], false);
+    assert!(!v["reasons"].to_string().contains("header_conflict"));
+}
+
+#[tokio::test]
+async fn deprecated_header_is_refused_from_its_sunset_release() {
+    let app = router("0.4.0");
+    let (status, _, v) = send(&app, ingest(&[("X-ACIP-Tools", "true")])).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{v}");
+    assert_eq!(v["error"], "removed_header");
+    assert_eq!(
+        v["extra"],
+        json!({"header": "x-acip-tools", "replacement": "x-acip-allow-tools", "removed_in": "0.4.0"})
+    );
+}
+
+#[test]
+fn deprecated_config_key_is_moved_onto_its_replacement() {
+    let registry = Registry::builtin().with_entry(renamed(
+        Form::ConfigKey,
+        "server.listen_port",
+        "server.port",
+    ));
+    let (cfg, uses) = Config::parse_with("[server]\nlisten_port = 19000\n", &registry).unwrap();
+    assert_eq!(cfg.server.unwrap().port, Some(19000));
+    assert_eq!(uses.len(), 1);
+    assert_eq!(
+        uses[0].message(),
+        "server.listen_port is deprecated and stops working in 0.4.0; set server.port instead"
+    );
+
+    let err = Config::parse_with("[server]\nlisten_port = 1\nport = 2\n", &registry).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "server.listen_port and server.port are both set; keep only server.port"
+    );
+
+    let err = Config::parse_with(
+        "[server]\nlisten_port = 1\n",
+        &registry.clone().with_version("0.4.0"),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "server.listen_port was removed in 0.4.0; set server.port instead"
+    );
+
+    // Without a deprecated key nothing is reported.
+    let (_, uses) = Config::parse_with("[server]\nport = 2\n", &registry).unwrap();
+    assert!(uses.is_empty());
+}
+
+#[test]
+fn deprecated_env_var_is_written_onto_its_config_key() {
+    let env = |name: &str| (name == "ACIP_REP_MED").then(|| "25".to_string());
+    let cfg = Config::parse("[reputation]\nmedium_score = 10\n").unwrap();
+    let (cfg, uses) = Registry::builtin().apply_env(Some(cfg), env).unwrap();
+    assert_eq!(cfg.unwrap().reputation.unwrap().medium_score, Some(25));
+    assert_eq!(uses.len(), 1);
+    assert_eq!(uses[0].replacement, "reputation.medium_score");
+
+    // Checked but not written without a config file.
+    let (cfg, uses) = Registry::builtin().apply_env(None, env).unwrap();
+    assert!(cfg.is_none());
+    assert_eq!(uses.len(), 1);
+
+    let bad = |name: &str| (name == "ACIP_REP_HIGH").then(|| "lots".to_string());
+    let err = Registry::builtin()
+        .apply_env(Some(Config::parse("").unwrap()), bad)
+        .unwrap_err();
+    assert_eq!(err.to_string(), "ACIP_REP_HIGH: \"lots\" is not an integer");
+
+    let err = Registry::builtin()
+        .with_version("0.3.0")
+        .apply_env(None, env)
+        .unwrap_err();
+    assert!(err
+        .to_string()
+        .contains("ACIP_REP_MED was removed in 0.3.0"));
+}
+
+#[test]
+fn each_deprecation_is_recorded_once() {
+    let used = Registry::builtin()
+        .check(&renamed(Form::Env, "ACIP_TEST_ONCE", "test.once"))
+        .unwrap();
+    assert!(deprecations::record(&used));
+    assert!(!deprecations::record(&used));
+    let in_use = deprecations::in_use();
+    assert_eq!(
+        in_use.iter().filter(|u| u.old == "ACIP_TEST_ONCE").count(),
+        1
+    );
+}
+
+#[test]
+fn doctor_reports_deprecated_env_vars_and_an_unreachable_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(&path, "[reputation]\nmedium_score = 10\n").unwrap();
+    let url = format!(
+        "http://{}",
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+    );
+
+    let out = cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "--output", "json", "doctor", "--path"])
+        .arg(&path)
+        .env("ACIP_REP_MED", "25")
+        .assert()
+        .code(0);
+    let v: Value = serde_json::from_slice(&out.get_output().stdout).unwrap();
+    let findings = v["findings"].as_array().unwrap();
+    assert_eq!(findings.len(), 2, "{v}");
+    assert_eq!(
+        findings[0],
+        json!({
+            "check": "env",
+            "level": "warn",
+            "message": "ACIP_REP_MED is deprecated and stops working in 0.3.0; set reputation.medium_score instead",
+        })
+    );
+    assert_eq!(findings[1]["check"], "sidecar");
+    assert_eq!(findings[1]["level"], "warn");
+
+    std::fs::write(&path, "[reputation]\nmedium_score = \"high\"\n").unwrap();
+    cargo_bin_cmd!("acipctl")
+        .args(["--url", &url, "doctor", "--path"])
+        .arg(&path)
+        .env_remove("ACIP_REP_MED")
+        .assert()
+        .code(1)
+        .stdout(predicates::str::contains("error config: "));
+}
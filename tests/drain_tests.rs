@@ -0,0 +1,312 @@
+mod util;
+
+use acip_sidecar::drain::{DrainControl, DRAIN_RETRY_AFTER_SECS};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest};
+use assert_cmd::cargo::cargo_bin_cmd;
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+    time::{Duration, Instant},
+};
+use tower::ServiceExt;
+use util::serve;
+
+const TOKEN: &str = "t0ken";
+
+static INIT: Once = Once::new();
+
+fn init_env() {
+    INIT.call_once(|| {
+        std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    });
+}
+
+/// Router with the real ingest route plus `/v1/acip/slow`, which finishes once `release` is set
+/// (or after `delay`, whichever comes first).
+fn router(
+    drain: Arc<DrainControl>,
+    token: Option<&str>,
+    release: Arc<AtomicBool>,
+    delay: Duration,
+) -> Router {
+    let st = AppStateBuilder::for_tests()
+        .with_drain(drain)
+        .build()
+        .unwrap();
+
+    let extra = Router::new()
+        .route("/v1/acip/ingest_source", post(ingest::ingest_source))
+        .route(
+            "/v1/acip/slow",
+            post(move || {
+                let release = release.clone();
+                async move {
+                    let start = Instant::now();
+                    while !release.load(Ordering::SeqCst) && start.elapsed() < delay {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    "done"
+                }
+            }),
+        );
+    app::build_router(st, token.map(str::to_string), extra)
+}
+
+fn request(method: &str, uri: &str, body: Option<Value>) -> Request<Body> {
+    let mut b = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-ACIP-Token", TOKEN);
+    if body.is_some() {
+        b = b.header("content-type", "application/json");
+    }
+    b.body(body.map(|v| Body::from(v.to_string())).unwrap_or_default())
+        .unwrap()
+}
+
+fn ingest_request() -> Request<Body> {
+    request(
+        "POST",
+        "/v1/acip/ingest_source",
+        Some(json!({
+            "source_id": "s1",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "text": "hello",
+        })),
+    )
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, axum::http::HeaderMap, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let headers = resp.headers().clone();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let v = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, headers, v)
+}
+
+#[tokio::test]
+async fn drain_rejects_new_ingest_and_resume_restores_it() {
+    init_env();
+    let drain = Arc::new(DrainControl::default());
+    let app = router(
+        drain.clone(),
+        Some(TOKEN),
+        Arc::new(AtomicBool::new(false)),
+        Duration::ZERO,
+    );
+
+    let (status, _, _) = send(&app, ingest_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, v) = send(
+        &app,
+        request(
+            "POST",
+            "/v1/acip/admin/drain",
+            Some(json!({"initiated_by": "alice"})),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["draining"], true);
+    assert_eq!(v["changed"], true);
+    assert_eq!(v["initiated_by"], "alice");
+
+    // Idempotent: the original initiator is kept.
+    let (_, _, v) = send(
+        &app,
+        request(
+            "POST",
+            "/v1/acip/admin/drain",
+            Some(json!({"initiated_by": "bob"})),
+        ),
+    )
+    .await;
+    assert_eq!(v["changed"], false);
+    assert_eq!(v["initiated_by"], "alice");
+
+    let (status, headers, v) = send(&app, ingest_request()).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(v["error"], "draining");
+    assert_eq!(v["extra"]["retry_after"], DRAIN_RETRY_AFTER_SECS);
+    assert_eq!(
+        headers[header::RETRY_AFTER],
+        DRAIN_RETRY_AFTER_SECS.to_string()
+    );
+
+    // Read-only routes keep working; readiness flips, liveness does not.
+    let (status, _, v) = send(&app, request("GET", "/v1/acip/status", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["drain"]["draining"], true);
+    assert_eq!(v["drain"]["initiated_by"], "alice");
+    assert!(v["drain"]["since_unix"].as_u64().unwrap() > 0);
+    let (status, _, _) = send(&app, request("GET", "/v1/acip/policies", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, v) = send(&app, request("GET", "/health/ready", None)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(v["status"], "draining");
+    let (status, _, _) = send(&app, request("GET", "/health/live", None)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _, v) = send(&app, request("POST", "/v1/acip/admin/resume", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["draining"], false);
+    assert_eq!(v["changed"], true);
+    let (_, _, v) = send(&app, request("POST", "/v1/acip/admin/resume", None)).await;
+    assert_eq!(v["changed"], false);
+
+    let (status, _, _) = send(&app, ingest_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _, _) = send(&app, request("GET", "/health/ready", None)).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn in_flight_work_completes_during_drain() {
+    init_env();
+    let drain = Arc::new(DrainControl::default());
+    let release = Arc::new(AtomicBool::new(false));
+    let app = router(
+        drain.clone(),
+        Some(TOKEN),
+        release.clone(),
+        Duration::from_secs(30),
+    );
+
+    let pending = tokio::spawn({
+        let app = app.clone();
+        async move { send(&app, request("POST", "/v1/acip/slow", None)).await }
+    });
+    while drain.in_flight() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    let (status, _, v) = send(&app, request("POST", "/v1/acip/admin/drain", None)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["in_flight"], 1);
+    assert_eq!(v["initiated_by"], "unknown");
+
+    let (status, _, _) = send(&app, request("POST", "/v1/acip/slow", None)).await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(drain.in_flight(), 1);
+
+    release.store(true, Ordering::SeqCst);
+    let (status, _, _) = pending.await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(drain.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn admin_routes_require_a_configured_token() {
+    init_env();
+    let app = router(
+        Arc::new(DrainControl::default()),
+        None,
+        Arc::new(AtomicBool::new(false)),
+        Duration::ZERO,
+    );
+    let (status, _, v) = send(&app, request("POST", "/v1/acip/admin/drain", None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(v["error"], "admin_disabled");
+
+    let app = router(
+        Arc::new(DrainControl::default()),
+        Some("other"),
+        Arc::new(AtomicBool::new(false)),
+        Duration::ZERO,
+    );
+    let (status, _, _) = send(&app, request("POST", "/v1/acip/admin/drain", None)).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+/// Start a slow request against `addr` and wait until the sidecar counts it as in flight.
+fn start_slow_request(addr: SocketAddr, drain: &DrainControl) -> std::thread::JoinHandle<u16> {
+    let handle = std::thread::spawn(move || {
+        reqwest::blocking::Client::new()
+            .post(format!("http://{addr}/v1/acip/slow"))
+            .header("X-ACIP-Token", TOKEN)
+            .send()
+            .unwrap()
+            .status()
+            .as_u16()
+    });
+    while drain.in_flight() == 0 {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    handle
+}
+
+#[test]
+fn acipctl_drain_waits_for_idle_then_resume() {
+    init_env();
+    let drain = Arc::new(DrainControl::default());
+    let addr = serve(router(
+        drain.clone(),
+        Some(TOKEN),
+        Arc::new(AtomicBool::new(false)),
+        Duration::from_millis(800),
+    ));
+    let slow = start_slow_request(addr, &drain);
+
+    cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}"), "--token", TOKEN])
+        .args(["drain", "--initiated-by", "ops", "--wait-for-idle"])
+        .args(["--timeout", "20s", "--interval", "100ms"])
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("\"initiated_by\": \"ops\""))
+        .stdout(predicates::str::contains(
+            "idle: no ingest requests in flight",
+        ));
+    assert_eq!(slow.join().unwrap(), 200);
+    assert_eq!(drain.in_flight(), 0);
+    assert!(drain.is_draining());
+
+    cargo_bin_cmd!("acipctl")
+        .args([
+            "--url",
+            &format!("http://{addr}"),
+            "--token",
+            TOKEN,
+            "resume",
+        ])
+        .assert()
+        .success();
+    assert!(!drain.is_draining());
+}
+
+#[test]
+fn acipctl_wait_for_idle_times_out() {
+    init_env();
+    let drain = Arc::new(DrainControl::default());
+    let addr = serve(router(
+        drain.clone(),
+        Some(TOKEN),
+        Arc::new(AtomicBool::new(false)),
+        Duration::from_secs(5),
+    ));
+    let _slow = start_slow_request(addr, &drain);
+
+    cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}"), "--token", TOKEN])
+        .args(["drain", "--wait-for-idle", "--timeout", "300ms"])
+        .args(["--interval", "100ms"])
+        .assert()
+        .code(1)
+        .stderr(predicates::str::contains("timed out"));
+}
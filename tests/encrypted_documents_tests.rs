@@ -0,0 +1,241 @@
+use acip_sidecar::model_policy::{EncryptedHandling, PolicyConfig};
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, policy_store, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::{fs, os::unix::fs::PermissionsExt};
+use tower::ServiceExt;
+
+const PASSWORD: &str = "correct horse battery";
+const WRONG: &str = "Tr0ub4dor&3";
+const PDF: &[u8] = b"%PDF-1.7\n1 0 obj << >> endobj\ntrailer << /Encrypt 5 0 R >>\n%%EOF";
+
+/// Stands in for qpdf + poppler: opens the document only with [`PASSWORD`].
+const FAKE_HELPER: &str = r#"#!/bin/sh
+header=$(head -n 1)
+cat >/dev/null
+case "$header" in
+  *'"password":"correct horse battery"'*)
+    out='{"ok":true,"kind":"pdf","text":"Quarterly figures, as discussed.","warnings":["pdf_encrypted","pdf_decrypted"],"stats":{"text_chars":32,"ocr_used":false,"ocr_chars":0},"outcome":"extracted"}' ;;
+  *'"password"'*)
+    out='{"ok":false,"kind":"pdf","text":"","warnings":["pdf_encrypted"],"stats":{"text_chars":0,"ocr_used":false,"ocr_chars":0},"outcome":"decryption_failed"}' ;;
+  *)
+    out='{"ok":false,"kind":"pdf","text":"","warnings":["pdf_encrypted"],"stats":{"text_chars":0,"ocr_used":false,"ocr_chars":0},"outcome":"encrypted_unreadable"}' ;;
+esac
+printf '%s' "$out" > "$ACIP_EXTRACTOR_OUT"
+"#;
+
+/// Fails, echoing its whole request line (password included) as diagnostics.
+const LEAKY_HELPER: &str = "#!/bin/sh\nhead -n 1 >&2\ncat >/dev/null\nexit 1\n";
+
+#[derive(Clone, Default)]
+struct LogBuf(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Everything logged by this test binary, from every thread.
+fn logs() -> &'static LogBuf {
+    static LOGS: OnceLock<LogBuf> = OnceLock::new();
+    LOGS.get_or_init(|| {
+        let logs = LogBuf::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_max_level(tracing::Level::TRACE)
+            .finish();
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+        logs
+    })
+}
+
+fn helper(dir: &tempfile::TempDir, name: &str, script: &str) -> String {
+    let path = dir.path().join(name);
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path.display().to_string()
+}
+
+fn test_state(model: Arc<ScriptedModel>) -> Arc<state::AppState> {
+    logs();
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    let mut policies = std::collections::BTreeMap::new();
+    policies.insert("default".to_string(), PolicyConfig::default());
+    policies.insert(
+        "review".to_string(),
+        PolicyConfig {
+            on_encrypted: EncryptedHandling::NeedsReview,
+            ..PolicyConfig::default()
+        },
+    );
+
+    AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .with_model(model)
+        .build()
+        .unwrap()
+}
+
+fn router(st: Arc<state::AppState>) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, None, extra)
+}
+
+/// Ingest [`PDF`] under `policy`; the response as text (not every answer is JSON).
+async fn ingest(app: &Router, policy: &str, password: Option<&str>) -> (StatusCode, String) {
+    let mut body = json!({
+        "source_id": "partner-mail-7",
+        "source_type": "pdf",
+        "content_type": "application/pdf",
+        "bytes_b64": B64.encode(PDF),
+    });
+    if let Some(p) = password {
+        body["document_password"] = json!(p);
+    }
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("X-ACIP-Policy", policy)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, String::from_utf8_lossy(&bytes).to_string())
+}
+
+fn parse(body: &str) -> Value {
+    serde_json::from_str(body).unwrap_or_else(|e| panic!("{e}: {body}"))
+}
+
+#[tokio::test]
+#[serial]
+async fn correct_password_extracts_and_scans_normally() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("ACIP_EXTRACTOR_BIN", helper(&dir, "fake", FAKE_HELPER));
+    let model = Arc::new(ScriptedModel::benign());
+    let app = router(test_state(model.clone()));
+
+    let (code, body) = ingest(&app, "default", Some(PASSWORD)).await;
+    assert_eq!(code, StatusCode::OK, "{body}");
+    let v = parse(&body);
+    assert!(model.calls() > 0, "the text was not sent to the models");
+    assert_eq!(v["model_length_chars"], 32, "{v}");
+    let steps = v["normalization_steps"].as_array().unwrap();
+    assert!(steps.contains(&json!("extract:pdf_encrypted")), "{v}");
+    assert!(steps.contains(&json!("extract:pdf_decrypted")), "{v}");
+    // Encryption alone is a mild signal, not a finding.
+    assert_eq!(v["threat"]["threat_score"], 1, "{v}");
+}
+
+#[tokio::test]
+#[serial]
+async fn wrong_and_missing_passwords_have_distinct_outcomes() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("ACIP_EXTRACTOR_BIN", helper(&dir, "fake", FAKE_HELPER));
+    let model = Arc::new(ScriptedModel::benign());
+    let app = router(test_state(model.clone()));
+
+    // `on_encrypted = "reject"` (the default).
+    let (code, body) = ingest(&app, "default", Some(WRONG)).await;
+    assert_eq!(code, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+    assert_eq!(parse(&body)["extra"]["reason"], "decryption_failed");
+    let (code, body) = ingest(&app, "default", None).await;
+    assert_eq!(code, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+    assert_eq!(parse(&body)["extra"]["reason"], "encrypted_unreadable");
+
+    // `on_encrypted = "needs_review"`.
+    let (code, body) = ingest(&app, "review", Some(WRONG)).await;
+    assert_eq!(code, StatusCode::OK, "{body}");
+    let v = parse(&body);
+    assert_eq!(v["action"], "needs_review");
+    assert_eq!(v["tools_allowed"], false);
+    assert!(v["reasons"][0]
+        .as_str()
+        .unwrap()
+        .starts_with("decryption_failed:"));
+    let (code, body) = ingest(&app, "review", None).await;
+    assert_eq!(code, StatusCode::OK, "{body}");
+    let v = parse(&body);
+    assert_eq!(v["action"], "needs_review");
+    assert!(v["reasons"][0]
+        .as_str()
+        .unwrap()
+        .starts_with("encrypted_unreadable:"));
+
+    assert_eq!(model.calls(), 0, "unread content went to a model");
+
+    // The real helper recognises the encryption itself.
+    std::env::set_var("ACIP_EXTRACTOR_BIN", env!("CARGO_BIN_EXE_acip-extract"));
+    let (code, body) = ingest(&app, "default", None).await;
+    assert_eq!(code, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+    assert_eq!(parse(&body)["extra"]["reason"], "encrypted_unreadable");
+
+    // `allow` is not a way to handle encrypted content.
+    let raw = r#"{"policies": {"default": {"on_encrypted": "allow"}}}"#;
+    assert!(policy_store::DeclaredPolicies::parse(raw).is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn the_password_appears_in_no_response_audit_entry_or_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let fake = helper(&dir, "fake", FAKE_HELPER);
+    let leaky = helper(&dir, "leaky", LEAKY_HELPER);
+    std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    let st = test_state(Arc::new(ScriptedModel::benign()));
+    let app = router(st.clone());
+
+    let mut bodies = vec![];
+    std::env::set_var("ACIP_EXTRACTOR_BIN", &fake);
+    for (policy, password) in [("default", PASSWORD), ("default", WRONG), ("review", WRONG)] {
+        bodies.push(ingest(&app, policy, Some(password)).await.1);
+    }
+    // A failing helper's diagnostics are redacted before they reach the error or the log.
+    std::env::set_var("ACIP_EXTRACTOR_BIN", &leaky);
+    let (code, body) = ingest(&app, "default", Some(PASSWORD)).await;
+    assert_eq!(code, StatusCode::BAD_REQUEST, "{body}");
+    assert!(body.contains("[redacted]"), "{body}");
+    bodies.push(body);
+    std::env::remove_var("ACIP_AUDIT_MODE");
+
+    let audit = serde_json::to_string(&st.incidents.list()).unwrap();
+    assert!(audit.contains("partner-mail-7"), "nothing was audited");
+    let logs = String::from_utf8_lossy(&logs().0.lock().unwrap()).to_string();
+    assert!(
+        logs.contains("extractor helper failed"),
+        "nothing was logged"
+    );
+    for secret in [PASSWORD, WRONG] {
+        for body in &bodies {
+            assert!(!body.contains(secret), "response: {body}");
+        }
+        assert!(!audit.contains(secret), "audit: {audit}");
+        assert!(!logs.contains(secret), "logs: {logs}");
+    }
+}
@@ -0,0 +1,198 @@
+use acip_sidecar::environment::{
+    self, Ceiling, Cgroup, ConflictClass, ExtractorLimits, Mode, Probe, Report, Rlimits, Severity,
+    TmpDir,
+};
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::{app, tmpdir::TmpDirSettings};
+use axum::{body::Body, http::Request, http::StatusCode, Router};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const MB: u64 = 1024 * 1024;
+
+fn limits() -> ExtractorLimits {
+    ExtractorLimits {
+        concurrency: 4,
+        rlimit_as_mb: 2048,
+        rlimit_nofile: 64,
+        rlimit_fsize_mb: 512,
+        rlimit_nproc: None,
+        rlimit_cpu_secs: 185,
+        tmp_min_free_mb: 512,
+    }
+}
+
+/// A roomy host: nothing conflicts with [`limits`].
+fn roomy() -> Probe {
+    Probe {
+        os: "linux".to_string(),
+        rlimits: Rlimits {
+            nofile: Ceiling::Limit(1_048_576),
+            address_space_bytes: Ceiling::Unlimited,
+            file_size_bytes: Ceiling::Unlimited,
+            nproc: Ceiling::Limit(63_000),
+            cpu_secs: Ceiling::Unlimited,
+        },
+        cgroup: Cgroup {
+            version: Some(2),
+            memory_bytes: Ceiling::Limit(16 * 1024 * MB),
+            pids: Ceiling::Limit(4096),
+        },
+        tmpdir: TmpDir {
+            path: "/var/tmp".to_string(),
+            free_bytes: Some(50 * 1024 * MB),
+            noexec: Some(false),
+        },
+        monotonic_clock: Some(true),
+    }
+}
+
+fn classes(probe: &Probe, limits: &ExtractorLimits) -> Vec<(ConflictClass, Severity)> {
+    environment::check(probe, limits)
+        .into_iter()
+        .map(|c| (c.class, c.severity))
+        .collect()
+}
+
+#[test]
+fn a_roomy_or_unreadable_host_has_no_conflicts() {
+    assert!(environment::check(&roomy(), &limits()).is_empty());
+    // Non-Linux, or every file hidden: nothing known, nothing checked.
+    assert!(environment::check(&Probe::default(), &limits()).is_empty());
+}
+
+#[test]
+fn configured_rlimits_above_the_process_hard_limits_are_hard_conflicts() {
+    let mut probe = roomy();
+    probe.rlimits = Rlimits {
+        nofile: Ceiling::Limit(32),
+        address_space_bytes: Ceiling::Limit(1024 * MB),
+        file_size_bytes: Ceiling::Limit(100 * MB),
+        nproc: Ceiling::Limit(16),
+        cpu_secs: Ceiling::Limit(60),
+    };
+    let mut limits = limits();
+    limits.rlimit_nproc = Some(32);
+
+    use ConflictClass::*;
+    assert_eq!(
+        classes(&probe, &limits),
+        [
+            (NofileAboveHardLimit, Severity::Hard),
+            (AddressSpaceAboveHardLimit, Severity::Hard),
+            (FileSizeAboveHardLimit, Severity::Hard),
+            (NprocAboveHardLimit, Severity::Hard),
+            (CpuAboveHardLimit, Severity::Hard),
+        ]
+    );
+    let conflicts = environment::check(&probe, &limits);
+    assert!(
+        conflicts[0].message.contains("rlimit_nofile 64"),
+        "{conflicts:?}"
+    );
+
+    // Equal to the hard limit is fine.
+    probe.rlimits.nofile = Ceiling::Limit(64);
+    assert!(!classes(&probe, &limits).contains(&(NofileAboveHardLimit, Severity::Hard)));
+}
+
+#[test]
+fn cgroup_memory_is_checked_per_helper_and_for_all_helpers_at_once() {
+    let mut probe = roomy();
+    // One helper fits, four at once do not.
+    probe.cgroup.memory_bytes = Ceiling::Limit(4096 * MB);
+    assert_eq!(
+        classes(&probe, &limits()),
+        [(ConflictClass::ConcurrentMemoryAboveCgroup, Severity::Soft)]
+    );
+    // A single helper's address space already exceeds the cgroup: reported once, as hard.
+    probe.cgroup.memory_bytes = Ceiling::Limit(1024 * MB);
+    assert_eq!(
+        classes(&probe, &limits()),
+        [(ConflictClass::AddressSpaceAboveCgroupMemory, Severity::Hard)]
+    );
+    probe.cgroup.memory_bytes = Ceiling::Unlimited;
+    assert!(classes(&probe, &limits()).is_empty());
+}
+
+#[test]
+fn pids_tmpdir_and_clock_problems_are_soft_conflicts() {
+    use ConflictClass::*;
+    let mut probe = roomy();
+    probe.cgroup.pids = Ceiling::Limit(4);
+    probe.tmpdir.noexec = Some(true);
+    probe.tmpdir.free_bytes = Some(600 * MB);
+    probe.monotonic_clock = Some(false);
+    assert_eq!(
+        classes(&probe, &limits()),
+        [
+            (PidsBelowConcurrency, Severity::Soft),
+            (TmpdirNoexec, Severity::Soft),
+            (TmpdirLowSpace, Severity::Soft),
+            (NoMonotonicClock, Severity::Soft),
+        ]
+    );
+}
+
+#[test]
+fn strict_mode_refuses_only_hard_conflicts() {
+    let report = |mode, probe: Probe| Report {
+        mode,
+        conflicts: environment::check(&probe, &limits()),
+        probe,
+        extractor: Some(limits()),
+    };
+    let mut hard = roomy();
+    hard.rlimits.nofile = Ceiling::Limit(32);
+    let mut soft = roomy();
+    soft.tmpdir.noexec = Some(true);
+
+    let err = report(Mode::Strict, hard.clone()).enforce().unwrap_err();
+    assert!(err.to_string().contains("rlimit_nofile"), "{err}");
+    assert!(report(Mode::Warn, hard).enforce().is_ok());
+    assert!(report(Mode::Strict, soft).enforce().is_ok());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn status_reports_the_probed_environment() {
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    st.environment = Arc::new(Report::assess(None, &TmpDirSettings::default()));
+    let app = app::build_router(Arc::new(st), None, Router::new());
+
+    let resp = app
+        .oneshot(Request::get("/v1/acip/status").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let v: Value = serde_json::from_slice(&body).unwrap();
+    let env = &v["environment"];
+
+    assert_eq!(env["mode"], "warn", "{env}");
+    assert_eq!(env["probe"]["os"], "linux");
+    assert_eq!(env["probe"]["monotonic_clock"], true);
+    // The test process always has a finite descriptor limit.
+    assert!(
+        env["probe"]["rlimits"]["nofile"].as_u64().unwrap() > 0,
+        "{env}"
+    );
+    let tmp = &env["probe"]["tmpdir"];
+    assert_eq!(tmp["path"], std::env::temp_dir().display().to_string());
+    assert!(tmp["free_bytes"].as_u64().is_some(), "{env}");
+    assert!(tmp["noexec"].is_boolean(), "{env}");
+    // Cgroup limits may be hidden; they are then "unknown", never missing.
+    for key in ["memory_bytes", "pids"] {
+        let c = &env["probe"]["cgroup"][key];
+        assert!(
+            c.is_u64() || c == "unlimited" || c == "unknown",
+            "{key}: {c}"
+        );
+    }
+    assert!(env["extractor"]["concurrency"].as_u64().unwrap() >= 1);
+    assert!(env["conflicts"].is_array());
+}
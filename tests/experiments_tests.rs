@@ -0,0 +1,278 @@
+use acip_sidecar::experiments::{
+    self, Arm, Assignment, ExperimentError, ExperimentRegistry, ExperimentRequest, Guardrails,
+};
+use acip_sidecar::model_policy::PolicyConfig;
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::sentry::Action;
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, policy_store, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const TOKEN: &str = "t0ken";
+
+fn policies() -> policy_store::PolicyStore {
+    let mut policies = std::collections::BTreeMap::new();
+    policies.insert("default".to_string(), PolicyConfig::default());
+    policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies })
+}
+
+fn request(fraction: f64, guardrails: Guardrails) -> ExperimentRequest {
+    serde_json::from_value(json!({
+        "policy": "default",
+        "definition": {"l2": {"model": "experimental-l2"}},
+        "fraction": fraction,
+        "max_duration_secs": 3600,
+        "guardrails": guardrails,
+    }))
+    .unwrap()
+}
+
+fn block_rate_guardrail() -> Guardrails {
+    Guardrails {
+        max_block_rate_delta_pct: Some(10.0),
+        min_decisions: Some(5),
+        ..Guardrails::default()
+    }
+}
+
+fn test_state() -> Arc<state::AppState> {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    AppStateBuilder::for_tests()
+        .with_policies(policies())
+        .with_model(Arc::new(ScriptedModel::benign()))
+        .build()
+        .unwrap()
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let mut b = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-ACIP-Token", TOKEN);
+    if body.is_some() {
+        b = b.header("content-type", "application/json");
+    }
+    let req = b
+        .body(body.map(|v| Body::from(v.to_string())).unwrap_or_default())
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[test]
+fn arm_assignment_is_stable_per_source_and_follows_the_fraction() {
+    let keys: Vec<String> = (0..2000).map(|i| format!("source_id:doc-{i}")).collect();
+    let on = keys
+        .iter()
+        .filter(|k| experiments::in_experimental_arm("exp-1", k, 0.25))
+        .count();
+    assert!((400..600).contains(&on), "{on} of 2000");
+    for k in &keys[..50] {
+        assert_eq!(
+            experiments::in_experimental_arm("exp-1", k, 0.25),
+            experiments::in_experimental_arm("exp-1", k, 0.25)
+        );
+        assert!(experiments::in_experimental_arm("exp-1", k, 1.0));
+    }
+
+    let reg = ExperimentRegistry::new(Arc::new(MockClock::new(1_000)));
+    let store = policies();
+    let view = reg
+        .start(request(0.5, block_rate_guardrail()), &store, "ops")
+        .unwrap();
+    let base = store.get("default").unwrap().clone();
+    for k in &keys[..50] {
+        let (config, a) = reg.assign("default", base.clone(), k, false);
+        let a = a.unwrap();
+        assert_eq!(a.experiment, view.id);
+        let expected = experiments::in_experimental_arm(&view.id, k, 0.5);
+        assert_eq!(a.arm == Arm::Experimental, expected);
+        assert_eq!(config.l2.model == "experimental-l2", expected);
+        // Paused (draining, read-only): everything on control.
+        let (config, a) = reg.assign("default", base.clone(), k, true);
+        assert_eq!(a.unwrap().arm, Arm::Control);
+        assert_eq!(config.l2.model, base.l2.model);
+    }
+    let (_, none) = reg.assign("other", base, "source_id:x", false);
+    assert!(none.is_none());
+}
+
+#[test]
+fn start_rejects_bad_requests_and_a_second_experiment() {
+    let reg = ExperimentRegistry::default();
+    let store = policies();
+
+    let mut req = request(0.5, block_rate_guardrail());
+    req.policy = "nope".into();
+    assert_eq!(
+        reg.start(req, &store, "ops").unwrap_err(),
+        ExperimentError::UnknownPolicy("nope".into())
+    );
+    for bad in [
+        request(0.0, block_rate_guardrail()),
+        request(1.5, block_rate_guardrail()),
+        request(0.5, Guardrails::default()),
+        ExperimentRequest {
+            max_duration_secs: experiments::MAX_DURATION_SECS + 1,
+            ..request(0.5, block_rate_guardrail())
+        },
+        ExperimentRequest {
+            definition: Default::default(),
+            ..request(0.5, block_rate_guardrail())
+        },
+    ] {
+        assert!(matches!(
+            reg.start(bad, &store, "ops"),
+            Err(ExperimentError::Invalid(_))
+        ));
+    }
+
+    let first = reg
+        .start(request(0.5, block_rate_guardrail()), &store, "ops")
+        .unwrap();
+    assert_eq!(
+        reg.start(request(0.1, block_rate_guardrail()), &store, "ops")
+            .unwrap_err(),
+        ExperimentError::AlreadyActive {
+            policy: "default".into(),
+            id: first.id,
+        }
+    );
+}
+
+#[test]
+fn tripped_guardrail_rolls_back_to_control() {
+    let reg = ExperimentRegistry::new(Arc::new(MockClock::new(1_000)));
+    let store = policies();
+    let view = reg
+        .start(request(0.5, block_rate_guardrail()), &store, "ops")
+        .unwrap();
+    let arm = |arm| Assignment {
+        experiment: view.id.clone(),
+        policy: "default".into(),
+        arm,
+    };
+
+    // Below min_decisions nothing trips, however bad the experimental arm looks.
+    for _ in 0..4 {
+        reg.observe(&arm(Arm::Control), Some(&Action::Allow), true);
+        reg.observe(&arm(Arm::Experimental), Some(&Action::Block), true);
+    }
+    assert_eq!(reg.snapshot().get("default"), Some(&view.id));
+    reg.observe(&arm(Arm::Control), Some(&Action::Allow), true);
+    reg.observe(&arm(Arm::Experimental), Some(&Action::Block), true);
+    assert!(reg.snapshot().is_empty());
+
+    let list = reg.list();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].status, experiments::ExperimentStatus::RolledBack);
+    assert_eq!(
+        (list[0].experimental.decisions, list[0].experimental.blocked),
+        (5, 5)
+    );
+    let changes: Vec<String> = reg.journal().into_iter().map(|e| e.change).collect();
+    assert_eq!(changes, ["started", "rolled_back"]);
+
+    let base = store.get("default").unwrap().clone();
+    for i in 0..20 {
+        let (config, a) = reg.assign("default", base.clone(), &format!("source_id:{i}"), false);
+        assert!(a.is_none());
+        assert_eq!(config.l2.model, base.l2.model);
+    }
+}
+
+#[test]
+fn experiment_expires_after_its_duration() {
+    let clock = Arc::new(MockClock::new(1_000));
+    let reg = ExperimentRegistry::new(clock.clone());
+    reg.start(request(1.0, block_rate_guardrail()), &policies(), "ops")
+        .unwrap();
+    clock.advance(3599);
+    assert_eq!(reg.snapshot().len(), 1);
+    clock.advance(1);
+    assert!(reg.snapshot().is_empty());
+    let list = reg.list();
+    assert_eq!(list[0].status, experiments::ExperimentStatus::Expired);
+    assert_eq!(list[0].ended_unix, Some(4_600));
+    assert_eq!(reg.journal().last().unwrap().change, "expired");
+}
+
+#[tokio::test]
+async fn experimental_decisions_carry_the_experiment_id() {
+    let st = test_state();
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let app = app::build_router(st.clone(), Some(TOKEN.to_string()), extra);
+
+    let body = json!({
+        "policy": "default",
+        "definition": {"l2": {"model": "experimental-l2"}},
+        "fraction": 1.0,
+        "max_duration_secs": 600,
+        "guardrails": {"max_error_rate_pct": 50.0},
+    });
+    let (code, v) = send(&app, "POST", "/v1/acip/experiments", Some(body.clone())).await;
+    assert_eq!(code, StatusCode::CREATED, "{v}");
+    let id = v["id"].as_str().unwrap().to_string();
+    let (code, v) = send(&app, "POST", "/v1/acip/experiments", Some(body)).await;
+    assert_eq!(code, StatusCode::CONFLICT, "{v}");
+    assert_eq!(v["error"], "experiment_active");
+    assert_eq!(v["extra"]["experiment"], id.as_str());
+
+    let (code, v) = send(
+        &app,
+        "POST",
+        "/v1/acip/ingest_source",
+        Some(json!({
+            "source_id": "mail-1",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "text": "Quarterly numbers attached.",
+        })),
+    )
+    .await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    assert_eq!(v["provenance"]["experiment"], id.as_str(), "{v}");
+    assert!(v["provenance"]["l2_model"]
+        .as_str()
+        .unwrap()
+        .contains("experimental-l2"));
+
+    let request_id = v["origin"]["request_id"].as_str().unwrap();
+    let (_, inc) = send(
+        &app,
+        "GET",
+        &format!("/v1/acip/incidents/{request_id}"),
+        None,
+    )
+    .await;
+    assert_eq!(inc["audit"]["experiment"], id.as_str(), "{inc}");
+
+    let (code, v) = send(&app, "GET", "/v1/acip/experiments", None).await;
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(v["experiments"][0]["experimental"]["runs"], 1);
+    assert_eq!(v["experiments"][0]["experimental"]["decisions"], 1);
+    assert_eq!(v["experiments"][0]["started_by"], "legacy");
+    assert_eq!(v["journal"][0]["change"], "started");
+
+    let (_, status) = send(&app, "GET", "/v1/acip/status", None).await;
+    assert_eq!(status["experiments"]["default"], id.as_str());
+}
@@ -0,0 +1,224 @@
+use acip_sidecar::extract::ExtractorError;
+use acip_sidecar::extractor_health::{BreakerSettings, BreakerState, ExtractorHealth};
+use acip_sidecar::model_policy::{ExtractorUnavailableHandling, PolicyConfig};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, policy_store, reputation};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::{json, Value};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tower::ServiceExt;
+
+use serial_test::serial;
+
+const HELPER: &str = env!("CARGO_BIN_EXE_acip-extract");
+
+fn init_env() {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub");
+    std::env::set_var("ACIP_EXTRACTOR_TIMEOUT_SECS", "180");
+    std::env::set_var("ACIP_EXTRACTOR_BIN", HELPER);
+}
+
+fn settings(threshold: u32) -> BreakerSettings {
+    BreakerSettings {
+        threshold,
+        probe_interval: Duration::from_millis(50),
+        probe_timeout: Duration::from_secs(10),
+    }
+}
+
+fn router(health: Arc<ExtractorHealth>, tmp: Arc<acip_sidecar::tmpdir::TmpDirManager>) -> Router {
+    let mut policies = std::collections::BTreeMap::new();
+    policies.insert("default".to_string(), PolicyConfig::default());
+    policies.insert(
+        "blocking".to_string(),
+        PolicyConfig {
+            on_extractor_unavailable: ExtractorUnavailableHandling::Block,
+            ..PolicyConfig::default()
+        },
+    );
+
+    let st = AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .with_tmp(tmp)
+        .with_extractor_health(health)
+        .build()
+        .unwrap();
+
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn call(app: &Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let v = serde_json::from_slice(&bytes)
+        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).to_string()));
+    (status, v)
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    call(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn ingest(app: &Router, policy: &str, body: Value) -> (StatusCode, Value) {
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-policy", policy)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    call(app, req).await
+}
+
+fn pdf() -> Value {
+    json!({
+        "source_id": "doc",
+        "source_type": "pdf",
+        "content_type": "application/pdf",
+        "bytes_b64": B64.encode(include_bytes!("fixtures/acip_known_text.pdf")),
+    })
+}
+
+fn svg() -> Value {
+    let svg = r#"<svg xmlns="http://www.w3.org/2000/svg"><text>Quarterly report</text></svg>"#;
+    json!({
+        "source_id": "drawing",
+        "source_type": "other",
+        "content_type": "image/svg+xml",
+        "bytes_b64": B64.encode(svg),
+    })
+}
+
+fn text() -> Value {
+    json!({
+        "source_id": "note",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Meeting moved to Thursday at 10am.",
+    })
+}
+
+async fn wait_for(health: &ExtractorHealth, state: BreakerState) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while health.state() != state {
+        assert!(Instant::now() < deadline, "breaker never became {state:?}");
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+fn first_reason(v: &Value) -> &str {
+    v["reasons"][0].as_str().unwrap_or_default()
+}
+
+#[test]
+fn breaker_opens_only_after_consecutive_helper_failures() {
+    let health = ExtractorHealth::new(settings(3), Arc::new(reputation::SystemClock));
+    let spawn = || ExtractorError::Spawn("No such file or directory".to_string());
+
+    health.record_failure(&spawn());
+    health.record_failure(&spawn());
+    health.record_success("ingest");
+    health.record_failure(&spawn());
+    assert!(health.is_available(), "a success resets the count");
+
+    // Document-caused failures do not count.
+    health.record_failure(&ExtractorError::Timeout);
+    health.record_failure(&ExtractorError::OutputTooLarge {
+        bytes: 10,
+        max_bytes: 1,
+    });
+    health.record_failure(&ExtractorError::Cancelled);
+    assert_eq!(health.snapshot().consecutive_failures, 1);
+
+    health.record_failure(&spawn());
+    health.record_failure(&spawn());
+    assert_eq!(health.state(), BreakerState::Open);
+    let snap = health.snapshot();
+    assert_eq!(snap.last_failure, Some("spawn_failed"));
+    assert_eq!(snap.transitions.len(), 1);
+    assert_eq!(snap.transitions[0].reason, "spawn_failed");
+
+    health.record_success("probe");
+    assert!(health.is_available());
+    assert_eq!(health.snapshot().transitions[1].reason, "probe_succeeded");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn broken_helper_degrades_extraction_and_recovers_without_restart() {
+    init_env();
+    let tmp = Arc::new(acip_sidecar::tmpdir::TmpDirManager::default());
+    let health = Arc::new(ExtractorHealth::new(
+        settings(2),
+        Arc::new(reputation::SystemClock),
+    ));
+    let app = router(health.clone(), tmp.clone());
+    acip_sidecar::extractor_health::start(health.clone(), tmp);
+
+    let (status, v) = ingest(&app, "default", svg()).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert!(!first_reason(&v).starts_with("helper_unavailable"), "{v}");
+
+    // Break the helper; the probes open the breaker.
+    std::env::set_var("ACIP_EXTRACTOR_BIN", "/definitely-not-a-real-binary");
+    wait_for(&health, BreakerState::Open).await;
+
+    let (status, v) = ingest(&app, "default", text()).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_ne!(v["action"], "needs_review", "{v}");
+
+    let started = Instant::now();
+    let (status, v) = ingest(&app, "default", pdf()).await;
+    assert!(started.elapsed() < Duration::from_millis(500));
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["action"], "needs_review");
+    assert_eq!(v["risk_level"], "medium");
+    assert_eq!(v["tools_allowed"], false);
+    assert!(
+        first_reason(&v).starts_with("helper_unavailable: extraction_failed (spawn_failed)"),
+        "{v}"
+    );
+
+    let (status, v) = ingest(&app, "blocking", pdf()).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["action"], "block");
+    assert_eq!(v["risk_level"], "high");
+
+    let (status, v) = get(&app, "/health/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["status"], "degraded");
+    assert_eq!(v["checks"]["extractor_available"], false);
+    let (_, v) = get(&app, "/v1/acip/status").await;
+    assert_eq!(v["extractor"]["breaker"]["state"], "open");
+
+    // Repair it; the next probe closes the breaker.
+    std::env::set_var("ACIP_EXTRACTOR_BIN", HELPER);
+    wait_for(&health, BreakerState::Closed).await;
+
+    let (status, v) = ingest(&app, "default", svg()).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert!(!first_reason(&v).starts_with("helper_unavailable"), "{v}");
+    let (_, v) = get(&app, "/health/ready").await;
+    assert_eq!(v["status"], "ok");
+    let (_, v) = get(&app, "/v1/acip/status").await;
+    let transitions: Vec<&str> = v["extractor"]["breaker"]["transitions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["state"].as_str().unwrap())
+        .collect();
+    assert_eq!(transitions, ["open", "closed"]);
+}
@@ -5,9 +5,9 @@ use axum::{
     Router,
 };
 use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
-use acip_sidecar::{app, ingest, policy_store, reputation, secrets, state};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest};
 use serde_json::Value;
-use std::{fs, os::unix::fs::PermissionsExt, sync::Arc};
+use std::{fs, os::unix::fs::PermissionsExt};
 use tower::ServiceExt;
 
 use serial_test::serial;
@@ -24,24 +24,7 @@ fn init_env() {
 }
 
 fn router() -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
-            head: 4000,
-            tail: 4000,
-            full_if_lte: 9000,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(reputation::InMemoryReputationStore::new()),
-    });
+    let st = AppStateBuilder::for_tests().build().unwrap();
 
     let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
     app::build_router(st, None, extra)
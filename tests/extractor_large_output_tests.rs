@@ -1,4 +1,5 @@
 use acip_sidecar::extract::{run_helper, ExtractKind, ExtractRequest};
+use acip_sidecar::tmpdir::TmpDirManager;
 use serial_test::serial;
 use std::time::Duration;
 
@@ -14,10 +15,17 @@ fn extractor_large_output_via_tempfile() {
         max_pages: None,
         dpi: None,
         max_output_chars: Some(2_000_000),
+        force_ocr: false,
+        password: None,
     };
 
-    let resp = run_helper(&req, b"%PDF-1.4\n", Duration::from_secs(10))
-        .expect("expected large response");
+    let resp = run_helper(
+        &TmpDirManager::default(),
+        &req,
+        b"%PDF-1.4\n",
+        Duration::from_secs(10),
+    )
+    .expect("expected large response");
 
     assert!(resp.ok);
     assert_eq!(resp.text.len(), 7_000_000);
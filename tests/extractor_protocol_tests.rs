@@ -0,0 +1,129 @@
+#![cfg(target_os = "linux")]
+
+use acip_sidecar::extract::{
+    failure_counts, parse_output, run_helper, ExtractKind, ExtractRequest, ExtractorError,
+    TailBuffer, STDERR_TAIL_BYTES,
+};
+use acip_sidecar::tmpdir::TmpDirManager;
+use serial_test::serial;
+use std::time::Duration;
+
+fn request() -> ExtractRequest {
+    ExtractRequest {
+        kind: ExtractKind::Svg,
+        content_type: None,
+        max_pages: None,
+        dpi: None,
+        max_output_chars: None,
+        force_ocr: false,
+        password: None,
+    }
+}
+
+/// Run the test-built helper in self-test failure `mode`; checks the failure was counted.
+fn fail(mode: &str, timeout: Duration) -> ExtractorError {
+    std::env::set_var("ACIP_EXTRACTOR_BIN", env!("CARGO_BIN_EXE_acip-extract"));
+    std::env::set_var("ACIP_EXTRACTOR_SELFTEST_FAIL", mode);
+    let before = failure_counts();
+
+    let err = run_helper(&TmpDirManager::default(), &request(), b"<svg/>", timeout)
+        .expect_err("self-test mode must fail");
+
+    std::env::remove_var("ACIP_EXTRACTOR_SELFTEST_FAIL");
+    let after = failure_counts();
+    assert_eq!(after[err.kind()], before[err.kind()] + 1, "{mode}: {err}");
+    err
+}
+
+#[test]
+#[serial]
+fn nonzero_exit_keeps_a_bounded_stderr_tail() {
+    match fail("exit", Duration::from_secs(10)) {
+        ExtractorError::NonZeroExit { exit_code, stderr } => {
+            assert_eq!(exit_code, Some(3));
+            assert!(stderr.ends_with("selftest_exit_marker"), "{stderr}");
+            assert!(stderr.starts_with("..."), "older lines are dropped");
+            assert!(stderr.len() <= STDERR_TAIL_BYTES + 3, "{}", stderr.len());
+        }
+        other => panic!("unexpected: {other:?}"),
+    }
+}
+
+#[test]
+#[serial]
+fn killed_helper_reports_the_signal_and_not_a_timeout() {
+    match fail("signal", Duration::from_secs(10)) {
+        ExtractorError::Signaled { signal, .. } => assert_eq!(signal, libc::SIGKILL),
+        other => panic!("unexpected: {other:?}"),
+    }
+    let err = fail("hang", Duration::from_millis(500));
+    assert!(matches!(err, ExtractorError::Timeout), "{err:?}");
+    assert_eq!(err.kind(), "timeout");
+}
+
+#[test]
+#[serial]
+fn output_problems_are_classified() {
+    let err = fail("empty", Duration::from_secs(10));
+    assert!(matches!(err, ExtractorError::EmptyOutput), "{err:?}");
+
+    match fail("truncated", Duration::from_secs(10)) {
+        ExtractorError::TruncatedOutput { bytes } => assert!(bytes > 0),
+        other => panic!("unexpected: {other:?}"),
+    }
+
+    let err = fail("malformed", Duration::from_secs(10));
+    assert!(
+        matches!(err, ExtractorError::MalformedOutput { .. }),
+        "{err:?}"
+    );
+
+    match fail("schema", Duration::from_secs(10)) {
+        ExtractorError::SchemaViolation { field, detail } => {
+            assert_eq!(field, "stats.ocr_used");
+            assert!(detail.contains("boolean"), "{detail}");
+        }
+        other => panic!("unexpected: {other:?}"),
+    }
+
+    let err = fail("no_output", Duration::from_secs(10));
+    assert!(matches!(err, ExtractorError::OutputMissing(_)), "{err:?}");
+    assert_eq!(err.kind(), "output_missing");
+}
+
+#[test]
+fn parse_output_names_the_offending_field() {
+    let missing =
+        br#"{"ok":true,"kind":"pdf","text":"x","stats":{"ocr_used":false,"ocr_chars":0}}"#;
+    match parse_output(missing) {
+        Err(ExtractorError::SchemaViolation { field, detail }) => {
+            assert_eq!(field, "stats.text_chars");
+            assert_eq!(detail, "missing");
+        }
+        other => panic!("unexpected: {other:?}"),
+    }
+    assert!(matches!(
+        parse_output(b"  \n"),
+        Err(ExtractorError::EmptyOutput)
+    ));
+    assert!(matches!(
+        parse_output(br#"{"ok":true,"kind":"#),
+        Err(ExtractorError::TruncatedOutput { bytes: 18 })
+    ));
+    assert!(parse_output(
+        br#"{"ok":true,"kind":"svg","text":"hi","stats":{"text_chars":2,"ocr_used":false,"ocr_chars":0}}"#
+    )
+    .is_ok());
+}
+
+#[test]
+fn tail_buffer_keeps_only_the_last_bytes() {
+    let mut tail = TailBuffer::new(8);
+    tail.push(b"abc");
+    assert_eq!(tail.text(), "abc");
+    tail.push(b"defghij");
+    assert_eq!(tail.text(), "...cdefghij");
+    tail.push(&[b'x'; 100]);
+    assert_eq!(tail.text(), format!("...{}", "x".repeat(8)));
+    assert_eq!(tail.dropped(), 102);
+}
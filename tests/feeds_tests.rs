@@ -0,0 +1,370 @@
+mod util;
+
+use acip_sidecar::config::FeedConfig;
+use acip_sidecar::egress;
+use acip_sidecar::feeds::{FeedError, FeedRegistry, FeedSpec, RefreshOutcome};
+use acip_sidecar::reputation::{Clock, MockClock, ReputationRecord};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, threat};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap, Request, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use serde_json::Value;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tower::ServiceExt;
+use util::serve;
+
+/// Feed file served with an ETag; counts full and `304` responses.
+#[derive(Default)]
+struct Fixture {
+    body: String,
+    etag: String,
+    full: usize,
+    not_modified: usize,
+}
+
+impl Fixture {
+    fn publish(&mut self, body: &str, etag: &str) {
+        self.body = body.to_string();
+        self.etag = etag.to_string();
+    }
+}
+
+async fn fixture_feed(State(f): State<Arc<Mutex<Fixture>>>, headers: HeaderMap) -> Response {
+    let mut f = f.lock().unwrap();
+    let etag = f.etag.clone();
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        == Some(etag.as_str())
+    {
+        f.not_modified += 1;
+        return StatusCode::NOT_MODIFIED.into_response();
+    }
+    f.full += 1;
+    ([(header::ETAG, etag)], f.body.clone()).into_response()
+}
+
+fn fixture(body: &str) -> (Arc<Mutex<Fixture>>, String) {
+    let f = Arc::new(Mutex::new(Fixture::default()));
+    f.lock().unwrap().publish(body, "\"v1\"");
+    let addr = serve(
+        Router::new()
+            .route("/feed.txt", get(fixture_feed))
+            .with_state(f.clone()),
+    );
+    (f, format!("http://{addr}/feed.txt"))
+}
+
+fn feed(toml_src: &str) -> FeedConfig {
+    toml::from_str(toml_src).unwrap()
+}
+
+fn blocklist(source: &str) -> FeedConfig {
+    feed(&format!(
+        "name = \"bad\"\ntype = \"domain_blocklist\"\nsource = \"{source}\"\nrefresh_secs = 60"
+    ))
+}
+
+/// Feeds from `cfgs`; the egress allowlist holds the loopback fixture host.
+fn registry(cfgs: &[FeedConfig], clock: Arc<dyn Clock>) -> Arc<FeedRegistry> {
+    let specs = FeedSpec::from_configs(cfgs, &hosts(&["127.0.0.1"])).unwrap();
+    Arc::new(FeedRegistry::new(specs, egress::client().unwrap(), clock))
+}
+
+fn hosts(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|h| h.to_string()).collect()
+}
+
+fn app_with(feeds: Arc<FeedRegistry>) -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let st = AppStateBuilder::for_tests()
+        .with_feeds(feeds)
+        .build()
+        .unwrap();
+    app::build_router(st, None, Router::new())
+}
+
+async fn send(app: &Router, method: &str, uri: &str) -> (StatusCode, Value) {
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn blocked(feeds: &FeedRegistry, text: &str) -> Vec<String> {
+    let mut a = threat::ThreatAssessment::none();
+    feeds.assess_urls(text, &Default::default(), &mut a);
+    a.indicators
+}
+
+#[tokio::test]
+async fn initial_load_then_conditional_refresh() {
+    let (f, url) = fixture("# vendor list\nevil.example\n\nphish.test\n");
+    let feeds = registry(&[blocklist(&url)], Arc::new(MockClock::new(1_000)));
+
+    assert!(blocked(&feeds, "see https://evil.example/x").is_empty());
+    assert_eq!(
+        feeds.refresh("bad").await.unwrap(),
+        RefreshOutcome::Updated { entries: 2 }
+    );
+    assert_eq!(
+        blocked(
+            &feeds,
+            "see https://cdn.evil.example/x and http://ok.example"
+        ),
+        vec!["feed_blocklist:bad:cdn.evil.example"]
+    );
+
+    // Unchanged upstream: a conditional request, answered with 304.
+    assert_eq!(
+        feeds.refresh("bad").await.unwrap(),
+        RefreshOutcome::NotModified
+    );
+    assert_eq!(f.lock().unwrap().full, 1);
+    assert_eq!(f.lock().unwrap().not_modified, 1);
+
+    f.lock()
+        .unwrap()
+        .publish("evil.example\nphish.test\nnew.test\n", "\"v2\"");
+    assert_eq!(
+        feeds.refresh("bad").await.unwrap(),
+        RefreshOutcome::Updated { entries: 3 }
+    );
+    assert_eq!(blocked(&feeds, "http://new.test").len(), 1);
+}
+
+#[tokio::test]
+async fn malformed_update_is_rejected_and_old_data_keeps_serving() {
+    let (f, url) = fixture("evil.example\n");
+    let feeds = registry(&[blocklist(&url)], Arc::new(MockClock::new(1_000)));
+    feeds.refresh("bad").await.unwrap();
+
+    for bad in ["evil.example\nnot a domain!\n", "# nothing here\n"] {
+        f.lock().unwrap().publish(bad, "\"broken\"");
+        let err = feeds.refresh("bad").await.unwrap_err();
+        assert!(matches!(err, FeedError::Refresh { .. }), "{err}");
+        assert_eq!(blocked(&feeds, "https://evil.example").len(), 1);
+    }
+    let status = feeds.status("bad").unwrap();
+    assert_eq!(status.entries, 1);
+    assert!(status.last_error.is_some());
+
+    // The rejected version left no validators behind: the next good one is fetched in full.
+    f.lock().unwrap().publish("other.example\n", "\"v3\"");
+    feeds.refresh("bad").await.unwrap();
+    assert!(blocked(&feeds, "https://evil.example").is_empty());
+    assert!(feeds.status("bad").unwrap().last_error.is_none());
+}
+
+#[tokio::test]
+async fn oversized_update_is_rejected() {
+    let (f, url) = fixture("evil.example\n");
+    let mut cfg = blocklist(&url);
+    cfg.max_bytes = Some(64);
+    let feeds = registry(&[cfg], Arc::new(MockClock::new(1_000)));
+    feeds.refresh("bad").await.unwrap();
+
+    let big: String = (0..20).map(|i| format!("host{i}.example\n")).collect();
+    f.lock().unwrap().publish(&big, "\"big\"");
+    assert!(feeds.refresh("bad").await.is_err());
+    assert_eq!(feeds.status("bad").unwrap().entries, 1);
+}
+
+#[tokio::test]
+async fn stale_feeds_degrade_readiness() {
+    let (_f, url) = fixture("evil.example\n");
+    let clock = Arc::new(MockClock::new(1_000));
+    let feeds = registry(&[blocklist(&url)], clock.clone());
+    let app = app_with(feeds.clone());
+
+    // Never loaded counts as stale.
+    let (status, v) = send(&app, "GET", "/health/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["status"], "degraded");
+    assert_eq!(v["stale_feeds"][0], "bad");
+
+    feeds.refresh("bad").await.unwrap();
+    let (_, v) = send(&app, "GET", "/health/ready").await;
+    assert_eq!(v["status"], "ok");
+    assert_eq!(v["checks"]["feeds_fresh"], true);
+
+    // 3 intervals of 60s without a successful refresh.
+    clock.advance(180);
+    assert!(feeds.stale_feeds().is_empty());
+    clock.advance(1);
+    assert_eq!(feeds.stale_feeds(), vec!["bad"]);
+    let (status, v) = send(&app, "GET", "/health/ready").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["status"], "degraded");
+    assert_eq!(v["checks"]["feeds_fresh"], false);
+
+    let (_, v) = send(&app, "GET", "/v1/acip/status").await;
+    assert_eq!(v["feeds"]["bad"]["stale"], true);
+    assert_eq!(v["feeds"]["bad"]["entries"], 1);
+    assert_eq!(v["feeds"]["bad"]["type"], "domain_blocklist");
+
+    // A 304 is a successful refresh.
+    feeds.refresh("bad").await.unwrap();
+    assert!(feeds.stale_feeds().is_empty());
+}
+
+#[tokio::test]
+async fn manual_refresh_endpoint() {
+    let (f, url) = fixture("evil.example\n");
+    let feeds = registry(&[blocklist(&url)], Arc::new(MockClock::new(1_000)));
+    let app = app_with(feeds.clone());
+
+    let (status, v) = send(&app, "POST", "/v1/acip/feeds/bad/refresh").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["outcome"], "updated");
+    assert_eq!(v["entries"], 1);
+    assert_eq!(v["status"]["entries"], 1);
+
+    let (_, v) = send(&app, "POST", "/v1/acip/feeds/bad/refresh").await;
+    assert_eq!(v["outcome"], "not_modified");
+
+    f.lock().unwrap().publish("<html>oops</html>\n", "\"err\"");
+    let (status, v) = send(&app, "POST", "/v1/acip/feeds/bad/refresh").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(v["error"], "feed_refresh_failed");
+
+    let (status, v) = send(&app, "POST", "/v1/acip/feeds/nope/refresh").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(v["error"], "unknown_feed");
+}
+
+#[tokio::test]
+async fn file_formats_allowlist_and_seeds() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv = dir.path().join("partners.csv");
+    std::fs::write(
+        &csv,
+        "id,domain\n1,partner.example\n2,\"docs.partner.test\"\n",
+    )
+    .unwrap();
+    let jsonl = dir.path().join("watch.jsonl");
+    std::fs::write(&jsonl, "{\"ioc\":{\"domain\":\"watch.example\"}}\n").unwrap();
+
+    let cfgs = [
+        feed(&format!(
+            "name = \"seed\"\ntype = \"reputation_seed\"\nsource = {:?}\nformat = \"jsonl\"\nfield = \"ioc.domain\"\nseed_score = 70",
+            jsonl.display().to_string()
+        )),
+        feed(&format!(
+            "name = \"partners\"\ntype = \"domain_allowlist\"\nsource = {:?}\nformat = \"csv\"\ncolumn = \"domain\"",
+            csv.display().to_string()
+        )),
+    ];
+    let feeds = registry(&cfgs, Arc::new(MockClock::new(1_000)));
+    feeds.refresh_all().await;
+    assert_eq!(feeds.status("partners").unwrap().entries, 2);
+    assert_eq!(
+        feeds.refresh("partners").await.unwrap(),
+        RefreshOutcome::NotModified
+    );
+
+    // Every link goes to a partner: the generic URL indicator is dropped.
+    let text = "Docs at https://docs.partner.test/guide";
+    let mut a = threat::assess(text);
+    assert!(a.indicators.iter().any(|i| i == "mentions_exfil:https://"));
+    feeds.assess_urls(text, &Default::default(), &mut a);
+    assert!(a.indicators.is_empty());
+    assert_eq!(a.threat_score, 0);
+
+    // One non-partner link keeps it.
+    let text = "https://partner.example and https://elsewhere.example";
+    let mut a = threat::assess(text);
+    feeds.assess_urls(text, &Default::default(), &mut a);
+    assert!(!a.indicators.is_empty());
+
+    let mut recs = vec![
+        ReputationRecord {
+            key: "host:api.watch.example".to_string(),
+            risk_score: 5,
+            ..Default::default()
+        },
+        ReputationRecord {
+            key: "source_id:watch.example".to_string(),
+            risk_score: 5,
+            ..Default::default()
+        },
+    ];
+    feeds.apply_seeds(&mut recs);
+    assert_eq!(recs[0].risk_score, 70);
+    assert_eq!(recs[1].risk_score, 5);
+}
+
+#[test]
+fn feed_configs_are_validated() {
+    let allowed = hosts(&["feeds.example", "10.0.0.5"]);
+    let ok = blocklist("https://feeds.example/list.txt");
+    assert!(FeedSpec::from_configs(std::slice::from_ref(&ok), &allowed).is_ok());
+    assert!(FeedSpec::from_configs(&[ok.clone(), ok], &allowed).is_err());
+
+    for bad in [
+        "name = \"x\"\ntype = \"domain_blocklist\"\nsource = \"http://feeds.example/list.txt\"",
+        "name = \"x\"\ntype = \"domain_blocklist\"\nsource = \"ftp://feeds.example/list.txt\"",
+        "name = \"x\"\ntype = \"domain_blocklist\"\nsource = \"a.csv\"\nformat = \"csv\"",
+        "name = \"x\"\ntype = \"domain_blocklist\"\nsource = \"a.jsonl\"\nformat = \"jsonl\"",
+        "name = \"x\"\ntype = \"domain_blocklist\"\nsource = \"a.txt\"\nrefresh_secs = 0",
+        "name = \"has space\"\ntype = \"domain_blocklist\"\nsource = \"a.txt\"",
+    ] {
+        assert!(
+            FeedSpec::from_configs(&[feed(bad)], &allowed).is_err(),
+            "{bad}"
+        );
+    }
+
+    // Order does not matter: feeds are keyed by name.
+    let a = feed("name = \"a\"\ntype = \"domain_allowlist\"\nsource = \"a.txt\"");
+    let b = feed("name = \"b\"\ntype = \"reputation_seed\"\nsource = \"b.txt\"");
+    let clock: Arc<dyn Clock> = Arc::new(MockClock::new(0));
+    let ab = registry(&[a.clone(), b.clone()], clock.clone());
+    let ba = registry(&[b, a], clock);
+    assert_eq!(ab.names(), ba.names());
+    assert_eq!(ab.snapshot(), ba.snapshot());
+}
+
+#[test]
+fn feed_sources_off_the_egress_allowlist_are_refused() {
+    let allowed = hosts(&["feeds.example", "10.0.0.5"]);
+    let err = |source: &str| {
+        FeedSpec::from_configs(&[blocklist(source)], &allowed)
+            .unwrap_err()
+            .to_string()
+    };
+    let off_list = err("https://evil.example.net/list.txt");
+    assert!(
+        off_list.contains("host not in ACIP_EGRESS_HOSTS"),
+        "{off_list}"
+    );
+    let userinfo = err("https://user:pw@feeds.example/list.txt");
+    assert!(userinfo.contains("credentials in the URL"), "{userinfo}");
+    let ip = err("https://10.0.0.5/list.txt");
+    assert!(ip.contains("IP address hosts are not allowed"), "{ip}");
+    // Loopback is not on the list either.
+    let loopback = err("http://127.0.0.1:9/list.txt");
+    assert!(loopback.contains("ACIP_EGRESS_HOSTS"), "{loopback}");
+}
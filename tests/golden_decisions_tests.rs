@@ -0,0 +1,214 @@
+use acip_sidecar::app;
+use acip_sidecar::sentry::ModelClient;
+use acip_sidecar::test_support::{self, GoldenMode, GoldenOutcome, ScriptedModel};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/golden")
+}
+
+fn init_env(mode: GoldenMode) {
+    std::env::set_var("ACIP_SENTRY_MODE", mode.sentry_mode());
+    // Keep the full threat assessment (indicators, detections) in the response.
+    std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    std::env::set_var("ACIP_REP_HALFLIFE_BASE_DAYS", "9999");
+    std::env::set_var("ACIP_REP_HALFLIFE_K", "0");
+    std::env::set_var("ACIP_REP_MED", "20");
+    std::env::set_var("ACIP_REP_HIGH", "50");
+    std::env::set_var("ACIP_REP_BAD", "150");
+    std::env::set_var("ACIP_EXTRACTOR_BIN", env!("CARGO_BIN_EXE_acip-extract"));
+}
+
+async fn run(model: Option<Arc<dyn ModelClient>>, body: Value) -> (u16, Value) {
+    let st = test_support::golden_state(model).unwrap();
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app::build_router(st, None, extra)
+        .oneshot(req)
+        .await
+        .unwrap();
+    let status = resp.status().as_u16();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// The whole corpus in one test: the sentry mode is process-wide.
+#[tokio::test]
+async fn decisions_match_the_checked_in_goldens() {
+    let dir = golden_dir();
+    let update = test_support::update_requested();
+    let fixtures = test_support::load_fixtures(&dir).unwrap();
+    let mut failures = vec![];
+    for mode in GoldenMode::ALL {
+        init_env(mode);
+        for f in &fixtures {
+            let missing = f.missing_tools();
+            if !missing.is_empty() {
+                eprintln!(
+                    "skipping {} ({}): {missing:?} not installed",
+                    f.name,
+                    mode.as_str()
+                );
+                continue;
+            }
+            let model: Option<Arc<dyn ModelClient>> = match mode {
+                GoldenMode::Heuristic => None,
+                GoldenMode::Scripted => Some(Arc::new(f.scripted_model())),
+            };
+            let (status, body) = run(model, f.request().unwrap()).await;
+            let golden =
+                test_support::golden(f, mode, test_support::normalize_decision(status, &body));
+            match test_support::check_golden(&f.golden_path(&dir, mode), &golden, update) {
+                Ok(GoldenOutcome::Created) => {
+                    eprintln!("wrote new golden for {} ({})", f.name, mode.as_str())
+                }
+                Ok(_) => {}
+                Err(e) => failures.push(format!("{e:#}")),
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn normalization_drops_run_specific_fields_and_orders_sets() {
+    let a = json!({
+        "action": "block",
+        "origin": { "request_id": "r-1", "marker": "m-1" },
+        "actor": "anonymous",
+        "fenced_content": "```external\nhi\n```",
+        "reasons": ["b", "a"],
+        "detected_patterns": ["z", "y"],
+        "threat": { "threat_score": 8, "indicators": ["q", "p"], "attack_types": ["jailbreak", "prompt_injection"] },
+    });
+    let mut b = a.clone();
+    b["origin"] = json!({ "request_id": "r-2", "marker": "m-2" });
+    b["detected_patterns"] = json!(["y", "z"]);
+    b["threat"]["indicators"] = json!(["p", "q"]);
+
+    let na = test_support::normalize_decision(200, &a);
+    assert_eq!(na, test_support::normalize_decision(200, &b));
+    let r = &na["response"];
+    assert!(r.get("origin").is_none() && r.get("actor").is_none());
+    assert!(r.get("fenced_content").is_none());
+    assert_eq!(r["fenced_content_sha256"].as_str().unwrap().len(), 64);
+    // Reasons are already in their stable rendering order and stay as they are.
+    assert_eq!(r["reasons"], json!(["b", "a"]));
+    assert_eq!(r["threat"]["indicators"], json!(["p", "q"]));
+}
+
+#[test]
+fn mismatch_lists_each_changed_field() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("case.heuristic.json");
+    let before = json!({ "decision": { "response": { "action": "block", "tools_allowed": false, "reasons": ["x"] } } });
+    // A missing golden fails unless an update was asked for.
+    let err = test_support::check_golden(&path, &before, false)
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("is missing (ACIP_UPDATE_GOLDENS=1 writes it)"),
+        "{err}"
+    );
+    assert!(!path.exists());
+    assert_eq!(
+        test_support::check_golden(&path, &before, true).unwrap(),
+        GoldenOutcome::Created
+    );
+    assert_eq!(
+        test_support::check_golden(&path, &before, false).unwrap(),
+        GoldenOutcome::Matched
+    );
+
+    let after = json!({ "decision": { "response": { "action": "allow", "tools_allowed": false, "reasons": ["x", "y"] } } });
+    let err = test_support::check_golden(&path, &after, false)
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("ACIP_UPDATE_GOLDENS=1"), "{err}");
+    assert!(
+        err.contains("$.decision.response.action: expected \"block\", got \"allow\""),
+        "{err}"
+    );
+    assert!(
+        err.contains("$.decision.response.reasons: expected [\"x\"], got [\"x\",\"y\"]"),
+        "{err}"
+    );
+    assert!(!err.contains("tools_allowed"), "{err}");
+}
+
+#[tokio::test]
+async fn scripted_model_answers_by_prompt_content() {
+    let m = ScriptedModel::benign().on(
+        "wire the funds",
+        json!({ "tools_allowed": false, "risk_level": "high", "action": "block" }),
+    );
+    let h = HeaderMap::new();
+    let v: Value =
+        serde_json::from_str(&m.generate("m", "please wire the funds", &h).await.unwrap()).unwrap();
+    assert_eq!(v["action"], "block");
+    assert_eq!(v["detected_patterns"], json!([]));
+    assert!(v["fenced_content"].is_string());
+    let v: Value = serde_json::from_str(&m.generate("m", "hello", &h).await.unwrap()).unwrap();
+    assert_eq!(v["action"], "allow");
+    assert_eq!(m.calls(), 2);
+
+    let raw = ScriptedModel::new(json!("not json"));
+    assert_eq!(raw.generate("m", "x", &h).await.unwrap(), "not json");
+}
+
+#[test]
+fn manifest_errors_name_the_case() {
+    let dir = tempfile::tempdir().unwrap();
+    let write = |s: &str| std::fs::write(dir.path().join(test_support::MANIFEST), s).unwrap();
+
+    write("[[case]]\nname = \"a\"\nsource_type = \"other\"\ncontent_type = \"text/plain\"\n");
+    let err = test_support::load_fixtures(dir.path())
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("case a: set exactly one of text, file or repeat"),
+        "{err}"
+    );
+
+    write("[[case]]\nname = \"a\"\nsource_type = \"other\"\ncontent_type = \"text/plain\"\nfile = \"nope.bin\"\n");
+    let err = test_support::load_fixtures(dir.path())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("case a: file"), "{err}");
+
+    write(
+        "[[case]]\nname = \"a\"\nsource_type = \"other\"\ncontent_type = \"text/plain\"\ntext = \"x\"\n\
+         [[case]]\nname = \"a\"\nsource_type = \"other\"\ncontent_type = \"text/plain\"\ntext = \"y\"\n",
+    );
+    let err = test_support::load_fixtures(dir.path())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("case a: duplicate name"), "{err}");
+
+    // The checked-in corpus loads.
+    let corpus = test_support::load_fixtures(&golden_dir()).unwrap();
+    assert!(corpus.len() >= 20);
+}
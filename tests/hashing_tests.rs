@@ -0,0 +1,380 @@
+use acip_sidecar::config::{HashingConfig, SiemConfig};
+use acip_sidecar::hashing::{self, IdHasher, SaltSource, PREVIOUS_SALT_SECRET, SALT_SECRET};
+use acip_sidecar::model_policy::PolicyConfig;
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::secrets::{Secret, SecretStore};
+use acip_sidecar::sentry::Decision;
+use acip_sidecar::siem::{SiemExport, SiemFormat, SiemSettings};
+use acip_sidecar::stats::{DecisionStats, DAY_SECS};
+use acip_sidecar::verdicts::VerdictHistory;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, reputation, state};
+use axum::{body::Body, http::Request, routing::post, Router};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+use tower::ServiceExt;
+
+const SOURCE_ID: &str = "crm:account-7781";
+const URL: &str = "https://crm.example.com/accounts/7781";
+const TITLE: &str = "Account 7781 notes";
+const TEXT: &str = "Renewal call moved to Thursday.";
+
+struct Secrets(HashMap<&'static str, &'static str>);
+
+impl SecretStore for Secrets {
+    fn get(&self, key: &str) -> Option<Secret> {
+        self.0.get(key).map(|v| Secret::new(*v))
+    }
+}
+
+fn plain_sha256(v: &str) -> String {
+    hex::encode(Sha256::digest(v.as_bytes()))
+}
+
+fn app_state(siem: SiemExport, hasher: IdHasher) -> Arc<state::AppState> {
+    AppStateBuilder::for_tests()
+        .with_siem(Arc::new(siem))
+        .with_hashing(Arc::new(hasher))
+        .build()
+        .unwrap()
+}
+
+const TOKEN: &str = "hashing-test-token";
+
+async fn get(app: &Router, uri: &str) -> Value {
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header("X-ACIP-Token", TOKEN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[test]
+fn export_ids_are_hmac_sha256_of_the_salt() {
+    // RFC 4231 test case 2.
+    assert_eq!(
+        IdHasher::new("Jefe").export_id("what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+}
+
+#[test]
+fn export_ids_are_salted_and_internal_digests_are_not() {
+    let a = IdHasher::new("deployment-a");
+    let b = IdHasher::new("deployment-b");
+
+    assert_eq!(
+        hashing::internal_only_digest(SOURCE_ID.as_bytes()),
+        plain_sha256(SOURCE_ID)
+    );
+    assert_ne!(a.export_id(SOURCE_ID), plain_sha256(SOURCE_ID));
+    assert_eq!(a.export_id(SOURCE_ID), a.export_id(SOURCE_ID));
+    // Two deployments cannot correlate the same identifier.
+    assert_ne!(a.export_id(SOURCE_ID), b.export_id(SOURCE_ID));
+    // Even the default, ephemeral salt never yields the unsalted digest.
+    assert_ne!(
+        IdHasher::default().export_id(SOURCE_ID),
+        plain_sha256(SOURCE_ID)
+    );
+}
+
+#[tokio::test]
+async fn outbound_surfaces_carry_no_unsalted_hashes() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("decisions.jsonl");
+    let cfg = SiemConfig {
+        format: SiemFormat::Ecs,
+        destination: out.to_str().unwrap().to_string(),
+        batch_size: None,
+        flush_interval_secs: None,
+        spool_capacity: None,
+        hash_observables: Some(true),
+        mappings: None,
+    };
+    let siem = SiemExport::new(
+        Some(SiemSettings::from_config(&cfg, &HashSet::new()).unwrap()),
+        Arc::new(reputation::SystemClock),
+    );
+    let st = app_state(siem, IdHasher::new("outbound-test-salt"));
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let app = app::build_router(st.clone(), None, extra);
+
+    let body = json!({
+        "source_id": SOURCE_ID,
+        "source_type": "other",
+        "content_type": "text/plain",
+        "url": URL,
+        "title": TITLE,
+        "text": TEXT,
+    });
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/v1/acip/ingest_source")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let answer: Value = serde_json::from_slice(&bytes).unwrap();
+    let content_sha = answer["digest"]["sha256"].as_str().unwrap().to_string();
+    let request_id = answer["origin"]["request_id"].as_str().unwrap();
+
+    st.siem.flush(&reqwest::Client::new()).await.unwrap();
+    let siem_out = std::fs::read_to_string(&out).unwrap();
+    let incident = get(&app, &format!("/v1/acip/incidents/{request_id}")).await;
+    let audit = incident["audit"].to_string();
+
+    // SIEM observables, event id and content hash.
+    let event: Value = serde_json::from_str(siem_out.lines().next().unwrap()).unwrap();
+    assert_eq!(
+        event["labels"]["acip_source_id"],
+        st.hashing.export_id(SOURCE_ID)
+    );
+    assert_eq!(event["url"]["original"], st.hashing.export_id(URL));
+    assert_eq!(event["labels"]["acip_title"], st.hashing.export_id(TITLE));
+    assert_eq!(
+        event["file"]["hash"]["sha256"],
+        st.hashing.export_id(&content_sha)
+    );
+    // The audit entry's content hash.
+    assert_eq!(
+        incident["audit"]["decision"]["content_sha256"],
+        st.hashing.export_id(&content_sha)
+    );
+
+    for unsalted in [
+        plain_sha256(SOURCE_ID),
+        plain_sha256(URL),
+        plain_sha256(TITLE),
+        content_sha.clone(),
+    ] {
+        assert!(
+            !siem_out.contains(&unsalted),
+            "SIEM export holds {unsalted}"
+        );
+        assert!(!audit.contains(&unsalted), "audit entry holds {unsalted}");
+    }
+}
+
+/// Collects formatted log output.
+#[derive(Clone, Default)]
+struct LogBuf(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn rules_drift_log_names_content_by_its_salted_id() {
+    let clock = Arc::new(MockClock::new(20_000 * DAY_SECS));
+    let hasher = Arc::new(IdHasher::new("log-test-salt"));
+    let history = VerdictHistory::new(100, clock.clone()).with_hashing(hasher.clone());
+    let stats = DecisionStats::in_memory(Default::default(), clock.clone());
+    let mut policy = PolicyConfig::default();
+    policy.cache.max_verdict_age_days = 1;
+    let decision = |action: &str, risk: &str| -> Decision {
+        serde_json::from_value(json!({
+            "tools_allowed": false,
+            "risk_level": risk,
+            "action": action,
+            "fenced_content": "```external\nX\n```",
+        }))
+        .unwrap()
+    };
+    let digest = plain_sha256(TEXT);
+
+    let logs = LogBuf::default();
+    let writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_ansi(false)
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        history.observe(
+            &stats,
+            "default",
+            &policy,
+            &digest,
+            &decision("allow", "low"),
+            None,
+        );
+        clock.advance(2 * DAY_SECS);
+        let reval = history
+            .observe(
+                &stats,
+                "default",
+                &policy,
+                &digest,
+                &decision("block", "high"),
+                None,
+            )
+            .unwrap();
+        assert!(!reval.agreed);
+    });
+
+    let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+    assert!(logs.contains("Rules drift"), "{logs}");
+    assert!(logs.contains(&hasher.export_id(&digest)), "{logs}");
+    assert!(!logs.contains(&digest), "{logs}");
+}
+
+#[test]
+fn rotation_window_accepts_the_previous_salt() {
+    let dir = tempfile::tempdir().unwrap();
+    let cfg = |accept_previous| HashingConfig {
+        salt_file: Some(dir.path().join("salt").to_str().unwrap().to_string()),
+        accept_previous_salt: Some(accept_previous),
+    };
+    let old = IdHasher::new("salt-2025");
+    let exported_before = old.export_id(SOURCE_ID);
+
+    let rotating = IdHasher::load(
+        &Secrets(HashMap::from([
+            (SALT_SECRET, "salt-2026"),
+            (PREVIOUS_SALT_SECRET, "salt-2025"),
+        ])),
+        Some(&cfg(true)),
+        false,
+    )
+    .unwrap();
+    assert!(rotating.snapshot().accepting_previous_salt);
+    // New exports use the new salt...
+    assert_eq!(
+        rotating.export_id(SOURCE_ID),
+        IdHasher::new("salt-2026").export_id(SOURCE_ID)
+    );
+    // ...and lookups still find data exported under the old one, including truncated ids.
+    assert_eq!(
+        rotating.candidates(SOURCE_ID),
+        [rotating.export_id(SOURCE_ID), exported_before.clone()]
+    );
+    assert!(rotating.matches(SOURCE_ID, &exported_before));
+    assert!(rotating.matches(SOURCE_ID, &exported_before[..32]));
+    assert!(!rotating.matches("crm:account-7782", &exported_before));
+
+    // Once the window closes the old exports no longer match.
+    let rotated = IdHasher::load(
+        &Secrets(HashMap::from([
+            (SALT_SECRET, "salt-2026"),
+            (PREVIOUS_SALT_SECRET, "salt-2025"),
+        ])),
+        Some(&cfg(false)),
+        false,
+    )
+    .unwrap();
+    assert!(!rotated.matches(SOURCE_ID, &exported_before));
+    assert_eq!(rotated.candidates(SOURCE_ID).len(), 1);
+
+    // The flag without a previous salt, or with the same salt twice, is a startup error.
+    let err = IdHasher::load(
+        &Secrets(HashMap::from([(SALT_SECRET, "salt-2026")])),
+        Some(&cfg(true)),
+        false,
+    )
+    .err()
+    .unwrap();
+    assert!(err.to_string().contains(PREVIOUS_SALT_SECRET), "{err}");
+    assert!(IdHasher::load(
+        &Secrets(HashMap::from([
+            (SALT_SECRET, "salt-2026"),
+            (PREVIOUS_SALT_SECRET, "salt-2026"),
+        ])),
+        Some(&cfg(true)),
+        false,
+    )
+    .is_err());
+}
+
+#[tokio::test]
+async fn admin_lookup_answers_under_both_salts() {
+    let hasher = IdHasher::new("salt-2026").with_previous("salt-2025");
+    let app = app::build_router(
+        app_state(SiemExport::default(), hasher),
+        Some(TOKEN.to_string()),
+        Router::new(),
+    );
+    let old = IdHasher::new("salt-2025").export_id(SOURCE_ID);
+
+    let v = get(
+        &app,
+        &format!("/v1/acip/admin/hashing/lookup?value={SOURCE_ID}&exported={old}"),
+    )
+    .await;
+    assert_eq!(
+        v["current"],
+        IdHasher::new("salt-2026").export_id(SOURCE_ID)
+    );
+    assert_eq!(v["previous"], old);
+    assert_eq!(v["matches"], true);
+}
+
+#[test]
+fn a_missing_salt_is_generated_once_and_kept() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state").join("hashing.salt");
+    let cfg = HashingConfig {
+        salt_file: Some(path.to_str().unwrap().to_string()),
+        accept_previous_salt: None,
+    };
+    let none = Secrets(HashMap::new());
+
+    let first = IdHasher::load(&none, Some(&cfg), false).unwrap();
+    assert_eq!(first.snapshot().salt_source, SaltSource::Generated);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o077, 0, "salt file mode {mode:o}");
+    }
+
+    let second = IdHasher::load(&none, Some(&cfg), false).unwrap();
+    assert_eq!(second.snapshot().salt_source, SaltSource::File);
+    assert_eq!(second.export_id(SOURCE_ID), first.export_id(SOURCE_ID));
+
+    // A secret wins over the file.
+    let secret = Secrets(HashMap::from([(SALT_SECRET, "from-the-secrets-store")]));
+    let third = IdHasher::load(&secret, Some(&cfg), false).unwrap();
+    assert_eq!(third.snapshot().salt_source, SaltSource::Secret);
+    assert_ne!(third.export_id(SOURCE_ID), first.export_id(SOURCE_ID));
+
+    // Read-only instances never write one.
+    let elsewhere = HashingConfig {
+        salt_file: Some(dir.path().join("absent").to_str().unwrap().to_string()),
+        accept_previous_salt: None,
+    };
+    let ro = IdHasher::load(&none, Some(&elsewhere), true).unwrap();
+    assert_eq!(ro.snapshot().salt_source, SaltSource::Ephemeral);
+    assert!(!dir.path().join("absent").exists());
+}
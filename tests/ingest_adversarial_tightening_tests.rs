@@ -4,26 +4,16 @@ use axum::{
     routing::post,
     Router,
 };
-use acip_sidecar::{app, ingest, policy_store, reputation, secrets, state};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, state};
 use serde_json::Value;
-use std::sync::Arc;
 use tower::ServiceExt;
 
 fn router_with_state(policy: state::Policy, normalize: state::NormalizeSettings) -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy,
-        normalize,
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(reputation::InMemoryReputationStore::new()),
-    });
+    let st = AppStateBuilder::for_tests()
+        .with_policy(policy)
+        .with_normalize(normalize)
+        .build()
+        .unwrap();
 
     let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
     app::build_router(st, None, extra)
@@ -66,6 +56,7 @@ async fn adversarial_tightening_reduces_window_for_markup() {
         window_tail_chars: 200,
         adversarial_threshold: 1,
         adversarial_tighten_factor: 0.5,
+        decode: Default::default(),
     };
 
     let app = router_with_state(policy, normalize);
@@ -113,6 +104,7 @@ async fn non_markup_does_not_tighten_windows() {
         window_tail_chars: 200,
         adversarial_threshold: 1,
         adversarial_tighten_factor: 0.5,
+        decode: Default::default(),
     };
 
     let app = router_with_state(policy, normalize);
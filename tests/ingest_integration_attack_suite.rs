@@ -5,7 +5,7 @@ use axum::{
     Router,
 };
 use acip_sidecar::reputation::ReputationStore;
-use acip_sidecar::{app, ingest, policy_store, reputation, secrets, state};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, reputation};
 use serde_json::Value;
 use std::sync::{Arc, Once};
 use tower::ServiceExt;
@@ -26,24 +26,10 @@ fn init_env() {
 }
 
 fn router_with_state(rep: Arc<reputation::InMemoryReputationStore>) -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
-            head: 4000,
-            tail: 4000,
-            full_if_lte: 9000,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: rep,
-    });
+    let st = AppStateBuilder::for_tests()
+        .with_reputation(rep)
+        .build()
+        .unwrap();
 
     let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
 
@@ -203,5 +189,5 @@ async fn threat_fields_populated_for_prompt_injection_like_text() {
 
     assert_eq!(status, StatusCode::OK);
     assert!(v["threat"]["threat_score"].as_u64().unwrap_or(0) >= 1);
-    assert!(v["threat"]["attack_types"].as_array().unwrap().len() >= 1);
+    assert!(!v["threat"]["attack_types"].as_array().unwrap().is_empty());
 }
@@ -5,12 +5,9 @@ use axum::{
     Router,
 };
 use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
-use acip_sidecar::{app, ingest, policy_store, reputation, secrets, state};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest};
 use serde_json::Value;
-use std::{
-    process::Command,
-    sync::{Arc, Once},
-};
+use std::{process::Command, sync::Once};
 use tower::ServiceExt;
 
 static INIT: Once = Once::new();
@@ -38,24 +35,7 @@ fn have_bin(name: &str) -> bool {
 }
 
 fn router() -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
-            head: 4000,
-            tail: 4000,
-            full_if_lte: 9000,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(reputation::InMemoryReputationStore::new()),
-    });
+    let st = AppStateBuilder::for_tests().build().unwrap();
 
     let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
     app::build_router(st, None, extra)
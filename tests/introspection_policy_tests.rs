@@ -4,9 +4,8 @@ use axum::{
     routing::get,
     Router,
 };
-use acip_sidecar::{policy_store, routes, state};
+use acip_sidecar::{app_state_builder::AppStateBuilder, policy_store, routes};
 use serde_json::Value;
-use std::sync::Arc;
 use tower::ServiceExt;
 
 fn app_with_policies(names: &[&str]) -> Router {
@@ -25,18 +24,10 @@ fn app_with_policies(names: &[&str]) -> Router {
 
     let store = policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies });
 
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
-            head: 4000,
-            tail: 4000,
-            full_if_lte: 9000,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(acip_sidecar::secrets::EnvStore),
-        policies: store,
-        reputation: Arc::new(acip_sidecar::reputation::InMemoryReputationStore::new()),
-    });
+    let st = AppStateBuilder::for_tests()
+        .with_policies(store)
+        .build()
+        .unwrap();
 
     Router::new()
         .route("/v1/acip/schema", get(routes::get_schema))
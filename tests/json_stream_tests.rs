@@ -0,0 +1,308 @@
+mod util;
+
+use acip_sidecar::json_stream::{stream_completed, stream_response, StreamFormat, NDJSON};
+use acip_sidecar::reputation::{self, ReputationStore};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, client, state};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use futures_util::stream;
+use http_body_util::BodyExt;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tower::ServiceExt;
+use util::serve;
+
+#[derive(Serialize, Clone)]
+struct Item {
+    n: usize,
+    reasons: Vec<String>,
+}
+
+fn item(n: usize) -> Item {
+    Item {
+        n,
+        reasons: vec!["x".repeat(1000)],
+    }
+}
+
+fn accept(v: &str) -> HeaderMap {
+    let mut h = HeaderMap::new();
+    h.insert("accept", HeaderValue::from_str(v).unwrap());
+    h
+}
+
+async fn body_frames(resp: Response) -> Vec<Vec<u8>> {
+    let mut body = resp.into_body();
+    let mut frames = vec![];
+    while let Some(frame) = body.frame().await {
+        if let Ok(data) = frame.unwrap().into_data() {
+            frames.push(data.to_vec());
+        }
+    }
+    frames
+}
+
+#[test]
+fn format_follows_accept_header() {
+    assert_eq!(
+        StreamFormat::from_headers(&HeaderMap::new()),
+        StreamFormat::JsonArray
+    );
+    assert_eq!(
+        StreamFormat::from_headers(&accept("application/json")),
+        StreamFormat::JsonArray
+    );
+    assert_eq!(
+        StreamFormat::from_headers(&accept("application/x-ndjson, application/json;q=0.5")),
+        StreamFormat::Ndjson
+    );
+}
+
+#[tokio::test]
+async fn both_forms_carry_the_same_items() {
+    let items: Vec<Item> = (0..3).map(item).collect();
+
+    let resp = stream_response(StreamFormat::JsonArray, stream::iter(items.clone()));
+    assert_eq!(resp.headers()["content-type"], "application/json");
+    let array: Vec<Value> = serde_json::from_slice(&body_frames(resp).await.concat()).unwrap();
+
+    let resp = stream_response(StreamFormat::Ndjson, stream::iter(items));
+    assert_eq!(resp.headers()["content-type"], NDJSON);
+    let body = String::from_utf8(body_frames(resp).await.concat()).unwrap();
+    let lines: Vec<Value> = body
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+
+    assert_eq!(array.len(), 3);
+    assert_eq!(array, lines);
+
+    let empty = stream_response(StreamFormat::JsonArray, stream::iter(Vec::<Item>::new()));
+    assert_eq!(body_frames(empty).await.concat(), b"[]");
+}
+
+#[tokio::test]
+async fn buffering_is_per_item_not_per_response() {
+    const N: usize = 5_000;
+
+    // Previous approach: the whole response is encoded before the first byte is sent.
+    let all: Vec<Item> = (0..N).map(item).collect();
+    let buffered = serde_json::to_vec(&all).unwrap().len();
+
+    let resp = stream_response(StreamFormat::JsonArray, stream::iter((0..N).map(item)));
+    let frames = body_frames(resp).await;
+    let largest = frames.iter().map(Vec::len).max().unwrap();
+    let total: usize = frames.iter().map(Vec::len).sum();
+
+    assert_eq!(total, buffered);
+    // The largest buffer is one encoded item, regardless of how many items there are.
+    assert!(largest < 1_100, "largest frame {largest} bytes");
+    assert!(buffered > N * 1_000);
+}
+
+#[tokio::test]
+async fn first_items_arrive_before_the_last_is_produced() {
+    let (tx, rx) = tokio::sync::mpsc::channel::<Item>(1);
+    let items = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|i| (i, rx)) });
+    let mut body = stream_response(StreamFormat::Ndjson, items).into_body();
+
+    tx.send(item(0)).await.unwrap();
+    let first = tokio::time::timeout(Duration::from_secs(5), body.frame())
+        .await
+        .expect("first item is sent while the producer is still open")
+        .unwrap()
+        .unwrap()
+        .into_data()
+        .unwrap();
+    let v: Value = serde_json::from_slice(&first).unwrap();
+    assert_eq!(v["n"], 0);
+
+    drop(tx);
+    assert!(body.frame().await.is_none());
+}
+
+fn delayed_jobs() -> Vec<impl std::future::Future<Output = Item> + Send + 'static> {
+    // Job 0 is the slowest, job 2 the fastest.
+    (0..3)
+        .map(|n| async move {
+            tokio::time::sleep(Duration::from_millis(60 * (3 - n as u64))).await;
+            item(n)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn ndjson_emits_in_completion_order_with_index() {
+    let resp = stream_completed(StreamFormat::Ndjson, delayed_jobs());
+    let body = String::from_utf8(body_frames(resp).await.concat()).unwrap();
+    let indexes: Vec<u64> = body
+        .lines()
+        .map(|l| {
+            serde_json::from_str::<Value>(l).unwrap()["index"]
+                .as_u64()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(indexes, vec![2, 1, 0]);
+}
+
+#[tokio::test]
+async fn json_array_keeps_request_order() {
+    let resp = stream_completed(StreamFormat::JsonArray, delayed_jobs());
+    let v: Vec<Value> = serde_json::from_slice(&body_frames(resp).await.concat()).unwrap();
+    let ns: Vec<u64> = v.iter().map(|i| i["n"].as_u64().unwrap()).collect();
+    assert_eq!(ns, vec![0, 1, 2]);
+    assert!(v.iter().all(|i| i.get("index").is_none()));
+}
+
+fn test_state() -> Arc<state::AppState> {
+    state_with_sources((0..250).map(|i| format!("s{i:03}")))
+}
+
+/// A state with one reputation record per source id, recorded in the given order.
+fn state_with_sources(source_ids: impl IntoIterator<Item = String>) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let reputation = Arc::new(reputation::InMemoryReputationStore::new());
+    for id in source_ids {
+        reputation.record(reputation::observation(id, None, 0, vec![]));
+    }
+
+    AppStateBuilder::for_tests()
+        .with_reputation(reputation)
+        .build()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reputation_records_export_as_ndjson() {
+    let app = app::build_router(test_state(), None, Router::new());
+    let resp = app
+        .oneshot(
+            axum::http::Request::builder()
+                .uri("/v1/acip/reputation/records?prefix=source_id:s1")
+                .header("accept", NDJSON)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], NDJSON);
+    let body = String::from_utf8(body_frames(resp).await.concat()).unwrap();
+    // Not paginated: all 100 `source_id:s1xx` keys, in key order.
+    let keys: Vec<String> = body
+        .lines()
+        .map(|l| {
+            serde_json::from_str::<Value>(l).unwrap()["key"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect();
+    assert_eq!(keys.len(), 100);
+    assert_eq!(keys.first().unwrap(), "source_id:s100");
+    assert!(keys.windows(2).all(|w| w[0] < w[1]));
+}
+
+#[tokio::test]
+async fn ndjson_export_lines_follow_key_order_not_insertion_order() {
+    let ids = ["zeta", "alpha", "mike", "bravo", "yankee"].map(String::from);
+    let app = app::build_router(state_with_sources(ids), None, Router::new());
+    let resp = app
+        .oneshot(
+            axum::http::Request::builder()
+                .uri("/v1/acip/reputation/records")
+                .header("accept", NDJSON)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let body = String::from_utf8(body_frames(resp).await.concat()).unwrap();
+    let keys: Vec<Value> = body
+        .lines()
+        .map(|l| serde_json::from_str::<Value>(l).unwrap()["key"].clone())
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "source_id:alpha",
+            "source_id:bravo",
+            "source_id:mike",
+            "source_id:yankee",
+            "source_id:zeta",
+        ]
+    );
+}
+
+#[test]
+fn client_reads_ndjson_and_array_forms() {
+    let array_only = Router::new().route(
+        "/array",
+        get(|| async { Json(json!([{"key": "a"}, {"key": "b"}])) }),
+    );
+    let addr = serve(app::build_router(test_state(), None, Router::new()).merge(array_only));
+    let c = client::Client::new(&format!("http://{addr}"), None);
+
+    let streamed: Vec<Value> = c
+        .stream_items("/v1/acip/reputation/records", &[])
+        .unwrap()
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(streamed.len(), 250);
+
+    let array: Vec<Value> = c
+        .stream_items("/array", &[])
+        .unwrap()
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(array, vec![json!({"key": "a"}), json!({"key": "b"})]);
+}
+
+#[tokio::test]
+async fn ndjson_is_redacted_per_line_and_stays_streamed() {
+    let cfg = acip_sidecar::config::RedactionConfig {
+        rules: vec![acip_sidecar::config::RedactionRuleConfig {
+            label: "host".to_string(),
+            literal: Some("corp.internal".to_string()),
+            regex: None,
+        }],
+    };
+    let redaction = Arc::new(acip_sidecar::redact::Redaction::from_config(Some(&cfg)).unwrap());
+    let app = Router::new()
+        .route(
+            "/items",
+            get(|| async {
+                let items = (0..3).map(|n| json!({"n": n, "host": format!("h{n}.corp.internal")}));
+                stream_response(StreamFormat::Ndjson, stream::iter(items))
+            }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            redaction,
+            acip_sidecar::redact::redact_response,
+        ));
+
+    let resp = app
+        .oneshot(
+            axum::http::Request::builder()
+                .uri("/items")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let frames = body_frames(resp).await;
+    assert_eq!(frames.len(), 3);
+    let lines: Vec<Value> = frames
+        .iter()
+        .map(|f| serde_json::from_slice(f).unwrap())
+        .collect();
+    assert_eq!(lines[2]["host"], "h2.[REDACTED:host]");
+}
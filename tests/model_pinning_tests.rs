@@ -0,0 +1,360 @@
+use acip_sidecar::egress;
+use acip_sidecar::loop_guard::LoopGuard;
+use acip_sidecar::model_pinning::{
+    ModelVersionMonitor, ObservedBy, VersionMismatchEvent, VersionNotifier, WebhookNotifier,
+};
+use acip_sidecar::model_policy::{ModelRef, PolicyConfig, Provider, VersionMismatchHandling};
+use acip_sidecar::policy_store::{PoliciesFile, PolicyStore};
+use acip_sidecar::sentry::{Action, DecisionEngine, Generation, ModelClient, SentryVerdict};
+use acip_sidecar::verdicts::Provenance;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Answers with a valid `allow` decision and reports `version` as the serving model version.
+struct VersionedClient {
+    version: Option<String>,
+}
+
+#[async_trait]
+impl ModelClient for VersionedClient {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        Ok(json!({
+            "tools_allowed": true,
+            "risk_level": "low",
+            "action": "allow",
+            "fenced_content": "```external\nhello\n```",
+            "reasons": ["ok"],
+            "detected_patterns": []
+        })
+        .to_string())
+    }
+
+    async fn generate_reporting(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> anyhow::Result<Generation> {
+        Ok(Generation {
+            text: self.generate(model, prompt, headers).await?,
+            model_version: self.version.clone(),
+            confidence: None,
+            prompt_tokens: None,
+        })
+    }
+
+    async fn probe_version(&self, _model: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.version.clone())
+    }
+}
+
+#[derive(Default)]
+struct RecordingNotifier {
+    events: Mutex<Vec<VersionMismatchEvent>>,
+}
+
+impl VersionNotifier for RecordingNotifier {
+    fn notify(&self, event: &VersionMismatchEvent) {
+        self.events.lock().unwrap().push(event.clone());
+    }
+}
+
+fn pinned_policy(handling: VersionMismatchHandling) -> PolicyConfig {
+    PolicyConfig {
+        l1: ModelRef {
+            provider: Provider::Gemini,
+            model: "gemini-2.0-flash".to_string(),
+            required_model_version: Some("gemini-2.0-flash-001".to_string()),
+            consistency_check: false,
+            context_tokens: None,
+        },
+        l2: ModelRef {
+            provider: Provider::Anthropic,
+            model: "claude-3-5-haiku-latest".to_string(),
+            required_model_version: None,
+            consistency_check: false,
+            context_tokens: None,
+        },
+        cache: Default::default(),
+        verdict_parsing: Default::default(),
+        on_garbled_text: Default::default(),
+        on_version_mismatch: handling,
+        on_low_confidence: Default::default(),
+        on_encrypted: Default::default(),
+        on_extractor_unavailable: Default::default(),
+        content_types: vec![],
+        url_allowlist: Default::default(),
+        active_probing: false,
+        locale: None,
+        remediation: Default::default(),
+    }
+}
+
+async fn verdict(policy: &PolicyConfig, version: Option<&str>) -> SentryVerdict {
+    let engine = DecisionEngine::new(
+        Box::new(VersionedClient {
+            version: version.map(str::to_string),
+        }),
+        Box::new(VersionedClient { version: None }),
+    );
+    engine
+        .decide_tiered(
+            "pinned",
+            policy,
+            &json!({"source_id": "x"}),
+            "```external\nhello\n```",
+            &HeaderMap::new(),
+        )
+        .await
+}
+
+fn monitor() -> (ModelVersionMonitor, Arc<RecordingNotifier>) {
+    let notifier = Arc::new(RecordingNotifier::default());
+    (ModelVersionMonitor::new(notifier.clone()), notifier)
+}
+
+#[tokio::test]
+async fn matching_version_keeps_verdict() {
+    let policy = pinned_policy(VersionMismatchHandling::FailClosed);
+    let v = verdict(&policy, Some("gemini-2.0-flash-001")).await;
+    assert_eq!(v.model_version.as_deref(), Some("gemini-2.0-flash-001"));
+
+    let (m, notifier) = monitor();
+    let (d, observed) = m.enforce(
+        "pinned",
+        &policy,
+        v.tier,
+        v.decision,
+        v.model_version.as_deref(),
+    );
+
+    assert!(d.tools_allowed);
+    assert!(matches!(d.action, Action::Allow));
+    assert_eq!(observed.as_deref(), Some("gemini-2.0-flash-001"));
+    assert_eq!(m.mismatch_count("pinned"), 0);
+    assert!(notifier.events.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn mismatch_fails_closed_by_default() {
+    let policy = pinned_policy(VersionMismatchHandling::default());
+    let v = verdict(&policy, Some("gemini-2.0-flash-002")).await;
+
+    let (m, _) = monitor();
+    let (d, observed) = m.enforce(
+        "pinned",
+        &policy,
+        v.tier,
+        v.decision,
+        v.model_version.as_deref(),
+    );
+
+    assert!(!d.tools_allowed);
+    assert!(matches!(d.action, Action::NeedsReview));
+    assert!(d
+        .reasons
+        .iter()
+        .any(|r| r.starts_with("model_version_mismatch:")
+            && r.contains("gemini-2.0-flash-002")
+            && r.contains("gemini-2.0-flash-001")));
+    assert_eq!(observed.as_deref(), Some("gemini-2.0-flash-002"));
+    assert_eq!(m.mismatch_count("pinned"), 1);
+}
+
+#[tokio::test]
+async fn unreported_version_counts_as_mismatch() {
+    let policy = pinned_policy(VersionMismatchHandling::FailClosed);
+    let v = verdict(&policy, None).await;
+
+    let (m, _) = monitor();
+    let (d, observed) = m.enforce("pinned", &policy, v.tier, v.decision, None);
+
+    assert!(!d.tools_allowed);
+    assert!(observed.is_none());
+    assert!(d
+        .reasons
+        .iter()
+        .any(|r| r.starts_with("model_version_mismatch:") && r.contains("unknown")));
+}
+
+#[tokio::test]
+async fn warn_keeps_verdict_and_flags_it() {
+    let policy = pinned_policy(VersionMismatchHandling::Warn);
+    let v = verdict(&policy, Some("gemini-2.0-flash-002")).await;
+
+    let (m, _) = monitor();
+    let (d, _) = m.enforce(
+        "pinned",
+        &policy,
+        v.tier,
+        v.decision,
+        v.model_version.as_deref(),
+    );
+
+    assert!(d.tools_allowed);
+    assert!(matches!(d.action, Action::Allow));
+    assert!(d
+        .reasons
+        .iter()
+        .any(|r| r.starts_with("model_version_unvalidated:")));
+    assert_eq!(m.mismatch_count("pinned"), 1);
+}
+
+#[tokio::test]
+async fn notifies_once_per_provider_version() {
+    let policy = pinned_policy(VersionMismatchHandling::Warn);
+    let (m, notifier) = monitor();
+
+    for version in [
+        "gemini-2.0-flash-002",
+        "gemini-2.0-flash-002",
+        "gemini-2.0-flash-003",
+    ] {
+        let v = verdict(&policy, Some(version)).await;
+        m.enforce("pinned", &policy, v.tier, v.decision, Some(version));
+    }
+
+    let events = notifier.events.lock().unwrap();
+    let observed: Vec<_> = events
+        .iter()
+        .map(|e| e.check.observed.clone().unwrap())
+        .collect();
+    assert_eq!(observed, ["gemini-2.0-flash-002", "gemini-2.0-flash-003"]);
+    assert!(events.iter().all(|e| e.observed_by == ObservedBy::Decision));
+    assert_eq!(m.mismatch_count("pinned"), 3);
+}
+
+#[tokio::test]
+async fn unpinned_model_passes_version_through() {
+    let mut policy = pinned_policy(VersionMismatchHandling::FailClosed);
+    policy.l1.required_model_version = None;
+    let v = verdict(&policy, Some("gemini-2.0-flash-002")).await;
+
+    let (m, _) = monitor();
+    let (d, observed) = m.enforce("pinned", &policy, v.tier, v.decision, Some("x"));
+
+    assert!(d.tools_allowed);
+    assert_eq!(observed.as_deref(), Some("x"));
+    assert_eq!(m.mismatch_count("pinned"), 0);
+}
+
+#[tokio::test]
+async fn startup_probe_reports_mismatch_and_fills_in_unreported_versions() {
+    let policy = pinned_policy(VersionMismatchHandling::FailClosed);
+    let mut policies = BTreeMap::new();
+    policies.insert("pinned".to_string(), policy.clone());
+    let store = PolicyStore::from_file(PoliciesFile { policies });
+
+    let (m, notifier) = monitor();
+    let mismatched = m
+        .self_test(&store, |_| {
+            Box::new(VersionedClient {
+                version: Some("gemini-2.0-flash-002".to_string()),
+            })
+        })
+        .await;
+
+    assert_eq!(mismatched.len(), 1);
+    assert_eq!(mismatched[0].model, "Gemini/gemini-2.0-flash");
+    assert_eq!(
+        notifier.events.lock().unwrap()[0].observed_by,
+        ObservedBy::StartupProbe
+    );
+
+    // A response without version metadata falls back to the probed version.
+    let v = verdict(&policy, None).await;
+    let (d, observed) = m.enforce("pinned", &policy, v.tier, v.decision, None);
+    assert_eq!(observed.as_deref(), Some("gemini-2.0-flash-002"));
+    assert!(!d.tools_allowed);
+    // Same provider and version: no second notification.
+    assert_eq!(notifier.events.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn provenance_carries_model_version_only_when_known() {
+    let policy = pinned_policy(VersionMismatchHandling::Warn);
+
+    let p =
+        serde_json::to_value(Provenance::observed(&policy, Some("gemini-2.0-flash-001"))).unwrap();
+    assert_eq!(p["model_version"], "gemini-2.0-flash-001");
+    assert_eq!(p["l1_model"], "Gemini/gemini-2.0-flash");
+
+    let p = serde_json::to_value(Provenance::observed(&policy, None)).unwrap();
+    assert!(p.get("model_version").is_none());
+}
+
+#[tokio::test]
+async fn notifications_carry_the_origin_and_skip_ingest_loops() {
+    let policy = pinned_policy(VersionMismatchHandling::Warn);
+    let guard = LoopGuard::default();
+    let (m, notifier) = monitor();
+
+    // A looped request still gets the version stage, but notifies nobody.
+    let first = guard.inspect(b"").unwrap();
+    let looped = guard.inspect(first.marker.as_bytes()).unwrap();
+    assert!(!looped.notifications_allowed());
+    let v = verdict(&policy, Some("gemini-2.0-flash-002")).await;
+    let (d, _) = m.enforce_for(
+        Some(&looped),
+        "pinned",
+        &policy,
+        v.tier,
+        v.decision,
+        v.model_version.as_deref(),
+    );
+    assert!(d
+        .reasons
+        .iter()
+        .any(|r| r.starts_with("model_version_unvalidated")));
+    assert_eq!(m.mismatch_count("pinned"), 1);
+    assert!(notifier.events.lock().unwrap().is_empty());
+
+    let v = verdict(&policy, Some("gemini-2.0-flash-002")).await;
+    m.enforce_for(
+        Some(&first),
+        "pinned",
+        &policy,
+        v.tier,
+        v.decision,
+        v.model_version.as_deref(),
+    );
+    let events = notifier.events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].origin.as_deref(), Some(first.marker.as_str()));
+}
+
+#[test]
+fn webhook_urls_pass_the_egress_check() {
+    let hosts = || ["hooks.example".to_string(), "localhost".to_string()].into();
+    let ok = |raw: &str| WebhookNotifier::from_url(egress::client().unwrap(), raw, hosts());
+    assert!(ok("https://hooks.example/model-versions").is_ok());
+    assert!(ok("http://localhost:9000/model-versions").is_ok());
+
+    for (raw, reason) in [
+        (
+            "https://elsewhere.example/hook",
+            "host not in ACIP_EGRESS_HOSTS",
+        ),
+        ("http://hooks.example/hook", "scheme must be https"),
+        (
+            "https://user:pw@hooks.example/hook",
+            "credentials in the URL",
+        ),
+        ("https://10.0.0.5/hook", "IP address hosts are not allowed"),
+    ] {
+        let err = ok(raw)
+            .err()
+            .unwrap_or_else(|| panic!("{raw} was accepted"));
+        let msg = err.to_string();
+        assert!(msg.starts_with("ACIP_MODEL_VERSION_WEBHOOK_URL"), "{msg}");
+        assert!(msg.ends_with(reason), "{raw}: {msg}");
+    }
+}
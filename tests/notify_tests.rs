@@ -0,0 +1,446 @@
+use acip_sidecar::config::NotifyConfig;
+use acip_sidecar::egress::{self, EgressPolicy};
+use acip_sidecar::jobs::CallbackState;
+use acip_sidecar::notify::{
+    CallbackError, CallbackSettings, DeliveryOrdering, Notifier, NotifySettings,
+};
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::{app, incidents, ingest};
+use axum::{body::Body, http::Request, http::StatusCode, routing::post, Json, Router};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tower::ServiceExt;
+use url::Url;
+
+type Received = Arc<Mutex<Vec<Value>>>;
+
+/// A webhook receiver on an ephemeral loopback port. `fail(payload, attempt)` (attempts of one
+/// event, by `seq.global`, count from 1) answers 503 instead of accepting.
+fn receiver(fail: impl Fn(&Value, u32) -> bool + Send + Sync + 'static) -> (Url, Received) {
+    let received = Received::default();
+    let attempts = Arc::new(Mutex::new(HashMap::<u64, u32>::new()));
+    let fail = Arc::new(fail);
+    let got = received.clone();
+    let router = Router::new().route(
+        "/hook",
+        post(move |Json(v): Json<Value>| {
+            let (got, attempts, fail) = (got.clone(), attempts.clone(), fail.clone());
+            async move {
+                let global = v["seq"]["global"].as_u64().unwrap_or_default();
+                let attempt = {
+                    let mut attempts = attempts.lock().unwrap();
+                    let n = attempts.entry(global).or_default();
+                    *n += 1;
+                    *n
+                };
+                if fail(&v, attempt) {
+                    return StatusCode::SERVICE_UNAVAILABLE;
+                }
+                got.lock().unwrap().push(v);
+                StatusCode::OK
+            }
+        }),
+    );
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, router).await.unwrap();
+        });
+    });
+    (format!("http://{addr}/hook").parse().unwrap(), received)
+}
+
+fn notifier(url: Url, ordering: DeliveryOrdering, max_blockage: Duration) -> Arc<Notifier> {
+    let settings = NotifySettings {
+        url: Some(url),
+        ordering,
+        max_blockage,
+        retry_initial: Duration::from_millis(20),
+        spool_capacity: 10,
+        callbacks: None,
+    };
+    Arc::new(Notifier::new(Some(settings), egress::client().unwrap()))
+}
+
+/// Wait until `n` events were delivered or given up on.
+async fn settled(n: &Notifier, events: u64) -> Value {
+    for _ in 0..500 {
+        let s = n.snapshot();
+        let done = s["delivered"].as_u64().unwrap() + s["undeliverable"].as_u64().unwrap();
+        if done >= events && n.queued() == 0 {
+            return s;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("notifications did not settle: {}", n.snapshot());
+}
+
+fn seqs(received: &[Value], key: &str, field: &str) -> Vec<u64> {
+    received
+        .iter()
+        .filter(|v| key.is_empty() || v["key"] == key)
+        .map(|v| v["seq"][field].as_u64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn per_key_delivers_in_order_within_each_key_despite_failures() {
+    // The first event of `a` fails three times before it is accepted.
+    let (url, received) =
+        receiver(|v, attempt| v["key"] == "a" && v["seq"]["key"] == 1 && attempt <= 3);
+    let n = notifier(url, DeliveryOrdering::PerKey, Duration::from_secs(10));
+    for i in 0..3 {
+        n.offer("a", json!({"key": "a", "i": i}), None);
+        n.offer("b", json!({"key": "b", "i": i}), None);
+    }
+    let status = settled(&n, 6).await;
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(seqs(&received, "a", "key"), [1, 2, 3]);
+    assert_eq!(seqs(&received, "b", "key"), [1, 2, 3]);
+    // `b` was not held up by `a`'s retries.
+    let last_b = received.iter().rposition(|v| v["key"] == "b").unwrap();
+    let first_a = received.iter().position(|v| v["key"] == "a").unwrap();
+    assert!(last_b < first_a, "{received:?}");
+    assert_eq!(status["retries"], 3, "{status}");
+    assert_eq!(status["ordering_violations"], 0, "{status}");
+    assert_eq!(status["ordering"], "per_key");
+}
+
+#[tokio::test]
+async fn a_delivery_blocked_past_the_limit_is_spooled_and_the_queue_continues() {
+    let (url, received) = receiver(|v, _| v["seq"]["global"] == 2);
+    let n = notifier(url, DeliveryOrdering::Global, Duration::from_millis(300));
+    n.offer("a", json!({"key": "a"}), None);
+    n.offer("b", json!({"key": "b"}), None);
+    n.offer("a", json!({"key": "a"}), None);
+    let status = settled(&n, 3).await;
+
+    assert_eq!(seqs(&received.lock().unwrap(), "", "global"), [1, 3]);
+    assert_eq!(status["delivered"], 2, "{status}");
+    assert_eq!(status["undeliverable"], 1, "{status}");
+    assert_eq!(status["ordering_violations"], 1, "{status}");
+    assert!(status["retries"].as_u64().unwrap() >= 1, "{status}");
+    let spooled = n.spooled();
+    assert_eq!(spooled.len(), 1);
+    assert_eq!(spooled[0]["seq"], json!({"global": 2, "key": 1}));
+}
+
+#[tokio::test]
+async fn a_redirecting_webhook_is_not_followed() {
+    let (target, received) = receiver(|_, _| false);
+    let moved = Router::new().route(
+        "/hook",
+        post(move || {
+            let target = target.to_string();
+            async move { axum::response::Redirect::temporary(&target) }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, moved).await.unwrap() });
+
+    let url = format!("http://{addr}/hook").parse().unwrap();
+    let n = notifier(url, DeliveryOrdering::None, Duration::from_millis(100));
+    n.offer("a", json!({"key": "a"}), None);
+    let status = settled(&n, 1).await;
+
+    assert!(received.lock().unwrap().is_empty());
+    assert_eq!(status["delivered"], 0, "{status}");
+    assert_eq!(status["undeliverable"], 1, "{status}");
+    let error = status["last_error"].as_str().unwrap();
+    assert!(error.contains("redirect"), "{error}");
+}
+
+#[tokio::test]
+async fn ingest_decisions_carry_gapless_sequence_numbers() {
+    let (url, received) = receiver(|_, _| false);
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    st.notify = notifier(url, DeliveryOrdering::Global, Duration::from_secs(10));
+    let st = Arc::new(st);
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    let app = app::build_router(st.clone(), None, extra);
+
+    let senders = ["alice", "bob", "alice", "alice", "bob"];
+    for (i, source_id) in senders.iter().enumerate() {
+        let body = json!({
+            "source_id": source_id,
+            "source_type": "clipboard",
+            "content_type": "text/plain",
+            "text": format!("Meeting {i} moved to Thursday at 10am."),
+        });
+        let req = Request::post("/v1/acip/ingest_source")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let resp = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    settled(&st.notify, 5).await;
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(seqs(&received, "", "global"), [1, 2, 3, 4, 5]);
+    let alice = st.hashing.export_id("source_id:alice");
+    let bob = st.hashing.export_id("source_id:bob");
+    assert_eq!(seqs(&received, &alice, "key"), [1, 2, 3]);
+    assert_eq!(seqs(&received, &bob, "key"), [1, 2]);
+    for v in &received {
+        assert!(v["request_id"].is_string(), "{v}");
+        assert!(
+            v["action"].is_string() && v["tools_allowed"].is_boolean(),
+            "{v}"
+        );
+    }
+}
+
+#[test]
+fn webhook_urls_are_checked_against_the_host_allowlist() {
+    let cfg = |url: &str| NotifyConfig {
+        webhook_url: Some(url.to_string()),
+        ordering: Some(DeliveryOrdering::PerKey),
+        max_blockage_secs: None,
+        retry_initial_ms: None,
+        spool_capacity: None,
+        per_request_callbacks: None,
+        callbacks_per_minute: None,
+        callback_allow_http: None,
+        callback_allow_ip_literals: None,
+    };
+    let hosts: HashSet<String> = ["hooks.example.com".to_string(), "127.0.0.1".to_string()].into();
+
+    let s = NotifySettings::from_config(&cfg("https://hooks.example.com/acip"), &hosts).unwrap();
+    assert_eq!(s.ordering, DeliveryOrdering::PerKey);
+    assert_eq!(s.max_blockage, Duration::from_secs(300));
+    assert!(NotifySettings::from_config(&cfg("http://127.0.0.1:9000/"), &hosts).is_ok());
+
+    let err = |url: &str| {
+        NotifySettings::from_config(&cfg(url), &hosts)
+            .unwrap_err()
+            .to_string()
+    };
+    assert!(err("http://hooks.example.com/acip").contains("scheme must be https"));
+    assert!(err("https://evil.example.net/").contains("ACIP_EGRESS_HOSTS"));
+    assert!(err("https://user:pw@hooks.example.com/").contains("credentials"));
+    assert!(err("not a url").contains("webhook_url"));
+}
+
+/// Per-request callbacks only (no webhook), to `127.0.0.1` over plain http, `per_minute` per
+/// token.
+fn callbacks_only(per_minute: u32) -> Arc<Notifier> {
+    let settings = NotifySettings {
+        url: None,
+        ordering: DeliveryOrdering::None,
+        max_blockage: Duration::from_secs(10),
+        retry_initial: Duration::from_millis(20),
+        spool_capacity: 10,
+        callbacks: Some(CallbackSettings {
+            per_minute,
+            egress: EgressPolicy {
+                allow_http: true,
+                allow_ip_literals: true,
+                ..EgressPolicy::new(["127.0.0.1".to_string()].into())
+            },
+        }),
+    };
+    Arc::new(Notifier::new(Some(settings), egress::client().unwrap()))
+}
+
+fn ingest_app(notify: Arc<Notifier>) -> (Arc<acip_sidecar::state::AppState>, Router) {
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    st.notify = notify;
+    let st = Arc::new(st);
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    (st.clone(), app::build_router(st, None, extra))
+}
+
+async fn ingest_with(
+    app: &Router,
+    callback_header: Option<&str>,
+    body: Value,
+) -> (StatusCode, Value) {
+    let mut req =
+        Request::post("/v1/acip/ingest_source").header("content-type", "application/json");
+    if let Some(url) = callback_header {
+        req = req.header("x-acip-callback-url", url);
+    }
+    let resp = app
+        .clone()
+        .oneshot(req.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = resp.status();
+    let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn text_body() -> Value {
+    json!({
+        "source_id": "alice",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Lunch is at noon.",
+    })
+}
+
+#[tokio::test]
+async fn a_permitted_callback_receives_the_decision_and_is_traceable() {
+    let (url, received) = receiver(|_, _| false);
+    let (st, app) = ingest_app(callbacks_only(10));
+
+    let (status, body) = ingest_with(&app, Some(url.as_str()), text_body()).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let request_id = body["origin"]["request_id"].as_str().unwrap().to_string();
+    for _ in 0..250 {
+        if st.notify.callback_state(&request_id) == Some(CallbackState::Delivered) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1, "{received:?}");
+    assert_eq!(received[0]["request_id"], request_id.as_str());
+    assert_eq!(received[0]["action"], body["action"]);
+    assert_eq!(received[0]["key"], st.hashing.export_id("source_id:alice"));
+    assert!(received[0].get("seq").is_none(), "{received:?}");
+
+    let incident = incidents::incident(&st, &request_id).unwrap();
+    assert_eq!(incident["webhook"], "delivered", "{incident}");
+    assert_eq!(
+        incident["audit"]["callback_host"], "127.0.0.1",
+        "{incident}"
+    );
+    let status = st.notify.snapshot();
+    assert_eq!(status["callbacks_delivered"], 1, "{status}");
+    assert_eq!(status["delivered"], 0, "{status}");
+    assert_eq!(status["webhook_url"], Value::Null);
+
+    // The body field works the same way.
+    let mut with_field = text_body();
+    with_field["callback_url"] = json!(url.as_str());
+    let (status, _) = ingest_with(&app, None, with_field.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    // Both at once is two callbacks.
+    let (status, body) = ingest_with(&app, Some(url.as_str()), with_field).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "callback_not_allowed");
+    assert_eq!(body["extra"]["reason"], "only one callback per request");
+}
+
+#[tokio::test]
+async fn callback_urls_are_checked_when_the_request_arrives() {
+    let (_, app) = ingest_app(callbacks_only(10));
+    let (status, body) = ingest_with(&app, Some("http://evil.example.net/hook"), text_body()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert_eq!(body["error"], "callback_not_allowed");
+    assert_eq!(body["extra"]["reason"], "host not in ACIP_EGRESS_HOSTS");
+    assert_eq!(
+        body["extra"]["callback_url"],
+        "http://evil.example.net/hook"
+    );
+
+    let strict = NotifySettings::from_config(
+        &NotifyConfig {
+            webhook_url: None,
+            ordering: None,
+            max_blockage_secs: None,
+            retry_initial_ms: None,
+            spool_capacity: None,
+            per_request_callbacks: Some(true),
+            callbacks_per_minute: None,
+            callback_allow_http: None,
+            callback_allow_ip_literals: None,
+        },
+        &["hooks.example.com".to_string(), "10.0.0.5".to_string()].into(),
+    )
+    .unwrap();
+    let n = Notifier::new(Some(strict), reqwest::Client::new());
+    let reason = |url: &str| match n.accept_callback("t", url) {
+        Err(CallbackError::NotAllowed { reason, .. }) => reason,
+        other => panic!("{url}: {other:?}"),
+    };
+    assert!(n
+        .accept_callback("t", "https://hooks.example.com/acip")
+        .is_ok());
+    assert_eq!(
+        reason("http://hooks.example.com/acip"),
+        "scheme must be https"
+    );
+    assert_eq!(
+        reason("https://10.0.0.5/acip"),
+        "IP address hosts are not allowed"
+    );
+    assert_eq!(
+        reason("https://user:pw@hooks.example.com/"),
+        "credentials in the URL"
+    );
+    assert_eq!(reason("not a url"), "invalid URL");
+    assert_eq!(n.snapshot()["callbacks_refused"], 4);
+}
+
+#[tokio::test]
+async fn callbacks_are_refused_unless_enabled() {
+    let (url, received) = receiver(|_, _| false);
+    let (_, app) = ingest_app(notifier(
+        url.clone(),
+        DeliveryOrdering::None,
+        Duration::from_secs(10),
+    ));
+    let (status, body) = ingest_with(&app, Some(url.as_str()), text_body()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert_eq!(body["error"], "callback_not_allowed");
+    assert_eq!(
+        body["extra"]["reason"],
+        "per-request callbacks are disabled"
+    );
+
+    let mut with_field = text_body();
+    with_field["callback_url"] = json!(url.as_str());
+    let (status, body) = ingest_with(&app, None, with_field).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["extra"]["reason"], "requires mode=async");
+    assert!(received.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn callbacks_over_the_per_token_rate_are_refused_with_their_own_code() {
+    let (url, _) = receiver(|_, _| false);
+    let (_, app) = ingest_app(callbacks_only(1));
+    let (status, _) = ingest_with(&app, Some(url.as_str()), text_body()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-callback-url", url.as_str())
+        .body(Body::from(text_body().to_string()))
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().contains_key("retry-after"));
+    let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["error"], "callback_rate_limited");
+    assert_eq!(body["extra"]["per_minute"], 1);
+
+    // Without a callback the request is not limited.
+    let (status, _) = ingest_with(&app, None, text_body()).await;
+    assert_eq!(status, StatusCode::OK);
+}
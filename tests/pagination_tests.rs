@@ -0,0 +1,257 @@
+mod util;
+
+use acip_sidecar::pagination::{paginate, PageParams, MAX_PAGE_SIZE, OFFSET_DEPRECATION};
+use acip_sidecar::reputation::{self, ReputationStore};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, client, policy_store, state};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    Router,
+};
+use serde_json::Value;
+use std::{
+    collections::BTreeSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tower::ServiceExt;
+use util::serve;
+
+fn params(limit: usize, cursor: Option<String>) -> PageParams {
+    PageParams {
+        limit: Some(limit),
+        cursor,
+        offset: None,
+    }
+}
+
+#[test]
+fn cursor_pages_have_no_skips_or_duplicates_while_items_arrive() {
+    // (timestamp, id): many timestamps repeat, so the id breaks ties.
+    let mut store: Vec<(u64, String)> = (0..40).map(|i| (i / 4, format!("orig-{i:02}"))).collect();
+    let originals: BTreeSet<String> = store.iter().map(|(_, id)| id.clone()).collect();
+
+    let mut seen: Vec<String> = vec![];
+    let mut cursor = None;
+    let mut round = 0u64;
+    loop {
+        let page = paginate(store.clone(), "events", |e| e.clone(), &params(7, cursor)).unwrap();
+        seen.extend(page.items.iter().map(|(_, id)| id.clone()));
+
+        // New items land both behind and ahead of the cursor between page requests.
+        round += 1;
+        store.push((0, format!("late-behind-{round}")));
+        store.push((5, format!("late-tie-{round}")));
+        store.push((100 + round, format!("late-ahead-{round}")));
+
+        match page.next_cursor {
+            Some(c) => cursor = Some(c),
+            None => break,
+        }
+    }
+
+    let unique: BTreeSet<String> = seen.iter().cloned().collect();
+    assert_eq!(
+        unique.len(),
+        seen.len(),
+        "duplicates across pages: {seen:?}"
+    );
+    assert!(originals.is_subset(&unique), "an original item was skipped");
+    // Items inserted ahead of the cursor are picked up; those behind it are not revisited.
+    assert!(seen.iter().any(|id| id.starts_with("late-ahead-")));
+    assert!(!seen.iter().any(|id| id.starts_with("late-behind-")));
+}
+
+#[test]
+fn page_size_is_enforced() {
+    let items: Vec<u32> = (0..1000).collect();
+    let p = paginate(items.clone(), "n", |n| *n, &PageParams::default()).unwrap();
+    assert_eq!(p.items.len(), 50);
+    assert_eq!(p.total_estimate, 1000);
+
+    let p = paginate(items.clone(), "n", |n| *n, &params(MAX_PAGE_SIZE, None)).unwrap();
+    assert_eq!(p.items.len(), MAX_PAGE_SIZE);
+
+    for limit in [0, MAX_PAGE_SIZE + 1] {
+        assert!(paginate(items.clone(), "n", |n| *n, &params(limit, None)).is_err());
+    }
+}
+
+#[test]
+fn last_page_has_no_cursor() {
+    let p = paginate(vec![3, 1, 2], "n", |n| *n, &params(3, None)).unwrap();
+    assert_eq!(p.items, vec![1, 2, 3]);
+    assert!(p.next_cursor.is_none());
+    assert!(p.deprecation.is_none());
+}
+
+fn test_state(extra_policies: &[&str]) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let mut policies = std::collections::BTreeMap::new();
+    for name in std::iter::once(&"default").chain(extra_policies) {
+        policies.insert(
+            name.to_string(),
+            acip_sidecar::model_policy::PolicyConfig::default(),
+        );
+    }
+
+    AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .build()
+        .unwrap()
+}
+
+fn observe(store: &dyn ReputationStore, source_id: &str) {
+    store.record(reputation::observation(
+        source_id.to_string(),
+        None,
+        0,
+        vec![],
+    ));
+}
+
+async fn get(app: &Router, uri: &str) -> (StatusCode, Value) {
+    let resp = app
+        .clone()
+        .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+fn keys(v: &Value) -> Vec<String> {
+    v["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["key"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn reputation_listing_is_cursor_stable_over_http() {
+    let st = test_state(&[]);
+    for i in 0..9 {
+        observe(st.reputation.as_ref(), &format!("s{}", i * 2));
+    }
+    let app = app::build_router(st.clone(), None, Router::new());
+
+    let mut seen = vec![];
+    let base = "/v1/acip/reputation/records?prefix=source_id:&limit=4";
+    let mut uri = base.to_string();
+    for round in 0.. {
+        let (status, v) = get(&app, &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        seen.extend(keys(&v));
+        // Odd ids sort between existing ones, on both sides of the cursor.
+        observe(st.reputation.as_ref(), &format!("s{}", round * 4 + 1));
+        match v["next_cursor"].as_str() {
+            Some(c) => uri = format!("{base}&cursor={c}"),
+            None => break,
+        }
+    }
+
+    let unique: BTreeSet<&String> = seen.iter().collect();
+    assert_eq!(unique.len(), seen.len(), "{seen:?}");
+    for i in 0..9 {
+        assert!(seen.contains(&format!("source_id:s{}", i * 2)), "{seen:?}");
+    }
+    let mut sorted = seen.clone();
+    sorted.sort();
+    assert_eq!(seen, sorted);
+}
+
+#[tokio::test]
+async fn limits_cursors_and_deprecated_offset_over_http() {
+    let app = app::build_router(test_state(&["a", "b", "c"]), None, Router::new());
+
+    let (status, v) = get(
+        &app,
+        &format!("/v1/acip/policies?limit={}", MAX_PAGE_SIZE + 1),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "invalid_request_field");
+    assert_eq!(v["extra"]["field"], "limit");
+    assert_eq!(v["extra"]["max"], MAX_PAGE_SIZE);
+
+    let (status, v) = get(&app, "/v1/acip/policies?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["items"], serde_json::json!(["a", "b"]));
+    assert_eq!(v["total_estimate"], 4);
+    assert!(v.get("deprecation").is_none());
+    let cursor = v["next_cursor"].as_str().unwrap().to_string();
+
+    // Offset still works for now, flagged as deprecated.
+    let (status, v) = get(&app, "/v1/acip/policies?limit=2&offset=2").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["items"], serde_json::json!(["c", "default"]));
+    assert_eq!(v["deprecation"], OFFSET_DEPRECATION);
+
+    let (status, _) = get(&app, &format!("/v1/acip/policies?cursor={cursor}&offset=1")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = get(&app, "/v1/acip/policies?cursor=not-a-cursor").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    // A cursor only works for the listing that issued it.
+    let (status, v) = get(
+        &app,
+        &format!("/v1/acip/reputation/records?cursor={cursor}"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["extra"]["field"], "cursor");
+}
+
+#[test]
+fn client_paginate_drains_three_pages_lazily() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let router = app::build_router(
+        test_state(&["p1", "p2", "p3", "p4", "p5"]),
+        None,
+        Router::new(),
+    )
+    .layer(middleware::from_fn(move |req: Request, next: Next| {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            next.run(req).await
+        }
+    }));
+    let addr = serve(router);
+    let c = client::Client::new(&format!("http://{addr}"), None);
+
+    let names: Vec<String> = c
+        .paginate("/v1/acip/policies", &[], 2)
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(names, vec!["default", "p1", "p2", "p3", "p4", "p5"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    requests.store(0, Ordering::SeqCst);
+    let first: Vec<String> = c
+        .paginate("/v1/acip/policies", &[], 2)
+        .take(2)
+        .collect::<anyhow::Result<_>>()
+        .unwrap();
+    assert_eq!(first, vec!["default", "p1"]);
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    let err = c
+        .paginate::<String>("/v1/acip/policies", &[("cursor", "bogus".into())], 2)
+        .next()
+        .unwrap();
+    assert!(err.is_err());
+}
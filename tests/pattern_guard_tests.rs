@@ -0,0 +1,271 @@
+use acip_sidecar::config::{Config, PatternConfig, RegexConfig};
+use acip_sidecar::patterns::{PatternPack, PACK_BUDGET_INDICATOR};
+use acip_sidecar::regex_guard::{self, RegexLimits, RegexRejected};
+use acip_sidecar::reputation::SystemClock;
+use acip_sidecar::threat::{AttackType, ThreatAssessment};
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn pattern(id: &str, regex: &str) -> PatternConfig {
+    PatternConfig {
+        id: id.to_string(),
+        regex: regex.to_string(),
+        attack_type: AttackType::DataExfiltration,
+        score: None,
+    }
+}
+
+/// Non-ASCII words: Unicode `\b` keeps the lazy DFA off, so the scan falls back to a slow engine.
+fn unicode_words(bytes: usize) -> String {
+    "слово данные ".repeat(bytes / "слово данные ".len())
+}
+
+/// Has no literal a prefilter could skip ahead on, and never matches [`unicode_words`].
+const SLOW: &str = r"\b\w+\b\s+\b\w+\b\s+\b\d{3}\b";
+
+#[test]
+fn huge_pattern_is_rejected_at_validation_with_its_size() {
+    let limits = RegexLimits {
+        size_limit: 64 * 1024 * 1024,
+        ..RegexLimits::default()
+    };
+    match regex_guard::compile("patterns.huge", r"\w{300}", &limits) {
+        Err(RegexRejected::OverBudget {
+            label,
+            size,
+            budget,
+        }) => {
+            assert_eq!(label, "patterns.huge");
+            assert_eq!(budget, limits.compiled_budget);
+            assert!(size > budget, "{size}");
+        }
+        other => panic!("unexpected: {other:?}"),
+    }
+
+    let cfg: Config = toml::from_str(
+        r#"
+[regex]
+size_limit_bytes = 67108864
+
+[[patterns]]
+id = "huge"
+regex = '\w{300}'
+attack_type = "data_exfiltration"
+"#,
+    )
+    .unwrap();
+    let err = cfg.validate().unwrap_err().to_string();
+    assert!(err.contains("patterns.huge: compiled size"), "{err}");
+    assert!(err.contains("exceeds the budget of 1048576 bytes"), "{err}");
+
+    // Under the default 4 MiB hard limit it is refused one way or the other.
+    let err = regex_guard::compile("p", r"\w{300}", &RegexLimits::default()).unwrap_err();
+    assert!(
+        matches!(
+            err,
+            RegexRejected::OverLimit { .. } | RegexRejected::OverBudget { .. }
+        ),
+        "{err}"
+    );
+
+    let nested = format!("{}a{}", "(".repeat(100), ")".repeat(100));
+    let err = regex_guard::compile("p", &nested, &RegexLimits::default()).unwrap_err();
+    assert!(matches!(err, RegexRejected::Invalid { .. }), "{err}");
+
+    let ok = regex_guard::compile("p", r"(?i)wire\s+transfer", &RegexLimits::default()).unwrap();
+    assert!(ok.compiled_bytes > 0 && ok.compiled_bytes < 1024 * 1024);
+}
+
+#[test]
+fn oversized_redaction_regex_is_refused() {
+    let cfg: Config = toml::from_str(
+        r#"
+[[redaction.rules]]
+label = "huge"
+regex = '\w{300}'
+"#,
+    )
+    .unwrap();
+    let err = acip_sidecar::redact::Redaction::default()
+        .reload_with_limits(
+            cfg.redaction.as_ref(),
+            &RegexLimits::from_config(cfg.regex.as_ref()),
+        )
+        .unwrap_err();
+    assert!(err.to_string().contains("redaction rule 0 (huge)"), "{err}");
+}
+
+#[test]
+fn matches_add_user_pattern_indicators() {
+    let pack = PatternPack::compile(
+        &[pattern("wire", r"(?i)wire\s+\$?\d+\s+to")],
+        &RegexLimits::default(),
+    )
+    .unwrap();
+    let mut a = ThreatAssessment::none();
+    pack.scan("Please WIRE $4000 to the account below.", &mut a);
+    assert_eq!(a.indicators, vec!["user_pattern:wire".to_string()]);
+    assert_eq!(a.attack_types, vec![AttackType::DataExfiltration]);
+    assert_eq!(a.threat_score, 3);
+
+    let err = PatternPack::compile(&[pattern("any", "x*")], &RegexLimits::default())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("empty string"), "{err}");
+    let err = PatternPack::compile(
+        &[pattern("a", "x"), pattern("a", "y")],
+        &RegexLimits::default(),
+    )
+    .err()
+    .unwrap();
+    assert!(err.to_string().contains("duplicate id"), "{err}");
+}
+
+#[test]
+fn slow_pattern_is_demoted_after_the_strike_count() {
+    let limits = RegexLimits::from_config(Some(&RegexConfig {
+        pattern_scan_budget_us: Some(1000),
+        pack_scan_budget_us: Some(60_000_000),
+        demote_after_strikes: Some(3),
+        ..RegexConfig::default()
+    }));
+    let pack = PatternPack::new(
+        &[pattern("fast", "^zzz"), pattern("slow", SLOW)],
+        &limits,
+        Arc::new(SystemClock),
+    )
+    .unwrap();
+    let text = unicode_words(256 * 1024);
+
+    for round in 1..=2 {
+        pack.scan(&text, &mut ThreatAssessment::none());
+        assert_eq!(pack.strikes("slow"), Some(round));
+        assert_eq!(pack.is_enabled("slow"), Some(true));
+    }
+    pack.scan(&text, &mut ThreatAssessment::none());
+    assert_eq!(pack.is_enabled("slow"), Some(false));
+    assert_eq!(pack.is_enabled("fast"), Some(true));
+    assert_eq!(pack.strikes("fast"), Some(0));
+
+    let journal = pack.journal();
+    assert_eq!(journal.len(), 1);
+    assert_eq!(journal[0].pattern, "slow");
+    assert_eq!(journal[0].change, "demoted");
+
+    // Disabled patterns no longer run.
+    pack.scan(&text, &mut ThreatAssessment::none());
+    let snap = pack.snapshot();
+    let slow = &snap["patterns"][1];
+    assert_eq!(slow["scans"], 3);
+    assert_eq!(slow["enabled"], false);
+    assert_eq!(snap["enabled"], 1);
+    assert_eq!(
+        snap["warnings"][0],
+        "pattern slow disabled after 3 scans over budget"
+    );
+}
+
+#[test]
+fn pack_budget_skips_the_remaining_patterns() {
+    let limits = RegexLimits {
+        pattern_scan_budget: Duration::from_secs(60),
+        pack_scan_budget: Duration::from_micros(1),
+        ..RegexLimits::default()
+    };
+    let pack = PatternPack::new(
+        &[pattern("slow", SLOW), pattern("later", "^zzz")],
+        &limits,
+        Arc::new(SystemClock),
+    )
+    .unwrap();
+    let mut a = ThreatAssessment::none();
+    pack.scan(&unicode_words(64 * 1024), &mut a);
+    assert_eq!(a.indicators, vec![PACK_BUDGET_INDICATOR.to_string()]);
+    let snap = pack.snapshot();
+    assert_eq!(snap["pack_budget_overruns"], 1);
+    assert_eq!(snap["patterns"][1]["scans"], 0);
+}
+
+fn lint(toml: &str, json: bool) -> (Option<i32>, String) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("patterns.toml");
+    std::fs::write(&path, toml).unwrap();
+    let mut cmd = cargo_bin_cmd!("acipctl");
+    cmd.args(["patterns", "lint"]).arg(&path);
+    if json {
+        cmd.arg("--json");
+    }
+    let out = cmd.output().unwrap();
+    (out.status.code(), String::from_utf8(out.stdout).unwrap())
+}
+
+#[test]
+fn lint_reports_sizes_and_timings() {
+    let (code, stdout) = lint(
+        r#"
+[regex]
+pattern_scan_budget_us = 5000000
+
+[[patterns]]
+id = "wire"
+regex = '(?i)wire\s+\$?\d+\s+to'
+attack_type = "data_exfiltration"
+
+[[patterns]]
+id = "huge"
+regex = '\w{300}'
+attack_type = "data_exfiltration"
+
+[[redaction.rules]]
+label = "acct"
+regex = '\bACCT-\d{8}\b'
+"#,
+        true,
+    );
+    assert_eq!(code, Some(1), "a rejected pattern fails the lint");
+    let reports: Vec<Value> = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(reports.len(), 3);
+
+    let wire = &reports[0];
+    assert_eq!(wire["label"], "patterns.wire");
+    assert_eq!(wire["status"], "ok");
+    assert!(wire["compiled_bytes"].as_u64().unwrap() > 0);
+    // A literal-led pattern stays far below the budget on every input, even unoptimised.
+    assert!(wire["slowest_us"].as_u64().unwrap() < 500_000, "{wire}");
+    assert!(wire["slowest_input"].is_string());
+
+    assert_eq!(reports[1]["label"], "patterns.huge");
+    assert_eq!(reports[1]["status"], "rejected");
+    assert!(reports[1]["error"].as_str().unwrap().contains("bytes"));
+
+    assert_eq!(reports[2]["label"], "redaction.acct");
+    assert_eq!(reports[2]["status"], "ok");
+}
+
+#[test]
+fn lint_flags_patterns_over_the_scan_budget() {
+    let pack = r#"
+[[patterns]]
+id = "words"
+regex = '\b\w+\b\s+\b\w+\b\s+\b\d{3}\b'
+attack_type = "prompt_injection"
+"#;
+    let (code, stdout) = lint(
+        &format!("[regex]\npattern_scan_budget_us = 1\n{pack}"),
+        false,
+    );
+    assert_eq!(code, Some(1));
+    assert!(
+        stdout.starts_with("patterns.words status=slow size="),
+        "{stdout}"
+    );
+
+    let (code, stdout) = lint(
+        &format!("[regex]\npattern_scan_budget_us = 60000000\n{pack}"),
+        false,
+    );
+    assert_eq!(code, Some(0), "{stdout}");
+    assert!(stdout.contains("status=ok"), "{stdout}");
+}
@@ -0,0 +1,143 @@
+use acip_sidecar::model_policy::Provider;
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+
+const BASE: &str = r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash"},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"}
+    },
+    "strict": {
+      "extends": "default",
+      "l2": {"model": "claude-3-5-sonnet"}
+    },
+    "stricter": {
+      "extends": "strict",
+      "l1": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"}
+    },
+    "standalone": {
+      "l1": {"provider": "gemini", "model": "g"},
+      "l2": {"provider": "gemini", "model": "g"}
+    }
+  }
+}"#;
+
+fn store(raw: &str) -> PolicyStore {
+    PolicyStore::from_declared(DeclaredPolicies::parse(raw).unwrap()).unwrap()
+}
+
+fn resolve_err(raw: &str) -> String {
+    let declared = DeclaredPolicies::parse(raw);
+    let err = match declared {
+        Ok(d) => PolicyStore::from_declared(d).err().unwrap(),
+        Err(e) => e,
+    };
+    format!("{err:#}")
+}
+
+#[test]
+fn child_inherits_unspecified_fields_field_by_field() {
+    let s = store(BASE);
+    let strict = s.get("strict").unwrap();
+
+    // l1 entirely inherited.
+    assert_eq!(strict.l1.provider, Provider::Gemini);
+    assert_eq!(strict.l1.model, "gemini-2.0-flash");
+    // l2.model overridden, l2.provider inherited.
+    assert_eq!(strict.l2.provider, Provider::Anthropic);
+    assert_eq!(strict.l2.model, "claude-3-5-sonnet");
+}
+
+#[test]
+fn multi_level_chain_resolves_nearest_ancestor_first() {
+    let s = store(BASE);
+    let p = s.get("stricter").unwrap();
+
+    assert_eq!(p.l1.provider, Provider::Anthropic);
+    assert_eq!(p.l2.model, "claude-3-5-sonnet");
+    assert_eq!(s.chain("stricter"), vec!["stricter", "strict", "default"]);
+}
+
+#[test]
+fn declared_form_stays_sparse() {
+    let s = store(BASE);
+    let d = s.declared("strict").unwrap();
+
+    assert_eq!(d.extends.as_deref(), Some("default"));
+    assert!(d.l1.is_none());
+    assert!(d.l2.as_ref().unwrap().provider.is_none());
+}
+
+#[test]
+fn unknown_parent_is_rejected() {
+    let err = resolve_err(
+        r#"{"policies": {
+            "default": {"l1": {"provider": "gemini", "model": "a"}, "l2": {"provider": "gemini", "model": "b"}},
+            "x": {"extends": "nope"}
+        }}"#,
+    );
+    assert!(err.contains("extends unknown policy 'nope'"), "{err}");
+}
+
+#[test]
+fn cycles_are_rejected() {
+    let err = resolve_err(
+        r#"{"policies": {
+            "default": {"l1": {"provider": "gemini", "model": "a"}, "l2": {"provider": "gemini", "model": "b"}},
+            "a": {"extends": "b"},
+            "b": {"extends": "a"}
+        }}"#,
+    );
+    assert!(err.contains("cycle"), "{err}");
+}
+
+#[test]
+fn overlong_chains_are_rejected() {
+    let err = resolve_err(
+        r#"{"policies": {
+            "default": {"l1": {"provider": "gemini", "model": "a"}, "l2": {"provider": "gemini", "model": "b"}},
+            "p1": {"extends": "default"},
+            "p2": {"extends": "p1"},
+            "p3": {"extends": "p2"},
+            "p4": {"extends": "p3"}
+        }}"#,
+    );
+    assert!(err.contains("exceeds"), "{err}");
+}
+
+#[test]
+fn name_field_cannot_be_declared() {
+    let err = resolve_err(
+        r#"{"policies": {
+            "default": {"l1": {"provider": "gemini", "model": "a"}, "l2": {"provider": "gemini", "model": "b"}},
+            "x": {"extends": "default", "name": "y"}
+        }}"#,
+    );
+    assert!(
+        err.contains("'name' cannot be declared or inherited"),
+        "{err}"
+    );
+}
+
+#[test]
+fn root_policy_missing_fields_is_rejected() {
+    let err = resolve_err(r#"{"policies": {"default": {"l1": {"provider": "gemini"}}}}"#);
+    assert!(err.contains("l1.model"), "{err}");
+}
+
+#[test]
+fn parent_edit_changes_child_revisions_only() {
+    let before = store(BASE);
+    let edited = BASE.replace("gemini-2.0-flash", "gemini-2.5-flash");
+    let after = store(&edited);
+
+    for child in ["default", "strict", "stricter"] {
+        assert_ne!(
+            before.revision(child),
+            after.revision(child),
+            "revision for {child} should change"
+        );
+    }
+    assert_eq!(before.revision("standalone"), after.revision("standalone"));
+    assert!(before.revision("missing").is_none());
+}
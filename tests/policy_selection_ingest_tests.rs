@@ -1,6 +1,5 @@
 use axum::{body::Body, http::Request, http::StatusCode, Router};
-use acip_sidecar::{policy_store, secrets, state};
-use std::sync::Arc;
+use acip_sidecar::{app_state_builder::AppStateBuilder, policy_store, state};
 use tower::ServiceExt;
 
 fn app() -> Router {
@@ -14,18 +13,17 @@ fn app() -> Router {
         acip_sidecar::model_policy::PolicyConfig::default(),
     );
 
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
+    let st = AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .with_policy(state::Policy {
             head: 4,
             tail: 4,
             full_if_lte: 6,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(acip_sidecar::reputation::InMemoryReputationStore::new()),
-    });
+        })
+        .build()
+        .unwrap();
 
     // Reuse the ingest handler from main.rs logic isn't possible here, so we just verify
     // the policy selection helper behavior via /v1/acip/policy.
@@ -0,0 +1,695 @@
+use acip_sidecar::config::ProbingConfig;
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::probing::{
+    is_private_address, Mechanism, ProbeSettings, Prober, Refusal, TlsOutcome,
+};
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::threat::{ScanStage, ThreatAssessment};
+use acip_sidecar::url_allowlist::UrlRef;
+use acip_sidecar::{app, state};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{any, get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio_rustls::rustls::{
+    self,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+};
+use tower::ServiceExt;
+
+const RESOLVED: &[&str] = &[
+    "good.test",
+    "www.good.test",
+    "fresh.test",
+    "gone.test",
+    "mismatch.test",
+    "other.test",
+    "a.test",
+    "b.test",
+    "c.test",
+    "d.test",
+    "e.test",
+];
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/probing")
+        .join(name)
+}
+
+/// A DNS server answering 127.0.0.1 for [`RESOLVED`] and NXDOMAIN otherwise, counting queries.
+async fn dns_server() -> (SocketAddr, Arc<Mutex<HashMap<String, usize>>>) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let queries = Arc::new(Mutex::new(HashMap::new()));
+    let seen = queries.clone();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 512];
+        loop {
+            let (n, from) = socket.recv_from(&mut buf).await.unwrap();
+            let q = &buf[..n];
+            let (mut pos, mut labels) = (12, vec![]);
+            while q[pos] != 0 {
+                let len = q[pos] as usize;
+                labels.push(String::from_utf8_lossy(&q[pos + 1..pos + 1 + len]).to_string());
+                pos += 1 + len;
+            }
+            let question_end = pos + 5;
+            let name = labels.join(".");
+            *seen.lock().unwrap().entry(name.clone()).or_insert(0) += 1;
+            let known = RESOLVED.contains(&name.as_str());
+            let mut r = q[..2].to_vec();
+            r.extend_from_slice(if known { &[0x81, 0x80] } else { &[0x81, 0x83] });
+            r.extend_from_slice(&[0, 1, 0, known as u8, 0, 0, 0, 0]);
+            r.extend_from_slice(&q[12..question_end]);
+            if known {
+                r.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+            }
+            socket.send_to(&r, from).await.unwrap();
+        }
+    });
+    (addr, queries)
+}
+
+/// RDAP: `fresh.test` was registered three days ago, `gone.test` is unknown, the rest in 2001.
+async fn rdap_server() -> (String, Arc<Mutex<Vec<String>>>) {
+    let asked = Arc::new(Mutex::new(vec![]));
+    let app = Router::new()
+        .route(
+            "/rdap/domain/:name",
+            get(
+                |State(asked): State<Arc<Mutex<Vec<String>>>>,
+                 axum::extract::Path(name): axum::extract::Path<String>| async move {
+                    asked.lock().unwrap().push(name.clone());
+                    let date = match name.as_str() {
+                        "gone.test" => return StatusCode::NOT_FOUND.into_response(),
+                        "fresh.test" => {
+                            (time::OffsetDateTime::now_utc() - time::Duration::days(3)).date()
+                        }
+                        _ => time::macros::date!(2001 - 05 - 01),
+                    };
+                    axum::Json(json!({
+                        "objectClassName": "domain",
+                        "ldhName": name,
+                        "events": [
+                            {"eventAction": "last changed", "eventDate": "2024-01-01T00:00:00Z"},
+                            {"eventAction": "registration", "eventDate": format!("{date}T12:00:00Z")},
+                        ],
+                    }))
+                    .into_response()
+                },
+            ),
+        )
+        .with_state(asked.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}/rdap"), asked)
+}
+
+/// One request the HTTPS fixture saw: method, host, path, body bytes.
+type Seen = (String, String, String, usize);
+
+async fn site(State(seen): State<Arc<Mutex<Vec<Seen>>>>, req: Request) -> Response {
+    let (parts, body) = req.into_parts();
+    let host = parts.headers[header::HOST].to_str().unwrap().to_string();
+    let port = host.rsplit(':').next().unwrap().to_string();
+    let path = parts.uri.path().to_string();
+    let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    seen.lock()
+        .unwrap()
+        .push((parts.method.to_string(), host, path.clone(), body.len()));
+    let redirect = |to: String| (StatusCode::FOUND, [(header::LOCATION, to)]).into_response();
+    if let Some(n) = path.strip_prefix("/hop/") {
+        let n: u32 = n.parse().unwrap();
+        return redirect(format!("/hop/{}", n + 1));
+    }
+    match path.as_str() {
+        "/away" => redirect(format!("https://other.test:{port}/")),
+        "/www" => redirect(format!("https://www.good.test:{port}/landing")),
+        _ => (
+            [
+                (header::CONTENT_TYPE, "text/html"),
+                (header::CONTENT_LENGTH, "5120"),
+            ],
+            Body::empty(),
+        )
+            .into_response(),
+    }
+}
+
+/// HTTPS on 127.0.0.1 with a certificate for `good.test` and `www.good.test` only.
+async fn https_server() -> (u16, Arc<Mutex<Vec<Seen>>>) {
+    let certs: Vec<CertificateDer<'static>> = CertificateDer::pem_file_iter(fixture("leaf.pem"))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let key = PrivateKeyDer::from_pem_file(fixture("leaf.key")).unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+    let seen = Arc::new(Mutex::new(vec![]));
+    let app = Router::new().fallback(any(site)).with_state(seen.clone());
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+        use hyper_util::server::conn::auto::Builder as ConnBuilder;
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let (acceptor, app) = (acceptor.clone(), app.clone());
+            tokio::spawn(async move {
+                // Handshakes the probes abort (name mismatch) end here.
+                let Ok(tls) = acceptor.accept(tcp).await else {
+                    return;
+                };
+                let svc = hyper::service::service_fn(
+                    move |req: hyper::Request<hyper::body::Incoming>| {
+                        app.clone().oneshot(req.map(Body::new))
+                    },
+                );
+                let _ = ConnBuilder::new(TokioExecutor::new())
+                    .serve_connection(TokioIo::new(tls), svc)
+                    .await;
+            });
+        }
+    });
+    (port, seen)
+}
+
+struct Fixtures {
+    dns: SocketAddr,
+    dns_queries: Arc<Mutex<HashMap<String, usize>>>,
+    rdap: String,
+    rdap_asked: Arc<Mutex<Vec<String>>>,
+    port: u16,
+    seen: Arc<Mutex<Vec<Seen>>>,
+}
+
+impl Fixtures {
+    async fn start() -> Self {
+        let (dns, dns_queries) = dns_server().await;
+        let (rdap, rdap_asked) = rdap_server().await;
+        let (port, seen) = https_server().await;
+        Self {
+            dns,
+            dns_queries,
+            rdap,
+            rdap_asked,
+            port,
+            seen,
+        }
+    }
+
+    fn config(&self) -> ProbingConfig {
+        ProbingConfig {
+            enabled: Some(true),
+            rdap_endpoint: Some(self.rdap.clone()),
+            dns_server: Some(self.dns.to_string()),
+            tls_ca_file: Some(fixture("ca.pem").to_str().unwrap().to_string()),
+            allow_private_addresses: Some(true),
+            ..ProbingConfig::default()
+        }
+    }
+
+    fn prober(&self, cfg: ProbingConfig) -> Prober {
+        Prober::new(ProbeSettings::from_config(Some(&cfg)).unwrap())
+    }
+
+    fn url(&self, host: &str, path: &str) -> String {
+        format!("https://{host}:{}{path}", self.port)
+    }
+
+    fn dns_queries(&self, name: &str) -> usize {
+        self.dns_queries
+            .lock()
+            .unwrap()
+            .get(name)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+fn indicators(threat: &ThreatAssessment) -> Vec<&str> {
+    threat.indicators.iter().map(String::as_str).collect()
+}
+
+#[tokio::test]
+async fn each_finding_becomes_a_probe_pattern() {
+    let fx = Fixtures::start().await;
+    let prober = fx.prober(fx.config());
+    let text = format!(
+        "Verify your password at {} or {}, see {} and {}; the old page {} is offline. \
+         Nothing wrong with {}.",
+        fx.url("fresh.test", "/"),
+        fx.url("mismatch.test", "/"),
+        fx.url("good.test", "/away"),
+        fx.url("nowhere.test", "/"),
+        fx.url("gone.test", "/"),
+        fx.url("good.test", "/ignored-second-link"),
+    );
+    let mut threat = ThreatAssessment::none();
+    let reports = prober.probe_document(&text, &mut threat).await;
+    assert_eq!(reports.len(), 5);
+
+    let found = indicators(&threat);
+    for expected in [
+        "probe:recently_registered:fresh.test",
+        "probe:cert_mismatch:mismatch.test",
+        "probe:cross_domain_redirect:good.test",
+        "probe:nonexistent_domain:nowhere.test",
+        "probe:nonexistent_domain:gone.test",
+    ] {
+        assert!(found.contains(&expected), "{expected} not in {found:?}");
+    }
+    assert!(
+        !found.contains(&"probe:cert_mismatch:good.test"),
+        "{found:?}"
+    );
+    assert!(
+        !found.contains(&"probe:recently_registered:good.test"),
+        "{found:?}"
+    );
+    let scores: u32 = reports
+        .iter()
+        .flat_map(|r| &r.findings)
+        .map(|f| u32::from(f.score()))
+        .sum();
+    assert_eq!(u32::from(threat.threat_score), scores);
+    assert!(threat.detected.iter().all(|d| d.stage == ScanStage::Probe));
+
+    let good = reports.iter().find(|r| r.host == "good.test").unwrap();
+    assert_eq!(good.tls, Some(TlsOutcome::Valid));
+    assert_eq!(good.registered_days_ago.map(|d| d > 365), Some(true));
+    assert_eq!(good.head[0].status, 302);
+    // The probe stops at the other registrable domain.
+    assert_eq!(good.head.len(), 1);
+    assert!(!fx
+        .seen
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|s| s.1.starts_with("other.test")));
+    let mismatch = reports.iter().find(|r| r.host == "mismatch.test").unwrap();
+    assert!(
+        matches!(&mismatch.tls, Some(TlsOutcome::NameMismatch { presented }) if presented.contains(&"good.test".to_string())),
+        "{:?}",
+        mismatch.tls
+    );
+    // An NXDOMAIN host is not asked about anywhere else.
+    assert!(!fx
+        .rdap_asked
+        .lock()
+        .unwrap()
+        .contains(&"nowhere.test".to_string()));
+}
+
+#[tokio::test]
+async fn head_probes_carry_no_body_and_follow_at_most_max_redirects() {
+    let fx = Fixtures::start().await;
+    let prober = fx.prober(ProbingConfig {
+        max_redirects: Some(2),
+        ..fx.config()
+    });
+    let mut threat = ThreatAssessment::none();
+    let reports = prober
+        .probe_document(&fx.url("good.test", "/hop/0"), &mut threat)
+        .await;
+    assert_eq!(reports[0].head.len(), 3);
+    assert!(reports[0].redirects_truncated);
+    let seen = fx.seen.lock().unwrap().clone();
+    let paths: Vec<&str> = seen.iter().map(|s| s.2.as_str()).collect();
+    assert_eq!(paths, ["/hop/0", "/hop/1", "/hop/2"]);
+
+    // A redirect to another host of the same registrable domain is followed after resolving it.
+    let prober = fx.prober(fx.config());
+    let reports = prober
+        .probe_document(&fx.url("good.test", "/www"), &mut threat)
+        .await;
+    let hops = &reports[0].head;
+    assert_eq!(hops.len(), 2);
+    assert_eq!(hops[1].host, "www.good.test");
+    assert_eq!(hops[1].content_type.as_deref(), Some("text/html"));
+    assert_eq!(hops[1].content_length, Some(5120));
+    assert!(reports[0].findings.is_empty(), "{:?}", reports[0]);
+    assert_eq!(fx.dns_queries("www.good.test"), 1);
+
+    let seen = fx.seen.lock().unwrap().clone();
+    assert_eq!(seen.len(), 5);
+    assert!(
+        seen.iter()
+            .all(|(method, _, _, body)| method == "HEAD" && *body == 0),
+        "{seen:?}"
+    );
+}
+
+#[tokio::test]
+async fn documents_probe_at_most_max_urls_and_skip_allowlisted_hosts() {
+    let fx = Fixtures::start().await;
+    let prober = fx.prober(ProbingConfig {
+        max_urls_per_document: Some(3),
+        ..fx.config()
+    });
+    let hosts = [
+        "good.test",
+        "a.test",
+        "b.test",
+        "c.test",
+        "d.test",
+        "e.test",
+    ];
+    let text = hosts
+        .iter()
+        .map(|h| fx.url(h, "/"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut threat = ThreatAssessment::none();
+    threat.urls.push(UrlRef {
+        host: "good.test".to_string(),
+        allowlisted: true,
+        allowlisted_by: Some("inline".to_string()),
+    });
+    let reports = prober.probe_document(&text, &mut threat).await;
+    let probed: Vec<&str> = reports.iter().map(|r| r.host.as_str()).collect();
+    assert_eq!(probed, ["a.test", "b.test", "c.test"]);
+    for h in hosts {
+        assert_eq!(fx.dns_queries(h), probed.contains(&h) as usize, "{h}");
+    }
+    let status = prober.snapshot();
+    assert_eq!(status["hosts_over_cap"], 2);
+    assert_eq!(status["hosts_probed"], 3);
+}
+
+#[tokio::test]
+async fn results_are_cached_per_host_until_the_ttl_runs_out() {
+    let fx = Fixtures::start().await;
+    let now = time::OffsetDateTime::now_utc().unix_timestamp() as u64;
+    let clock = Arc::new(MockClock::new(now));
+    let prober = fx
+        .prober(ProbingConfig {
+            cache_ttl_secs: Some(600),
+            ..fx.config()
+        })
+        .with_clock(clock.clone());
+    let probe = |text: String| {
+        let prober = &prober;
+        async move {
+            let mut threat = ThreatAssessment::none();
+            prober.probe_document(&text, &mut threat).await;
+            indicators(&threat)
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    let first = probe(fx.url("good.test", "/away")).await;
+    // Another link to the same host, in another document.
+    let second = probe(format!("see {}", fx.url("good.test", "/elsewhere"))).await;
+    assert_eq!(first, second);
+    assert_eq!(first, ["probe:cross_domain_redirect:good.test"]);
+    assert_eq!(fx.dns_queries("good.test"), 1);
+    assert_eq!(fx.seen.lock().unwrap().len(), 1);
+    assert_eq!(prober.snapshot()["cache_hits"], 1);
+
+    clock.set(now + 601);
+    probe(fx.url("good.test", "/elsewhere")).await;
+    assert_eq!(fx.dns_queries("good.test"), 2);
+}
+
+#[tokio::test]
+async fn the_egress_gate_enforces_mechanisms_rate_and_address_policy() {
+    let fx = Fixtures::start().await;
+    // DNS only: nothing is sent to the site or to RDAP.
+    let prober = fx.prober(ProbingConfig {
+        mechanisms: Some(vec![Mechanism::Dns]),
+        ..fx.config()
+    });
+    let mut threat = ThreatAssessment::none();
+    let reports = prober
+        .probe_document(&fx.url("good.test", "/"), &mut threat)
+        .await;
+    assert_eq!(
+        reports[0].refused.get(&Mechanism::Tls),
+        Some(&Refusal::NotAllowed)
+    );
+    assert_eq!(
+        reports[0].refused.get(&Mechanism::Head),
+        Some(&Refusal::NotAllowed)
+    );
+    assert!(fx.seen.lock().unwrap().is_empty());
+    assert!(fx.rdap_asked.lock().unwrap().is_empty());
+
+    // Loopback answers are refused unless `allow_private_addresses`.
+    let prober = fx.prober(ProbingConfig {
+        allow_private_addresses: None,
+        ..fx.config()
+    });
+    let reports = prober
+        .probe_document(&fx.url("good.test", "/"), &mut ThreatAssessment::none())
+        .await;
+    assert_eq!(
+        reports[0].refused.get(&Mechanism::Tls),
+        Some(&Refusal::PrivateAddress)
+    );
+    assert!(fx.seen.lock().unwrap().is_empty());
+
+    // Four probes a minute cover one host (dns, rdap, tls, head) and nothing more.
+    let prober = fx.prober(ProbingConfig {
+        probes_per_minute: Some(4),
+        ..fx.config()
+    });
+    prober
+        .probe_document(&fx.url("a.test", "/"), &mut ThreatAssessment::none())
+        .await;
+    let reports = prober
+        .probe_document(&fx.url("b.test", "/"), &mut ThreatAssessment::none())
+        .await;
+    assert_eq!(
+        reports[0].refused.get(&Mechanism::Dns),
+        Some(&Refusal::RateLimited)
+    );
+    assert_eq!(fx.dns_queries("b.test"), 0);
+    // Rate-limited hosts are not cached.
+    assert_eq!(prober.snapshot()["cached_hosts"], 1);
+}
+
+#[test]
+fn non_global_addresses_are_refused() {
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "0.1.2.3",
+        "192.0.0.8",
+        "198.18.0.1",
+        "198.19.255.255",
+        "240.0.0.1",
+        "255.255.255.255",
+        "224.0.0.1",
+        "::1",
+        "::",
+        "fd00::1",
+        "fe80::1",
+        "ff02::1",
+        "::ffff:10.0.0.1",
+        // NAT64 and 6to4 around a private IPv4 address.
+        "64:ff9b::a9fe:a9fe",
+        "64:ff9b::7f00:1",
+        "2002:a00:1::1",
+        "2002:c0a8:101::",
+    ] {
+        assert!(is_private_address(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in [
+        "93.184.216.34",
+        "198.20.0.1",
+        "2606:4700::1111",
+        "::ffff:93.184.216.34",
+        "64:ff9b::5db8:d822",
+        "2002:5db8:d822::1",
+    ] {
+        assert!(!is_private_address(ip.parse().unwrap()), "{ip}");
+    }
+}
+
+#[test]
+fn probing_is_off_by_default_and_checks_its_settings() {
+    let off = Prober::from_config(None).unwrap();
+    assert!(!off.settings().enabled);
+    assert_eq!(off.snapshot(), json!({"enabled": false}));
+    let policy = acip_sidecar::model_policy::PolicyConfig {
+        active_probing: true,
+        ..Default::default()
+    };
+    assert!(!off.applies(Some(&policy), 100, false, false));
+
+    let cfg = ProbingConfig {
+        enabled: Some(true),
+        dns_server: Some("127.0.0.1".to_string()),
+        ..ProbingConfig::default()
+    };
+    let on = Prober::from_config(Some(&cfg)).unwrap();
+    assert_eq!(
+        on.settings().dns_server,
+        Some("127.0.0.1:53".parse().unwrap())
+    );
+    assert!(on.applies(Some(&policy), 10, false, false));
+    assert!(!on.applies(Some(&policy), 9, false, false));
+    assert!(!on.applies(Some(&policy), 100, true, false));
+    assert!(!on.applies(Some(&policy), 100, false, true));
+    let default_policy = acip_sidecar::model_policy::PolicyConfig::default();
+    assert!(!on.applies(Some(&default_policy), 100, false, false));
+
+    for bad in [
+        ProbingConfig {
+            rdap_endpoint: Some("http://rdap.example/".to_string()),
+            ..cfg.clone()
+        },
+        ProbingConfig {
+            dns_server: Some("resolver.example".to_string()),
+            ..cfg.clone()
+        },
+        ProbingConfig {
+            tls_ca_file: Some(fixture("missing.pem").to_str().unwrap().to_string()),
+            ..cfg.clone()
+        },
+    ] {
+        assert!(Prober::from_config(Some(&bad)).is_err(), "{bad:?}");
+    }
+}
+
+fn probing_state(fx: &Fixtures, model: ScriptedModel) -> Arc<state::AppState> {
+    let st = test_support::golden_state(Some(Arc::new(model))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    let raw = json!({"policies": {
+        "default": {
+            "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+            "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" },
+            "active_probing": true,
+        },
+    }});
+    st.policies =
+        PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap();
+    st.probes = Arc::new(fx.prober(fx.config()));
+    Arc::new(st)
+}
+
+async fn ingest(st: Arc<state::AppState>, text: &str) -> Value {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let body = json!({
+        "source_id": "page-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": text,
+    });
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app::build_router(st, None, extra)
+        .oneshot(req)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+#[serial]
+async fn ingest_probes_escalated_documents_and_status_labels_it() {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    let fx = Fixtures::start().await;
+    let st = probing_state(&fx, ScriptedModel::benign());
+
+    // Below the threshold: no probes.
+    let v = ingest(
+        st.clone(),
+        &format!("Minutes are at {}", fx.url("nowhere.test", "/")),
+    )
+    .await;
+    assert_eq!(fx.dns_queries("nowhere.test"), 0, "{v}");
+
+    let v = ingest(
+        st.clone(),
+        &format!("Confirm your password at {}", fx.url("nowhere.test", "/")),
+    )
+    .await;
+    assert_eq!(fx.dns_queries("nowhere.test"), 1);
+    let audit: Vec<&str> = v["threat_audit"]["indicators"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i.as_str().unwrap())
+        .collect();
+    assert!(
+        audit.contains(&"probe:nonexistent_domain:nowhere.test"),
+        "{audit:?}"
+    );
+    assert!(v["threat_audit"]["detected"].as_array().unwrap().contains(
+        &json!({"indicator": "probe:nonexistent_domain:nowhere.test", "stage": "probe"})
+    ));
+
+    let req = Request::get("/v1/acip/status").body(Body::empty()).unwrap();
+    let resp = app::build_router(st, None, Router::new())
+        .oneshot(req)
+        .await
+        .unwrap();
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let status: Value = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(status["probing"]["enabled"], true);
+    assert_eq!(
+        status["probing"]["notice"],
+        acip_sidecar::probing::STATUS_NOTICE
+    );
+    assert_eq!(status["probing"]["documents"], 1);
+    std::env::remove_var("ACIP_AUDIT_MODE");
+}
+
+#[tokio::test]
+#[serial]
+async fn heuristic_only_mode_never_probes() {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub");
+    let fx = Fixtures::start().await;
+    let st = probing_state(&fx, ScriptedModel::benign());
+    ingest(
+        st,
+        &format!("Confirm your password at {}", fx.url("nowhere.test", "/")),
+    )
+    .await;
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    assert_eq!(fx.dns_queries("nowhere.test"), 0);
+}
@@ -0,0 +1,309 @@
+mod util;
+
+use acip_sidecar::capabilities::Rejection;
+use acip_sidecar::reputation::{
+    self, InMemoryReputationStore, JsonFileReputationStore, ReadOnlyReputationStore,
+    ReputationStore,
+};
+use acip_sidecar::sentry::{Action, RiskLevel};
+use acip_sidecar::stats::{DecisionSample, DecisionStats, GroupBy, StatsSettings};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, client, read_only, state};
+use assert_cmd::cargo::cargo_bin_cmd;
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{path::Path, sync::Arc};
+use tower::ServiceExt;
+use util::serve;
+
+const TOKEN: &str = "t0ken";
+
+fn sample() -> DecisionSample {
+    DecisionSample {
+        tenant: "anonymous".to_string(),
+        policy: "default".to_string(),
+        source_type: "html".to_string(),
+        risk_level: RiskLevel::High,
+        action: Action::Block,
+        patterns: vec!["ignore_previous".to_string()],
+        escalated: false,
+        severity: 10,
+        quality: None,
+        confidence: None,
+    }
+}
+
+/// Write a reputation file and a stats file the way a production instance would.
+fn seed_stores(dir: &Path) {
+    let rep = JsonFileReputationStore::load_or_create(dir.join("reputation.json")).unwrap();
+    rep.record(reputation::observation(
+        "s1".to_string(),
+        Some("evil.example".to_string()),
+        40,
+        vec!["prompt_injection".to_string()],
+    ));
+    let stats = DecisionStats::load_or_create(
+        dir.join("stats.json"),
+        StatsSettings::default(),
+        Arc::new(reputation::SystemClock),
+    )
+    .unwrap();
+    stats.record(&sample());
+}
+
+fn file_hash(path: &Path) -> String {
+    hex::encode(Sha256::digest(std::fs::read(path).unwrap()))
+}
+
+fn read_only_state(dir: &Path) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    AppStateBuilder::for_tests()
+        .with_reputation(
+            read_only::open_reputation_store(&format!(
+                "file:{}",
+                dir.join("reputation.json").display()
+            ))
+            .unwrap(),
+        )
+        .with_stats(
+            read_only::open_stats_store(
+                &format!("file:{}", dir.join("stats.json").display()),
+                StatsSettings::default(),
+                Arc::new(reputation::SystemClock),
+            )
+            .unwrap(),
+        )
+        .with_read_only(true)
+        .build()
+        .unwrap()
+}
+
+fn router(st: Arc<state::AppState>, token: Option<&str>) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, token.map(str::to_string), extra)
+}
+
+async fn send(app: &Router, method: &str, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let mut b = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("X-ACIP-Token", TOKEN);
+    if body.is_some() {
+        b = b.header("content-type", "application/json");
+    }
+    let req = b
+        .body(body.map(|v| Body::from(v.to_string())).unwrap_or_default())
+        .unwrap();
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn mutating_endpoints_are_refused_and_queries_leave_the_stores_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    seed_stores(dir.path());
+    let reputation_hash = file_hash(&dir.path().join("reputation.json"));
+    let stats_hash = file_hash(&dir.path().join("stats.json"));
+    let st = read_only_state(dir.path());
+    let app = router(st.clone(), Some(TOKEN));
+
+    let ingest = json!({
+        "source_id": "s2",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": "hello",
+    });
+    let mutating = [
+        ("POST", "/v1/acip/ingest_source", Some(ingest)),
+        ("POST", "/v1/acip/uploads", Some(json!({"total_bytes": 10}))),
+        ("GET", "/v1/acip/uploads/u1", None),
+        ("PUT", "/v1/acip/uploads/u1/chunks/0", None),
+        ("POST", "/v1/acip/uploads/u1/complete", Some(json!({}))),
+        ("GET", "/v1/acip/jobs/j1", None),
+        ("POST", "/v1/acip/feeds/blocklist/refresh", None),
+        ("POST", "/v1/acip/admin/drain", None),
+        ("POST", "/v1/acip/admin/resume", None),
+    ];
+    for (method, path, body) in mutating {
+        let (status, v) = send(&app, method, path, body).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{method} {path}");
+        assert_eq!(v["error"], "read_only_mode", "{method} {path}");
+        assert_eq!(v["extra"]["method"], method);
+        assert_eq!(v["extra"]["path"], path);
+    }
+
+    let (status, v) = send(&app, "GET", "/v1/acip/status", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["read_only"], true);
+
+    let (status, v) = send(&app, "GET", "/v1/acip/capabilities", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["features"]["read_only"], true);
+    let available = |path: &str| {
+        v["endpoints"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|e| e["path"] == path)
+            .unwrap()["available"]
+            .clone()
+    };
+    assert_eq!(available("/v1/acip/ingest_source"), false);
+    assert_eq!(available("/v1/acip/feeds/:name/refresh"), false);
+    assert_eq!(available("/v1/acip/admin/drain"), false);
+    assert_eq!(available("/v1/acip/stats"), true);
+
+    let (status, v) = send(&app, "GET", "/v1/acip/reputation?key=source_id:s1", None).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    let (status, v) = send(&app, "GET", "/v1/acip/reputation/records", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["items"].as_array().unwrap().len(), 2);
+    let (status, v) = send(&app, "GET", "/v1/acip/stats?days=7", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["rows"][0]["decisions"], 1, "{v}");
+    for path in [
+        "/v1/acip/schema",
+        "/v1/acip/policies",
+        "/v1/acip/policy",
+        "/v1/acip/stats/aggregate",
+    ] {
+        let (status, _) = send(&app, "GET", path, None).await;
+        assert_eq!(status, StatusCode::OK, "{path}");
+    }
+
+    assert!(!st.drain.is_draining());
+    assert_eq!(
+        file_hash(&dir.path().join("reputation.json")),
+        reputation_hash
+    );
+    assert_eq!(file_hash(&dir.path().join("stats.json")), stats_hash);
+}
+
+#[test]
+fn read_only_stores_never_write() {
+    let dir = tempfile::tempdir().unwrap();
+    seed_stores(dir.path());
+    let path = dir.path().join("reputation.json");
+    let hash = file_hash(&path);
+
+    let store = JsonFileReputationStore::open_read_only(&path).unwrap();
+    let before = store.get("source_id:s1").unwrap();
+    let recs = store.record(reputation::observation("s1".to_string(), None, 90, vec![]));
+    assert_eq!(recs[0].seen_count, before.seen_count);
+    assert_eq!(
+        store.get("source_id:s1").unwrap().risk_score,
+        before.risk_score
+    );
+    assert_eq!(file_hash(&path), hash);
+
+    let mem = ReadOnlyReputationStore(InMemoryReputationStore::new());
+    let recs = mem.record(reputation::observation(
+        "s9".to_string(),
+        Some("h.example".to_string()),
+        10,
+        vec![],
+    ));
+    assert_eq!(recs.len(), 2);
+    assert_eq!(recs[1].key, "host:h.example");
+    assert_eq!(recs[1].seen_count, 0);
+    assert!(mem.list().is_empty());
+
+    let stats_path = dir.path().join("stats.json");
+    let stats_hash = file_hash(&stats_path);
+    let stats = DecisionStats::open_read_only(
+        &stats_path,
+        StatsSettings::default(),
+        Arc::new(reputation::SystemClock),
+    )
+    .unwrap();
+    let before = serde_json::to_value(stats.report(7, GroupBy::Pattern)).unwrap();
+    stats.record(&sample());
+    stats.record_false_positive("ignore_previous");
+    assert_eq!(
+        serde_json::to_value(stats.report(7, GroupBy::Pattern)).unwrap(),
+        before
+    );
+    assert_eq!(file_hash(&stats_path), stats_hash);
+}
+
+#[test]
+fn startup_fails_when_a_store_cannot_be_opened_read_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing = format!("file:{}", dir.path().join("missing.json").display());
+    assert!(read_only::open_reputation_store(&missing).is_err());
+    assert!(read_only::open_stats_store(
+        &missing,
+        StatsSettings::default(),
+        Arc::new(reputation::SystemClock)
+    )
+    .is_err());
+    assert!(!dir.path().join("missing.json").exists());
+
+    // A corrupt file is reported, not quarantined or replaced.
+    let corrupt = dir.path().join("corrupt.json");
+    std::fs::write(&corrupt, "{not json").unwrap();
+    assert!(read_only::open_reputation_store(&format!("file:{}", corrupt.display())).is_err());
+    assert_eq!(std::fs::read_to_string(&corrupt).unwrap(), "{not json");
+
+    assert!(read_only::open_reputation_store("redis://localhost").is_err());
+    assert!(read_only::open_reputation_store("memory").is_ok());
+}
+
+#[test]
+fn client_and_acipctl_report_read_only_mode() {
+    let dir = tempfile::tempdir().unwrap();
+    seed_stores(dir.path());
+    let addr = serve(router(read_only_state(dir.path()), None));
+    let c = client::Client::new(&format!("http://{addr}"), None);
+
+    let err = c
+        .ingest(
+            &json!({"source_id": "s1", "source_type": "other", "content_type": "text/plain", "text": "hi"}),
+            &[],
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<Rejection>(),
+        Some(&Rejection::ReadOnlyMode)
+    );
+
+    let file = dir.path().join("doc.txt");
+    std::fs::write(&file, "hello").unwrap();
+    let err = c
+        .upload_resumable(
+            &file,
+            &json!({"source_id": "s1", "source_type": "other", "content_type": "text/plain"}),
+            &[],
+            &dir.path().join("doc.state.json"),
+            &client::RetryPolicy::default(),
+        )
+        .unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<Rejection>(),
+        Some(&Rejection::ReadOnlyMode)
+    );
+
+    cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}")])
+        .args(["ingest-text", "--source-id", "s1"])
+        .write_stdin("hello")
+        .assert()
+        .code(3)
+        .stderr(predicates::str::contains("read_only_mode"))
+        .stderr(predicates::str::contains("sidecar is in read-only mode"));
+}
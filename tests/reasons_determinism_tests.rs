@@ -0,0 +1,212 @@
+use acip_sidecar::ingest::apply_decision_stages;
+use acip_sidecar::model_policy::{GarbledTextHandling, PolicyConfig};
+use acip_sidecar::reasons::{Reason, ReasonSet, ReasonStage};
+use acip_sidecar::reputation::ReputationRecord;
+use acip_sidecar::reputation_policy::ReputationThresholds;
+use acip_sidecar::sentry::{DecisionEngine, ModelClient};
+use acip_sidecar::text_quality;
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use std::time::Duration;
+
+/// Small deterministic PRNG (xorshift64*) so failures are reproducible from the seed.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn shuffle<T>(&mut self, v: &mut [T]) {
+        for i in (1..v.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            v.swap(i, j);
+        }
+    }
+}
+
+/// Provider that answers after a jittered delay with the model reasons in shuffled order.
+struct JitteryModel {
+    delay: Duration,
+    reasons: Vec<&'static str>,
+}
+
+#[async_trait]
+impl ModelClient for JitteryModel {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        tokio::time::sleep(self.delay).await;
+        Ok(serde_json::json!({
+            "tools_allowed": true,
+            "risk_level": "low",
+            "action": "allow",
+            "fenced_content": "```external\nX\n```",
+            "reasons": self.reasons,
+            "detected_patterns": [],
+        })
+        .to_string())
+    }
+}
+
+fn thresholds() -> ReputationThresholds {
+    ReputationThresholds {
+        medium_score: 20,
+        high_score: 50,
+        bad_actor_score: 150,
+        half_life_base_days: 9999.0,
+        half_life_k: 0.0,
+        trust_max_discount: 0.0,
+        trust_full_age_days: 365.0,
+        trust_full_clean_ingests: 1000,
+    }
+}
+
+fn records() -> Vec<ReputationRecord> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    vec![
+        ReputationRecord {
+            key: "source_id:a".to_string(),
+            risk_score: 10,
+            suspected_attack_count: 1,
+            last_seen_unix: now,
+            ..Default::default()
+        },
+        ReputationRecord {
+            key: "host:example.com".to_string(),
+            risk_score: 30,
+            suspected_attack_count: 2,
+            last_seen_unix: now,
+            ..Default::default()
+        },
+    ]
+}
+
+async fn run_once(rng: &mut Rng) -> Vec<String> {
+    let mut reasons = vec![
+        "External links present",
+        "external links present.",
+        "benign prose",
+        "contains an instruction to the reader",
+    ];
+    rng.shuffle(&mut reasons);
+    let l1 = JitteryModel {
+        delay: Duration::from_millis(rng.next_u64() % 5),
+        reasons,
+    };
+    let l2 = JitteryModel {
+        delay: Duration::ZERO,
+        reasons: vec![],
+    };
+    let engine = DecisionEngine::new(Box::new(l1), Box::new(l2));
+
+    let d = engine
+        .decide(
+            "default",
+            &PolicyConfig::default(),
+            &serde_json::json!({}),
+            "```external\nX\n```",
+            &HeaderMap::new(),
+        )
+        .await;
+
+    let mut recs = records();
+    rng.shuffle(&mut recs);
+    apply_decision_stages(
+        d,
+        true,
+        false,
+        &[],
+        &recs,
+        &thresholds(),
+        &text_quality::assess(""),
+        GarbledTextHandling::Ignore,
+    )
+    .reasons
+}
+
+#[tokio::test]
+async fn reasons_are_byte_identical_across_runs() {
+    let mut rng = Rng(0xD15C_0BA1_1DEA_0001);
+    let first = serde_json::to_vec(&run_once(&mut rng).await).unwrap();
+
+    for _ in 0..49 {
+        let next = serde_json::to_vec(&run_once(&mut rng).await).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&next),
+            String::from_utf8_lossy(&first)
+        );
+    }
+
+    let reasons: Vec<String> = serde_json::from_slice(&first).unwrap();
+    assert_eq!(
+        reasons
+            .iter()
+            .filter(|r| r.contains("links present"))
+            .count(),
+        1,
+        "{reasons:?}"
+    );
+    assert!(reasons.iter().any(|r| r.ends_with("(x2)")), "{reasons:?}");
+    // Model reasons first, then markup cap, then reputation context.
+    let markup = reasons
+        .iter()
+        .position(|r| r.starts_with("tools hard-capped for markup"))
+        .unwrap();
+    let rep = reasons
+        .iter()
+        .position(|r| r.starts_with("source reputation:"))
+        .unwrap();
+    assert!(markup > 0 && markup < rep, "{reasons:?}");
+}
+
+#[test]
+fn higher_priority_stage_wins_duplicate_ids() {
+    let mut set = ReasonSet::default();
+    set.push(Reason::new(
+        ReasonStage::Sentry,
+        "tools not authorized by caller",
+    ));
+    set.push(Reason::new(
+        ReasonStage::Authorization,
+        "tools not authorized by caller (set X-ACIP-Allow-Tools=true to allow)",
+    ));
+
+    let sorted = set.sorted();
+    assert_eq!(sorted.len(), 1);
+    assert_eq!(sorted[0].stage, ReasonStage::Authorization);
+    assert_eq!(sorted[0].id, "tools.not_authorized");
+    assert_eq!(sorted[0].count, 2);
+}
+
+#[test]
+fn limit_never_evicts_pinned_reasons() {
+    let mut set = ReasonSet::default();
+    set.extend(
+        ReasonStage::Sentry,
+        (0..10).map(|i| format!("model reason {i:02}")),
+    );
+    set.push(Reason::new(
+        ReasonStage::Markup,
+        "tools hard-capped for markup content (html/svg)",
+    ));
+
+    let out = set.render_limited(3);
+    assert_eq!(
+        out,
+        vec![
+            "model reason 00",
+            "model reason 01",
+            "tools hard-capped for markup content (html/svg)",
+        ]
+    );
+}
@@ -0,0 +1,309 @@
+use acip_sidecar::redact::{RedactingMakeWriter, Redaction};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, config, ingest};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::Value;
+use std::io::Write;
+use std::sync::{Arc, Mutex, Once};
+use tower::ServiceExt;
+
+static INIT: Once = Once::new();
+
+fn init_env() {
+    INIT.call_once(|| {
+        std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+        std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    });
+}
+
+const FIXTURE: &str = include_str!("fixtures/redaction_sensitive.txt");
+
+const CONFIG: &str = r#"
+[[redaction.rules]]
+label = "hostname"
+regex = '[a-z0-9-]+\.corp\.internal'
+
+[[redaction.rules]]
+label = "codename"
+literal = "BLUEHERON"
+
+[[redaction.rules]]
+label = "customer_id"
+regex = 'CUST-[0-9]{6}'
+
+[[redaction.rules]]
+label = "paste_site"
+literal = "pastebin"
+"#;
+
+/// Strings that must not appear in any output.
+const SENSITIVE: &[&str] = &["blueheron", "corp.internal", "cust-004217", "pastebin"];
+
+fn redaction_config(raw: &str) -> config::RedactionConfig {
+    let cfg: config::Config = toml::from_str(raw).unwrap();
+    cfg.redaction.unwrap()
+}
+
+fn router(redaction: Arc<Redaction>) -> Router {
+    let st = AppStateBuilder::for_tests()
+        .with_redaction(redaction)
+        .build()
+        .unwrap();
+
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn call(app: &Router, req: Request<Body>) -> (StatusCode, String) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, String::from_utf8(bytes.to_vec()).unwrap())
+}
+
+fn get(uri: &str) -> Request<Body> {
+    Request::builder().uri(uri).body(Body::empty()).unwrap()
+}
+
+fn assert_clean(surface: &str, body: &str) {
+    let lower = body.to_lowercase();
+    for s in SENSITIVE {
+        assert!(!lower.contains(s), "{surface} leaked {s:?}: {body}");
+    }
+}
+
+#[tokio::test]
+async fn every_response_surface_is_redacted_but_scanners_see_the_original() {
+    init_env();
+    let redaction = Arc::new(Redaction::from_config(Some(&redaction_config(CONFIG))).unwrap());
+    let app = router(redaction.clone());
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "source_id": "BLUEHERON-feed",
+                "source_type": "other",
+                "content_type": "text/plain",
+                "url": "https://build01.corp.internal/notes",
+                "title": "CUST-004217 notes",
+                "text": FIXTURE,
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_clean("ingest", &body);
+
+    let v: Value = serde_json::from_str(&body).unwrap();
+    let fenced = v["fenced_content"].as_str().unwrap();
+    for label in ["hostname", "codename", "customer_id", "paste_site"] {
+        assert!(
+            fenced.contains(&format!("[REDACTED:{label}]")),
+            "{label}: {fenced}"
+        );
+    }
+    // The detector matched the original text; only the reported indicator is redacted.
+    let indicators: Vec<&str> = v["threat_audit"]["indicators"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i.as_str().unwrap())
+        .collect();
+    assert!(
+        indicators.contains(&"mentions_exfil:[REDACTED:paste_site]"),
+        "{indicators:?}"
+    );
+    assert!(indicators.contains(&"contains_phrase:ignore previous"));
+    assert!(v["threat"]["threat_score"].as_u64().unwrap() > 0);
+    let reasons = v["reasons"].to_string();
+    assert!(reasons.contains("[REDACTED:"), "{reasons}");
+
+    // Reputation records are keyed by the unredacted host and source id.
+    let (status, body) = call(
+        &app,
+        get("/v1/acip/reputation?key=host:build01.corp.internal"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_clean("reputation", &body);
+    let (status, body) = call(&app, get("/v1/acip/reputation?key=host:ci-7.corp.internal")).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_clean("reputation error", &body);
+
+    let (_, body) = call(&app, get("/v1/acip/stats?group_by=pattern")).await;
+    assert_clean("stats", &body);
+    assert!(
+        body.contains("mentions_exfil:[REDACTED:paste_site]"),
+        "{body}"
+    );
+
+    // Error bodies echoing request input.
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-policy", "blueheron")
+        .body(Body::from(
+            serde_json::json!({
+                "source_id": "s",
+                "source_type": "other",
+                "content_type": "text/plain",
+                "text": "hi",
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let (status, body) = call(&app, req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_clean("unknown policy", &body);
+
+    // Per-label counts are visible in /status.
+    let (_, body) = call(&app, get("/v1/acip/status")).await;
+    let status: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(status["redaction"]["rules"], 4);
+    for label in ["hostname", "codename", "customer_id", "paste_site"] {
+        assert!(
+            status["redaction"]["counts"][label].as_u64().unwrap() > 0,
+            "{label}: {body}"
+        );
+    }
+}
+
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn log_lines_are_redacted() {
+    let redaction = Arc::new(Redaction::from_config(Some(&redaction_config(CONFIG))).unwrap());
+    let buf = SharedBuf::default();
+    let sink = buf.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_ansi(false)
+        .with_writer(RedactingMakeWriter::new(redaction, move || sink.clone()))
+        .finish();
+
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!(host = "build01.corp.internal", "fetch failed for BlueHeron");
+        tracing::info!("customer CUST-004217 requested a pastebin export");
+    });
+
+    let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    assert_eq!(out.lines().count(), 2, "{out}");
+    assert_clean("log", &out);
+    assert!(out.contains("[REDACTED:hostname]"), "{out}");
+    assert!(out.contains("[REDACTED:codename]"), "{out}");
+    assert!(out.contains("[REDACTED:customer_id]"), "{out}");
+}
+
+#[test]
+fn overlapping_rules_prefer_the_longest_leftmost_match() {
+    let cfg = redaction_config(
+        r#"
+[[redaction.rules]]
+label = "codename"
+literal = "heron"
+
+[[redaction.rules]]
+label = "project"
+regex = 'blue[a-z]+'
+"#,
+    );
+    let r = Redaction::from_config(Some(&cfg)).unwrap();
+    assert_eq!(
+        r.redact_str("blueheron and heron"),
+        "[REDACTED:project] and [REDACTED:codename]"
+    );
+    assert_eq!(r.counts()["project"], 1);
+    assert_eq!(r.counts()["codename"], 1);
+}
+
+#[test]
+fn reload_swaps_rules_and_keeps_counts() {
+    let r = Redaction::from_config(Some(&redaction_config(CONFIG))).unwrap();
+    assert_eq!(r.redact_str("BLUEHERON"), "[REDACTED:codename]");
+
+    // Invalid rules are rejected and the previous set stays active.
+    let bad = redaction_config(
+        r#"
+[[redaction.rules]]
+label = "broken"
+regex = '('
+"#,
+    );
+    assert!(r.reload(Some(&bad)).is_err());
+    assert_eq!(r.redact_str("BLUEHERON"), "[REDACTED:codename]");
+
+    let next = redaction_config(
+        r#"
+[[redaction.rules]]
+label = "codename"
+literal = "GREYWOLF"
+"#,
+    );
+    r.reload(Some(&next)).unwrap();
+    assert_eq!(r.rule_count(), 1);
+    assert_eq!(
+        r.redact_str("BLUEHERON GREYWOLF"),
+        "BLUEHERON [REDACTED:codename]"
+    );
+    assert_eq!(r.counts()["codename"], 3);
+    assert_eq!(r.counts()["hostname"], 0);
+}
+
+#[test]
+fn invalid_rules_are_rejected() {
+    for raw in [
+        "[[redaction.rules]]\nlabel = \"x\"\nliteral = \"a\"\nregex = \"b\"\n",
+        "[[redaction.rules]]\nlabel = \"x\"\n",
+        "[[redaction.rules]]\nlabel = \"x\"\nregex = \"a*\"\n",
+        "[[redaction.rules]]\nlabel = \"bad label\"\nliteral = \"a\"\n",
+        "[[redaction.rules]]\nlabel = \"x\"\nliteral = \"\"\n",
+    ] {
+        assert!(
+            Redaction::from_config(Some(&redaction_config(raw))).is_err(),
+            "{raw}"
+        );
+    }
+}
+
+/// Throughput check: `cargo test --test redaction_tests -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn redaction_throughput() {
+    let r = Redaction::from_config(Some(&redaction_config(CONFIG))).unwrap();
+    let clean = "The quick brown fox jumps over the lazy dog. ".repeat(100_000);
+    let dirty = FIXTURE.repeat(10_000);
+
+    for (name, text) in [("clean", &clean), ("dirty", &dirty)] {
+        let start = std::time::Instant::now();
+        let rounds = 10;
+        for _ in 0..rounds {
+            std::hint::black_box(r.redact_str(text));
+        }
+        let secs = start.elapsed().as_secs_f64();
+        let mb = (text.len() * rounds) as f64 / (1024.0 * 1024.0);
+        println!("{name}: {:.1} MiB/s", mb / secs);
+    }
+}
@@ -0,0 +1,421 @@
+use acip_sidecar::model_policy::PolicyConfig;
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::remediation::{Catalog, Evidence, KnownIds, Remediation, RemediationPolicy};
+use acip_sidecar::test_support::{self, ScriptedModel};
+use acip_sidecar::threat::AttackType;
+use acip_sidecar::{app, config::RemediationConfig, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+fn builtin() -> Catalog {
+    Catalog::load(None, &KnownIds::default()).unwrap().0
+}
+
+fn evidence(action: &str, patterns: &[&str], attack_types: &[AttackType]) -> Evidence {
+    Evidence {
+        action: action.to_string(),
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        attack_types: attack_types.to_vec(),
+        reasons: vec![],
+    }
+}
+
+fn ids(hints: &[acip_sidecar::remediation::Hint]) -> Vec<&str> {
+    hints.iter().map(|h| h.id.as_str()).collect()
+}
+
+#[test]
+fn patterns_and_families_select_hints() {
+    let catalog = builtin();
+    let policy = PolicyConfig::default();
+
+    // A family selector matches every id under it, including open-ended probe findings.
+    let hints = catalog.resolve(
+        &policy,
+        &evidence("sanitize", &["probe:cert_mismatch:evil.test"], &[]),
+    );
+    assert_eq!(ids(&hints), ["do_not_follow_links"]);
+    assert_eq!(hints[0].locale, "en");
+
+    // Several signals: attack types and patterns together, each hint once.
+    let hints = catalog.resolve(
+        &policy,
+        &evidence(
+            "block",
+            &["social_pressure:urgent", "mentions_sensitive:password"],
+            &[AttackType::SocialEngineering, AttackType::CredentialTheft],
+        ),
+    );
+    assert_eq!(
+        ids(&hints),
+        [
+            "verify_out_of_band",
+            "never_share_credentials",
+            "report_internal_impersonation"
+        ]
+    );
+
+    // Reason ids and actions are outcomes.
+    let mut e = evidence("needs_review", &[], &[]);
+    e.reasons = vec!["text_quality.garbled".to_string()];
+    assert_eq!(
+        ids(&catalog.resolve(&policy, &e)),
+        ["request_readable_copy", "review_manually"]
+    );
+
+    // A prefix that is not a family does not match.
+    assert!(catalog
+        .resolve(
+            &policy,
+            &evidence("block", &["social_pressureX:urgent"], &[])
+        )
+        .is_empty());
+}
+
+#[test]
+fn hints_are_ordered_by_priority_and_capped() {
+    let catalog = builtin();
+    let e = evidence(
+        "needs_review",
+        &["html_scan:script", "feed_blocklist:evil.test"],
+        &[AttackType::PromptInjection, AttackType::SocialEngineering],
+    );
+    let all = catalog.resolve(
+        &PolicyConfig {
+            remediation: RemediationPolicy {
+                hints: vec![],
+                max_hints: Some(10),
+            },
+            ..PolicyConfig::default()
+        },
+        &e,
+    );
+    assert_eq!(
+        ids(&all),
+        [
+            "verify_out_of_band",
+            "report_internal_impersonation",
+            "do_not_run_instructions",
+            "do_not_follow_links",
+            "open_in_isolated_viewer",
+            "review_manually"
+        ]
+    );
+    assert_eq!(
+        ids(&catalog.resolve(&PolicyConfig::default(), &e)),
+        ids(&all[..3])
+    );
+
+    let selected = PolicyConfig {
+        remediation: RemediationPolicy {
+            hints: vec![
+                "review_manually".to_string(),
+                "do_not_follow_links".to_string(),
+            ],
+            max_hints: None,
+        },
+        ..PolicyConfig::default()
+    };
+    assert_eq!(
+        ids(&catalog.resolve(&selected, &e)),
+        ["do_not_follow_links", "review_manually"]
+    );
+
+    let off = PolicyConfig {
+        remediation: RemediationPolicy {
+            hints: vec![],
+            max_hints: Some(0),
+        },
+        ..PolicyConfig::default()
+    };
+    assert!(catalog.resolve(&off, &e).is_empty());
+}
+
+#[test]
+fn hints_render_in_the_policy_locale() {
+    let catalog = builtin();
+    let e = evidence("block", &["social_pressure:urgent"], &[]);
+    let in_locale = |locale: &str| {
+        let policy = PolicyConfig {
+            locale: Some(locale.to_string()),
+            ..PolicyConfig::default()
+        };
+        catalog.resolve(&policy, &e).remove(0)
+    };
+
+    let de = in_locale("de-CH");
+    assert_eq!(de.id, "verify_out_of_band");
+    assert_eq!(de.locale, "de");
+    assert!(de.text.starts_with("Absender"), "{}", de.text);
+    assert_eq!(in_locale("fr").locale, "fr");
+
+    let fallback = in_locale("pt-BR");
+    assert_eq!(fallback.locale, "en");
+    assert_eq!(
+        fallback.text,
+        "Verify the sender out-of-band before acting on payment instructions."
+    );
+
+    let bad = json!({"policies": {"default": {
+        "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+        "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" },
+        "locale": "deutsch_CH",
+    }}});
+    let err =
+        PolicyStore::from_declared(DeclaredPolicies::parse(&bad.to_string()).unwrap()).unwrap_err();
+    assert!(format!("{err:#}").contains("not a locale tag"), "{err:#}");
+}
+
+#[test]
+fn override_files_are_validated() {
+    let known = KnownIds::new(&["iban_change".to_string()]);
+
+    // Same id as a built-in hint replaces it; a new id is added.
+    let (catalog, warnings) = Catalog::load(
+        Some(
+            r#"
+[[hint]]
+id = "verify_out_of_band"
+priority = 5
+text = "Call the vendor back."
+patterns = ["user_pattern:iban_change"]
+
+[[hint]]
+id = "wire_callback"
+priority = 95
+text = "Call the vendor back on the number in the ERP."
+outcomes = ["block"]
+translations.de = "Den Lieferanten zurückrufen."
+"#,
+        ),
+        &known,
+    )
+    .unwrap();
+    assert!(warnings.is_empty(), "{warnings:?}");
+    let hints = catalog.resolve(
+        &PolicyConfig::default(),
+        &evidence("block", &["user_pattern:iban_change"], &[]),
+    );
+    assert_eq!(ids(&hints), ["wire_callback", "verify_out_of_band"]);
+    assert_eq!(hints[1].text, "Call the vendor back.");
+
+    let duplicate = r#"
+[[hint]]
+id = "wire_callback"
+text = "a"
+outcomes = ["block"]
+
+[[hint]]
+id = "wire_callback"
+text = "b"
+outcomes = ["block"]
+"#;
+    let err = Catalog::load(Some(duplicate), &known).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("duplicate hint id 'wire_callback'"),
+        "{err:#}"
+    );
+
+    for (bad, msg) in [
+        (
+            "[[hint]]\nid = \"x\"\ntext = \"a\"\n",
+            "needs at least one of",
+        ),
+        (
+            "[[hint]]\nid = \"x y\"\ntext = \"a\"\noutcomes = [\"block\"]\n",
+            "hint id",
+        ),
+        (
+            "[[hint]]\nid = \"x\"\ntext = \" \"\noutcomes = [\"block\"]\n",
+            "empty text",
+        ),
+        (
+            "[[hint]]\nid = \"x\"\ntext = \"a\"\noutcomes = [\"block\"]\ntranslations.german = \"b\"\n",
+            "not a locale tag",
+        ),
+        (
+            "[[hint]]\nid = \"x\"\ntext = \"a\"\noutcomes = [\"block\"]\nseverity = 3\n",
+            "unknown field",
+        ),
+    ] {
+        let err = Catalog::load(Some(bad), &known).unwrap_err();
+        assert!(format!("{err:#}").contains(msg), "{bad}: {err:#}");
+    }
+
+    // Typos load, with a warning naming the closest known id.
+    let (_, warnings) = Catalog::load(
+        Some(
+            r#"
+[[hint]]
+id = "typo"
+text = "a"
+patterns = ["social_presure", "user_pattern:iban_chnage"]
+outcomes = ["text_quality.garbeld"]
+"#,
+        ),
+        &known,
+    )
+    .unwrap();
+    assert_eq!(warnings.len(), 3, "{warnings:?}");
+    assert!(
+        warnings[0].contains("did you mean 'social_pressure'"),
+        "{warnings:?}"
+    );
+    assert!(
+        warnings[1].contains("did you mean 'user_pattern:iban_change'"),
+        "{warnings:?}"
+    );
+    assert!(
+        warnings[2].contains("did you mean 'text_quality.garbled'"),
+        "{warnings:?}"
+    );
+}
+
+#[test]
+fn policies_must_name_existing_hints_and_reload_keeps_good_hints() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("hints.toml");
+    std::fs::write(
+        &path,
+        "[[hint]]\nid = \"wire_callback\"\ntext = \"a\"\noutcomes = [\"block\"]\n",
+    )
+    .unwrap();
+    let cfg = RemediationConfig {
+        hints_file: Some(path.to_str().unwrap().to_string()),
+    };
+    let remediation = Remediation::from_config(Some(&cfg), &[]).unwrap();
+    let builtin_count = builtin().len();
+    assert_eq!(remediation.catalog().len(), builtin_count + 1);
+
+    let raw = json!({"policies": {"default": {
+        "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+        "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" },
+        "remediation": { "hints": ["wire_calback"] },
+    }}});
+    let policies =
+        PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap();
+    let err = remediation.check_policy_refs(&policies).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("unknown hint 'wire_calback' (did you mean 'wire_callback'?)"),
+        "{err:#}"
+    );
+
+    std::fs::write(&path, "[[hint]]\nid = \"broken\"\ntext = \"a\"\n").unwrap();
+    assert!(remediation.reload(Some(&cfg)).is_err());
+    assert_eq!(remediation.catalog().len(), builtin_count + 1);
+    assert_eq!(remediation.reload(None).unwrap(), builtin_count);
+}
+
+fn remediation_state(model: ScriptedModel) -> Arc<state::AppState> {
+    let st = test_support::golden_state(Some(Arc::new(model))).unwrap();
+    let mut st = Arc::into_inner(st).unwrap();
+    let raw = json!({"policies": {
+        "default": {
+            "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+            "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" },
+        },
+        "de": {
+            "extends": "default",
+            "locale": "de",
+            "remediation": { "max_hints": 1 },
+        },
+    }});
+    st.policies =
+        PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap();
+    Arc::new(st)
+}
+
+async fn ingest(st: Arc<state::AppState>, policy: &str, text: &str) -> Value {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let body = json!({
+        "source_id": "mail-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": text,
+    });
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-policy", policy)
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let resp = app::build_router(st, None, extra)
+        .oneshot(req)
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+const PHISH: &str = "URGENT: the CFO needs the wire sent immediately, new bank details attached.";
+
+#[tokio::test]
+#[serial]
+async fn non_allow_decisions_carry_hints_without_audit_mode() {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    std::env::remove_var("ACIP_AUDIT_MODE");
+    let model = ScriptedModel::benign().on(
+        "wire sent",
+        json!({
+            "tools_allowed": false,
+            "risk_level": "high",
+            "action": "block",
+            "reasons": ["scripted: payment fraud"],
+        }),
+    );
+    let st = remediation_state(model);
+
+    let v = ingest(st.clone(), "default", PHISH).await;
+    assert_eq!(v["action"], "block", "{v}");
+    assert!(v.get("threat_audit").is_none());
+    assert_eq!(
+        v["remediation"],
+        json!([
+            {
+                "id": "verify_out_of_band",
+                "text": "Verify the sender out-of-band before acting on payment instructions.",
+                "locale": "en"
+            },
+            {
+                "id": "report_internal_impersonation",
+                "text": "Report to security if the sender claims to be internal.",
+                "locale": "en"
+            }
+        ])
+    );
+
+    let v = ingest(st.clone(), "de", PHISH).await;
+    let hints = v["remediation"].as_array().unwrap();
+    assert_eq!(hints.len(), 1);
+    assert_eq!(hints[0]["id"], "verify_out_of_band");
+    assert_eq!(hints[0]["locale"], "de");
+
+    let v = ingest(st, "default", "Minutes of the weekly sync.").await;
+    assert_eq!(v["action"], "allow");
+    assert!(v.get("remediation").is_none(), "{v}");
+}
+
+#[tokio::test]
+#[serial]
+async fn heuristic_only_allows_carry_no_hints() {
+    for mode in ["stub", "stub-open"] {
+        std::env::set_var("ACIP_SENTRY_MODE", mode);
+        let v = ingest(remediation_state(ScriptedModel::benign()), "default", PHISH).await;
+        assert_eq!(v["action"], "allow", "{mode}: {v}");
+        assert!(v.get("remediation").is_none(), "{mode}: {v}");
+    }
+    std::env::remove_var("ACIP_SENTRY_MODE");
+}
@@ -0,0 +1,165 @@
+use acip_sidecar::reputation::{
+    self, InMemoryReputationStore, JsonFileReputationStore, ReputationStore,
+};
+use acip_sidecar::reputation_limits::{CardinalityGuard, CardinalitySettings};
+use acip_sidecar::reputation_policy::{risk_breakdown, ReputationThresholds};
+
+const NOW: u64 = 1_760_000_000;
+
+fn guard(max_records: usize, prefilter: bool) -> CardinalityGuard {
+    CardinalityGuard::new(
+        CardinalitySettings {
+            max_records,
+            prefilter,
+            ..Default::default()
+        },
+        ReputationThresholds::from_env(),
+    )
+}
+
+fn see(store: &dyn ReputationStore, source: &str, threat: u8, at: u64) {
+    let mut obs = reputation::observation(source.to_string(), None, threat, vec![]);
+    obs.now_unix = at;
+    store.record(obs);
+}
+
+fn has(store: &dyn ReputationStore, source: &str) -> bool {
+    store.get(&format!("source_id:{source}")).is_some()
+}
+
+#[test]
+fn evicts_lowest_score_then_oldest_and_never_the_protected_band() {
+    let store = InMemoryReputationStore::new().with_limits(guard(3, false));
+    see(&store, "hot", 100, NOW - 10);
+    see(&store, "mid", 20, NOW - 2000);
+    see(&store, "old", 0, NOW - 1000);
+    assert_eq!(store.cardinality().evictions, 0);
+
+    // Over the cap: the zero-score records go before the scored one, oldest first.
+    see(&store, "new", 0, NOW - 5);
+    assert!(!has(&store, "old"));
+    see(&store, "fresh", 0, NOW);
+    assert!(!has(&store, "new"));
+    see(&store, "fresher", 0, NOW);
+    assert!(!has(&store, "fresh"));
+    see(&store, "freshest", 0, NOW);
+    assert!(!has(&store, "fresher"));
+    assert!(has(&store, "hot") && has(&store, "mid") && has(&store, "freshest"));
+
+    let snap = store.cardinality();
+    assert_eq!(snap.records, 3);
+    assert_eq!(snap.max_records, Some(3));
+    assert_eq!(snap.evictions, 4);
+
+    // Only protected records left to evict: the store runs over the cap instead.
+    let store = InMemoryReputationStore::new().with_limits(guard(1, false));
+    see(&store, "bad-1", 100, NOW);
+    see(&store, "bad-2", 100, NOW);
+    see(&store, "bad-3", 100, NOW);
+    assert!(has(&store, "bad-1") && has(&store, "bad-2") && has(&store, "bad-3"));
+    assert_eq!(store.cardinality().records, 3);
+    assert_eq!(store.cardinality().evictions, 0);
+
+    // A clean key is kept while it is being recorded, then goes first.
+    see(&store, "clean-1", 0, NOW);
+    assert_eq!(store.cardinality().records, 4);
+    see(&store, "clean-2", 0, NOW);
+    assert!(!has(&store, "clean-1") && has(&store, "clean-2"));
+    assert_eq!(store.cardinality().evictions, 1);
+}
+
+#[test]
+fn surviving_records_score_as_in_an_unbounded_store() {
+    let bounded = InMemoryReputationStore::new().with_limits(guard(4, false));
+    let unbounded = InMemoryReputationStore::new();
+    let traffic = [
+        ("a", 30, NOW - 90_000),
+        ("b", 0, NOW - 80_000),
+        ("a", 60, NOW - 70_000),
+        ("c", 10, NOW - 60_000),
+        ("d", 0, NOW - 50_000),
+        ("e", 5, NOW - 40_000),
+        ("b", 0, NOW - 30_000),
+        ("f", 0, NOW - 20_000),
+        ("c", 15, NOW - 10_000),
+    ];
+    for (source, threat, at) in traffic {
+        see(&bounded, source, threat, at);
+        see(&unbounded, source, threat, at);
+    }
+    assert!(bounded.cardinality().evictions > 0);
+
+    let t = ReputationThresholds::from_env();
+    for rec in bounded.list() {
+        let full = unbounded.get(&rec.key).unwrap();
+        assert_eq!(
+            serde_json::to_value(&rec).unwrap(),
+            serde_json::to_value(&full).unwrap()
+        );
+        assert_eq!(
+            risk_breakdown(NOW, &rec, &t).effective_risk,
+            risk_breakdown(NOW, &full, &t).effective_risk
+        );
+    }
+}
+
+#[test]
+fn prefilter_allocates_on_the_second_clean_sighting_or_the_first_risky_one() {
+    let store = InMemoryReputationStore::new().with_limits(guard(0, true));
+
+    let mut obs = reputation::observation(
+        "once".to_string(),
+        Some("one-off.example".to_string()),
+        0,
+        vec![],
+    );
+    obs.now_unix = NOW;
+    let reported = store.record(obs.clone());
+    // Reported as after one sighting, but nothing stored.
+    assert_eq!(reported.len(), 2);
+    assert_eq!(reported[0].seen_count, 1);
+    assert!(store.list().is_empty());
+    assert_eq!(store.cardinality().prefilter_suppressed, 2);
+
+    store.record(obs);
+    assert_eq!(store.get("source_id:once").unwrap().seen_count, 1);
+    assert_eq!(store.get("host:one-off.example").unwrap().seen_count, 1);
+
+    see(&store, "risky", 30, NOW);
+    assert_eq!(store.get("source_id:risky").unwrap().risk_score, 30);
+
+    let snap = store.cardinality();
+    assert!(snap.prefilter);
+    assert_eq!(snap.records, 3);
+    assert_eq!(snap.prefilter_suppressed, 2);
+}
+
+#[test]
+fn file_store_past_the_cap_is_trimmed_on_load_and_write() {
+    // Trimming on load scores at the wall clock.
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    {
+        let store = JsonFileReputationStore::load_or_create(&path).unwrap();
+        see(&store, "bad", 100, now);
+        see(&store, "quiet-1", 0, now - 100);
+        see(&store, "quiet-2", 0, now);
+    }
+
+    let store = JsonFileReputationStore::load_or_create(&path)
+        .unwrap()
+        .with_limits(guard(2, false));
+    assert!(!has(&store, "quiet-1"));
+    assert!(has(&store, "bad") && has(&store, "quiet-2"));
+
+    see(&store, "quiet-3", 0, now + 10);
+    drop(store);
+    let reopened = JsonFileReputationStore::load_or_create(&path).unwrap();
+    let mut keys = reopened.keys();
+    keys.sort();
+    assert_eq!(keys, ["source_id:bad", "source_id:quiet-3"]);
+}
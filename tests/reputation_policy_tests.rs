@@ -1,5 +1,9 @@
-use acip_sidecar::reputation::ReputationRecord;
-use acip_sidecar::reputation_policy::{apply_reputation, ReputationThresholds};
+use acip_sidecar::reasons::{ReasonSet, ReasonStage};
+use acip_sidecar::reputation::{Clock, MockClock, ReputationRecord};
+use acip_sidecar::reputation_policy::{
+    apply_reputation, apply_reputation_with_clock, rank_records, risk_breakdown,
+    ReputationThresholds,
+};
 use acip_sidecar::sentry::{Action, Decision, RiskLevel};
 
 fn base_decision(tools_allowed: bool) -> Decision {
@@ -10,6 +14,7 @@ fn base_decision(tools_allowed: bool) -> Decision {
         fenced_content: "```external\nX\n```".to_string(),
         reasons: vec![],
         detected_patterns: vec![],
+        confidence: None,
     }
 }
 
@@ -32,6 +37,9 @@ fn reputation_bumps_risk() {
         bad_actor_score: 150,
         half_life_base_days: 2.0,
         half_life_k: 0.5,
+        trust_max_discount: 0.0,
+        trust_full_age_days: 365.0,
+        trust_full_clean_ingests: 1000,
     };
 
     let out = apply_reputation(base_decision(false), false, &[rec], &t);
@@ -60,6 +68,9 @@ fn explicit_tool_auth_overrides_until_bad_actor_cutoff() {
         bad_actor_score: 150,
         half_life_base_days: 2.0,
         half_life_k: 0.5,
+        trust_max_discount: 0.0,
+        trust_full_age_days: 365.0,
+        trust_full_clean_ingests: 1000,
     };
 
     // Model wants tools, caller authorizes tools.
@@ -88,6 +99,9 @@ fn bad_actor_cutoff_always_caps_tools() {
         bad_actor_score: 150,
         half_life_base_days: 2.0,
         half_life_k: 0.5,
+        trust_max_discount: 0.0,
+        trust_full_age_days: 365.0,
+        trust_full_clean_ingests: 1000,
     };
 
     let out = apply_reputation(base_decision(true), true, &[rec], &t);
@@ -95,3 +109,219 @@ fn bad_actor_cutoff_always_caps_tools() {
     assert!(matches!(out.risk_level, RiskLevel::High));
     assert!(matches!(out.action, Action::NeedsReview));
 }
+
+const DAY: u64 = 86_400;
+
+fn trust_thresholds() -> ReputationThresholds {
+    ReputationThresholds {
+        medium_score: 20,
+        high_score: 50,
+        bad_actor_score: 150,
+        // Effectively no decay so only the trust discount differs.
+        half_life_base_days: 9999.0,
+        half_life_k: 0.0,
+        trust_max_discount: 0.5,
+        trust_full_age_days: 365.0,
+        trust_full_clean_ingests: 1000,
+    }
+}
+
+fn record(key: &str, risk: u64, first_seen: u64, total_ingests: u64, now: u64) -> ReputationRecord {
+    ReputationRecord {
+        key: key.to_string(),
+        risk_score: risk,
+        suspected_attack_count: 1,
+        seen_count: total_ingests,
+        first_seen_unix: first_seen,
+        last_seen_unix: now,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn aged_clean_source_lands_in_lower_band_than_new_source() {
+    let now = 4 * 365 * DAY;
+    let clock = MockClock::new(now);
+    let t = trust_thresholds();
+
+    let new_src = record("source_id:new", 30, now, 1, now);
+    let aged_src = record("source_id:partner", 30, now - 3 * 365 * DAY, 10_001, now);
+
+    let new_b = risk_breakdown(now, &new_src, &t);
+    let aged_b = risk_breakdown(now, &aged_src, &t);
+    assert_eq!(new_b.raw_risk, aged_b.raw_risk);
+    assert_eq!(new_b.effective_risk, 30);
+    assert_eq!(aged_b.effective_risk, 15);
+    assert!((aged_b.trust_discount - 0.5).abs() < 1e-9);
+
+    let out_new = apply_reputation_with_clock(base_decision(false), false, &[new_src], &t, &clock);
+    let out_aged =
+        apply_reputation_with_clock(base_decision(false), false, &[aged_src], &t, &clock);
+    assert!(matches!(out_new.risk_level, RiskLevel::Medium));
+    assert!(matches!(out_aged.risk_level, RiskLevel::Low));
+    assert!(out_aged
+        .reasons
+        .iter()
+        .any(|r| r.contains("trust_discount=0.50")));
+}
+
+#[test]
+fn trust_discount_does_not_soften_bad_actor_cutoff() {
+    let now = 4 * 365 * DAY;
+    let clock = MockClock::new(now);
+    let t = trust_thresholds();
+
+    let new_src = record("source_id:new", 200, now, 1, now);
+    let aged_src = record("source_id:partner", 200, now - 3 * 365 * DAY, 10_001, now);
+
+    assert_eq!(risk_breakdown(now, &aged_src, &t).trust_discount, 0.0);
+
+    for rec in [new_src, aged_src] {
+        let out = apply_reputation_with_clock(base_decision(true), true, &[rec], &t, &clock);
+        assert!(!out.tools_allowed);
+        assert!(matches!(out.risk_level, RiskLevel::High));
+        assert!(matches!(out.action, Action::NeedsReview));
+    }
+}
+
+#[test]
+fn trust_discount_grows_with_account_age() {
+    let start = 10 * DAY;
+    let clock = MockClock::new(start);
+    let t = trust_thresholds();
+    let rec = record("host:example.com", 30, start, 1, start);
+
+    let before = risk_breakdown(clock.now_unix(), &rec, &t).trust_discount;
+    clock.advance(365 * DAY);
+    let after = risk_breakdown(clock.now_unix(), &rec, &t).trust_discount;
+    assert!(after > before);
+}
+
+fn ranked_keys(now: u64, records: &[ReputationRecord], t: &ReputationThresholds) -> Vec<String> {
+    rank_records(now, records, t)
+        .into_iter()
+        .map(|(r, _)| r.key.clone())
+        .collect()
+}
+
+fn source_line(out: &Decision) -> &str {
+    out.reasons
+        .iter()
+        .find(|r| r.starts_with("source reputation:"))
+        .unwrap()
+}
+
+#[test]
+fn ties_fall_through_attacks_raw_risk_recency_then_key() {
+    let now = 4 * 365 * DAY;
+    let clock = MockClock::new(now);
+    let t = trust_thresholds();
+    let base = record("host:b.example", 30, now, 1, now);
+
+    // Same effective risk; more suspected attacks wins.
+    let attacks = ReputationRecord {
+        key: "host:c.example".to_string(),
+        suspected_attack_count: 2,
+        ..base.clone()
+    };
+    assert_eq!(
+        ranked_keys(now, &[base.clone(), attacks.clone()], &t)[0],
+        "host:c.example"
+    );
+
+    // Same effective risk and attacks; the higher raw risk wins (the aged source's 40 is
+    // discounted down to 20).
+    let plain = record("host:b.example", 20, now, 1, now);
+    let raw = record("host:d.example", 40, now - 3 * 365 * DAY, 10_001, now);
+    assert_eq!(
+        risk_breakdown(now, &raw, &t).effective_risk,
+        risk_breakdown(now, &plain, &t).effective_risk
+    );
+    assert_eq!(ranked_keys(now, &[plain, raw], &t)[0], "host:d.example");
+
+    // Same so far; the more recently seen wins.
+    let recent = ReputationRecord {
+        key: "host:e.example".to_string(),
+        last_seen_unix: now,
+        ..base.clone()
+    };
+    let older = ReputationRecord {
+        last_seen_unix: now - 60,
+        ..base.clone()
+    };
+    assert_eq!(ranked_keys(now, &[older, recent], &t)[0], "host:e.example");
+
+    // Identical but for the key; the smaller key wins.
+    let a = ReputationRecord {
+        key: "host:a.example".to_string(),
+        ..base.clone()
+    };
+    for records in [[a.clone(), base.clone()], [base.clone(), a.clone()]] {
+        let out = apply_reputation_with_clock(base_decision(false), false, &records, &t, &clock);
+        assert!(source_line(&out).contains("key=host:a.example"), "{out:?}");
+    }
+}
+
+#[test]
+fn input_order_does_not_change_the_decision() {
+    let now = 4 * 365 * DAY;
+    let clock = MockClock::new(now);
+    let t = trust_thresholds();
+    let records = vec![
+        record("host:a.example", 30, now, 1, now),
+        record("host:b.example", 30, now, 1, now),
+        record("source_id:s1", 60, now, 1, now),
+        record("host:c.example", 5, now, 1, now),
+        ReputationRecord {
+            last_seen_unix: now - DAY,
+            ..record("host:d.example", 60, now - DAY, 1, now)
+        },
+    ];
+
+    let expected = apply_reputation_with_clock(base_decision(true), true, &records, &t, &clock);
+    let mut shuffled = records.clone();
+    for i in 0..records.len() * 4 {
+        shuffled.rotate_left(1 + i % 3);
+        shuffled.swap(0, i % records.len());
+        let out = apply_reputation_with_clock(base_decision(true), true, &shuffled, &t, &clock);
+        assert_eq!(out.reasons, expected.reasons, "{shuffled:?}");
+        assert_eq!(out.risk_level, expected.risk_level);
+        assert_eq!(out.tools_allowed, expected.tools_allowed);
+    }
+    assert!(source_line(&expected).contains("key=source_id:s1"));
+}
+
+#[test]
+fn every_key_above_the_medium_threshold_is_reported() {
+    let now = 4 * 365 * DAY;
+    let clock = MockClock::new(now);
+    let t = trust_thresholds();
+    let records = [
+        record("host:low.example", 10, now, 1, now),
+        record("host:mid.example", 25, now, 1, now),
+        record("source_id:doc-7", 40, now, 1, now),
+        record("host:worst.example", 90, now, 1, now),
+    ];
+
+    let out = apply_reputation_with_clock(base_decision(false), false, &records, &t, &clock);
+    assert!(source_line(&out).contains("key=host:worst.example"));
+    assert!(matches!(out.risk_level, RiskLevel::High));
+    // The riskiest key is named once, by the source line; the rest follow in rank order.
+    assert_eq!(
+        out.reasons,
+        [
+            "source reputation: key=host:worst.example effective_risk=90 raw_risk=90 suspected_attacks=1 trust_discount=0.00",
+            "reputation flagged: key=source_id:doc-7 effective_risk=40 raw_risk=40 suspected_attacks=1",
+            "reputation flagged: key=host:mid.example effective_risk=25 raw_risk=25 suspected_attacks=1",
+        ]
+    );
+
+    let mut set = ReasonSet::default();
+    set.extend(ReasonStage::Reputation, out.reasons.clone());
+    let flagged: Vec<String> = set
+        .sorted()
+        .into_iter()
+        .filter_map(|r| r.id.strip_prefix("reputation.flagged:").map(str::to_string))
+        .collect();
+    assert_eq!(flagged, ["host:mid.example", "source_id:doc-7"]);
+}
@@ -0,0 +1,264 @@
+use acip_sidecar::app_state_builder::AppStateBuilder;
+use acip_sidecar::config::{RedactionConfig, RedactionRuleConfig, ResponseHeadersConfig};
+use acip_sidecar::jobs::{self, JobState};
+use acip_sidecar::redact::Redaction;
+use acip_sidecar::risk_headers::{self, RiskHeaders};
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::{app, state};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+const PHISH: &str = "URGENT: the CFO needs the wire sent immediately, new bank details attached.";
+
+fn model() -> ScriptedModel {
+    ScriptedModel::benign()
+        .on(
+            "wire sent",
+            json!({
+                "tools_allowed": false,
+                "risk_level": "high",
+                "action": "block",
+                "reasons": ["scripted: payment fraud"],
+                "detected_patterns": ["wire_fraud", "ceo_fraud", "urgency", "new_bank_details"],
+            }),
+        )
+        .on("[garbled]", json!("this is not a verdict"))
+}
+
+fn test_state(builder: AppStateBuilder) -> Arc<state::AppState> {
+    std::env::remove_var("ACIP_SENTRY_MODE");
+    std::env::remove_var("ACIP_AUDIT_MODE");
+    builder.with_model(Arc::new(model())).build().unwrap()
+}
+
+fn router(st: Arc<state::AppState>) -> Router {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, None, extra)
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let (parts, body) = resp.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+    (
+        parts.status,
+        parts.headers,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+async fn ingest(app: &Router, query: &str, body: Value) -> (StatusCode, HeaderMap, Value) {
+    let req = Request::post(format!("/v1/acip/ingest_source{query}"))
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, req).await
+}
+
+fn text(text: &str) -> Value {
+    json!({
+        "source_id": "mail-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": text,
+    })
+}
+
+fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).map(|v| v.to_str().unwrap())
+}
+
+/// The summary headers say exactly what the body says.
+fn assert_mirrors(headers: &HeaderMap, body: &Value) {
+    assert_eq!(header(headers, "x-acip-action"), body["action"].as_str());
+    assert_eq!(
+        header(headers, "x-acip-risk-level"),
+        body["risk_level"].as_str()
+    );
+    assert_eq!(
+        header(headers, "x-acip-tools-allowed"),
+        Some(
+            body["tools_allowed"]
+                .as_bool()
+                .unwrap()
+                .to_string()
+                .as_str()
+        )
+    );
+    assert_eq!(
+        header(headers, "x-acip-request-id"),
+        body["origin"]["request_id"].as_str()
+    );
+}
+
+fn assert_no_summary(headers: &HeaderMap) {
+    for name in risk_headers::NAMES {
+        assert!(headers.get(name).is_none(), "{name}: {headers:?}");
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn decisions_carry_summary_headers() {
+    let app = router(test_state(AppStateBuilder::for_tests()));
+
+    let (code, headers, v) = ingest(&app, "", text("Minutes of the weekly sync.")).await;
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(header(&headers, "x-acip-action"), Some("allow"));
+    assert_mirrors(&headers, &v);
+
+    let (code, headers, v) = ingest(&app, "", text(PHISH)).await;
+    assert_eq!(code, StatusCode::OK);
+    assert_eq!(header(&headers, "x-acip-action"), Some("block"));
+    assert_eq!(header(&headers, "x-acip-risk-level"), Some("high"));
+    assert_eq!(header(&headers, "x-acip-tools-allowed"), Some("false"));
+    assert_mirrors(&headers, &v);
+    // Opt-in only.
+    assert!(!v["detected_patterns"].as_array().unwrap().is_empty());
+    assert!(headers.get("x-acip-patterns").is_none());
+
+    // A verdict that never parses fails closed; that is still a decision.
+    let (code, headers, v) = ingest(&app, "", text("[garbled] minutes")).await;
+    assert_eq!(code, StatusCode::OK);
+    assert_ne!(v["action"], "allow", "{v}");
+    assert_mirrors(&headers, &v);
+}
+
+#[tokio::test]
+#[serial]
+async fn patterns_header_is_opt_in_and_capped() {
+    let st =
+        test_state(AppStateBuilder::for_tests().with_risk_headers(RiskHeaders { max_patterns: 2 }));
+    let (_, headers, v) = ingest(&router(st), "", text(PHISH)).await;
+    let patterns: Vec<&str> = v["detected_patterns"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p.as_str().unwrap())
+        .collect();
+    assert!(patterns.len() > 2, "{v}");
+    assert_eq!(
+        header(&headers, "x-acip-patterns"),
+        Some(patterns[..2].join(",").as_str())
+    );
+
+    // No patterns, no header.
+    let st =
+        test_state(AppStateBuilder::for_tests().with_risk_headers(RiskHeaders { max_patterns: 2 }));
+    let (_, headers, v) = ingest(&router(st), "", text("Minutes of the weekly sync.")).await;
+    assert_eq!(v["detected_patterns"], json!([]));
+    assert!(headers.get("x-acip-patterns").is_none());
+    assert_mirrors(&headers, &v);
+}
+
+#[tokio::test]
+#[serial]
+async fn headers_follow_the_shaped_body() {
+    // Audit mode adds fields and output redaction rewrites them; the headers must still agree.
+    let redaction = Redaction::from_config(Some(&RedactionConfig {
+        rules: vec![RedactionRuleConfig {
+            label: "case".to_string(),
+            literal: Some("ceo_fraud".to_string()),
+            regex: None,
+        }],
+    }))
+    .unwrap();
+    let st = test_state(
+        AppStateBuilder::for_tests()
+            .with_redaction(Arc::new(redaction))
+            .with_risk_headers(RiskHeaders { max_patterns: 5 }),
+    );
+    std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    let (code, headers, v) = ingest(&router(st), "", text(PHISH)).await;
+    std::env::remove_var("ACIP_AUDIT_MODE");
+    assert_eq!(code, StatusCode::OK);
+    assert!(v.get("threat_audit").is_some(), "{v}");
+    assert_mirrors(&headers, &v);
+
+    let patterns = header(&headers, "x-acip-patterns").unwrap();
+    assert!(patterns.contains("[REDACTED:case]"), "{patterns}");
+    assert!(!patterns.contains("ceo_fraud"), "{patterns}");
+    let body: Vec<&str> = v["detected_patterns"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .take(5)
+        .map(|p| p.as_str().unwrap())
+        .collect();
+    assert_eq!(patterns, body.join(","));
+}
+
+#[tokio::test]
+#[serial]
+async fn errors_carry_no_summary_headers() {
+    let st =
+        test_state(AppStateBuilder::for_tests().with_risk_headers(RiskHeaders { max_patterns: 5 }));
+    let app = router(st);
+
+    let mut bad = text(PHISH);
+    bad["source_type"] = json!("email");
+    let (code, headers, _) = ingest(&app, "", bad).await;
+    assert!(code.is_client_error(), "{code}");
+    assert_no_summary(&headers);
+
+    let req = Request::post("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-policy", "nope")
+        .body(Body::from(text(PHISH).to_string()))
+        .unwrap();
+    let (code, headers, _) = send(&app, req).await;
+    assert!(code.is_client_error(), "{code}");
+    assert_no_summary(&headers);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[serial]
+async fn async_jobs_carry_headers_once_complete() {
+    let st = test_state(AppStateBuilder::for_tests());
+    jobs::start(st.clone());
+    let app = router(st);
+
+    let (code, headers, job) = ingest(&app, "?mode=async", text(PHISH)).await;
+    assert_eq!(code, StatusCode::ACCEPTED);
+    assert_no_summary(&headers);
+
+    let uri = format!("/v1/acip/jobs/{}", job["job_id"].as_str().unwrap());
+    for _ in 0..200 {
+        let (code, headers, v) = send(&app, Request::get(&uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(code, StatusCode::OK);
+        if v["status"] == json!(JobState::Complete) {
+            assert_eq!(header(&headers, "x-acip-action"), Some("block"));
+            assert_mirrors(&headers, &v["result"]);
+            return;
+        }
+        assert_no_summary(&headers);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("job did not complete");
+}
+
+#[test]
+fn patterns_header_config() {
+    assert_eq!(RiskHeaders::from_config(None).unwrap().max_patterns, 0);
+    let cfg = |patterns, max_patterns| ResponseHeadersConfig {
+        patterns,
+        max_patterns,
+    };
+    let max = |c| RiskHeaders::from_config(Some(&c)).map(|h| h.max_patterns);
+    assert_eq!(max(cfg(None, Some(3))).unwrap(), 0);
+    assert_eq!(max(cfg(Some(true), None)).unwrap(), 5);
+    assert_eq!(max(cfg(Some(true), Some(3))).unwrap(), 3);
+    assert!(max(cfg(Some(true), Some(0))).is_err());
+    assert!(max(cfg(Some(true), Some(33))).is_err());
+}
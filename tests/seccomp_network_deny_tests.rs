@@ -1,6 +1,7 @@
 #![cfg(target_os = "linux")]
 
 use acip_sidecar::extract::{run_helper, ExtractKind, ExtractRequest, ExtractorError};
+use acip_sidecar::tmpdir::TmpDirManager;
 use serial_test::serial;
 use std::time::Duration;
 
@@ -22,11 +23,17 @@ fn extractor_seccomp_denies_network_syscalls_when_enabled() {
         max_pages: None,
         dpi: None,
         max_output_chars: None,
+        force_ocr: false,
+        password: None,
     };
 
-    let err = run_helper(&req, b"<svg></svg>", Duration::from_secs(10))
-        .err()
-        .expect("expected extractor to fail under selftest");
+    let err = run_helper(
+        &TmpDirManager::default(),
+        &req,
+        b"<svg></svg>",
+        Duration::from_secs(10),
+    )
+    .expect_err("expected extractor to fail under selftest");
 
     match err {
         ExtractorError::NonZeroExit { stderr, .. } => {
@@ -0,0 +1,258 @@
+use acip_sidecar::config::TokenConfig;
+use acip_sidecar::extract::ExtractRequest;
+use acip_sidecar::ingest::{self, IngestRequest};
+use acip_sidecar::secrets::{Secret, SecretStore};
+use acip_sidecar::sentry::{AnthropicClient, GeminiClient, ModelClient};
+use acip_sidecar::test_support::{self, leak_scan, ScriptedModel};
+use acip_sidecar::{app, startup};
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+use tower::ServiceExt;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+struct Store(HashMap<&'static str, &'static str>);
+
+impl SecretStore for Store {
+    fn get(&self, key: &str) -> Option<Secret> {
+        self.0.get(key).map(|v| Secret::new(*v))
+    }
+}
+
+async fn body_text(resp: axum::response::Response) -> String {
+    let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+/// A provider stub on an ephemeral port that answers only when `header` carries `key`.
+async fn provider(header: &'static str, key: &'static str, reply: Value) -> String {
+    let router = Router::new().fallback(move |headers: HeaderMap| {
+        let reply = reply.clone();
+        async move {
+            if headers.get(header).and_then(|v| v.to_str().ok()) != Some(key) {
+                return (StatusCode::UNAUTHORIZED, Json(json!({"error": "bad key"})));
+            }
+            (StatusCode::OK, Json(reply))
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{addr}")
+}
+
+#[test]
+fn secrets_print_redacted_and_deserialize_from_strings() {
+    let s = Secret::new("hunter2-debug");
+    assert_eq!(format!("{s:?}"), "[REDACTED]");
+    assert_eq!(s.to_string(), "[REDACTED]");
+    assert_eq!(s.expose(), "hunter2-debug");
+    assert!(s.ct_eq("hunter2-debug") && !s.ct_eq("hunter2"));
+
+    let req: IngestRequest = serde_json::from_value(json!({
+        "source_id": "s", "source_type": "pdf", "content_type": "application/pdf",
+        "document_password": "hunter2-ingest",
+    }))
+    .unwrap();
+    assert_eq!(
+        req.document_password.as_ref().map(Secret::expose),
+        Some("hunter2-ingest")
+    );
+    assert!(!format!("{req:?}").contains("hunter2"), "{req:?}");
+
+    // The helper's request line is the one place the password is serialized, by opt-in.
+    let line: ExtractRequest =
+        serde_json::from_value(json!({"kind": "pdf", "password": "hunter2-helper"})).unwrap();
+    assert!(!format!("{line:?}").contains("hunter2"));
+    let wire = serde_json::to_value(&line).unwrap();
+    assert_eq!(wire["password"], "hunter2-helper");
+    let none: ExtractRequest = serde_json::from_value(json!({"kind": "pdf"})).unwrap();
+    assert!(serde_json::to_value(&none)
+        .unwrap()
+        .get("password")
+        .is_none());
+}
+
+#[test]
+fn secrets_zeroize_on_drop_and_on_request() {
+    fn zeroized_on_drop<T: ZeroizeOnDrop>() {}
+    zeroized_on_drop::<Secret>();
+
+    let mut s = Secret::new("wipe-me");
+    let copy = s.clone();
+    s.zeroize();
+    assert_eq!(s.expose(), "");
+    assert!(s.is_blank());
+    // Clones own their buffer.
+    assert_eq!(copy.expose(), "wipe-me");
+}
+
+#[test]
+fn the_scan_catches_a_planted_leak() {
+    const PLANTED: &str = "planted-7f3a-secret";
+    let scan = leak_scan();
+    scan.watch(PLANTED);
+
+    let secret = Secret::new(PLANTED);
+    tracing::info!(token = ?secret, "wrapped: {secret}");
+    scan.assert_not_leaked(PLANTED);
+
+    tracing::debug!("careless: {}", secret.expose());
+    let err = anyhow::anyhow!("lookup failed").context(format!("key {}", secret.expose()));
+    tracing::warn!(error = %format!("{err:#}"), "nested");
+    scan.check("response", &json!({"echo": secret.expose()}).to_string());
+    let leaks = scan.leaks_of(PLANTED);
+    assert_eq!(leaks.len(), 3, "{leaks:#?}");
+    assert!(leaks[2].starts_with("response: "), "{leaks:#?}");
+    let caught = std::panic::catch_unwind(|| scan.assert_not_leaked(PLANTED));
+    assert!(caught.is_err());
+}
+
+#[tokio::test]
+async fn token_auth_works_through_the_wrapper_without_leaking() {
+    const TOKEN: &str = "tok-legacy-51c0";
+    const NAMED: &str = "tok-named-9e21";
+    let scan = leak_scan();
+    scan.watch(TOKEN);
+    scan.watch(NAMED);
+
+    let store: Arc<dyn SecretStore> = Arc::new(Store(
+        [("ACIP_AUTH_TOKEN", TOKEN), ("REVIEW_TOKEN", NAMED)].into(),
+    ));
+    let named = [TokenConfig {
+        name: "review".to_string(),
+        secret: "REVIEW_TOKEN".to_string(),
+        scopes: vec!["read".to_string()],
+    }];
+    let tokens = startup::resolve_tokens(true, &store, "ACIP_AUTH_TOKEN", &named).unwrap();
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let app = app::build_router_with_tokens(st, tokens, Router::new());
+
+    for (token, want) in [
+        (Some(TOKEN), StatusCode::OK),
+        (Some(NAMED), StatusCode::OK),
+        (Some("tok-wrong"), StatusCode::UNAUTHORIZED),
+        (None, StatusCode::UNAUTHORIZED),
+    ] {
+        let mut req = Request::get("/v1/acip/status");
+        if let Some(t) = token {
+            req = req.header("x-acip-token", t);
+        }
+        let resp = app
+            .clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), want, "{token:?}");
+        scan.check("status", &body_text(resp).await);
+    }
+    scan.assert_not_leaked(TOKEN);
+    scan.assert_not_leaked(NAMED);
+}
+
+#[tokio::test]
+async fn document_passwords_stay_out_of_logs_and_responses() {
+    const PASSWORD: &str = "pdf-pass-0c4d";
+    let scan = leak_scan();
+    scan.watch(PASSWORD);
+
+    let st = test_support::golden_state(Some(Arc::new(ScriptedModel::benign()))).unwrap();
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    let app = app::build_router(st, None, extra);
+    let body = json!({
+        "source_id": "mail-1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Quarterly numbers attached.",
+        "document_password": PASSWORD,
+    });
+    let resp = app
+        .oneshot(
+            Request::post("/v1/acip/ingest_source")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    scan.check("ingest", &body_text(resp).await);
+    scan.assert_not_leaked(PASSWORD);
+}
+
+#[tokio::test]
+async fn provider_keys_are_sent_in_headers_and_never_logged() {
+    const ANTHROPIC: &str = "sk-ant-test-4b1e";
+    const GEMINI: &str = "gm-test-key-77aa";
+    let scan = leak_scan();
+    scan.watch(ANTHROPIC);
+    scan.watch(GEMINI);
+
+    let store: Arc<dyn SecretStore> = Arc::new(Store(
+        [("ANTHROPIC_API_KEY", ANTHROPIC), ("GEMINI_API_KEY", GEMINI)].into(),
+    ));
+    let http = reqwest::Client::new();
+    let anthropic_url = provider(
+        "x-api-key",
+        ANTHROPIC,
+        json!({"content": [{"text": "from anthropic"}], "model": "claude-test"}),
+    )
+    .await;
+    let gemini_url = provider(
+        "x-goog-api-key",
+        GEMINI,
+        json!({"candidates": [{"content": {"parts": [{"text": "from gemini"}]}}]}),
+    )
+    .await;
+
+    let anthropic = AnthropicClient::new(http.clone(), store.clone()).with_base_url(&anthropic_url);
+    let gemini = GeminiClient::new(http.clone(), store.clone()).with_base_url(&gemini_url);
+    let headers = HeaderMap::new();
+    assert_eq!(
+        anthropic.generate("m", "hi", &headers).await.unwrap(),
+        "from anthropic"
+    );
+    assert_eq!(
+        gemini.generate("m", "hi", &headers).await.unwrap(),
+        "from gemini"
+    );
+
+    // A refused key and an unreachable provider: neither error names the key.
+    let wrong: Arc<dyn SecretStore> = Arc::new(Store([("GEMINI_API_KEY", "nope")].into()));
+    let err = GeminiClient::new(http.clone(), wrong)
+        .with_base_url(&gemini_url)
+        .generate("m", "hi", &headers)
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("401"), "{err:#}");
+    let err = GeminiClient::new(http, store)
+        .with_base_url("http://127.0.0.1:9")
+        .generate("m", "hi", &headers)
+        .await
+        .unwrap_err();
+    scan.check("gemini error", &format!("{err:#} / {err:?}"));
+    tracing::warn!(error = %format!("{err:#}"), "model call failed");
+
+    scan.assert_not_leaked(ANTHROPIC);
+    scan.assert_not_leaked(GEMINI);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn core_dumps_can_be_disabled() {
+    acip_sidecar::secrets::disable_core_dumps().unwrap();
+    assert_eq!(unsafe { libc::prctl(libc::PR_GET_DUMPABLE, 0, 0, 0, 0) }, 0);
+    let mut lim = libc::rlimit {
+        rlim_cur: 1,
+        rlim_max: 1,
+    };
+    assert_eq!(unsafe { libc::getrlimit(libc::RLIMIT_CORE, &mut lim) }, 0);
+    assert_eq!((lim.rlim_cur, lim.rlim_max), (0, 0));
+}
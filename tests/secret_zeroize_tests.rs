@@ -0,0 +1,90 @@
+//! Drop check for [`Secret`]: an allocator that looks at every block as it is freed reports
+//! whether a canary value was still in it.
+
+use acip_sidecar::secrets::{EnvFileStore, Secret, SecretStore};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SECRET_CANARY: &[u8] = b"canary-secret-5d2e91";
+const PLAIN_CANARY: &[u8] = b"canary-plain-0b7c44";
+const FILE_CANARY: &[u8] = b"canary-file-a8f310";
+
+static FREED_WITH: [AtomicUsize; 3] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+struct Inspecting;
+
+unsafe impl GlobalAlloc for Inspecting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // Still ours until `System.dealloc`, so reading it is sound.
+        let block = std::slice::from_raw_parts(ptr, layout.size());
+        for (i, canary) in [SECRET_CANARY, PLAIN_CANARY, FILE_CANARY]
+            .iter()
+            .enumerate()
+        {
+            if block.windows(canary.len()).any(|w| w == *canary) {
+                FREED_WITH[i].fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: Inspecting = Inspecting;
+
+fn freed_with(i: usize) -> usize {
+    FREED_WITH[i].load(Ordering::SeqCst)
+}
+
+fn canary(bytes: &[u8]) -> String {
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+#[test]
+fn a_dropped_secret_leaves_nothing_behind() {
+    // Control: a plain String is freed with its contents intact, so the check can see one.
+    drop(std::hint::black_box(canary(PLAIN_CANARY)));
+    assert!(freed_with(1) > 0);
+
+    let secret = Secret::new(canary(SECRET_CANARY));
+    let clone = secret.clone();
+    assert_eq!(clone.expose().as_bytes(), SECRET_CANARY);
+    drop(std::hint::black_box(secret));
+    drop(std::hint::black_box(clone));
+    assert_eq!(freed_with(0), 0);
+}
+
+#[test]
+fn secrets_file_contents_are_wiped_after_loading() {
+    let dir = tempfile::tempdir().unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(dir.path(), std::fs::Permissions::from_mode(0o700)).unwrap();
+    }
+    let path = dir.path().join("secrets.env");
+    std::fs::write(&path, format!("API_KEY={}\n", canary(FILE_CANARY))).unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    }
+
+    // Writing the file freed plain copies; only what happens from here on counts.
+    let before = freed_with(2);
+    let store = EnvFileStore::load(&path).unwrap();
+    assert_eq!(
+        store.get("API_KEY").unwrap().expose().as_bytes(),
+        FILE_CANARY
+    );
+    drop(store);
+    assert_eq!(freed_with(2), before);
+}
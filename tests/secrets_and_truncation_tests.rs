@@ -69,6 +69,6 @@ fn secrets_file_permissions_enforced() {
         // Now make file private too.
         fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
         let store = secrets::EnvFileStore::load(&path).unwrap();
-        assert_eq!(store.get("KEY").unwrap(), "value");
+        assert_eq!(store.get("KEY").unwrap().expose(), "value");
     }
 }
@@ -64,11 +64,29 @@ fn policy() -> PolicyConfig {
         l1: acip_sidecar::model_policy::ModelRef {
             provider: Provider::Gemini,
             model: "gemini-2.0-flash".to_string(),
+            required_model_version: None,
+            consistency_check: false,
+            context_tokens: None,
         },
         l2: acip_sidecar::model_policy::ModelRef {
             provider: Provider::Anthropic,
             model: "claude-3-5-haiku-latest".to_string(),
+            required_model_version: None,
+            consistency_check: false,
+            context_tokens: None,
         },
+        cache: Default::default(),
+        verdict_parsing: Default::default(),
+        on_garbled_text: Default::default(),
+        on_version_mismatch: Default::default(),
+        on_low_confidence: Default::default(),
+        on_encrypted: Default::default(),
+        on_extractor_unavailable: Default::default(),
+        content_types: vec![],
+        url_allowlist: Default::default(),
+        active_probing: false,
+        locale: None,
+        remediation: Default::default(),
     }
 }
 
@@ -8,6 +8,23 @@ fn token_env_defaults_when_missing() {
         policy: None,
         security: None,
         normalize: None,
+        reputation: None,
+        redaction: None,
+        loop_protection: None,
+        content_types: None,
+        siem: None,
+        notify: None,
+        cors: None,
+        startup: None,
+        chunking: None,
+        probing: None,
+        remediation: None,
+        response_headers: None,
+        regex: None,
+        telemetry: None,
+        hashing: None,
+        patterns: vec![],
+        feeds: vec![],
     };
     assert_eq!(server_config::token_env(Some(&cfg)), "ACIP_AUTH_TOKEN");
     assert_eq!(server_config::token_env(None), "ACIP_AUTH_TOKEN");
@@ -21,6 +38,23 @@ fn allow_insecure_loopback_defaults_true() {
         policy: None,
         security: None,
         normalize: None,
+        reputation: None,
+        redaction: None,
+        loop_protection: None,
+        content_types: None,
+        siem: None,
+        notify: None,
+        cors: None,
+        startup: None,
+        chunking: None,
+        probing: None,
+        remediation: None,
+        response_headers: None,
+        regex: None,
+        telemetry: None,
+        hashing: None,
+        patterns: vec![],
+        feeds: vec![],
     };
     assert!(server_config::allow_insecure_loopback(Some(&cfg)));
     assert!(server_config::allow_insecure_loopback(None));
@@ -34,6 +68,23 @@ fn require_token_setting_defaults_true() {
         policy: None,
         security: None,
         normalize: None,
+        reputation: None,
+        redaction: None,
+        loop_protection: None,
+        content_types: None,
+        siem: None,
+        notify: None,
+        cors: None,
+        startup: None,
+        chunking: None,
+        probing: None,
+        remediation: None,
+        response_headers: None,
+        regex: None,
+        telemetry: None,
+        hashing: None,
+        patterns: vec![],
+        feeds: vec![],
     };
     assert!(server_config::require_token_setting(Some(&cfg)));
     assert!(server_config::require_token_setting(None));
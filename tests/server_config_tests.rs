@@ -27,6 +27,9 @@ fn cli_overrides_config() {
             host: Some("127.0.0.1".to_string()),
             port: Some(1111),
             unix_socket: None,
+            read_only: None,
+            on_client_disconnect: None,
+            blocking_pool_size: None,
         }),
         policy: Some(config::PolicyConfig {
             policies_file: Some("/etc/acip/policies.json".to_string()),
@@ -36,6 +39,23 @@ fn cli_overrides_config() {
         }),
         security: None,
         normalize: None,
+        reputation: None,
+        redaction: None,
+        loop_protection: None,
+        content_types: None,
+        siem: None,
+        notify: None,
+        cors: None,
+        startup: None,
+        chunking: None,
+        probing: None,
+        remediation: None,
+        response_headers: None,
+        regex: None,
+        telemetry: None,
+        hashing: None,
+        patterns: vec![],
+        feeds: vec![],
     };
 
     let cli = server_config::CliOverrides {
@@ -0,0 +1,282 @@
+use acip_sidecar::config::SiemConfig;
+use acip_sidecar::hashing::IdHasher;
+use acip_sidecar::redact::Redaction;
+use acip_sidecar::siem::{DecisionEvent, Mappings, SiemExport, SiemFormat, SiemSettings};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, reputation, state};
+use axum::{body::Body, http::StatusCode, Router};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/siem");
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{FIXTURES}/{name}")).unwrap()
+}
+
+/// The salt the golden files were rendered with.
+fn hasher() -> IdHasher {
+    IdHasher::new("siem-test-salt")
+}
+
+fn decision() -> DecisionEvent {
+    serde_json::from_str(&fixture("decision.json")).unwrap()
+}
+
+fn config(format: SiemFormat, destination: &str) -> SiemConfig {
+    SiemConfig {
+        format,
+        destination: destination.to_string(),
+        batch_size: None,
+        flush_interval_secs: None,
+        spool_capacity: None,
+        hash_observables: None,
+        mappings: None,
+    }
+}
+
+/// Dotted paths (array items by index) of the leaves that differ between `a` and `b`.
+fn changed_leaves(a: &Value, b: &Value, path: &str, out: &mut BTreeSet<String>) {
+    let child = |k: &str| {
+        if path.is_empty() {
+            k.to_string()
+        } else {
+            format!("{path}.{k}")
+        }
+    };
+    match (a, b) {
+        (Value::Object(x), Value::Object(y)) => {
+            for k in x.keys().chain(y.keys()) {
+                let null = Value::Null;
+                changed_leaves(
+                    x.get(k).unwrap_or(&null),
+                    y.get(k).unwrap_or(&null),
+                    &child(k),
+                    out,
+                );
+            }
+        }
+        (Value::Array(x), Value::Array(y)) if x.len() == y.len() => {
+            for (i, (x, y)) in x.iter().zip(y).enumerate() {
+                changed_leaves(x, y, &child(&i.to_string()), out);
+            }
+        }
+        _ if a != b => {
+            out.insert(path.to_string());
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn fixture_decision_matches_golden_files() {
+    let mappings = Mappings::load(None).unwrap();
+    for (format, golden) in [
+        (SiemFormat::Ocsf, "expected_ocsf.json"),
+        (SiemFormat::Ecs, "expected_ecs.json"),
+    ] {
+        let rendered = decision().render(mappings.format(format), &hasher(), true);
+        let expected: Value = serde_json::from_str(&fixture(golden)).unwrap();
+        assert_eq!(
+            rendered,
+            expected,
+            "{golden}:\n{}",
+            serde_json::to_string_pretty(&rendered).unwrap()
+        );
+    }
+}
+
+#[test]
+fn mapping_override_changes_exactly_the_overridden_fields() {
+    let base = Mappings::load(None).unwrap();
+    let custom = Mappings::load(Some(&fixture("mapping_override.toml"))).unwrap();
+
+    let diff = |format| {
+        let mut out = BTreeSet::new();
+        changed_leaves(
+            &decision().render(base.format(format), &hasher(), true),
+            &decision().render(custom.format(format), &hasher(), true),
+            "",
+            &mut out,
+        );
+        out
+    };
+    assert_eq!(
+        diff(SiemFormat::Ecs),
+        BTreeSet::from([
+            "event.severity".to_string(),
+            "threat.technique.id.2".to_string(),
+            "threat.technique.name.2".to_string(),
+        ])
+    );
+    assert_eq!(
+        diff(SiemFormat::Ocsf),
+        BTreeSet::from([
+            "disposition".to_string(),
+            "disposition_id".to_string(),
+            "metadata.product.vendor_name".to_string(),
+        ])
+    );
+}
+
+#[test]
+fn observables_are_hashed_unless_disabled() {
+    let mappings = Mappings::load(None).unwrap();
+    let d = decision();
+    let hashed = d.render(mappings.format(SiemFormat::Ecs), &hasher(), true);
+    let url = d.url.as_deref().unwrap();
+    assert_eq!(hashed["url"]["original"], hasher().export_id(url));
+    assert_ne!(
+        hashed["url"]["original"],
+        hex::encode(Sha256::digest(url.as_bytes()))
+    );
+    let plain = d.render(mappings.format(SiemFormat::Ecs), &hasher(), false);
+    assert_eq!(plain["url"]["original"], "https://mail.example.com/m/4411");
+    assert_eq!(plain["labels"]["acip_source_id"], "mail:4411");
+}
+
+#[test]
+fn invalid_mappings_and_destinations_are_refused() {
+    let err = Mappings::load(Some("[ecs.fields]\n\"user.email\" = \"email\"\n")).unwrap_err();
+    assert!(err.to_string().contains("unknown attribute"), "{err}");
+    let err = Mappings::load(Some("[ocsf.severity.critical]\n\"severity_id\" = 5\n")).unwrap_err();
+    assert!(err.to_string().contains("unknown risk level"), "{err}");
+
+    let no_hosts = HashSet::new();
+    let err = SiemSettings::from_config(
+        &config(SiemFormat::Ocsf, "https://siem.example.com/ingest"),
+        &no_hosts,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("ACIP_EGRESS_HOSTS"), "{err}");
+    let err = SiemSettings::from_config(
+        &config(SiemFormat::Ocsf, "http://siem.example.com/ingest"),
+        &HashSet::from(["siem.example.com".to_string()]),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("scheme must be https"), "{err}");
+
+    SiemSettings::from_config(
+        &config(SiemFormat::Ocsf, "https://siem.example.com/ingest"),
+        &HashSet::from(["siem.example.com".to_string()]),
+    )
+    .unwrap();
+}
+
+fn app_state(siem: SiemExport) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    AppStateBuilder::for_tests()
+        .with_siem(Arc::new(siem))
+        .with_hashing(Arc::new(hasher()))
+        .build()
+        .unwrap()
+}
+
+fn sidecar(state: Arc<state::AppState>) -> Router {
+    let ingest = Router::new().route(
+        "/v1/acip/ingest_source",
+        axum::routing::post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(state, None, ingest)
+}
+
+#[tokio::test]
+async fn ingest_decisions_are_spooled_and_shipped_to_a_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("decisions.jsonl");
+    let settings = SiemSettings::from_config(
+        &config(SiemFormat::Ocsf, out.to_str().unwrap()),
+        &HashSet::new(),
+    )
+    .unwrap();
+    let state = app_state(SiemExport::new(
+        Some(settings),
+        Arc::new(reputation::SystemClock),
+    ));
+
+    let body = json!({
+        "source_id": "doc-1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Quarterly numbers attached.",
+    });
+    let resp = sidecar(state.clone())
+        .oneshot(
+            axum::http::Request::builder()
+                .method("POST")
+                .uri("/v1/acip/ingest_source")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let answer: Value = serde_json::from_slice(&bytes).unwrap();
+    assert!(answer["action"].is_string(), "response is passed through");
+
+    assert_eq!(state.siem.spooled(), 1);
+    assert_eq!(state.siem.flush(&reqwest::Client::new()).await, Ok(1));
+    assert_eq!(state.siem.spooled(), 0);
+
+    let lines = std::fs::read_to_string(&out).unwrap();
+    let events: Vec<Value> = lines
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event["class_uid"], 2004);
+    assert!(event["disposition_id"].is_number(), "{event}");
+    assert_eq!(
+        event["evidences"]["data"]["content_sha256"],
+        state
+            .hashing
+            .export_id(answer["digest"]["sha256"].as_str().unwrap())
+    );
+    assert_eq!(
+        event["observables"][0]["value"],
+        state.hashing.export_id("doc-1")
+    );
+    assert_eq!(state.siem.snapshot()["exported"], 1);
+}
+
+#[tokio::test]
+async fn failed_batches_stay_spooled_and_a_full_spool_drops_the_oldest() {
+    let dir = tempfile::tempdir().unwrap();
+    // A directory cannot be opened for appending.
+    let mut cfg = config(SiemFormat::Ecs, dir.path().to_str().unwrap());
+    cfg.spool_capacity = Some(2);
+    let siem = SiemExport::new(
+        Some(SiemSettings::from_config(&cfg, &HashSet::new()).unwrap()),
+        Arc::new(reputation::SystemClock),
+    );
+
+    let redaction = Redaction::default();
+    for id in ["a", "b", "c"] {
+        siem.offer(
+            &redaction,
+            &hasher(),
+            &DecisionEvent {
+                source_id: id.to_string(),
+                ..decision()
+            },
+        );
+    }
+    assert_eq!(siem.spooled(), 2);
+
+    assert!(siem.flush(&reqwest::Client::new()).await.is_err());
+    assert_eq!(siem.spooled(), 2, "the failed batch is kept for a retry");
+    let snap = siem.snapshot();
+    assert_eq!(snap["dropped"], 1);
+    assert_eq!(snap["failed_batches"], 1);
+    assert_eq!(snap["exported"], 0);
+    assert!(snap["last_error"].is_string());
+}
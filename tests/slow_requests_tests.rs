@@ -0,0 +1,328 @@
+use acip_sidecar::reputation::{MockClock, SystemClock};
+use acip_sidecar::slow_requests::{
+    self, CaptureReason, RunInfo, SlowRequestLog, SlowRequestSettings, Stage, Timing,
+};
+use acip_sidecar::token_auth::{Scope, TokenSet};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as B64, Engine as _};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{fs, os::unix::fs::PermissionsExt, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+fn settings(threshold_ms: u64) -> SlowRequestSettings {
+    SlowRequestSettings {
+        threshold: Duration::from_millis(threshold_ms),
+        ..SlowRequestSettings::default()
+    }
+}
+
+fn test_state(log: SlowRequestLog) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+    AppStateBuilder::for_tests()
+        .with_slow_requests(Arc::new(log))
+        .build()
+        .unwrap()
+}
+
+fn extra() -> Router<Arc<state::AppState>> {
+    Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source))
+}
+
+fn router(log: SlowRequestLog) -> Router {
+    app::build_router(test_state(log), None, extra())
+}
+
+async fn call(app: &Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+fn ingest_request(body: Value, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut b = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json");
+    for (k, v) in headers {
+        b = b.header(*k, *v);
+    }
+    b.body(Body::from(body.to_string())).unwrap()
+}
+
+fn text_body() -> Value {
+    json!({
+        "source_id": "s1",
+        "source_type": "clipboard",
+        "content_type": "text/plain",
+        "text": "Meeting moved to Thursday at 10am.",
+    })
+}
+
+async fn list(app: &Router, token: Option<&str>) -> Vec<Value> {
+    let mut b = Request::builder().uri("/v1/acip/slow_requests?since=0");
+    if let Some(t) = token {
+        b = b.header("X-ACIP-Token", t);
+    }
+    let (status, v) = call(app, b.body(Body::empty()).unwrap()).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    v["records"].as_array().unwrap().clone()
+}
+
+/// An extractor that sleeps before answering with one character of text.
+fn slow_extractor(dir: &std::path::Path) -> std::path::PathBuf {
+    let script = dir.join("slow.sh");
+    fs::write(
+        &script,
+        "#!/bin/sh\nsleep 0.4\nout='{\"ok\":true,\"kind\":\"pdf\",\"text\":\"x\",\"warnings\":[],\"stats\":{\"text_chars\":1,\"ocr_used\":false,\"ocr_chars\":0}}'\nif [ -n \"$ACIP_EXTRACTOR_OUT\" ]; then printf \"%s\" \"$out\" > \"$ACIP_EXTRACTOR_OUT\"; else printf \"%s\" \"$out\"; fi\n",
+    )
+    .unwrap();
+    let mut perms = fs::metadata(&script).unwrap().permissions();
+    perms.set_mode(0o755);
+    fs::set_permissions(&script, perms).unwrap();
+    script
+}
+
+#[tokio::test]
+#[serial]
+async fn slow_extraction_is_attributed_to_the_extract_stage() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("ACIP_EXTRACTOR_BIN", slow_extractor(dir.path()));
+    std::env::set_var("ACIP_EXTRACTOR_TIMEOUT_SECS", "30");
+
+    let app = router(SlowRequestLog::in_memory(
+        settings(200),
+        Arc::new(SystemClock),
+    ));
+    let pdf = include_bytes!("fixtures/acip_known_text.pdf");
+    let body = json!({
+        "source_id": "slow-pdf",
+        "source_type": "pdf",
+        "content_type": "application/pdf",
+        "bytes_b64": B64.encode(pdf),
+    });
+    let (status, v) = call(&app, ingest_request(body, &[])).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+
+    let records = list(&app, None).await;
+    assert_eq!(records.len(), 1, "{records:?}");
+    let r = &records[0];
+    assert_eq!(r["request_id"], v["origin"]["request_id"]);
+    assert_eq!(r["reason"], "threshold");
+    assert_eq!(r["dominant_stage"], "extract");
+    assert_eq!(r["source_type"], "pdf");
+    assert_eq!(r["input_bytes"], pdf.len());
+    assert_eq!(r["extracted_chars"], 1);
+    let extract = r["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["stage"] == "extract")
+        .unwrap();
+    assert_eq!(extract["pool"], "blocking");
+    assert!(extract["ms"].as_f64().unwrap() >= 350.0, "{extract}");
+    assert!(r["total_ms"].as_f64().unwrap() >= extract["ms"].as_f64().unwrap());
+}
+
+#[tokio::test]
+#[serial]
+async fn runs_under_the_threshold_are_recorded_only_when_forced() {
+    let app = router(SlowRequestLog::in_memory(
+        settings(60_000),
+        Arc::new(SystemClock),
+    ));
+    let (status, _) = call(&app, ingest_request(text_body(), &[])).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(list(&app, None).await.is_empty());
+
+    let (status, v) = call(
+        &app,
+        ingest_request(text_body(), &[("X-ACIP-Force-Timing", "true")]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    let records = list(&app, None).await;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["reason"], "forced");
+    assert_eq!(records[0]["request_id"], v["origin"]["request_id"]);
+    let stages: Vec<&str> = records[0]["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["stage"].as_str().unwrap())
+        .collect();
+    assert_eq!(stages.first(), Some(&"prepare"));
+    assert_eq!(stages.last(), Some(&"serialize"));
+    assert!(!stages.contains(&"extract"));
+    // Timings and sizes only, never content.
+    assert!(!records[0].to_string().contains("Thursday"));
+}
+
+#[tokio::test]
+#[serial]
+async fn force_timing_needs_the_support_scope() {
+    let mut tokens = TokenSet::default();
+    tokens
+        .add("ingester", "secret-ingester", [Scope::Ingest].into())
+        .unwrap();
+    tokens
+        .add(
+            "support",
+            "secret-support",
+            [Scope::Ingest, Scope::Support].into(),
+        )
+        .unwrap();
+    let state = test_state(SlowRequestLog::in_memory(
+        settings(60_000),
+        Arc::new(SystemClock),
+    ));
+    let app = app::build_router_with_tokens(state, tokens, extra());
+
+    let forced = [
+        ("X-ACIP-Token", "secret-ingester"),
+        ("X-ACIP-Force-Timing", "true"),
+    ];
+    let (status, v) = call(&app, ingest_request(text_body(), &forced)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{v}");
+    assert_eq!(v["error"], "insufficient_scope");
+    assert_eq!(v["extra"]["required"], "support");
+    assert_eq!(v["extra"]["header"], slow_requests::FORCE_HEADER);
+
+    // Without the header the same token ingests as usual.
+    let (status, _) = call(
+        &app,
+        ingest_request(text_body(), &[("X-ACIP-Token", "secret-ingester")]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let forced = [
+        ("X-ACIP-Token", "secret-support"),
+        ("X-ACIP-Force-Timing", "true"),
+    ];
+    let (status, _) = call(&app, ingest_request(text_body(), &forced)).await;
+    assert_eq!(status, StatusCode::OK);
+    let records = list(&app, Some("secret-support")).await;
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["actor"], "support");
+}
+
+#[test]
+fn sampling_rate_holds_over_many_requests() {
+    let ids: Vec<String> = (0..20_000).map(|i| format!("req-{i}")).collect();
+    let share = |rate: f64| {
+        ids.iter()
+            .filter(|id| slow_requests::sampled(id, rate))
+            .count() as f64
+            / ids.len() as f64
+    };
+    for rate in [0.01, 0.1, 0.5] {
+        let got = share(rate);
+        assert!((got - rate).abs() < 0.01, "rate {rate}: sampled {got}");
+    }
+    assert_eq!(share(0.0), 0.0);
+    assert_eq!(share(1.0), 1.0);
+
+    // Decided by the id alone.
+    let log = SlowRequestLog::in_memory(
+        SlowRequestSettings {
+            sample_rate: 0.1,
+            ..settings(60_000)
+        },
+        Arc::new(SystemClock),
+    );
+    for id in &ids[..200] {
+        let first = log.should_capture(Duration::ZERO, id, false);
+        assert_eq!(first, log.should_capture(Duration::ZERO, id, false));
+        assert!(first.is_none() || first == Some(CaptureReason::Sampled));
+    }
+    assert_eq!(
+        log.should_capture(Duration::from_secs(60), "any", false),
+        Some(CaptureReason::Threshold)
+    );
+}
+
+fn record(log: &SlowRequestLog, id: &str) {
+    let mut timing = Timing::start();
+    timing.request_id = Some(id.to_string());
+    timing.lap(Stage::Prepare);
+    let run = RunInfo {
+        actor: "anonymous",
+        policy: "default",
+        source_type: "other",
+        http_status: StatusCode::OK,
+        input_bytes: 10,
+        forced: true,
+    };
+    assert!(log.finish(timing, run).is_some());
+}
+
+#[test]
+fn retention_purges_old_records() {
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let log = SlowRequestLog::in_memory(
+        SlowRequestSettings {
+            retention: Duration::from_secs(3600),
+            max_records: 3,
+            ..settings(60_000)
+        },
+        clock.clone(),
+    );
+    record(&log, "old");
+    clock.advance(1800);
+    record(&log, "newer");
+    assert_eq!(log.list(0, 10).len(), 2);
+    assert_eq!(log.list(1_000_001, 10)[0].request_id, "newer");
+
+    clock.advance(1801);
+    assert_eq!(log.prune(), 1);
+    assert!(log.get("old").is_none());
+    assert!(log.get("newer").is_some());
+
+    // Beyond max_records the oldest go first.
+    for id in ["a", "b", "c"] {
+        record(&log, id);
+    }
+    let ids: Vec<String> = log.list(0, 10).into_iter().map(|r| r.request_id).collect();
+    assert_eq!(ids, ["c", "b", "a"]);
+}
+
+#[test]
+fn file_backend_keeps_records_and_read_only_records_nothing() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("slow.json");
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let log = SlowRequestLog::load_or_create(&path, settings(60_000), clock.clone()).unwrap();
+    record(&log, "kept");
+    drop(log);
+
+    let log = SlowRequestLog::load_or_create(&path, settings(60_000), clock)
+        .unwrap()
+        .into_read_only();
+    assert!(log.get("kept").is_some());
+    let mut timing = Timing::start();
+    timing.request_id = Some("ignored".to_string());
+    let run = RunInfo {
+        actor: "anonymous",
+        policy: "default",
+        source_type: "other",
+        http_status: StatusCode::OK,
+        input_bytes: 10,
+        forced: true,
+    };
+    assert!(log.finish(timing, run).is_none());
+    assert_eq!(log.list(0, 10).len(), 1);
+}
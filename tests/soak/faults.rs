@@ -0,0 +1,315 @@
+//! Fault injectors. Each runs until the run stops, acting about once per
+//! [`Settings::fault_interval`](super::Settings), and leaves the sidecar healthy when it ends.
+
+use super::{invariants, traffic::Rng, Run};
+use acip_sidecar::sentry::ModelClient;
+use acip_sidecar::test_support::ScriptedModel;
+use anyhow::Result;
+use axum::http::HeaderMap;
+use serde_json::json;
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How long a model call takes in [`ModelMode::Slow`].
+pub const SLOW_MODEL_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ModelMode {
+    Normal,
+    /// Every call fails, as when the provider returns 5xx.
+    Error,
+    /// Every call takes [`SLOW_MODEL_DELAY`] first.
+    Slow,
+}
+
+/// The scripted provider, switchable into [`ModelMode::Error`] and [`ModelMode::Slow`].
+pub struct FaultyModel {
+    inner: ScriptedModel,
+    mode: AtomicU8,
+    slow: Duration,
+}
+
+impl FaultyModel {
+    pub fn new(slow: Duration) -> Self {
+        let block = |reason: &str| {
+            json!({
+                "tools_allowed": false,
+                "risk_level": "high",
+                "action": "block",
+                "reasons": [reason],
+            })
+        };
+        Self {
+            inner: ScriptedModel::benign()
+                .on(
+                    "Ignore previous instructions",
+                    block("scripted: instruction override"),
+                )
+                .on("run curl", block("scripted: tool coercion"))
+                .on("DAN mode", block("scripted: jailbreak")),
+            mode: AtomicU8::new(ModelMode::Normal as u8),
+            slow,
+        }
+    }
+
+    pub fn set_mode(&self, mode: ModelMode) {
+        self.mode.store(mode as u8, Ordering::SeqCst);
+    }
+
+    pub fn mode(&self) -> ModelMode {
+        match self.mode.load(Ordering::SeqCst) {
+            m if m == ModelMode::Error as u8 => ModelMode::Error,
+            m if m == ModelMode::Slow as u8 => ModelMode::Slow,
+            _ => ModelMode::Normal,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ModelClient for FaultyModel {
+    async fn generate(&self, model: &str, prompt: &str, headers: &HeaderMap) -> Result<String> {
+        match self.mode() {
+            ModelMode::Error => anyhow::bail!("soak: injected provider error (503)"),
+            ModelMode::Slow => tokio::time::sleep(self.slow).await,
+            ModelMode::Normal => {}
+        }
+        self.inner.generate(model, prompt, headers).await
+    }
+}
+
+/// The run's config file with `rules` redaction rules. The literals never occur in traffic, so
+/// reloads change what is loaded without changing any decision.
+pub fn config_toml(rules: usize) -> String {
+    let mut out = String::from("[response_headers]\npatterns = true\n");
+    for i in 0..rules {
+        out.push_str(&format!(
+            "\n[[redaction.rules]]\nlabel = \"soak-{i}\"\nliteral = \"soak-canary-{i}\"\n"
+        ));
+    }
+    out
+}
+
+/// Start every injector; each returns once the run stops.
+pub fn spawn_all(run: &Arc<Run>) -> Vec<tokio::task::JoinHandle<()>> {
+    let seed = run.settings.seed;
+    vec![
+        tokio::spawn(kill_helpers(run.clone(), Rng::new(seed ^ 1))),
+        tokio::spawn(flip_model(run.clone(), Rng::new(seed ^ 2))),
+        tokio::spawn(fill_tmpdir(run.clone(), Rng::new(seed ^ 3))),
+        tokio::spawn(reload_config(run.clone(), Rng::new(seed ^ 4))),
+        tokio::spawn(toggle_drain(run.clone(), Rng::new(seed ^ 5))),
+    ]
+}
+
+/// Between half and one and a half times `mean`.
+fn jitter(rng: &mut Rng, mean: Duration) -> Duration {
+    mean.mul_f64(0.5 + rng.unit())
+}
+
+/// SIGKILL a running extractor helper from outside, as the OOM killer would.
+async fn kill_helpers(run: Arc<Run>, mut rng: Rng) {
+    let name = invariants::helper_name();
+    while run
+        .pause(jitter(&mut rng, run.settings.fault_interval / 3))
+        .await
+    {
+        let live: Vec<_> = invariants::helper_children(&name)
+            .into_iter()
+            .filter(|h| !h.zombie)
+            .collect();
+        if live.is_empty() {
+            continue;
+        }
+        let victim = &live[rng.below(live.len())];
+        if unsafe { libc::kill(victim.pid, libc::SIGKILL) } == 0 {
+            run.report.fault("helper_killed");
+        }
+    }
+}
+
+/// Put the provider into error or slow mode for a while.
+async fn flip_model(run: Arc<Run>, mut rng: Rng) {
+    let model = &run.sidecar.model;
+    while run
+        .pause(jitter(&mut rng, run.settings.fault_interval))
+        .await
+    {
+        let (mode, name) = if rng.chance(0.5) {
+            (ModelMode::Error, "model_error")
+        } else {
+            (ModelMode::Slow, "model_slow")
+        };
+        model.set_mode(mode);
+        run.report.fault(name);
+        let held = run
+            .pause(jitter(&mut rng, run.settings.fault_interval / 2))
+            .await;
+        model.set_mode(ModelMode::Normal);
+        if !held {
+            break;
+        }
+    }
+    model.set_mode(ModelMode::Normal);
+}
+
+/// Fill the temp dir's filesystem past the extraction floor, then free it again.
+async fn fill_tmpdir(run: Arc<Run>, mut rng: Rng) {
+    let paths = &run.sidecar.paths;
+    let tmp = run.sidecar.state.tmp.clone();
+    while run
+        .pause(jitter(&mut rng, run.settings.fault_interval * 2))
+        .await
+    {
+        let Some(free) = invariants::free_bytes(&paths.tmp) else {
+            return;
+        };
+        let floor = tmp.settings().min_free_bytes;
+        let need = free.saturating_sub(floor) + 8 * 1024 * 1024;
+        // Someone else freed a lot of space since startup; do not write gigabytes.
+        if need > 4 * super::FILL_HEADROOM_BYTES {
+            run.report.note(format!(
+                "tmpdir fill skipped: {need} bytes needed to reach the floor"
+            ));
+            continue;
+        }
+        let filler = paths.filler.clone();
+        let written = tokio::task::spawn_blocking(move || write_filler(&filler, need)).await;
+        if !matches!(written, Ok(Ok(()))) {
+            run.report.note(format!("tmpdir fill failed: {written:?}"));
+            let _ = std::fs::remove_file(&paths.filler);
+            continue;
+        }
+        run.report.fault("tmpdir_filled");
+        if tmp.check_capacity().is_ok() {
+            run.report
+                .note("tmpdir fill did not cross the free-space floor".to_string());
+        }
+        run.pause(jitter(&mut rng, run.settings.fault_interval / 2))
+            .await;
+        let _ = std::fs::remove_file(&paths.filler);
+        run.report.fault("tmpdir_freed");
+    }
+    let _ = std::fs::remove_file(&paths.filler);
+}
+
+fn write_filler(path: &std::path::Path, bytes: u64) -> std::io::Result<()> {
+    let chunk = vec![0u8; 1024 * 1024];
+    let mut f = std::fs::File::create(path)?;
+    let mut left = bytes;
+    while left > 0 {
+        let n = left.min(chunk.len() as u64) as usize;
+        f.write_all(&chunk[..n])?;
+        left -= n as u64;
+    }
+    f.sync_all()
+}
+
+/// Rewrite the config and SIGHUP the process; every fourth time the config does not parse and
+/// the loaded rules must stay as they were.
+async fn reload_config(run: Arc<Run>, mut rng: Rng) {
+    let path = &run.sidecar.paths.config;
+    let redaction = &run.sidecar.redaction;
+    let mut generation = 0usize;
+    while run
+        .pause(jitter(&mut rng, run.settings.fault_interval))
+        .await
+    {
+        generation += 1;
+        let before = redaction.rule_count();
+        let broken = generation.is_multiple_of(4);
+        let expected = if broken { before } else { 1 + generation % 3 };
+        let contents = if broken {
+            "[response_headers\npatterns = ".to_string()
+        } else {
+            config_toml(expected)
+        };
+        if let Err(e) = std::fs::write(path, contents) {
+            run.report.note(format!("config rewrite failed: {e}"));
+            continue;
+        }
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGHUP);
+        }
+        run.report.fault(if broken {
+            "sighup_broken_config"
+        } else {
+            "sighup_reload"
+        });
+
+        // The reload is asynchronous: wait for it, or long enough for it to have happened.
+        let mut applied = false;
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if !broken && redaction.rule_count() == expected {
+                applied = true;
+                break;
+            }
+        }
+        if broken {
+            if redaction.rule_count() != before {
+                run.report.violation(
+                    "reload",
+                    format!(
+                        "a config that does not parse changed the redaction rules ({before} -> {})",
+                        redaction.rule_count()
+                    ),
+                );
+            }
+            let _ = std::fs::write(path, config_toml(before));
+        } else if !applied {
+            run.report.violation(
+                "reload",
+                format!(
+                    "SIGHUP did not load {expected} redaction rules (still {})",
+                    redaction.rule_count()
+                ),
+            );
+        }
+    }
+}
+
+/// Drain through the admin API for a while, then resume.
+async fn toggle_drain(run: Arc<Run>, mut rng: Rng) {
+    while run
+        .pause(jitter(&mut rng, run.settings.fault_interval * 2))
+        .await
+    {
+        admin(&run, "/v1/acip/admin/drain").await;
+        run.report.fault("drained");
+        let held = run
+            .pause(jitter(&mut rng, run.settings.fault_interval / 3))
+            .await;
+        admin(&run, "/v1/acip/admin/resume").await;
+        run.report.fault("resumed");
+        if !held {
+            break;
+        }
+    }
+    if run.sidecar.state.drain.is_draining() {
+        admin(&run, "/v1/acip/admin/resume").await;
+    }
+}
+
+async fn admin(run: &Run, path: &str) {
+    let resp = run
+        .client
+        .post(run.sidecar.url(path))
+        .header("x-acip-token", &run.sidecar.token)
+        .json(&json!({"initiated_by": "soak"}))
+        .send()
+        .await;
+    match resp {
+        Ok(r) if r.status().is_success() => {}
+        Ok(r) => run
+            .report
+            .violation("admin", format!("{path}: {}", r.status())),
+        Err(e) => run.report.violation("admin", format!("{path}: {e}")),
+    }
+}
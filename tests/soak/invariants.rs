@@ -0,0 +1,256 @@
+//! Invariant checks: per response, sampled continuously while the run goes on, and once at the
+//! end.
+
+use super::{traffic, Run};
+use acip_sidecar::reputation::ReputationRecord;
+use acip_sidecar::tmpdir::TMP_PREFIX;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Open file descriptors of this process.
+pub fn fd_count() -> usize {
+    std::fs::read_dir("/proc/self/fd")
+        .map(|d| d.count())
+        .unwrap_or(0)
+}
+
+/// Resident set size of this process.
+pub fn rss_bytes() -> u64 {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+        .unwrap_or(0)
+}
+
+/// Free space on the filesystem holding `path`, as `TmpDirManager` measures it.
+#[allow(clippy::unnecessary_cast)] // statvfs field widths differ between targets.
+pub fn free_bytes(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+    let c = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c.as_ptr(), &mut st) } != 0 {
+        return None;
+    }
+    Some((st.f_bavail as u64).saturating_mul(st.f_frsize as u64))
+}
+
+/// The helper's process name (`comm`), as `/proc` reports it.
+pub fn helper_name() -> String {
+    let bin = env!("CARGO_BIN_EXE_acip-extract");
+    let name = Path::new(bin)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // The kernel keeps the first 15 bytes.
+    name.chars().take(15).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Helper {
+    pub pid: i32,
+    /// Exited but not yet waited for.
+    pub zombie: bool,
+}
+
+/// Children of this process named `name`.
+pub fn helper_children(name: &str) -> Vec<Helper> {
+    let me = std::process::id() as i32;
+    let Ok(dir) = std::fs::read_dir("/proc") else {
+        return vec![];
+    };
+    let mut out = vec![];
+    for entry in dir.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|p| p.parse::<i32>().ok())
+        else {
+            continue;
+        };
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        // `pid (comm) state ppid ...`; comm may hold spaces and parentheses.
+        let (Some(open), Some(close)) = (stat.find('('), stat.rfind(')')) else {
+            continue;
+        };
+        let comm = &stat[open + 1..close];
+        let mut rest = stat[close + 1..].split_whitespace();
+        let state = rest.next().unwrap_or("");
+        let ppid = rest.next().and_then(|p| p.parse::<i32>().ok());
+        if comm == name && ppid == Some(me) {
+            out.push(Helper {
+                pid,
+                zombie: state == "Z",
+            });
+        }
+    }
+    out
+}
+
+/// Extractor temp entries directly under `base`.
+pub fn temp_entries(base: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(base)
+        .map(|d| {
+            d.flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with(TMP_PREFIX))
+                .map(|e| e.path())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Problems with one response on its own.
+pub fn check_response(
+    kind: traffic::Kind,
+    outcome: &traffic::Outcome,
+) -> Vec<(&'static str, String)> {
+    let mut out = vec![];
+    let status = outcome.status;
+    if status >= 500 && outcome.body.iter().all(u8::is_ascii_whitespace) {
+        out.push(("bodyless_5xx", format!("{} got {status}", kind.as_str())));
+    }
+    if kind.is_invalid() && (200..300).contains(&status) {
+        out.push((
+            "invalid_accepted",
+            format!("{} got {status}", kind.as_str()),
+        ));
+    }
+    if status == 200 {
+        let action = serde_json::from_slice::<serde_json::Value>(&outcome.body)
+            .ok()
+            .and_then(|v| v["action"].as_str().map(str::to_string));
+        match action {
+            None => out.push((
+                "no_decision",
+                format!("{} got 200 without one", kind.as_str()),
+            )),
+            Some(action) if outcome.action_header.as_deref() != Some(action.as_str()) => {
+                out.push((
+                    "header_mismatch",
+                    format!(
+                        "{}: body says {action}, header says {:?}",
+                        kind.as_str(),
+                        outcome.action_header
+                    ),
+                ))
+            }
+            Some(_) => {}
+        }
+    }
+    out
+}
+
+/// Figures taken after warm-up; growth is measured from here.
+#[derive(Debug, Clone, Copy)]
+pub struct Baseline {
+    pub fds: usize,
+    pub rss_bytes: u64,
+}
+
+impl Baseline {
+    pub fn now() -> Self {
+        Self {
+            fds: fd_count(),
+            rss_bytes: rss_bytes(),
+        }
+    }
+}
+
+/// How long a helper may stay unreaped before it counts as leaked.
+const ZOMBIE_GRACE: Duration = Duration::from_secs(2);
+
+/// Sample fds, RSS and helpers until the run stops, recording peaks and violations.
+pub async fn monitor(run: Arc<Run>, baseline: Baseline) {
+    let fd_bound = baseline.fds + run.settings.fd_slack;
+    let rss_bound = baseline.rss_bytes + run.settings.rss_budget_bytes;
+    let name = helper_name();
+    let mut zombies: HashMap<i32, Instant> = HashMap::new();
+    while run.pause(Duration::from_millis(250)).await {
+        let fds = fd_count();
+        let rss = rss_bytes();
+        let helpers = helper_children(&name);
+        run.report.sample(fds, rss, helpers.len());
+        if fds > fd_bound {
+            run.report.violation(
+                "fd_growth",
+                format!(
+                    "{fds} open fds, bound {fd_bound} (baseline {})",
+                    baseline.fds
+                ),
+            );
+        }
+        if rss > rss_bound {
+            run.report.violation(
+                "rss_budget",
+                format!(
+                    "RSS {} MB, budget {} MB",
+                    rss / (1024 * 1024),
+                    rss_bound / (1024 * 1024)
+                ),
+            );
+        }
+        let now = Instant::now();
+        let current: HashSet<i32> = helpers.iter().filter(|h| h.zombie).map(|h| h.pid).collect();
+        zombies.retain(|pid, _| current.contains(pid));
+        for pid in current {
+            let since = *zombies.entry(pid).or_insert(now);
+            if now - since > ZOMBIE_GRACE {
+                run.report
+                    .violation("unreaped_helper", format!("helper {pid} left a zombie"));
+                zombies.insert(pid, now);
+            }
+        }
+    }
+}
+
+/// Poll `check` until it returns `None` or `within` runs out; the last problem otherwise.
+pub async fn settle(within: Duration, mut check: impl FnMut() -> Option<String>) -> Option<String> {
+    let deadline = Instant::now() + within;
+    loop {
+        let problem = check()?;
+        if Instant::now() >= deadline {
+            return Some(problem);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Differences between the expected sightings per key and the store's records.
+pub fn reputation_mismatches(
+    expected: &HashMap<String, u64>,
+    records: &[ReputationRecord],
+) -> Vec<String> {
+    let mut out = vec![];
+    let actual: HashMap<&str, &ReputationRecord> =
+        records.iter().map(|r| (r.key.as_str(), r)).collect();
+    let mut keys: Vec<&String> = expected.keys().collect();
+    keys.sort();
+    for key in keys {
+        let want = expected[key];
+        match actual.get(key.as_str()) {
+            None => out.push(format!("{key}: expected {want} sightings, no record")),
+            Some(r) if r.seen_count != want => out.push(format!(
+                "{key}: expected {want} sightings, seen_count {}",
+                r.seen_count
+            )),
+            Some(_) => {}
+        }
+    }
+    for r in records {
+        if !expected.contains_key(&r.key) {
+            out.push(format!(
+                "{}: {} sightings, none expected",
+                r.key, r.seen_count
+            ));
+        }
+    }
+    out
+}
@@ -0,0 +1,304 @@
+//! Soak and fault-injection harness for `tests/soak_tests.rs` (see `docs/WORKFLOW.md`).
+//!
+//! [`Sidecar::start`] stands up the whole server in this process, on a loopback port, with
+//! file-backed stores in a temp dir. [`traffic`] drives it, [`faults`] breaks it, [`invariants`]
+//! watches it and [`report`] says what happened.
+
+pub mod faults;
+pub mod invariants;
+pub mod report;
+pub mod traffic;
+
+use acip_sidecar::app_state_builder::AppStateBuilder;
+use acip_sidecar::extractor_health::{BreakerSettings, ExtractorHealth};
+use acip_sidecar::policy_store::{PoliciesFile, PolicyStore};
+use acip_sidecar::reputation::{JsonFileReputationStore, SystemClock};
+use acip_sidecar::slow_requests::{SlowRequestLog, SlowRequestSettings};
+use acip_sidecar::stats::{DecisionStats, StatsSettings};
+use acip_sidecar::tmpdir::{TmpDirManager, TmpDirSettings};
+use acip_sidecar::{
+    app, config, extractor_health, ingest, jobs, model_policy, redact, regex_guard, remediation,
+    startup, state, tmpdir, uploads,
+};
+use anyhow::{Context, Result};
+use axum::{routing::post, Router};
+use std::{
+    net::SocketAddr,
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How long and how hard to run; `ACIP_SOAK_*` environment variables, CI profile by default.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub duration: Duration,
+    pub rps: u32,
+    /// Requests in flight at most.
+    pub concurrency: usize,
+    pub seed: u64,
+    /// Distinct source ids, drawn zipfian.
+    pub sources: usize,
+    /// Mean time between actions of each fault injector.
+    pub fault_interval: Duration,
+    /// Allowed RSS growth over the warmed-up baseline.
+    pub rss_budget_bytes: u64,
+    /// Allowed open fds over the warmed-up baseline while traffic runs.
+    pub fd_slack: usize,
+    /// How long each extractor helper takes, so there is a window to kill it in.
+    pub helper_delay: Duration,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        let env_u64 = |key: &str| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.trim().parse::<u64>().ok())
+        };
+        let seed = env_u64("ACIP_SOAK_SEED").unwrap_or_else(|| {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        let concurrency = env_u64("ACIP_SOAK_CONCURRENCY").unwrap_or(16).max(1) as usize;
+        Self {
+            duration: Duration::from_secs(env_u64("ACIP_SOAK_SECS").unwrap_or(60).max(1)),
+            rps: env_u64("ACIP_SOAK_RPS").unwrap_or(25).clamp(1, 10_000) as u32,
+            concurrency,
+            seed,
+            sources: env_u64("ACIP_SOAK_SOURCES").unwrap_or(64).max(1) as usize,
+            fault_interval: Duration::from_millis(
+                env_u64("ACIP_SOAK_FAULT_MS").unwrap_or(3000).max(100),
+            ),
+            rss_budget_bytes: env_u64("ACIP_SOAK_RSS_BUDGET_MB").unwrap_or(256) * 1024 * 1024,
+            fd_slack: env_u64("ACIP_SOAK_FD_SLACK")
+                .map(|v| v as usize)
+                .unwrap_or(64 + 4 * concurrency),
+            helper_delay: Duration::from_millis(
+                env_u64("ACIP_SOAK_HELPER_DELAY_MS").unwrap_or(150),
+            ),
+        }
+    }
+}
+
+/// Free space kept above the extraction floor; the tmpdir injector fills past it.
+pub const FILL_HEADROOM_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Files of one run, all under one temp dir.
+pub struct Paths {
+    pub root: PathBuf,
+    pub config: PathBuf,
+    pub reputation: PathBuf,
+    /// Base directory of the extractor's temp entries.
+    pub tmp: PathBuf,
+    /// Written by the tmpdir injector, on the same filesystem as `tmp`.
+    pub filler: PathBuf,
+}
+
+/// The sidecar under test.
+pub struct Sidecar {
+    pub state: Arc<state::AppState>,
+    pub addr: SocketAddr,
+    /// The `ACIP_AUTH_TOKEN` from the run's secrets file (all scopes).
+    pub token: String,
+    pub model: Arc<faults::FaultyModel>,
+    pub redaction: Arc<redact::Redaction>,
+    pub paths: Paths,
+    _dir: tempfile::TempDir,
+}
+
+impl Sidecar {
+    /// Built the way `main` builds it: config and secrets files, SIGHUP reloads, file-backed
+    /// reputation, stats and slow-request stores, the extractor helper binary and the
+    /// background tasks. Only the model provider is replaced.
+    pub async fn start(settings: &Settings) -> Result<Self> {
+        let dir = tempfile::tempdir()?;
+        let root = dir.path().to_path_buf();
+        let paths = Paths {
+            config: root.join("config.toml"),
+            reputation: root.join("reputation.json"),
+            tmp: root.join("tmp"),
+            filler: root.join("filler"),
+            root,
+        };
+        // The secrets file must sit in a private directory.
+        std::fs::set_permissions(&paths.root, std::fs::Permissions::from_mode(0o700))?;
+        std::fs::create_dir(&paths.tmp)?;
+        std::fs::write(&paths.config, faults::config_toml(1))?;
+        let token = format!("soak-{:016x}", settings.seed);
+        let secrets_path = paths.root.join("secrets.env");
+        write_private(&secrets_path, &format!("ACIP_AUTH_TOKEN={token}\n"))?;
+
+        std::env::set_var("ACIP_EXTRACTOR_BIN", env!("CARGO_BIN_EXE_acip-extract"));
+        std::env::set_var("ACIP_EXTRACTOR_SELFTEST_PDF", "1");
+        std::env::set_var(
+            "ACIP_EXTRACTOR_SELFTEST_DELAY_MS",
+            settings.helper_delay.as_millis().to_string(),
+        );
+        std::env::set_var("ACIP_EXTRACTOR_TIMEOUT_SECS", "10");
+        for key in [
+            "ACIP_SENTRY_MODE",
+            "ACIP_AUDIT_MODE",
+            "ACIP_REPUTATION_STORE",
+        ] {
+            std::env::remove_var(key);
+        }
+
+        let cfg = config::Config::load(&paths.config)?;
+        let secrets = startup::build_secrets_store(Some(secrets_path))?;
+        let redaction = Arc::new(redact::Redaction::default());
+        redaction.reload_with_limits(
+            cfg.redaction.as_ref(),
+            &regex_guard::RegexLimits::from_config(cfg.regex.as_ref()),
+        )?;
+        let remediation = Arc::new(remediation::Remediation::from_config(
+            cfg.remediation.as_ref(),
+            &[],
+        )?);
+        startup::spawn_config_reloader(
+            paths.config.clone(),
+            redaction.clone(),
+            remediation.clone(),
+        );
+
+        let clock = Arc::new(SystemClock);
+        let free = invariants::free_bytes(&paths.tmp).context("statvfs on the temp dir")?;
+        let tmp = Arc::new(TmpDirManager::new(TmpDirSettings {
+            base: paths.tmp.clone(),
+            min_free_bytes: free.saturating_sub(FILL_HEADROOM_BYTES),
+            monitor_interval: Duration::from_secs(1),
+            ..TmpDirSettings::default()
+        }));
+        let health = Arc::new(ExtractorHealth::new(
+            BreakerSettings {
+                threshold: 3,
+                probe_interval: Duration::from_secs(1),
+                probe_timeout: Duration::from_secs(5),
+            },
+            clock.clone(),
+        ));
+        let mut policies = std::collections::BTreeMap::new();
+        policies.insert("default".to_string(), model_policy::PolicyConfig::default());
+        let model = Arc::new(faults::FaultyModel::new(faults::SLOW_MODEL_DELAY));
+
+        let (state, tokens) = AppStateBuilder::new(Some(cfg), secrets)
+            .with_token_required(true)
+            .with_policies(PolicyStore::from_file(PoliciesFile { policies }))
+            .with_reputation(Arc::new(JsonFileReputationStore::load_or_create(
+                &paths.reputation,
+            )?))
+            .with_stats(Arc::new(DecisionStats::load_or_create(
+                paths.root.join("stats.json"),
+                StatsSettings::from_env(),
+                clock.clone(),
+            )?))
+            .with_slow_requests(Arc::new(SlowRequestLog::load_or_create(
+                paths.root.join("slow_requests.json"),
+                SlowRequestSettings::from_env(),
+                clock,
+            )?))
+            .with_redaction(redaction.clone())
+            .with_remediation(remediation)
+            .with_tmp(tmp)
+            .with_extractor_health(health)
+            .with_model(model.clone())
+            .build_with_tokens()?;
+
+        tmpdir::start(state.tmp.clone());
+        uploads::start(state.uploads.clone());
+        extractor_health::start(state.extractor_health.clone(), state.tmp.clone());
+        jobs::start(state.clone());
+
+        let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+        let router = app::build_router_with_tokens(state.clone(), tokens, extra);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let _ = axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await;
+        });
+
+        Ok(Self {
+            state,
+            addr,
+            token,
+            model,
+            redaction,
+            paths,
+            _dir: dir,
+        })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{path}", self.addr)
+    }
+}
+
+fn write_private(path: &std::path::Path, contents: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut f = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    f.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+/// Shared by the traffic generator, the injectors and the monitor.
+pub struct Run {
+    pub settings: Settings,
+    pub sidecar: Sidecar,
+    pub report: report::Report,
+    pub ledger: traffic::Ledger,
+    pub client: reqwest::Client,
+    stop: AtomicBool,
+}
+
+impl Run {
+    pub fn new(settings: Settings, sidecar: Sidecar) -> Result<Self> {
+        Ok(Self {
+            settings,
+            sidecar,
+            report: report::Report::default(),
+            ledger: traffic::Ledger::default(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(120))
+                // A connection per request, so open fds settle once traffic stops.
+                .pool_max_idle_per_host(0)
+                .build()?,
+            stop: AtomicBool::new(false),
+        })
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `d`, waking early on [`Self::stop`]; false once stopped.
+    pub async fn pause(&self, d: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + d;
+        while !self.is_stopped() {
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            tokio::time::sleep((deadline - now).min(Duration::from_millis(100))).await;
+        }
+        false
+    }
+}
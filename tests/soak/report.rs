@@ -0,0 +1,198 @@
+//! What a run did and what it found, printed at the end and optionally written as JSON
+//! (`ACIP_SOAK_REPORT`).
+
+use super::{invariants::Baseline, traffic::Kind, Settings};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, fmt::Write, sync::Mutex, time::Duration};
+
+/// Details kept per violation kind; the rest are only counted.
+const SAMPLES_KEPT: usize = 5;
+
+#[derive(Debug, Default, Clone)]
+struct Violation {
+    count: u64,
+    samples: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Peaks {
+    fds: usize,
+    rss_bytes: u64,
+    helpers: usize,
+    samples: u64,
+}
+
+#[derive(Default)]
+pub struct Report {
+    requests: Mutex<BTreeMap<(Kind, u16), u64>>,
+    faults: Mutex<BTreeMap<&'static str, u64>>,
+    violations: Mutex<BTreeMap<&'static str, Violation>>,
+    notes: Mutex<Vec<String>>,
+    peaks: Mutex<Peaks>,
+}
+
+/// Figures known only once the run is over.
+pub struct Summary {
+    pub elapsed: Duration,
+    pub baseline: Baseline,
+    pub final_fds: usize,
+    pub reputation_keys: usize,
+    pub recorded_sightings: u64,
+}
+
+impl Report {
+    pub fn request(&self, kind: Kind, status: u16) {
+        *self
+            .requests
+            .lock()
+            .unwrap()
+            .entry((kind, status))
+            .or_default() += 1;
+    }
+
+    pub fn fault(&self, name: &'static str) {
+        *self.faults.lock().unwrap().entry(name).or_default() += 1;
+    }
+
+    pub fn violation(&self, what: &'static str, detail: String) {
+        let mut violations = self.violations.lock().unwrap();
+        let v = violations.entry(what).or_default();
+        v.count += 1;
+        if v.samples.len() < SAMPLES_KEPT {
+            v.samples.push(detail);
+        }
+    }
+
+    /// Something worth knowing that is not a failure of the sidecar.
+    pub fn note(&self, note: String) {
+        let mut notes = self.notes.lock().unwrap();
+        if notes.len() < 50 && !notes.contains(&note) {
+            notes.push(note);
+        }
+    }
+
+    pub fn sample(&self, fds: usize, rss_bytes: u64, helpers: usize) {
+        let mut p = self.peaks.lock().unwrap();
+        p.fds = p.fds.max(fds);
+        p.rss_bytes = p.rss_bytes.max(rss_bytes);
+        p.helpers = p.helpers.max(helpers);
+        p.samples += 1;
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.violations.lock().unwrap().is_empty()
+    }
+
+    pub fn total_requests(&self) -> u64 {
+        self.requests.lock().unwrap().values().sum()
+    }
+
+    pub fn to_json(&self, settings: &Settings, summary: &Summary) -> Value {
+        let mut requests: BTreeMap<&str, BTreeMap<String, u64>> = BTreeMap::new();
+        for ((kind, status), n) in self.requests.lock().unwrap().iter() {
+            requests
+                .entry(kind.as_str())
+                .or_default()
+                .insert(status.to_string(), *n);
+        }
+        let violations: BTreeMap<&str, Value> = self
+            .violations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (*k, json!({"count": v.count, "samples": v.samples})))
+            .collect();
+        let peaks = *self.peaks.lock().unwrap();
+        json!({
+            "settings": {
+                "secs": settings.duration.as_secs(),
+                "rps": settings.rps,
+                "concurrency": settings.concurrency,
+                "seed": settings.seed,
+                "sources": settings.sources,
+            },
+            "elapsed_secs": summary.elapsed.as_secs_f64(),
+            "requests": requests,
+            "faults": *self.faults.lock().unwrap(),
+            "fds": {
+                "baseline": summary.baseline.fds,
+                "peak": peaks.fds,
+                "final": summary.final_fds,
+            },
+            "rss_bytes": {
+                "baseline": summary.baseline.rss_bytes,
+                "peak": peaks.rss_bytes,
+            },
+            "peak_helpers": peaks.helpers,
+            "reputation": {
+                "keys": summary.reputation_keys,
+                "sightings": summary.recorded_sightings,
+            },
+            "violations": violations,
+            "notes": *self.notes.lock().unwrap(),
+        })
+    }
+
+    pub fn render(&self, settings: &Settings, summary: &Summary) -> String {
+        const MB: u64 = 1024 * 1024;
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "soak: {:.0}s at {} rps, concurrency {}, seed {} ({} requests)",
+            summary.elapsed.as_secs_f64(),
+            settings.rps,
+            settings.concurrency,
+            settings.seed,
+            self.total_requests()
+        );
+        let mut by_kind: BTreeMap<Kind, Vec<String>> = BTreeMap::new();
+        for ((kind, status), n) in self.requests.lock().unwrap().iter() {
+            by_kind
+                .entry(*kind)
+                .or_default()
+                .push(format!("{status}: {n}"));
+        }
+        for (kind, statuses) in by_kind {
+            let _ = writeln!(out, "  {:<10} {}", kind.as_str(), statuses.join(", "));
+        }
+        let faults: Vec<String> = self
+            .faults
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(k, n)| format!("{k} {n}"))
+            .collect();
+        let _ = writeln!(out, "  faults: {}", faults.join(", "));
+        let peaks = *self.peaks.lock().unwrap();
+        let _ = writeln!(
+            out,
+            "  fds: baseline {}, peak {}, final {}; rss: baseline {} MB, peak {} MB; helpers: peak {} ({} samples)",
+            summary.baseline.fds,
+            peaks.fds,
+            summary.final_fds,
+            summary.baseline.rss_bytes / MB,
+            peaks.rss_bytes / MB,
+            peaks.helpers,
+            peaks.samples
+        );
+        let _ = writeln!(
+            out,
+            "  reputation: {} keys, {} sightings",
+            summary.reputation_keys, summary.recorded_sightings
+        );
+        for note in self.notes.lock().unwrap().iter() {
+            let _ = writeln!(out, "  note: {note}");
+        }
+        let violations = self.violations.lock().unwrap();
+        if violations.is_empty() {
+            let _ = writeln!(out, "  violations: none");
+        }
+        for (what, v) in violations.iter() {
+            let _ = writeln!(out, "  VIOLATION {what} x{}", v.count);
+            for s in &v.samples {
+                let _ = writeln!(out, "    {s}");
+            }
+        }
+        out
+    }
+}
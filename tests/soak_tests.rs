@@ -0,0 +1,142 @@
+//! Soak test: mixed traffic against the whole sidecar while faults are injected, with the
+//! invariants checked throughout. Ignored by default; `cargo xtask soak` runs it (see
+//! `docs/WORKFLOW.md` for the knobs).
+
+mod soak;
+
+use acip_sidecar::reputation::{JsonFileReputationStore, ReputationStore};
+use soak::{
+    faults, invariants,
+    report::Summary,
+    traffic::{self, Kind},
+    Run, Settings, Sidecar,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Semaphore, time::MissedTickBehavior};
+
+/// Open fds allowed over the baseline once everything has stopped.
+const FINAL_FD_SLACK: usize = 16;
+
+/// How long leftovers get to go away after the last request.
+const SETTLE: Duration = Duration::from_secs(10);
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+#[ignore = "long-running; use `cargo xtask soak`"]
+async fn mixed_traffic_under_faults_keeps_invariants() {
+    let settings = Settings::from_env();
+    let sidecar = Sidecar::start(&settings).await.unwrap();
+    let run = Arc::new(Run::new(settings.clone(), sidecar).unwrap());
+    let mut traffic = traffic::Generator::new(
+        settings.seed,
+        settings.sources,
+        traffic::Corpus::load().unwrap(),
+    );
+
+    // One of each first, so lazy state and pools are in the baseline.
+    for kind in [
+        Kind::Text,
+        Kind::Html,
+        Kind::Pdf,
+        Kind::Svg,
+        Kind::Fixture,
+        Kind::Malformed,
+        Kind::Oversized,
+    ] {
+        traffic::send(&run, traffic.plan(kind)).await;
+    }
+    let baseline = invariants::Baseline::now();
+
+    let monitor = tokio::spawn(invariants::monitor(run.clone(), baseline));
+    let injectors = faults::spawn_all(&run);
+
+    let started = Instant::now();
+    let in_flight = Arc::new(Semaphore::new(settings.concurrency));
+    let mut tick = tokio::time::interval(Duration::from_secs_f64(1.0 / f64::from(settings.rps)));
+    tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    while started.elapsed() < settings.duration {
+        tick.tick().await;
+        let permit = in_flight.clone().acquire_owned().await.unwrap();
+        let planned = traffic.next();
+        let run = run.clone();
+        tokio::spawn(async move {
+            traffic::send(&run, planned).await;
+            drop(permit);
+        });
+    }
+    run.stop();
+    for injector in injectors {
+        injector.await.unwrap();
+    }
+    monitor.await.unwrap();
+    let _drained = in_flight
+        .acquire_many(settings.concurrency as u32)
+        .await
+        .unwrap();
+    let elapsed = started.elapsed();
+
+    // Nothing may outlive the requests that needed it.
+    let sidecar = &run.sidecar;
+    let helper = invariants::helper_name();
+    let left = invariants::settle(SETTLE, || {
+        let helpers = invariants::helper_children(&helper);
+        (!helpers.is_empty()).then(|| format!("helper processes left: {helpers:?}"))
+    })
+    .await;
+    if let Some(left) = left {
+        run.report.violation("orphaned_helpers", left);
+    }
+    let left = invariants::settle(SETTLE, || {
+        let entries = invariants::temp_entries(&sidecar.paths.tmp);
+        let owned = sidecar.state.tmp.owned_count();
+        (!entries.is_empty() || owned > 0)
+            .then(|| format!("temp entries left: {entries:?} ({owned} registered)"))
+    })
+    .await;
+    if let Some(left) = left {
+        run.report.violation("stray_temp_files", left);
+    }
+    let fd_bound = baseline.fds + FINAL_FD_SLACK;
+    let left = invariants::settle(SETTLE, || {
+        let fds = invariants::fd_count();
+        (fds > fd_bound).then(|| format!("{fds} fds open after the run, bound {fd_bound}"))
+    })
+    .await;
+    if let Some(left) = left {
+        run.report.violation("fd_growth", left);
+    }
+
+    // Reputation totals, in memory and as persisted.
+    let expected = run.ledger.expected();
+    for m in invariants::reputation_mismatches(&expected, &sidecar.state.reputation.list()) {
+        run.report.violation("reputation", m);
+    }
+    match JsonFileReputationStore::open_read_only(&sidecar.paths.reputation) {
+        Ok(persisted) => {
+            for m in invariants::reputation_mismatches(&expected, &persisted.list()) {
+                run.report.violation("reputation_file", m);
+            }
+        }
+        Err(e) => run.report.violation("reputation_file", format!("{e:#}")),
+    }
+
+    let summary = Summary {
+        elapsed,
+        baseline,
+        final_fds: invariants::fd_count(),
+        reputation_keys: expected.len(),
+        recorded_sightings: expected.values().sum(),
+    };
+    let rendered = run.report.render(&settings, &summary);
+    println!("{rendered}");
+    if let Ok(path) = std::env::var("ACIP_SOAK_REPORT") {
+        let json = run.report.to_json(&settings, &summary);
+        std::fs::write(&path, serde_json::to_vec_pretty(&json).unwrap()).unwrap();
+    }
+    assert!(
+        run.report.is_clean(),
+        "soak invariants violated:\n{rendered}"
+    );
+}
@@ -5,7 +5,7 @@ use std::{fs, os::unix::fs::PermissionsExt};
 fn secrets_store_falls_back_to_env() {
     std::env::set_var("ACIP_TEST_SECRET", "hello");
     let secrets = startup::build_secrets_store(None).unwrap();
-    assert_eq!(secrets.get("ACIP_TEST_SECRET").unwrap(), "hello");
+    assert_eq!(secrets.get("ACIP_TEST_SECRET").unwrap().expose(), "hello");
 }
 
 #[test]
@@ -25,7 +25,7 @@ fn secrets_store_reads_file_when_provided() {
     fs::set_permissions(dpath, dperms).unwrap();
 
     let secrets = startup::build_secrets_store(Some(secrets_path)).unwrap();
-    assert_eq!(secrets.get("ACIP_AUTH_TOKEN").unwrap(), "abc");
+    assert_eq!(secrets.get("ACIP_AUTH_TOKEN").unwrap().expose(), "abc");
 }
 
 #[test]
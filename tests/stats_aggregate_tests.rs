@@ -0,0 +1,388 @@
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::sentry::{Action, RiskLevel};
+use acip_sidecar::stats::{DecisionSample, DecisionStats, StatsSettings, DAY_SECS};
+use acip_sidecar::stats_aggregate::{
+    AggregateError, AggregateReport, AggregateSettings, Dimension, Variant,
+};
+use acip_sidecar::token_auth::{Scope, TokenSet};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+const DAY0: u64 = 20_000 * DAY_SECS;
+
+fn sample(
+    tenant: &str,
+    policy: &str,
+    source_type: &str,
+    action: Action,
+    pattern: &str,
+) -> DecisionSample {
+    DecisionSample {
+        tenant: tenant.to_string(),
+        policy: policy.to_string(),
+        source_type: source_type.to_string(),
+        risk_level: RiskLevel::Low,
+        action,
+        patterns: vec![pattern.to_string()],
+        escalated: false,
+        severity: 0,
+        quality: None,
+        confidence: None,
+    }
+}
+
+fn stats(aggregate: AggregateSettings) -> (DecisionStats, Arc<MockClock>) {
+    let clock = Arc::new(MockClock::new(DAY0 + 3_600));
+    let settings = StatsSettings {
+        aggregate,
+        ..StatsSettings::default()
+    };
+    (DecisionStats::in_memory(settings, clock.clone()), clock)
+}
+
+fn k3() -> AggregateSettings {
+    AggregateSettings {
+        min_tenants: 3,
+        min_events: 1,
+        ..AggregateSettings::default()
+    }
+}
+
+/// Three tenants share `default`/html/allow/`p:common`; only two ever use the `secret` policy,
+/// `pdf` sources, or hit `p:rare`.
+fn seed(stats: &DecisionStats) {
+    for tenant in ["t1", "t2", "t3"] {
+        for _ in 0..5 {
+            stats.record(&sample(
+                tenant,
+                "default",
+                "html",
+                Action::Allow,
+                "p:common",
+            ));
+        }
+        stats.record(&sample(
+            tenant,
+            "default",
+            "html",
+            Action::Block,
+            "p:common",
+        ));
+    }
+    for tenant in ["t1", "t2"] {
+        stats.record(&sample(tenant, "secret", "pdf", Action::Block, "p:rare"));
+    }
+}
+
+/// Every non-empty combination of dimensions.
+fn all_groupings() -> Vec<Vec<Dimension>> {
+    (1..16u8)
+        .map(|mask| {
+            Dimension::ALL
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, d)| d)
+                .collect()
+        })
+        .collect()
+}
+
+fn sum(report: &AggregateReport) -> u64 {
+    report.rows.iter().map(|r| r.count).sum::<u64>() + report.other
+}
+
+#[test]
+fn a_cell_with_too_few_tenants_is_suppressed_in_every_grouping() {
+    let (stats, _) = stats(k3());
+    seed(&stats);
+    let snapshot = stats.aggregate_snapshot(7);
+
+    for group_by in all_groupings() {
+        let report = snapshot.query(&group_by, Variant::Internal).unwrap();
+        for row in &report.rows {
+            for hidden in ["secret", "pdf", "p:rare"] {
+                assert!(
+                    row.key.values().all(|v| v != hidden),
+                    "{group_by:?} exposes {hidden}: {row:?}"
+                );
+            }
+        }
+        // The suppressed decisions (or hits) are always the same `other`.
+        assert_eq!(report.other, 2, "{group_by:?}");
+        assert_eq!(sum(&report), 20, "{group_by:?}");
+    }
+
+    // Blocks from the suppressed cell are not in the published totals.
+    let totals = snapshot
+        .query(&[Dimension::Action], Variant::Internal)
+        .unwrap()
+        .totals
+        .unwrap();
+    assert_eq!(totals.decisions, 20);
+    assert_eq!(totals.blocked, 3);
+}
+
+#[test]
+fn the_kth_tenant_publishes_the_cell() {
+    let (stats, _) = stats(AggregateSettings {
+        snapshot_secs: 0,
+        ..k3()
+    });
+    seed(&stats);
+    stats.record(&sample("t3", "secret", "pdf", Action::Block, "p:rare"));
+
+    let report = stats
+        .aggregate_snapshot(7)
+        .query(&[Dimension::Policy], Variant::Internal)
+        .unwrap();
+    let secret = report
+        .rows
+        .iter()
+        .find(|r| r.key["policy"] == "secret")
+        .unwrap();
+    assert_eq!(secret.count, 3);
+    assert_eq!(report.other, 0);
+}
+
+#[test]
+fn small_cells_are_suppressed_by_event_count() {
+    let (stats, _) = stats(AggregateSettings {
+        min_tenants: 1,
+        min_events: 4,
+        ..AggregateSettings::default()
+    });
+    seed(&stats);
+    let report = stats
+        .aggregate_snapshot(7)
+        .query(&[Dimension::Policy, Dimension::Action], Variant::Internal)
+        .unwrap();
+    // allow: 15 published; block: 3 in `default` and 2 in `secret`, both below 4.
+    assert_eq!(report.rows.len(), 1);
+    assert_eq!(report.rows[0].count, 15);
+    assert_eq!(report.other, 5);
+}
+
+#[test]
+fn too_few_tenants_overall_publishes_nothing() {
+    let (stats, _) = stats(k3());
+    for tenant in ["t1", "t2"] {
+        stats.record(&sample(
+            tenant,
+            "default",
+            "html",
+            Action::Block,
+            "p:common",
+        ));
+    }
+    let report = stats
+        .aggregate_snapshot(7)
+        .query(&[Dimension::Policy], Variant::Internal)
+        .unwrap();
+    assert!(report.rows.is_empty());
+    assert!(report.totals.is_none());
+    assert_eq!(report.other, 2);
+}
+
+#[test]
+fn noise_stays_within_the_bound_and_is_labeled() {
+    let (stats, _) = stats(AggregateSettings {
+        min_tenants: 3,
+        min_events: 1,
+        epsilon: Some(0.05),
+        noise_bound: 4,
+        snapshot_secs: 60,
+    });
+    for tenant in ["t1", "t2", "t3"] {
+        for p in 0..40 {
+            for _ in 0..50 {
+                stats.record(&sample(
+                    tenant,
+                    &format!("p{p}"),
+                    "html",
+                    Action::Allow,
+                    "x",
+                ));
+            }
+        }
+    }
+    let snapshot = stats.aggregate_snapshot(7);
+    let exact = snapshot
+        .query(&[Dimension::Policy], Variant::Internal)
+        .unwrap();
+    let noisy = snapshot
+        .query(&[Dimension::Policy], Variant::External)
+        .unwrap();
+
+    assert!(!exact.approximate && exact.noise.is_none());
+    assert!(noisy.approximate);
+    assert_eq!(noisy.noise.as_ref().unwrap().bound, 4);
+    assert_eq!(noisy.rows.len(), 40);
+
+    let mut moved = 0;
+    for row in &noisy.rows {
+        assert!(row.count.abs_diff(150) <= 4, "{row:?}");
+        if row.count != 150 {
+            moved += 1;
+        }
+    }
+    assert!(moved > 0);
+    let totals = noisy.totals.as_ref().unwrap();
+    assert!(totals.decisions.abs_diff(6_000) <= 4);
+
+    // Fixed per snapshot: asking again does not average the noise away.
+    assert_eq!(
+        snapshot
+            .query(&[Dimension::Policy], Variant::External)
+            .unwrap(),
+        noisy
+    );
+}
+
+#[test]
+fn external_variant_needs_epsilon() {
+    let (stats, _) = stats(k3());
+    seed(&stats);
+    assert_eq!(
+        stats
+            .aggregate_snapshot(7)
+            .query(&[Dimension::Policy], Variant::External),
+        Err(AggregateError::NoiseDisabled)
+    );
+}
+
+#[test]
+fn concurrent_queries_share_one_snapshot() {
+    let (stats, clock) = stats(k3());
+    seed(&stats);
+    let stats = Arc::new(stats);
+
+    let reports: Vec<AggregateReport> = std::thread::scope(|s| {
+        let writer = s.spawn(|| {
+            for _ in 0..200 {
+                stats.record(&sample("t1", "default", "html", Action::Allow, "p:common"));
+            }
+        });
+        let readers: Vec<_> = (0..8)
+            .map(|i| {
+                let stats = stats.clone();
+                s.spawn(move || {
+                    let group_by = all_groupings()[i].clone();
+                    let first = stats.aggregate_snapshot(7);
+                    let again = stats.aggregate_snapshot(7);
+                    assert!(Arc::ptr_eq(&first, &again));
+                    first.query(&group_by, Variant::Internal).unwrap()
+                })
+            })
+            .collect();
+        writer.join().unwrap();
+        readers.into_iter().map(|r| r.join().unwrap()).collect()
+    });
+
+    let id = &reports[0].snapshot_id;
+    let total = reports[0].totals.as_ref().unwrap().decisions;
+    for r in &reports {
+        assert_eq!(&r.snapshot_id, id);
+        assert_eq!(r.totals.as_ref().unwrap().decisions, total);
+        if !r.group_by.contains(&Dimension::Pattern) {
+            assert_eq!(sum(r), total);
+        }
+    }
+
+    // A new snapshot once the old one expires picks up later decisions.
+    clock.advance(61);
+    let fresh = stats
+        .aggregate_snapshot(7)
+        .query(&[Dimension::Policy], Variant::Internal)
+        .unwrap();
+    assert_ne!(&fresh.snapshot_id, id);
+    assert_eq!(fresh.totals.unwrap().decisions, 220);
+}
+
+fn app_with(stats: DecisionStats, tokens: TokenSet) -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let st = AppStateBuilder::for_tests()
+        .with_stats(Arc::new(stats))
+        .build()
+        .unwrap();
+    app::build_router_with_tokens(st, tokens, Router::new())
+}
+
+async fn get(app: &Router, uri: &str, token: &str) -> (StatusCode, Value) {
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri(uri)
+                .header("X-ACIP-Token", token)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn aggregate_endpoint_needs_platform_admin() {
+    let (stats, _) = stats(k3());
+    seed(&stats);
+    let mut tokens = TokenSet::default();
+    tokens
+        .add("platform", "secret-platform", [Scope::PlatformAdmin].into())
+        .unwrap();
+    tokens
+        .add("t1", "secret-t1", [Scope::Read, Scope::Ingest].into())
+        .unwrap();
+    let app = app_with(stats, tokens);
+
+    let (status, v) = get(&app, "/v1/acip/stats/aggregate", "secret-t1").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(v["extra"]["required"], "platform_admin");
+
+    let (status, v) = get(
+        &app,
+        "/v1/acip/stats/aggregate?group_by=action,policy&days=7",
+        "secret-platform",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["group_by"], serde_json::json!(["policy", "action"]));
+    assert_eq!(v["metric"], "decisions");
+    assert_eq!(v["approximate"], false);
+    assert_eq!(v["rows"][0]["policy"], "default");
+    assert_eq!(v["rows"][0]["action"], "allow");
+    assert_eq!(v["rows"][0]["count"], 15);
+    assert_eq!(v["other"], 2);
+
+    let (status, v) = get(
+        &app,
+        "/v1/acip/stats/aggregate?group_by=tenant",
+        "secret-platform",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "invalid_group_by");
+
+    let (status, v) = get(
+        &app,
+        "/v1/acip/stats/aggregate?variant=external",
+        "secret-platform",
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "aggregate_noise_disabled");
+}
@@ -0,0 +1,217 @@
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::sentry::{Action, RiskLevel};
+use acip_sidecar::stats::{
+    DecisionSample, DecisionStats, GroupBy, PatternRow, PolicyRow, SourceTypeRow, StatsRow,
+    StatsSettings, DAY_SECS, OTHER_BUCKET,
+};
+use acip_sidecar::text_quality::QualityBucket;
+use std::sync::Arc;
+
+const DAY0: u64 = 20_000 * DAY_SECS;
+
+fn sample(policy: &str, action: Action, patterns: &[&str], escalated: bool) -> DecisionSample {
+    DecisionSample {
+        tenant: "anonymous".to_string(),
+        policy: policy.to_string(),
+        source_type: "html".to_string(),
+        risk_level: match action {
+            Action::Block | Action::NeedsReview => RiskLevel::High,
+            _ => RiskLevel::Low,
+        },
+        action,
+        patterns: patterns.iter().map(|p| p.to_string()).collect(),
+        escalated,
+        severity: 10,
+        quality: None,
+        confidence: None,
+    }
+}
+
+fn settings(retain_days: u64, top_k: usize) -> StatsSettings {
+    StatsSettings {
+        retain_days,
+        top_k,
+        ..StatsSettings::default()
+    }
+}
+
+/// Day 0: 200 decisions, all `default` (150 allow, 50 block).
+/// Day 1: 100 decisions (default 40 allow, strict 60 needs_review, 30 of them escalated).
+fn seed(stats: &DecisionStats, clock: &MockClock) {
+    clock.set(DAY0 + 3_600);
+    for i in 0..200 {
+        if i < 150 {
+            stats.record(&sample("default", Action::Allow, &["p:benign"], false));
+        } else {
+            stats.record(&sample(
+                "default",
+                Action::Block,
+                &["p:ignore_previous"],
+                false,
+            ));
+        }
+    }
+
+    clock.set(DAY0 + DAY_SECS + 3_600);
+    for _ in 0..40 {
+        stats.record(&sample("default", Action::Allow, &[], false));
+    }
+    for i in 0..60 {
+        stats.record(&sample(
+            "strict",
+            Action::NeedsReview,
+            &["p:ignore_previous", "p:tool_coercion"],
+            i % 2 == 0,
+        ));
+    }
+}
+
+fn policy_rows(stats: &DecisionStats, days: u64) -> Vec<PolicyRow> {
+    stats
+        .report(days, GroupBy::Policy)
+        .rows
+        .into_iter()
+        .map(|r| match r {
+            StatsRow::Policy(p) => p,
+            other => panic!("unexpected row {other:?}"),
+        })
+        .collect()
+}
+
+fn pattern_rows(stats: &DecisionStats, days: u64) -> Vec<PatternRow> {
+    stats
+        .report(days, GroupBy::Pattern)
+        .rows
+        .into_iter()
+        .map(|r| match r {
+            StatsRow::Pattern(p) => p,
+            other => panic!("unexpected row {other:?}"),
+        })
+        .collect()
+}
+
+#[test]
+fn groups_decisions_by_policy_across_days() {
+    let clock = Arc::new(MockClock::new(0));
+    let stats = DecisionStats::in_memory(settings(7, 10), clock.clone());
+    seed(&stats, &clock);
+
+    let rows = policy_rows(&stats, 7);
+    assert_eq!(rows.len(), 2);
+
+    let default = &rows[0];
+    assert_eq!(default.policy, "default");
+    assert_eq!(default.decisions, 240);
+    assert_eq!(default.by_action["allow"], 190);
+    assert_eq!(default.by_action["block"], 50);
+    assert_eq!(default.by_risk_level["high"], 50);
+    assert_eq!(default.escalation_rate, 0.0);
+
+    let strict = &rows[1];
+    assert_eq!(strict.decisions, 60);
+    assert_eq!(strict.by_action["needs_review"], 60);
+    assert_eq!(strict.escalations, 30);
+    assert_eq!(strict.escalation_rate, 0.5);
+
+    // Only today.
+    let today = policy_rows(&stats, 1);
+    assert_eq!(today[0].decisions, 40);
+}
+
+#[test]
+fn pattern_rows_join_false_positive_counts() {
+    let clock = Arc::new(MockClock::new(0));
+    let stats = DecisionStats::in_memory(settings(7, 10), clock.clone());
+    seed(&stats, &clock);
+    for _ in 0..11 {
+        stats.record_false_positive("p:ignore_previous");
+    }
+
+    let rows = pattern_rows(&stats, 7);
+    let ignore = rows
+        .iter()
+        .find(|r| r.pattern == "p:ignore_previous")
+        .unwrap();
+    assert_eq!(ignore.hits, 110);
+    assert_eq!(ignore.escalated_outcomes, 110);
+    assert_eq!(ignore.escalated_share, 1.0);
+    assert_eq!(ignore.false_positives, 11);
+    assert_eq!(ignore.overturn_rate, 0.1);
+
+    let benign = rows.iter().find(|r| r.pattern == "p:benign").unwrap();
+    assert_eq!(benign.hits, 150);
+    assert_eq!(benign.escalated_share, 0.0);
+
+    // Sorted by hits, descending.
+    let hits: Vec<u64> = rows.iter().map(|r| r.hits).collect();
+    assert_eq!(hits, vec![150, 110, 60]);
+}
+
+#[test]
+fn top_k_folds_the_tail_into_other() {
+    let clock = Arc::new(MockClock::new(DAY0));
+    let stats = DecisionStats::in_memory(settings(7, 2), clock.clone());
+    seed(&stats, &clock);
+    stats.record(&sample("default", Action::Allow, &["p:rare"], false));
+
+    let rows = pattern_rows(&stats, 7);
+    let names: Vec<&str> = rows.iter().map(|r| r.pattern.as_str()).collect();
+    assert_eq!(names, vec!["p:benign", "p:ignore_previous", OTHER_BUCKET]);
+    assert_eq!(rows[2].hits, 61);
+}
+
+#[test]
+fn source_type_rows_average_severity_and_count_quality() {
+    let clock = Arc::new(MockClock::new(DAY0));
+    let stats = DecisionStats::in_memory(settings(7, 10), clock.clone());
+    let mut s = sample("default", Action::Allow, &[], false);
+    s.source_type = "pdf".to_string();
+    s.severity = 30;
+    s.quality = Some(QualityBucket::Garbled);
+    stats.record(&s);
+    s.severity = 10;
+    stats.record(&s);
+
+    let rows = stats.report(7, GroupBy::SourceType).rows;
+    assert_eq!(
+        rows,
+        vec![StatsRow::SourceType(SourceTypeRow {
+            source_type: "pdf".to_string(),
+            decisions: 2,
+            avg_severity: 20.0,
+            by_quality: [("garbled".to_string(), 2)].into(),
+        })]
+    );
+}
+
+#[test]
+fn old_days_expire_after_retention() {
+    let clock = Arc::new(MockClock::new(0));
+    let stats = DecisionStats::in_memory(settings(2, 10), clock.clone());
+    seed(&stats, &clock);
+    assert_eq!(policy_rows(&stats, 7)[0].decisions, 240);
+
+    // Day 2: day 0 falls out of the two-day window.
+    clock.advance(DAY_SECS);
+    stats.record(&sample("default", Action::Allow, &[], false));
+
+    let rows = policy_rows(&stats, 7);
+    assert_eq!(rows[0].decisions, 41);
+    assert_eq!(rows[1].decisions, 60);
+    assert_eq!(stats.report(30, GroupBy::Policy).days, 2);
+}
+
+#[test]
+fn file_backend_survives_restart() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.json");
+    let clock = Arc::new(MockClock::new(0));
+
+    {
+        let stats = DecisionStats::load_or_create(&path, settings(7, 10), clock.clone()).unwrap();
+        seed(&stats, &clock);
+    }
+
+    let reloaded = DecisionStats::load_or_create(&path, settings(7, 10), clock.clone()).unwrap();
+    assert_eq!(policy_rows(&reloaded, 7)[0].decisions, 240);
+}
@@ -4,30 +4,19 @@ use axum::{
     routing::get,
     Router,
 };
-use acip_sidecar::{policy_store, routes, state};
+use acip_sidecar::{app_state_builder::AppStateBuilder, routes, state};
 use serde_json::Value;
-use std::sync::Arc;
 use tower::ServiceExt;
 
 fn app() -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
+    let st = AppStateBuilder::for_tests()
+        .with_policy(state::Policy {
             head: 1,
             tail: 2,
             full_if_lte: 3,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(acip_sidecar::secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(acip_sidecar::reputation::InMemoryReputationStore::new()),
-    });
+        })
+        .build()
+        .unwrap();
 
     Router::new()
         .route(
@@ -63,5 +52,7 @@ async fn status_endpoint_returns_ok_and_policy_values() {
     assert_eq!(v["policy"]["head"], 1);
     assert_eq!(v["policy"]["tail"], 2);
     assert_eq!(v["policy"]["full_if_lte"], 3);
-    assert!(v["policies"].as_array().unwrap().len() >= 1);
+    assert!(!v["policies"].as_array().unwrap().is_empty());
+    // In-memory stores have no files.
+    assert_eq!(v["storage"], serde_json::json!([]));
 }
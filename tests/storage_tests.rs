@@ -0,0 +1,215 @@
+use acip_sidecar::reputation::{self, JsonFileReputationStore, ReputationStore};
+use acip_sidecar::stats::{DecisionStats, StatsSettings};
+use acip_sidecar::storage::{self, CheckStatus, StorageError, StoreFile};
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A reputation file as written before store files were versioned (format version 1).
+const REPUTATION_V1: &str = r#"{
+  "records": {
+    "source_id:mail-1": {
+      "key": "source_id:mail-1",
+      "seen_count": 3,
+      "suspected_attack_count": 1,
+      "last_seen_unix": 1700000000,
+      "last_attack_types": [
+        "PromptInjection"
+      ],
+      "risk_score": 40
+    }
+  }
+}"#;
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_slice(&fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn v1_reputation_file_is_migrated_and_backed_up_until_the_first_write() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    fs::write(&path, REPUTATION_V1).unwrap();
+
+    let store = JsonFileReputationStore::load_or_create(&path).unwrap();
+    let rec = store.get("source_id:mail-1").unwrap();
+    assert_eq!(rec.first_seen_unix, 1_700_000_000);
+    assert_eq!(rec.total_ingests(), 3);
+    assert_eq!(rec.clean_ingests(), 2);
+    assert_eq!(rec.risk_score, 40);
+
+    // Nothing written yet: the original stays as it was and a byte-exact copy is kept.
+    let backup = storage::backup_path(&path, 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), REPUTATION_V1);
+    assert_eq!(fs::read_to_string(&backup).unwrap(), REPUTATION_V1);
+    let info = store.storage().unwrap();
+    assert_eq!(info.format, "reputation");
+    assert_eq!(info.version, 1);
+    assert_eq!(info.supported, storage::REPUTATION.current);
+    assert_eq!(info.migrated_from, Some(1));
+    assert_eq!(info.backup, Some(backup.display().to_string()));
+
+    let mut obs = reputation::observation("mail-2".to_string(), None, 0, vec![]);
+    obs.now_unix = 1_700_000_100;
+    store.record(obs);
+
+    assert!(!backup.exists(), "backup removed after the first write");
+    let v = read_json(&path);
+    assert_eq!(v["format"], "reputation");
+    assert_eq!(v["format_version"], 2);
+    assert_eq!(v["min_release"], env!("CARGO_PKG_VERSION"));
+    let migrated = &v["records"]["source_id:mail-1"];
+    assert_eq!(migrated["first_seen_unix"], 1_700_000_000);
+    assert_eq!(migrated["seen_count"], 3);
+    let info = store.storage().unwrap();
+    assert_eq!(info.version, 2);
+    assert_eq!(info.backup, None);
+
+    // Reopening the migrated file runs no migration.
+    drop(store);
+    let store = JsonFileReputationStore::load_or_create(&path).unwrap();
+    assert_eq!(store.storage().unwrap().migrated_from, None);
+    assert!(!backup.exists());
+}
+
+#[test]
+fn read_only_open_migrates_in_memory_without_a_backup() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    fs::write(&path, REPUTATION_V1).unwrap();
+
+    let store = JsonFileReputationStore::open_read_only(&path).unwrap();
+    assert_eq!(
+        store.get("source_id:mail-1").unwrap().first_seen_unix,
+        1_700_000_000
+    );
+    assert!(!storage::backup_path(&path, 1).exists());
+    assert_eq!(fs::read_to_string(&path).unwrap(), REPUTATION_V1);
+}
+
+#[test]
+fn newer_file_is_refused_with_its_version_and_release() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    let newer = r#"{"format":"reputation","format_version":9,"min_release":"3.2.0","records":{}}"#;
+    fs::write(&path, newer).unwrap();
+
+    let err = match JsonFileReputationStore::load_or_create(&path) {
+        Ok(_) => panic!("a version 9 file must not open"),
+        Err(e) => e,
+    };
+    let msg = format!("{err:#}");
+    assert!(msg.contains("version 9"), "{msg}");
+    assert!(msg.contains("3.2.0"), "{msg}");
+    assert!(matches!(
+        err.downcast_ref::<StorageError>(),
+        Some(StorageError::TooNew { version: 9, .. })
+    ));
+    // Left untouched, not quarantined.
+    assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+}
+
+#[test]
+fn file_of_another_format_is_refused() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.json");
+    fs::write(
+        &path,
+        r#"{"format":"reputation","format_version":2,"records":{}}"#,
+    )
+    .unwrap();
+
+    let err = DecisionStats::load_or_create(
+        &path,
+        StatsSettings::default(),
+        Arc::new(reputation::SystemClock),
+    )
+    .err()
+    .unwrap();
+    assert!(format!("{err}").contains("\"reputation\""), "{err}");
+}
+
+#[test]
+fn unversioned_stats_file_opens_as_version_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stats.json");
+    fs::write(&path, r#"{"days":{}}"#).unwrap();
+
+    let stats = DecisionStats::load_or_create(
+        &path,
+        StatsSettings::default(),
+        Arc::new(reputation::SystemClock),
+    )
+    .unwrap();
+    let info = stats.storage().unwrap();
+    assert_eq!((info.version, info.supported), (1, 1));
+    assert_eq!(info.migrated_from, None);
+}
+
+#[test]
+fn check_reports_pending_migrations_without_applying_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    fs::write(&path, REPUTATION_V1).unwrap();
+
+    let report = storage::check(&path, &storage::REPUTATION);
+    assert_eq!(report.status, CheckStatus::MigrationPending);
+    assert_eq!(report.version, Some(1));
+    assert_eq!(report.pending.len(), 1);
+    assert_eq!((report.pending[0].from, report.pending[0].to), (1, 2));
+    assert_eq!(fs::read_to_string(&path).unwrap(), REPUTATION_V1);
+    assert!(!storage::backup_path(&path, 1).exists());
+
+    let missing = storage::check(&dir.path().join("none.json"), &storage::STATS);
+    assert_eq!(missing.status, CheckStatus::Missing);
+}
+
+#[test]
+fn written_files_carry_the_envelope() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("rep.json");
+    let file = StoreFile::new(&path, &storage::REPUTATION);
+    file.write(&serde_json::json!({"records": {}})).unwrap();
+
+    let report = storage::check(&path, &storage::REPUTATION);
+    assert_eq!(report.status, CheckStatus::Current);
+    assert_eq!(report.version, Some(storage::REPUTATION.current));
+}
+
+#[test]
+fn acipctl_storage_check_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    let rep = dir.path().join("rep.json");
+    fs::write(&rep, REPUTATION_V1).unwrap();
+    let stats = dir.path().join("stats.json");
+    fs::write(&stats, r#"{"format":"stats","format_version":7,"days":{}}"#).unwrap();
+
+    let out = cargo_bin_cmd!("acipctl")
+        .env_remove("ACIP_STATS_STORE")
+        .env_remove("ACIP_SLOW_REQUEST_STORE")
+        .args(["storage", "check", "--json", "--reputation"])
+        .arg(&rep)
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1), "migration pending");
+    let reports: Vec<Value> = serde_json::from_slice(&out.stdout).unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0]["status"], "migration_pending");
+    assert_eq!(reports[0]["pending"][0]["from"], 1);
+    assert!(!storage::backup_path(&rep, 1).exists());
+
+    let out = cargo_bin_cmd!("acipctl")
+        .env("ACIP_STATS_STORE", format!("file:{}", stats.display()))
+        .env_remove("ACIP_SLOW_REQUEST_STORE")
+        .args(["storage", "check", "--reputation"])
+        .arg(&rep)
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(2), "a newer file cannot be opened");
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("stats path="), "{stdout}");
+    assert!(stdout.contains("status=too_new"), "{stdout}");
+    assert!(stdout.contains("migrate v1 -> v2"), "{stdout}");
+}
@@ -0,0 +1,275 @@
+use acip_sidecar::config::FeedConfig;
+use acip_sidecar::feeds::{FeedRegistry, FeedSpec};
+use acip_sidecar::sentry::ModelClient;
+use acip_sidecar::telemetry::{
+    InMemoryExporter, SpanKind, SpanRecord, Telemetry, TraceContext, TRACEPARENT,
+};
+use acip_sidecar::test_support::ScriptedModel;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, state};
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, StatusCode},
+    routing::{get, post},
+    Router,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+use tower::ServiceExt;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+fn caller() -> String {
+    format!("00-{TRACE_ID}-{PARENT_ID}-01")
+}
+
+/// Answers like a benign provider and keeps the headers of every model request.
+#[derive(Default)]
+struct CapturingModel {
+    seen: Mutex<Vec<HeaderMap>>,
+}
+
+#[async_trait]
+impl ModelClient for CapturingModel {
+    async fn generate(
+        &self,
+        model: &str,
+        prompt: &str,
+        headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        self.seen.lock().unwrap().push(headers.clone());
+        ScriptedModel::benign()
+            .generate(model, prompt, headers)
+            .await
+    }
+}
+
+fn test_state(exporter: Arc<InMemoryExporter>, model: Arc<CapturingModel>) -> Arc<state::AppState> {
+    std::env::set_var("ACIP_SENTRY_MODE", "live");
+    AppStateBuilder::for_tests()
+        .with_model(model)
+        .with_telemetry(Arc::new(Telemetry::new(exporter)))
+        .build()
+        .unwrap()
+}
+
+async fn ingest(st: Arc<state::AppState>, traceparent: Option<&str>) -> Value {
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    let mut req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json");
+    if let Some(tp) = traceparent {
+        req = req
+            .header(TRACEPARENT, tp)
+            .header("tracestate", "vendor=abc");
+    }
+    let body = json!({
+        "source_id": "doc-1",
+        "source_type": "other",
+        "content_type": "text/plain",
+        "text": "Quarterly numbers attached, nothing unusual.",
+    });
+    let resp = app::build_router(st, None, extra)
+        .oneshot(req.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+fn named<'a>(spans: &'a [SpanRecord], name: &str) -> Vec<&'a SpanRecord> {
+    spans.iter().filter(|s| s.name == name).collect()
+}
+
+#[test]
+fn traceparent_parsing_follows_the_spec() {
+    let ctx = TraceContext::parse(&caller(), Some(" vendor=abc ")).unwrap();
+    assert_eq!(ctx.trace_id.to_string(), TRACE_ID);
+    assert_eq!(ctx.span_id.to_string(), PARENT_ID);
+    assert!(ctx.sampled());
+    assert_eq!(ctx.tracestate.as_deref(), Some("vendor=abc"));
+    assert_eq!(ctx.traceparent(), caller());
+
+    // A later version may append fields.
+    let future = format!("01-{TRACE_ID}-{PARENT_ID}-00-extra");
+    assert!(!TraceContext::parse(&future, None).unwrap().sampled());
+
+    for bad in [
+        "",
+        "garbage",
+        &format!("ff-{TRACE_ID}-{PARENT_ID}-01"),
+        &format!("00-{}-{PARENT_ID}-01", TRACE_ID.to_uppercase()),
+        &format!("00-{}-{PARENT_ID}-01", "0".repeat(32)),
+        &format!("00-{TRACE_ID}-{}-01", "0".repeat(16)),
+        &format!("00-{TRACE_ID}-{PARENT_ID}-01-extra"),
+        &format!("00-{TRACE_ID}-{PARENT_ID}-1"),
+        &format!("00_{TRACE_ID}_{PARENT_ID}_01"),
+        &format!("0g-{TRACE_ID}-{PARENT_ID}-01"),
+    ] {
+        assert!(TraceContext::parse(bad, None).is_none(), "{bad:?}");
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.append(TRACEPARENT, caller().parse().unwrap());
+    headers.append("tracestate", "a=1".parse().unwrap());
+    headers.append("tracestate", "b=2".parse().unwrap());
+    let ctx = TraceContext::from_headers(&headers).unwrap();
+    assert_eq!(ctx.tracestate.as_deref(), Some("a=1,b=2"));
+    headers.append(TRACEPARENT, caller().parse().unwrap());
+    assert!(TraceContext::from_headers(&headers).is_none());
+}
+
+#[tokio::test]
+async fn request_joins_the_callers_trace_and_stages_nest_under_it() {
+    let exporter = Arc::new(InMemoryExporter::default());
+    let model = Arc::new(CapturingModel::default());
+    let v = ingest(test_state(exporter.clone(), model.clone()), Some(&caller())).await;
+    let request_id = v["origin"]["request_id"].as_str().unwrap();
+
+    let spans = exporter.spans();
+    assert!(spans.iter().all(|s| s.trace_id == TRACE_ID), "{spans:?}");
+
+    let request = named(&spans, "acip.ingest");
+    assert_eq!(request.len(), 1);
+    let request = request[0];
+    assert_eq!(request.kind, SpanKind::Server);
+    assert_eq!(request.parent_span_id.as_deref(), Some(PARENT_ID));
+    assert_eq!(request.attributes["acip.request_id"], request_id);
+    assert_eq!(request.attributes["http.response.status_code"], 200);
+
+    let stages: Vec<&SpanRecord> = spans
+        .iter()
+        .filter(|s| s.name.starts_with("acip.stage."))
+        .collect();
+    for name in [
+        "acip.stage.prepare",
+        "acip.stage.model",
+        "acip.stage.serialize",
+    ] {
+        assert_eq!(named(&spans, name).len(), 1, "{name}: {spans:?}");
+    }
+    for s in &stages {
+        assert_eq!(s.parent_span_id.as_deref(), Some(request.span_id.as_str()));
+        assert!(s.start_unix_nanos >= request.start_unix_nanos);
+        assert!(s.end_unix_nanos <= request.end_unix_nanos);
+    }
+
+    let model_stage = named(&spans, "acip.stage.model")[0];
+    let calls = named(&spans, "acip.model_call");
+    assert!(!calls.is_empty());
+    for c in calls {
+        assert_eq!(
+            c.parent_span_id.as_deref(),
+            Some(model_stage.span_id.as_str())
+        );
+    }
+
+    // The provider saw the model stage's context, with the caller's tracestate.
+    let seen = model.seen.lock().unwrap();
+    assert!(!seen.is_empty());
+    for headers in seen.iter() {
+        let ctx = TraceContext::from_headers(headers).unwrap();
+        assert_eq!(ctx.trace_id.to_string(), TRACE_ID);
+        assert_eq!(ctx.span_id.to_string(), model_stage.span_id);
+        assert_eq!(ctx.tracestate.as_deref(), Some("vendor=abc"));
+    }
+}
+
+#[tokio::test]
+async fn malformed_traceparent_starts_a_new_root_trace() {
+    let exporter = Arc::new(InMemoryExporter::default());
+    let model = Arc::new(CapturingModel::default());
+    let bad = format!("00-{}-{PARENT_ID}-01", "0".repeat(32));
+    ingest(test_state(exporter.clone(), model.clone()), Some(&bad)).await;
+
+    let spans = exporter.spans();
+    let request = named(&spans, "acip.ingest")[0];
+    assert!(request.parent_span_id.is_none());
+    assert_ne!(request.trace_id, "0".repeat(32));
+    assert!(spans.iter().all(|s| s.trace_id == request.trace_id));
+
+    let seen = model.seen.lock().unwrap();
+    let ctx = TraceContext::from_headers(&seen[0]).unwrap();
+    assert_eq!(ctx.trace_id.to_string(), request.trace_id);
+}
+
+#[tokio::test]
+async fn feed_refresh_carries_the_trace_to_the_feed_server() {
+    let seen: Arc<Mutex<Option<String>>> = Arc::default();
+    let recorded = seen.clone();
+    let server = Router::new().route(
+        "/list.txt",
+        get(move |headers: HeaderMap| async move {
+            *recorded.lock().unwrap() = headers
+                .get(TRACEPARENT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            "evil.example\n"
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, server).await.unwrap() });
+
+    let cfg: FeedConfig = toml::from_str(&format!(
+        "name = \"bad\"\ntype = \"domain_blocklist\"\nsource = \"http://{addr}/list.txt\"\nrefresh_secs = 60"
+    ))
+    .unwrap();
+    let exporter = Arc::new(InMemoryExporter::default());
+    let specs = FeedSpec::from_configs(&[cfg], &HashSet::from(["127.0.0.1".to_string()])).unwrap();
+    let feeds = FeedRegistry::new(
+        specs,
+        reqwest::Client::new(),
+        Arc::new(acip_sidecar::reputation::SystemClock),
+    )
+    .with_telemetry(Telemetry::new(exporter.clone()));
+
+    let parent = TraceContext::parse(&caller(), None).unwrap();
+    feeds.refresh_traced("bad", Some(&parent)).await.unwrap();
+
+    let spans = exporter.spans();
+    assert_eq!(spans.len(), 1);
+    let span = &spans[0];
+    assert_eq!(span.name, "acip.feed.refresh");
+    assert_eq!(span.trace_id, TRACE_ID);
+    assert_eq!(span.parent_span_id.as_deref(), Some(PARENT_ID));
+    assert_eq!(span.attributes["acip.feed"], "bad");
+
+    let sent = seen.lock().unwrap().clone().unwrap();
+    assert_eq!(sent, format!("00-{TRACE_ID}-{}-01", span.span_id));
+}
+
+#[test]
+fn telemetry_config_needs_an_http_endpoint() {
+    let http = reqwest::Client::new();
+    assert!(!Telemetry::from_config(None, http.clone())
+        .unwrap()
+        .is_enabled());
+    let cfg = acip_sidecar::config::TelemetryConfig {
+        otlp_endpoint: Some("ftp://collector:4318".to_string()),
+        service_name: None,
+    };
+    let err = Telemetry::from_config(Some(&cfg), http.clone())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("otlp_endpoint"), "{err}");
+
+    let cfg = acip_sidecar::config::TelemetryConfig {
+        otlp_endpoint: Some("http://collector:4318".to_string()),
+        service_name: None,
+    };
+    let t = Telemetry::from_config(Some(&cfg), http).unwrap();
+    assert_eq!(t.is_enabled(), cfg!(feature = "otel"));
+}
@@ -0,0 +1,145 @@
+use acip_sidecar::model_policy::{GarbledTextHandling, PolicyConfig};
+use acip_sidecar::text_quality::{assess, QualityBucket, SAMPLE_THRESHOLD_BYTES};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest, policy_store};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+const CLEAN: &str = include_str!("fixtures/text_quality_clean.txt");
+const OCR_NOISE: &str = include_str!("fixtures/text_quality_ocr_noise.txt");
+const ONE_CHAR_LINES: &str = include_str!("fixtures/text_quality_one_char_lines.txt");
+const LATIN1_MISDECODED: &str = include_str!("fixtures/text_quality_latin1_misdecoded.txt");
+
+#[test]
+fn clean_prose_is_good() {
+    let q = assess(CLEAN);
+    assert_eq!(q.bucket, QualityBucket::Good, "{q:?}");
+    assert_eq!(q.language, Some("en"));
+    assert!(q.dictionary_word_ratio > 0.4, "{q:?}");
+    assert_eq!(q.replacement_chars, 0);
+    assert_eq!(q.mojibake_sequences, 0);
+    assert!(!q.sampled);
+}
+
+#[test]
+fn ocr_noise_is_garbled() {
+    let q = assess(OCR_NOISE);
+    assert_eq!(q.bucket, QualityBucket::Garbled, "{q:?}");
+    assert!(q.dictionary_word_ratio < 0.1, "{q:?}");
+}
+
+#[test]
+fn one_char_per_line_pdf_output_is_garbled() {
+    let q = assess(ONE_CHAR_LINES);
+    assert_eq!(q.bucket, QualityBucket::Garbled, "{q:?}");
+    assert!(q.single_char_line_ratio > 0.9, "{q:?}");
+    assert!(q.avg_line_length < 1.5, "{q:?}");
+}
+
+#[test]
+fn latin1_misdecoded_text_is_degraded() {
+    let q = assess(LATIN1_MISDECODED);
+    assert_eq!(q.bucket, QualityBucket::Degraded, "{q:?}");
+    assert_eq!(q.language, Some("fr"));
+    assert!(q.mojibake_sequences > 20, "{q:?}");
+}
+
+#[test]
+fn replacement_characters_count_against_quality() {
+    let broken: String = CLEAN
+        .split(' ')
+        .map(|w| format!("{w}\u{FFFD}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let q = assess(&broken);
+    assert!(q.replacement_chars > 100, "{q:?}");
+    assert_eq!(q.bucket, QualityBucket::Garbled, "{q:?}");
+}
+
+#[test]
+fn short_and_empty_texts_are_not_judged_by_dictionary() {
+    assert_eq!(assess("").bucket, QualityBucket::Good);
+    assert_eq!(assess("Kubernetes rollout").bucket, QualityBucket::Good);
+}
+
+#[test]
+fn huge_texts_are_sampled() {
+    let big = CLEAN.repeat(SAMPLE_THRESHOLD_BYTES / CLEAN.len() * 8);
+    let q = assess(&big);
+    assert!(q.sampled);
+    assert!(q.analyzed_chars < big.len() / 4, "{q:?}");
+    assert_eq!(q.bucket, QualityBucket::Good, "{q:?}");
+
+    let big_noise = OCR_NOISE.repeat(SAMPLE_THRESHOLD_BYTES / OCR_NOISE.len() * 8);
+    assert_eq!(assess(&big_noise).bucket, QualityBucket::Garbled);
+}
+
+fn app_with(on_garbled: GarbledTextHandling) -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let mut policies = std::collections::BTreeMap::new();
+    policies.insert(
+        "default".to_string(),
+        PolicyConfig {
+            on_garbled_text: on_garbled,
+            ..PolicyConfig::default()
+        },
+    );
+
+    let st = AppStateBuilder::for_tests()
+        .with_policies(policy_store::PolicyStore::from_file(
+            policy_store::PoliciesFile { policies },
+        ))
+        .build()
+        .unwrap();
+
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn ingest_text(app: Router, text: &str) -> Value {
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "source_id": "scan-1",
+                "source_type": "other",
+                "content_type": "text/plain",
+                "text": text,
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let resp = app.oneshot(req).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn quality_is_reported_and_garbled_can_route_to_review() {
+    let v = ingest_text(app_with(GarbledTextHandling::Ignore), OCR_NOISE).await;
+    assert_eq!(v["text_quality"]["bucket"], "garbled");
+    assert_eq!(v["action"], "allow");
+
+    let v = ingest_text(app_with(GarbledTextHandling::NeedsReview), OCR_NOISE).await;
+    assert_eq!(v["action"], "needs_review");
+    assert_eq!(v["tools_allowed"], false);
+    let reasons = v["reasons"].to_string();
+    assert!(reasons.contains("extracted text garbled"), "{reasons}");
+
+    // Clean text is untouched by the option.
+    let v = ingest_text(app_with(GarbledTextHandling::NeedsReview), CLEAN).await;
+    assert_eq!(v["text_quality"]["bucket"], "good");
+    assert_eq!(v["action"], "allow");
+}
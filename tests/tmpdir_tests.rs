@@ -0,0 +1,255 @@
+use acip_sidecar::extract::{run_helper, ExtractKind, ExtractRequest, ExtractorError};
+use acip_sidecar::tmpdir::{TmpDirManager, TmpDirSettings, TMP_PREFIX};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, ingest};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::{
+    fs::File,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tower::ServiceExt;
+
+fn settings(base: &Path) -> TmpDirSettings {
+    TmpDirSettings {
+        base: base.to_path_buf(),
+        orphan_max_age: Duration::from_secs(3600),
+        ..TmpDirSettings::default()
+    }
+}
+
+fn age(path: &Path, by: Duration) {
+    File::open(path)
+        .unwrap()
+        .set_modified(SystemTime::now() - by)
+        .unwrap();
+}
+
+fn names(base: &Path) -> Vec<String> {
+    let mut v: Vec<String> = std::fs::read_dir(base)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    v.sort();
+    v
+}
+
+#[test]
+fn startup_cleanup_removes_old_prefixed_orphans_only() {
+    let base = tempfile::tempdir().unwrap();
+    let day = Duration::from_secs(86_400);
+
+    let old_dir = base.path().join(format!("{TMP_PREFIX}extractor-dead1"));
+    std::fs::create_dir(&old_dir).unwrap();
+    std::fs::write(old_dir.join("out.json"), vec![0u8; 1000]).unwrap();
+    age(&old_dir, day);
+    let old_file = base.path().join(format!("{TMP_PREFIX}spool.bin"));
+    std::fs::write(&old_file, vec![0u8; 500]).unwrap();
+    age(&old_file, day);
+
+    let recent = base.path().join(format!("{TMP_PREFIX}extractor-fresh"));
+    std::fs::create_dir(&recent).unwrap();
+    let foreign = base.path().join("someone-else.tmp");
+    std::fs::write(&foreign, b"x").unwrap();
+    age(&foreign, day);
+
+    let tmp = TmpDirManager::new(settings(base.path()));
+    let report = tmp.cleanup_orphans().unwrap();
+
+    assert_eq!(report.removed, 2);
+    assert_eq!(report.removed_bytes, 1500);
+    assert_eq!(report.skipped_recent, 1);
+    assert_eq!(report.failed, 0);
+    assert_eq!(
+        names(base.path()),
+        vec![
+            format!("{TMP_PREFIX}extractor-fresh"),
+            "someone-else.tmp".to_string()
+        ]
+    );
+}
+
+#[test]
+fn in_use_entries_are_never_cleaned() {
+    let base = tempfile::tempdir().unwrap();
+    let tmp = TmpDirManager::new(TmpDirSettings {
+        orphan_max_age: Duration::ZERO,
+        ..settings(base.path())
+    });
+
+    let dir = tmp.create_dir("extractor").unwrap();
+    std::fs::write(dir.path().join("out.json"), b"{}").unwrap();
+    age(dir.path(), Duration::from_secs(86_400));
+    assert!(tmp.is_owned(dir.path()));
+
+    let report = tmp.cleanup_orphans().unwrap();
+    assert_eq!(report.removed, 0);
+    assert_eq!(report.skipped_in_use, 1);
+    assert!(dir.path().join("out.json").exists());
+
+    let path = dir.path().to_path_buf();
+    drop(dir);
+    assert!(!path.exists());
+    assert_eq!(tmp.owned_count(), 0);
+}
+
+#[test]
+fn usage_is_measured_and_soft_limit_flagged() {
+    let base = tempfile::tempdir().unwrap();
+    let tmp = TmpDirManager::new(TmpDirSettings {
+        soft_limit_bytes: 1024,
+        ..settings(base.path())
+    });
+
+    let dir = tmp.create_dir("extractor").unwrap();
+    std::fs::write(dir.path().join("out.json"), vec![0u8; 600]).unwrap();
+    std::fs::write(base.path().join("unrelated"), vec![0u8; 4096]).unwrap();
+
+    let usage = tmp.refresh();
+    assert_eq!(usage.used_bytes, 600);
+    assert_eq!(usage.owned_entries, 1);
+    assert!(usage.free_bytes.unwrap() > 0);
+    assert_eq!(tmp.snapshot()["over_soft_limit"], false);
+
+    std::fs::write(dir.path().join("page-1.png"), vec![0u8; 600]).unwrap();
+    assert_eq!(tmp.refresh().used_bytes, 1200);
+    let snap = tmp.snapshot();
+    assert_eq!(snap["over_soft_limit"], true);
+    assert_eq!(snap["used_bytes"], 1200);
+}
+
+#[test]
+#[serial]
+fn helper_scratch_dir_is_tracked_and_removed() {
+    let base = tempfile::tempdir().unwrap();
+    let tmp = TmpDirManager::new(settings(base.path()));
+    let req = ExtractRequest {
+        kind: ExtractKind::Svg,
+        content_type: None,
+        max_pages: None,
+        dpi: None,
+        max_output_chars: None,
+        force_ocr: false,
+        password: None,
+    };
+
+    // No such binary: the scratch dir is created first and must not leak on failure.
+    std::env::set_var("ACIP_EXTRACTOR_BIN", "/nonexistent/acip-extract");
+    let err = run_helper(&tmp, &req, b"<svg/>", Duration::from_secs(5)).unwrap_err();
+    std::env::remove_var("ACIP_EXTRACTOR_BIN");
+
+    assert!(matches!(err, ExtractorError::Spawn(_)), "{err:?}");
+    assert_eq!(tmp.owned_count(), 0);
+    assert!(names(base.path()).is_empty());
+}
+
+fn router(tmp: TmpDirManager) -> Router {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let st = AppStateBuilder::for_tests()
+        .with_tmp(Arc::new(tmp))
+        .build()
+        .unwrap();
+
+    let extra = Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source));
+    app::build_router(st, None, extra)
+}
+
+async fn post_json(app: &Router, uri: &str, body: Value) -> (StatusCode, Value) {
+    let resp = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let v = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, v)
+}
+
+#[tokio::test]
+async fn extraction_is_refused_below_the_free_space_floor() {
+    // A volume with less free space than the floor; stands in for a filled small tmpfs.
+    let base = tempfile::tempdir().unwrap();
+    let app = router(TmpDirManager::new(TmpDirSettings {
+        min_free_bytes: u64::MAX,
+        ..settings(base.path())
+    }));
+
+    let (status, v) = post_json(
+        &app,
+        "/v1/acip/ingest_source",
+        json!({
+            "source_id": "doc",
+            "source_type": "pdf",
+            "content_type": "application/pdf",
+            "bytes_b64": "JVBERi0xLjQK",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(v["error"], "storage_exhausted");
+    assert_eq!(v["extra"]["min_free_bytes"], u64::MAX);
+    assert!(v["extra"]["free_bytes"].as_u64().is_some());
+    assert!(names(base.path()).is_empty());
+
+    // Text ingest needs no scratch space and keeps working.
+    let (status, _) = post_json(
+        &app,
+        "/v1/acip/ingest_source",
+        json!({
+            "source_id": "t",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "text": "hello",
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn status_reports_tmpdir_usage() {
+    let base = tempfile::tempdir().unwrap();
+    let tmp = TmpDirManager::new(settings(base.path()));
+    tmp.refresh();
+    let app = router(tmp);
+
+    let resp = app
+        .oneshot(
+            Request::builder()
+                .uri("/v1/acip/status")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    let v: Value = serde_json::from_slice(&bytes).unwrap();
+    let t = &v["tmpdir"];
+    assert_eq!(t["path"], base.path().display().to_string());
+    assert_eq!(t["used_bytes"], 0);
+    assert!(t["free_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(t["min_free_bytes"], 256 * 1024 * 1024);
+}
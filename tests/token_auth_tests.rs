@@ -3,29 +3,11 @@ use axum::{
     http::{Request, StatusCode},
     Router,
 };
-use acip_sidecar::{app, policy_store, reputation, secrets, state};
-use std::sync::Arc;
+use acip_sidecar::{app, app_state_builder::AppStateBuilder};
 use tower::ServiceExt;
 
 fn app_with_token(token: Option<String>) -> Router {
-    let mut policies = std::collections::BTreeMap::new();
-    policies.insert(
-        "default".to_string(),
-        acip_sidecar::model_policy::PolicyConfig::default(),
-    );
-
-    let st = Arc::new(state::AppState {
-        policy: state::Policy {
-            head: 4000,
-            tail: 4000,
-            full_if_lte: 9000,
-        },
-        normalize: state::NormalizeSettings::from_config(None),
-        http: reqwest::Client::new(),
-        secrets: Arc::new(secrets::EnvStore),
-        policies: policy_store::PolicyStore::from_file(policy_store::PoliciesFile { policies }),
-        reputation: Arc::new(reputation::InMemoryReputationStore::new()),
-    });
+    let st = AppStateBuilder::for_tests().build().unwrap();
 
     app::build_router(st, token, Router::new())
 }
@@ -0,0 +1,264 @@
+use acip_sidecar::model_policy::{PolicyConfig, Provider};
+use acip_sidecar::sentry::{
+    self, ContextExceeded, DecisionEngine, ModelClient, ModelTier, SentryVerdict,
+};
+use acip_sidecar::token_budget::{
+    self, Heuristic, TokenEstimator, COMPLETION_TOKENS, TRUNCATION_MARKER,
+};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("tests/fixtures/tokens/{name}")).unwrap()
+}
+
+fn allow() -> String {
+    json!({
+        "tools_allowed": true,
+        "risk_level": "low",
+        "action": "allow",
+        "fenced_content": "```external\nX\n```",
+        "reasons": [],
+        "detected_patterns": []
+    })
+    .to_string()
+}
+
+/// Allows everything, keeping the prompts it was sent. Prompts over `limit_chars` characters
+/// are refused the way a provider refuses a prompt over its context window.
+struct RecordingClient {
+    limit_chars: usize,
+    prompts: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingClient {
+    fn new(limit_chars: usize) -> (Self, Arc<Mutex<Vec<String>>>) {
+        let prompts = Arc::<Mutex<Vec<String>>>::default();
+        let client = Self {
+            limit_chars,
+            prompts: prompts.clone(),
+        };
+        (client, prompts)
+    }
+}
+
+#[async_trait]
+impl ModelClient for RecordingClient {
+    async fn generate(
+        &self,
+        _model: &str,
+        prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        if prompt.chars().count() > self.limit_chars {
+            return Err(ContextExceeded {
+                provider: "anthropic",
+                message: "prompt is too long".to_string(),
+            }
+            .into());
+        }
+        Ok(allow())
+    }
+}
+
+/// A policy whose L1 leaves `content_tokens` for the content once the template and the
+/// completion are paid for.
+fn policy_with_content_allowance(content_tokens: u64) -> PolicyConfig {
+    let mut p = PolicyConfig::default();
+    let template = DecisionEngine::build_prompt("default", &p, &json!({}), "");
+    let template_tokens = Heuristic::for_provider(&p.l1.provider).estimate(&template);
+    p.l1.context_tokens = Some(COMPLETION_TOKENS + template_tokens + content_tokens);
+    p
+}
+
+async fn decide(
+    l1: RecordingClient,
+    l2: RecordingClient,
+    p: &PolicyConfig,
+    content: &str,
+) -> SentryVerdict {
+    DecisionEngine::new(Box::new(l1), Box::new(l2))
+        .decide_tiered("default", p, &json!({}), content, &HeaderMap::new())
+        .await
+}
+
+#[tokio::test]
+async fn cjk_and_emoji_within_character_budgets_are_trimmed_to_the_token_budget() {
+    for name in ["cjk.txt", "emoji.txt", "prose.txt"] {
+        let content = fixture(name);
+        let chars = content.chars().count() as u64;
+        // Room for half a token per character: plenty for English, not for CJK or emoji.
+        let p = policy_with_content_allowance(chars / 2);
+        let (l1, prompts) = RecordingClient::new(usize::MAX);
+        let (l2, _) = RecordingClient::new(usize::MAX);
+        let v = decide(l1, l2, &p, &content).await;
+
+        assert_eq!(v.tier, ModelTier::L1, "{name}");
+        let budget = &v.budgets[0];
+        let prompt = prompts.lock().unwrap()[0].clone();
+        let est = Heuristic::for_provider(&Provider::Gemini);
+        assert!(est.estimate(&prompt) <= budget.max_prompt_tokens, "{name}");
+        assert_eq!(budget.prompt_tokens, est.estimate(&prompt), "{name}");
+        assert_eq!(v.calls[0].prompt_tokens, budget.prompt_tokens, "{name}");
+        assert!(v.calls[0].prompt_tokens_estimated, "{name}");
+
+        if name == "prose.txt" {
+            assert!(!budget.trimmed, "{name}: {budget:?}");
+            assert!(prompt.contains(&content));
+            continue;
+        }
+        assert!(budget.trimmed, "{name}: {budget:?}");
+        assert!(budget.estimated_content_tokens > budget.max_content_tokens);
+        assert!(prompt.contains(TRUNCATION_MARKER), "{name}");
+        // Both ends survive, and trimming is deterministic.
+        let head: String = content.chars().take(20).collect();
+        let tail: String = content.chars().skip(content.chars().count() - 20).collect();
+        assert!(prompt.contains(&head) && prompt.contains(&tail), "{name}");
+        let (again, _) = token_budget::fit_prompt(&p.l1, &content, None, |c| {
+            DecisionEngine::build_prompt("default", &p, &json!({}), c)
+        });
+        assert_eq!(again, prompt, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn context_exceeded_is_retried_once_with_half_the_budget() {
+    let content = fixture("cjk.txt").repeat(4);
+    let p = PolicyConfig::default();
+    let full = DecisionEngine::build_prompt("default", &p, &json!({}), &content);
+    // The provider takes two thirds of the content, whatever its advertised window.
+    let limit = full.chars().count() - content.chars().count() / 3;
+    let (l1, prompts) = RecordingClient::new(limit);
+    let (l2, l2_prompts) = RecordingClient::new(usize::MAX);
+    let v = decide(l1, l2, &p, &content).await;
+
+    assert_eq!(v.tier, ModelTier::L1);
+    assert!(l2_prompts.lock().unwrap().is_empty());
+    let prompts = prompts.lock().unwrap();
+    assert_eq!(prompts.len(), 2);
+    assert_eq!(prompts[0], full);
+    assert!(prompts[1].contains(TRUNCATION_MARKER));
+
+    let calls: Vec<(bool, bool)> = v.calls.iter().map(|c| (c.ok, c.context_retry)).collect();
+    assert_eq!(calls, [(false, false), (true, true)]);
+    let (first, retry) = (&v.budgets[0], &v.budgets[1]);
+    assert!(!first.trimmed && !first.context_retry);
+    assert!(retry.trimmed && retry.context_retry);
+    assert_eq!(retry.max_content_tokens, first.estimated_content_tokens / 2);
+}
+
+#[tokio::test]
+async fn a_second_context_exceeded_falls_through_to_l2() {
+    let content = fixture("emoji.txt");
+    let p = PolicyConfig::default();
+    let (l1, prompts) = RecordingClient::new(10);
+    let (l2, _) = RecordingClient::new(usize::MAX);
+    let v = decide(l1, l2, &p, &content).await;
+
+    assert_eq!(prompts.lock().unwrap().len(), 2);
+    assert_eq!(v.tier, ModelTier::L2);
+    let tiers: Vec<ModelTier> = v.calls.iter().map(|c| c.tier).collect();
+    assert_eq!(tiers, [ModelTier::L1, ModelTier::L1, ModelTier::L2]);
+    assert!(v.decision.tools_allowed);
+}
+
+#[test]
+fn provider_context_errors_are_recognised() {
+    assert!(sentry::is_context_exceeded(
+        r#"{"type":"error","error":{"type":"invalid_request_error","message":"prompt is too long: 212345 tokens > 200000 maximum"}}"#
+    ));
+    assert!(sentry::is_context_exceeded(
+        r#"{"error":{"code":400,"message":"The input token count (1100000) exceeds the maximum number of tokens allowed (1048576).","status":"INVALID_ARGUMENT"}}"#
+    ));
+    assert!(!sentry::is_context_exceeded(
+        r#"{"error":{"code":400,"message":"API key not valid. Please pass a valid API key.","status":"INVALID_ARGUMENT"}}"#
+    ));
+}
+
+#[test]
+fn heuristic_weighs_scripts_differently() {
+    let est = Heuristic::for_provider(&Provider::Gemini);
+    assert_eq!(est.estimate(""), 0);
+    assert_eq!(est.estimate("abcdefgh"), 2);
+    // Per character, emoji are heavier than CJK, and CJK heavier than English.
+    let per_char = |s: &str| est.estimate(s) as f64 / s.chars().count() as f64;
+    let (prose, cjk, emoji) = (
+        fixture("prose.txt"),
+        fixture("cjk.txt"),
+        fixture("emoji.txt"),
+    );
+    assert!(per_char(&prose) < 0.4);
+    assert!(per_char(&cjk) > 2.0 * per_char(&prose));
+    assert!(per_char(&emoji) > per_char(&cjk));
+    // Anthropic's tokenizer packs English less densely.
+    let claude = Heuristic::for_provider(&Provider::Anthropic);
+    assert!(claude.estimate(&prose) > est.estimate(&prose));
+}
+
+#[test]
+fn trimming_keeps_head_and_tail_within_budget() {
+    let est = Heuristic::for_provider(&Provider::Gemini);
+    let content = "头".repeat(100) + &"尾".repeat(100);
+    let (trimmed, cut) = token_budget::trim_to_budget(&content, 60, &est);
+    assert!(cut);
+    assert!(est.estimate(&trimmed) <= 60);
+    let (head, tail) = trimmed.split_once(TRUNCATION_MARKER).unwrap();
+    assert!(head.chars().all(|c| c == '头') && !head.is_empty());
+    assert!(tail.chars().all(|c| c == '尾') && !tail.is_empty());
+    assert!(head.chars().count() - tail.chars().count() <= 1);
+
+    assert_eq!(
+        token_budget::trim_to_budget("short", 60, &est),
+        ("short".to_string(), false)
+    );
+}
+
+#[cfg(feature = "bpe")]
+mod bpe {
+    use super::fixture;
+    use acip_sidecar::model_policy::Provider;
+    use acip_sidecar::token_budget::bpe::BpeTokenizer;
+    use acip_sidecar::token_budget::{Heuristic, TokenEstimator};
+    use std::collections::HashMap;
+
+    #[test]
+    fn merges_lowest_ranked_pairs_first() {
+        let mut ranks: HashMap<Vec<u8>, u32> = (0..=255u8).map(|b| (vec![b], b as u32)).collect();
+        ranks.insert(b"ab".to_vec(), 256);
+        ranks.insert(b"abc".to_vec(), 257);
+        let t = BpeTokenizer::from_ranks("test", ranks);
+        assert_eq!(t.estimate("abab"), 2);
+        assert_eq!(t.estimate("abcab"), 2);
+        assert_eq!(t.estimate("xyz"), 3);
+        // A space is split off with the following word.
+        assert_eq!(t.estimate("ab ab"), 3);
+    }
+
+    /// Heuristic estimates against a real tokenizer: set `ACIP_TOKENIZER_DIR` to a directory
+    /// holding `<ACIP_TOKENIZER_REFERENCE>.tiktoken` (default `cl100k_base`).
+    #[test]
+    fn heuristic_stays_within_tolerance_of_a_reference_tokenizer() {
+        let Ok(dir) = std::env::var("ACIP_TOKENIZER_DIR") else {
+            eprintln!("ACIP_TOKENIZER_DIR not set; skipping the reference comparison");
+            return;
+        };
+        let name =
+            std::env::var("ACIP_TOKENIZER_REFERENCE").unwrap_or_else(|_| "cl100k_base".into());
+        let path = std::path::Path::new(&dir).join(format!("{name}.tiktoken"));
+        let reference = BpeTokenizer::load(&name, &path).unwrap();
+        let est = Heuristic::for_provider(&Provider::Gemini);
+        for f in ["prose.txt", "cjk.txt", "emoji.txt"] {
+            let text = fixture(f);
+            let (estimated, actual) = (est.estimate(&text), reference.estimate(&text));
+            let ratio = estimated as f64 / actual as f64;
+            // Over-estimating only costs context; under-estimating risks a provider 400.
+            assert!(
+                (0.9..=2.5).contains(&ratio),
+                "{f}: estimated {estimated}, {name} counts {actual}"
+            );
+        }
+    }
+}
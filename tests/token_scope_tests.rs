@@ -0,0 +1,247 @@
+use acip_sidecar::token_auth::{Scope, TokenSet};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, config, ingest, startup, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::sync::{Arc, Once};
+use tower::ServiceExt;
+
+static INIT: Once = Once::new();
+
+fn init_env() {
+    INIT.call_once(|| {
+        std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+        std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    });
+}
+
+fn test_state() -> Arc<state::AppState> {
+    AppStateBuilder::for_tests().build().unwrap()
+}
+
+fn extra() -> Router<Arc<state::AppState>> {
+    Router::new().route("/v1/acip/ingest_source", post(ingest::ingest_source))
+}
+
+fn all_but(missing: Scope) -> BTreeSet<Scope> {
+    Scope::ALL.into_iter().filter(|s| *s != missing).collect()
+}
+
+/// One token per scope, named `no-<scope>`, holding every other scope; plus `full`.
+fn scoped_app() -> Router {
+    let mut tokens = TokenSet::default();
+    for scope in Scope::ALL {
+        let name = format!("no-{}", scope.as_str());
+        tokens
+            .add(&name, &format!("secret-{name}"), all_but(scope))
+            .unwrap();
+    }
+    tokens
+        .add("full", "secret-full", Scope::ALL.into_iter().collect())
+        .unwrap();
+    app::build_router_with_tokens(test_state(), tokens, extra())
+}
+
+/// Every token-protected endpoint and the scope it requires.
+const ENDPOINTS: &[(&str, &str, Scope)] = &[
+    ("GET", "/v1/acip/schema", Scope::Read),
+    ("GET", "/v1/acip/capabilities", Scope::Read),
+    ("GET", "/v1/acip/policies", Scope::Read),
+    ("GET", "/v1/acip/policy", Scope::Read),
+    ("GET", "/v1/acip/status", Scope::Read),
+    ("GET", "/v1/acip/reputation?key=source_id:s1", Scope::Read),
+    ("GET", "/v1/acip/reputation/records", Scope::Read),
+    ("GET", "/v1/acip/stats", Scope::Read),
+    ("GET", "/v1/acip/stats/aggregate", Scope::PlatformAdmin),
+    ("GET", "/v1/acip/slow_requests", Scope::Support),
+    ("GET", "/v1/acip/incidents/unknown", Scope::Support),
+    ("POST", "/v1/acip/ingest_source", Scope::Ingest),
+    ("POST", "/v1/acip/uploads", Scope::Ingest),
+    ("GET", "/v1/acip/jobs/unknown", Scope::Ingest),
+    (
+        "POST",
+        "/v1/acip/feeds/unknown/refresh",
+        Scope::ReputationAdmin,
+    ),
+    ("POST", "/v1/acip/admin/drain", Scope::Drain),
+    ("POST", "/v1/acip/admin/resume", Scope::Drain),
+    ("GET", "/v1/acip/admin/incidents/check", Scope::Support),
+];
+
+fn request(method: &str, uri: &str, token: Option<&str>) -> Request<Body> {
+    let mut b = Request::builder().method(method).uri(uri);
+    if let Some(t) = token {
+        b = b.header("X-ACIP-Token", t);
+    }
+    let body = if uri.ends_with("ingest_source") {
+        b = b.header("content-type", "application/json");
+        serde_json::json!({
+            "source_id": "s1",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "text": "hello world",
+        })
+        .to_string()
+    } else {
+        String::new()
+    };
+    b.body(Body::from(body)).unwrap()
+}
+
+async fn call(app: Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+/// Past auth: the handler ran (an unknown reputation key is still a 404).
+fn allowed(status: StatusCode) -> bool {
+    status != StatusCode::UNAUTHORIZED && status != StatusCode::FORBIDDEN
+}
+
+#[tokio::test]
+async fn each_endpoint_refuses_a_token_without_its_scope() {
+    init_env();
+    for (method, uri, scope) in ENDPOINTS {
+        let token = format!("secret-no-{}", scope.as_str());
+        let (status, v) = call(scoped_app(), request(method, uri, Some(&token))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{method} {uri}");
+        assert_eq!(v["error"], "insufficient_scope", "{method} {uri}");
+        assert_eq!(v["extra"]["required"], scope.as_str(), "{method} {uri}");
+        assert_eq!(
+            v["extra"]["token"],
+            format!("no-{}", scope.as_str()),
+            "{method} {uri}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn other_missing_scopes_do_not_matter() {
+    init_env();
+    for (method, uri, scope) in ENDPOINTS {
+        for other in Scope::ALL.into_iter().filter(|s| s != scope) {
+            let token = format!("secret-no-{}", other.as_str());
+            let (status, _) = call(scoped_app(), request(method, uri, Some(&token))).await;
+            assert!(
+                allowed(status),
+                "{method} {uri} without {other:?}: {status}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn bad_or_missing_token_is_401_not_403() {
+    init_env();
+    for (method, uri, _) in ENDPOINTS {
+        let (status, _) = call(scoped_app(), request(method, uri, None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {uri}");
+        let (status, _) = call(scoped_app(), request(method, uri, Some("nope"))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{method} {uri}");
+    }
+}
+
+#[tokio::test]
+async fn ingest_audit_names_the_token_not_its_value() {
+    init_env();
+    let mut tokens = TokenSet::default();
+    tokens
+        .add("review-team", "rt-value", [Scope::Ingest].into())
+        .unwrap();
+    let app = app::build_router_with_tokens(test_state(), tokens, extra());
+
+    let (status, v) = call(
+        app,
+        request("POST", "/v1/acip/ingest_source", Some("rt-value")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["actor"], "review-team");
+    assert!(!v.to_string().contains("rt-value"));
+}
+
+#[tokio::test]
+async fn legacy_single_token_has_every_scope() {
+    init_env();
+    for (method, uri, _) in ENDPOINTS {
+        let app = app::build_router(test_state(), Some("secret".into()), extra());
+        let (status, v) = call(app, request(method, uri, Some("secret"))).await;
+        assert!(allowed(status), "{method} {uri}: {status}");
+        if uri.ends_with("ingest_source") {
+            assert_eq!(v["actor"], TokenSet::LEGACY_NAME);
+        }
+    }
+}
+
+#[tokio::test]
+async fn unknown_admin_paths_are_404_without_a_token() {
+    init_env();
+    let app = app::build_router(test_state(), None, extra());
+    let (status, v) = call(app.clone(), request("POST", "/v1/acip/admin/drain", None)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(v["error"], "admin_disabled");
+
+    let (status, _) = call(app, request("POST", "/v1/acip/admin/nope", None)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn unknown_scope_name_is_a_config_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.toml");
+    std::fs::write(
+        &path,
+        r#"
+[security]
+require_token = true
+
+[[security.tokens]]
+name = "reviewers"
+secret = "ACIP_REVIEW_TOKEN"
+scopes = ["read", "quarantine_raed"]
+"#,
+    )
+    .unwrap();
+    let err = config::Config::load(&path).unwrap_err();
+    let msg = format!("{err:#}");
+    assert!(msg.contains("quarantine_raed"), "{msg}");
+    assert!(msg.contains("reviewers"), "{msg}");
+}
+
+#[test]
+fn named_tokens_resolve_from_the_secrets_store() {
+    std::env::set_var("ACIP_SCOPE_TEST_READER", "reader-value");
+    let secrets = startup::build_secrets_store(None).unwrap();
+    let named = vec![config::TokenConfig {
+        name: "reader".into(),
+        secret: "ACIP_SCOPE_TEST_READER".into(),
+        scopes: vec!["read".into()],
+    }];
+
+    // The legacy token is optional once named tokens exist.
+    let set = startup::resolve_tokens(true, &secrets, "ACIP_SCOPE_TEST_UNSET", &named).unwrap();
+    assert_eq!(set.names(), vec!["reader".to_string()]);
+    let actor = set.resolve("reader-value").unwrap();
+    assert_eq!(actor.name, "reader");
+    assert!(actor.has(Scope::Read) && !actor.has(Scope::Ingest));
+    assert!(set.resolve("reader-valu").is_none());
+
+    let missing = vec![config::TokenConfig {
+        secret: "ACIP_SCOPE_TEST_ABSENT".into(),
+        ..named[0].clone()
+    }];
+    assert!(startup::resolve_tokens(true, &secrets, "ACIP_SCOPE_TEST_UNSET", &missing).is_err());
+}
@@ -0,0 +1,410 @@
+mod util;
+
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::tmpdir::{TmpDirManager, TmpDirSettings, TMP_PREFIX};
+use acip_sidecar::uploads::{UploadSettings, UploadStore};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder, client, state};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tower::ServiceExt;
+use util::serve;
+
+const CHUNK: usize = 1024;
+
+fn sha(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Three chunks of text: two full, one short.
+fn payload() -> Vec<u8> {
+    "field scan bundle line\n".repeat(120).into_bytes()
+}
+
+struct Fixture {
+    state: Arc<state::AppState>,
+    clock: Arc<MockClock>,
+    tmp: Arc<TmpDirManager>,
+    _base: tempfile::TempDir,
+}
+
+fn fixture(max_sessions: usize) -> Fixture {
+    std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+
+    let base = tempfile::tempdir().unwrap();
+    let tmp = Arc::new(TmpDirManager::new(TmpDirSettings {
+        base: base.path().to_path_buf(),
+        min_free_bytes: 0,
+        ..TmpDirSettings::default()
+    }));
+    let clock = Arc::new(MockClock::new(1_000_000));
+    let uploads = Arc::new(UploadStore::new(
+        UploadSettings {
+            chunk_bytes: CHUNK as u64,
+            max_upload_bytes: 64 * 1024,
+            max_sessions,
+            session_ttl: Duration::from_secs(600),
+            ..UploadSettings::default()
+        },
+        tmp.clone(),
+        clock.clone(),
+    ));
+
+    let state = AppStateBuilder::for_tests()
+        .with_tmp(tmp.clone())
+        .with_uploads(uploads)
+        .build()
+        .unwrap();
+
+    Fixture {
+        state,
+        clock,
+        tmp,
+        _base: base,
+    }
+}
+
+impl Fixture {
+    fn router(&self) -> Router {
+        app::build_router(self.state.clone(), None, Router::new())
+    }
+
+    async fn call(&self, req: Request<Body>) -> (StatusCode, Value) {
+        let resp = self.router().oneshot(req).await.unwrap();
+        let status = resp.status();
+        let bytes = http_body_util::BodyExt::collect(resp.into_body())
+            .await
+            .unwrap()
+            .to_bytes();
+        (
+            status,
+            serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+        )
+    }
+
+    async fn create(&self, total_bytes: usize) -> (StatusCode, Value) {
+        let body = json!({
+            "source_id": "scanner-7",
+            "source_type": "other",
+            "content_type": "text/plain",
+            "total_bytes": total_bytes,
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/v1/acip/uploads")
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        self.call(req).await
+    }
+
+    async fn put(&self, id: &str, n: usize, chunk: &[u8]) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .method("PUT")
+            .uri(format!("/v1/acip/uploads/{id}/chunks/{n}"))
+            .header("x-acip-chunk-sha256", sha(chunk))
+            .body(Body::from(chunk.to_vec()))
+            .unwrap();
+        self.call(req).await
+    }
+
+    async fn status(&self, id: &str) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .uri(format!("/v1/acip/uploads/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        self.call(req).await
+    }
+
+    async fn complete(&self, id: &str, sha256: &str) -> (StatusCode, Value) {
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/v1/acip/uploads/{id}/complete"))
+            .header("content-type", "application/json")
+            .body(Body::from(json!({ "sha256": sha256 }).to_string()))
+            .unwrap();
+        self.call(req).await
+    }
+
+    fn upload_dirs(&self) -> usize {
+        std::fs::read_dir(&self.tmp.settings().base)
+            .unwrap()
+            .filter(|e| {
+                let name = e.as_ref().unwrap().file_name();
+                name.to_string_lossy()
+                    .starts_with(&format!("{TMP_PREFIX}upload-"))
+            })
+            .count()
+    }
+}
+
+fn chunks(data: &[u8]) -> Vec<&[u8]> {
+    data.chunks(CHUNK).collect()
+}
+
+#[tokio::test]
+async fn out_of_order_chunks_assemble_and_ingest() {
+    let f = fixture(4);
+    let data = payload();
+    let (status, v) = f.create(data.len()).await;
+    assert_eq!(status, StatusCode::CREATED, "{v}");
+    assert_eq!(v["chunk_bytes"], CHUNK);
+    assert_eq!(v["chunk_count"], 3);
+    assert_eq!(v["expires_unix"], 1_000_600);
+    let id = v["upload_id"].as_str().unwrap().to_string();
+
+    let parts = chunks(&data);
+    for n in [2, 0] {
+        let (status, v) = f.put(&id, n, parts[n]).await;
+        assert_eq!(status, StatusCode::OK, "{v}");
+        assert_eq!(v["duplicate"], false);
+    }
+    let (_, v) = f.status(&id).await;
+    assert_eq!(v["received"], json!([0, 2]));
+    assert_eq!(v["missing"], json!([1]));
+
+    let (status, v) = f.complete(&id, &sha(&data)).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(v["error"], "upload_incomplete");
+    assert_eq!(v["extra"]["missing"], json!([1]));
+
+    f.put(&id, 1, parts[1]).await;
+    let (status, v) = f.complete(&id, &sha(&data)).await;
+    assert_eq!(status, StatusCode::OK, "{v}");
+    assert_eq!(v["digest"]["sha256"], sha(&data));
+    assert_eq!(v["digest"]["length"], data.len());
+    assert_eq!(v["action"], "allow");
+
+    // The session and its directory are gone once ingest succeeded.
+    assert_eq!(f.status(&id).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(f.upload_dirs(), 0);
+    assert_eq!(f.tmp.owned_count(), 0);
+}
+
+#[tokio::test]
+async fn duplicate_chunks_are_verified_noops() {
+    let f = fixture(4);
+    let data = payload();
+    let id = f.create(data.len()).await.1["upload_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let parts = chunks(&data);
+
+    assert_eq!(f.put(&id, 0, parts[0]).await.1["duplicate"], false);
+    let (status, v) = f.put(&id, 0, parts[0]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["duplicate"], true);
+    assert_eq!(v["remaining"], 2);
+
+    // Same index, different content: refused, the stored chunk is kept.
+    let other = vec![b'x'; CHUNK];
+    let (status, v) = f.put(&id, 0, &other).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(v["error"], "chunk_conflict");
+    assert_eq!(v["extra"]["stored_sha256"], sha(parts[0]));
+
+    // A body that does not match its checksum header is never stored.
+    let req = Request::builder()
+        .method("PUT")
+        .uri(format!("/v1/acip/uploads/{id}/chunks/1"))
+        .header("x-acip-chunk-sha256", sha(parts[0]))
+        .body(Body::from(parts[1].to_vec()))
+        .unwrap();
+    let (status, v) = f.call(req).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "chunk_checksum_mismatch");
+
+    let (status, v) = f.put(&id, 1, &parts[1][..10]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(v["error"], "invalid_chunk_length");
+    let (status, _) = f.put(&id, 3, parts[2]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    assert_eq!(f.status(&id).await.1["received"], json!([0]));
+}
+
+#[tokio::test]
+async fn hash_mismatch_is_rejected_at_complete() {
+    let f = fixture(4);
+    let data = payload();
+    let id = f.create(data.len()).await.1["upload_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    for (n, part) in chunks(&data).into_iter().enumerate() {
+        f.put(&id, n, part).await;
+    }
+
+    let wrong = sha(b"something else");
+    let (status, v) = f.complete(&id, &wrong).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(v["error"], "upload_hash_mismatch");
+    assert_eq!(v["extra"]["expected"], wrong);
+    assert_eq!(v["extra"]["actual"], sha(&data));
+
+    // The session stays open for a corrected retry.
+    assert_eq!(f.status(&id).await.0, StatusCode::OK);
+    assert_eq!(f.complete(&id, &sha(&data)).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expired_sessions_are_swept_with_their_chunks() {
+    let f = fixture(4);
+    let data = payload();
+    let id = f.create(data.len()).await.1["upload_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let keep = f.create(data.len()).await.1["upload_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    f.put(&id, 0, chunks(&data)[0]).await;
+    assert_eq!(f.upload_dirs(), 2);
+
+    // Activity pushes the expiry out.
+    f.clock.advance(500);
+    f.put(&keep, 0, chunks(&data)[0]).await;
+    f.clock.advance(200);
+
+    assert_eq!(f.state.uploads.sweep_expired(), 1);
+    assert_eq!(f.status(&id).await.0, StatusCode::NOT_FOUND);
+    assert_eq!(f.status(&keep).await.0, StatusCode::OK);
+    assert_eq!(f.upload_dirs(), 1);
+    assert_eq!(f.tmp.owned_count(), 1);
+}
+
+#[tokio::test]
+async fn session_count_and_size_are_bounded() {
+    let f = fixture(1);
+    let (status, v) = f.create(64 * 1024 + 1).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(v["error"], "invalid_upload_size");
+    assert_eq!(f.create(0).await.0, StatusCode::BAD_REQUEST);
+
+    assert_eq!(f.create(10).await.0, StatusCode::CREATED);
+    let (status, v) = f.create(10).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(v["extra"]["max_sessions"], 1);
+}
+
+/// Lets `allow` chunk PUTs through, then answers 503 as if the link dropped.
+fn flaky(router: Router, allow: usize, puts: Arc<AtomicUsize>) -> Router {
+    router.layer(middleware::from_fn(
+        move |req: axum::extract::Request, next: Next| {
+            let puts = puts.clone();
+            async move {
+                if req.method() == "PUT" && puts.fetch_add(1, Ordering::SeqCst) >= allow {
+                    return StatusCode::SERVICE_UNAVAILABLE.into_response();
+                }
+                let resp: Response = next.run(req).await;
+                resp
+            }
+        },
+    ))
+}
+
+fn write_file(dir: &Path, len: usize) -> std::path::PathBuf {
+    let path = dir.join("bundle.txt");
+    let data: Vec<u8> = "scan line\n".bytes().cycle().take(len).collect();
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn client_resumes_after_restart_from_its_state_file() {
+    let f = fixture(4);
+    let work = tempfile::tempdir().unwrap();
+    let path = write_file(work.path(), CHUNK * 5 + 100);
+    let state_file = work.path().join("bundle.txt.acip-upload.json");
+    let meta = json!({
+        "source_id": "scanner-7",
+        "source_type": "other",
+        "content_type": "text/plain",
+    });
+    let no_retry = client::RetryPolicy {
+        chunk_retries: 0,
+        backoff: Duration::ZERO,
+    };
+
+    // First run: the link drops after two chunks.
+    let first_puts = Arc::new(AtomicUsize::new(0));
+    let addr = serve(flaky(f.router(), 2, first_puts.clone()));
+    let c = client::Client::new(&format!("http://{addr}"), None);
+    let err = c
+        .upload_resumable(&path, &meta, &[], &state_file, &no_retry)
+        .unwrap_err();
+    assert!(err.to_string().contains("chunk 2"), "{err:#}");
+    let saved: client::UploadState =
+        serde_json::from_slice(&std::fs::read(&state_file).unwrap()).unwrap();
+    assert_eq!(
+        f.state.uploads.status(&saved.upload_id).unwrap().received,
+        vec![0, 1]
+    );
+
+    // Restarted client: only the four missing chunks are sent.
+    let second_puts = Arc::new(AtomicUsize::new(0));
+    let addr = serve(flaky(f.router(), usize::MAX, second_puts.clone()));
+    let c = client::Client::new(&format!("http://{addr}"), None);
+    let v = c
+        .upload_resumable(&path, &meta, &[], &state_file, &no_retry)
+        .unwrap();
+    assert_eq!(second_puts.load(Ordering::SeqCst), 4);
+    assert_eq!(v["digest"]["sha256"], sha(&std::fs::read(&path).unwrap()));
+    assert!(!state_file.exists());
+    assert!(f.state.uploads.is_empty());
+
+    // A state file naming a session the sidecar no longer has starts over.
+    std::fs::write(
+        &state_file,
+        serde_json::to_vec(&client::UploadState {
+            upload_id: "gone".into(),
+            ..saved
+        })
+        .unwrap(),
+    )
+    .unwrap();
+    c.upload_resumable(&path, &meta, &[], &state_file, &no_retry)
+        .unwrap();
+    assert_eq!(second_puts.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn acipctl_ingest_file_resumable_uses_chunked_upload() {
+    let f = fixture(4);
+    let work = tempfile::tempdir().unwrap();
+    let path = write_file(work.path(), CHUNK * 2 + 1);
+    let puts = Arc::new(AtomicUsize::new(0));
+    let addr = serve(flaky(f.router(), usize::MAX, puts.clone()));
+
+    let out = assert_cmd::cargo::cargo_bin_cmd!("acipctl")
+        .args(["--url", &format!("http://{addr}")])
+        .args(["ingest-file", "--source-id", "scanner-7"])
+        .args(["--source-type", "other", "--content-type", "text/plain"])
+        .args(["--resumable", "--resumable-threshold", "1000"])
+        .arg(&path)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    let v: Value = serde_json::from_slice(&out).unwrap();
+    assert_eq!(v["digest"]["length"], CHUNK * 2 + 1);
+    assert_eq!(puts.load(Ordering::SeqCst), 3);
+    assert!(!work.path().join("bundle.txt.acip-upload.json").exists());
+}
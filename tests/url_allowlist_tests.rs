@@ -0,0 +1,340 @@
+use acip_sidecar::config::FeedConfig;
+use acip_sidecar::feeds::{self, FeedRegistry};
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::url_allowlist::{
+    covers, is_public_suffix, normalize_host, registrable_domain, UrlAllowlistConfig,
+};
+use acip_sidecar::{app, app_state_builder::AppStateBuilder};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    routing::post,
+    Router,
+};
+use serde_json::{json, Value};
+use std::sync::{Arc, Once};
+use tower::ServiceExt;
+
+static INIT: Once = Once::new();
+
+fn init_env() {
+    INIT.call_once(|| {
+        std::env::set_var("ACIP_SENTRY_MODE", "stub-open");
+        std::env::set_var("ACIP_AUDIT_MODE", "ENABLED");
+    });
+}
+
+/// `default` has no allowlist; `docs` allowlists `example.com` inline and the `partners` feed.
+fn policies() -> PolicyStore {
+    let raw = json!({
+        "policies": {
+            "default": {
+                "l1": { "provider": "gemini", "model": "gemini-2.0-flash" },
+                "l2": { "provider": "anthropic", "model": "claude-3-5-haiku-latest" }
+            },
+            "docs": {
+                "extends": "default",
+                "url_allowlist": { "domains": ["Example.com."], "feeds": ["partners"] }
+            }
+        }
+    });
+    PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap()
+}
+
+/// A `partners` allowlist feed listing `partner.net` and, wrongly, the public suffix `co.uk`.
+async fn partner_feed(dir: &tempfile::TempDir) -> Arc<FeedRegistry> {
+    let path = dir.path().join("partners.txt");
+    std::fs::write(&path, "partner.net\nco.uk\n").unwrap();
+    let cfg: FeedConfig = toml::from_str(&format!(
+        "name = \"partners\"\ntype = \"domain_allowlist\"\nsource = {:?}",
+        path.to_str().unwrap()
+    ))
+    .unwrap();
+    let feeds = FeedRegistry::from_config(
+        &[cfg],
+        reqwest::Client::new(),
+        Arc::new(MockClock::new(1_000)),
+    )
+    .unwrap();
+    feeds.refresh("partners").await.unwrap();
+    Arc::new(feeds)
+}
+
+fn router(feeds: Arc<FeedRegistry>) -> Router {
+    init_env();
+    let st = AppStateBuilder::for_tests()
+        .with_policies(policies())
+        .with_feeds(feeds)
+        .build()
+        .unwrap();
+    let extra = Router::new().route(
+        "/v1/acip/ingest_source",
+        post(acip_sidecar::ingest::ingest_source),
+    );
+    app::build_router(st, None, extra)
+}
+
+async fn send(app: &Router, req: Request<Body>) -> (StatusCode, Value) {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let status = resp.status();
+    let bytes = http_body_util::BodyExt::collect(resp.into_body())
+        .await
+        .unwrap()
+        .to_bytes();
+    (
+        status,
+        serde_json::from_slice(&bytes)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into())),
+    )
+}
+
+/// Ingest `html` under `policy`; the decision.
+async fn ingest_html(app: &Router, policy: &str, html: &str) -> Value {
+    let req = Request::builder()
+        .method("POST")
+        .uri("/v1/acip/ingest_source")
+        .header("content-type", "application/json")
+        .header("x-acip-policy", policy)
+        .body(Body::from(
+            json!({
+                "source_id": "page",
+                "source_type": "html",
+                "content_type": "text/html",
+                "text": html,
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let (code, v) = send(app, req).await;
+    assert_eq!(code, StatusCode::OK, "{v}");
+    v
+}
+
+fn page(links: &[&str]) -> String {
+    let anchors: String = links
+        .iter()
+        .map(|l| format!("<a href=\"{l}\">read more</a>"))
+        .collect();
+    format!("<html><body><p>Release notes for this week.</p>{anchors}</body></html>")
+}
+
+fn indicators(v: &Value) -> Vec<String> {
+    serde_json::from_value(v["threat_audit"]["indicators"].clone()).unwrap()
+}
+
+#[test]
+fn entries_cover_subdomains_on_label_boundaries_only() {
+    assert!(covers("example.com", "example.com"));
+    assert!(covers("example.com", "docs.example.com"));
+    assert!(covers("example.com", "a.b.example.com"));
+    assert!(!covers("example.com", "notexample.com"));
+    assert!(!covers("example.com", "example.com.evil.test"));
+    assert!(!covers("docs.example.com", "example.com"));
+
+    assert!(covers("example.co.uk", "a.example.co.uk"));
+    assert!(!covers("example.co.uk", "other.co.uk"));
+    // Public suffixes would cover unrelated registrants.
+    assert!(!covers("co.uk", "example.co.uk"));
+    assert!(!covers("com", "example.com"));
+    assert!(!covers("github.io", "someone.github.io"));
+}
+
+#[test]
+fn unlisted_cctld_categories_count_as_public_suffixes() {
+    // Not in PUBLIC_SUFFIXES, but a registry category under a country-code TLD.
+    for suffix in ["com.pe", "gov.ng", "ac.ke", "gob.mx"] {
+        assert!(is_public_suffix(suffix), "{suffix}");
+        assert!(!covers(suffix, &format!("shop.{suffix}")), "{suffix}");
+    }
+    assert_eq!(registrable_domain("docs.shop.com.pe"), "shop.com.pe");
+    assert!(covers("shop.com.pe", "docs.shop.com.pe"));
+
+    // Registrable names: a category label under a generic TLD, or another label under a ccTLD.
+    for domain in ["co.com", "example.pe", "com.example"] {
+        assert!(!is_public_suffix(domain), "{domain}");
+    }
+}
+
+#[test]
+fn idn_hosts_compare_in_punycode_and_homographs_stay_distinct() {
+    assert_eq!(
+        normalize_host("Bücher.Example").as_deref(),
+        Some("xn--bcher-kva.example")
+    );
+    assert_eq!(
+        normalize_host("*.xn--bcher-kva.example.").as_deref(),
+        Some("xn--bcher-kva.example")
+    );
+    assert_eq!(
+        feeds::url_hosts("see https://bücher.example/katalog"),
+        ["xn--bcher-kva.example".to_string()].into()
+    );
+
+    // `exаmple.com` with a Cyrillic `а`.
+    let homograph = normalize_host("ex\u{0430}mple.com").unwrap();
+    assert!(homograph.starts_with("xn--"), "{homograph}");
+    assert!(!covers("example.com", &homograph));
+
+    let (hosts, complete) =
+        feeds::url_hosts_checked("https://docs.example.com and a bare http:// prefix");
+    assert_eq!(hosts, ["docs.example.com".to_string()].into());
+    assert!(!complete);
+}
+
+#[test]
+fn policies_refuse_public_suffixes_and_replace_lists_whole() {
+    let docs = policies().get("docs").unwrap().url_allowlist.clone();
+    assert_eq!(docs.domains, ["example.com"]);
+    assert_eq!(docs.feeds, ["partners"]);
+
+    for bad in ["co.uk", "com", "github.io", "com.pe", "not a domain"] {
+        let raw = json!({"policies": {"p": {
+            "l1": { "provider": "gemini", "model": "m" },
+            "l2": { "provider": "anthropic", "model": "m" },
+            "url_allowlist": { "domains": [bad] }
+        }}});
+        let err = PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap())
+            .err()
+            .unwrap_or_else(|| panic!("{bad} was accepted"));
+        assert!(format!("{err:#}").contains("url_allowlist"), "{err:#}");
+    }
+
+    let raw = json!({"policies": {
+        "base": {
+            "l1": { "provider": "gemini", "model": "m" },
+            "l2": { "provider": "anthropic", "model": "m" },
+            "url_allowlist": { "domains": ["a.example"], "feeds": ["partners"] }
+        },
+        "child": { "extends": "base", "url_allowlist": { "domains": ["b.example"] } }
+    }});
+    let store =
+        PolicyStore::from_declared(DeclaredPolicies::parse(&raw.to_string()).unwrap()).unwrap();
+    assert_eq!(
+        store.get("child").unwrap().url_allowlist,
+        UrlAllowlistConfig {
+            domains: vec!["b.example".into()],
+            feeds: vec![],
+        }
+    );
+}
+
+#[tokio::test]
+async fn referenced_feeds_allowlist_hosts_but_never_public_suffixes() {
+    let dir = tempfile::tempdir().unwrap();
+    let feeds = partner_feed(&dir).await;
+    let docs = policies().get("docs").unwrap().url_allowlist.clone();
+
+    let hosts = feeds::url_hosts(
+        "https://docs.example.com https://cdn.partner.net https://shop.co.uk https://evil.test",
+    );
+    let refs: Value = serde_json::to_value(feeds.url_refs(&hosts, &docs)).unwrap();
+    assert_eq!(
+        refs,
+        json!([
+            { "host": "cdn.partner.net", "allowlisted": true, "allowlisted_by": "partners" },
+            { "host": "docs.example.com", "allowlisted": true, "allowlisted_by": "inline" },
+            { "host": "evil.test", "allowlisted": false },
+            { "host": "shop.co.uk", "allowlisted": false },
+        ])
+    );
+
+    let effective = serde_json::to_value(feeds.effective_allowlist(&docs)).unwrap();
+    assert_eq!(
+        effective,
+        json!({"domains": ["example.com"], "feeds": {"partners": 2}})
+    );
+
+    feeds.check_policy_refs(&policies()).unwrap();
+    let missing = FeedRegistry::default().check_policy_refs(&policies());
+    assert!(format!("{:#}", missing.unwrap_err()).contains("unknown feed 'partners'"));
+}
+
+#[tokio::test]
+async fn allowlisted_links_add_no_severity_but_mixed_documents_keep_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = router(partner_feed(&dir).await);
+    let allowlisted = page(&[
+        "https://docs.example.com/guide",
+        "https://cdn.partner.net/a.js",
+    ]);
+    let mixed = page(&[
+        "https://docs.example.com/guide",
+        "https://evil.test/collect",
+    ]);
+
+    // Without an allowlist the links are external references for both markup scanners.
+    let plain = ingest_html(&app, "default", &allowlisted).await;
+    let baseline = plain["threat"]["threat_score"].as_u64().unwrap();
+    assert!(indicators(&plain).contains(&"html_scan:https".to_string()));
+    assert!(indicators(&plain).contains(&"xml_scan:https".to_string()));
+    assert_eq!(plain["threat_audit"]["urls"][0]["allowlisted"], false);
+
+    let v = ingest_html(&app, "docs", &allowlisted).await;
+    assert_eq!(
+        v["threat"]["threat_score"].as_u64().unwrap() + 2,
+        baseline,
+        "{v}"
+    );
+    assert!(!indicators(&v).iter().any(|i| i.ends_with(":https")), "{v}");
+    assert_eq!(
+        v["threat_audit"]["urls"],
+        json!([
+            { "host": "cdn.partner.net", "allowlisted": true, "allowlisted_by": "partners" },
+            { "host": "docs.example.com", "allowlisted": true, "allowlisted_by": "inline" },
+        ])
+    );
+
+    // One link elsewhere: every external-reference signal stays.
+    let v = ingest_html(&app, "docs", &mixed).await;
+    assert_eq!(
+        v["threat"]["threat_score"].as_u64().unwrap(),
+        baseline,
+        "{v}"
+    );
+    assert!(indicators(&v).contains(&"html_scan:https".to_string()));
+    assert_eq!(v["threat_audit"]["urls"][1]["host"], "evil.test");
+    assert_eq!(v["threat_audit"]["urls"][1]["allowlisted"], false);
+
+    // A homograph of an allowlisted domain is just another host.
+    let v = ingest_html(
+        &app,
+        "docs",
+        &page(&["https://docs.ex\u{0430}mple.com/guide"]),
+    )
+    .await;
+    assert!(indicators(&v).contains(&"html_scan:https".to_string()));
+    assert_eq!(v["threat_audit"]["urls"][0]["allowlisted"], false);
+}
+
+#[tokio::test]
+async fn capabilities_and_policy_report_the_effective_allowlist() {
+    let dir = tempfile::tempdir().unwrap();
+    let app = router(partner_feed(&dir).await);
+    let get = |uri: &str| {
+        Request::builder()
+            .uri(uri)
+            .header("x-acip-policy", "docs")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let (code, caps) = send(&app, get("/v1/acip/capabilities")).await;
+    assert_eq!(code, StatusCode::OK, "{caps}");
+    let docs = caps["policies"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["name"] == "docs")
+        .unwrap();
+    let expected = json!({"domains": ["example.com"], "feeds": {"partners": 2}});
+    assert_eq!(docs["url_allowlist"], expected);
+
+    let (code, policy) = send(&app, get("/v1/acip/policy")).await;
+    assert_eq!(code, StatusCode::OK, "{policy}");
+    assert_eq!(policy["url_allowlist"], expected);
+    assert_eq!(
+        policy["policy"]["url_allowlist"]["domains"],
+        json!(["example.com"])
+    );
+}
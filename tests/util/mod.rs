@@ -1 +1,27 @@
+//! Helpers shared by the integration tests; not every test binary uses all of them.
+#![allow(dead_code)]
+
 pub mod bin;
+
+use axum::Router;
+use std::net::SocketAddr;
+
+/// Serve `router` on an ephemeral loopback port from a background thread.
+pub fn serve(router: Router) -> SocketAddr {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, router).await.unwrap();
+        });
+    });
+
+    addr
+}
@@ -0,0 +1,279 @@
+use acip_sidecar::model_policy::{PolicyConfig, VerdictParsing};
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::reputation::SystemClock;
+use acip_sidecar::sentry::{
+    parse_decision, Action, DecisionEngine, ModelClient, ModelTier, Repair, RiskLevel,
+};
+use acip_sidecar::stats::{DecisionStats, GroupBy, StatsRow, StatsSettings};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+fn valid() -> Value {
+    json!({
+        "tools_allowed": false,
+        "risk_level": "low",
+        "action": "allow",
+        "fenced_content": "```external\nX\n```",
+        "reasons": ["ok"],
+        "detected_patterns": []
+    })
+}
+
+fn with(f: impl FnOnce(&mut serde_json::Map<String, Value>)) -> String {
+    let mut v = valid();
+    f(v.as_object_mut().unwrap());
+    v.to_string()
+}
+
+/// Each entry: (input, the single repair it needs).
+fn repairable() -> Vec<(String, Repair)> {
+    vec![
+        (
+            format!("```json\n{}\n```", valid()),
+            Repair::StripCodeFences,
+        ),
+        (
+            format!("Here is my verdict: {} Hope this helps.", valid()),
+            Repair::ExtractEmbeddedJson,
+        ),
+        (
+            with(|m| {
+                m.insert("risk_level".into(), json!("LOW"));
+            }),
+            Repair::LowercaseEnums,
+        ),
+        (
+            with(|m| {
+                m.insert("action".into(), json!("Needs_Review"));
+            }),
+            Repair::LowercaseEnums,
+        ),
+        (
+            with(|m| {
+                m.insert("confidence".into(), json!(0.9));
+            }),
+            Repair::DropUnknownFields,
+        ),
+        (
+            with(|m| {
+                m.remove("reasons");
+            }),
+            Repair::DefaultMissingArrays,
+        ),
+        (
+            with(|m| {
+                m.remove("detected_patterns");
+            }),
+            Repair::DefaultMissingArrays,
+        ),
+    ]
+}
+
+#[test]
+fn each_repair_is_applied_and_recorded() {
+    for (input, repair) in repairable() {
+        let p = parse_decision(&input, VerdictParsing::Repair)
+            .unwrap_or_else(|e| panic!("{input}: {e:#}"));
+        assert_eq!(p.repairs, vec![repair], "{input}");
+    }
+}
+
+#[test]
+fn strict_rejects_everything_repair_fixes() {
+    for (input, _) in repairable() {
+        assert!(
+            parse_decision(&input, VerdictParsing::Strict).is_err(),
+            "strict accepted {input}"
+        );
+    }
+}
+
+#[test]
+fn clean_output_needs_no_repairs_in_either_mode() {
+    let raw = format!("\n  {}  \n", valid());
+    for mode in [VerdictParsing::Strict, VerdictParsing::Repair] {
+        let p = parse_decision(&raw, mode).unwrap();
+        assert!(p.repairs.is_empty());
+        assert_eq!(p.decision.risk_level, RiskLevel::Low);
+    }
+}
+
+#[test]
+fn repairs_combine_in_application_order() {
+    let mut v = valid();
+    let m = v.as_object_mut().unwrap();
+    m.insert("action".into(), json!("BLOCK"));
+    m.insert("confidence".into(), json!("high"));
+    m.remove("detected_patterns");
+    let raw = format!("```\n{v}\n```");
+
+    let p = parse_decision(&raw, VerdictParsing::Repair).unwrap();
+    assert_eq!(
+        p.repairs,
+        vec![
+            Repair::StripCodeFences,
+            Repair::LowercaseEnums,
+            Repair::DropUnknownFields,
+            Repair::DefaultMissingArrays,
+        ]
+    );
+    assert_eq!(p.decision.action, Action::Block);
+    assert!(p.decision.detected_patterns.is_empty());
+}
+
+#[test]
+fn deviations_outside_the_repair_set_fail_in_both_modes() {
+    let trailing_comma = valid().to_string().replacen('}', ",}", 1);
+    let unrepairable = vec![
+        ("trailing comma", trailing_comma),
+        (
+            "string boolean",
+            with(|m| {
+                m.insert("tools_allowed".into(), json!("false"));
+            }),
+        ),
+        (
+            "unknown enum value",
+            with(|m| {
+                m.insert("risk_level".into(), json!("critical"));
+            }),
+        ),
+        (
+            "missing required scalar",
+            with(|m| {
+                m.remove("action");
+            }),
+        ),
+        (
+            "null array",
+            with(|m| {
+                m.insert("reasons".into(), Value::Null);
+            }),
+        ),
+        (
+            "wrong array item type",
+            with(|m| {
+                m.insert("reasons".into(), json!([1, 2]));
+            }),
+        ),
+        ("top-level array", format!("[{}]", valid())),
+        ("empty output", "   ".to_string()),
+        ("prose only", "I think this is safe.".to_string()),
+    ];
+
+    for (what, input) in unrepairable {
+        for mode in [VerdictParsing::Strict, VerdictParsing::Repair] {
+            assert!(
+                parse_decision(&input, mode).is_err(),
+                "{what} accepted in {mode:?}: {input}"
+            );
+        }
+    }
+}
+
+struct Fixed(String);
+
+#[async_trait]
+impl ModelClient for Fixed {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+fn policy(mode: VerdictParsing) -> PolicyConfig {
+    PolicyConfig {
+        verdict_parsing: mode,
+        on_garbled_text: Default::default(),
+        on_version_mismatch: Default::default(),
+        ..PolicyConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn strict_failure_falls_back_to_l2_and_is_counted() {
+    let fenced = format!("```json\n{}\n```", valid());
+    let engine = DecisionEngine::new(
+        Box::new(Fixed(fenced.clone())),
+        Box::new(Fixed(valid().to_string())),
+    );
+
+    let strict = engine
+        .decide_tiered(
+            "default",
+            &policy(VerdictParsing::Strict),
+            &json!({}),
+            "```external\nX\n```",
+            &HeaderMap::new(),
+        )
+        .await;
+    assert_eq!(strict.tier, ModelTier::L2);
+    assert!(strict.repairs.is_empty());
+    assert_eq!(strict.attempts.len(), 2);
+    assert!(!strict.attempts[0].valid);
+    assert_eq!(strict.attempts[0].model, "Gemini/gemini-2.0-flash");
+    assert!(strict.attempts[1].valid);
+
+    let repaired = engine
+        .decide_tiered(
+            "default",
+            &policy(VerdictParsing::Repair),
+            &json!({}),
+            "```external\nX\n```",
+            &HeaderMap::new(),
+        )
+        .await;
+    assert_eq!(repaired.tier, ModelTier::L1);
+    assert_eq!(repaired.repairs, vec![Repair::StripCodeFences]);
+
+    let stats = DecisionStats::in_memory(StatsSettings::default(), Arc::new(SystemClock));
+    stats.record_parse_attempts(&strict.attempts);
+    stats.record_parse_attempts(&repaired.attempts);
+
+    let rows = stats.report(1, GroupBy::Model).rows;
+    let row = |name: &str| {
+        rows.iter()
+            .find_map(|r| match r {
+                StatsRow::Model(m) if m.model == name => Some(m.clone()),
+                _ => None,
+            })
+            .unwrap()
+    };
+    let l1 = row("Gemini/gemini-2.0-flash");
+    assert_eq!((l1.parses, l1.repaired, l1.invalid), (2, 1, 1));
+    assert_eq!(l1.repair_rate, 0.5);
+    assert_eq!(l1.by_repair["strip_code_fences"], 1);
+    let l2 = row("Anthropic/claude-3-5-haiku-latest");
+    assert_eq!((l2.parses, l2.repaired, l2.invalid), (1, 0, 0));
+}
+
+#[test]
+fn verdict_parsing_is_inherited() {
+    let store = PolicyStore::from_declared(
+        DeclaredPolicies::parse(
+            r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash"},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"}
+    },
+    "strict": {"extends": "default", "verdict_parsing": "strict"},
+    "child": {"extends": "strict"}
+  }
+}"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let mode = |name: &str| store.get(name).unwrap().verdict_parsing;
+    assert_eq!(mode("default"), VerdictParsing::Repair);
+    assert_eq!(mode("strict"), VerdictParsing::Strict);
+    assert_eq!(mode("child"), VerdictParsing::Strict);
+}
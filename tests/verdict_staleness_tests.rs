@@ -0,0 +1,246 @@
+use acip_sidecar::model_policy::{PolicyConfig, DEFAULT_MAX_VERDICT_AGE_DAYS};
+use acip_sidecar::policy_store::{DeclaredPolicies, PolicyStore};
+use acip_sidecar::reputation::MockClock;
+use acip_sidecar::sentry::{Action, Decision, DecisionEngine, ModelClient, RiskLevel};
+use acip_sidecar::stats::{DecisionStats, GroupBy, PolicyRow, StatsRow, DAY_SECS};
+use acip_sidecar::threat;
+use acip_sidecar::verdicts::{Provenance, Staleness, VerdictHistory};
+use async_trait::async_trait;
+use axum::http::HeaderMap;
+use std::sync::Arc;
+
+const T0: u64 = 20_000 * DAY_SECS;
+const DIGEST: &str = "4f2a";
+
+/// Provider with a fixed verdict.
+struct FixedModel {
+    action: &'static str,
+    risk_level: &'static str,
+}
+
+#[async_trait]
+impl ModelClient for FixedModel {
+    async fn generate(
+        &self,
+        _model: &str,
+        _prompt: &str,
+        _headers: &HeaderMap,
+    ) -> anyhow::Result<String> {
+        Ok(serde_json::json!({
+            "tools_allowed": false,
+            "risk_level": self.risk_level,
+            "action": self.action,
+            "fenced_content": "```external\nX\n```",
+            "reasons": ["fixed"],
+            "detected_patterns": [],
+        })
+        .to_string())
+    }
+}
+
+async fn decide(policy: &PolicyConfig, action: &'static str, risk_level: &'static str) -> Decision {
+    let engine = DecisionEngine::new(
+        Box::new(FixedModel { action, risk_level }),
+        Box::new(FixedModel { action, risk_level }),
+    );
+    engine
+        .decide(
+            "default",
+            policy,
+            &serde_json::json!({}),
+            "```external\nX\n```",
+            &HeaderMap::new(),
+        )
+        .await
+}
+
+fn policy(max_age_days: u64) -> PolicyConfig {
+    let mut p = PolicyConfig::default();
+    p.cache.max_verdict_age_days = max_age_days;
+    p
+}
+
+fn setup() -> (Arc<MockClock>, VerdictHistory, DecisionStats) {
+    let clock = Arc::new(MockClock::new(T0));
+    let history = VerdictHistory::new(100, clock.clone());
+    let stats = DecisionStats::in_memory(Default::default(), clock.clone());
+    (clock, history, stats)
+}
+
+fn policy_row(stats: &DecisionStats) -> PolicyRow {
+    match stats.report(7, GroupBy::Policy).rows.into_iter().next() {
+        Some(StatsRow::Policy(p)) => p,
+        other => panic!("unexpected row {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn verdicts_expire_after_max_age() {
+    let (clock, history, stats) = setup();
+    let p = policy(7);
+    let d = decide(&p, "allow", "low").await;
+
+    assert!(history
+        .observe(&stats, "default", &p, DIGEST, &d, None)
+        .is_none());
+
+    // Within the window: fresh, no revalidation.
+    clock.advance(6 * DAY_SECS);
+    let (_, staleness) = history
+        .lookup("default", DIGEST, 7, &Provenance::current(&p))
+        .unwrap();
+    assert_eq!(staleness, Staleness::Fresh);
+    assert!(history
+        .observe(&stats, "default", &p, DIGEST, &d, None)
+        .is_none());
+
+    // Past the window (measured from the last re-run).
+    clock.advance(7 * DAY_SECS + 1);
+    let (_, staleness) = history
+        .lookup("default", DIGEST, 7, &Provenance::current(&p))
+        .unwrap();
+    assert_eq!(staleness, Staleness::Aged);
+
+    let reval = history
+        .observe(&stats, "default", &p, DIGEST, &d, None)
+        .unwrap();
+    assert_eq!(reval.staleness, Staleness::Aged);
+    assert!(reval.agreed);
+
+    let row = policy_row(&stats);
+    assert_eq!(row.revalidations, 1);
+    assert_eq!(row.revalidation_divergences, 0);
+    assert_eq!(row.revalidation_agreement_rate, 1.0);
+
+    // The re-check itself is now the fresh verdict.
+    assert!(history
+        .observe(&stats, "default", &p, DIGEST, &d, None)
+        .is_none());
+}
+
+#[tokio::test]
+async fn provenance_change_invalidates_immediately() {
+    let (_clock, history, stats) = setup();
+    let p = policy(DEFAULT_MAX_VERDICT_AGE_DAYS);
+    let d = decide(&p, "allow", "low").await;
+    history.observe(&stats, "default", &p, DIGEST, &d, None);
+
+    // A different pattern pack marks the verdict stale without waiting out the age.
+    let mut other_pack = Provenance::current(&p);
+    assert_eq!(other_pack.pattern_pack, threat::pattern_pack_hash());
+    other_pack.pattern_pack = "0000000000000000".to_string();
+    let (_, staleness) = history
+        .lookup("default", DIGEST, DEFAULT_MAX_VERDICT_AGE_DAYS, &other_pack)
+        .unwrap();
+    assert_eq!(staleness, Staleness::ProvenanceChanged);
+
+    // So does a model identifier change in the policy.
+    let mut upgraded = p.clone();
+    upgraded.l1.model = "gemini-2.5-flash".to_string();
+    let reval = history
+        .observe(&stats, "default", &upgraded, DIGEST, &d, None)
+        .unwrap();
+    assert_eq!(reval.staleness, Staleness::ProvenanceChanged);
+    assert!(reval.agreed);
+    assert_eq!(
+        reval.previous.provenance.l1_model,
+        "Gemini/gemini-2.0-flash"
+    );
+
+    // Verdicts are tracked per policy.
+    assert!(history
+        .observe(&stats, "strict", &upgraded, DIGEST, &d, None)
+        .is_none());
+
+    // And so does a new version reported behind the same model name.
+    let reval = history
+        .observe(
+            &stats,
+            "strict",
+            &upgraded,
+            DIGEST,
+            &d,
+            Some("gemini-2.5-flash-002"),
+        )
+        .unwrap();
+    assert_eq!(reval.staleness, Staleness::ProvenanceChanged);
+    assert_eq!(reval.previous.provenance.model_version, None);
+}
+
+#[tokio::test]
+async fn divergent_recheck_counts_as_rules_drift() {
+    let (clock, history, stats) = setup();
+    let p = policy(1);
+
+    let before = decide(&p, "allow", "low").await;
+    history.observe(&stats, "default", &p, DIGEST, &before, None);
+
+    // The provider now blocks the same content.
+    clock.advance(2 * DAY_SECS);
+    let after = decide(&p, "block", "high").await;
+    assert_eq!(after.action, Action::Block);
+    let reval = history
+        .observe(&stats, "default", &p, DIGEST, &after, None)
+        .unwrap();
+    assert!(!reval.agreed);
+    assert_eq!(reval.previous.action, Action::Allow);
+    assert_eq!(reval.previous.risk_level, RiskLevel::Low);
+
+    // A second stale re-check that agrees.
+    clock.advance(2 * DAY_SECS);
+    let again = decide(&p, "block", "high").await;
+    assert!(
+        history
+            .observe(&stats, "default", &p, DIGEST, &again, None)
+            .unwrap()
+            .agreed
+    );
+
+    let row = policy_row(&stats);
+    assert_eq!(row.revalidations, 2);
+    assert_eq!(row.revalidation_divergences, 1);
+    assert_eq!(row.revalidation_agreement_rate, 0.5);
+}
+
+#[test]
+fn oldest_verdict_is_evicted_at_capacity() {
+    let clock = Arc::new(MockClock::new(T0));
+    let history = VerdictHistory::new(2, clock.clone());
+    let stats = DecisionStats::in_memory(Default::default(), clock.clone());
+    let p = PolicyConfig::default();
+    let d = Decision::fail_closed("x".to_string(), vec![]);
+    let prov = Provenance::current(&p);
+
+    for digest in ["a", "b", "c"] {
+        history.observe(&stats, "default", &p, digest, &d, None);
+        clock.advance(1);
+    }
+    assert!(history.lookup("default", "a", 30, &prov).is_none());
+    assert!(history.lookup("default", "b", 30, &prov).is_some());
+    assert!(history.lookup("default", "c", 30, &prov).is_some());
+}
+
+#[test]
+fn max_verdict_age_is_inherited() {
+    let store = PolicyStore::from_declared(
+        DeclaredPolicies::parse(
+            r#"{
+  "policies": {
+    "default": {
+      "l1": {"provider": "gemini", "model": "gemini-2.0-flash"},
+      "l2": {"provider": "anthropic", "model": "claude-3-5-haiku-latest"}
+    },
+    "fast_moving": {"extends": "default", "cache": {"max_verdict_age_days": 3}},
+    "child": {"extends": "fast_moving"}
+  }
+}"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+
+    let age = |name: &str| store.get(name).unwrap().cache.max_verdict_age_days;
+    assert_eq!(age("default"), DEFAULT_MAX_VERDICT_AGE_DAYS);
+    assert_eq!(age("fast_moving"), 3);
+    assert_eq!(age("child"), 3);
+}
@@ -0,0 +1,111 @@
+//! Repository tasks: `cargo xtask <task>`.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+const USAGE: &str = "\
+usage: cargo xtask <task>
+
+tasks:
+  soak [options]   run the soak and fault-injection test (tests/soak_tests.rs)
+
+soak options (each overrides the matching ACIP_SOAK_* variable):
+  --secs N          how long to send traffic (ACIP_SOAK_SECS, default 60)
+  --rps N           requests per second (ACIP_SOAK_RPS, default 25)
+  --concurrency N   requests in flight at most (ACIP_SOAK_CONCURRENCY, default 16)
+  --seed N          traffic and fault seed (ACIP_SOAK_SEED, default: time-based)
+  --report PATH     also write the report as JSON (ACIP_SOAK_REPORT)
+  --debug           build without --release
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("soak") => match soak(&args[1..]) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("xtask soak: {e}\n\n{USAGE}");
+                ExitCode::from(2)
+            }
+        },
+        Some("-h" | "--help" | "help") => {
+            print!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        Some(other) => {
+            eprintln!("xtask: unknown task `{other}`\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        None => {
+            eprint!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn soak(args: &[String]) -> Result<ExitCode, String> {
+    let mut vars: Vec<(&str, String)> = vec![];
+    let mut release = true;
+    let mut it = args.iter();
+    while let Some(arg) = it.next() {
+        let var = match arg.as_str() {
+            "--secs" => "ACIP_SOAK_SECS",
+            "--rps" => "ACIP_SOAK_RPS",
+            "--concurrency" => "ACIP_SOAK_CONCURRENCY",
+            "--seed" => "ACIP_SOAK_SEED",
+            "--report" => "ACIP_SOAK_REPORT",
+            "--debug" => {
+                release = false;
+                continue;
+            }
+            other => return Err(format!("unknown option `{other}`")),
+        };
+        let value = it.next().ok_or_else(|| format!("{arg} needs a value"))?;
+        if var != "ACIP_SOAK_REPORT" && value.parse::<u64>().is_err() {
+            return Err(format!("{arg} expects a number, got `{value}`"));
+        }
+        vars.push((var, value.clone()));
+    }
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    cmd.current_dir(repo_root()).arg("test");
+    if release {
+        cmd.arg("--release");
+    }
+    cmd.args(["--test", "soak_tests", "--", "--ignored", "--nocapture"]);
+    for (var, value) in vars {
+        // A relative report path means relative to where xtask was run.
+        let value = if var == "ACIP_SOAK_REPORT" {
+            absolute(Path::new(&value)).display().to_string()
+        } else {
+            value
+        };
+        cmd.env(var, value);
+    }
+
+    let status = cmd
+        .status()
+        .map_err(|e| format!("failed to run cargo: {e}"))?;
+    Ok(match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+fn repo_root() -> PathBuf {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    manifest_dir.parent().unwrap_or(manifest_dir).to_path_buf()
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    env::current_dir()
+        .map(|cwd| cwd.join(path))
+        .unwrap_or_else(|_| path.to_path_buf())
+}